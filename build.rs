@@ -40,6 +40,7 @@ error: the `wasm32-wasi` target is not installed
     std::fs::create_dir_all("target/test-programs").unwrap();
 
     build_wasm_test_program("rust-http-test.wasm", "crates/http/tests/rust-http-test");
+    build_wasm_test_program("config-test.wasm", "crates/http/tests/config-test");
     build_wasm_test_program("redis-rust.wasm", "crates/redis/tests/rust");
     build_wasm_test_program("wagi-test.wasm", "crates/http/tests/wagi-test");
 
@@ -0,0 +1,180 @@
+use std::{
+    fs::OpenOptions,
+    io::{ErrorKind, Read, Seek, SeekFrom, Write},
+    path::PathBuf,
+};
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use spin_engine::DataDir;
+use walkdir::WalkDir;
+
+use crate::{spin_blobstore::ObjectMetadata, Container, NotFound};
+
+/// A container that stores objects as files under a local directory,
+/// optionally bounded by the quota of the data directory it is in.
+pub(crate) struct FileContainer {
+    root: PathBuf,
+    quota: Option<DataDir>,
+}
+
+impl FileContainer {
+    pub(crate) fn new(root: PathBuf) -> Self {
+        Self { root, quota: None }
+    }
+
+    pub(crate) fn with_quota(root: PathBuf, quota: DataDir) -> Self {
+        Self {
+            root,
+            quota: Some(quota),
+        }
+    }
+
+    /// Checks that `bytes` more can be written, if the container has a quota.
+    fn reserve(&self, bytes: u64) -> Result<()> {
+        match &self.quota {
+            Some(quota) => quota.reserve(bytes),
+            None => Ok(()),
+        }
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.root.join(name)
+    }
+
+    fn create_parent(&self, name: &str) -> Result<PathBuf> {
+        let path = self.path(name);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Cannot create directory {}", parent.display()))?;
+        }
+        Ok(path)
+    }
+}
+
+// Objects are local files, read and written like the files of WASI.
+#[async_trait]
+impl Container for FileContainer {
+    async fn put(&self, name: &str, data: &[u8]) -> Result<()> {
+        // Replacing an object frees its current size.
+        let existing = std::fs::metadata(self.path(name))
+            .map(|m| m.len())
+            .unwrap_or(0);
+        self.reserve((data.len() as u64).saturating_sub(existing))?;
+        let path = self.create_parent(name)?;
+        std::fs::write(&path, data).with_context(|| format!("Cannot write {}", path.display()))
+    }
+
+    async fn append(&self, name: &str, data: &[u8]) -> Result<()> {
+        self.reserve(data.len() as u64)?;
+        let path = self.create_parent(name)?;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .with_context(|| format!("Cannot open {}", path.display()))?;
+        file.write_all(data)?;
+        Ok(())
+    }
+
+    async fn get_range(&self, name: &str, offset: u64, len: Option<u64>) -> Result<Vec<u8>> {
+        let path = self.path(name);
+        let mut file = match std::fs::File::open(&path) {
+            Err(e) if e.kind() == ErrorKind::NotFound => return Err(NotFound.into()),
+            other => other.with_context(|| format!("Cannot open {}", path.display()))?,
+        };
+        file.seek(SeekFrom::Start(offset))?;
+        let mut buf = vec![];
+        match len {
+            Some(len) => file.take(len).read_to_end(&mut buf)?,
+            None => file.read_to_end(&mut buf)?,
+        };
+        Ok(buf)
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<ObjectMetadata>> {
+        if !self.root.exists() {
+            return Ok(vec![]);
+        }
+        let mut objects = vec![];
+        for entry in WalkDir::new(&self.root).sort_by_file_name() {
+            let entry = entry?;
+            if !entry.file_type().is_file() {
+                continue;
+            }
+            let name = entry
+                .path()
+                .strip_prefix(&self.root)?
+                .to_string_lossy()
+                .replace('\\', "/");
+            if name.starts_with(prefix) {
+                objects.push(ObjectMetadata {
+                    name,
+                    size: entry.metadata()?.len(),
+                });
+            }
+        }
+        Ok(objects)
+    }
+
+    async fn delete(&self, name: &str) -> Result<()> {
+        match std::fs::remove_file(self.path(name)) {
+            Err(e) if e.kind() == ErrorKind::NotFound => Err(NotFound.into()),
+            other => Ok(other?),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_file_container_roundtrip() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let container = FileContainer::new(dir.path().to_owned());
+
+        container.put("a/b.txt", b"Hello").await?;
+        container.append("a/b.txt", b", Fermyon").await?;
+        assert_eq!(
+            container.get_range("a/b.txt", 0, None).await?,
+            b"Hello, Fermyon"
+        );
+        assert_eq!(container.get_range("a/b.txt", 7, Some(3)).await?, b"Fer");
+
+        let objects = container.list("a/").await?;
+        assert_eq!(objects.len(), 1);
+        assert_eq!(objects[0].name, "a/b.txt");
+        assert_eq!(objects[0].size, 14);
+
+        container.delete("a/b.txt").await?;
+        assert!(container
+            .get_range("a/b.txt", 0, None)
+            .await
+            .unwrap_err()
+            .downcast_ref::<NotFound>()
+            .is_some());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_file_container_quota() -> Result<()> {
+        let root = tempfile::tempdir()?;
+        let data_dir = spin_engine::DataDirConfig {
+            root: Some(root.path().to_owned()),
+            max_bytes: Some(10),
+        }
+        .resolve("my-app")
+        .unwrap();
+        let container =
+            FileContainer::with_quota(data_dir.path().join("blobstore/c"), data_dir.clone());
+
+        container.put("a", b"12345678").await?;
+        container.put("a", b"1234567890").await?;
+        assert!(container.append("a", b"1").await.is_err());
+        assert!(container.put("b", b"1").await.is_err());
+        container.delete("a").await?;
+        container.put("b", b"1").await?;
+        Ok(())
+    }
+}
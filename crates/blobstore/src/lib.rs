@@ -0,0 +1,339 @@
+//! A blob storage host interface for Spin components.
+
+mod fs;
+mod s3;
+
+use std::{collections::HashMap, path::PathBuf, sync::Arc};
+
+use anyhow::Context;
+use async_trait::async_trait;
+use serde::Deserialize;
+use spin_blobstore::*;
+use spin_engine::{
+    host_component::{HostComponent, HostComponentsStateHandle},
+    DataDir, QuotaExceeded, RuntimeContext, ServiceUnavailable,
+};
+use spin_manifest::CoreComponent;
+use wit_bindgen_wasmtime::wasmtime::Linker;
+
+pub use spin_blobstore::add_to_linker;
+
+wit_bindgen_wasmtime::export!({
+    paths: ["../../wit/ephemeral/spin-blobstore.wit"],
+    async: *,
+});
+
+/// The directory, relative to the data directory of the application or else
+/// to the working directory of the runtime, in which containers without
+/// explicit runtime configuration are stored.
+const DEFAULT_BLOBSTORE_DIR: &str = ".spin/blobstore";
+
+/// Runtime configuration for a blob store container.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "snake_case", tag = "type")]
+pub enum ContainerConfig {
+    /// Store objects as files in a local directory.
+    Filesystem {
+        /// The directory in which to store objects.
+        path: PathBuf,
+    },
+    /// Store objects in a bucket of an S3-compatible service.
+    S3 {
+        /// The service endpoint, for example `http://localhost:9000`.
+        endpoint: String,
+        /// The bucket name.
+        bucket: String,
+        /// The bucket region.
+        #[serde(default = "default_region")]
+        region: String,
+        /// Access key ID. Defaults to the `AWS_ACCESS_KEY_ID` environment variable.
+        access_key: Option<String>,
+        /// Secret access key. Defaults to the `AWS_SECRET_ACCESS_KEY` environment variable.
+        secret_key: Option<String>,
+    },
+}
+
+fn default_region() -> String {
+    "us-east-1".to_string()
+}
+
+/// A backing store for a single container.
+#[async_trait]
+pub(crate) trait Container: Send + Sync {
+    async fn put(&self, name: &str, data: &[u8]) -> anyhow::Result<()>;
+    async fn append(&self, name: &str, data: &[u8]) -> anyhow::Result<()>;
+    async fn get_range(&self, name: &str, offset: u64, len: Option<u64>)
+        -> anyhow::Result<Vec<u8>>;
+    async fn list(&self, prefix: &str) -> anyhow::Result<Vec<ObjectMetadata>>;
+    async fn delete(&self, name: &str) -> anyhow::Result<()>;
+
+    /// Checks that the backing store can be reached.
+    async fn check(&self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+/// A container of a blob store component started without its backend,
+/// returning `unavailable` errors.
+struct UnavailableContainer(ServiceUnavailable);
+
+#[async_trait]
+impl Container for UnavailableContainer {
+    async fn put(&self, _name: &str, _data: &[u8]) -> anyhow::Result<()> {
+        Err(self.0.clone().into())
+    }
+
+    async fn append(&self, _name: &str, _data: &[u8]) -> anyhow::Result<()> {
+        Err(self.0.clone().into())
+    }
+
+    async fn get_range(
+        &self,
+        _name: &str,
+        _offset: u64,
+        _len: Option<u64>,
+    ) -> anyhow::Result<Vec<u8>> {
+        Err(self.0.clone().into())
+    }
+
+    async fn list(&self, _prefix: &str) -> anyhow::Result<Vec<ObjectMetadata>> {
+        Err(self.0.clone().into())
+    }
+
+    async fn delete(&self, _name: &str) -> anyhow::Result<()> {
+        Err(self.0.clone().into())
+    }
+}
+
+/// Errors returned by a container backend that map to a specific guest error.
+#[derive(Debug)]
+pub(crate) struct NotFound;
+
+impl std::fmt::Display for NotFound {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "object not found")
+    }
+}
+
+impl std::error::Error for NotFound {}
+
+/// The blob store host component.
+#[derive(Clone, Default)]
+pub struct BlobStoreComponent {
+    containers: HashMap<String, ContainerConfig>,
+    data_dir: Option<DataDir>,
+    unavailable: Option<ServiceUnavailable>,
+}
+
+impl BlobStoreComponent {
+    /// Creates a blob store host component with the given container configuration.
+    /// Containers that are not configured are stored on the local filesystem.
+    pub fn new(containers: HashMap<String, ContainerConfig>) -> Self {
+        Self {
+            containers,
+            data_dir: None,
+            unavailable: None,
+        }
+    }
+
+    /// Stores containers that are not configured in the data directory of the
+    /// application, within its quota.
+    pub fn with_data_dir(mut self, data_dir: Option<DataDir>) -> Self {
+        self.data_dir = data_dir;
+        self
+    }
+
+    /// Returns `unavailable` errors for all containers, as the backing store
+    /// was unavailable when the application started.
+    pub fn unavailable(mut self, error: ServiceUnavailable) -> Self {
+        self.unavailable = Some(error);
+        self
+    }
+
+    /// Checks that the backing stores of the configured containers can be
+    /// reached.
+    pub async fn check(&self) -> anyhow::Result<()> {
+        for name in self.containers.keys() {
+            self.open(name)?
+                .check()
+                .await
+                .with_context(|| format!("Cannot reach blob container {:?}", name))?;
+        }
+        Ok(())
+    }
+
+    fn open(&self, name: &str) -> anyhow::Result<Arc<dyn Container>> {
+        if let Some(error) = &self.unavailable {
+            return Ok(Arc::new(UnavailableContainer(error.clone())));
+        }
+        let config = match self.containers.get(name) {
+            Some(config) => config.clone(),
+            None => match &self.data_dir {
+                Some(data_dir) => {
+                    let path = data_dir.path().join(DEFAULT_BLOBSTORE_DIR).join(name);
+                    return Ok(Arc::new(fs::FileContainer::with_quota(
+                        path,
+                        data_dir.clone(),
+                    )));
+                }
+                None => ContainerConfig::Filesystem {
+                    path: PathBuf::from(DEFAULT_BLOBSTORE_DIR).join(name),
+                },
+            },
+        };
+        Ok(match config {
+            ContainerConfig::Filesystem { path } => Arc::new(fs::FileContainer::new(path)),
+            ContainerConfig::S3 {
+                endpoint,
+                bucket,
+                region,
+                access_key,
+                secret_key,
+            } => Arc::new(s3::S3Container::new(
+                endpoint, bucket, region, access_key, secret_key,
+            )?),
+        })
+    }
+}
+
+impl HostComponent for BlobStoreComponent {
+    type State = BlobStore;
+
+    fn add_to_linker<T: Send>(
+        linker: &mut Linker<RuntimeContext<T>>,
+        state_handle: HostComponentsStateHandle<Self::State>,
+    ) -> anyhow::Result<()> {
+        add_to_linker(linker, move |ctx| state_handle.get_mut(ctx))
+    }
+
+    fn build_state(&self, component: &CoreComponent) -> anyhow::Result<Self::State> {
+        let containers = component
+            .wasm
+            .allowed_blob_containers
+            .iter()
+            .map(|name| Ok((name.clone(), self.open(name)?)))
+            .collect::<anyhow::Result<_>>()?;
+        Ok(BlobStore { containers })
+    }
+}
+
+/// Per-component blob store state, holding the containers the component may access.
+pub struct BlobStore {
+    containers: HashMap<String, Arc<dyn Container>>,
+}
+
+impl BlobStore {
+    fn container(&self, name: &str) -> Result<&Arc<dyn Container>, Error> {
+        self.containers.get(name).ok_or_else(|| {
+            Error::AccessDenied(format!(
+                "container {:?} is not in allowed_blob_containers",
+                name
+            ))
+        })
+    }
+}
+
+#[async_trait]
+impl spin_blobstore::SpinBlobstore for BlobStore {
+    async fn put(&mut self, container: &str, name: &str, data: &[u8]) -> Result<(), Error> {
+        validate_name(name)?;
+        self.container(container)?
+            .put(name, data)
+            .await
+            .map_err(to_error)
+    }
+
+    async fn append(&mut self, container: &str, name: &str, data: &[u8]) -> Result<(), Error> {
+        validate_name(name)?;
+        self.container(container)?
+            .append(name, data)
+            .await
+            .map_err(to_error)
+    }
+
+    async fn get(&mut self, container: &str, name: &str) -> Result<Vec<u8>, Error> {
+        validate_name(name)?;
+        self.container(container)?
+            .get_range(name, 0, None)
+            .await
+            .map_err(to_error)
+    }
+
+    async fn get_range(
+        &mut self,
+        container: &str,
+        name: &str,
+        offset: u64,
+        len: u64,
+    ) -> Result<Vec<u8>, Error> {
+        validate_name(name)?;
+        self.container(container)?
+            .get_range(name, offset, Some(len))
+            .await
+            .map_err(to_error)
+    }
+
+    async fn list(
+        &mut self,
+        container: &str,
+        prefix: Option<&str>,
+    ) -> Result<Vec<ObjectMetadata>, Error> {
+        self.container(container)?
+            .list(prefix.unwrap_or_default())
+            .await
+            .map_err(to_error)
+    }
+
+    async fn delete(&mut self, container: &str, name: &str) -> Result<(), Error> {
+        validate_name(name)?;
+        self.container(container)?
+            .delete(name)
+            .await
+            .map_err(to_error)
+    }
+}
+
+/// Object names are `/`-separated relative paths which may not contain
+/// empty, `.` or `..` segments, nor backslashes, which separate segments of
+/// the paths of filesystem containers on Windows.
+fn validate_name(name: &str) -> Result<(), Error> {
+    if name.is_empty()
+        || name.starts_with('/')
+        || name.contains('\\')
+        || name
+            .split('/')
+            .any(|segment| segment.is_empty() || segment == "." || segment == "..")
+    {
+        return Err(Error::InvalidName(name.to_string()));
+    }
+    Ok(())
+}
+
+fn to_error(e: anyhow::Error) -> Error {
+    if e.downcast_ref::<NotFound>().is_some() {
+        Error::NotFound(e.to_string())
+    } else if e.is::<ServiceUnavailable>() {
+        Error::Unavailable(e.to_string())
+    } else if e.downcast_ref::<QuotaExceeded>().is_some() {
+        // Exceeding the quota is the guest's doing, not a host failure.
+        Error::Other(e.to_string())
+    } else {
+        tracing::log::error!("Blob store error: {:?}", e);
+        Error::Other(e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_name() {
+        for name in ["a", "a/b", "a/b.txt", "images/2022/cat.png"] {
+            assert!(validate_name(name).is_ok(), "{}", name);
+        }
+        for name in ["", "/a", "a//b", "a/./b", "../a", "a/..", "..\\a", "a\\b"] {
+            assert!(validate_name(name).is_err(), "{}", name);
+        }
+    }
+}
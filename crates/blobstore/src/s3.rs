@@ -0,0 +1,167 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use s3::{creds::Credentials, serde_types::Part, Bucket, Region};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+
+use crate::{spin_blobstore::ObjectMetadata, Container, NotFound};
+
+/// The size of the buffer between the download and the upload of an object
+/// being appended to.
+const APPEND_PIPE_SIZE: usize = 64 * 1024;
+
+/// The size of the parts of the upload of an object being appended to. S3
+/// requires every part but the last to be at least 5 MiB.
+const APPEND_PART_SIZE: usize = 8 * 1024 * 1024;
+
+const CONTENT_TYPE: &str = "application/octet-stream";
+
+/// A container backed by a bucket of an S3-compatible object storage service.
+pub(crate) struct S3Container {
+    bucket: Bucket,
+}
+
+impl S3Container {
+    pub(crate) fn new(
+        endpoint: String,
+        bucket: String,
+        region: String,
+        access_key: Option<String>,
+        secret_key: Option<String>,
+    ) -> Result<Self> {
+        let credentials = Credentials::new(
+            access_key.as_deref(),
+            secret_key.as_deref(),
+            None,
+            None,
+            None,
+        )
+        .context("Cannot load S3 credentials")?;
+        let bucket = Bucket::new(&bucket, Region::Custom { region, endpoint }, credentials)
+            .with_context(|| format!("Cannot open S3 bucket {}", bucket))?
+            .with_path_style();
+        Ok(Self { bucket })
+    }
+
+    fn check_status(status: u16, name: &str) -> Result<()> {
+        match status {
+            200..=299 => Ok(()),
+            404 => Err(NotFound.into()),
+            status => anyhow::bail!("S3 request for {} failed with status {}", name, status),
+        }
+    }
+
+    /// Uploads what is read as the parts of a multipart upload.
+    async fn upload_parts(
+        &self,
+        name: &str,
+        upload_id: &str,
+        reader: &mut (impl AsyncRead + Unpin),
+    ) -> Result<Vec<Part>> {
+        let mut parts = vec![];
+        loop {
+            let mut chunk = Vec::with_capacity(APPEND_PART_SIZE);
+            (&mut *reader)
+                .take(APPEND_PART_SIZE as u64)
+                .read_to_end(&mut chunk)
+                .await?;
+            let last = chunk.len() < APPEND_PART_SIZE;
+            let part_number = parts.len() as u32 + 1;
+            parts.push(
+                self.bucket
+                    .put_multipart_chunk(chunk, name, part_number, upload_id, CONTENT_TYPE)
+                    .await?,
+            );
+            if last {
+                return Ok(parts);
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Container for S3Container {
+    async fn put(&self, name: &str, data: &[u8]) -> Result<()> {
+        let res = self.bucket.put_object(name, data).await?;
+        Self::check_status(res.status_code(), name)
+    }
+
+    async fn append(&self, name: &str, data: &[u8]) -> Result<()> {
+        // S3 has no append operation: the existing object, then the data, are
+        // streamed into a multipart upload of the object, so that neither is
+        // held in memory whole. The object is only replaced once the upload
+        // completes, so a failed copy leaves it as it was.
+        let upload_id = self
+            .bucket
+            .initiate_multipart_upload(name, CONTENT_TYPE)
+            .await?
+            .upload_id;
+        let (mut writer, mut reader) = tokio::io::duplex(APPEND_PIPE_SIZE);
+        let copy = async move {
+            let status = self.bucket.get_object_stream(name, &mut writer).await?;
+            Self::check_status(status, name)?;
+            writer.write_all(data).await?;
+            writer.shutdown().await?;
+            Ok::<_, anyhow::Error>(())
+        };
+        let upload = self.upload_parts(name, &upload_id, &mut reader);
+        let res = match tokio::try_join!(copy, upload) {
+            Ok((_, parts)) => self
+                .bucket
+                .complete_multipart_upload(name, &upload_id, parts)
+                .await
+                .map_err(anyhow::Error::from)
+                .and_then(|res| Self::check_status(res.status_code(), name)),
+            Err(e) => Err(e),
+        };
+        if res.is_err() {
+            // Parts of incomplete uploads are kept, and billed, until aborted.
+            if let Err(e) = self.bucket.abort_upload(name, &upload_id).await {
+                tracing::log::warn!("Cannot abort the upload of S3 object {}: {}", name, e);
+            }
+        }
+        match res {
+            Err(e) if e.downcast_ref::<NotFound>().is_some() => self.put(name, data).await,
+            res => res,
+        }
+    }
+
+    async fn get_range(&self, name: &str, offset: u64, len: Option<u64>) -> Result<Vec<u8>> {
+        let res = match len {
+            Some(0) => return Ok(vec![]),
+            Some(len) => {
+                self.bucket
+                    .get_object_range(name, offset, Some(offset + len - 1))
+                    .await?
+            }
+            None if offset == 0 => self.bucket.get_object(name).await?,
+            None => self.bucket.get_object_range(name, offset, None).await?,
+        };
+        Self::check_status(res.status_code(), name)?;
+        Ok(res.bytes().to_vec())
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<ObjectMetadata>> {
+        let results = self.bucket.list(prefix.to_string(), None).await?;
+        Ok(results
+            .into_iter()
+            .flat_map(|page| page.contents)
+            .map(|object| ObjectMetadata {
+                name: object.key,
+                size: object.size,
+            })
+            .collect())
+    }
+
+    async fn delete(&self, name: &str) -> Result<()> {
+        let res = self.bucket.delete_object(name).await?;
+        Self::check_status(res.status_code(), name)
+    }
+
+    async fn check(&self) -> Result<()> {
+        let (_, status) = self
+            .bucket
+            .list_page(String::new(), None, None, None, Some(1))
+            .await?;
+        Self::check_status(status, &self.bucket.name)
+    }
+}
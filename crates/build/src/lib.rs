@@ -3,73 +3,313 @@
 //! A library for building Spin components.
 
 use anyhow::{bail, Context, Result};
+use futures::stream::{FuturesUnordered, StreamExt};
 use path_absolutize::Absolutize;
-use spin_loader::local::config::{RawAppManifest, RawComponentManifest};
-use std::path::{Path, PathBuf};
-use subprocess::{Exec, Redirection};
-use tracing::log;
+use spin_loader::local::config::RawAppManifest;
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    process::Stdio,
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, BufReader},
+    process::Command,
+};
 
-/// If present, run the build command of each component.
-pub async fn build(app: RawAppManifest, src: &Path) -> Result<()> {
+/// Options for building the components of an application.
+#[derive(Clone, Debug)]
+pub struct BuildOptions {
+    /// The largest number of build commands run at once.
+    pub jobs: usize,
+    /// The components to build. If not set, every component is built. The
+    /// components they depend on are assumed to be up to date.
+    pub components: Option<HashSet<String>>,
+    /// Whether to also build the components depending, directly or
+    /// indirectly, on those in `components`.
+    pub dependents: bool,
+}
+
+impl Default for BuildOptions {
+    fn default() -> Self {
+        Self {
+            jobs: default_jobs(),
+            components: None,
+            dependents: false,
+        }
+    }
+}
+
+/// The number of build commands run at once by default: the parallelism
+/// available to the process.
+pub fn default_jobs() -> usize {
+    std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+}
+
+/// If present, run the build command of each component, running up to
+/// `options.jobs` at once. A component is built once the components it
+/// depends on have been built.
+pub async fn build(app: RawAppManifest, src: &Path, options: &BuildOptions) -> Result<()> {
+    if options.jobs == 0 {
+        bail!("The number of build jobs must be greater than zero.");
+    }
     let src = src.absolutize()?;
-    let results = futures::future::join_all(
-        app.components
-            .into_iter()
-            .map(|c| build_component(c, &src))
-            .collect::<Vec<_>>(),
-    )
-    .await;
-
-    for r in results {
-        if r.is_err() {
-            bail!(r.err().unwrap());
+    let mut pending = plan(&app, options)?;
+    // Output is only prefixed when builds may be interleaved.
+    let prefix = pending.len() > 1 && options.jobs > 1;
+
+    let mut built = HashSet::new();
+    let mut running = FuturesUnordered::new();
+    let mut failed = vec![];
+    loop {
+        // Once a build fails, those running are left to complete, but no
+        // other build starts.
+        while failed.is_empty() && running.len() < options.jobs {
+            let ready = pending
+                .iter()
+                .position(|b: &Build| b.depends_on.iter().all(|d| built.contains(d)));
+            match ready {
+                Some(i) => running.push(build_component(pending.remove(i), &src, prefix)),
+                None => break,
+            }
+        }
+        match running.next().await {
+            Some((id, Ok(()))) => {
+                built.insert(id);
+            }
+            Some((id, Err(e))) => {
+                eprintln!("{:#}", e);
+                failed.push(id);
+            }
+            None => break,
         }
     }
 
+    if !failed.is_empty() {
+        failed.sort();
+        bail!(
+            "Build failed for component(s) {}; {} other component(s) were not built.",
+            failed.join(", "),
+            pending.len()
+        );
+    }
     println!("Successfully ran the build command for the Spin components.");
     Ok(())
 }
 
-/// Run the build command of the component.
-async fn build_component(raw: RawComponentManifest, src: impl AsRef<Path>) -> Result<()> {
-    match raw.build {
-        Some(b) => {
-            println!(
-                "Executing the build command for component {}: {}",
-                raw.id, b.command
-            );
-            let workdir = construct_workdir(src.as_ref(), b.workdir.as_ref())?;
-            if b.workdir.is_some() {
-                println!("Working directory: {:?}", workdir);
-            }
+/// The build commands of a component.
+#[derive(Debug)]
+struct Build {
+    id: String,
+    /// The commands to run before `command`.
+    pre: Vec<String>,
+    command: String,
+    /// The commands to run after `command`.
+    post: Vec<String>,
+    workdir: Option<PathBuf>,
+    /// The components to build first.
+    depends_on: Vec<String>,
+}
 
-            let res = Exec::shell(&b.command)
-                .cwd(workdir)
-                .stdout(Redirection::Pipe)
-                .capture()
-                .with_context(|| {
-                    format!(
-                        "Cannot spawn build process '{:?}' for component {}.",
-                        &b.command, raw.id
-                    )
-                })?;
-
-            if !res.stdout_str().is_empty() {
-                log::info!("Standard output for component {}", raw.id);
-                print!("{}", res.stdout_str());
-            }
+/// Returns the builds to run: those of the selected components, and of the
+/// components depending on them if requested, or of all components.
+fn plan(app: &RawAppManifest, options: &BuildOptions) -> Result<Vec<Build>> {
+    let ids: HashSet<&str> = app.components.iter().map(|c| c.id.as_str()).collect();
+    if let Some(selected) = &options.components {
+        let mut unknown: Vec<&str> = selected
+            .iter()
+            .map(String::as_str)
+            .filter(|id| !ids.contains(id))
+            .collect();
+        if !unknown.is_empty() {
+            unknown.sort_unstable();
+            bail!("Unknown component(s) {}.", unknown.join(", "));
+        }
+    }
+    let mut builds: Vec<Build> = vec![];
+    for c in &app.components {
+        let b = match &c.build {
+            Some(b) => b,
+            None => continue,
+        };
+        if let Some(d) = b.depends_on.iter().find(|d| !ids.contains(d.as_str())) {
+            bail!("Component {} depends on unknown component {}.", c.id, d);
+        }
+        builds.push(Build {
+            id: c.id.clone(),
+            pre: b.pre.clone(),
+            command: b.command.clone(),
+            post: b.post.clone(),
+            workdir: b.workdir.clone(),
+            depends_on: b.depends_on.clone(),
+        });
+    }
 
-            if !res.success() {
-                bail!(
-                    "Build command for component {} failed with status {:?}.",
-                    raw.id,
-                    res.exit_status
-                );
+    // Components without a build command have nothing to wait for.
+    let buildable: HashSet<String> = builds.iter().map(|b| b.id.clone()).collect();
+    for b in &mut builds {
+        b.depends_on.retain(|d| buildable.contains(d));
+    }
+    check_cycles(&builds)?;
+
+    if let Some(selected) = &options.components {
+        let mut included: HashSet<String> = selected.clone();
+        // Add the components depending on those included until none is left.
+        while options.dependents {
+            let dependents: Vec<String> = builds
+                .iter()
+                .filter(|b| !included.contains(&b.id))
+                .filter(|b| b.depends_on.iter().any(|d| included.contains(d)))
+                .map(|b| b.id.clone())
+                .collect();
+            if dependents.is_empty() {
+                break;
             }
+            included.extend(dependents);
+        }
+        builds.retain(|b| included.contains(&b.id));
+        for b in &mut builds {
+            b.depends_on.retain(|d| included.contains(d));
+        }
+    }
+    Ok(builds)
+}
 
-            Ok(())
+/// Fails if components depend on each other, directly or indirectly.
+fn check_cycles(builds: &[Build]) -> Result<()> {
+    let mut remaining: HashMap<&str, Vec<&str>> = builds
+        .iter()
+        .map(|b| {
+            let deps = b.depends_on.iter().map(String::as_str).collect();
+            (b.id.as_str(), deps)
+        })
+        .collect();
+    loop {
+        let done: Vec<&str> = remaining
+            .iter()
+            .filter(|(_, deps)| deps.iter().all(|d| !remaining.contains_key(d)))
+            .map(|(id, _)| *id)
+            .collect();
+        if done.is_empty() {
+            break;
+        }
+        for id in done {
+            remaining.remove(id);
+        }
+    }
+    if !remaining.is_empty() {
+        let mut cycle: Vec<&str> = remaining.into_keys().collect();
+        cycle.sort_unstable();
+        bail!(
+            "Components {} cannot be built, as their build dependencies form a cycle.",
+            cycle.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// Run the build command of the component, returning its ID.
+async fn build_component(build: Build, src: &Path, prefix: bool) -> (String, Result<()>) {
+    let res = run_build(&build, src, prefix).await;
+    (build.id, res)
+}
+
+async fn run_build(build: &Build, src: &Path, prefix: bool) -> Result<()> {
+    let workdir = construct_workdir(src, build.workdir.as_ref())?;
+    let label = match prefix {
+        true => format!("[{}] ", build.id),
+        false => String::new(),
+    };
+    let steps = build
+        .pre
+        .iter()
+        .map(|c| ("pre-build", c))
+        .chain(std::iter::once(("build", &build.command)))
+        .chain(build.post.iter().map(|c| ("post-build", c)));
+    for (kind, command) in steps {
+        println!(
+            "Executing the {} command for component {}: {}",
+            kind, build.id, command
+        );
+        if build.workdir.is_some() {
+            println!("Working directory: {:?}", workdir);
+        }
+        run_command(&build.id, kind, command, &workdir, &label).await?;
+    }
+    Ok(())
+}
+
+/// Runs a build command of a component in the working directory, printing
+/// its output after the given label.
+async fn run_command(
+    id: &str,
+    kind: &str,
+    command: &str,
+    workdir: &Path,
+    label: &str,
+) -> Result<()> {
+    let mut cmd = shell(command);
+    cmd.current_dir(workdir)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    if spin_loader::offline::is_offline() {
+        // Keep toolchains that support it from fetching dependencies.
+        cmd.env("CARGO_NET_OFFLINE", "true")
+            .env("npm_config_offline", "true");
+    }
+    let mut child = cmd.spawn().with_context(|| {
+        format!(
+            "Cannot spawn {} process '{:?}' for component {}.",
+            kind, command, id
+        )
+    })?;
+
+    let stdout = child.stdout.take().context("Build process has no stdout")?;
+    let stderr = child.stderr.take().context("Build process has no stderr")?;
+    let (status, _, _) = tokio::join!(
+        child.wait(),
+        forward_lines(stdout, label, false),
+        forward_lines(stderr, label, true)
+    );
+    let status = status
+        .with_context(|| format!("Cannot wait for {} process for component {}.", kind, id))?;
+
+    if !status.success() {
+        bail!(
+            "The {} command for component {} failed with status {:?}: {}",
+            kind,
+            id,
+            status.code(),
+            command
+        );
+    }
+    Ok(())
+}
+
+/// Runs a command line in the platform's shell.
+fn shell(command: &str) -> Command {
+    let (shell, flag) = if cfg!(windows) {
+        ("cmd", "/C")
+    } else {
+        ("sh", "-c")
+    };
+    let mut cmd = Command::new(shell);
+    cmd.arg(flag).arg(command);
+    cmd
+}
+
+/// Prints the lines of the output of a build as they are written, after the
+/// given label.
+async fn forward_lines(output: impl AsyncRead + Unpin, label: &str, stderr: bool) {
+    let mut lines = BufReader::new(output).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        if stderr {
+            eprintln!("{}{}", label, line);
+        } else {
+            println!("{}{}", label, line);
         }
-        _ => Ok(()),
     }
 }
 
@@ -94,3 +334,136 @@ fn construct_workdir(src: impl AsRef<Path>, workdir: Option<impl AsRef<Path>>) -
 
     Ok(cwd)
 }
+
+#[cfg(test)]
+mod tests {
+    use spin_loader::local::config::RawAppManifestAnyVersion;
+
+    use super::*;
+
+    fn app(components: &str) -> RawAppManifest {
+        let manifest = format!(
+            r#"
+spin_version = "1"
+name = "builds"
+version = "1.0.0"
+trigger = {{ type = "http", base = "/" }}
+{}"#,
+            components
+        );
+        let RawAppManifestAnyVersion::V1(app) = toml::from_str(&manifest).unwrap();
+        app
+    }
+
+    fn component(id: &str, depends_on: &[&str]) -> String {
+        format!(
+            r#"
+[[component]]
+id = "{}"
+source = "{}.wasm"
+[component.trigger]
+route = "/{}"
+[component.build]
+command = "make"
+depends_on = {:?}
+"#,
+            id, id, id, depends_on
+        )
+    }
+
+    fn ids(builds: &[Build]) -> Vec<&str> {
+        let mut ids: Vec<&str> = builds.iter().map(|b| b.id.as_str()).collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    #[test]
+    fn test_plan_selects_dependents() -> Result<()> {
+        let app = app(&[
+            component("lib", &[]),
+            component("api", &["lib"]),
+            component("web", &["api"]),
+            component("admin", &[]),
+        ]
+        .concat());
+        assert_eq!(
+            ids(&plan(&app, &BuildOptions::default())?),
+            ["admin", "api", "lib", "web"]
+        );
+
+        let mut options = BuildOptions {
+            components: Some(["api".to_owned()].into()),
+            ..Default::default()
+        };
+        assert_eq!(ids(&plan(&app, &options)?), ["api"]);
+
+        options.dependents = true;
+        let builds = plan(&app, &options)?;
+        assert_eq!(ids(&builds), ["api", "web"]);
+        // The components api depends on are not rebuilt.
+        assert!(builds
+            .iter()
+            .find(|b| b.id == "api")
+            .unwrap()
+            .depends_on
+            .is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_plan_rejects_invalid_dependencies() {
+        let all = BuildOptions::default();
+        let unknown = app(&component("api", &["lib"]));
+        assert!(plan(&unknown, &all).is_err());
+
+        let selected_unknown = BuildOptions {
+            components: Some(["web".to_owned()].into()),
+            ..Default::default()
+        };
+        let err = plan(&app(&component("api", &[])), &selected_unknown).unwrap_err();
+        assert!(err.to_string().contains("web"), "{}", err);
+
+        let cycle = app(&[
+            component("a", &["c"]),
+            component("b", &["a"]),
+            component("c", &["b"]),
+            component("d", &["a"]),
+        ]
+        .concat());
+        let err = plan(&cycle, &all).unwrap_err().to_string();
+        assert!(err.contains("a, b, c,"), "{}", err);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_run_build_runs_hooks_in_order() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        std::fs::create_dir(dir.path().join("api"))?;
+        let build = Build {
+            id: "api".to_owned(),
+            pre: vec!["echo pre >> log".to_owned()],
+            command: "echo build >> log".to_owned(),
+            post: vec![
+                "echo post1 >> log".to_owned(),
+                "echo post2 >> log".to_owned(),
+            ],
+            workdir: Some("api".into()),
+            depends_on: vec![],
+        };
+        run_build(&build, &dir.path().join("spin.toml"), false).await?;
+        let log = std::fs::read_to_string(dir.path().join("api").join("log"))?;
+        assert_eq!(log, "pre\nbuild\npost1\npost2\n");
+
+        // A failing hook stops the build.
+        let failing = Build {
+            pre: vec!["false".to_owned()],
+            ..build
+        };
+        assert!(run_build(&failing, &dir.path().join("spin.toml"), false)
+            .await
+            .is_err());
+        let log = std::fs::read_to_string(dir.path().join("api").join("log"))?;
+        assert_eq!(log, "pre\nbuild\npost1\npost2\n");
+        Ok(())
+    }
+}
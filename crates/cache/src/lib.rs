@@ -0,0 +1,105 @@
+//! An in-memory cache host interface for Spin components.
+
+mod memory;
+
+use std::{sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use serde::Deserialize;
+use spin_cache::*;
+use spin_engine::{
+    host_component::{HostComponent, HostComponentsStateHandle},
+    RuntimeContext,
+};
+use spin_manifest::CoreComponent;
+use wit_bindgen_wasmtime::wasmtime::Linker;
+
+pub use memory::{CacheValue, MemoryCache};
+pub use spin_cache::add_to_linker;
+
+wit_bindgen_wasmtime::export!({
+    paths: ["../../wit/ephemeral/spin-cache.wit"],
+    async: *,
+});
+
+/// Runtime configuration for the cache.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "snake_case")]
+pub struct CacheConfig {
+    /// The largest number of entries held, beyond which the least recently
+    /// used are evicted.
+    pub max_entries: usize,
+    /// The largest total size of the keys and values held, in bytes, beyond
+    /// which the least recently used entries are evicted.
+    pub max_bytes: usize,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            max_entries: 10_000,
+            max_bytes: 64 * 1024 * 1024,
+        }
+    }
+}
+
+/// The cache host component. All the components of an application share
+/// the same cache, which lives as long as the process.
+#[derive(Clone)]
+pub struct CacheComponent {
+    cache: Arc<MemoryCache>,
+}
+
+impl CacheComponent {
+    /// Creates a cache host component with an empty cache.
+    pub fn new(config: &CacheConfig) -> Self {
+        Self {
+            cache: Arc::new(MemoryCache::new(config.clone())),
+        }
+    }
+}
+
+impl HostComponent for CacheComponent {
+    type State = Cache;
+
+    fn add_to_linker<T: Send>(
+        linker: &mut Linker<RuntimeContext<T>>,
+        state_handle: HostComponentsStateHandle<Self::State>,
+    ) -> anyhow::Result<()> {
+        add_to_linker(linker, move |ctx| state_handle.get_mut(ctx))
+    }
+
+    fn build_state(&self, _component: &CoreComponent) -> anyhow::Result<Self::State> {
+        Ok(Cache {
+            cache: self.cache.clone(),
+        })
+    }
+}
+
+/// Per-component cache state.
+pub struct Cache {
+    cache: Arc<MemoryCache>,
+}
+
+#[async_trait]
+impl spin_cache::SpinCache for Cache {
+    async fn get(&mut self, key: &str) -> Result<Option<Vec<u8>>, Error> {
+        Ok(self.cache.get(key))
+    }
+
+    async fn set(&mut self, key: &str, value: &[u8], ttl_ms: Option<u64>) -> Result<(), Error> {
+        if self
+            .cache
+            .set(key, value.to_vec(), ttl_ms.map(Duration::from_millis))
+        {
+            Ok(())
+        } else {
+            Err(Error::TooLarge)
+        }
+    }
+
+    async fn delete(&mut self, key: &str) -> Result<(), Error> {
+        self.cache.delete(key);
+        Ok(())
+    }
+}
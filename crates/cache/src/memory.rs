@@ -0,0 +1,194 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::CacheConfig;
+
+/// A value held in a `MemoryCache`.
+pub trait CacheValue: Clone {
+    /// The size of the value, in bytes, counted against the bounds of the
+    /// cache.
+    fn size(&self) -> usize;
+}
+
+impl CacheValue for Vec<u8> {
+    fn size(&self) -> usize {
+        self.len()
+    }
+}
+
+/// A bounded cache local to the current process, evicting the least recently
+/// used entries when full.
+pub struct MemoryCache<V = Vec<u8>> {
+    config: CacheConfig,
+    state: Mutex<State<V>>,
+}
+
+struct State<V> {
+    entries: HashMap<String, Entry<V>>,
+    /// Keys by the tick they were last used at, least recent first.
+    recency: BTreeMap<u64, String>,
+    tick: u64,
+    bytes: usize,
+}
+
+impl<V> Default for State<V> {
+    fn default() -> Self {
+        Self {
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+            tick: 0,
+            bytes: 0,
+        }
+    }
+}
+
+struct Entry<V> {
+    value: V,
+    expiry: Option<Instant>,
+    used: u64,
+}
+
+impl<V: CacheValue> MemoryCache<V> {
+    /// Creates an empty cache.
+    pub fn new(config: CacheConfig) -> Self {
+        Self {
+            config,
+            state: Mutex::default(),
+        }
+    }
+
+    /// The value cached for a key, if it has not expired or been evicted.
+    pub fn get(&self, key: &str) -> Option<V> {
+        self.get_at(key, Instant::now())
+    }
+
+    /// Caches a value, evicting the least recently used entries to make room
+    /// for it. Returns false if the entry is larger than the whole cache.
+    pub fn set(&self, key: &str, value: V, ttl: Option<Duration>) -> bool {
+        self.set_at(key, value, ttl, Instant::now())
+    }
+
+    /// Removes the value cached for a key, if any.
+    pub fn delete(&self, key: &str) {
+        self.state.lock().unwrap().remove(key);
+    }
+
+    fn get_at(&self, key: &str, now: Instant) -> Option<V> {
+        let mut state = self.state.lock().unwrap();
+        let expired = match state.entries.get(key) {
+            None => return None,
+            Some(entry) => entry.expiry.map_or(false, |expiry| expiry <= now),
+        };
+        if expired {
+            state.remove(key);
+            return None;
+        }
+        let tick = state.next_tick();
+        let entry = state.entries.get_mut(key)?;
+        let previous = std::mem::replace(&mut entry.used, tick);
+        let value = entry.value.clone();
+        state.recency.remove(&previous);
+        state.recency.insert(tick, key.to_owned());
+        Some(value)
+    }
+
+    fn set_at(&self, key: &str, value: V, ttl: Option<Duration>, now: Instant) -> bool {
+        let size = key.len() + value.size();
+        if size > self.config.max_bytes || self.config.max_entries == 0 {
+            return false;
+        }
+        let mut state = self.state.lock().unwrap();
+        state.remove(key);
+        while state.entries.len() >= self.config.max_entries
+            || state.bytes + size > self.config.max_bytes
+        {
+            let oldest = match state.recency.values().next() {
+                Some(key) => key.clone(),
+                None => break,
+            };
+            state.remove(&oldest);
+        }
+        let tick = state.next_tick();
+        state.bytes += size;
+        state.recency.insert(tick, key.to_owned());
+        state.entries.insert(
+            key.to_owned(),
+            Entry {
+                value,
+                expiry: ttl.map(|ttl| now + ttl),
+                used: tick,
+            },
+        );
+        true
+    }
+}
+
+impl<V: CacheValue> State<V> {
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.recency.remove(&entry.used);
+            self.bytes -= key.len() + entry.value.size();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_cache(max_entries: usize, max_bytes: usize) -> MemoryCache {
+        MemoryCache::new(CacheConfig {
+            max_entries,
+            max_bytes,
+        })
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let cache = new_cache(2, 1024);
+        assert!(cache.set("a", b"1".to_vec(), None));
+        assert!(cache.set("b", b"2".to_vec(), None));
+        assert_eq!(cache.get("a"), Some(b"1".to_vec()));
+        assert!(cache.set("c", b"3".to_vec(), None));
+        assert_eq!(cache.get("b"), None);
+        assert_eq!(cache.get("a"), Some(b"1".to_vec()));
+        assert_eq!(cache.get("c"), Some(b"3".to_vec()));
+
+        let cache = new_cache(10, 8);
+        assert!(cache.set("a", b"123".to_vec(), None));
+        assert!(cache.set("b", b"456".to_vec(), None));
+        assert!(!cache.set("c", b"123456789".to_vec(), None));
+        assert!(cache.set("a", b"1".to_vec(), None));
+        assert!(cache.set("c", b"12".to_vec(), None));
+        assert_eq!(cache.get("b"), None);
+        assert_eq!(cache.get("a"), Some(b"1".to_vec()));
+    }
+
+    #[test]
+    fn test_expires_entries() {
+        let cache = new_cache(10, 1024);
+        let now = Instant::now();
+        assert!(cache.set_at("a", b"1".to_vec(), Some(Duration::from_secs(5)), now));
+        assert!(cache.set_at("b", b"2".to_vec(), None, now));
+        assert_eq!(
+            cache.get_at("a", now + Duration::from_secs(4)),
+            Some(b"1".to_vec())
+        );
+        assert_eq!(cache.get_at("a", now + Duration::from_secs(5)), None);
+        assert_eq!(
+            cache.get_at("b", now + Duration::from_secs(60)),
+            Some(b"2".to_vec())
+        );
+        cache.delete("b");
+        assert_eq!(cache.get("b"), None);
+        assert_eq!(cache.state.lock().unwrap().bytes, 0);
+    }
+}
@@ -1,9 +1,14 @@
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc};
+
+use async_trait::async_trait;
 
 use crate::{Error, Key, Resolver, TreePath};
 
 mod wit {
-    wit_bindgen_wasmtime::export!("../../wit/ephemeral/spin-config.wit");
+    wit_bindgen_wasmtime::export!({
+        paths: ["../../wit/ephemeral/spin-config.wit"],
+        async: *,
+    });
 }
 pub use wit::spin_config::add_to_linker;
 
@@ -11,6 +16,8 @@ pub use wit::spin_config::add_to_linker;
 pub struct ComponentConfig {
     component_root: TreePath,
     resolver: Arc<Resolver>,
+    /// Values taken from the request being handled, by key.
+    request_values: HashMap<String, String>,
 }
 
 impl ComponentConfig {
@@ -22,15 +29,31 @@ impl ComponentConfig {
         Ok(Self {
             component_root,
             resolver,
+            request_values: HashMap::new(),
         })
     }
+
+    /// Sets values taken from the request being handled, which take
+    /// precedence over the configuration of the component.
+    pub fn set_request_values(&mut self, values: impl IntoIterator<Item = (String, String)>) {
+        self.request_values.extend(values);
+    }
 }
 
+#[async_trait]
 impl wit::spin_config::SpinConfig for ComponentConfig {
-    fn get_config(&mut self, key: &str) -> Result<String, wit::spin_config::Error> {
+    async fn get_config(&mut self, key: &str) -> Result<String, wit::spin_config::Error> {
         let key = Key::new(key)?;
+        if let Some(value) = self.request_values.get(key.as_ref()) {
+            return Ok(value.clone());
+        }
         let path = &self.component_root + key;
-        Ok(self.resolver.resolve(&path)?)
+        // Providers such as Vault block on their requests.
+        let resolver = self.resolver.clone();
+        let value = tokio::task::spawn_blocking(move || resolver.resolve(&path))
+            .await
+            .map_err(|e| wit::spin_config::Error::Other(e.to_string()))??;
+        Ok(value)
     }
 }
 
@@ -6,7 +6,7 @@ mod tree;
 
 use std::fmt::Debug;
 
-pub use provider::Provider;
+pub use provider::{Provider, ProviderConfig};
 pub use tree::{Tree, TreePath};
 
 use template::{Part, Template};
@@ -57,16 +57,91 @@ impl Resolver {
         })
     }
 
+    /// The Tree the Resolver resolves values in.
+    pub fn tree(&self) -> &Tree {
+        &self.tree
+    }
+
     /// Adds a config Provider to the Resolver.
     pub fn add_provider(&mut self, provider: impl Provider + 'static) {
         self.providers.push(Box::new(provider));
     }
 
+    /// Adds the configured Providers to the Resolver, in order.
+    ///
+    /// Vault providers read secrets with blocking requests, so values must
+    /// not be resolved from an async context until they have been validated.
+    pub fn add_providers<'a>(&mut self, configs: impl IntoIterator<Item = &'a ProviderConfig>) {
+        for config in configs {
+            match config {
+                ProviderConfig::Env { prefix } => {
+                    self.add_provider(provider::env::EnvProvider::new(prefix))
+                }
+                ProviderConfig::File { dir } => {
+                    self.add_provider(provider::file::FileProvider::new(dir))
+                }
+                ProviderConfig::Vault(config) => {
+                    self.add_provider(provider::vault::VaultProvider::new(config.clone()))
+                }
+            }
+        }
+    }
+
+    /// Resolves every value in the Tree, so that missing values are reported
+    /// before components read them. Values are checked top-level first, as
+    /// a missing top-level value would fail every value referencing it.
+    pub fn validate(&self) -> Result<()> {
+        let (top_level, nested): (Vec<_>, Vec<_>) =
+            self.tree.paths().partition(|path| path.size() == 1);
+        for paths in [top_level, nested] {
+            let failures = paths
+                .into_iter()
+                .filter_map(|path| self.resolve(path).err().map(|e| format!("{}: {}", path, e)))
+                .collect::<Vec<_>>();
+            if !failures.is_empty() {
+                return Err(Error::InvalidPath(failures.join("; ")));
+            }
+        }
+        Ok(())
+    }
+
     /// Resolves a config value for the given path.
     pub fn resolve(&self, path: &TreePath) -> Result<String> {
         self.resolve_path(path, 0)
     }
 
+    /// Whether the value at the given path is a secret, or is made from one.
+    pub fn is_secret(&self, path: &TreePath) -> bool {
+        self.is_secret_path(path, 0)
+    }
+
+    fn is_secret_path(&self, path: &TreePath, depth: usize) -> bool {
+        if depth > Self::RECURSION_LIMIT {
+            // Values that cannot be resolved are not shown either.
+            return true;
+        }
+        let slot = match self.tree.get(path) {
+            Ok(slot) => slot,
+            Err(_) => return false,
+        };
+        slot.secret
+            || slot
+                .default
+                .iter()
+                .flat_map(|t| t.parts())
+                .any(|part| match part {
+                    Part::Lit(_) => false,
+                    Part::Expr(expr) => {
+                        let expr_path = if expr.starts_with('.') {
+                            path.resolve_relative(expr)
+                        } else {
+                            TreePath::new(expr.to_string())
+                        };
+                        expr_path.map_or(false, |p| self.is_secret_path(&p, depth + 1))
+                    }
+                })
+    }
+
     // Simple protection against infinite recursion
     const RECURSION_LIMIT: usize = 100;
 
@@ -243,6 +318,59 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn resolver_validate() {
+        let tree: Tree = toml! {
+            api_host = { default = "api.example.com" }
+            api_key = { required = true, secret = true }
+        }
+        .try_into()
+        .unwrap();
+        let mut resolver = Resolver::new(tree).unwrap();
+        let err = resolver.validate().unwrap_err().to_string();
+        assert!(err.contains("api_key"), "{}", err);
+        assert!(!err.contains("api_host"), "{}", err);
+
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("api_key"), "1234").unwrap();
+        resolver.add_providers(&[ProviderConfig::File {
+            dir: dir.path().to_owned(),
+        }]);
+        resolver.validate().unwrap();
+        let path = TreePath::new("api_key").unwrap();
+        assert_eq!(resolver.resolve(&path).unwrap(), "1234");
+    }
+
+    #[test]
+    fn resolver_is_secret() {
+        let mut tree: Tree = toml! {
+            api_host = { default = "api.example.com" }
+            api_key = { default = "1234", secret = true }
+        }
+        .try_into()
+        .unwrap();
+        tree.merge_defaults(
+            &TreePath::new("child").unwrap(),
+            toml! {
+                url = "https://{{ api_host }}"
+                auth = "Bearer {{ api_key }}"
+            }
+            .try_into::<HashMap<String, String>>()
+            .unwrap(),
+        )
+        .unwrap();
+        let resolver = Resolver::new(tree).unwrap();
+        for (path, secret) in [
+            ("api_host", false),
+            ("api_key", true),
+            ("child.url", false),
+            ("child.auth", true),
+        ] {
+            let path = TreePath::new(path).unwrap();
+            assert_eq!(resolver.is_secret(&path), secret, "{}", path);
+        }
+    }
+
     #[test]
     fn keys_good() {
         for key in ["a", "abc", "a1b2c3", "a_1", "a_1_b_3"] {
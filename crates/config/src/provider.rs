@@ -1,12 +1,50 @@
-use std::fmt::Debug;
+use std::{fmt::Debug, path::PathBuf};
+
+use serde::Deserialize;
 
 use crate::Key;
 
 /// Environment variable based provider.
 pub mod env;
+/// File based provider.
+pub mod file;
+/// HashiCorp Vault based provider.
+pub mod vault;
 
 /// A config provider.
 pub trait Provider: Debug + Send + Sync {
     /// Returns the value at the given config path, if it exists.
     fn get(&self, key: &Key) -> anyhow::Result<Option<String>>;
 }
+
+/// Runtime configuration for a config provider.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields, rename_all = "snake_case", tag = "type")]
+pub enum ProviderConfig {
+    /// Values from environment variables named after the upper-cased key,
+    /// with the prefix and an underscore prepended.
+    Env {
+        /// The prefix of the environment variables.
+        #[serde(default = "default_env_prefix")]
+        prefix: String,
+    },
+    /// Values from files named after the key in a directory.
+    File {
+        /// The directory holding the files.
+        dir: PathBuf,
+    },
+    /// Values from the fields of secrets in HashiCorp Vault.
+    Vault(vault::VaultConfig),
+}
+
+fn default_env_prefix() -> String {
+    env::DEFAULT_PREFIX.to_string()
+}
+
+impl Default for ProviderConfig {
+    fn default() -> Self {
+        Self::Env {
+            prefix: default_env_prefix(),
+        }
+    }
+}
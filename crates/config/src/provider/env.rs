@@ -2,7 +2,7 @@ use anyhow::Context;
 
 use crate::{Key, Provider};
 
-const DEFAULT_PREFIX: &str = "SPIN_APP";
+pub(crate) const DEFAULT_PREFIX: &str = "SPIN_APP";
 
 /// A config Provider that uses environment variables.
 #[derive(Debug)]
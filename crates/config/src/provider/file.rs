@@ -0,0 +1,48 @@
+use std::path::PathBuf;
+
+use anyhow::Context;
+
+use crate::{Key, Provider};
+
+/// A config Provider that reads values from files named after their keys in
+/// a directory, such as secrets mounted by a container orchestrator.
+#[derive(Debug)]
+pub struct FileProvider {
+    dir: PathBuf,
+}
+
+impl FileProvider {
+    /// Creates a new FileProvider reading files in the given directory.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+}
+
+impl Provider for FileProvider {
+    fn get(&self, key: &Key) -> anyhow::Result<Option<String>> {
+        let path = self.dir.join(key.as_ref());
+        match std::fs::read_to_string(&path) {
+            // Files written by editors and `echo` end with a newline.
+            Ok(value) => Ok(Some(value.trim_end_matches(['\n', '\r']).to_string())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).with_context(|| format!("failed to read {}", path.display())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn provider_get() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("db_password"), "sesame\n").unwrap();
+        let provider = FileProvider::new(dir.path());
+        assert_eq!(
+            provider.get(&Key::new("db_password").unwrap()).unwrap(),
+            Some("sesame".to_string())
+        );
+        assert_eq!(provider.get(&Key::new("api_key").unwrap()).unwrap(), None);
+    }
+}
@@ -0,0 +1,318 @@
+use std::{
+    collections::HashMap,
+    sync::{Mutex, MutexGuard},
+};
+
+use anyhow::{anyhow, bail, Context};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use crate::{Key, Provider};
+
+/// The environment variable holding the token of the `token` auth method.
+pub const VAULT_TOKEN_ENV: &str = "VAULT_TOKEN";
+
+const DEFAULT_MOUNT: &str = "secret";
+
+/// How a VaultProvider logs in to Vault.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields, rename_all = "snake_case", tag = "method")]
+pub enum VaultAuth {
+    /// A token, read from the VAULT_TOKEN environment variable if not set.
+    Token {
+        /// The name of the environment variable holding the token.
+        #[serde(default = "default_token_env")]
+        token_env: String,
+    },
+    /// The AppRole auth method, with the secret ID read from an environment
+    /// variable.
+    Approle {
+        /// The role ID.
+        role_id: String,
+        /// The name of the environment variable holding the secret ID.
+        secret_id_env: String,
+        /// The path the AppRole auth method is mounted at.
+        #[serde(default = "default_approle_mount")]
+        mount: String,
+    },
+}
+
+fn default_token_env() -> String {
+    VAULT_TOKEN_ENV.to_string()
+}
+
+fn default_approle_mount() -> String {
+    "approle".to_string()
+}
+
+impl Default for VaultAuth {
+    fn default() -> Self {
+        Self::Token {
+            token_env: default_token_env(),
+        }
+    }
+}
+
+/// Runtime configuration for a VaultProvider.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct VaultConfig {
+    /// The address of the Vault server, such as `https://vault.example.com:8200`.
+    pub address: String,
+    /// The Vault Enterprise namespace, if any.
+    #[serde(default)]
+    pub namespace: Option<String>,
+    /// The path the KV secrets engine is mounted at.
+    #[serde(default = "default_mount")]
+    pub mount: String,
+    /// The version of the KV secrets engine.
+    #[serde(default = "default_kv_version")]
+    pub kv_version: u8,
+    /// The secret whose fields are the values of the keys of the same name.
+    #[serde(default)]
+    pub path: Option<String>,
+    /// Keys read from another secret or field, as `<path>#<field>`.
+    #[serde(default)]
+    pub keys: HashMap<String, String>,
+    /// How to log in to Vault.
+    #[serde(default)]
+    pub auth: VaultAuth,
+}
+
+fn default_mount() -> String {
+    DEFAULT_MOUNT.to_string()
+}
+
+fn default_kv_version() -> u8 {
+    2
+}
+
+/// A config Provider that reads values from the fields of secrets in a
+/// HashiCorp Vault KV secrets engine.
+///
+/// Secrets are read with blocking requests the first time one of their keys
+/// is resolved, and kept for the lifetime of the provider, so that values are
+/// fetched once when the application's variables are validated at startup.
+#[derive(Debug)]
+pub struct VaultProvider {
+    config: VaultConfig,
+    client: reqwest::blocking::Client,
+    token: Mutex<Option<String>>,
+    /// The fields of the secrets read so far, or none for missing secrets.
+    secrets: Mutex<HashMap<String, Option<Map<String, Value>>>>,
+}
+
+impl VaultProvider {
+    /// Creates a new VaultProvider.
+    pub fn new(config: VaultConfig) -> Self {
+        Self {
+            config,
+            client: reqwest::blocking::Client::new(),
+            token: Mutex::new(None),
+            secrets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The secret path and field holding the value of a key, if any.
+    fn location<'a>(&'a self, key: &'a str) -> anyhow::Result<Option<(&'a str, &'a str)>> {
+        match self.config.keys.get(key) {
+            Some(mapping) => {
+                let (path, field) = mapping.rsplit_once('#').with_context(|| {
+                    format!(
+                        "invalid Vault mapping {:?} for key {}: expected <path>#<field>",
+                        mapping, key
+                    )
+                })?;
+                if path.is_empty() || field.is_empty() {
+                    bail!(
+                        "invalid Vault mapping {:?} for key {}: expected <path>#<field>",
+                        mapping,
+                        key
+                    );
+                }
+                Ok(Some((path, field)))
+            }
+            None => Ok(self.config.path.as_deref().map(|path| (path, key))),
+        }
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!(
+            "{}/v1/{}",
+            self.config.address.trim_end_matches('/'),
+            path.trim_matches('/')
+        )
+    }
+
+    fn request(&self, method: reqwest::Method, path: &str) -> reqwest::blocking::RequestBuilder {
+        let mut request = self.client.request(method, self.url(path));
+        if let Some(namespace) = &self.config.namespace {
+            request = request.header("X-Vault-Namespace", namespace);
+        }
+        request
+    }
+
+    fn token(&self) -> anyhow::Result<MutexGuard<'_, Option<String>>> {
+        let mut token = self.token.lock().unwrap();
+        if token.is_none() {
+            *token = Some(match &self.config.auth {
+                VaultAuth::Token { token_env } => std::env::var(token_env)
+                    .with_context(|| format!("no Vault token in env var {}", token_env))?,
+                VaultAuth::Approle {
+                    role_id,
+                    secret_id_env,
+                    mount,
+                } => {
+                    let secret_id = std::env::var(secret_id_env).with_context(|| {
+                        format!("no Vault AppRole secret ID in env var {}", secret_id_env)
+                    })?;
+                    let response: Value = self
+                        .request(
+                            reqwest::Method::POST,
+                            &format!("auth/{}/login", mount.trim_matches('/')),
+                        )
+                        .json(&serde_json::json!({ "role_id": role_id, "secret_id": secret_id }))
+                        .send()
+                        .context("failed to log in to Vault")?
+                        .error_for_status()
+                        .context("failed to log in to Vault")?
+                        .json()?;
+                    response["auth"]["client_token"]
+                        .as_str()
+                        .context("Vault login response has no client token")?
+                        .to_string()
+                }
+            });
+        }
+        Ok(token)
+    }
+
+    /// Reads the fields of a secret, or none if it doesn't exist.
+    fn read_secret(&self, path: &str) -> anyhow::Result<Option<Map<String, Value>>> {
+        let mount = self.config.mount.trim_matches('/');
+        let path = path.trim_matches('/');
+        let api_path = match self.config.kv_version {
+            1 => format!("{}/{}", mount, path),
+            2 => format!("{}/data/{}", mount, path),
+            version => bail!("unsupported Vault KV version {}: expected 1 or 2", version),
+        };
+        let token = self.token()?;
+        let response = self
+            .request(reqwest::Method::GET, &api_path)
+            .header("X-Vault-Token", token.as_deref().unwrap_or_default())
+            .send()
+            .with_context(|| format!("failed to read Vault secret {}", path))?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let body: Value = response
+            .error_for_status()
+            .with_context(|| format!("failed to read Vault secret {}", path))?
+            .json()?;
+        secret_fields(body, self.config.kv_version)
+            .map(Some)
+            .with_context(|| format!("unexpected response reading Vault secret {}", path))
+    }
+}
+
+/// The fields of a secret in a KV read response.
+fn secret_fields(body: Value, kv_version: u8) -> anyhow::Result<Map<String, Value>> {
+    let data = match kv_version {
+        1 => body.get("data"),
+        _ => body.get("data").and_then(|data| data.get("data")),
+    };
+    match data {
+        Some(Value::Object(fields)) => Ok(fields.clone()),
+        // A deleted KV v2 secret has null data.
+        Some(Value::Null) => Ok(Map::new()),
+        _ => Err(anyhow!("no secret data")),
+    }
+}
+
+impl Provider for VaultProvider {
+    fn get(&self, key: &Key) -> anyhow::Result<Option<String>> {
+        let (path, field) = match self.location(key.as_ref())? {
+            Some(location) => location,
+            None => return Ok(None),
+        };
+        let mut secrets = self.secrets.lock().unwrap();
+        if !secrets.contains_key(path) {
+            let fields = self.read_secret(path)?;
+            secrets.insert(path.to_string(), fields);
+        }
+        let value = secrets[path].as_ref().and_then(|fields| fields.get(field));
+        match value {
+            None | Some(Value::Null) => Ok(None),
+            Some(Value::String(value)) => Ok(Some(value.clone())),
+            // Numbers and booleans are stored unquoted.
+            Some(value) => Ok(Some(value.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn config(toml: &str) -> VaultConfig {
+        toml::from_str(toml).unwrap()
+    }
+
+    #[test]
+    fn provider_location() {
+        let provider = VaultProvider::new(config(
+            r#"
+            address = "http://127.0.0.1:8200"
+            path = "my-app"
+            keys = { db_password = "databases/prod#password", bad = "databases/prod" }
+            "#,
+        ));
+        assert_eq!(
+            provider.location("api_key").unwrap(),
+            Some(("my-app", "api_key"))
+        );
+        assert_eq!(
+            provider.location("db_password").unwrap(),
+            Some(("databases/prod", "password"))
+        );
+        assert!(provider.location("bad").is_err());
+
+        let provider = VaultProvider::new(config(r#"address = "http://127.0.0.1:8200""#));
+        assert_eq!(provider.location("api_key").unwrap(), None);
+        assert_eq!(
+            provider.url("/secret/data/x"),
+            "http://127.0.0.1:8200/v1/secret/data/x"
+        );
+    }
+
+    #[test]
+    fn provider_secret_fields() {
+        let v2 = serde_json::json!({ "data": { "data": { "api_key": "1234" }, "metadata": {} } });
+        assert_eq!(secret_fields(v2, 2).unwrap()["api_key"], "1234");
+        let v1 = serde_json::json!({ "data": { "api_key": "1234" } });
+        assert_eq!(secret_fields(v1, 1).unwrap()["api_key"], "1234");
+        let deleted = serde_json::json!({ "data": { "data": null } });
+        assert!(secret_fields(deleted, 2).unwrap().is_empty());
+        assert!(secret_fields(serde_json::json!({}), 2).is_err());
+    }
+
+    #[test]
+    fn provider_auth_config() {
+        let approle = config(
+            r#"
+            address = "http://127.0.0.1:8200"
+            auth = { method = "approle", role_id = "my-role", secret_id_env = "SECRET_ID" }
+            "#,
+        );
+        assert_eq!(
+            approle.auth,
+            VaultAuth::Approle {
+                role_id: "my-role".to_string(),
+                secret_id_env: "SECRET_ID".to_string(),
+                mount: "approle".to_string(),
+            }
+        );
+        assert_eq!(approle.mount, "secret");
+        assert_eq!(approle.kv_version, 2);
+    }
+}
@@ -1,6 +1,9 @@
 use std::collections::BTreeMap;
 use std::collections::HashMap;
 
+use schemars::gen::SchemaGenerator;
+use schemars::schema::{InstanceType, ObjectValidation, Schema, SchemaObject, StringValidation};
+use schemars::JsonSchema;
 use serde::Deserialize;
 use serde::Serialize;
 
@@ -18,6 +21,11 @@ impl Tree {
             .ok_or_else(|| Error::InvalidPath(format!("no slot at path: {}", path)))
     }
 
+    /// Produces an iterator over the paths of the slots in the tree.
+    pub fn paths(&self) -> impl Iterator<Item = &TreePath> {
+        self.0.keys()
+    }
+
     pub fn merge(&mut self, base: &TreePath, other: Tree) -> Result<()> {
         for (subpath, slot) in other.0.into_iter() {
             self.merge_slot(base + &subpath, slot)?;
@@ -50,6 +58,34 @@ impl Tree {
     }
 }
 
+impl JsonSchema for Tree {
+    fn schema_name() -> String {
+        "Variables".to_owned()
+    }
+
+    // Trees are read from the `[variables]` of a manifest: a table of slots
+    // by key.
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        let key = Schema::Object(SchemaObject {
+            instance_type: Some(InstanceType::String.into()),
+            string: Some(Box::new(StringValidation {
+                pattern: Some("^[a-z](_?[a-z0-9])*$".to_owned()),
+                ..Default::default()
+            })),
+            ..Default::default()
+        });
+        Schema::Object(SchemaObject {
+            instance_type: Some(InstanceType::Object.into()),
+            object: Some(Box::new(ObjectValidation {
+                property_names: Some(Box::new(key)),
+                additional_properties: Some(Box::new(gen.subschema_for::<RawSlot>())),
+                ..Default::default()
+            })),
+            ..Default::default()
+        })
+    }
+}
+
 /// A path into a config tree.
 #[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd, Deserialize, Serialize)]
 #[serde(try_from = "String")]
@@ -196,11 +232,16 @@ impl std::fmt::Debug for Slot {
 #[derive(Debug, Deserialize, PartialEq, Eq)]
 pub struct RawSection(pub HashMap<String, RawSlot>);
 
-#[derive(Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+/// A variable.
+#[derive(Debug, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
 #[serde(default)]
 pub struct RawSlot {
+    /// The default value, which may reference other variables. A variable
+    /// that is not required must have one.
     pub default: Option<String>,
+    /// Whether a value must be provided when the application runs.
     pub required: bool,
+    /// Whether the value is kept out of logs and errors.
     pub secret: bool,
 }
 
@@ -0,0 +1,216 @@
+use std::path::PathBuf;
+
+use anyhow::{anyhow, bail, Context, Result};
+use ed25519_dalek::{Signer as _, Verifier as _};
+use hmac::{Hmac, Mac};
+use p256::ecdsa::signature::{Signer as _, Verifier as _};
+use serde::Deserialize;
+use sha2::{Sha256, Sha512};
+
+/// Key algorithms.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum KeyAlgorithm {
+    /// HMAC with SHA-256.
+    HmacSha256,
+    /// HMAC with SHA-512.
+    HmacSha512,
+    /// Ed25519 signatures.
+    Ed25519,
+    /// ECDSA signatures over the NIST P-256 curve, with SHA-256.
+    EcdsaP256,
+}
+
+/// An entry in the OS keyring.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct KeyringEntry {
+    /// The keyring service name.
+    pub service: String,
+    /// The keyring user name.
+    pub user: String,
+}
+
+/// Runtime configuration for a named key.
+///
+/// Key material is hex-encoded, and must be provided by exactly one of
+/// `value`, `file` or `keyring`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub struct KeyConfig {
+    /// The key algorithm.
+    pub algorithm: KeyAlgorithm,
+    /// The hex-encoded key material.
+    pub value: Option<String>,
+    /// A file containing the hex-encoded key material.
+    pub file: Option<PathBuf>,
+    /// An OS keyring entry containing the hex-encoded key material.
+    pub keyring: Option<KeyringEntry>,
+}
+
+impl KeyConfig {
+    fn material(&self) -> Result<Vec<u8>> {
+        let encoded = match (&self.value, &self.file, &self.keyring) {
+            (Some(value), None, None) => value.clone(),
+            (None, Some(file), None) => std::fs::read_to_string(file)
+                .with_context(|| format!("Cannot read key file {}", file.display()))?,
+            (None, None, Some(entry)) => keyring::Entry::new(&entry.service, &entry.user)
+                .get_password()
+                .with_context(|| {
+                    format!(
+                        "Cannot read keyring entry {}/{}",
+                        entry.service, entry.user
+                    )
+                })?,
+            _ => bail!("exactly one of `value`, `file` or `keyring` must be set"),
+        };
+        hex::decode(encoded.trim()).context("key material must be hex-encoded")
+    }
+}
+
+/// A loaded key.
+pub(crate) enum Key {
+    HmacSha256(Vec<u8>),
+    HmacSha512(Vec<u8>),
+    Ed25519(ed25519_dalek::Keypair),
+    EcdsaP256(p256::ecdsa::SigningKey),
+}
+
+impl Key {
+    pub(crate) fn load(config: &KeyConfig) -> Result<Self> {
+        let material = config.material()?;
+        Ok(match config.algorithm {
+            KeyAlgorithm::HmacSha256 => Self::HmacSha256(material),
+            KeyAlgorithm::HmacSha512 => Self::HmacSha512(material),
+            KeyAlgorithm::Ed25519 => {
+                let secret = ed25519_dalek::SecretKey::from_bytes(&material)
+                    .map_err(|e| anyhow!("Invalid Ed25519 key: {}", e))?;
+                let public = (&secret).into();
+                Self::Ed25519(ed25519_dalek::Keypair { secret, public })
+            }
+            KeyAlgorithm::EcdsaP256 => Self::EcdsaP256(
+                p256::ecdsa::SigningKey::from_bytes(&material)
+                    .map_err(|e| anyhow!("Invalid ECDSA P-256 key: {}", e))?,
+            ),
+        })
+    }
+
+    pub(crate) fn is_hmac(&self) -> bool {
+        matches!(self, Self::HmacSha256(_) | Self::HmacSha512(_))
+    }
+
+    pub(crate) fn sign(&self, data: &[u8]) -> Result<Vec<u8>> {
+        Ok(match self {
+            Self::HmacSha256(key) => {
+                let mut mac = Hmac::<Sha256>::new_from_slice(key)?;
+                mac.update(data);
+                mac.finalize().into_bytes().to_vec()
+            }
+            Self::HmacSha512(key) => {
+                let mut mac = Hmac::<Sha512>::new_from_slice(key)?;
+                mac.update(data);
+                mac.finalize().into_bytes().to_vec()
+            }
+            Self::Ed25519(keypair) => keypair.sign(data).to_bytes().to_vec(),
+            Self::EcdsaP256(key) => {
+                let signature: p256::ecdsa::Signature = key.sign(data);
+                signature.to_der().as_bytes().to_vec()
+            }
+        })
+    }
+
+    /// Returns `Ok(false)` for well-formed signatures that do not match, and
+    /// an error for malformed ones.
+    pub(crate) fn verify(&self, data: &[u8], signature: &[u8]) -> Result<bool> {
+        Ok(match self {
+            Self::HmacSha256(key) => {
+                let mut mac = Hmac::<Sha256>::new_from_slice(key)?;
+                mac.update(data);
+                mac.verify_slice(signature).is_ok()
+            }
+            Self::HmacSha512(key) => {
+                let mut mac = Hmac::<Sha512>::new_from_slice(key)?;
+                mac.update(data);
+                mac.verify_slice(signature).is_ok()
+            }
+            Self::Ed25519(keypair) => {
+                let signature = ed25519_dalek::Signature::from_bytes(signature)
+                    .map_err(|_| InvalidSignature)?;
+                keypair.public.verify(data, &signature).is_ok()
+            }
+            Self::EcdsaP256(key) => {
+                let signature =
+                    p256::ecdsa::Signature::from_der(signature).map_err(|_| InvalidSignature)?;
+                key.verifying_key().verify(data, &signature).is_ok()
+            }
+        })
+    }
+
+    pub(crate) fn public_key(&self) -> Option<Vec<u8>> {
+        match self {
+            Self::HmacSha256(_) | Self::HmacSha512(_) => None,
+            Self::Ed25519(keypair) => Some(keypair.public.to_bytes().to_vec()),
+            Self::EcdsaP256(key) => Some(
+                key.verifying_key()
+                    .to_encoded_point(false)
+                    .as_bytes()
+                    .to_vec(),
+            ),
+        }
+    }
+}
+
+/// A malformed signature.
+#[derive(Debug)]
+pub(crate) struct InvalidSignature;
+
+impl std::fmt::Display for InvalidSignature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid signature")
+    }
+}
+
+impl std::error::Error for InvalidSignature {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(algorithm: KeyAlgorithm, value: &str) -> Key {
+        Key::load(&KeyConfig {
+            algorithm,
+            value: Some(value.to_string()),
+            file: None,
+            keyring: None,
+        })
+        .unwrap()
+    }
+
+    const SEED: &str = "9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60";
+
+    #[test]
+    fn test_sign_and_verify() {
+        for algorithm in [
+            KeyAlgorithm::HmacSha256,
+            KeyAlgorithm::HmacSha512,
+            KeyAlgorithm::Ed25519,
+            KeyAlgorithm::EcdsaP256,
+        ] {
+            let key = key(algorithm, SEED);
+            let signature = key.sign(b"Fermyon").unwrap();
+            assert!(key.verify(b"Fermyon", &signature).unwrap(), "{:?}", algorithm);
+            assert!(!key.verify(b"Spin", &signature).unwrap(), "{:?}", algorithm);
+        }
+    }
+
+    #[test]
+    fn test_key_sources_are_exclusive() {
+        let config = KeyConfig {
+            algorithm: KeyAlgorithm::HmacSha256,
+            value: Some(SEED.to_string()),
+            file: Some("key.hex".into()),
+            keyring: None,
+        };
+        assert!(Key::load(&config).is_err());
+    }
+}
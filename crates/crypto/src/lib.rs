@@ -0,0 +1,163 @@
+//! A cryptography host interface for Spin components.
+//!
+//! Keys are configured by name in the runtime configuration, so guest
+//! code can sign and verify data without ever holding private key material.
+
+mod keys;
+
+use std::{collections::HashMap, sync::Arc};
+
+use anyhow::Context;
+use async_trait::async_trait;
+use sha2::Digest;
+use spin_crypto::*;
+use spin_engine::{
+    host_component::{HostComponent, HostComponentsStateHandle},
+    RuntimeContext,
+};
+use spin_manifest::CoreComponent;
+use wit_bindgen_wasmtime::wasmtime::Linker;
+
+use keys::{InvalidSignature, Key};
+pub use keys::{KeyAlgorithm, KeyConfig, KeyringEntry};
+pub use spin_crypto::add_to_linker;
+
+wit_bindgen_wasmtime::export!({
+    paths: ["../../wit/ephemeral/spin-crypto.wit"],
+    async: *,
+});
+
+/// The cryptography host component.
+#[derive(Clone, Default)]
+pub struct CryptoComponent {
+    keys: Arc<HashMap<String, Key>>,
+}
+
+impl CryptoComponent {
+    /// Creates a cryptography host component, loading the configured keys.
+    pub fn new(keys: &HashMap<String, KeyConfig>) -> anyhow::Result<Self> {
+        let keys = keys
+            .iter()
+            .map(|(name, config)| {
+                let key = Key::load(config).with_context(|| format!("Cannot load key {}", name))?;
+                Ok((name.clone(), key))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Self {
+            keys: Arc::new(keys),
+        })
+    }
+}
+
+impl HostComponent for CryptoComponent {
+    type State = Crypto;
+
+    fn add_to_linker<T: Send>(
+        linker: &mut Linker<RuntimeContext<T>>,
+        state_handle: HostComponentsStateHandle<Self::State>,
+    ) -> anyhow::Result<()> {
+        add_to_linker(linker, move |ctx| state_handle.get_mut(ctx))
+    }
+
+    fn build_state(&self, component: &CoreComponent) -> anyhow::Result<Self::State> {
+        Ok(Crypto {
+            keys: self.keys.clone(),
+            allowed_keys: component.wasm.allowed_crypto_keys.clone(),
+        })
+    }
+}
+
+/// Per-component cryptography state.
+pub struct Crypto {
+    keys: Arc<HashMap<String, Key>>,
+    /// The keys the component may use.
+    allowed_keys: Vec<String>,
+}
+
+impl Crypto {
+    fn key(&self, name: &str) -> Result<&Key, Error> {
+        if !self.allowed_keys.iter().any(|allowed| allowed == name) {
+            return Err(Error::Other(format!(
+                "key {:?} is not in allowed_crypto_keys",
+                name
+            )));
+        }
+        self.keys
+            .get(name)
+            .ok_or_else(|| Error::UnknownKey(name.to_string()))
+    }
+}
+
+#[async_trait]
+impl spin_crypto::SpinCrypto for Crypto {
+    async fn hash(&mut self, algorithm: HashAlgorithm, data: &[u8]) -> Vec<u8> {
+        match algorithm {
+            HashAlgorithm::Sha256 => sha2::Sha256::digest(data).to_vec(),
+            HashAlgorithm::Sha384 => sha2::Sha384::digest(data).to_vec(),
+            HashAlgorithm::Sha512 => sha2::Sha512::digest(data).to_vec(),
+            HashAlgorithm::Sha3256 => sha3::Sha3_256::digest(data).to_vec(),
+            HashAlgorithm::Sha3512 => sha3::Sha3_512::digest(data).to_vec(),
+        }
+    }
+
+    async fn hmac(&mut self, key: &str, data: &[u8]) -> Result<Vec<u8>, Error> {
+        let key = self.key(key)?;
+        if !key.is_hmac() {
+            return Err(Error::Unsupported("key is not an HMAC key".to_string()));
+        }
+        key.sign(data).map_err(to_error)
+    }
+
+    async fn sign(&mut self, key: &str, data: &[u8]) -> Result<Vec<u8>, Error> {
+        self.key(key)?.sign(data).map_err(to_error)
+    }
+
+    async fn verify(&mut self, key: &str, data: &[u8], signature: &[u8]) -> Result<bool, Error> {
+        self.key(key)?.verify(data, signature).map_err(to_error)
+    }
+
+    async fn public_key(&mut self, key: &str) -> Result<Vec<u8>, Error> {
+        self.key(key)?
+            .public_key()
+            .ok_or_else(|| Error::Unsupported("HMAC keys have no public key".to_string()))
+    }
+}
+
+fn to_error(e: anyhow::Error) -> Error {
+    if e.downcast_ref::<InvalidSignature>().is_some() {
+        Error::InvalidSignature
+    } else {
+        Error::Other(e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_components_only_use_allowed_keys() -> anyhow::Result<()> {
+        let config = KeyConfig {
+            algorithm: KeyAlgorithm::HmacSha256,
+            value: Some("73336372337473336372337473336372".to_string()),
+            file: None,
+            keyring: None,
+        };
+        let component = CryptoComponent::new(&[("signing".to_string(), config)].into())?;
+        let crypto = |allowed_keys: Vec<String>| Crypto {
+            keys: component.keys.clone(),
+            allowed_keys,
+        };
+
+        assert!(matches!(
+            crypto(vec![]).key("signing"),
+            Err(Error::Other(_))
+        ));
+        assert!(crypto(vec!["signing".to_string()]).key("signing").is_ok());
+        assert!(matches!(
+            crypto(vec!["missing".to_string()]).key("missing"),
+            Err(Error::UnknownKey(_))
+        ));
+        Ok(())
+    }
+}
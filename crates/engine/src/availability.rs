@@ -0,0 +1,184 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Mutex,
+};
+
+use anyhow::Result;
+use serde::Deserialize;
+use tracing::log;
+
+/// What a host service does when its backend is unavailable when the
+/// application starts.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OnUnavailable {
+    /// The application fails to start.
+    Fail,
+    /// The application starts, and the service returns `unavailable` errors
+    /// to components.
+    Degrade,
+}
+
+impl Default for OnUnavailable {
+    fn default() -> Self {
+        Self::Fail
+    }
+}
+
+/// Runtime configuration for a host service with a backend.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub struct ServiceConfig {
+    /// What the service does when its backend is unavailable at startup.
+    #[serde(default)]
+    pub on_unavailable: OnUnavailable,
+}
+
+/// The backend of a host service is unavailable. The backends of degraded
+/// services return it, and host components report it to components as an
+/// `unavailable` error.
+#[derive(Clone, Debug)]
+pub struct ServiceUnavailable {
+    /// The name of the service.
+    pub service: String,
+    /// Why the backend is unavailable.
+    pub reason: String,
+}
+
+impl std::fmt::Display for ServiceUnavailable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} is unavailable: {}", self.service, self.reason)
+    }
+}
+
+impl std::error::Error for ServiceUnavailable {}
+
+/// The host services of an application that started degraded, as reported by
+/// health endpoints.
+#[derive(Debug, Default)]
+pub struct ServiceHealth {
+    policies: HashMap<String, OnUnavailable>,
+    degraded: Mutex<BTreeMap<String, String>>,
+}
+
+impl ServiceHealth {
+    /// Tracks the services of an application, with the given configuration
+    /// by service name. Services that are not configured fail when their
+    /// backend is unavailable.
+    pub fn new(services: &HashMap<String, ServiceConfig>) -> Self {
+        Self {
+            policies: services
+                .iter()
+                .map(|(name, config)| (name.clone(), config.on_unavailable))
+                .collect(),
+            degraded: Default::default(),
+        }
+    }
+
+    /// Checks that the configured services are among the known ones.
+    pub fn validate(&self, known: &[&str]) -> Result<()> {
+        for name in self.policies.keys() {
+            if !known.contains(&name.as_str()) {
+                anyhow::bail!(
+                    "Unknown service {:?} in [services]: expected one of {}",
+                    name,
+                    known.join(", ")
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Starts a service with its backend, if it is available. Otherwise, as
+    /// the policy of the service says, either fails, or starts the service
+    /// degraded and records it.
+    pub fn start<S>(
+        &self,
+        service: &str,
+        available: impl FnOnce() -> Result<S>,
+        degraded: impl FnOnce(ServiceUnavailable) -> S,
+    ) -> Result<S> {
+        let error = match available() {
+            Ok(started) => return Ok(started),
+            Err(e) => e,
+        };
+        match self.policies.get(service).copied().unwrap_or_default() {
+            OnUnavailable::Fail => Err(error.context(format!(
+                "The {} service is unavailable; set on_unavailable = \"degrade\" in [services.{}] to start without it",
+                service, service
+            ))),
+            OnUnavailable::Degrade => {
+                let reason = format!("{:#}", error);
+                log::warn!("Starting without the {} service: {}", service, reason);
+                self.degraded
+                    .lock()
+                    .unwrap()
+                    .insert(service.to_owned(), reason.clone());
+                Ok(degraded(ServiceUnavailable {
+                    service: service.to_owned(),
+                    reason,
+                }))
+            }
+        }
+    }
+
+    /// The services that started degraded, with the reason their backend
+    /// was unavailable.
+    pub fn degraded(&self) -> BTreeMap<String, String> {
+        self.degraded.lock().unwrap().clone()
+    }
+
+    /// The body of a health check: `OK`, or `DEGRADED` followed by a line
+    /// for each degraded service.
+    pub fn report(&self) -> String {
+        let degraded = self.degraded();
+        if degraded.is_empty() {
+            return "OK".to_owned();
+        }
+        let mut report = "DEGRADED\n".to_owned();
+        for (service, reason) in degraded {
+            report.push_str(&format!("{}: {}\n", service, reason));
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_start() {
+        let services = [(
+            "lock".to_owned(),
+            ServiceConfig {
+                on_unavailable: OnUnavailable::Degrade,
+            },
+        )]
+        .into_iter()
+        .collect();
+        let health = ServiceHealth::new(&services);
+        assert!(health.validate(&["lock", "pubsub"]).is_ok());
+        assert!(health.validate(&["pubsub"]).is_err());
+
+        let up = health.start("pubsub", || Ok("redis"), |_| "none").unwrap();
+        assert_eq!(up, "redis");
+        assert_eq!(health.report(), "OK");
+
+        let err = health
+            .start("pubsub", || anyhow::bail!("connection refused"), |_| "none")
+            .unwrap_err();
+        assert!(format!("{:#}", err).contains("connection refused"));
+        assert!(health.degraded().is_empty());
+
+        let degraded = health
+            .start(
+                "lock",
+                || anyhow::bail!("connection refused"),
+                |e| e.to_string(),
+            )
+            .unwrap();
+        assert_eq!(degraded, "lock is unavailable: connection refused");
+        assert_eq!(health.report(), "DEGRADED\nlock: connection refused\n");
+    }
+}
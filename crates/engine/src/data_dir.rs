@@ -0,0 +1,163 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+use crate::temp_dir::dir_size;
+
+/// Configuration of the data directories of applications, for hosts running
+/// several applications.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub struct DataDirConfig {
+    /// The directory holding a data directory for each application. If not
+    /// set, applications keep their state next to their manifest.
+    pub root: Option<PathBuf>,
+    /// The maximum size of the data directory of an application. Writes that
+    /// would exceed it fail.
+    pub max_bytes: Option<u64>,
+}
+
+impl DataDirConfig {
+    /// The data directory of the application with the given name, if a root
+    /// is configured. Applications with the same name share a directory, so
+    /// that their state outlives new versions.
+    pub fn resolve(&self, app: &str) -> Option<DataDir> {
+        let root = self.root.as_ref()?;
+        Some(DataDir {
+            path: root.join(crate::sanitize(app)),
+            max_bytes: self.max_bytes,
+        })
+    }
+
+    /// The data directories of all applications under the root.
+    pub fn list(&self) -> Result<Vec<DataDir>> {
+        let root = match &self.root {
+            Some(root) if root.exists() => root,
+            _ => return Ok(vec![]),
+        };
+        let mut dirs = vec![];
+        for entry in std::fs::read_dir(root)
+            .with_context(|| format!("Cannot read data directory {}", root.display()))?
+        {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() {
+                dirs.push(DataDir {
+                    path: entry.path(),
+                    max_bytes: self.max_bytes,
+                });
+            }
+        }
+        dirs.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(dirs)
+    }
+}
+
+/// The directory holding the state of an application, such as its blob
+/// store containers, temporary directories and jobs, bounded by a quota.
+#[derive(Clone, Debug)]
+pub struct DataDir {
+    path: PathBuf,
+    max_bytes: Option<u64>,
+}
+
+impl DataDir {
+    /// The path of the directory.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The maximum size of the directory, if any.
+    pub fn max_bytes(&self) -> Option<u64> {
+        self.max_bytes
+    }
+
+    /// The current size of the directory.
+    pub fn usage(&self) -> Result<u64> {
+        if !self.path.exists() {
+            return Ok(0);
+        }
+        dir_size(&self.path).with_context(|| format!("Cannot read {}", self.path.display()))
+    }
+
+    /// Checks that `bytes` more can be written to the directory without
+    /// exceeding its quota.
+    pub fn reserve(&self, bytes: u64) -> Result<()> {
+        let max_bytes = match self.max_bytes {
+            Some(max_bytes) => max_bytes,
+            None => return Ok(()),
+        };
+        let usage = self.usage()?;
+        if usage.saturating_add(bytes) > max_bytes {
+            return Err(QuotaExceeded {
+                usage,
+                requested: bytes,
+                max_bytes,
+            }
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Removes the directory and all the state it holds.
+    pub fn remove(&self) -> Result<()> {
+        if self.path.exists() {
+            std::fs::remove_dir_all(&self.path)
+                .with_context(|| format!("Cannot remove {}", self.path.display()))?;
+        }
+        Ok(())
+    }
+}
+
+/// A write would exceed the quota of a data directory.
+#[derive(Debug)]
+pub struct QuotaExceeded {
+    usage: u64,
+    requested: u64,
+    max_bytes: u64,
+}
+
+impl std::fmt::Display for QuotaExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "writing {} bytes would exceed the data quota of {} bytes ({} bytes used)",
+            self.requested, self.max_bytes, self.usage
+        )
+    }
+}
+
+impl std::error::Error for QuotaExceeded {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quota() -> Result<()> {
+        let root = tempfile::tempdir()?;
+        let config = DataDirConfig {
+            root: Some(root.path().to_owned()),
+            max_bytes: Some(10),
+        };
+        let dir = config.resolve("my-app").unwrap();
+        assert_eq!(dir.path(), root.path().join("my-app"));
+        assert_eq!(dir.usage()?, 0);
+
+        std::fs::create_dir_all(dir.path())?;
+        std::fs::write(dir.path().join("state"), "12345678")?;
+        dir.reserve(2)?;
+        let err = dir.reserve(3).unwrap_err();
+        assert!(err.downcast_ref::<QuotaExceeded>().is_some());
+
+        assert_eq!(config.list()?.len(), 1);
+        dir.remove()?;
+        assert!(config.list()?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_no_root() {
+        assert!(DataDirConfig::default().resolve("my-app").is_none());
+    }
+}
@@ -0,0 +1,289 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use serde::Deserialize;
+
+const HOUR: Duration = Duration::from_secs(60 * 60);
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Quotas on the outbound traffic of components.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub struct EgressConfig {
+    /// The quotas of components without quotas of their own.
+    #[serde(default)]
+    pub default: EgressLimits,
+    /// The quotas of components, by component ID, falling back to the
+    /// default quotas for the limits they do not set.
+    #[serde(default)]
+    pub component: HashMap<String, EgressLimits>,
+}
+
+impl EgressConfig {
+    fn limits(&self, component: &str) -> EgressLimits {
+        let own = self.component.get(component).cloned().unwrap_or_default();
+        EgressLimits {
+            max_requests_per_hour: own
+                .max_requests_per_hour
+                .or(self.default.max_requests_per_hour),
+            max_bytes_per_day: own.max_bytes_per_day.or(self.default.max_bytes_per_day),
+        }
+    }
+}
+
+/// Quotas on the outbound traffic of a component.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub struct EgressLimits {
+    /// The maximum number of outbound requests in an hour.
+    pub max_requests_per_hour: Option<u64>,
+    /// The maximum number of bytes sent and received in a day. A request
+    /// started within the quota completes, even if it exceeds it.
+    pub max_bytes_per_day: Option<u64>,
+}
+
+/// A kind of outbound traffic.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EgressKind {
+    /// Outbound HTTP requests.
+    Http,
+    /// Outbound Redis commands.
+    Redis,
+    /// Outbound PostgreSQL statements.
+    Postgres,
+    /// Outbound MySQL statements.
+    Mysql,
+}
+
+impl EgressKind {
+    /// The name of the kind, as reported in metrics.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Http => "http",
+            Self::Redis => "redis",
+            Self::Postgres => "postgres",
+            Self::Mysql => "mysql",
+        }
+    }
+}
+
+/// Meters the outbound traffic of the components of an application, and
+/// enforces their quotas.
+#[derive(Debug, Default)]
+pub struct EgressMeter {
+    config: EgressConfig,
+    components: Mutex<HashMap<String, ComponentEgress>>,
+}
+
+impl EgressMeter {
+    /// Creates a meter enforcing the given quotas.
+    pub fn new(config: &EgressConfig) -> Self {
+        Self {
+            config: config.clone(),
+            components: Default::default(),
+        }
+    }
+
+    /// Counts an outbound request of the component, unless it would exceed
+    /// the quotas of the component.
+    pub fn admit(&self, component: &str, kind: EgressKind) -> Result<(), EgressQuotaExceeded> {
+        self.admit_at(component, kind, Instant::now())
+    }
+
+    /// Counts the bytes sent and received by an outbound request of the
+    /// component.
+    pub fn record(&self, component: &str, kind: EgressKind, bytes: u64) {
+        self.record_at(component, kind, bytes, Instant::now())
+    }
+
+    /// The outbound traffic of each component since the meter was created.
+    pub fn stats(&self) -> BTreeMap<String, EgressStats> {
+        self.components
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(component, egress)| (component.clone(), egress.stats.clone()))
+            .collect()
+    }
+
+    fn admit_at(
+        &self,
+        component: &str,
+        kind: EgressKind,
+        now: Instant,
+    ) -> Result<(), EgressQuotaExceeded> {
+        let limits = self.config.limits(component);
+        let mut components = self.components.lock().unwrap();
+        let egress = components
+            .entry(component.to_owned())
+            .or_insert_with(|| ComponentEgress::new(now));
+        let requests = egress.hour.current(now, HOUR);
+        let bytes = egress.day.current(now, DAY);
+        let exceeded = match (limits.max_requests_per_hour, limits.max_bytes_per_day) {
+            (Some(max), _) if requests >= max => Some(EgressQuotaExceeded::Requests(max)),
+            (_, Some(max)) if bytes >= max => Some(EgressQuotaExceeded::Bytes(max)),
+            _ => None,
+        };
+        if let Some(exceeded) = exceeded {
+            *egress.stats.rejected.entry(kind.as_str()).or_default() += 1;
+            return Err(exceeded);
+        }
+        egress.hour.used += 1;
+        *egress.stats.requests.entry(kind.as_str()).or_default() += 1;
+        Ok(())
+    }
+
+    fn record_at(&self, component: &str, kind: EgressKind, bytes: u64, now: Instant) {
+        let mut components = self.components.lock().unwrap();
+        let egress = components
+            .entry(component.to_owned())
+            .or_insert_with(|| ComponentEgress::new(now));
+        egress.day.current(now, DAY);
+        egress.day.used = egress.day.used.saturating_add(bytes);
+        *egress.stats.bytes.entry(kind.as_str()).or_default() += bytes;
+    }
+}
+
+/// The outbound traffic of a component, by kind.
+#[derive(Clone, Debug, Default)]
+pub struct EgressStats {
+    /// The number of outbound requests made.
+    pub requests: BTreeMap<&'static str, u64>,
+    /// The number of bytes sent and received.
+    pub bytes: BTreeMap<&'static str, u64>,
+    /// The number of outbound requests rejected by a quota.
+    pub rejected: BTreeMap<&'static str, u64>,
+}
+
+#[derive(Debug)]
+struct ComponentEgress {
+    /// Requests in the current hour.
+    hour: Window,
+    /// Bytes in the current day.
+    day: Window,
+    stats: EgressStats,
+}
+
+impl ComponentEgress {
+    fn new(now: Instant) -> Self {
+        Self {
+            hour: Window::new(now),
+            day: Window::new(now),
+            stats: Default::default(),
+        }
+    }
+}
+
+/// A quota window, starting with the first traffic of a component.
+#[derive(Debug)]
+struct Window {
+    start: Instant,
+    used: u64,
+}
+
+impl Window {
+    fn new(start: Instant) -> Self {
+        Self { start, used: 0 }
+    }
+
+    /// The use in the window, starting a new window once it has elapsed.
+    fn current(&mut self, now: Instant, period: Duration) -> u64 {
+        if now.duration_since(self.start) >= period {
+            *self = Self::new(now);
+        }
+        self.used
+    }
+}
+
+/// An outbound request would exceed a quota of its component.
+#[derive(Debug, PartialEq, Eq)]
+pub enum EgressQuotaExceeded {
+    /// The component made the maximum number of requests in the hour.
+    Requests(u64),
+    /// The component sent and received the maximum number of bytes in the
+    /// day.
+    Bytes(u64),
+}
+
+impl std::fmt::Display for EgressQuotaExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Requests(max) => write!(f, "the quota of {} outbound requests per hour", max),
+            Self::Bytes(max) => write!(f, "the quota of {} outbound bytes per day", max),
+        }
+    }
+}
+
+impl std::error::Error for EgressQuotaExceeded {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn meter() -> EgressMeter {
+        let config = toml::from_str(
+            r#"
+            [default]
+            max_requests_per_hour = 2
+            max_bytes_per_day = 100
+
+            [component.reports]
+            max_bytes_per_day = 10
+            "#,
+        )
+        .unwrap();
+        EgressMeter::new(&config)
+    }
+
+    #[test]
+    fn test_requests_quota() {
+        let meter = meter();
+        let start = Instant::now();
+        meter.admit_at("api", EgressKind::Http, start).unwrap();
+        meter.admit_at("api", EgressKind::Redis, start).unwrap();
+        assert_eq!(
+            meter.admit_at("api", EgressKind::Http, start),
+            Err(EgressQuotaExceeded::Requests(2))
+        );
+        meter
+            .admit_at("api", EgressKind::Http, start + HOUR)
+            .unwrap();
+
+        let stats = &meter.stats()["api"];
+        assert_eq!(stats.requests["http"], 2);
+        assert_eq!(stats.requests["redis"], 1);
+        assert_eq!(stats.rejected["http"], 1);
+    }
+
+    #[test]
+    fn test_bytes_quota() {
+        let meter = meter();
+        let start = Instant::now();
+        meter
+            .admit_at("reports", EgressKind::Postgres, start)
+            .unwrap();
+        meter.record_at("reports", EgressKind::Postgres, 25, start);
+        assert_eq!(
+            meter.admit_at("reports", EgressKind::Postgres, start),
+            Err(EgressQuotaExceeded::Bytes(10))
+        );
+        meter
+            .admit_at("reports", EgressKind::Postgres, start + DAY)
+            .unwrap();
+        assert_eq!(meter.stats()["reports"].bytes["postgres"], 25);
+    }
+
+    #[test]
+    fn test_unlimited() {
+        let meter = EgressMeter::default();
+        let now = Instant::now();
+        for _ in 0..100 {
+            meter.admit_at("api", EgressKind::Http, now).unwrap();
+            meter.record_at("api", EgressKind::Http, 1 << 20, now);
+        }
+        assert_eq!(meter.stats()["api"].requests["http"], 100);
+    }
+}
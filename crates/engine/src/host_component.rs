@@ -1,10 +1,11 @@
 use std::{any::Any, marker::PhantomData};
 
-use anyhow::Result;
+use anyhow::{Context, Result};
+use serde::de::DeserializeOwned;
 use spin_manifest::CoreComponent;
 use wasmtime::Linker;
 
-use crate::RuntimeContext;
+use crate::{RuntimeContext, TaskSpawner};
 
 /// Represents a host implementation of a Wasm interface.
 pub trait HostComponent: Send + Sync {
@@ -12,17 +13,65 @@ pub trait HostComponent: Send + Sync {
     type State: Any + Send;
 
     /// Add this component to the given Linker, using the given runtime state-getting handle.
-    fn add_to_linker<T>(
+    fn add_to_linker<T: Send>(
         linker: &mut Linker<RuntimeContext<T>>,
         state_handle: HostComponentsStateHandle<Self::State>,
     ) -> Result<()>;
 
     /// Build a new runtime state object for the given component.
     fn build_state(&self, component: &CoreComponent) -> Result<Self::State>;
+
+    /// Build a new runtime state object for an invocation of the given
+    /// component. By default this calls `build_state`; host components that
+    /// run work in the background override it to keep the spawner, so that
+    /// their tasks are tracked, and cancelled, with the invocation.
+    fn build_invocation_state(
+        &self,
+        component: &CoreComponent,
+        _tasks: &TaskSpawner,
+    ) -> Result<Self::State> {
+        self.build_state(component)
+    }
+
+    /// Add this host component to the given Linker. By default this calls
+    /// `add_to_linker`; host components whose interface is only known once
+    /// they are created, such as those loaded from plugins, override it.
+    fn add_instance_to_linker<T: Send>(
+        &self,
+        linker: &mut Linker<RuntimeContext<T>>,
+        state_handle: HostComponentsStateHandle<Self::State>,
+    ) -> Result<()> {
+        Self::add_to_linker(linker, state_handle)
+    }
+}
+
+/// Get the configuration a component gives the named host component in the
+/// `host_config` table of the application manifest, if any.
+///
+/// Host components call this from `build_state` to read their per-component
+/// settings into their own configuration type.
+pub fn host_config<C: DeserializeOwned>(
+    component: &CoreComponent,
+    name: &str,
+) -> Result<Option<C>> {
+    component
+        .wasm
+        .host_config
+        .get(name)
+        .map(|config| {
+            config.clone().try_into().with_context(|| {
+                format!(
+                    "Invalid configuration for host component {:?} in component {:?}",
+                    name, component.id
+                )
+            })
+        })
+        .transpose()
 }
 type HostComponentState = Box<dyn Any + Send>;
 
-type StateBuilder = Box<dyn Fn(&CoreComponent) -> Result<HostComponentState> + Send + Sync>;
+type StateBuilder =
+    Box<dyn Fn(&CoreComponent, &TaskSpawner) -> Result<HostComponentState> + Send + Sync>;
 
 #[derive(Default)]
 pub(crate) struct HostComponents {
@@ -30,7 +79,7 @@ pub(crate) struct HostComponents {
 }
 
 impl HostComponents {
-    pub(crate) fn insert<T: 'static, Component: HostComponent + 'static>(
+    pub(crate) fn insert<T: Send + 'static, Component: HostComponent + 'static>(
         &mut self,
         linker: &mut Linker<RuntimeContext<T>>,
         host_component: Component,
@@ -39,18 +88,22 @@ impl HostComponents {
             idx: self.state_builders.len(),
             _phantom: PhantomData,
         };
-        Component::add_to_linker(linker, handle)?;
-        self.state_builders.push(Box::new(move |c| {
-            Ok(Box::new(host_component.build_state(c)?))
+        host_component.add_instance_to_linker(linker, handle)?;
+        self.state_builders.push(Box::new(move |c, tasks| {
+            Ok(Box::new(host_component.build_invocation_state(c, tasks)?))
         }));
         Ok(())
     }
 
-    pub(crate) fn build_state(&self, c: &CoreComponent) -> Result<HostComponentsState> {
+    pub(crate) fn build_state(
+        &self,
+        c: &CoreComponent,
+        tasks: &TaskSpawner,
+    ) -> Result<HostComponentsState> {
         Ok(HostComponentsState(
             self.state_builders
                 .iter()
-                .map(|build_state| build_state(c))
+                .map(|build_state| build_state(c, tasks))
                 .collect::<Result<_>>()?,
         ))
     }
@@ -98,3 +151,46 @@ impl<T> Clone for HostComponentsStateHandle<T> {
 }
 
 impl<T> Copy for HostComponentsStateHandle<T> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use spin_manifest::{ModuleSource, WasmConfig};
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct LedgerConfig {
+        account: String,
+        #[serde(default)]
+        read_only: bool,
+    }
+
+    fn component(host_config: &str) -> CoreComponent {
+        CoreComponent {
+            source: ModuleSource::FileReference("ledger.wasm".into()),
+            id: "ledger".to_owned(),
+            description: None,
+            wasm: WasmConfig {
+                host_config: toml::from_str(host_config).unwrap(),
+                ..Default::default()
+            },
+        }
+    }
+
+    #[test]
+    fn test_host_config() -> Result<()> {
+        let c = component("[acme-ledger]\naccount = \"orders\"\n");
+        assert_eq!(
+            host_config::<LedgerConfig>(&c, "acme-ledger")?,
+            Some(LedgerConfig {
+                account: "orders".to_owned(),
+                read_only: false,
+            })
+        );
+        assert_eq!(host_config::<LedgerConfig>(&c, "other")?, None);
+
+        let c = component("[acme-ledger]\nread_only = true\n");
+        assert!(host_config::<LedgerConfig>(&c, "acme-ledger").is_err());
+        Ok(())
+    }
+}
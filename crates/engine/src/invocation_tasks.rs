@@ -0,0 +1,299 @@
+use std::{
+    future::Future,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, Weak,
+    },
+    time::{Duration, Instant},
+};
+
+use tokio::task::JoinHandle;
+use tracing::log;
+
+/// The tasks spawned on behalf of an invocation of a component, cancelled
+/// when the invocation ends.
+///
+/// Owned by the invocation's `RuntimeContext`, so the invocation ends, and
+/// its tasks are reaped, when its store is dropped. Tasks still running then
+/// have outlived the invocation: async tasks are aborted, and blocking tasks,
+/// which cannot be aborted, are left to finish. Both are reported as leaks,
+/// as warnings in debug builds.
+#[derive(Default)]
+pub struct InvocationTasks {
+    registry: Arc<Mutex<Registry>>,
+}
+
+/// Spawns tasks tracked by an invocation. Cheap to clone, so host components
+/// can keep one in their state.
+#[derive(Clone, Default)]
+pub struct TaskSpawner {
+    registry: Arc<Mutex<Registry>>,
+}
+
+#[derive(Default)]
+struct Registry {
+    component: String,
+    tasks: Vec<TrackedTask>,
+    ended: bool,
+}
+
+lazy_static::lazy_static! {
+    /// The registries of the invocations in progress in the process, for
+    /// task dumps.
+    static ref INVOCATIONS: Mutex<Vec<Weak<Mutex<Registry>>>> = Default::default();
+}
+
+struct TrackedTask {
+    name: String,
+    started: Instant,
+    done: Arc<AtomicBool>,
+    // Set for async tasks, which are aborted if they outlive the invocation.
+    handle: Option<JoinHandle<()>>,
+}
+
+impl InvocationTasks {
+    /// Creates the task registry of an invocation of the given component.
+    pub(crate) fn new(component: &str) -> Self {
+        let registry = Arc::new(Mutex::new(Registry {
+            component: component.to_owned(),
+            ..Default::default()
+        }));
+        let mut invocations = INVOCATIONS.lock().unwrap();
+        invocations.retain(|r| r.strong_count() > 0);
+        invocations.push(Arc::downgrade(&registry));
+        Self { registry }
+    }
+
+    /// A spawner of tasks tracked by the invocation.
+    pub fn spawner(&self) -> TaskSpawner {
+        TaskSpawner {
+            registry: self.registry.clone(),
+        }
+    }
+
+    /// The names of the tasks still running.
+    pub fn running(&self) -> Vec<String> {
+        let registry = self.registry.lock().unwrap();
+        registry
+            .tasks
+            .iter()
+            .filter(|t| !t.done.load(Ordering::Acquire))
+            .map(|t| t.name.clone())
+            .collect()
+    }
+}
+
+impl Drop for InvocationTasks {
+    fn drop(&mut self) {
+        let mut registry = self.registry.lock().unwrap();
+        registry.ended = true;
+        for task in registry.tasks.drain(..) {
+            if task.done.load(Ordering::Acquire) {
+                continue;
+            }
+            let action = match task.handle {
+                Some(handle) => {
+                    handle.abort();
+                    "cancelled"
+                }
+                None => "left to finish",
+            };
+            report_leak(&format!(
+                "Task {} spawned by component {} outlived its invocation, and was {}",
+                task.name, registry.component, action
+            ));
+        }
+    }
+}
+
+impl TaskSpawner {
+    /// Spawns a background task on the Tokio runtime, cancelled if it is
+    /// still running when the invocation ends. Tasks spawned once the
+    /// invocation has ended are not run.
+    pub fn spawn(&self, name: &str, task: impl Future<Output = ()> + Send + 'static) {
+        let mut registry = self.registry.lock().unwrap();
+        if registry.ended {
+            report_leak(&format!(
+                "Task {} was spawned by component {} after its invocation ended, and was not run",
+                name, registry.component
+            ));
+            return;
+        }
+        registry.reap();
+        let done = Arc::new(AtomicBool::new(false));
+        let handle = tokio::spawn({
+            let done = done.clone();
+            async move {
+                task.await;
+                done.store(true, Ordering::Release);
+            }
+        });
+        registry.tasks.push(TrackedTask {
+            name: name.to_owned(),
+            started: Instant::now(),
+            done,
+            handle: Some(handle),
+        });
+    }
+
+    /// Runs blocking work on the Tokio blocking pool, returning its handle.
+    /// Blocking work cannot be cancelled, so if it is still running when the
+    /// invocation ends, it is reported but left to finish.
+    pub fn spawn_blocking<T: Send + 'static>(
+        &self,
+        name: &str,
+        work: impl FnOnce() -> T + Send + 'static,
+    ) -> JoinHandle<T> {
+        let done = Arc::new(AtomicBool::new(false));
+        let handle = tokio::task::spawn_blocking({
+            let done = done.clone();
+            move || {
+                let result = work();
+                done.store(true, Ordering::Release);
+                result
+            }
+        });
+        let mut registry = self.registry.lock().unwrap();
+        if registry.ended {
+            report_leak(&format!(
+                "Blocking task {} was spawned by component {} after its invocation ended",
+                name, registry.component
+            ));
+        } else {
+            registry.reap();
+            registry.tasks.push(TrackedTask {
+                name: name.to_owned(),
+                started: Instant::now(),
+                done,
+                handle: None,
+            });
+        }
+        handle
+    }
+}
+
+impl Registry {
+    /// Forgets the tasks that have finished, so long invocations spawning
+    /// many tasks do not accumulate them.
+    fn reap(&mut self) {
+        self.tasks.retain(|t| !t.done.load(Ordering::Acquire));
+    }
+}
+
+/// A task running on behalf of an invocation of a component.
+#[derive(Clone, Debug)]
+pub struct TaskInfo {
+    /// The component whose invocation spawned the task.
+    pub component: String,
+    /// The name of the task.
+    pub name: String,
+    /// Whether the task runs on the blocking pool.
+    pub blocking: bool,
+    /// How long the task has been running.
+    pub age: Duration,
+}
+
+/// The tasks running on behalf of the invocations in progress in the
+/// process, oldest first.
+pub fn task_dump() -> Vec<TaskInfo> {
+    let registries: Vec<_> = INVOCATIONS
+        .lock()
+        .unwrap()
+        .iter()
+        .filter_map(Weak::upgrade)
+        .collect();
+    let now = Instant::now();
+    let mut tasks: Vec<_> = registries
+        .iter()
+        .flat_map(|registry| {
+            let registry = registry.lock().unwrap();
+            registry
+                .tasks
+                .iter()
+                .filter(|t| !t.done.load(Ordering::Acquire))
+                .map(|t| TaskInfo {
+                    component: registry.component.clone(),
+                    name: t.name.clone(),
+                    blocking: t.handle.is_none(),
+                    age: now.saturating_duration_since(t.started),
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    tasks.sort_by(|a, b| b.age.cmp(&a.age));
+    tasks
+}
+
+fn report_leak(message: &str) {
+    if cfg!(debug_assertions) {
+        log::warn!("{}", message);
+    } else {
+        log::debug!("{}", message);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::oneshot;
+
+    #[tokio::test]
+    async fn test_tasks_are_cancelled_when_the_invocation_ends() {
+        let tasks = InvocationTasks::new("hello");
+        let spawner = tasks.spawner();
+
+        let (finished_tx, finished_rx) = oneshot::channel();
+        spawner.spawn("quick", async move {
+            finished_tx.send(()).unwrap();
+        });
+        finished_rx.await.unwrap();
+
+        let (_hold_tx, hold_rx) = oneshot::channel::<()>();
+        let (dropped_tx, dropped_rx) = oneshot::channel::<()>();
+        spawner.spawn("stray", async move {
+            // Dropped, closing the channel, when the task is aborted.
+            let _dropped_tx = dropped_tx;
+            let _ = hold_rx.await;
+        });
+        tokio::task::yield_now().await;
+        assert_eq!(tasks.running(), vec!["stray".to_owned()]);
+
+        drop(tasks);
+        assert!(dropped_rx.await.is_err());
+
+        // The invocation has ended, so further tasks are not run.
+        let (late_tx, late_rx) = oneshot::channel();
+        spawner.spawn("late", async move {
+            late_tx.send(()).unwrap();
+        });
+        assert!(late_rx.await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_blocking_tasks_are_tracked() {
+        let tasks = InvocationTasks::new("hello");
+        let result = tasks.spawner().spawn_blocking("work", || 42);
+        assert_eq!(result.await.unwrap(), 42);
+        assert!(tasks.running().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_task_dump() {
+        let tasks = InvocationTasks::new("dumped");
+        let (_hold_tx, hold_rx) = oneshot::channel::<()>();
+        tasks.spawner().spawn("waiting", async move {
+            let _ = hold_rx.await;
+        });
+        let dumped = |component: &str| {
+            task_dump()
+                .into_iter()
+                .filter(|t| t.component == component)
+                .map(|t| (t.name, t.blocking))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(dumped("dumped"), vec![("waiting".to_owned(), false)]);
+
+        drop(tasks);
+        assert!(dumped("dumped").is_empty());
+    }
+}
@@ -0,0 +1,92 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Mutex,
+    time::Duration,
+};
+
+/// The upper bounds of the buckets invocation durations are counted in, in
+/// seconds.
+pub const DURATION_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Whether a call into a guest failed, beyond failing to run at all.
+pub trait InvocationOutcome {
+    /// Whether the guest call failed.
+    fn is_failure(&self) -> bool;
+}
+
+impl<T, E> InvocationOutcome for Result<T, E> {
+    fn is_failure(&self) -> bool {
+        self.is_err()
+    }
+}
+
+/// Counts the invocations of the components of an application, their
+/// failures and their durations.
+#[derive(Debug, Default)]
+pub struct InvocationMeter {
+    components: Mutex<HashMap<String, InvocationStats>>,
+}
+
+/// The invocations of a component.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct InvocationStats {
+    /// The number of invocations.
+    pub invocations: u64,
+    /// The number of invocations that failed.
+    pub failures: u64,
+    /// The total duration of the invocations, in seconds.
+    pub duration_seconds: f64,
+    /// The number of invocations that took at most each of the
+    /// `DURATION_BUCKETS`.
+    pub duration_buckets: [u64; DURATION_BUCKETS.len()],
+}
+
+impl InvocationMeter {
+    /// Records an invocation of a component.
+    pub fn record(&self, component: &str, duration: Duration, failed: bool) {
+        let mut components = self.components.lock().unwrap();
+        let stats = components.entry(component.to_owned()).or_default();
+        let seconds = duration.as_secs_f64();
+        stats.invocations += 1;
+        if failed {
+            stats.failures += 1;
+        }
+        stats.duration_seconds += seconds;
+        for (bucket, bound) in stats.duration_buckets.iter_mut().zip(DURATION_BUCKETS) {
+            if seconds <= bound {
+                *bucket += 1;
+            }
+        }
+    }
+
+    /// Returns the invocations of each component that has been invoked.
+    pub fn stats(&self) -> BTreeMap<String, InvocationStats> {
+        let components = self.components.lock().unwrap();
+        components
+            .iter()
+            .map(|(id, stats)| (id.clone(), stats.clone()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_durations_are_counted_in_buckets() {
+        let meter = InvocationMeter::default();
+        meter.record("hello", Duration::from_millis(20), false);
+        meter.record("hello", Duration::from_secs(3), true);
+        meter.record("hello", Duration::from_secs(60), false);
+
+        let stats = &meter.stats()["hello"];
+        assert_eq!(stats.invocations, 3);
+        assert_eq!(stats.failures, 1);
+        assert!((stats.duration_seconds - 63.02).abs() < 1e-9);
+        // The buckets are cumulative, and the last invocation is in none.
+        assert_eq!(stats.duration_buckets, [0, 0, 1, 1, 1, 1, 1, 1, 1, 2, 2]);
+    }
+}
@@ -10,12 +10,16 @@ use wasi_common::{
     WasiFile,
 };
 
+use crate::logs::ComponentLogger;
+
 /// Prepares a WASI pipe which writes to a memory buffer, optionally
-/// copying to the specified output stream.
+/// copying to the specified output stream through the logger of the
+/// component.
 pub fn redirect_to_mem_buffer(
     follow: Follow,
+    logger: &ComponentLogger,
 ) -> (WritePipe<WriteDestinations>, Arc<RwLock<WriteDestinations>>) {
-    let immediate = follow.writer();
+    let immediate = follow.logged_writer(logger);
 
     let buffer: Vec<u8> = vec![];
     let std_dests = WriteDestinations { buffer, immediate };
@@ -79,8 +83,16 @@ impl Default for ModuleIoRedirects {
 impl ModuleIoRedirects {
     /// Constructs the ModuleIoRedirects, and RedirectReadHandles instances the default way
     pub fn new(follow: bool) -> Self {
-        let rrh = RedirectReadHandles::new(follow);
+        Self::from_read_handles(RedirectReadHandles::new(follow))
+    }
+
+    /// Constructs the ModuleIoRedirects of a component, whose output is
+    /// followed through its logger.
+    pub fn for_component(follow: bool, logger: &ComponentLogger) -> Self {
+        Self::from_read_handles(RedirectReadHandles::for_component(follow, logger))
+    }
 
+    fn from_read_handles(rrh: RedirectReadHandles) -> Self {
         let in_stdpipe: Box<dyn WasiFile> = Box::new(ReadPipe::from(vec![]));
         let out_stdpipe: Box<dyn WasiFile> = Box::new(WritePipe::from_shared(rrh.stdout.clone()));
         let err_stdpipe: Box<dyn WasiFile> = Box::new(WritePipe::from_shared(rrh.stderr.clone()));
@@ -134,9 +146,25 @@ impl Default for RedirectReadHandles {
 impl RedirectReadHandles {
     /// Creates a new RedirectReadHandles instance
     pub fn new(follow: bool) -> Self {
-        let out_immediate = Follow::stdout(follow).writer();
-        let err_immediate = Follow::stderr(follow).writer();
+        Self::with_immediate(
+            Follow::stdout(follow).writer(),
+            Follow::stderr(follow).writer(),
+        )
+    }
+
+    /// Creates the RedirectReadHandles of a component, whose output is
+    /// followed through its logger.
+    pub fn for_component(follow: bool, logger: &ComponentLogger) -> Self {
+        Self::with_immediate(
+            Follow::stdout(follow).logged_writer(logger),
+            Follow::stderr(follow).logged_writer(logger),
+        )
+    }
 
+    fn with_immediate(
+        out_immediate: Box<dyn Write + Send + Sync>,
+        err_immediate: Box<dyn Write + Send + Sync>,
+    ) -> Self {
         let out_buffer: Vec<u8> = vec![];
         let err_buffer: Vec<u8> = vec![];
 
@@ -197,6 +225,21 @@ impl Follow {
         }
     }
 
+    /// Writes the followed lines through the logger of the component, which
+    /// tags them with the component ID.
+    pub(crate) fn logged_writer(&self, logger: &ComponentLogger) -> Box<dyn Write + Send + Sync> {
+        let stream = match self {
+            Self::None => return Box::new(DiscardingWriter),
+            Self::Stdout => "stdout",
+            Self::Stderr => "stderr",
+        };
+        Box::new(LoggedWriter {
+            logger: logger.clone(),
+            stream,
+            partial: vec![],
+        })
+    }
+
     /// Follow on stdout if so specified.
     pub fn stdout(follow_on_stdout: bool) -> Self {
         if follow_on_stdout {
@@ -243,6 +286,52 @@ impl Write for WriteDestinations {
     }
 }
 
+/// Writes whole lines of a followed stream through the logger of the
+/// component, so that the lines of concurrent components are not mixed.
+struct LoggedWriter {
+    logger: ComponentLogger,
+    stream: &'static str,
+    /// The last line written, until it is complete.
+    partial: Vec<u8>,
+}
+
+impl LoggedWriter {
+    fn write_lines(&mut self, lines: &[u8]) -> std::io::Result<()> {
+        let mut out = vec![];
+        for line in lines.split_inclusive(|b| *b == b'\n') {
+            self.logger.write_line(&mut out, line, self.stream, true);
+        }
+        match self.stream {
+            "stderr" => std::io::stderr().write_all(&out),
+            _ => std::io::stdout().write_all(&out),
+        }
+    }
+}
+
+impl Write for LoggedWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.partial.extend_from_slice(buf);
+        if let Some(end) = self.partial.iter().rposition(|b| *b == b'\n') {
+            let lines: Vec<u8> = self.partial.drain(..=end).collect();
+            self.write_lines(&lines)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for LoggedWriter {
+    fn drop(&mut self) {
+        if !self.partial.is_empty() {
+            let line = std::mem::take(&mut self.partial);
+            let _ = self.write_lines(&line);
+        }
+    }
+}
+
 struct DiscardingWriter;
 
 impl Write for DiscardingWriter {
@@ -2,29 +2,79 @@
 
 #![deny(missing_docs)]
 
+mod availability;
+mod data_dir;
+mod egress;
 /// Host components.
 pub mod host_component;
+mod invocation_tasks;
+mod invocations;
 /// Input / Output redirects.
 pub mod io;
-
-use std::{collections::HashMap, io::Write, path::PathBuf, sync::Arc};
+mod limits;
+/// Component log files.
+pub mod logs;
+/// Compiled modules persisted across processes.
+pub mod module_cache;
+mod pool;
+mod quota_dir;
+mod scheduling;
+mod self_address;
+/// Trace context propagation across requests.
+pub mod telemetry;
+mod temp_dir;
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    future::Future,
+    io::Write,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
 
 use anyhow::{bail, Context, Result};
 use host_component::{HostComponent, HostComponents, HostComponentsState};
+use invocation_tasks::InvocationTasks;
 use io::{FollowComponents, OutputBuffers, RedirectPipes};
+use limits::Limiter;
+use logs::{ComponentLogger, LogFormat, LogRotationConfig};
+use module_cache::ModuleCacheDir;
+use pool::InstancePool;
+use sha2::{Digest, Sha256};
 use spin_config::{host_component::ComponentConfig, Resolver};
-use spin_manifest::{CoreComponent, DirectoryMount, ModuleSource};
+use spin_manifest::{CoreComponent, DirectoryMount, LoadPolicy, ModuleSource};
+use temp_dir::InvocationTempDir;
+use tempfile::TempDir;
 use tokio::{
+    sync::{Mutex as AsyncMutex, OwnedSemaphorePermit},
     task::JoinHandle,
     time::{sleep, Duration},
 };
 use tracing::{instrument, log};
 use wasi_common::WasiCtx;
-use wasmtime::{Instance, InstancePre, Linker, Module, Store};
+use wasmtime::{Instance, InstancePre, Linker, Module, Store, StoreLimits};
 use wasmtime_wasi::{ambient_authority, Dir, WasiCtxBuilder};
 
+pub use availability::{OnUnavailable, ServiceConfig, ServiceHealth, ServiceUnavailable};
+pub use data_dir::{DataDir, DataDirConfig, QuotaExceeded};
+pub use egress::{
+    EgressConfig, EgressKind, EgressLimits, EgressMeter, EgressQuotaExceeded, EgressStats,
+};
+pub use invocation_tasks::{task_dump, TaskInfo, TaskSpawner};
+pub use invocations::{InvocationMeter, InvocationOutcome, InvocationStats, DURATION_BUCKETS};
+pub use limits::LimitExceeded;
+pub use pool::InstancePoolConfig;
+pub use scheduling::SchedulingConfig;
+pub use self_address::SelfAddress;
+pub use temp_dir::{TempDirConfig, TempDirMode, GUEST_TEMP_DIR};
+
 const SPIN_HOME: &str = ".spin";
 
+/// The environment variable through which components can read the version
+/// of their application.
+pub const APP_VERSION_ENV: &str = "SPIN_APP_VERSION";
+
 /// Builder-specific configuration.
 #[derive(Clone, Debug, Default)]
 pub struct ExecutionContextConfiguration {
@@ -34,10 +84,44 @@ pub struct ExecutionContextConfiguration {
     pub label: String,
     /// Log directory on host.
     pub log_dir: Option<PathBuf>,
+    /// Log file rotation configuration.
+    pub log_rotation: LogRotationConfig,
+    /// The format component output is logged in.
+    pub log_format: LogFormat,
     /// Component log following configuration.
     pub follow_components: FollowComponents,
     /// Application configuration resolver.
     pub config_resolver: Option<Arc<Resolver>>,
+    /// Component temporary directory configuration.
+    pub temp_dir: TempDirConfig,
+    /// Version of the application, exposed to components as
+    /// `SPIN_APP_VERSION` unless they declare that variable themselves.
+    pub app_version: Option<String>,
+    /// Directory compiled modules are persisted in, if any.
+    pub module_cache: Option<ModuleCacheDir>,
+    /// How guests yield to other tasks. If not set, guests run until they
+    /// return, on blocking threads.
+    pub scheduling: Option<SchedulingConfig>,
+    /// The pool instances are allocated from. If not set, instances are
+    /// allocated on demand, without limit. The engine must be configured to
+    /// match.
+    pub instance_pool: Option<InstancePoolConfig>,
+}
+
+impl ExecutionContextConfiguration {
+    /// How the epoch advances: as scheduled, or by default if guests only
+    /// need interrupting at their timeout.
+    fn epoch_ticker(&self) -> Option<SchedulingConfig> {
+        let timeouts = self
+            .components
+            .iter()
+            .any(|c| c.wasm.limits.timeout_ms.is_some());
+        match &self.scheduling {
+            Some(scheduling) => Some(scheduling.clone()),
+            None if timeouts => Some(SchedulingConfig::default()),
+            None => None,
+        }
+    }
 }
 
 /// Top-level runtime context data to be passed to a component.
@@ -51,11 +135,23 @@ pub struct RuntimeContext<T> {
     pub host_components_state: HostComponentsState,
     /// Generic runtime data that can be configured by specialized engines.
     pub data: Option<T>,
+    /// The temporary directory of the invocation.
+    temp_dir: Option<InvocationTempDir>,
+    /// The tasks spawned on behalf of the invocation.
+    tasks: InvocationTasks,
+    /// The module the invocation runs, counting the invocations in flight.
+    generation: Option<Arc<()>>,
+    /// The limits on the memory of the instance.
+    limits: StoreLimits,
+    /// The reservations of the instance, against the concurrent instances of
+    /// the component and the slots of the instance pool.
+    permits: Vec<OwnedSemaphorePermit>,
 }
 
-/// The engine struct that encapsulate wasmtime engine
+/// The engine struct that encapsulate wasmtime engine, with a digest of its
+/// configuration identifying the modules it can reuse.
 #[derive(Clone, Default)]
-pub struct Engine(wasmtime::Engine);
+pub struct Engine(wasmtime::Engine, String);
 
 impl Engine {
     /// Create a new engine and initialize it with the given config.
@@ -65,7 +161,16 @@ impl Engine {
         // See https://github.com/bytecodealliance/wit-bindgen/blob/main/crates/wasmlink.
         config.wasm_multi_memory(true);
         config.wasm_module_linking(true);
-        Ok(Self(wasmtime::Engine::new(&config)?))
+        // Guests are called asynchronously, so that they can yield.
+        config.async_support(true);
+        let digest = Sha256::new()
+            .chain_update(env!("CARGO_PKG_VERSION"))
+            .chain_update(format!("{:?}", config))
+            .finalize();
+        Ok(Self(
+            wasmtime::Engine::new(&config)?,
+            module_cache::hex(&digest[..8]),
+        ))
     }
 
     /// Get a clone of the internal `wasmtime::Engine`.
@@ -83,9 +188,10 @@ pub struct Builder<T: Default> {
     store: Store<RuntimeContext<T>>,
     engine: Engine,
     host_components: HostComponents,
+    invocations: Arc<InvocationMeter>,
 }
 
-impl<T: Default + 'static> Builder<T> {
+impl<T: Default + Send + 'static> Builder<T> {
     /// Creates a new instance of the execution builder.
     pub fn new(config: ExecutionContextConfiguration) -> Result<Builder<T>> {
         Self::with_engine(config, Engine::new(Default::default())?)
@@ -107,6 +213,7 @@ impl<T: Default + 'static> Builder<T> {
             store,
             engine,
             host_components,
+            invocations: Default::default(),
         })
     }
 
@@ -134,44 +241,64 @@ impl<T: Default + 'static> Builder<T> {
         Ok(self)
     }
 
+    /// Counts the invocations of components with the given meter, which can
+    /// be read from outside the execution context.
+    pub fn invocation_meter(&mut self, meter: Arc<InvocationMeter>) -> &mut Self {
+        self.invocations = meter;
+        self
+    }
+
     /// Builds a new instance of the execution context.
     #[instrument(skip(self))]
     pub async fn build(mut self) -> Result<ExecutionContext<T>> {
         let _sloth_warning = warn_if_slothful();
         let mut components = HashMap::new();
+        let mut temp_dirs = HashMap::new();
+        let modules = ModuleCache::default();
+        let pool = match &self.config.instance_pool {
+            Some(config) => {
+                let reserved = self
+                    .config
+                    .components
+                    .iter()
+                    .filter_map(|c| c.wasm.limits.min_instances)
+                    .sum();
+                Some(Arc::new(InstancePool::new(config, reserved)?))
+            }
+            None => None,
+        };
         for c in &self.config.components {
-            let core = c.clone();
-            let module = match c.source.clone() {
-                ModuleSource::FileReference(p) => {
-                    let module = Module::from_file(&self.engine.0, &p).with_context(|| {
-                        format!(
-                            "Cannot create module for component {} from file {}",
-                            &c.id,
-                            &p.display()
-                        )
-                    })?;
-                    log::trace!("Created module for component {} from file {:?}", &c.id, &p);
-                    module
-                }
-                ModuleSource::Buffer(bytes, info) => {
-                    let module =
-                        Module::from_binary(&self.engine.0, &bytes).with_context(|| {
-                            format!("Cannot create module for component {} from {}", &c.id, info)
-                        })?;
-                    log::trace!(
-                        "Created module for component {} from {} with size {}",
-                        &c.id,
-                        info,
-                        bytes.len()
-                    );
-                    module
+            if self.config.temp_dir.mode == TempDirMode::PerComponent {
+                temp_dirs.insert(c.id.clone(), self.config.temp_dir.create(&c.id)?);
+            }
+
+            let loaded = match c.wasm.load {
+                LoadPolicy::Eager => Some(load(
+                    &self.engine,
+                    &self.linker,
+                    &mut self.store,
+                    &modules,
+                    self.config.module_cache.as_ref(),
+                    c,
+                )?),
+                LoadPolicy::Lazy => {
+                    log::trace!("Deferring loading component {} until it is invoked", &c.id);
+                    None
                 }
             };
+            let component = Component {
+                core: c.clone(),
+                loaded: Arc::new(Mutex::new(loaded)),
+                loading: Default::default(),
+                limiter: Limiter::new(&c.wasm.limits, pool.as_ref()),
+            };
+            components.insert(c.id.clone(), component);
+        }
 
-            let pre = Arc::new(self.linker.instantiate_pre(&mut self.store, &module)?);
-            log::trace!("Created pre-instance from module for component {}.", &c.id);
-
-            components.insert(c.id.clone(), Component { core, pre });
+        // Guests that do not yield are stopped at their timeout by epoch
+        // interruption, so the epoch advances for them too.
+        if let Some(scheduling) = self.config.epoch_ticker() {
+            scheduling.start_ticker(&self.engine.0);
         }
 
         log::trace!("Execution context initialized.");
@@ -181,6 +308,10 @@ impl<T: Default + 'static> Builder<T> {
             engine: self.engine,
             components,
             host_components: Arc::new(self.host_components),
+            temp_dirs: Arc::new(temp_dirs),
+            linker: Arc::new(self.linker),
+            modules: Arc::new(modules),
+            invocations: self.invocations,
         })
     }
 
@@ -204,8 +335,172 @@ impl<T: Default + 'static> Builder<T> {
 pub struct Component<T: Default> {
     /// Configuration for the component.
     pub core: CoreComponent,
-    /// The pre-instance of the component
-    pub pre: Arc<InstancePre<RuntimeContext<T>>>,
+    /// The pre-instance of the component, once it is loaded.
+    loaded: Arc<Mutex<Option<Loaded<T>>>>,
+    /// Held while the module of the component is compiled, so that it is
+    /// compiled once however many invocations wait for it.
+    loading: Arc<AsyncMutex<()>>,
+    /// The resource limits of the component.
+    limiter: Limiter,
+}
+
+impl<T: Default> Component<T> {
+    /// The pre-instance of the component and its generation, if it is loaded.
+    fn pre(&self) -> Option<(Arc<InstancePre<RuntimeContext<T>>>, Arc<()>)> {
+        let loaded = self.loaded.lock().unwrap();
+        loaded
+            .as_ref()
+            .map(|loaded| (loaded.pre.clone(), loaded.generation.clone()))
+    }
+}
+
+/// The compiled and linked module of a component.
+struct Loaded<T: Default> {
+    pre: Arc<InstancePre<RuntimeContext<T>>>,
+    /// The time taken to compile and link the module.
+    duration: Duration,
+    /// Held by the stores of the invocations of this module.
+    generation: Arc<()>,
+}
+
+/// The previous module of a reloaded component, until the invocations that
+/// were running it complete.
+pub struct Draining {
+    generation: Arc<()>,
+}
+
+impl Draining {
+    /// The number of invocations still running the previous module.
+    pub fn in_flight(&self) -> usize {
+        Arc::strong_count(&self.generation) - 1
+    }
+
+    /// Waits for the invocations running the previous module to complete.
+    pub async fn drained(self) {
+        while self.in_flight() > 0 {
+            sleep(Duration::from_millis(50)).await;
+        }
+    }
+}
+
+/// How the module of a component was loaded.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LoadStats {
+    /// When the module is compiled.
+    pub policy: LoadPolicy,
+    /// The time taken to compile and link the module, if it is loaded.
+    pub duration: Option<Duration>,
+}
+
+/// Compiled modules by the SHA-256 digest of their source, so that
+/// components with the same module, such as a file server on many routes,
+/// share one compiled module.
+type ModuleCache = Mutex<HashMap<Vec<u8>, Module>>;
+
+/// Compiles the module of a component, unless another component has the same
+/// module, and links it.
+fn load<T: Default>(
+    engine: &Engine,
+    linker: &Linker<RuntimeContext<T>>,
+    store: &mut Store<RuntimeContext<T>>,
+    modules: &ModuleCache,
+    disk: Option<&ModuleCacheDir>,
+    c: &CoreComponent,
+) -> Result<Loaded<T>> {
+    let start = Instant::now();
+    let module = match c.source.clone() {
+        ModuleSource::FileReference(p) => {
+            let bytes = std::fs::read(&p).with_context(|| {
+                format!(
+                    "Cannot read module for component {} from file {}",
+                    &c.id,
+                    &p.display()
+                )
+            })?;
+            compile(engine, modules, disk, &c.id, &bytes, |bytes| {
+                let module = Module::new(&engine.0, bytes).with_context(|| {
+                    format!(
+                        "Cannot create module for component {} from file {}",
+                        &c.id,
+                        &p.display()
+                    )
+                })?;
+                log::trace!("Created module for component {} from file {:?}", &c.id, &p);
+                Ok(module)
+            })?
+        }
+        ModuleSource::Buffer(bytes, info) => {
+            compile(engine, modules, disk, &c.id, &bytes, |bytes| {
+                let module = Module::from_binary(&engine.0, bytes).with_context(|| {
+                    format!("Cannot create module for component {} from {}", &c.id, info)
+                })?;
+                log::trace!(
+                    "Created module for component {} from {} with size {}",
+                    &c.id,
+                    info,
+                    bytes.len()
+                );
+                Ok(module)
+            })?
+        }
+    };
+
+    let pre = Arc::new(linker.instantiate_pre(store, &module)?);
+    log::trace!("Created pre-instance from module for component {}.", &c.id);
+
+    Ok(Loaded {
+        pre,
+        duration: start.elapsed(),
+        generation: Arc::new(()),
+    })
+}
+
+/// Returns the module compiled from the given source, compiling it if no
+/// module has been compiled from the same source, in this process or, if
+/// there is a cache directory, in an earlier one.
+fn compile(
+    engine: &Engine,
+    modules: &ModuleCache,
+    disk: Option<&ModuleCacheDir>,
+    component: &str,
+    bytes: &[u8],
+    create: impl FnOnce(&[u8]) -> Result<Module>,
+) -> Result<Module> {
+    ensure_core_module(component, bytes)?;
+    let digest = Sha256::digest(bytes).to_vec();
+    if let Some(module) = modules.lock().unwrap().get(&digest) {
+        log::trace!("Reusing module compiled for another component");
+        return Ok(module.clone());
+    }
+    let module = match disk.and_then(|disk| disk.get(engine, &digest)) {
+        Some(module) => module,
+        None => {
+            let module = create(bytes)?;
+            if let Some(disk) = disk {
+                // The cache only saves time, so failing to write it is not
+                // an error.
+                if let Err(e) = disk.put(engine, &digest, &module) {
+                    log::warn!("Failed to cache compiled module: {:#}", e);
+                }
+            }
+            module
+        }
+    };
+    modules.lock().unwrap().insert(digest, module.clone());
+    Ok(module)
+}
+
+/// Fails if the binary of a component is a component of the WebAssembly
+/// component model rather than a core module. Wasmtime would only report it
+/// as an invalid module.
+fn ensure_core_module(component: &str, bytes: &[u8]) -> Result<()> {
+    if spin_manifest::is_wasm_component(bytes) {
+        bail!(
+            "The module of component {} is a WebAssembly component. This version of Spin only runs core WebAssembly modules: build it without a component adapter, such as for the wasm32-wasi target",
+            component
+        );
+    }
+    Ok(())
 }
 
 /// A generic execution context for WebAssembly components.
@@ -219,31 +514,193 @@ pub struct ExecutionContext<T: Default> {
     pub components: HashMap<String, Component<T>>,
 
     host_components: Arc<HostComponents>,
+    // Per-component temporary directories, removed when the last clone of
+    // the execution context is dropped.
+    temp_dirs: Arc<HashMap<String, TempDir>>,
+    // The linker for components loaded once they are invoked.
+    linker: Arc<Linker<RuntimeContext<T>>>,
+    // Compiled modules, shared by components with the same module.
+    modules: Arc<ModuleCache>,
+    // Counts the invocations of components.
+    invocations: Arc<InvocationMeter>,
 }
 
 impl<T: Default> ExecutionContext<T> {
     /// Creates a store for a given component given its configuration and runtime data.
     #[instrument(skip(self, data, io))]
-    pub fn prepare_component(
+    pub async fn prepare_component(
         &self,
         component: &str,
         data: Option<T>,
         io: Option<RedirectPipes>,
         env: Option<HashMap<String, String>>,
         args: Option<Vec<String>>,
-    ) -> Result<(Store<RuntimeContext<T>>, Instance)> {
+    ) -> Result<(Store<RuntimeContext<T>>, Instance)>
+    where
+        T: Send + 'static,
+    {
         log::trace!("Preparing component {}", component);
         let component = match self.components.get(component) {
             Some(c) => c,
             None => bail!("Cannot find component {}", component),
         };
 
+        let permits = component.limiter.acquire().await?;
+        let (pre, generation) = self.load(component).await?;
         let mut store = self.store(component, data, io, env, args)?;
-        let instance = component.pre.instantiate(&mut store)?;
+        store.data_mut().generation = Some(generation);
+        store.data_mut().permits = permits;
+        let instance = pre.instantiate_async(&mut store).await?;
 
         Ok((store, instance))
     }
 
+    /// Runs a call into a guest of the given component. If guests yield, the
+    /// call runs on the current task like any other future; otherwise it runs
+    /// on a blocking thread, so that a guest computing for a long time cannot
+    /// hold an executor thread. Calls running past the timeout of the
+    /// component fail with `LimitExceeded::Timeout`. Calls are counted as
+    /// invocations of the component, failed if they return a failure.
+    pub async fn run_guest<R: InvocationOutcome + Send + 'static>(
+        &self,
+        component: &str,
+        call: impl Future<Output = R> + Send + 'static,
+    ) -> Result<R> {
+        let limiter = match self.components.get(component) {
+            Some(c) => &c.limiter,
+            None => bail!("Cannot find component {}", component),
+        };
+        let start = std::time::Instant::now();
+        let result = if self.config.scheduling.is_some() {
+            limiter.run(async move { Ok(call.await) }).await
+        } else {
+            let handle = tokio::runtime::Handle::current();
+            let blocking = tokio::task::spawn_blocking(move || handle.block_on(call));
+            limiter.run(async move { Ok(blocking.await?) }).await
+        };
+        let failed = match &result {
+            Ok(outcome) => outcome.is_failure(),
+            Err(_) => true,
+        };
+        self.invocations.record(component, start.elapsed(), failed);
+        result
+    }
+
+    /// Returns the invocations of each component that has been invoked.
+    pub fn invocation_stats(&self) -> BTreeMap<String, InvocationStats> {
+        self.invocations.stats()
+    }
+
+    /// Whether the module of the given component has been loaded.
+    pub fn is_loaded(&self, component: &str) -> bool {
+        match self.components.get(component) {
+            Some(c) => c.loaded.lock().unwrap().is_some(),
+            None => false,
+        }
+    }
+
+    /// Returns how the module of each component was loaded.
+    pub fn load_stats(&self) -> BTreeMap<String, LoadStats> {
+        self.components
+            .iter()
+            .map(|(id, c)| {
+                let stats = LoadStats {
+                    policy: c.core.wasm.load,
+                    duration: c.loaded.lock().unwrap().as_ref().map(|l| l.duration),
+                };
+                (id.clone(), stats)
+            })
+            .collect()
+    }
+
+    /// Reloads the module of a component from its source, for example after
+    /// its file was rebuilt. New invocations run the new module, while those
+    /// in flight complete with the previous one, which is returned to wait
+    /// for them. Components that have not been loaded yet are left to load
+    /// on their first invocation, and if the new module fails to compile the
+    /// previous one is kept.
+    pub async fn reload(&self, component: &str) -> Result<Option<Draining>>
+    where
+        T: Send + 'static,
+    {
+        let component = match self.components.get(component) {
+            Some(c) => c,
+            None => bail!("Cannot find component {}", component),
+        };
+        let _loading = component.loading.lock().await;
+        if component.loaded.lock().unwrap().is_none() {
+            return Ok(None);
+        }
+        // Invocations keep running the previous module while the new one
+        // compiles.
+        let reloaded = self.compile(component).await?;
+        log::info!(
+            "Reloaded component {} in {}ms",
+            component.core.id,
+            reloaded.duration.as_millis()
+        );
+        let previous = component.loaded.lock().unwrap().replace(reloaded);
+        Ok(previous.map(|previous| Draining {
+            generation: previous.generation,
+        }))
+    }
+
+    /// Returns the pre-instance of a component, with the generation its
+    /// invocations hold, loading it first if it is loaded lazily and has not
+    /// been invoked yet. Concurrent invocations wait for the component to
+    /// load, so that it is only loaded once.
+    async fn load(
+        &self,
+        component: &Component<T>,
+    ) -> Result<(Arc<InstancePre<RuntimeContext<T>>>, Arc<()>)>
+    where
+        T: Send + 'static,
+    {
+        if let Some(pre) = component.pre() {
+            return Ok(pre);
+        }
+        let _loading = component.loading.lock().await;
+        if let Some(pre) = component.pre() {
+            return Ok(pre);
+        }
+        let lazy = self.compile(component).await?;
+        log::info!(
+            "Loaded component {} on first invocation in {}ms",
+            component.core.id,
+            lazy.duration.as_millis()
+        );
+        let pre = (lazy.pre.clone(), lazy.generation.clone());
+        *component.loaded.lock().unwrap() = Some(lazy);
+        Ok(pre)
+    }
+
+    /// Compiles and links the module of a component on a blocking thread, so
+    /// that compiling does not hold an executor thread.
+    async fn compile(&self, component: &Component<T>) -> Result<Loaded<T>>
+    where
+        T: Send + 'static,
+    {
+        let engine = self.engine.clone();
+        let linker = self.linker.clone();
+        let modules = self.modules.clone();
+        let disk = self.config.module_cache.clone();
+        let core = component.core.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut store = Store::new(&engine.0, RuntimeContext::default());
+            load(&engine, &linker, &mut store, &modules, disk.as_ref(), &core)
+        })
+        .await?
+    }
+
+    /// Returns the logger of the output of a component.
+    pub fn logger(&self, component: &str) -> ComponentLogger {
+        let level = self
+            .components
+            .get(component)
+            .and_then(|c| c.core.wasm.log_level);
+        ComponentLogger::new(component, level, self.config.log_format)
+    }
+
     /// Save logs for a given component in the log directory on the host
     pub fn save_output_to_logs(
         &self,
@@ -252,49 +709,35 @@ impl<T: Default> ExecutionContext<T> {
         save_stdout: bool,
         save_stderr: bool,
     ) -> Result<()> {
-        let sanitized_label = sanitize(&self.config.label);
-        let sanitized_component_name = sanitize(&component);
-
-        let log_dir = match &self.config.log_dir {
-            Some(l) => l.clone(),
-            None => match dirs::home_dir() {
-                Some(h) => h.join(SPIN_HOME).join(&sanitized_label).join("logs"),
-                None => PathBuf::from(&sanitized_label).join("logs"),
-            },
-        };
-
-        let stdout_filename = log_dir.join(sanitize(format!(
-            "{}_{}.txt",
-            sanitized_component_name, "stdout",
-        )));
-
-        let stderr_filename = log_dir.join(sanitize(format!(
-            "{}_{}.txt",
-            sanitized_component_name, "stderr"
-        )));
+        let log_dir = logs::log_dir(&self.config.label, self.config.log_dir.as_deref());
+        let stdout_filename = logs::log_file(&log_dir, component, "stdout");
+        let stderr_filename = logs::log_file(&log_dir, component, "stderr");
 
         std::fs::create_dir_all(&log_dir)?;
 
         log::trace!("Saving logs to {:?} {:?}", stdout_filename, stderr_filename);
+        let logger = self.logger(component);
 
         if save_stdout {
+            self.config.log_rotation.rotate_if_due(&stdout_filename)?;
             let mut file = std::fs::OpenOptions::new()
                 .write(true)
                 .append(true)
                 .create(true)
                 .open(stdout_filename)?;
-            let contents = ior.stdout();
-            file.write_all(contents)?;
+            let contents = logger.format_output(ior.stdout(), "stdout");
+            file.write_all(&contents)?;
         }
 
         if save_stderr {
+            self.config.log_rotation.rotate_if_due(&stderr_filename)?;
             let mut file = std::fs::OpenOptions::new()
                 .write(true)
                 .append(true)
                 .create(true)
                 .open(stderr_filename)?;
-            let contents = ior.stderr();
-            file.write_all(contents)?;
+            let contents = logger.format_output(ior.stderr(), "stderr");
+            file.write_all(&contents)?;
         }
 
         Ok(())
@@ -309,7 +752,12 @@ impl<T: Default> ExecutionContext<T> {
         args: Option<Vec<String>>,
     ) -> Result<Store<RuntimeContext<T>>> {
         log::trace!("Creating store.");
-        let (env, dirs) = Self::wasi_config(component, env)?;
+        let (mut env, dirs) = Self::wasi_config(component, env)?;
+        if let Some(version) = &self.config.app_version {
+            if !env.iter().any(|(k, _)| k == APP_VERSION_ENV) {
+                env.push((APP_VERSION_ENV.to_owned(), version.clone()));
+            }
+        }
         let mut ctx = RuntimeContext::default();
         let mut wasi_ctx = WasiCtxBuilder::new()
             .args(&args.unwrap_or_default())?
@@ -321,6 +769,7 @@ impl<T: Default> ExecutionContext<T> {
             None => wasi_ctx = wasi_ctx.inherit_stdio(),
         };
 
+        let mounts_temp_dir = dirs.iter().any(|dir| dir.guest == GUEST_TEMP_DIR);
         for dir in dirs {
             let guest = dir.guest;
             let host = dir.host;
@@ -328,20 +777,70 @@ impl<T: Default> ExecutionContext<T> {
                 wasi_ctx.preopened_dir(Dir::open_ambient_dir(host, ambient_authority())?, guest)?;
         }
 
+        // Components that mount their own files at the temporary directory
+        // path keep them.
+        if !mounts_temp_dir {
+            ctx.temp_dir = self.temp_dir(&component.core.id)?;
+        }
+
         if let Some(resolver) = &self.config.config_resolver {
             ctx.component_config =
                 Some(ComponentConfig::new(&component.core.id, resolver.clone())?);
         }
 
-        ctx.host_components_state = self.host_components.build_state(&component.core)?;
+        ctx.tasks = InvocationTasks::new(&component.core.id);
+        ctx.host_components_state = self
+            .host_components
+            .build_state(&component.core, &ctx.tasks.spawner())?;
 
-        ctx.wasi = Some(wasi_ctx.build());
+        let mut wasi = wasi_ctx.build();
+        if let Some(temp_dir) = &ctx.temp_dir {
+            wasi.push_preopened_dir(temp_dir.wasi_dir()?, GUEST_TEMP_DIR)?;
+        }
+        ctx.wasi = Some(wasi);
         ctx.data = data;
 
-        let store = Store::new(&self.engine.0, ctx);
+        let limit_memory = match component.limiter.store_limits() {
+            Some(limits) => {
+                ctx.limits = limits;
+                true
+            }
+            None => false,
+        };
+
+        let mut store = Store::new(&self.engine.0, ctx);
+        if limit_memory {
+            store.limiter(|ctx| &mut ctx.limits);
+        }
+        match (&self.config.scheduling, component.limiter.timeout()) {
+            (Some(scheduling), _) => scheduling.configure_store(&mut store),
+            // A guest that does not yield keeps running on its blocking thread
+            // past its timeout, until it traps at the epoch deadline.
+            (None, Some(timeout)) => {
+                let tick = SchedulingConfig::default().epoch_tick_ms.max(1);
+                store.set_epoch_deadline(timeout.as_millis() as u64 / tick + 2);
+            }
+            (None, None) => store.set_epoch_deadline(u64::MAX / 2),
+        }
         Ok(store)
     }
 
+    fn temp_dir(&self, component: &str) -> Result<Option<InvocationTempDir>> {
+        let config = &self.config.temp_dir;
+        Ok(match config.mode {
+            TempDirMode::PerInvocation => Some(InvocationTempDir::owned(
+                component,
+                config.create(component)?,
+                config.max_bytes,
+            )),
+            TempDirMode::PerComponent => self
+                .temp_dirs
+                .get(component)
+                .map(|dir| InvocationTempDir::shared(component, dir.path(), config.max_bytes)),
+            TempDirMode::Disabled => None,
+        })
+    }
+
     #[allow(clippy::type_complexity)]
     fn wasi_config(
         component: &Component<T>,
@@ -414,3 +913,77 @@ async fn warn_slow() {
     println!("Preparing Wasm modules is taking a few seconds...");
     println!();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn execution_context(module: &std::path::Path) -> Result<ExecutionContext<()>> {
+        let component = CoreComponent {
+            source: ModuleSource::FileReference(module.to_owned()),
+            id: "test".to_owned(),
+            description: None,
+            wasm: spin_manifest::WasmConfig {
+                load: LoadPolicy::Lazy,
+                ..Default::default()
+            },
+        };
+        let config = ExecutionContextConfiguration {
+            components: vec![component],
+            temp_dir: TempDirConfig {
+                mode: TempDirMode::Disabled,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        Builder::build_default(config).await
+    }
+
+    #[tokio::test]
+    async fn test_reloading_drains_the_previous_module() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let module = dir.path().join("test.wat");
+        std::fs::write(&module, "(module)")?;
+        let ctx = execution_context(&module).await?;
+
+        // Components that have not been invoked load on their first invocation.
+        assert!(ctx.reload("test").await?.is_none());
+        assert!(!ctx.is_loaded("test"));
+        let (store, _) = ctx
+            .prepare_component("test", None, None, None, None)
+            .await?;
+        assert!(ctx.is_loaded("test"));
+
+        std::fs::write(&module, r#"(module (func (export "_start")))"#)?;
+        let draining = ctx.reload("test").await?.expect("the module was loaded");
+        assert_eq!(draining.in_flight(), 1);
+        let (mut reloaded, instance) = ctx
+            .prepare_component("test", None, None, None, None)
+            .await?;
+        assert!(instance.get_func(&mut reloaded, "_start").is_some());
+        assert_eq!(draining.in_flight(), 1);
+
+        drop(store);
+        assert_eq!(draining.in_flight(), 0);
+        tokio::time::timeout(Duration::from_secs(1), draining.drained()).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_failed_reloads_keep_the_previous_module() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let module = dir.path().join("test.wat");
+        std::fs::write(&module, r#"(module (func (export "_start")))"#)?;
+        let ctx = execution_context(&module).await?;
+        ctx.prepare_component("test", None, None, None, None)
+            .await?;
+
+        std::fs::write(&module, "(module")?;
+        assert!(ctx.reload("test").await.is_err());
+        let (mut store, instance) = ctx
+            .prepare_component("test", None, None, None, None)
+            .await?;
+        assert!(instance.get_func(&mut store, "_start").is_some());
+        Ok(())
+    }
+}
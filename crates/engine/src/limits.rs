@@ -0,0 +1,190 @@
+use std::{future::Future, sync::Arc, time::Duration};
+
+use spin_manifest::ComponentLimits;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use wasmtime::{StoreLimits, StoreLimitsBuilder};
+
+use crate::pool::InstancePool;
+
+/// Enforces the resource limits of a component on its invocations.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Limiter {
+    max_memory_bytes: Option<usize>,
+    timeout: Option<Duration>,
+    instances: Option<(usize, Arc<Semaphore>)>,
+    /// Invocations waiting for an instance, if they may wait.
+    queue: Option<Arc<Semaphore>>,
+    /// The instance pool, and the slots the component reserves in it.
+    pool: Option<(Arc<InstancePool>, Option<Arc<Semaphore>>)>,
+}
+
+impl Limiter {
+    pub(crate) fn new(limits: &ComponentLimits, pool: Option<&Arc<InstancePool>>) -> Self {
+        Self {
+            max_memory_bytes: limits
+                .max_memory_bytes
+                .map(|max| usize::try_from(max).unwrap_or(usize::MAX)),
+            timeout: limits.timeout_ms.map(Duration::from_millis),
+            instances: limits
+                .max_concurrent
+                .map(|max| (max, Arc::new(Semaphore::new(max)))),
+            queue: limits.max_queued.map(|max| Arc::new(Semaphore::new(max))),
+            pool: pool.map(|pool| {
+                let reserved = limits
+                    .min_instances
+                    .map(|min| Arc::new(Semaphore::new(min)));
+                (pool.clone(), reserved)
+            }),
+        }
+    }
+
+    /// How long an invocation may run for, if limited.
+    pub(crate) fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    /// Reserves an instance of the component, and its slot in the instance
+    /// pool, released once the permits are dropped with the store of the
+    /// instance. Invocations wait for an instance while the queue of the
+    /// component has room.
+    pub(crate) async fn acquire(&self) -> Result<Vec<OwnedSemaphorePermit>, LimitExceeded> {
+        let mut permits = vec![];
+        if let Some((max, instances)) = &self.instances {
+            let permit = match instances.clone().try_acquire_owned() {
+                Ok(permit) => permit,
+                Err(_) => {
+                    let _queued = self
+                        .queue
+                        .as_ref()
+                        .and_then(|queue| queue.clone().try_acquire_owned().ok())
+                        .ok_or(LimitExceeded::Instances(*max))?;
+                    // The semaphore is never closed.
+                    instances
+                        .clone()
+                        .acquire_owned()
+                        .await
+                        .expect("instance limit closed")
+                }
+            };
+            permits.push(permit);
+        }
+        if let Some((pool, reserved)) = &self.pool {
+            permits.push(pool.acquire(reserved.as_ref()).await?);
+        }
+        Ok(permits)
+    }
+
+    /// The limits on the memory of an instance, if limited.
+    pub(crate) fn store_limits(&self) -> Option<StoreLimits> {
+        self.max_memory_bytes
+            .map(|max| StoreLimitsBuilder::new().memory_size(max).build())
+    }
+
+    /// Runs a call into a guest, failing once it runs past the timeout.
+    pub(crate) async fn run<R>(
+        &self,
+        call: impl Future<Output = anyhow::Result<R>>,
+    ) -> anyhow::Result<R> {
+        match self.timeout {
+            Some(timeout) => tokio::time::timeout(timeout, call)
+                .await
+                .map_err(|_| LimitExceeded::Timeout(timeout))?,
+            None => call.await,
+        }
+    }
+}
+
+/// An invocation of a component exceeded one of its resource limits.
+#[derive(Debug, PartialEq, Eq)]
+pub enum LimitExceeded {
+    /// The invocation ran for longer than the timeout of the component.
+    Timeout(Duration),
+    /// The component was running its maximum number of instances, with as
+    /// many invocations waiting as its queue holds.
+    Instances(usize),
+    /// The instance pool was full, with as many invocations waiting as its
+    /// queue holds.
+    Pool(u32),
+}
+
+impl std::fmt::Display for LimitExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Timeout(timeout) => write!(
+                f,
+                "the invocation exceeded the timeout of {}ms",
+                timeout.as_millis()
+            ),
+            Self::Instances(max) => write!(f, "the limit of {} concurrent instances", max),
+            Self::Pool(max) => write!(f, "the {} instances of the instance pool", max),
+        }
+    }
+}
+
+impl std::error::Error for LimitExceeded {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(limits: &str) -> Limiter {
+        Limiter::new(&toml::from_str(limits).unwrap(), None)
+    }
+
+    #[tokio::test]
+    async fn test_max_concurrent() {
+        let limiter = limiter("max_concurrent = 1");
+        let first = limiter.acquire().await.unwrap();
+        assert_eq!(first.len(), 1);
+        assert_eq!(
+            limiter.acquire().await.unwrap_err(),
+            LimitExceeded::Instances(1)
+        );
+        drop(first);
+        assert_eq!(limiter.acquire().await.unwrap().len(), 1);
+
+        assert!(Limiter::default().acquire().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_max_queued() {
+        let limiter = Arc::new(limiter("max_concurrent = 1\nmax_queued = 1"));
+        let busy = limiter.acquire().await.unwrap();
+
+        let waiting = {
+            let limiter = limiter.clone();
+            tokio::spawn(async move { limiter.acquire().await.map(|_| ()) })
+        };
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(
+            limiter.acquire().await.unwrap_err(),
+            LimitExceeded::Instances(1)
+        );
+
+        drop(busy);
+        tokio::time::timeout(Duration::from_secs(10), waiting)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_timeout() {
+        let limiter = limiter("timeout_ms = 10");
+        let ok = limiter.run(async { Ok(42) }).await.unwrap();
+        assert_eq!(ok, 42);
+
+        let err = limiter
+            .run(async {
+                tokio::time::sleep(Duration::from_secs(10)).await;
+                Ok(())
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<LimitExceeded>(),
+            Some(&LimitExceeded::Timeout(Duration::from_millis(10)))
+        );
+    }
+}
@@ -0,0 +1,396 @@
+use std::{
+    borrow::Cow,
+    fs::File,
+    io::{BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{bail, Result};
+use serde::Deserialize;
+use spin_manifest::LogLevel;
+use tracing::log;
+
+use crate::{sanitize, SPIN_HOME};
+
+fn default_max_files() -> usize {
+    5
+}
+
+/// Rotation of component log files.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub struct LogRotationConfig {
+    /// Rotate a log file once it reaches this size.
+    pub max_bytes: Option<u64>,
+    /// Rotate a log file once it is this old, in seconds.
+    pub max_age_secs: Option<u64>,
+    /// The number of rotated files kept for each log file.
+    #[serde(default = "default_max_files")]
+    pub max_files: usize,
+    /// Whether rotated files are compressed with gzip.
+    #[serde(default)]
+    pub compress: bool,
+}
+
+impl Default for LogRotationConfig {
+    fn default() -> Self {
+        Self {
+            max_bytes: None,
+            max_age_secs: None,
+            max_files: default_max_files(),
+            compress: false,
+        }
+    }
+}
+
+impl LogRotationConfig {
+    /// Rotates the given log file if it is due, so that the next write
+    /// starts a new file.
+    pub(crate) fn rotate_if_due(&self, path: &Path) -> Result<()> {
+        let metadata = match std::fs::metadata(path) {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+        let too_large = matches!(self.max_bytes, Some(max) if metadata.len() >= max);
+        // File creation times are not available on all platforms, in
+        // which case only size-based rotation applies.
+        let too_old = match (self.max_age_secs, metadata.created()) {
+            (Some(max), Ok(created)) => {
+                created.elapsed().unwrap_or_default() >= Duration::from_secs(max)
+            }
+            _ => false,
+        };
+        if !too_large && !too_old {
+            return Ok(());
+        }
+
+        let millis = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
+        let mut rotated = path.as_os_str().to_owned();
+        rotated.push(format!(".{:016}", millis));
+        let rotated = PathBuf::from(rotated);
+        match std::fs::rename(path, &rotated) {
+            Ok(()) => log::trace!("Rotated log file {:?} to {:?}", path, rotated),
+            // Another invocation rotated it first.
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e.into()),
+        }
+
+        if self.compress {
+            // Compress in the background, so that large files do not delay
+            // the invocation that triggered the rotation, and prune once the
+            // compressed file replaced the rotated one.
+            let (config, path) = (self.clone(), path.to_owned());
+            std::thread::spawn(move || {
+                if let Err(e) = compress(&rotated) {
+                    log::warn!("Cannot compress log file {:?}: {}", rotated, e);
+                }
+                if let Err(e) = config.prune(&path) {
+                    log::warn!("Cannot prune rotated log files of {:?}: {}", path, e);
+                }
+            });
+            return Ok(());
+        }
+        self.prune(path)
+    }
+
+    /// Removes the oldest rotated files of the given log file, keeping
+    /// `max_files` of them.
+    fn prune(&self, path: &Path) -> Result<()> {
+        let rotated_files = rotated_files(path)?;
+        let excess = rotated_files.len().saturating_sub(self.max_files);
+        for old in &rotated_files[..excess] {
+            // An uncompressed file may have a partially compressed copy.
+            let mut compressed = old.as_os_str().to_owned();
+            compressed.push(".gz");
+            for file in [old, &PathBuf::from(compressed)] {
+                match std::fs::remove_file(file) {
+                    Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                        log::warn!("Cannot remove rotated log file {:?}: {}", file, e)
+                    }
+                    _ => (),
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The format component output is written in, to log files and when it is
+/// followed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogFormat {
+    /// The output as the component wrote it. Followed lines are prefixed
+    /// with the component ID.
+    Text,
+    /// A JSON object per line, with the time, component, stream, level and
+    /// message of the line.
+    Json,
+}
+
+impl Default for LogFormat {
+    fn default() -> Self {
+        Self::Text
+    }
+}
+
+impl FromStr for LogFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            _ => bail!("Unknown log format {:?}: expected text or json", s),
+        }
+    }
+}
+
+/// Logs the output of a component: drops the lines less severe than the log
+/// level of the component, and formats the others.
+#[derive(Clone, Debug)]
+pub struct ComponentLogger {
+    component: String,
+    level: Option<LogLevel>,
+    format: LogFormat,
+}
+
+impl ComponentLogger {
+    /// Creates the logger of a component, keeping all its output if it has
+    /// no log level.
+    pub fn new(component: impl Into<String>, level: Option<LogLevel>, format: LogFormat) -> Self {
+        Self {
+            component: component.into(),
+            level,
+            format,
+        }
+    }
+
+    /// Formats the output a component wrote to a stream, either `stdout` or
+    /// `stderr`, for its log file.
+    pub(crate) fn format_output<'a>(&self, output: &'a [u8], stream: &str) -> Cow<'a, [u8]> {
+        if self.level.is_none() && self.format == LogFormat::Text {
+            return Cow::Borrowed(output);
+        }
+        let mut formatted = vec![];
+        for line in output.split_inclusive(|b| *b == b'\n') {
+            self.write_line(&mut formatted, line, stream, false);
+        }
+        Cow::Owned(formatted)
+    }
+
+    /// Writes a line of output of the component, unless it is less severe
+    /// than the log level of the component. Text lines are prefixed with the
+    /// component ID if `tagged`, as when output is followed.
+    pub(crate) fn write_line(&self, out: &mut Vec<u8>, line: &[u8], stream: &str, tagged: bool) {
+        let line = String::from_utf8_lossy(line);
+        let message = line.trim_end_matches(&['\r', '\n'][..]);
+        let level = line_level(message).unwrap_or(if stream == "stderr" {
+            LogLevel::Error
+        } else {
+            LogLevel::Info
+        });
+        if matches!(self.level, Some(min) if level < min) {
+            return;
+        }
+        // Writing to a vector cannot fail.
+        match self.format {
+            LogFormat::Text if tagged => writeln!(out, "[{}] {}", self.component, message),
+            LogFormat::Text => writeln!(out, "{}", message),
+            LogFormat::Json => {
+                let record = serde_json::json!({
+                    "timestamp": chrono::Utc::now()
+                        .to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+                    "component": self.component,
+                    "stream": stream,
+                    "level": level.as_str(),
+                    "message": message,
+                });
+                writeln!(out, "{}", record)
+            }
+        }
+        .unwrap();
+    }
+}
+
+/// The level a line of output starts with, such as `WARN` or `[debug]`.
+fn line_level(line: &str) -> Option<LogLevel> {
+    let line = line.trim_start();
+    let line = line.strip_prefix('[').unwrap_or(line);
+    let word_len = line
+        .find(|c: char| !c.is_ascii_alphabetic())
+        .unwrap_or(line.len());
+    match line[..word_len].to_ascii_lowercase().as_str() {
+        "trace" => Some(LogLevel::Trace),
+        "debug" => Some(LogLevel::Debug),
+        "info" => Some(LogLevel::Info),
+        "warn" | "warning" => Some(LogLevel::Warn),
+        "error" => Some(LogLevel::Error),
+        _ => None,
+    }
+}
+
+fn compress(path: &Path) -> Result<()> {
+    let mut compressed = path.as_os_str().to_owned();
+    compressed.push(".gz");
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut encoder = flate2::write::GzEncoder::new(
+        BufWriter::new(File::create(&compressed)?),
+        flate2::Compression::default(),
+    );
+    std::io::copy(&mut reader, &mut encoder)?;
+    encoder.finish()?;
+    std::fs::remove_file(path)?;
+    Ok(())
+}
+
+/// Returns the directory holding the logs of the given application.
+pub fn log_dir(label: &str, log_dir: Option<&Path>) -> PathBuf {
+    let sanitized_label = sanitize(label);
+    match log_dir {
+        Some(l) => l.to_path_buf(),
+        None => match dirs::home_dir() {
+            Some(h) => h.join(SPIN_HOME).join(&sanitized_label).join("logs"),
+            None => PathBuf::from(&sanitized_label).join("logs"),
+        },
+    }
+}
+
+/// Returns the path of the log file for the given component output stream,
+/// either `stdout` or `stderr`.
+pub fn log_file(log_dir: &Path, component: &str, stream: &str) -> PathBuf {
+    let sanitized_component_name = sanitize(component);
+    log_dir.join(sanitize(format!(
+        "{}_{}.txt",
+        sanitized_component_name, stream
+    )))
+}
+
+/// Returns the rotated files of the given log file, oldest first. A file
+/// being compressed is returned once, uncompressed, as its compressed copy
+/// is not complete yet.
+pub fn rotated_files(path: &Path) -> Result<Vec<PathBuf>> {
+    let (dir, name) = match (path.parent(), path.file_name().and_then(|n| n.to_str())) {
+        (Some(dir), Some(name)) => (dir, name),
+        _ => return Ok(vec![]),
+    };
+    let prefix = format!("{}.", name);
+    let mut rotated: Vec<_> = std::fs::read_dir(dir)?
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let file_name = entry.file_name();
+            let suffix = file_name.to_str()?.strip_prefix(&prefix)?;
+            let timestamp = suffix.strip_suffix(".gz").unwrap_or(suffix);
+            if timestamp.is_empty() || !timestamp.bytes().all(|b| b.is_ascii_digit()) {
+                return None;
+            }
+            Some((timestamp.to_string(), entry.path()))
+        })
+        .collect();
+    // Sorting puts an uncompressed file before its compressed copy.
+    rotated.sort();
+    rotated.dedup_by(|later, earlier| later.0 == earlier.0);
+    Ok(rotated.into_iter().map(|(_, path)| path).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_size_based_rotation_keeps_max_files() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("component_stdout.txt");
+        let config = LogRotationConfig {
+            max_bytes: Some(4),
+            max_files: 2,
+            ..Default::default()
+        };
+
+        for _ in 0..4 {
+            std::fs::write(&path, "12345")?;
+            config.rotate_if_due(&path)?;
+            assert!(!path.exists());
+            // Rotated file names have millisecond precision.
+            std::thread::sleep(Duration::from_millis(2));
+        }
+        assert_eq!(rotated_files(&path)?.len(), 2);
+
+        std::fs::write(&path, "123")?;
+        config.rotate_if_due(&path)?;
+        assert!(path.exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_files_being_compressed_are_counted_once() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("component_stdout.txt");
+        for name in [
+            "component_stdout.txt.0001.gz",
+            "component_stdout.txt.0002",
+            "component_stdout.txt.0002.gz",
+            "component_stdout.txt.0003.gz",
+            "component_stdout.txt.0004",
+        ] {
+            std::fs::write(dir.path().join(name), "")?;
+        }
+        assert_eq!(
+            rotated_files(&path)?,
+            [
+                "component_stdout.txt.0001.gz",
+                "component_stdout.txt.0002",
+                "component_stdout.txt.0003.gz",
+                "component_stdout.txt.0004",
+            ]
+            .map(|name| dir.path().join(name))
+        );
+
+        let config = LogRotationConfig {
+            max_files: 2,
+            ..Default::default()
+        };
+        config.prune(&path)?;
+        assert_eq!(
+            std::fs::read_dir(dir.path())?.count(),
+            2,
+            "the partially compressed copy is removed too"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_output_is_filtered_by_level() {
+        let logger = ComponentLogger::new("hello", Some(LogLevel::Warn), LogFormat::Text);
+        let output = b"DEBUG connecting\n[warn] slow response\nplain output\n";
+        assert_eq!(
+            logger.format_output(output, "stdout").as_ref(),
+            b"[warn] slow response\n"
+        );
+        // Lines without a level are errors on stderr.
+        assert_eq!(
+            logger.format_output(b"plain output", "stderr").as_ref(),
+            b"plain output\n"
+        );
+
+        let all = ComponentLogger::new("hello", None, LogFormat::Text);
+        assert_eq!(all.format_output(output, "stdout").as_ref(), output);
+        let mut followed = vec![];
+        all.write_line(&mut followed, b"info: ready\n", "stdout", true);
+        assert_eq!(followed, b"[hello] info: ready\n");
+    }
+
+    #[test]
+    fn test_json_output() -> Result<()> {
+        let logger = ComponentLogger::new("hello", None, LogFormat::Json);
+        let output = logger.format_output(b"ERROR failed\n", "stdout");
+        let record: serde_json::Value = serde_json::from_slice(&output)?;
+        assert_eq!(record["component"], "hello");
+        assert_eq!(record["stream"], "stdout");
+        assert_eq!(record["level"], "error");
+        assert_eq!(record["message"], "ERROR failed");
+        Ok(())
+    }
+}
@@ -0,0 +1,256 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+use spin_manifest::{CoreComponent, ModuleSource};
+use tracing::log;
+use wasmtime::Module;
+
+use crate::Engine;
+
+/// The extension of compiled modules in the cache directory.
+const COMPILED_EXTENSION: &str = "cwasm";
+
+/// The default directory compiled modules are persisted in.
+pub fn default_dir() -> Option<PathBuf> {
+    dirs::data_local_dir().map(|dir| dir.join("spin").join("modules"))
+}
+
+/// Compiled modules persisted in a directory, keyed by the digest of their
+/// source and the configuration of the engine that compiled them, so that
+/// they are reused across processes and applications.
+#[derive(Clone, Debug)]
+pub struct ModuleCacheDir {
+    path: PathBuf,
+}
+
+/// The contents of a module cache directory.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ModuleCacheStats {
+    /// The number of compiled modules.
+    pub entries: usize,
+    /// The total size of the compiled modules, in bytes.
+    pub bytes: u64,
+}
+
+/// The outcome of compiling the modules of components ahead of time.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PrecompileStats {
+    /// The number of modules compiled and added to the cache.
+    pub compiled: usize,
+    /// The number of modules that were already in the cache.
+    pub cached: usize,
+}
+
+impl ModuleCacheDir {
+    /// Creates a cache persisting compiled modules in the given directory.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// The directory compiled modules are persisted in.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the module compiled from the source with the given digest by
+    /// an engine with the same configuration, if there is one. Entries that
+    /// cannot be read, for example because they were compiled by another
+    /// version of Wasmtime, are ignored.
+    pub(crate) fn get(&self, engine: &Engine, digest: &[u8]) -> Option<Module> {
+        let path = self.entry(engine, digest);
+        let bytes = std::fs::read(&path).ok()?;
+        // Safety: entries are only written by `put`, from modules serialized
+        // by Wasmtime, and Wasmtime checks that they are compatible with the
+        // engine.
+        match unsafe { Module::deserialize(&engine.0, &bytes) } {
+            Ok(module) => {
+                log::trace!("Loaded compiled module from {:?}", path);
+                Some(module)
+            }
+            Err(e) => {
+                log::debug!("Ignoring compiled module {:?}: {:#}", path, e);
+                None
+            }
+        }
+    }
+
+    /// Persists the module compiled from the source with the given digest.
+    pub(crate) fn put(&self, engine: &Engine, digest: &[u8], module: &Module) -> Result<()> {
+        std::fs::create_dir_all(&self.path)
+            .with_context(|| format!("Cannot create module cache directory {:?}", self.path))?;
+        let path = self.entry(engine, digest);
+        // Written to a temporary file first, so that other processes never
+        // read a partial entry.
+        let mut temp = tempfile::NamedTempFile::new_in(&self.path)?;
+        std::io::Write::write_all(&mut temp, &module.serialize()?)?;
+        temp.persist(&path)
+            .with_context(|| format!("Cannot write compiled module {:?}", path))?;
+        Ok(())
+    }
+
+    /// Compiles the modules of the given components with the engine, unless
+    /// they are already in the cache, so that loading them later only reads
+    /// the cache. Components sharing a module compile it once.
+    pub fn precompile(
+        &self,
+        engine: &Engine,
+        components: &[CoreComponent],
+    ) -> Result<PrecompileStats> {
+        let mut stats = PrecompileStats::default();
+        let mut seen = HashSet::new();
+        for c in components {
+            let (bytes, origin) = match &c.source {
+                ModuleSource::FileReference(p) => {
+                    let bytes = std::fs::read(p).with_context(|| {
+                        format!(
+                            "Cannot read module for component {} from file {}",
+                            &c.id,
+                            p.display()
+                        )
+                    })?;
+                    (bytes, p.display().to_string())
+                }
+                ModuleSource::Buffer(bytes, info) => (bytes.clone(), info.clone()),
+            };
+            crate::ensure_core_module(&c.id, &bytes)?;
+            let digest = Sha256::digest(&bytes).to_vec();
+            if !seen.insert(digest.clone()) {
+                continue;
+            }
+            if self.entry(engine, &digest).exists() {
+                log::trace!("Module for component {} is already compiled", &c.id);
+                stats.cached += 1;
+                continue;
+            }
+            let module = Module::new(&engine.0, &bytes).with_context(|| {
+                format!(
+                    "Cannot create module for component {} from {}",
+                    &c.id, origin
+                )
+            })?;
+            self.put(engine, &digest, &module)?;
+            log::trace!("Compiled module for component {} from {}", &c.id, origin);
+            stats.compiled += 1;
+        }
+        Ok(stats)
+    }
+
+    /// Returns the number and total size of the compiled modules.
+    pub fn stats(&self) -> Result<ModuleCacheStats> {
+        let mut stats = ModuleCacheStats::default();
+        let entries = match std::fs::read_dir(&self.path) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(stats),
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("Cannot read module cache directory {:?}", self.path))
+            }
+        };
+        for entry in entries {
+            let entry = entry?;
+            if entry.path().extension().and_then(|e| e.to_str()) == Some(COMPILED_EXTENSION) {
+                stats.entries += 1;
+                stats.bytes += entry.metadata()?.len();
+            }
+        }
+        Ok(stats)
+    }
+
+    fn entry(&self, engine: &Engine, digest: &[u8]) -> PathBuf {
+        self.path.join(format!(
+            "{}-{}.{}",
+            hex(digest),
+            engine.1,
+            COMPILED_EXTENSION
+        ))
+    }
+}
+
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compiled_modules_are_reused() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let cache = ModuleCacheDir::new(dir.path().join("modules"));
+        assert_eq!(cache.stats()?, ModuleCacheStats::default());
+
+        let engine = Engine::new(Default::default())?;
+        let digest = [1u8; 32];
+        assert!(cache.get(&engine, &digest).is_none());
+
+        let module = Module::new(&engine.0, "(module (func (export \"run\")))")?;
+        cache.put(&engine, &digest, &module)?;
+        let cached = cache.get(&engine, &digest).unwrap();
+        assert!(cached.get_export("run").is_some());
+        assert_eq!(cache.stats()?.entries, 1);
+
+        // A corrupt entry is ignored rather than failing the load.
+        std::fs::write(cache.entry(&engine, &digest), b"corrupt")?;
+        assert!(cache.get(&engine, &digest).is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_precompile() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let cache = ModuleCacheDir::new(dir.path().join("modules"));
+        let engine = Engine::new(Default::default())?;
+        let wat = b"(module (func (export \"run\")))".to_vec();
+        let component = |id: &str| CoreComponent {
+            source: ModuleSource::Buffer(wat.clone(), "test".to_owned()),
+            id: id.to_owned(),
+            description: None,
+            wasm: Default::default(),
+        };
+        let components = [component("a"), component("b")];
+
+        let stats = cache.precompile(&engine, &components)?;
+        assert_eq!(
+            stats,
+            PrecompileStats {
+                compiled: 1,
+                cached: 0
+            }
+        );
+        let digest = Sha256::digest(&wat);
+        assert!(cache.get(&engine, &digest).is_some());
+
+        let stats = cache.precompile(&engine, &components)?;
+        assert_eq!(
+            stats,
+            PrecompileStats {
+                compiled: 0,
+                cached: 1
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_components_are_rejected() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let cache = ModuleCacheDir::new(dir.path().join("modules"));
+        let engine = Engine::new(Default::default())?;
+        // The preamble of a component: magic number, version and layer.
+        let binary = b"\0asm\x0d\0\x01\0".to_vec();
+        let component = CoreComponent {
+            source: ModuleSource::Buffer(binary, "test".to_owned()),
+            id: "a".to_owned(),
+            description: None,
+            wasm: Default::default(),
+        };
+        let err = cache.precompile(&engine, &[component]).unwrap_err();
+        assert!(err.to_string().contains("is a WebAssembly component"));
+        Ok(())
+    }
+}
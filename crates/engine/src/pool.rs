@@ -0,0 +1,203 @@
+use std::sync::Arc;
+
+use anyhow::{bail, Result};
+use serde::Deserialize;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use wasmtime::{
+    InstanceAllocationStrategy, InstanceLimits, ModuleLimits, PoolingAllocationStrategy,
+};
+
+use crate::LimitExceeded;
+
+const WASM_PAGE_SIZE: u64 = 64 * 1024;
+
+/// Runtime configuration for the pool of instances components run in.
+///
+/// Slots for instances are reserved when the application starts and reused
+/// across invocations, rather than allocated for each invocation, and
+/// invocations wait for a free slot once all of them are in use.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "snake_case")]
+pub struct InstancePoolConfig {
+    /// The number of slots, that is the maximum number of instances running
+    /// at once across components.
+    pub max_instances: u32,
+    /// The maximum size of the linear memory of an instance, in bytes. Each
+    /// slot reserves address space for it.
+    pub max_memory_bytes: u64,
+    /// The maximum number of invocations waiting for a free slot. If not
+    /// set, the queue is not limited.
+    pub max_queued: Option<usize>,
+}
+
+impl Default for InstancePoolConfig {
+    fn default() -> Self {
+        Self {
+            max_instances: 1000,
+            max_memory_bytes: 256 * 1024 * 1024,
+            max_queued: None,
+        }
+    }
+}
+
+impl InstancePoolConfig {
+    /// Makes the engine allocate instances from the slots of the pool.
+    pub fn configure_engine(&self, config: &mut wasmtime::Config) {
+        config.allocation_strategy(InstanceAllocationStrategy::Pooling {
+            strategy: PoolingAllocationStrategy::ReuseAffinity,
+            // The defaults suit small modules, and modules built with
+            // wit-bindgen and the standard library often exceed them.
+            module_limits: ModuleLimits {
+                imported_functions: 10_000,
+                types: 10_000,
+                functions: 100_000,
+                globals: 1_000,
+                table_elements: 20_000,
+                memory_pages: (self.max_memory_bytes / WASM_PAGE_SIZE).max(1),
+                ..Default::default()
+            },
+            instance_limits: InstanceLimits {
+                count: self.max_instances,
+            },
+        });
+    }
+}
+
+/// Admits invocations to the slots of the instance pool. Components may
+/// reserve slots, which the other components cannot use.
+#[derive(Debug)]
+pub(crate) struct InstancePool {
+    max_instances: u32,
+    shared: Arc<Semaphore>,
+    queue: Option<Arc<Semaphore>>,
+}
+
+impl InstancePool {
+    /// Creates the pool, less the slots reserved by components.
+    pub(crate) fn new(config: &InstancePoolConfig, reserved: usize) -> Result<Self> {
+        let max_instances = config.max_instances as usize;
+        if max_instances == 0 {
+            bail!("The instance pool must hold at least one instance");
+        }
+        if reserved > max_instances {
+            bail!(
+                "Components reserve {} instances, more than the {} instances of the pool",
+                reserved,
+                max_instances
+            );
+        }
+        Ok(Self {
+            max_instances: config.max_instances,
+            shared: Arc::new(Semaphore::new(max_instances - reserved)),
+            queue: config.max_queued.map(|max| Arc::new(Semaphore::new(max))),
+        })
+    }
+
+    /// Takes a slot, reserved by the component or else shared, waiting for
+    /// one to be free unless the queue is full.
+    pub(crate) async fn acquire(
+        &self,
+        reserved: Option<&Arc<Semaphore>>,
+    ) -> Result<OwnedSemaphorePermit, LimitExceeded> {
+        let free = reserved
+            .and_then(|r| r.clone().try_acquire_owned().ok())
+            .or_else(|| self.shared.clone().try_acquire_owned().ok());
+        if let Some(permit) = free {
+            return Ok(permit);
+        }
+
+        let _queued = match &self.queue {
+            Some(queue) => Some(
+                queue
+                    .clone()
+                    .try_acquire_owned()
+                    .map_err(|_| LimitExceeded::Pool(self.max_instances))?,
+            ),
+            None => None,
+        };
+        let permit = match reserved {
+            Some(reserved) => tokio::select! {
+                permit = reserved.clone().acquire_owned() => permit,
+                permit = self.shared.clone().acquire_owned() => permit,
+            },
+            None => self.shared.clone().acquire_owned().await,
+        };
+        // The semaphores are never closed.
+        Ok(permit.expect("instance pool closed"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn pool(max_instances: u32, reserved: usize, max_queued: Option<usize>) -> InstancePool {
+        let config = InstancePoolConfig {
+            max_instances,
+            max_queued,
+            ..Default::default()
+        };
+        InstancePool::new(&config, reserved).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_reserved_slots() {
+        let pool = pool(2, 1, Some(0));
+        let reserved = Arc::new(Semaphore::new(1));
+
+        // The component takes its reserved slot, then the shared one, which
+        // leaves none for other components.
+        let first = pool.acquire(Some(&reserved)).await.unwrap();
+        let second = pool.acquire(Some(&reserved)).await.unwrap();
+        assert_eq!(
+            pool.acquire(None).await.unwrap_err(),
+            LimitExceeded::Pool(2)
+        );
+
+        // Other components cannot take the reserved slot.
+        drop(first);
+        assert_eq!(
+            pool.acquire(None).await.unwrap_err(),
+            LimitExceeded::Pool(2)
+        );
+        drop(second);
+        pool.acquire(None).await.unwrap();
+
+        let config = InstancePoolConfig {
+            max_instances: 2,
+            ..Default::default()
+        };
+        assert!(InstancePool::new(&config, 3).is_err());
+        let config = InstancePoolConfig {
+            max_instances: 0,
+            ..Default::default()
+        };
+        assert!(InstancePool::new(&config, 0).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_queue() {
+        let pool = Arc::new(pool(1, 0, Some(1)));
+        let busy = pool.acquire(None).await.unwrap();
+
+        let waiting = {
+            let pool = pool.clone();
+            tokio::spawn(async move { pool.acquire(None).await.map(|_| ()) })
+        };
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        // The queue holds one invocation.
+        assert_eq!(
+            pool.acquire(None).await.unwrap_err(),
+            LimitExceeded::Pool(1)
+        );
+
+        drop(busy);
+        tokio::time::timeout(Duration::from_secs(10), waiting)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+    }
+}
@@ -0,0 +1,388 @@
+//! WASI directories that limit the size of the files written in them.
+
+use std::{
+    any::Any,
+    io::{IoSlice, IoSliceMut, SeekFrom},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use async_trait::async_trait;
+use wasi_common::{
+    dir::{ReaddirCursor, ReaddirEntity, WasiDir},
+    file::{Advice, FdFlags, FileType, Filestat, OFlags, WasiFile},
+    Error, ErrorExt, SystemTimeSpec,
+};
+
+/// The size of the files in a directory, and the most it may grow to.
+pub(crate) struct Quota {
+    used: AtomicU64,
+    max_bytes: u64,
+}
+
+impl Quota {
+    pub(crate) fn new(used: u64, max_bytes: u64) -> Arc<Self> {
+        Arc::new(Self {
+            used: AtomicU64::new(used),
+            max_bytes,
+        })
+    }
+
+    /// Fails if the files may not grow by the given number of bytes.
+    fn check(&self, growth: u64) -> Result<(), Error> {
+        let used = self.used.load(Ordering::Relaxed);
+        if growth > 0 && used.saturating_add(growth) > self.max_bytes {
+            return Err(Error::perm().context(format!(
+                "temporary directory would exceed its {} byte limit",
+                self.max_bytes
+            )));
+        }
+        Ok(())
+    }
+
+    /// Records that a file changed size.
+    fn record(&self, before: u64, after: u64) {
+        if after >= before {
+            self.used.fetch_add(after - before, Ordering::Relaxed);
+        } else {
+            let _ = self
+                .used
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| {
+                    Some(used.saturating_sub(before - after))
+                });
+        }
+    }
+}
+
+/// A directory whose files, and those of its subdirectories, fail to grow
+/// past the quota. Other operations are those of the wrapped directory.
+pub(crate) struct QuotaDir {
+    inner: Box<dyn WasiDir>,
+    quota: Arc<Quota>,
+}
+
+impl QuotaDir {
+    pub(crate) fn new(inner: Box<dyn WasiDir>, quota: Arc<Quota>) -> Self {
+        Self { inner, quota }
+    }
+}
+
+#[async_trait]
+impl WasiDir for QuotaDir {
+    // Renames and links downcast the target directory to that of the wrapped
+    // directory.
+    fn as_any(&self) -> &dyn Any {
+        self.inner.as_any()
+    }
+
+    async fn open_file(
+        &self,
+        symlink_follow: bool,
+        path: &str,
+        oflags: OFlags,
+        read: bool,
+        write: bool,
+        fdflags: FdFlags,
+    ) -> Result<Box<dyn WasiFile>, Error> {
+        let truncated = if oflags.contains(OFlags::TRUNCATE) {
+            self.inner
+                .get_path_filestat(path, symlink_follow)
+                .await
+                .map(|stat| stat.size)
+                .unwrap_or(0)
+        } else {
+            0
+        };
+        let file = self
+            .inner
+            .open_file(symlink_follow, path, oflags, read, write, fdflags)
+            .await?;
+        self.quota.record(truncated, 0);
+        if !write {
+            return Ok(file);
+        }
+        Ok(Box::new(QuotaFile {
+            inner: file,
+            quota: self.quota.clone(),
+        }))
+    }
+
+    async fn open_dir(&self, symlink_follow: bool, path: &str) -> Result<Box<dyn WasiDir>, Error> {
+        let dir = self.inner.open_dir(symlink_follow, path).await?;
+        Ok(Box::new(QuotaDir::new(dir, self.quota.clone())))
+    }
+
+    async fn create_dir(&self, path: &str) -> Result<(), Error> {
+        self.inner.create_dir(path).await
+    }
+
+    async fn readdir(
+        &self,
+        cursor: ReaddirCursor,
+    ) -> Result<Box<dyn Iterator<Item = Result<ReaddirEntity, Error>> + Send>, Error> {
+        self.inner.readdir(cursor).await
+    }
+
+    async fn symlink(&self, old_path: &str, new_path: &str) -> Result<(), Error> {
+        self.inner.symlink(old_path, new_path).await
+    }
+
+    async fn remove_dir(&self, path: &str) -> Result<(), Error> {
+        self.inner.remove_dir(path).await
+    }
+
+    async fn unlink_file(&self, path: &str) -> Result<(), Error> {
+        // Files with other links keep their content.
+        let freed = match self.inner.get_path_filestat(path, false).await {
+            Ok(stat) if stat.nlink <= 1 => stat.size,
+            _ => 0,
+        };
+        self.inner.unlink_file(path).await?;
+        self.quota.record(freed, 0);
+        Ok(())
+    }
+
+    async fn read_link(&self, path: &str) -> Result<PathBuf, Error> {
+        self.inner.read_link(path).await
+    }
+
+    async fn get_filestat(&self) -> Result<Filestat, Error> {
+        self.inner.get_filestat().await
+    }
+
+    async fn get_path_filestat(
+        &self,
+        path: &str,
+        follow_symlinks: bool,
+    ) -> Result<Filestat, Error> {
+        self.inner.get_path_filestat(path, follow_symlinks).await
+    }
+
+    async fn rename(
+        &self,
+        path: &str,
+        dest_dir: &dyn WasiDir,
+        dest_path: &str,
+    ) -> Result<(), Error> {
+        self.inner.rename(path, dest_dir, dest_path).await
+    }
+
+    async fn hard_link(
+        &self,
+        path: &str,
+        target_dir: &dyn WasiDir,
+        target_path: &str,
+    ) -> Result<(), Error> {
+        self.inner.hard_link(path, target_dir, target_path).await
+    }
+
+    async fn set_times(
+        &self,
+        path: &str,
+        atime: Option<SystemTimeSpec>,
+        mtime: Option<SystemTimeSpec>,
+        follow_symlinks: bool,
+    ) -> Result<(), Error> {
+        self.inner
+            .set_times(path, atime, mtime, follow_symlinks)
+            .await
+    }
+}
+
+/// A file opened for writing in a quota directory.
+struct QuotaFile {
+    inner: Box<dyn WasiFile>,
+    quota: Arc<Quota>,
+}
+
+impl QuotaFile {
+    async fn size(&self) -> Result<u64, Error> {
+        Ok(self.inner.get_filestat().await?.size)
+    }
+
+    /// Fails if writing the given number of bytes at the position would grow
+    /// the file past the quota. Returns the size of the file.
+    async fn check_write(&self, position: Option<u64>, len: u64) -> Result<u64, Error> {
+        let size = self.size().await?;
+        let position = match position {
+            Some(position) => position,
+            None if self.inner.get_fdflags().await?.contains(FdFlags::APPEND) => size,
+            None => self.inner.seek(SeekFrom::Current(0)).await?,
+        };
+        self.quota
+            .check(position.saturating_add(len).saturating_sub(size))?;
+        Ok(size)
+    }
+
+    /// Records the change of size of the file since it was of the given size.
+    async fn record(&self, before: u64) -> Result<(), Error> {
+        self.quota.record(before, self.size().await?);
+        Ok(())
+    }
+}
+
+fn total_len(bufs: &[IoSlice<'_>]) -> u64 {
+    bufs.iter().map(|buf| buf.len() as u64).sum()
+}
+
+#[async_trait]
+impl WasiFile for QuotaFile {
+    fn as_any(&self) -> &dyn Any {
+        self.inner.as_any()
+    }
+
+    async fn sock_accept(&mut self, fdflags: FdFlags) -> Result<Box<dyn WasiFile>, Error> {
+        self.inner.sock_accept(fdflags).await
+    }
+
+    async fn datasync(&self) -> Result<(), Error> {
+        self.inner.datasync().await
+    }
+
+    async fn sync(&self) -> Result<(), Error> {
+        self.inner.sync().await
+    }
+
+    async fn get_filetype(&self) -> Result<FileType, Error> {
+        self.inner.get_filetype().await
+    }
+
+    async fn get_fdflags(&self) -> Result<FdFlags, Error> {
+        self.inner.get_fdflags().await
+    }
+
+    async fn set_fdflags(&mut self, flags: FdFlags) -> Result<(), Error> {
+        self.inner.set_fdflags(flags).await
+    }
+
+    async fn get_filestat(&self) -> Result<Filestat, Error> {
+        self.inner.get_filestat().await
+    }
+
+    async fn set_filestat_size(&self, size: u64) -> Result<(), Error> {
+        let before = self.size().await?;
+        self.quota.check(size.saturating_sub(before))?;
+        self.inner.set_filestat_size(size).await?;
+        self.record(before).await
+    }
+
+    async fn advise(&self, offset: u64, len: u64, advice: Advice) -> Result<(), Error> {
+        self.inner.advise(offset, len, advice).await
+    }
+
+    async fn allocate(&self, offset: u64, len: u64) -> Result<(), Error> {
+        let before = self.check_write(Some(offset), len).await?;
+        self.inner.allocate(offset, len).await?;
+        self.record(before).await
+    }
+
+    async fn set_times(
+        &self,
+        atime: Option<SystemTimeSpec>,
+        mtime: Option<SystemTimeSpec>,
+    ) -> Result<(), Error> {
+        self.inner.set_times(atime, mtime).await
+    }
+
+    async fn read_vectored<'a>(&self, bufs: &mut [IoSliceMut<'a>]) -> Result<u64, Error> {
+        self.inner.read_vectored(bufs).await
+    }
+
+    async fn read_vectored_at<'a>(
+        &self,
+        bufs: &mut [IoSliceMut<'a>],
+        offset: u64,
+    ) -> Result<u64, Error> {
+        self.inner.read_vectored_at(bufs, offset).await
+    }
+
+    async fn write_vectored<'a>(&self, bufs: &[IoSlice<'a>]) -> Result<u64, Error> {
+        let before = self.check_write(None, total_len(bufs)).await?;
+        let written = self.inner.write_vectored(bufs).await?;
+        self.record(before).await?;
+        Ok(written)
+    }
+
+    async fn write_vectored_at<'a>(&self, bufs: &[IoSlice<'a>], offset: u64) -> Result<u64, Error> {
+        let before = self.check_write(Some(offset), total_len(bufs)).await?;
+        let written = self.inner.write_vectored_at(bufs, offset).await?;
+        self.record(before).await?;
+        Ok(written)
+    }
+
+    async fn seek(&self, pos: SeekFrom) -> Result<u64, Error> {
+        self.inner.seek(pos).await
+    }
+
+    async fn peek(&self, buf: &mut [u8]) -> Result<u64, Error> {
+        self.inner.peek(buf).await
+    }
+
+    async fn num_ready_bytes(&self) -> Result<u64, Error> {
+        self.inner.num_ready_bytes().await
+    }
+
+    fn isatty(&self) -> bool {
+        self.inner.isatty()
+    }
+
+    async fn readable(&self) -> Result<(), Error> {
+        self.inner.readable().await
+    }
+
+    async fn writable(&self) -> Result<(), Error> {
+        self.inner.writable().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasmtime_wasi::{ambient_authority, Dir};
+
+    fn quota_dir(path: &std::path::Path, max_bytes: u64) -> anyhow::Result<QuotaDir> {
+        let dir = Dir::open_ambient_dir(path, ambient_authority())?;
+        Ok(QuotaDir::new(
+            Box::new(wasi_cap_std_sync::dir::Dir::from_cap_std(dir)),
+            Quota::new(0, max_bytes),
+        ))
+    }
+
+    async fn create(dir: &QuotaDir, path: &str) -> anyhow::Result<Box<dyn WasiFile>> {
+        Ok(dir
+            .open_file(false, path, OFlags::CREATE, false, true, FdFlags::empty())
+            .await?)
+    }
+
+    #[tokio::test]
+    async fn test_writes_past_the_quota_fail() -> anyhow::Result<()> {
+        let root = tempfile::tempdir()?;
+        let dir = quota_dir(root.path(), 16)?;
+
+        let file = create(&dir, "scratch.txt").await?;
+        file.write_vectored(&[IoSlice::new(&[0; 10])]).await?;
+        // Overwriting does not grow the file.
+        file.write_vectored_at(&[IoSlice::new(&[1; 10])], 0).await?;
+        assert!(file
+            .write_vectored(&[IoSlice::new(&[0; 10])])
+            .await
+            .is_err());
+        assert!(create(&dir, "other.txt")
+            .await?
+            .set_filestat_size(10)
+            .await
+            .is_err());
+
+        // Removing files frees their space.
+        drop(file);
+        dir.unlink_file("scratch.txt").await?;
+        create(&dir, "other.txt")
+            .await?
+            .write_vectored(&[IoSlice::new(&[0; 16])])
+            .await?;
+        Ok(())
+    }
+}
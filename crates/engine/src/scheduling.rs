@@ -0,0 +1,84 @@
+use std::time::Duration;
+
+use serde::Deserialize;
+use wasmtime::Store;
+
+/// Cooperative scheduling of guests, so that a guest computing for a long
+/// time yields to other tasks rather than holding an executor thread.
+///
+/// Guests are interrupted at epoch boundaries: the engine's epoch advances
+/// every `epoch_tick_ms`, and a guest yields once it has run for
+/// `yield_ticks` epochs since it started or last yielded.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "snake_case")]
+pub struct SchedulingConfig {
+    /// How often the epoch advances, in milliseconds.
+    pub epoch_tick_ms: u64,
+    /// The number of epochs a guest runs for before yielding.
+    pub yield_ticks: u64,
+}
+
+impl Default for SchedulingConfig {
+    fn default() -> Self {
+        Self {
+            epoch_tick_ms: 10,
+            yield_ticks: 1,
+        }
+    }
+}
+
+impl SchedulingConfig {
+    /// Makes guests in the store yield every `yield_ticks` epochs.
+    pub(crate) fn configure_store<T>(&self, store: &mut Store<T>) {
+        store.set_epoch_deadline(self.yield_ticks);
+        store.epoch_deadline_async_yield_and_update(self.yield_ticks);
+    }
+
+    /// Advances the epoch of the engine every `epoch_tick_ms`, for as long as
+    /// the process runs.
+    pub(crate) fn start_ticker(&self, engine: &wasmtime::Engine) {
+        let engine = engine.clone();
+        let interval = Duration::from_millis(self.epoch_tick_ms.max(1));
+        std::thread::Builder::new()
+            .name("spin-epoch-ticker".to_owned())
+            .spawn(move || loop {
+                std::thread::sleep(interval);
+                engine.increment_epoch();
+            })
+            .expect("failed to start the epoch ticker thread");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wasmtime::{Instance, Module};
+
+    use super::*;
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_spinning_guest_yields_to_other_tasks() -> anyhow::Result<()> {
+        let mut config = wasmtime::Config::new();
+        config.epoch_interruption(true);
+        let engine = crate::Engine::new(config)?.inner();
+        let scheduling = SchedulingConfig {
+            epoch_tick_ms: 1,
+            yield_ticks: 1,
+        };
+        scheduling.start_ticker(&engine);
+
+        let module = Module::new(&engine, r#"(module (func (export "spin") (loop br 0)))"#)?;
+        let mut store = Store::new(&engine, ());
+        scheduling.configure_store(&mut store);
+        let instance = Instance::new_async(&mut store, &module, &[]).await?;
+        let spin = instance.get_typed_func::<(), (), _>(&mut store, "spin")?;
+        let guest = tokio::spawn(async move { spin.call_async(&mut store, ()).await });
+
+        // The runtime has a single thread, so other tasks only run while the
+        // guest, which never returns, yields.
+        let other = tokio::spawn(async { 42 });
+        let answer = tokio::time::timeout(Duration::from_secs(10), other).await??;
+        assert_eq!(answer, 42);
+        guest.abort();
+        Ok(())
+    }
+}
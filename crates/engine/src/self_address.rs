@@ -0,0 +1,59 @@
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::RwLock,
+};
+
+/// The address an application is served on, once its trigger listens. Outbound
+/// requests to it are requests from the application to itself.
+#[derive(Debug, Default)]
+pub struct SelfAddress(RwLock<Option<SocketAddr>>);
+
+impl SelfAddress {
+    /// Records the address the application is served on.
+    pub fn set(&self, address: SocketAddr) {
+        *self.0.write().unwrap() = Some(address);
+    }
+
+    /// The address the application is served on, if it listens on one.
+    pub fn get(&self) -> Option<SocketAddr> {
+        *self.0.read().unwrap()
+    }
+
+    /// Whether a request to the host and port reaches the application. An
+    /// application listening on a loopback or unspecified address is reached
+    /// through `localhost` and the loopback addresses.
+    pub fn matches(&self, host: &str, port: u16) -> bool {
+        let address = match self.get() {
+            Some(address) if address.port() == port => address,
+            _ => return false,
+        };
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        let local = address.ip().is_loopback() || address.ip().is_unspecified();
+        match host.parse::<IpAddr>() {
+            Ok(ip) => ip == address.ip() || (local && ip.is_loopback()),
+            Err(_) => local && host.eq_ignore_ascii_case("localhost"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches() {
+        let address = SelfAddress::default();
+        assert!(!address.matches("localhost", 3000));
+
+        address.set("0.0.0.0:3000".parse().unwrap());
+        assert!(address.matches("localhost", 3000));
+        assert!(address.matches("127.0.0.1", 3000));
+        assert!(address.matches("[::1]", 3000));
+        assert!(!address.matches("localhost", 3001));
+        assert!(!address.matches("example.com", 3000));
+
+        address.set("10.0.0.5:8080".parse().unwrap());
+        assert!(address.matches("10.0.0.5", 8080));
+        assert!(!address.matches("localhost", 8080));
+    }
+}
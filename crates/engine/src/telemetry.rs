@@ -0,0 +1,21 @@
+use opentelemetry::global;
+use opentelemetry_http::{HeaderExtractor, HeaderInjector};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// Adds the trace context of the current span to the headers of an outgoing
+/// request, so that the service it is sent to continues the trace. Nothing is
+/// added unless traces are exported.
+pub fn inject_trace_context(headers: &mut http::HeaderMap) {
+    let context = tracing::Span::current().context();
+    global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&context, &mut HeaderInjector(headers))
+    });
+}
+
+/// Makes the span continue the trace of an incoming request, if its headers
+/// carry one.
+pub fn continue_trace(span: &tracing::Span, headers: &http::HeaderMap) {
+    let context =
+        global::get_text_map_propagator(|propagator| propagator.extract(&HeaderExtractor(headers)));
+    span.set_parent(context);
+}
@@ -0,0 +1,196 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use tempfile::TempDir;
+use tracing::log;
+use wasi_common::dir::WasiDir;
+use wasmtime_wasi::{ambient_authority, Dir};
+
+use crate::quota_dir::{Quota, QuotaDir};
+
+/// The guest path temporary directories are mounted at.
+pub const GUEST_TEMP_DIR: &str = "/tmp";
+
+/// How temporary directories are provided to components.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum TempDirMode {
+    /// Each invocation gets an empty directory, removed when it returns.
+    PerInvocation,
+    /// Each component gets a directory shared by all its invocations, removed
+    /// when the application stops.
+    PerComponent,
+    /// Components get no temporary directory.
+    Disabled,
+}
+
+impl Default for TempDirMode {
+    fn default() -> Self {
+        Self::PerInvocation
+    }
+}
+
+/// Configuration of component temporary directories.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub struct TempDirConfig {
+    /// How temporary directories are provided.
+    #[serde(default)]
+    pub mode: TempDirMode,
+    /// The host directory temporary directories are created in. Defaults to
+    /// the system temporary directory.
+    pub dir: Option<PathBuf>,
+    /// The maximum size of a temporary directory. Writes that would exceed
+    /// it fail. Per-component directories that concurrent invocations made
+    /// exceed it are emptied when an invocation returns.
+    pub max_bytes: Option<u64>,
+}
+
+impl TempDirConfig {
+    pub(crate) fn create(&self, component: &str) -> Result<TempDir> {
+        let mut builder = tempfile::Builder::new();
+        builder.prefix("spin-");
+        let suffix = format!("-{}", component);
+        builder.suffix(&suffix);
+        match &self.dir {
+            Some(dir) => {
+                std::fs::create_dir_all(dir)?;
+                builder.tempdir_in(dir)
+            }
+            None => builder.tempdir(),
+        }
+        .with_context(|| format!("Cannot create temporary directory for {}", component))
+    }
+}
+
+/// The temporary directory of an invocation, limited to its quota and, for
+/// per-invocation directories, removed when dropped.
+pub(crate) struct InvocationTempDir {
+    component: String,
+    path: PathBuf,
+    // Set for per-invocation directories, which are removed on drop.
+    owned: Option<TempDir>,
+    max_bytes: Option<u64>,
+    quota: Option<Arc<Quota>>,
+}
+
+impl InvocationTempDir {
+    pub(crate) fn owned(component: &str, dir: TempDir, max_bytes: Option<u64>) -> Self {
+        Self {
+            component: component.to_string(),
+            path: dir.path().to_path_buf(),
+            owned: Some(dir),
+            max_bytes,
+            quota: max_bytes.map(|max_bytes| Quota::new(0, max_bytes)),
+        }
+    }
+
+    pub(crate) fn shared(component: &str, path: &Path, max_bytes: Option<u64>) -> Self {
+        Self {
+            component: component.to_string(),
+            path: path.to_path_buf(),
+            owned: None,
+            max_bytes,
+            quota: max_bytes.map(|max_bytes| Quota::new(dir_size(path).unwrap_or(0), max_bytes)),
+        }
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Opens the directory for the guest, failing writes past the quota.
+    pub(crate) fn wasi_dir(&self) -> Result<Box<dyn WasiDir>> {
+        let dir = Dir::open_ambient_dir(&self.path, ambient_authority())
+            .with_context(|| format!("Cannot open temporary directory {:?}", self.path))?;
+        let dir: Box<dyn WasiDir> = Box::new(wasi_cap_std_sync::dir::Dir::from_cap_std(dir));
+        Ok(match &self.quota {
+            Some(quota) => Box::new(QuotaDir::new(dir, quota.clone())),
+            None => dir,
+        })
+    }
+}
+
+impl Drop for InvocationTempDir {
+    fn drop(&mut self) {
+        let max_bytes = match self.max_bytes {
+            Some(max_bytes) => max_bytes,
+            None => return,
+        };
+        let size = dir_size(&self.path).unwrap_or(0);
+        if size <= max_bytes {
+            return;
+        }
+        log::warn!(
+            "Component {} used {} bytes of temporary storage, exceeding the {} byte limit",
+            self.component,
+            size,
+            max_bytes
+        );
+        if self.owned.is_none() {
+            if let Err(e) = clear_dir(&self.path) {
+                log::error!("Cannot empty temporary directory {:?}: {}", self.path, e);
+            }
+        }
+    }
+}
+
+pub(crate) fn dir_size(path: &Path) -> std::io::Result<u64> {
+    let mut size = 0;
+    for entry in std::fs::read_dir(path)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        size += if metadata.is_dir() {
+            dir_size(&entry.path())?
+        } else {
+            metadata.len()
+        };
+    }
+    Ok(size)
+}
+
+fn clear_dir(path: &Path) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(path)? {
+        let entry = entry?;
+        if entry.metadata()?.is_dir() {
+            std::fs::remove_dir_all(entry.path())?;
+        } else {
+            std::fs::remove_file(entry.path())?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_per_invocation_dir_is_removed() -> Result<()> {
+        let config = TempDirConfig::default();
+        let dir = InvocationTempDir::owned("test", config.create("test")?, None);
+        let path = dir.path().to_path_buf();
+        std::fs::write(path.join("scratch.txt"), "data")?;
+        drop(dir);
+        assert!(!path.exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_shared_dir_over_quota_is_emptied() -> Result<()> {
+        let root = TempDirConfig::default().create("test")?;
+        std::fs::write(root.path().join("small.txt"), "data")?;
+        drop(InvocationTempDir::shared("test", root.path(), Some(16)));
+        assert!(root.path().join("small.txt").exists());
+
+        std::fs::write(root.path().join("large.txt"), [0u8; 32])?;
+        drop(InvocationTempDir::shared("test", root.path(), Some(16)));
+        assert!(root.path().exists());
+        assert_eq!(std::fs::read_dir(root.path())?.count(), 0);
+        Ok(())
+    }
+}
@@ -0,0 +1,349 @@
+//! Host components loaded from dynamic libraries.
+//!
+//! A host plugin is a shared library exporting the following C functions:
+//!
+//! ```c
+//! // The plugin ABI version the library was built for.
+//! uint32_t spin_host_plugin_abi_version(void);
+//! // The name of the plugin: the module components import its functions
+//! // from, and the key of its table in `host_config`.
+//! const char *spin_host_plugin_name(void);
+//! // The functions of the plugin.
+//! uint32_t spin_host_plugin_function_count(void);
+//! const char *spin_host_plugin_function_name(uint32_t function);
+//! // Creates the plugin state for an instance of a component, from the
+//! // component ID and its configuration as JSON (empty if it has none).
+//! // Returns NULL on failure.
+//! void *spin_host_plugin_instantiate(const uint8_t *component_id, size_t component_id_len,
+//!                                    const uint8_t *config, size_t config_len);
+//! // Calls a function with the input bytes, setting the output bytes. Returns
+//! // 0 on success; otherwise the output holds an error message.
+//! int32_t spin_host_plugin_call(void *instance, uint32_t function,
+//!                               const uint8_t *input, size_t input_len,
+//!                               uint8_t **output, size_t *output_len);
+//! // Frees an output of spin_host_plugin_call.
+//! void spin_host_plugin_free(uint8_t *output, size_t output_len);
+//! // Drops the plugin state of a component instance.
+//! void spin_host_plugin_drop(void *instance);
+//! ```
+//!
+//! Components import each function from the module named after the plugin,
+//! as `(func (param $input_ptr i32) (param $input_len i32) (param $ret_ptr i32)
+//! (result i32))`. The output is allocated in the component's memory with its
+//! `canonical_abi_realloc` export, and its pointer and length written at
+//! `$ret_ptr`; the result is the status returned by the plugin.
+//!
+//! Only `spin_host_plugin_abi_version` is called before the ABI version is
+//! checked, so that the layout of the other functions can change between
+//! versions.
+
+use std::{
+    ffi::{c_void, CStr},
+    os::raw::c_char,
+    path::{Path, PathBuf},
+    ptr::{self, NonNull},
+    sync::Arc,
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use libloading::Library;
+use serde::Deserialize;
+use spin_engine::{
+    host_component::{host_config, HostComponent, HostComponentsStateHandle},
+    RuntimeContext,
+};
+use spin_manifest::CoreComponent;
+use wasmtime::{Caller, Extern, Linker, Trap};
+
+/// The version of the host plugin ABI supported by this version of Spin.
+pub const ABI_VERSION: u32 = 1;
+
+type AbiVersionFn = unsafe extern "C" fn() -> u32;
+type NameFn = unsafe extern "C" fn() -> *const c_char;
+type FunctionCountFn = unsafe extern "C" fn() -> u32;
+type FunctionNameFn = unsafe extern "C" fn(u32) -> *const c_char;
+type InstantiateFn = unsafe extern "C" fn(*const u8, usize, *const u8, usize) -> *mut c_void;
+type CallFn =
+    unsafe extern "C" fn(*mut c_void, u32, *const u8, usize, *mut *mut u8, *mut usize) -> i32;
+type FreeFn = unsafe extern "C" fn(*mut u8, usize);
+type DropFn = unsafe extern "C" fn(*mut c_void);
+
+/// Runtime configuration for a host plugin.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub struct HostPluginConfig {
+    /// Path to the shared library of the plugin.
+    pub path: PathBuf,
+}
+
+/// A host component loaded from a host plugin.
+#[derive(Clone)]
+pub struct HostPlugin {
+    plugin: Arc<Plugin>,
+}
+
+/// The functions of a loaded plugin library.
+struct Plugin {
+    name: String,
+    functions: Vec<String>,
+    instantiate: InstantiateFn,
+    call: CallFn,
+    free: FreeFn,
+    drop: DropFn,
+    // Keeps the functions above loaded.
+    _library: Library,
+}
+
+impl HostPlugin {
+    /// Loads the host plugin from the given shared library, checking that it
+    /// was built for the supported ABI version.
+    pub fn load(path: &Path) -> Result<Self> {
+        // Safety: loading a library runs its initializers, so plugins are
+        // trusted like Spin itself; they are configured by the operator.
+        let library = unsafe { Library::new(path) }
+            .with_context(|| format!("Cannot load host plugin {}", path.display()))?;
+        let plugin = unsafe { Plugin::new(library) }
+            .with_context(|| format!("Invalid host plugin {}", path.display()))?;
+        tracing::info!(
+            "Loaded host plugin {} from {} with functions {:?}",
+            plugin.name,
+            path.display(),
+            plugin.functions
+        );
+        Ok(Self {
+            plugin: Arc::new(plugin),
+        })
+    }
+
+    /// The name of the plugin.
+    pub fn name(&self) -> &str {
+        &self.plugin.name
+    }
+}
+
+impl Plugin {
+    /// Resolves the functions of the plugin.
+    ///
+    /// # Safety
+    ///
+    /// The library must export the functions of the plugin ABI version it
+    /// reports, with the documented signatures.
+    unsafe fn new(library: Library) -> Result<Self> {
+        let abi_version = library.get::<AbiVersionFn>(b"spin_host_plugin_abi_version\0")?;
+        check_abi_version(abi_version())?;
+
+        let name = string((*library.get::<NameFn>(b"spin_host_plugin_name\0")?)())
+            .context("Invalid plugin name")?;
+        let function_count =
+            *library.get::<FunctionCountFn>(b"spin_host_plugin_function_count\0")?;
+        let function_name = *library.get::<FunctionNameFn>(b"spin_host_plugin_function_name\0")?;
+        let functions = (0..function_count())
+            .map(|i| {
+                string(function_name(i)).with_context(|| format!("Invalid name of function {}", i))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            name,
+            functions,
+            instantiate: *library.get::<InstantiateFn>(b"spin_host_plugin_instantiate\0")?,
+            call: *library.get::<CallFn>(b"spin_host_plugin_call\0")?,
+            free: *library.get::<FreeFn>(b"spin_host_plugin_free\0")?,
+            drop: *library.get::<DropFn>(b"spin_host_plugin_drop\0")?,
+            _library: library,
+        })
+    }
+}
+
+/// Checks the ABI version reported by a plugin.
+fn check_abi_version(version: u32) -> Result<()> {
+    if version != ABI_VERSION {
+        bail!(
+            "The plugin was built for host plugin ABI version {}, but this version of Spin supports version {}",
+            version,
+            ABI_VERSION
+        );
+    }
+    Ok(())
+}
+
+/// Copies a string returned by a plugin.
+///
+/// # Safety
+///
+/// The pointer must be null or point to a NUL-terminated string.
+unsafe fn string(s: *const c_char) -> Result<String> {
+    if s.is_null() {
+        bail!("The plugin returned a null string");
+    }
+    Ok(CStr::from_ptr(s).to_str()?.to_owned())
+}
+
+impl HostComponent for HostPlugin {
+    type State = PluginInstance;
+
+    fn add_to_linker<T: Send>(
+        _linker: &mut Linker<RuntimeContext<T>>,
+        _state_handle: HostComponentsStateHandle<Self::State>,
+    ) -> Result<()> {
+        bail!("Host plugins can only be linked once loaded")
+    }
+
+    fn add_instance_to_linker<T: Send>(
+        &self,
+        linker: &mut Linker<RuntimeContext<T>>,
+        state_handle: HostComponentsStateHandle<Self::State>,
+    ) -> Result<()> {
+        for (index, function) in self.plugin.functions.iter().enumerate() {
+            let index = index as u32;
+            // The output is allocated by calling back into the component,
+            // which must be done with `call_async` as guests run
+            // asynchronously.
+            linker.func_wrap3_async(
+                &self.plugin.name,
+                function,
+                move |mut caller: Caller<'_, RuntimeContext<T>>,
+                      input_ptr: i32,
+                      input_len: i32,
+                      ret_ptr: i32| {
+                    Box::new(async move {
+                        call(
+                            &mut caller,
+                            state_handle,
+                            index,
+                            input_ptr,
+                            input_len,
+                            ret_ptr,
+                        )
+                        .await
+                        .map_err(|e| Trap::new(format!("{:#}", e)))
+                    })
+                },
+            )?;
+        }
+        Ok(())
+    }
+
+    fn build_state(&self, component: &CoreComponent) -> Result<Self::State> {
+        let config = match host_config::<serde_json::Value>(component, &self.plugin.name)? {
+            Some(config) => serde_json::to_vec(&config)?,
+            None => vec![],
+        };
+        let instance = unsafe {
+            (self.plugin.instantiate)(
+                component.id.as_ptr(),
+                component.id.len(),
+                config.as_ptr(),
+                config.len(),
+            )
+        };
+        let instance = NonNull::new(instance).ok_or_else(|| {
+            anyhow!(
+                "Host plugin {} failed to instantiate for component {}",
+                self.plugin.name,
+                component.id
+            )
+        })?;
+        Ok(PluginInstance {
+            plugin: self.plugin.clone(),
+            instance,
+        })
+    }
+}
+
+/// The plugin state of a component instance.
+pub struct PluginInstance {
+    plugin: Arc<Plugin>,
+    instance: NonNull<c_void>,
+}
+
+// Safety: plugins must allow their instances to be used from any thread,
+// one call at a time.
+unsafe impl Send for PluginInstance {}
+
+impl PluginInstance {
+    /// Calls a function of the plugin, returning its status and output.
+    fn call(&self, function: u32, input: &[u8]) -> (i32, Vec<u8>) {
+        let mut output = ptr::null_mut();
+        let mut output_len = 0;
+        unsafe {
+            let status = (self.plugin.call)(
+                self.instance.as_ptr(),
+                function,
+                input.as_ptr(),
+                input.len(),
+                &mut output,
+                &mut output_len,
+            );
+            if output.is_null() {
+                return (status, vec![]);
+            }
+            let bytes = std::slice::from_raw_parts(output, output_len).to_vec();
+            (self.plugin.free)(output, output_len);
+            (status, bytes)
+        }
+    }
+}
+
+impl Drop for PluginInstance {
+    fn drop(&mut self) {
+        unsafe { (self.plugin.drop)(self.instance.as_ptr()) }
+    }
+}
+
+/// Calls a plugin function for a component, copying the input from and the
+/// output to the memory of the component.
+async fn call<T: Send>(
+    caller: &mut Caller<'_, RuntimeContext<T>>,
+    state_handle: HostComponentsStateHandle<PluginInstance>,
+    function: u32,
+    input_ptr: i32,
+    input_len: i32,
+    ret_ptr: i32,
+) -> Result<i32> {
+    let memory = match caller.get_export("memory") {
+        Some(Extern::Memory(memory)) => memory,
+        _ => bail!("The component does not export its memory"),
+    };
+    let mut input = vec![0; input_len as u32 as usize];
+    memory.read(&*caller, input_ptr as u32 as usize, &mut input)?;
+
+    let (status, output) = state_handle.get(caller.data()).call(function, &input);
+
+    let realloc = caller
+        .get_export("canonical_abi_realloc")
+        .and_then(Extern::into_func)
+        .context("The component does not export canonical_abi_realloc")?
+        .typed::<(i32, i32, i32, i32), i32, _>(&*caller)?;
+    let output_ptr = realloc
+        .call_async(&mut *caller, (0, 0, 1, output.len() as i32))
+        .await?;
+    memory.write(&mut *caller, output_ptr as u32 as usize, &output)?;
+
+    let mut ret = [0; 8];
+    ret[..4].copy_from_slice(&output_ptr.to_le_bytes());
+    ret[4..].copy_from_slice(&(output.len() as i32).to_le_bytes());
+    memory.write(&mut *caller, ret_ptr as u32 as usize, &ret)?;
+    Ok(status)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_abi_version_handshake() {
+        assert!(check_abi_version(ABI_VERSION).is_ok());
+        let err = check_abi_version(ABI_VERSION + 1).unwrap_err();
+        assert!(err.to_string().contains("ABI version 2"));
+    }
+
+    #[test]
+    fn test_load_rejects_non_libraries() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("libnot_a_plugin.so");
+        std::fs::write(&path, b"not a library")?;
+        let err = HostPlugin::load(&path).err().unwrap();
+        assert!(err.to_string().contains("Cannot load host plugin"));
+        Ok(())
+    }
+}
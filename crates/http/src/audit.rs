@@ -0,0 +1,394 @@
+//! Request auditing for the HTTP trigger.
+
+use std::{
+    path::PathBuf,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{bail, Context, Result};
+use futures::StreamExt;
+use hyper::{body::Bytes, Body, Request};
+use s3::{creds::Credentials, Bucket, Region};
+use serde::Serialize;
+use spin_manifest::HttpAuditConfig;
+use tokio::sync::mpsc;
+use tracing::log;
+
+/// Maximum number of audit records waiting to be written to the sink.
+/// Records submitted while the queue is full are dropped rather than
+/// delaying the request.
+const AUDIT_QUEUE_CAPACITY: usize = 1024;
+
+/// The most body bytes recorded for routes that do not set `max_body_bytes`.
+const DEFAULT_MAX_BODY_BYTES: usize = 1024 * 1024;
+
+const REDACTED: &str = "[REDACTED]";
+
+/// The destination of audit records.
+#[derive(Clone, Debug)]
+pub enum AuditSink {
+    /// Write records as files in a local directory.
+    Directory(PathBuf),
+    /// Upload records to a bucket of an S3-compatible object storage service.
+    /// Requests are signed with the credentials in the `AWS_ACCESS_KEY_ID`
+    /// and `AWS_SECRET_ACCESS_KEY` environment variables.
+    ObjectStore {
+        /// The service endpoint, for example `http://localhost:9000`.
+        endpoint: String,
+        /// The bucket name.
+        bucket: String,
+        /// The prefix of the object names, empty or ending with `/`.
+        prefix: String,
+        /// The bucket region.
+        region: String,
+    },
+}
+
+impl AuditSink {
+    /// An object store sink uploading to the bucket, and under the prefix,
+    /// of a URL of the form `{endpoint}/{bucket}/{prefix}`.
+    pub fn object_store(url: &str, region: &str) -> Result<Self> {
+        let url: url::Url = url.parse().context("Invalid audit object store URL")?;
+        if url.cannot_be_a_base() || url.host_str().is_none() {
+            bail!("Audit object store URL {} has no host", url);
+        }
+        if url.query().is_some() {
+            bail!("Audit object store URL must not have a query: requests are signed with the AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY credentials");
+        }
+        let mut segments = url
+            .path_segments()
+            .into_iter()
+            .flatten()
+            .filter(|s| !s.is_empty())
+            .map(|s| percent_encoding::percent_decode_str(s).decode_utf8_lossy());
+        let bucket = match segments.next() {
+            Some(bucket) => bucket.into_owned(),
+            None => bail!("Audit object store URL {} does not name a bucket", url),
+        };
+        let prefix = segments.map(|s| format!("{}/", s)).collect();
+        Ok(Self::ObjectStore {
+            endpoint: url.origin().ascii_serialization(),
+            bucket,
+            prefix,
+            region: region.to_owned(),
+        })
+    }
+}
+
+/// Where an auditor writes records.
+enum Destination {
+    Directory(PathBuf),
+    Bucket { bucket: Bucket, prefix: String },
+}
+
+impl Destination {
+    fn new(sink: AuditSink) -> Result<Self> {
+        Ok(match sink {
+            AuditSink::Directory(dir) => Self::Directory(dir),
+            AuditSink::ObjectStore {
+                endpoint,
+                bucket,
+                prefix,
+                region,
+            } => {
+                let credentials = Credentials::new(None, None, None, None, None)
+                    .context("Cannot load audit object store credentials")?;
+                let bucket = Bucket::new(&bucket, Region::Custom { region, endpoint }, credentials)
+                    .with_context(|| format!("Cannot open audit bucket {}", bucket))?
+                    .with_path_style();
+                Self::Bucket { bucket, prefix }
+            }
+        })
+    }
+}
+
+/// A single audited request.
+#[derive(Debug, Serialize)]
+pub(crate) struct AuditRecord {
+    id: String,
+    timestamp: u64,
+    component: String,
+    method: String,
+    uri: String,
+    client_addr: String,
+    headers: Vec<(String, String)>,
+    /// The length of the body, unless it was not read whole and its length
+    /// was not given.
+    body_len: Option<u64>,
+    body_truncated: bool,
+    #[serde(skip)]
+    body: Option<Bytes>,
+}
+
+impl AuditRecord {
+    fn new(
+        id: String,
+        component: &str,
+        req: &http::request::Parts,
+        client_addr: std::net::SocketAddr,
+        body: Option<&Bytes>,
+        body_len: Option<u64>,
+        config: &HttpAuditConfig,
+    ) -> Self {
+        let headers = req
+            .headers
+            .iter()
+            .map(|(name, value)| {
+                let value = if config
+                    .redact_headers
+                    .iter()
+                    .any(|r| r.eq_ignore_ascii_case(name.as_str()))
+                {
+                    REDACTED.to_string()
+                } else {
+                    String::from_utf8_lossy(value.as_bytes()).to_string()
+                };
+                (name.to_string(), value)
+            })
+            .collect();
+
+        let max = config.max_body_bytes.unwrap_or(DEFAULT_MAX_BODY_BYTES);
+        let (body, body_truncated) = match body {
+            Some(body) if body.len() > max => (Some(body.slice(..max)), true),
+            Some(body) => (Some(body.clone()), false),
+            None => (None, false),
+        };
+
+        Self {
+            id,
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+            component: component.to_string(),
+            method: req.method.to_string(),
+            uri: req.uri.to_string(),
+            client_addr: client_addr.to_string(),
+            headers,
+            body_len,
+            body_truncated,
+            body,
+        }
+    }
+}
+
+/// Copies audited requests to an audit sink in the background.
+pub(crate) struct Auditor {
+    sender: mpsc::Sender<AuditRecord>,
+    sequence: AtomicU64,
+}
+
+impl Auditor {
+    /// Creates a new auditor and spawns the task writing records to the sink.
+    pub(crate) fn new(sink: AuditSink) -> Result<Self> {
+        let destination = Destination::new(sink)?;
+        let (sender, mut receiver) = mpsc::channel::<AuditRecord>(AUDIT_QUEUE_CAPACITY);
+        tokio::spawn(async move {
+            while let Some(record) = receiver.recv().await {
+                if let Err(e) = write_record(&destination, &record).await {
+                    log::error!("Failed to write audit record {}: {:?}", record.id, e);
+                }
+            }
+        });
+        Ok(Self {
+            sender,
+            sequence: AtomicU64::new(0),
+        })
+    }
+
+    /// Submits an audit record for the request, and returns an equivalent
+    /// request to pass to the executor. If the body is recorded, at most the
+    /// recorded bytes are buffered, and the rest of the body streams on.
+    pub(crate) async fn tee(
+        &self,
+        req: Request<Body>,
+        component: &str,
+        client_addr: std::net::SocketAddr,
+        config: &HttpAuditConfig,
+    ) -> Result<Request<Body>> {
+        let (parts, body) = req.into_parts();
+        let content_length = parts
+            .headers
+            .get(http::header::CONTENT_LENGTH)
+            .and_then(|len| len.to_str().ok()?.parse().ok());
+        let (recorded, body_len, body) = if config.include_body {
+            let max = config.max_body_bytes.unwrap_or(DEFAULT_MAX_BODY_BYTES);
+            let (prefix, rest) = read_prefix(body, max).await?;
+            let body_len = match rest {
+                Some(_) => content_length,
+                None => Some(prefix.len() as u64),
+            };
+            let body = match rest {
+                Some(rest) => {
+                    let read = futures::stream::iter([Ok::<_, hyper::Error>(prefix.clone())]);
+                    Body::wrap_stream(read.chain(rest))
+                }
+                None => Body::from(prefix.clone()),
+            };
+            (Some(prefix), body_len, body)
+        } else {
+            (None, content_length, body)
+        };
+
+        let id = format!(
+            "{}-{}",
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_nanos())
+                .unwrap_or_default(),
+            self.sequence.fetch_add(1, Ordering::Relaxed)
+        );
+        let record = AuditRecord::new(
+            id,
+            component,
+            &parts,
+            client_addr,
+            recorded.as_ref(),
+            body_len,
+            config,
+        );
+        if let Err(e) = self.sender.try_send(record) {
+            log::warn!("Dropping audit record for component {}: {}", component, e);
+        }
+
+        Ok(Request::from_parts(parts, body))
+    }
+}
+
+/// Reads the body until more than `max` bytes are read, returning them and,
+/// if the body did not end, the rest of it.
+async fn read_prefix(mut body: Body, max: usize) -> Result<(Bytes, Option<Body>)> {
+    let mut chunks = vec![];
+    let mut size = 0;
+    while let Some(chunk) = body.next().await {
+        let chunk = chunk?;
+        size += chunk.len();
+        chunks.push(chunk);
+        if size > max {
+            return Ok((Bytes::from(chunks.concat()), Some(body)));
+        }
+    }
+    Ok((Bytes::from(chunks.concat()), None))
+}
+
+async fn write_record(destination: &Destination, record: &AuditRecord) -> Result<()> {
+    let metadata = serde_json::to_vec(record)?;
+    match destination {
+        Destination::Directory(dir) => {
+            tokio::fs::create_dir_all(dir).await.with_context(|| {
+                format!("Cannot create audit directory {}", dir.display())
+            })?;
+            tokio::fs::write(dir.join(format!("{}.json", record.id)), metadata).await?;
+            if let Some(body) = &record.body {
+                tokio::fs::write(dir.join(format!("{}.body", record.id)), body).await?;
+            }
+        }
+        Destination::Bucket { bucket, prefix } => {
+            let key = format!("{}{}.json", prefix, record.id);
+            put_object(bucket, &key, &metadata).await?;
+            if let Some(body) = &record.body {
+                let key = format!("{}{}.body", prefix, record.id);
+                put_object(bucket, &key, body).await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn put_object(bucket: &Bucket, key: &str, data: &[u8]) -> Result<()> {
+    let res = bucket.put_object(key, data).await?;
+    anyhow::ensure!(
+        (200..300).contains(&res.status_code()),
+        "Object store responded to the upload of {} with status {}",
+        key,
+        res.status_code()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_audit_record_redacts_headers_and_truncates_body() {
+        let req = Request::post("https://myservice.fermyon.dev/audit")
+            .header("Authorization", "Bearer secret")
+            .header("x-custom", "visible")
+            .body(())
+            .unwrap();
+        let (parts, _) = req.into_parts();
+        let config = HttpAuditConfig {
+            max_body_bytes: Some(4),
+            ..Default::default()
+        };
+        let body = Bytes::from_static(b"Fermyon");
+
+        let record = AuditRecord::new(
+            "id".to_string(),
+            "component",
+            &parts,
+            spin_testing::test_socket_addr(),
+            Some(&body),
+            Some(7),
+            &config,
+        );
+
+        assert!(record
+            .headers
+            .contains(&("authorization".to_string(), REDACTED.to_string())));
+        assert!(record
+            .headers
+            .contains(&("x-custom".to_string(), "visible".to_string())));
+        assert_eq!(record.body_len, Some(7));
+        assert!(record.body_truncated);
+        assert_eq!(record.body.unwrap().as_ref(), b"Ferm");
+    }
+
+    #[tokio::test]
+    async fn test_only_the_recorded_body_is_buffered() -> Result<()> {
+        let chunks = ["Fer", "myon", " Spin"].map(Ok::<_, std::io::Error>);
+        let body = Body::wrap_stream(futures::stream::iter(chunks));
+        let (prefix, rest) = read_prefix(body, 4).await?;
+        assert_eq!(prefix.as_ref(), b"Fermyon");
+        let rest = hyper::body::to_bytes(rest.expect("the body should not be read whole")).await?;
+        assert_eq!(rest.as_ref(), b" Spin");
+
+        let (prefix, rest) = read_prefix(Body::from("Spin"), 4).await?;
+        assert_eq!(prefix.as_ref(), b"Spin");
+        assert!(rest.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_object_store_urls() -> Result<()> {
+        match AuditSink::object_store("https://s3.example.com:9000/audit/spin%20app/", "eu")? {
+            AuditSink::ObjectStore {
+                endpoint,
+                bucket,
+                prefix,
+                region,
+            } => {
+                assert_eq!(endpoint, "https://s3.example.com:9000");
+                assert_eq!(bucket, "audit");
+                assert_eq!(prefix, "spin app/");
+                assert_eq!(region, "eu");
+            }
+            sink => panic!("unexpected sink {:?}", sink),
+        }
+        assert!(matches!(
+            AuditSink::object_store("http://localhost:9000/audit", "us-east-1")?,
+            AuditSink::ObjectStore { prefix, .. } if prefix.is_empty()
+        ));
+
+        assert!(AuditSink::object_store("https://s3.example.com", "us-east-1").is_err());
+        assert!(AuditSink::object_store(
+            "https://s3.example.com/audit/record.json?X-Amz-Signature=abc",
+            "us-east-1"
+        )
+        .is_err());
+        assert!(AuditSink::object_store("mailto:audit@example.com", "us-east-1").is_err());
+        Ok(())
+    }
+}
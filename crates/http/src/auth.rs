@@ -0,0 +1,124 @@
+use std::{fmt::Write, sync::Arc};
+
+use anyhow::Result;
+use http::{header::AUTHORIZATION, HeaderValue, StatusCode};
+use hyper::{Body, Request, Response};
+use serde_json::{Map, Value};
+use spin_jwt::JwtProviders;
+use spin_manifest::HttpAuthConfig;
+use tracing::log;
+
+/// The header through which the claims of a validated token are passed
+/// to the component, as a JSON object.
+pub(crate) const JWT_CLAIMS_HEADER: &str = "spin-jwt-claims";
+
+/// Authenticates a request for a route with authentication configuration.
+///
+/// On success, the token claims are added to the request headers. On
+/// failure, the response to send instead of invoking the component is
+/// returned.
+pub(crate) async fn authenticate(
+    jwt: &Arc<JwtProviders>,
+    config: &HttpAuthConfig,
+    req: &mut Request<Body>,
+) -> Result<Option<Response<Body>>> {
+    let token = match bearer_token(req) {
+        Some(token) => token,
+        None => return Ok(Some(unauthorized())),
+    };
+
+    let claims = match jwt.validator(&config.jwt) {
+        Some(validator) => validator.validate(&token).await,
+        None => Err(anyhow::anyhow!("Unknown JWT validator {}", config.jwt)),
+    };
+
+    match claims {
+        Ok(claims) => {
+            req.headers_mut()
+                .insert(JWT_CLAIMS_HEADER, claims_header(&claims)?);
+            Ok(None)
+        }
+        Err(e) => {
+            log::info!("Rejecting request with invalid token: {:#}", e);
+            Ok(Some(unauthorized()))
+        }
+    }
+}
+
+/// The value of the claims header: the claims as JSON, with the characters
+/// header values cannot hold, which are only found in strings, escaped.
+pub(crate) fn claims_header(claims: &Map<String, Value>) -> Result<HeaderValue> {
+    let json = serde_json::to_string(claims)?;
+    let mut escaped = String::with_capacity(json.len());
+    for c in json.chars() {
+        if c.is_ascii() && !c.is_ascii_control() {
+            escaped.push(c);
+        } else {
+            for unit in c.encode_utf16(&mut [0; 2]) {
+                write!(escaped, "\\u{:04x}", unit)?;
+            }
+        }
+    }
+    Ok(HeaderValue::from_str(&escaped)?)
+}
+
+/// The bearer token of a request, if it has one.
+pub(crate) fn bearer_token(req: &Request<Body>) -> Option<String> {
+    let value = req.headers().get(AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, token) = value.split_once(' ')?;
+    if scheme.eq_ignore_ascii_case("bearer") && !token.trim().is_empty() {
+        Some(token.trim().to_string())
+    } else {
+        None
+    }
+}
+
+/// The response to requests without a valid bearer token.
+pub(crate) fn unauthorized() -> Response<Body> {
+    let mut res = Response::new(Body::empty());
+    *res.status_mut() = StatusCode::UNAUTHORIZED;
+    res.headers_mut()
+        .insert("www-authenticate", HeaderValue::from_static("Bearer"));
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(authorization: &str) -> Request<Body> {
+        Request::builder()
+            .header(AUTHORIZATION, authorization)
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_bearer_token() {
+        assert_eq!(
+            bearer_token(&request("Bearer abc.def.ghi")).as_deref(),
+            Some("abc.def.ghi")
+        );
+        assert_eq!(
+            bearer_token(&request("bearer abc.def.ghi")).as_deref(),
+            Some("abc.def.ghi")
+        );
+        assert_eq!(bearer_token(&request("Basic dXNlcjpwYXNz")), None);
+        assert_eq!(bearer_token(&request("Bearer ")), None);
+        assert_eq!(bearer_token(&Request::new(Body::empty())), None);
+    }
+
+    #[test]
+    fn test_claims_header_escapes_non_ascii() -> Result<()> {
+        let claims = serde_json::json!({ "name": "Zoë 😀", "sub": "user" });
+        let claims = claims.as_object().unwrap();
+        let header = claims_header(claims)?;
+        assert_eq!(
+            header.to_str()?,
+            r#"{"name":"Zo\u00eb \ud83d\ude00","sub":"user"}"#
+        );
+        let parsed: Map<String, Value> = serde_json::from_slice(header.as_bytes())?;
+        assert_eq!(&parsed, claims);
+        Ok(())
+    }
+}
@@ -0,0 +1,255 @@
+//! Bounded handling of request and response bodies: executors that need whole
+//! bodies read them up to a limit, and executors that stream them spool large
+//! bodies to temporary files rather than holding them in memory.
+
+use std::{
+    fs::File,
+    io::{Seek, SeekFrom, Write},
+    path::PathBuf,
+    sync::{Arc, RwLock},
+};
+
+use anyhow::{Context, Result};
+use bytes::{Bytes, BytesMut};
+use http::StatusCode;
+use hyper::{body::HttpBody, Body, Response};
+use spin_trigger::HttpBodyConfig;
+use tokio::io::AsyncWriteExt;
+use tokio_util::io::ReaderStream;
+use wasi_common::{pipe::ReadPipe, WasiFile};
+
+/// Reads a whole body, unless it is larger than the limit.
+pub(crate) async fn read_to_limit(mut body: Body, limit: u64) -> Result<Option<Bytes>> {
+    if body.size_hint().lower() > limit {
+        return Ok(None);
+    }
+    let mut bytes = BytesMut::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk?;
+        if (bytes.len() + chunk.len()) as u64 > limit {
+            return Ok(None);
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    Ok(Some(bytes.freeze()))
+}
+
+/// The response to a request whose body is larger than the executor accepts.
+pub(crate) fn too_large() -> Result<Response<Body>> {
+    Ok(Response::builder()
+        .status(StatusCode::PAYLOAD_TOO_LARGE)
+        .body(Body::empty())?)
+}
+
+/// A request body, held in memory or, past the in-memory limit, in a
+/// temporary file.
+pub(crate) enum Spooled {
+    Memory(Vec<u8>),
+    File { file: File, len: u64 },
+}
+
+impl Spooled {
+    /// Reads the body, spooling it to a temporary file once it exceeds the
+    /// in-memory limit.
+    pub(crate) async fn read(mut body: Body, config: &HttpBodyConfig) -> Result<Self> {
+        let mut buffer = Vec::new();
+        let mut file: Option<tokio::fs::File> = None;
+        let mut len = 0;
+        while let Some(chunk) = body.data().await {
+            let chunk = chunk?;
+            len += chunk.len() as u64;
+            match &mut file {
+                Some(file) => file.write_all(&chunk).await?,
+                None if (buffer.len() + chunk.len()) as u64 > config.max_in_memory_bytes => {
+                    let mut spool = tokio::fs::File::from_std(spool_file(&config.spool_dir)?);
+                    spool.write_all(&buffer).await?;
+                    spool.write_all(&chunk).await?;
+                    buffer = Vec::new();
+                    file = Some(spool);
+                }
+                None => buffer.extend_from_slice(&chunk),
+            }
+        }
+        match file {
+            None => Ok(Self::Memory(buffer)),
+            Some(mut spool) => {
+                spool.flush().await?;
+                let mut file = spool.into_std().await;
+                file.seek(SeekFrom::Start(0))?;
+                Ok(Self::File { file, len })
+            }
+        }
+    }
+
+    /// The length of the body.
+    pub(crate) fn len(&self) -> u64 {
+        match self {
+            Self::Memory(bytes) => bytes.len() as u64,
+            Self::File { len, .. } => *len,
+        }
+    }
+
+    /// A pipe from which a guest reads the body, such as its standard input.
+    pub(crate) fn into_pipe(self) -> Box<dyn WasiFile> {
+        match self {
+            Self::Memory(bytes) => Box::new(ReadPipe::from(bytes)),
+            Self::File { file, .. } => Box::new(ReadPipe::new(file)),
+        }
+    }
+}
+
+/// Collects the output of a guest, spooling it to a temporary file once it
+/// exceeds the in-memory limit, so that it can be streamed to the client.
+pub(crate) struct SpoolWriter {
+    max_in_memory_bytes: u64,
+    spool_dir: Option<PathBuf>,
+    /// The output, or its beginning once it is spooled.
+    head: Vec<u8>,
+    /// The whole output, once it is spooled.
+    file: Option<File>,
+}
+
+impl SpoolWriter {
+    pub(crate) fn new(config: &HttpBodyConfig) -> Self {
+        Self {
+            max_in_memory_bytes: config.max_in_memory_bytes,
+            spool_dir: config.spool_dir.clone(),
+            head: vec![],
+            file: None,
+        }
+    }
+
+    /// The output held in memory.
+    pub(crate) fn head(&self) -> &[u8] {
+        &self.head
+    }
+
+    /// Composes the response from the CGI output of a Wagi guest, streaming
+    /// its body from the spooled output.
+    pub(crate) fn compose_response(&mut self) -> Result<Response<Body>> {
+        let head = std::mem::take(&mut self.head);
+        let mut file = match self.file.take() {
+            Some(file) => file,
+            None => return wagi::handlers::compose_response(Arc::new(RwLock::new(head))),
+        };
+        let end = header_end(&head)
+            .context("The headers of the response are larger than the in-memory limit")?;
+        let mut res =
+            wagi::handlers::compose_response(Arc::new(RwLock::new(head[..end].to_vec())))?;
+        file.seek(SeekFrom::Start(end as u64))?;
+        *res.body_mut() = Body::wrap_stream(ReaderStream::new(tokio::fs::File::from_std(file)));
+        Ok(res)
+    }
+}
+
+impl Write for SpoolWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match &mut self.file {
+            Some(file) => file.write_all(buf)?,
+            None if (self.head.len() + buf.len()) as u64 > self.max_in_memory_bytes => {
+                let mut file = spool_file(&self.spool_dir)?;
+                file.write_all(&self.head)?;
+                file.write_all(buf)?;
+                self.file = Some(file);
+            }
+            None => self.head.extend_from_slice(buf),
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match &mut self.file {
+            Some(file) => file.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A temporary file, removed once closed.
+fn spool_file(dir: &Option<PathBuf>) -> std::io::Result<File> {
+    match dir {
+        Some(dir) => tempfile::tempfile_in(dir),
+        None => tempfile::tempfile(),
+    }
+}
+
+/// The offset of the body in CGI output, after the blank line ending its
+/// headers. As in Wagi, carriage returns are ignored in the headers.
+fn header_end(output: &[u8]) -> Option<usize> {
+    let mut last = 0;
+    for (i, byte) in output.iter().enumerate() {
+        match byte {
+            b'\r' => continue,
+            b'\n' if last == b'\n' => return Some(i + 1),
+            _ => last = *byte,
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(max_in_memory_bytes: u64) -> HttpBodyConfig {
+        HttpBodyConfig {
+            max_in_memory_bytes,
+            ..Default::default()
+        }
+    }
+
+    fn chunked(chunks: &'static [&'static str]) -> Body {
+        Body::wrap_stream(futures::stream::iter(
+            chunks.iter().map(|c| Ok::<_, std::io::Error>(*c)),
+        ))
+    }
+
+    #[tokio::test]
+    async fn test_read_to_limit() -> Result<()> {
+        let body = read_to_limit(chunked(&["hello ", "world"]), 11).await?;
+        assert_eq!(body.as_deref(), Some(&b"hello world"[..]));
+        assert!(read_to_limit(chunked(&["hello ", "world"]), 10)
+            .await?
+            .is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_spooled() -> Result<()> {
+        let spooled = Spooled::read(chunked(&["hello ", "world"]), &config(64)).await?;
+        assert!(matches!(spooled, Spooled::Memory(_)));
+
+        let spooled = Spooled::read(chunked(&["hello ", "world"]), &config(8)).await?;
+        assert_eq!(spooled.len(), 11);
+        match spooled {
+            Spooled::File { mut file, .. } => {
+                let mut contents = String::new();
+                std::io::Read::read_to_string(&mut file, &mut contents)?;
+                assert_eq!(contents, "hello world");
+            }
+            Spooled::Memory(_) => panic!("expected the body to be spooled"),
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_spooled_response() -> Result<()> {
+        let mut writer = SpoolWriter::new(&config(32));
+        writer.write_all(b"content-type: text/plain\r\n\r\n")?;
+        writer.write_all(b"a body longer than the in-memory limit")?;
+        assert!(writer.file.is_some());
+
+        let res = writer.compose_response()?;
+        assert_eq!(res.headers()["content-type"], "text/plain");
+        let body = hyper::body::to_bytes(res.into_body()).await?;
+        assert_eq!(&body[..], b"a body longer than the in-memory limit");
+        Ok(())
+    }
+
+    #[test]
+    fn test_header_end() {
+        assert_eq!(header_end(b"status: 200\n\nbody"), Some(13));
+        assert_eq!(header_end(b"status: 200\r\n\r\nbody"), Some(15));
+        assert_eq!(header_end(b"status: 200\n"), None);
+    }
+}
@@ -0,0 +1,271 @@
+//! Cross-origin resource sharing (CORS) for component routes.
+
+use http::{header, HeaderMap, HeaderValue, Method, Request, Response, StatusCode};
+use hyper::Body;
+use spin_manifest::HttpCorsConfig;
+
+/// Allows any origin, method or header.
+const ANY: &str = "*";
+
+/// The outcome of checking a request against the CORS configuration of its
+/// route.
+pub(crate) enum CorsCheck {
+    /// The request is not a cross-origin request.
+    NotCors,
+    /// The request is a preflight request, answered without invoking the
+    /// component.
+    Preflight(Response<Body>),
+    /// The request is a cross-origin request the route allows, from the
+    /// given origin.
+    Allowed(HeaderValue),
+    /// The request is a cross-origin request the route does not allow.
+    Forbidden,
+}
+
+/// Checks a request against the CORS configuration of its route.
+pub(crate) fn check<B>(config: &HttpCorsConfig, req: &Request<B>) -> CorsCheck {
+    let origin = match req.headers().get(header::ORIGIN) {
+        Some(origin) if !is_same_origin(origin, req) => origin.clone(),
+        _ => return CorsCheck::NotCors,
+    };
+    let allowed_origin = origin
+        .to_str()
+        .map(|o| allows(&config.allowed_origins, o, true))
+        .unwrap_or(false);
+
+    let requested_method = req.headers().get(header::ACCESS_CONTROL_REQUEST_METHOD);
+    if req.method() == Method::OPTIONS && requested_method.is_some() {
+        let method = requested_method.and_then(|m| m.to_str().ok()).unwrap_or("");
+        let headers = req
+            .headers()
+            .get(header::ACCESS_CONTROL_REQUEST_HEADERS)
+            .and_then(|h| h.to_str().ok())
+            .unwrap_or("");
+        let allowed = allowed_origin
+            && allows(&config.allowed_methods, method, true)
+            && headers
+                .split(',')
+                .map(str::trim)
+                .filter(|h| !h.is_empty())
+                .all(|h| allows(&config.allowed_headers, h, false));
+        if !allowed {
+            return CorsCheck::Forbidden;
+        }
+        return CorsCheck::Preflight(preflight(config, &origin, method, headers));
+    }
+
+    if allowed_origin && allows(&config.allowed_methods, req.method().as_str(), true) {
+        CorsCheck::Allowed(origin)
+    } else {
+        CorsCheck::Forbidden
+    }
+}
+
+/// Adds the CORS headers of the route to the response to an allowed
+/// cross-origin request.
+pub(crate) fn apply(config: &HttpCorsConfig, origin: &HeaderValue, headers: &mut HeaderMap) {
+    allow_origin(config, origin, headers);
+    if !config.expose_headers.is_empty() {
+        if let Ok(value) = HeaderValue::from_str(&config.expose_headers.join(", ")) {
+            headers.insert(header::ACCESS_CONTROL_EXPOSE_HEADERS, value);
+        }
+    }
+}
+
+fn preflight(
+    config: &HttpCorsConfig,
+    origin: &HeaderValue,
+    method: &str,
+    requested_headers: &str,
+) -> Response<Body> {
+    let mut res = Response::new(Body::empty());
+    *res.status_mut() = StatusCode::NO_CONTENT;
+    let headers = res.headers_mut();
+    allow_origin(config, origin, headers);
+
+    // Wildcards echo what was requested, as browsers do not accept them
+    // for requests with credentials.
+    let methods = if config.allowed_methods.iter().any(|m| m == ANY) {
+        method.to_owned()
+    } else {
+        config.allowed_methods.join(", ")
+    };
+    if let Ok(value) = HeaderValue::from_str(&methods) {
+        headers.insert(header::ACCESS_CONTROL_ALLOW_METHODS, value);
+    }
+    if !requested_headers.is_empty() {
+        let allowed = if config.allowed_headers.iter().any(|h| h == ANY) {
+            requested_headers.to_owned()
+        } else {
+            config.allowed_headers.join(", ")
+        };
+        if let Ok(value) = HeaderValue::from_str(&allowed) {
+            headers.insert(header::ACCESS_CONTROL_ALLOW_HEADERS, value);
+        }
+    }
+    if let Some(max_age) = config.max_age_secs {
+        headers.insert(header::ACCESS_CONTROL_MAX_AGE, HeaderValue::from(max_age));
+    }
+    res
+}
+
+fn allow_origin(config: &HttpCorsConfig, origin: &HeaderValue, headers: &mut HeaderMap) {
+    if config.allowed_origins.iter().any(|o| o == ANY) && !config.allow_credentials {
+        headers.insert(
+            header::ACCESS_CONTROL_ALLOW_ORIGIN,
+            HeaderValue::from_static(ANY),
+        );
+    } else {
+        headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin.clone());
+        headers.append(header::VARY, HeaderValue::from_static("origin"));
+    }
+    if config.allow_credentials {
+        headers.insert(
+            header::ACCESS_CONTROL_ALLOW_CREDENTIALS,
+            HeaderValue::from_static("true"),
+        );
+    }
+}
+
+/// Whether the value is in the allowed list, or the list allows any value.
+fn allows(allowed: &[String], value: &str, case_sensitive: bool) -> bool {
+    allowed.iter().any(|a| {
+        a == ANY
+            || (case_sensitive && a == value)
+            || (!case_sensitive && a.eq_ignore_ascii_case(value))
+    })
+}
+
+/// Whether the origin of the request is that of the application itself.
+fn is_same_origin<B>(origin: &HeaderValue, req: &Request<B>) -> bool {
+    match (req.uri().scheme_str(), req.uri().authority()) {
+        (Some(scheme), Some(authority)) => {
+            origin.as_bytes() == format!("{}://{}", scheme, authority).as_bytes()
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> HttpCorsConfig {
+        HttpCorsConfig {
+            allowed_origins: vec!["https://app.example.com".to_owned()],
+            allowed_methods: vec!["GET".to_owned(), "PUT".to_owned()],
+            allowed_headers: vec!["Content-Type".to_owned()],
+            expose_headers: vec!["x-request-id".to_owned()],
+            allow_credentials: true,
+            max_age_secs: Some(600),
+        }
+    }
+
+    fn request(method: &str, origin: Option<&str>) -> http::request::Builder {
+        let builder = Request::builder()
+            .method(method)
+            .uri("https://api.example.com/items");
+        match origin {
+            Some(origin) => builder.header(header::ORIGIN, origin),
+            None => builder,
+        }
+    }
+
+    #[test]
+    fn test_preflight() {
+        let req = request("OPTIONS", Some("https://app.example.com"))
+            .header(header::ACCESS_CONTROL_REQUEST_METHOD, "PUT")
+            .header(header::ACCESS_CONTROL_REQUEST_HEADERS, "content-type")
+            .body(())
+            .unwrap();
+        let res = match check(&config(), &req) {
+            CorsCheck::Preflight(res) => res,
+            _ => panic!("expected a preflight response"),
+        };
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+        let headers = res.headers();
+        assert_eq!(
+            headers[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://app.example.com"
+        );
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_METHODS], "GET, PUT");
+        assert_eq!(
+            headers[header::ACCESS_CONTROL_ALLOW_HEADERS],
+            "Content-Type"
+        );
+        assert_eq!(headers[header::ACCESS_CONTROL_MAX_AGE], "600");
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_CREDENTIALS], "true");
+
+        let req = request("OPTIONS", Some("https://app.example.com"))
+            .header(header::ACCESS_CONTROL_REQUEST_METHOD, "DELETE")
+            .body(())
+            .unwrap();
+        assert!(matches!(check(&config(), &req), CorsCheck::Forbidden));
+
+        let req = request("OPTIONS", Some("https://app.example.com"))
+            .header(header::ACCESS_CONTROL_REQUEST_METHOD, "PUT")
+            .header(header::ACCESS_CONTROL_REQUEST_HEADERS, "x-secret")
+            .body(())
+            .unwrap();
+        assert!(matches!(check(&config(), &req), CorsCheck::Forbidden));
+    }
+
+    #[test]
+    fn test_requests() {
+        let req = request("GET", None).body(()).unwrap();
+        assert!(matches!(check(&config(), &req), CorsCheck::NotCors));
+
+        let req = request("PUT", Some("https://api.example.com"))
+            .body(())
+            .unwrap();
+        assert!(matches!(check(&config(), &req), CorsCheck::NotCors));
+
+        let req = request("GET", Some("https://evil.example.com"))
+            .body(())
+            .unwrap();
+        assert!(matches!(check(&config(), &req), CorsCheck::Forbidden));
+
+        let req = request("GET", Some("https://app.example.com"))
+            .body(())
+            .unwrap();
+        let origin = match check(&config(), &req) {
+            CorsCheck::Allowed(origin) => origin,
+            _ => panic!("expected the request to be allowed"),
+        };
+        let mut headers = HeaderMap::new();
+        apply(&config(), &origin, &mut headers);
+        assert_eq!(
+            headers[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://app.example.com"
+        );
+        assert_eq!(
+            headers[header::ACCESS_CONTROL_EXPOSE_HEADERS],
+            "x-request-id"
+        );
+        assert_eq!(headers[header::VARY], "origin");
+    }
+
+    #[test]
+    fn test_any_origin() {
+        let config = HttpCorsConfig {
+            allowed_origins: vec![ANY.to_owned()],
+            ..Default::default()
+        };
+        let req = request("POST", Some("https://other.example.com"))
+            .body(())
+            .unwrap();
+        let origin = match check(&config, &req) {
+            CorsCheck::Allowed(origin) => origin,
+            _ => panic!("expected the request to be allowed"),
+        };
+        let mut headers = HeaderMap::new();
+        apply(&config, &origin, &mut headers);
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_ORIGIN], "*");
+        assert!(headers.get(header::VARY).is_none());
+
+        let req = request("DELETE", Some("https://other.example.com"))
+            .body(())
+            .unwrap();
+        assert!(matches!(check(&config, &req), CorsCheck::Forbidden));
+    }
+}
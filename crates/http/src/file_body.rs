@@ -0,0 +1,107 @@
+//! Responses whose body is a file mounted in the component.
+//!
+//! Instead of reading a file and copying it through guest memory, a component
+//! can set the `spin-file-body` response header to the guest path of a mounted
+//! file, and the trigger streams the file from the host.
+
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use http::header::{HeaderValue, CONTENT_LENGTH};
+use hyper::{Body, Response};
+use spin_manifest::DirectoryMount;
+use tokio_util::io::ReaderStream;
+use tracing::log;
+
+/// The response header naming the guest path of the response body file.
+pub(crate) const FILE_BODY_HEADER: &str = "spin-file-body";
+
+/// Replaces the body of the response with the file named by its
+/// `spin-file-body` header, if set.
+pub(crate) async fn resolve(
+    mut res: Response<Body>,
+    mounts: &[DirectoryMount],
+) -> Result<Response<Body>> {
+    let guest_path = match res.headers_mut().remove(FILE_BODY_HEADER) {
+        Some(value) => value
+            .to_str()
+            .context("Invalid spin-file-body header")?
+            .to_string(),
+        None => return Ok(res),
+    };
+
+    let host_path = host_path(mounts, &guest_path)?;
+    let file = tokio::fs::File::open(&host_path)
+        .await
+        .with_context(|| format!("Cannot open response body file {}", guest_path))?;
+    let metadata = file.metadata().await?;
+    if !metadata.is_file() {
+        bail!("Response body file {} is not a regular file", guest_path);
+    }
+    log::trace!("Streaming response body from {}", host_path.display());
+
+    res.headers_mut()
+        .insert(CONTENT_LENGTH, HeaderValue::from(metadata.len()));
+    *res.body_mut() = Body::wrap_stream(ReaderStream::new(file));
+    Ok(res)
+}
+
+/// Maps a guest path to the host path of the mounted file, refusing paths
+/// that would escape the mounted directory.
+pub(crate) fn host_path(mounts: &[DirectoryMount], guest_path: &str) -> Result<PathBuf> {
+    // Guest paths are POSIX paths, which are not absolute for `Path` on
+    // Windows, as they have no drive.
+    let absolute = guest_path.starts_with('/');
+    let guest_path = Path::new(guest_path);
+    if !absolute
+        || guest_path
+            .components()
+            .any(|c| matches!(c, Component::ParentDir | Component::Prefix(_)))
+    {
+        bail!(
+            "Response body file {} must be an absolute path",
+            guest_path.display()
+        );
+    }
+
+    let (mount, relative) = mounts
+        .iter()
+        .filter_map(|m| Some((m, guest_path.strip_prefix(&m.guest).ok()?)))
+        .max_by_key(|(m, _)| m.guest.len())
+        .with_context(|| format!("{} is not in a mounted directory", guest_path.display()))?;
+
+    // Symlinks in the mounted directory must not lead outside of it.
+    let root = mount.host.canonicalize()?;
+    let path = root.join(relative).canonicalize()?;
+    if !path.starts_with(&root) {
+        bail!("{} is not in a mounted directory", guest_path.display());
+    }
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_host_path() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let dir = temp_dir.path();
+        std::fs::create_dir_all(dir.join("static"))?;
+        std::fs::write(dir.join("static/index.html"), "<h1>Spin</h1>")?;
+        let mounts = vec![DirectoryMount {
+            guest: "/".to_string(),
+            host: dir.to_path_buf(),
+        }];
+
+        assert_eq!(
+            host_path(&mounts, "/static/index.html")?,
+            dir.canonicalize()?.join("static/index.html")
+        );
+        assert!(host_path(&mounts, "/static/../../etc/passwd").is_err());
+        assert!(host_path(&mounts, "static/index.html").is_err());
+        assert!(host_path(&mounts, "C:/static/index.html").is_err());
+        assert!(host_path(&mounts, "/static/missing.html").is_err());
+        Ok(())
+    }
+}
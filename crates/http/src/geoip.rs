@@ -0,0 +1,94 @@
+use std::net::IpAddr;
+
+use anyhow::{Context, Result};
+use http::{HeaderMap, HeaderValue};
+use maxminddb::{geoip2, Reader};
+use spin_trigger::GeoIpConfig;
+use tracing::log;
+
+/// The header with the ISO 3166-1 country code of the client.
+const COUNTRY_HEADER: &str = "spin-client-country";
+/// The header with the autonomous system number of the client.
+const ASN_HEADER: &str = "spin-client-asn";
+/// The header with the autonomous system organization of the client.
+const AS_ORG_HEADER: &str = "spin-client-as-org";
+
+/// Resolves client addresses against local MaxMind databases.
+pub(crate) struct GeoIp {
+    country: Option<Reader<Vec<u8>>>,
+    asn: Option<Reader<Vec<u8>>>,
+}
+
+impl GeoIp {
+    pub(crate) fn open(config: &GeoIpConfig) -> Result<Self> {
+        let open = |path: &std::path::Path| {
+            Reader::open_readfile(path)
+                .with_context(|| format!("Cannot open MMDB database {}", path.display()))
+        };
+        Ok(Self {
+            country: config.country_database.as_deref().map(open).transpose()?,
+            asn: config.asn_database.as_deref().map(open).transpose()?,
+        })
+    }
+
+    /// Removes any enrichment headers sent by the client.
+    pub(crate) fn strip(headers: &mut HeaderMap) {
+        for name in [COUNTRY_HEADER, ASN_HEADER, AS_ORG_HEADER] {
+            headers.remove(name);
+        }
+    }
+
+    /// Adds enrichment headers for the given client address. Addresses that
+    /// are not in the databases, such as private ones, are left unenriched.
+    pub(crate) fn enrich(&self, headers: &mut HeaderMap, ip: IpAddr) {
+        if let Some(reader) = &self.country {
+            match reader.lookup::<geoip2::Country>(ip) {
+                Ok(country) => {
+                    if let Some(code) = country.country.and_then(|c| c.iso_code) {
+                        insert(headers, COUNTRY_HEADER, code);
+                    }
+                }
+                Err(e) => log::trace!("No country found for {}: {}", ip, e),
+            }
+        }
+        if let Some(reader) = &self.asn {
+            match reader.lookup::<geoip2::Asn>(ip) {
+                Ok(asn) => {
+                    if let Some(number) = asn.autonomous_system_number {
+                        insert(headers, ASN_HEADER, &number.to_string());
+                    }
+                    if let Some(org) = asn.autonomous_system_organization {
+                        insert(headers, AS_ORG_HEADER, org);
+                    }
+                }
+                Err(e) => log::trace!("No ASN found for {}: {}", ip, e),
+            }
+        }
+    }
+}
+
+fn insert(headers: &mut HeaderMap, name: &'static str, value: &str) {
+    if let Ok(value) = HeaderValue::from_str(value) {
+        headers.insert(name, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strip_removes_client_supplied_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert(COUNTRY_HEADER, HeaderValue::from_static("NZ"));
+        headers.insert(ASN_HEADER, HeaderValue::from_static("64512"));
+        headers.insert("accept", HeaderValue::from_static("*/*"));
+        GeoIp::strip(&mut headers);
+        assert_eq!(headers.len(), 1);
+
+        // Without databases, nothing is added back.
+        let geoip = GeoIp::open(&GeoIpConfig::default()).unwrap();
+        geoip.enrich(&mut headers, "1.1.1.1".parse().unwrap());
+        assert_eq!(headers.len(), 1);
+    }
+}
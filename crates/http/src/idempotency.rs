@@ -0,0 +1,516 @@
+//! Replaying the responses of idempotent routes for the HTTP trigger.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use http::{HeaderValue, StatusCode};
+use hyper::{Body, Request, Response};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use spin_manifest::HttpIdempotencyConfig;
+use spin_trigger::IdempotencyConfig;
+use tracing::log;
+
+/// The header set on replayed responses.
+pub(crate) const REPLAYED_HEADER: &str = "idempotent-replayed";
+
+const REDIS_KEY_PREFIX: &str = "spin:idempotency:";
+
+/// How long a key is claimed for while its request is handled, so that the
+/// claims of instances that stopped before completing them expire.
+const IN_PROGRESS_LEASE: Duration = Duration::from_secs(600);
+
+/// The number of entries a memory store holds before it removes those that
+/// expired. If too few have, it holds twice as many before trying again.
+const MIN_PRUNED_ENTRIES: usize = 1024;
+
+/// The state of an idempotency key.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case", tag = "state")]
+enum Entry {
+    /// A request with the key is being handled.
+    InProgress { fingerprint: String },
+    /// A request with the key was handled, with this response.
+    Completed {
+        fingerprint: String,
+        response: StoredResponse,
+    },
+}
+
+impl Entry {
+    fn fingerprint(&self) -> &str {
+        match self {
+            Self::InProgress { fingerprint } | Self::Completed { fingerprint, .. } => fingerprint,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize)]
+struct StoredResponse {
+    status: u16,
+    headers: Vec<(String, Vec<u8>)>,
+    body: Vec<u8>,
+}
+
+/// A store for the entries of idempotency keys.
+#[async_trait]
+trait EntryStore: Send + Sync {
+    /// Sets the entry of the key, unless it has one, in which case that
+    /// entry is returned.
+    async fn insert_new(&self, key: &str, entry: &Entry, ttl: Duration) -> Result<Option<Entry>>;
+    /// Sets the entry of the key.
+    async fn set(&self, key: &str, entry: &Entry, ttl: Duration) -> Result<()>;
+    /// Removes the entry of the key.
+    async fn remove(&self, key: &str) -> Result<()>;
+}
+
+/// Stores the responses of idempotent routes, and replays them for
+/// requests with the same idempotency key.
+pub(crate) struct Idempotency {
+    store: Arc<dyn EntryStore>,
+}
+
+/// The outcome of checking a request to an idempotent route.
+pub(crate) enum Admission {
+    /// The request should be handled by the component, and its response
+    /// completed with the claim, if the request has an idempotency key.
+    Handle(Request<Body>, Option<Claim>),
+    /// The response to send instead of handling the request.
+    Respond(Response<Body>),
+}
+
+impl Idempotency {
+    pub(crate) fn new(config: &IdempotencyConfig) -> Result<Self> {
+        let store: Arc<dyn EntryStore> = match config {
+            IdempotencyConfig::Memory => Arc::new(MemoryStore::default()),
+            IdempotencyConfig::Redis { address } => Arc::new(RedisStore {
+                client: redis::Client::open(address.as_str())?,
+            }),
+        };
+        Ok(Self { store })
+    }
+
+    /// Checks a request for a component route with idempotency
+    /// configuration, claiming its idempotency key, or returning the
+    /// response to replay.
+    pub(crate) async fn admit(
+        &self,
+        component: &str,
+        config: &HttpIdempotencyConfig,
+        req: Request<Body>,
+    ) -> Result<Admission> {
+        let key = match req.headers().get(config.header.as_str()) {
+            Some(key) => format!("{}:{}", component, String::from_utf8_lossy(key.as_bytes())),
+            None if config.required => {
+                return Ok(Admission::Respond(error_response(
+                    StatusCode::BAD_REQUEST,
+                    &format!("Missing {} header", config.header),
+                )?))
+            }
+            None => return Ok(Admission::Handle(req, None)),
+        };
+
+        let (parts, body) = req.into_parts();
+        let body = hyper::body::to_bytes(body).await?;
+        let fingerprint = fingerprint(&parts, &body);
+        let req = Request::from_parts(parts, Body::from(body));
+
+        let ttl = Duration::from_secs(config.ttl_secs);
+        let claimed = Entry::InProgress {
+            fingerprint: fingerprint.clone(),
+        };
+        let existing = self
+            .store
+            .insert_new(&key, &claimed, ttl.min(IN_PROGRESS_LEASE))
+            .await?;
+        match existing {
+            None => Ok(Admission::Handle(
+                req,
+                Some(Claim {
+                    store: self.store.clone(),
+                    key,
+                    fingerprint,
+                    ttl,
+                    released: false,
+                }),
+            )),
+            Some(entry) if entry.fingerprint() != fingerprint => {
+                Ok(Admission::Respond(error_response(
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    "Idempotency key was used for a different request",
+                )?))
+            }
+            Some(Entry::InProgress { .. }) => Ok(Admission::Respond(error_response(
+                StatusCode::CONFLICT,
+                "A request with this idempotency key is in progress",
+            )?)),
+            Some(Entry::Completed { response, .. }) => Ok(Admission::Respond(replay(response)?)),
+        }
+    }
+}
+
+/// A claimed idempotency key, to be completed with the response to the
+/// request. A claim dropped before it is completed or abandoned, for example
+/// because the client disconnected, is abandoned.
+pub(crate) struct Claim {
+    store: Arc<dyn EntryStore>,
+    key: String,
+    fingerprint: String,
+    ttl: Duration,
+    /// Whether the key was completed or abandoned.
+    released: bool,
+}
+
+impl Claim {
+    /// Stores the response to replay for the key, and returns it. Server
+    /// errors are not stored, so that the request can be retried.
+    pub(crate) async fn complete(mut self, res: Response<Body>) -> Result<Response<Body>> {
+        if res.status().is_server_error() {
+            self.abandon().await;
+            return Ok(res);
+        }
+
+        let (parts, body) = res.into_parts();
+        let body = hyper::body::to_bytes(body).await?;
+        let entry = Entry::Completed {
+            fingerprint: self.fingerprint.clone(),
+            response: StoredResponse {
+                status: parts.status.as_u16(),
+                headers: parts
+                    .headers
+                    .iter()
+                    .map(|(name, value)| (name.to_string(), value.as_bytes().to_vec()))
+                    .collect(),
+                body: body.to_vec(),
+            },
+        };
+        if let Err(e) = self.store.set(&self.key, &entry, self.ttl).await {
+            log::error!(
+                "Failed to store response for idempotency key {}: {:?}",
+                self.key,
+                e
+            );
+        }
+        self.released = true;
+        Ok(Response::from_parts(parts, Body::from(body)))
+    }
+
+    /// Releases the key without a response, so that the request can be
+    /// retried.
+    pub(crate) async fn abandon(mut self) {
+        if let Err(e) = self.store.remove(&self.key).await {
+            log::error!("Failed to release idempotency key {}: {:?}", self.key, e);
+        }
+        self.released = true;
+    }
+}
+
+impl Drop for Claim {
+    fn drop(&mut self) {
+        if self.released {
+            return;
+        }
+        let store = self.store.clone();
+        let key = std::mem::take(&mut self.key);
+        match tokio::runtime::Handle::try_current() {
+            Ok(handle) => {
+                handle.spawn(async move {
+                    if let Err(e) = store.remove(&key).await {
+                        log::error!("Failed to release idempotency key {}: {:?}", key, e);
+                    }
+                });
+            }
+            // The claim expires with its lease.
+            Err(_) => log::warn!("Cannot release idempotency key {} outside a runtime", key),
+        }
+    }
+}
+
+/// Identifies the request an idempotency key was first used for, so that
+/// reusing the key for another request is detected.
+fn fingerprint(parts: &http::request::Parts, body: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(parts.method.as_str());
+    hasher.update([0]);
+    hasher.update(
+        parts
+            .uri
+            .path_and_query()
+            .map(|p| p.as_str())
+            .unwrap_or("/"),
+    );
+    hasher.update([0]);
+    hasher.update(body);
+    format!("{:x}", hasher.finalize())
+}
+
+fn replay(stored: StoredResponse) -> Result<Response<Body>> {
+    let mut builder = Response::builder().status(stored.status);
+    for (name, value) in stored.headers {
+        builder = builder.header(name, HeaderValue::from_bytes(&value)?);
+    }
+    Ok(builder
+        .header(REPLAYED_HEADER, "true")
+        .body(Body::from(stored.body))?)
+}
+
+fn error_response(status: StatusCode, message: &str) -> Result<Response<Body>> {
+    Ok(Response::builder()
+        .status(status)
+        .body(Body::from(message.to_string()))?)
+}
+
+/// Entries held in memory, expiring lazily.
+struct MemoryStore {
+    entries: Mutex<MemoryEntries>,
+}
+
+struct MemoryEntries {
+    entries: HashMap<String, (Entry, Instant)>,
+    /// The number of entries at which those that expired are removed.
+    prune_at: usize,
+}
+
+impl Default for MemoryStore {
+    fn default() -> Self {
+        Self {
+            entries: Mutex::new(MemoryEntries {
+                entries: HashMap::new(),
+                prune_at: MIN_PRUNED_ENTRIES,
+            }),
+        }
+    }
+}
+
+#[async_trait]
+impl EntryStore for MemoryStore {
+    async fn insert_new(&self, key: &str, entry: &Entry, ttl: Duration) -> Result<Option<Entry>> {
+        let now = Instant::now();
+        let mut entries = self.entries.lock().unwrap();
+        if entries.entries.len() >= entries.prune_at {
+            entries.entries.retain(|_, (_, expires)| *expires > now);
+            // Pruning again before as many entries are added keeps inserting
+            // constant time on average.
+            entries.prune_at = MIN_PRUNED_ENTRIES.max(2 * entries.entries.len());
+        }
+        match entries.entries.get(key) {
+            Some((existing, expires)) if *expires > now => Ok(Some(existing.clone())),
+            _ => {
+                entries
+                    .entries
+                    .insert(key.to_owned(), (entry.clone(), now + ttl));
+                Ok(None)
+            }
+        }
+    }
+
+    async fn set(&self, key: &str, entry: &Entry, ttl: Duration) -> Result<()> {
+        let expires = Instant::now() + ttl;
+        self.entries
+            .lock()
+            .unwrap()
+            .entries
+            .insert(key.to_owned(), (entry.clone(), expires));
+        Ok(())
+    }
+
+    async fn remove(&self, key: &str) -> Result<()> {
+        self.entries.lock().unwrap().entries.remove(key);
+        Ok(())
+    }
+}
+
+/// Entries held in a Redis server, shared by all Spin instances using it.
+struct RedisStore {
+    client: redis::Client,
+}
+
+impl RedisStore {
+    fn key(key: &str) -> String {
+        format!("{}{}", REDIS_KEY_PREFIX, key)
+    }
+}
+
+#[async_trait]
+impl EntryStore for RedisStore {
+    async fn insert_new(&self, key: &str, entry: &Entry, ttl: Duration) -> Result<Option<Entry>> {
+        let mut conn = self.client.get_async_connection().await?;
+        let set: Option<String> = redis::cmd("SET")
+            .arg(Self::key(key))
+            .arg(serde_json::to_vec(entry)?)
+            .arg("NX")
+            .arg("PX")
+            .arg(ttl.as_millis() as u64)
+            .query_async(&mut conn)
+            .await?;
+        if set.is_some() {
+            return Ok(None);
+        }
+        let existing: Option<Vec<u8>> = redis::cmd("GET")
+            .arg(Self::key(key))
+            .query_async(&mut conn)
+            .await?;
+        match existing {
+            Some(existing) => Ok(Some(
+                serde_json::from_slice(&existing)
+                    .with_context(|| format!("Invalid entry for idempotency key {}", key))?,
+            )),
+            // The entry expired in between: treat the key as claimed by
+            // the earlier request, which is safe, and rare.
+            None => Ok(Some(entry.clone())),
+        }
+    }
+
+    async fn set(&self, key: &str, entry: &Entry, ttl: Duration) -> Result<()> {
+        let mut conn = self.client.get_async_connection().await?;
+        redis::cmd("SET")
+            .arg(Self::key(key))
+            .arg(serde_json::to_vec(entry)?)
+            .arg("PX")
+            .arg(ttl.as_millis() as u64)
+            .query_async(&mut conn)
+            .await?;
+        Ok(())
+    }
+
+    async fn remove(&self, key: &str) -> Result<()> {
+        let mut conn = self.client.get_async_connection().await?;
+        redis::cmd("DEL")
+            .arg(Self::key(key))
+            .query_async(&mut conn)
+            .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(key: &str, body: &'static str) -> Request<Body> {
+        Request::post("https://myservice.fermyon.dev/payments")
+            .header("idempotency-key", key)
+            .body(Body::from(body))
+            .unwrap()
+    }
+
+    async fn admit(idempotency: &Idempotency, req: Request<Body>) -> Admission {
+        idempotency
+            .admit("payments", &Default::default(), req)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_responses_are_replayed_for_duplicate_keys() -> Result<()> {
+        let idempotency = Idempotency::new(&IdempotencyConfig::Memory)?;
+
+        let claim = match admit(&idempotency, request("k1", "pay 10")).await {
+            Admission::Handle(_, Some(claim)) => claim,
+            _ => panic!("first request should be handled"),
+        };
+        match admit(&idempotency, request("k1", "pay 10")).await {
+            Admission::Respond(res) => assert_eq!(res.status(), StatusCode::CONFLICT),
+            _ => panic!("request in progress should be rejected"),
+        }
+
+        let res = Response::builder()
+            .status(StatusCode::CREATED)
+            .header("x-payment", "p-1")
+            .body(Body::from("created"))?;
+        claim.complete(res).await?;
+
+        match admit(&idempotency, request("k1", "pay 10")).await {
+            Admission::Respond(res) => {
+                assert_eq!(res.status(), StatusCode::CREATED);
+                assert_eq!(res.headers()["x-payment"], "p-1");
+                assert_eq!(res.headers()[REPLAYED_HEADER], "true");
+                let body = hyper::body::to_bytes(res.into_body()).await?;
+                assert_eq!(body.as_ref(), b"created");
+            }
+            _ => panic!("duplicate request should be replayed"),
+        }
+        match admit(&idempotency, request("k1", "pay 20")).await {
+            Admission::Respond(res) => {
+                assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY)
+            }
+            _ => panic!("reused key should be rejected"),
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_server_errors_are_not_replayed() -> Result<()> {
+        let idempotency = Idempotency::new(&IdempotencyConfig::Memory)?;
+        let claim = match admit(&idempotency, request("k1", "pay 10")).await {
+            Admission::Handle(_, Some(claim)) => claim,
+            _ => panic!("first request should be handled"),
+        };
+        let res = Response::builder()
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .body(Body::empty())?;
+        claim.complete(res).await?;
+
+        assert!(matches!(
+            admit(&idempotency, request("k1", "pay 10")).await,
+            Admission::Handle(_, Some(_))
+        ));
+        assert!(matches!(
+            admit(
+                &idempotency,
+                Request::post("/payments").body(Body::empty())?
+            )
+            .await,
+            Admission::Handle(_, None)
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_dropped_claims_are_released() -> Result<()> {
+        let idempotency = Idempotency::new(&IdempotencyConfig::Memory)?;
+        match admit(&idempotency, request("k1", "pay 10")).await {
+            Admission::Handle(_, Some(claim)) => drop(claim),
+            _ => panic!("first request should be handled"),
+        }
+
+        // The key is released in the background.
+        for _ in 0..10 {
+            tokio::task::yield_now().await;
+            if let Admission::Handle(_, Some(_)) =
+                admit(&idempotency, request("k1", "pay 10")).await
+            {
+                return Ok(());
+            }
+        }
+        panic!("the key of a dropped claim should be released")
+    }
+
+    #[tokio::test]
+    async fn test_expired_entries_are_replaced() -> Result<()> {
+        let store = MemoryStore::default();
+        let entry = |fingerprint: &str| Entry::InProgress {
+            fingerprint: fingerprint.to_owned(),
+        };
+        assert!(store
+            .insert_new("k1", &entry("a"), Duration::ZERO)
+            .await?
+            .is_none());
+        assert!(store
+            .insert_new("k1", &entry("b"), Duration::from_secs(60))
+            .await?
+            .is_none());
+        assert_eq!(
+            store
+                .insert_new("k1", &entry("c"), Duration::from_secs(60))
+                .await?,
+            Some(entry("b"))
+        );
+        Ok(())
+    }
+}
@@ -1,17 +1,34 @@
 //! Implementation for the Spin HTTP engine.
 
+mod audit;
+mod auth;
+mod body;
+mod cors;
+mod file_body;
+mod geoip;
+mod idempotency;
+mod listener;
+mod metrics;
+mod middleware;
+mod native;
+mod request_config;
+mod response_cache;
 pub mod routes;
+mod rules;
 mod spin;
+mod static_files;
+mod strict;
+mod telemetry;
 mod tls;
 mod wagi;
 
-use std::{future::ready, net::SocketAddr, path::PathBuf, sync::Arc};
+use std::{future::ready, net::SocketAddr, path::PathBuf, sync::Arc, time::Instant};
 
-use anyhow::{Context, Error, Result};
+use anyhow::{bail, Context, Error, Result};
 use async_trait::async_trait;
 use clap::Args;
 use futures_util::stream::StreamExt;
-use http::{uri::Scheme, StatusCode, Uri};
+use http::{uri::Scheme, HeaderValue, StatusCode, Uri};
 use hyper::{
     server::accept,
     server::conn::AddrStream,
@@ -19,21 +36,41 @@ use hyper::{
     Body, Request, Response, Server,
 };
 use spin_http::SpinHttpData;
+use spin_jwt::JwtProviders;
 use spin_manifest::{ComponentMap, HttpConfig, HttpTriggerConfiguration, TriggerConfig};
-use spin_trigger::TriggerExecutor;
+use spin_trigger::{
+    AdaptiveLimiter, HttpBodyConfig, Overloaded, RouteProfile, RuntimeConfig, Scheduler,
+    ShutdownSignal, TriggerExecutor,
+};
 pub use tls::TlsConfig;
 use tls_listener::TlsListener;
 use tokio::net::{TcpListener, TcpStream};
 use tokio_rustls::server::TlsStream;
-use tracing::log;
+use tracing::{log, Instrument};
 
+pub use crate::{audit::AuditSink, native::remove_hop_by_hop_headers};
 use crate::{
+    audit::Auditor,
+    cors::CorsCheck,
+    geoip::GeoIp,
+    idempotency::{Admission, Idempotency},
+    listener::{ListenAddress, Listener},
+    middleware::Middlewares,
+    native::NativeRoutes,
+    response_cache::ResponseCache,
     routes::{RoutePattern, Router},
+    rules::{RequestRules, Verdict},
     spin::SpinHttpExecutor,
+    static_files::StaticFileExecutor,
+    strict::{StrictHttp, Violation},
+    telemetry::RequestMetrics,
     wagi::WagiHttpExecutor,
 };
 
-wit_bindgen_wasmtime::import!("../../wit/ephemeral/spin-http.wit");
+wit_bindgen_wasmtime::import!({
+    paths: ["../../wit/ephemeral/spin-http.wit"],
+    async: *,
+});
 
 type ExecutionContext = spin_engine::ExecutionContext<SpinHttpData>;
 type RuntimeContext = spin_engine::RuntimeContext<SpinHttpData>;
@@ -51,16 +88,69 @@ pub struct HttpTrigger {
     component_triggers: ComponentMap<HttpConfig>,
     /// Router.
     router: Router,
+    /// Routes handled natively by the trigger.
+    native_routes: NativeRoutes,
     /// Spin execution context.
     engine: ExecutionContext,
+    /// Request auditor, if an audit sink was configured.
+    auditor: Option<Auditor>,
+    /// JWT validators for authenticated routes.
+    jwt: Arc<JwtProviders>,
+    /// Middlewares of the routes with a `middleware`.
+    middlewares: Middlewares,
+    /// Client location databases, if configured.
+    geoip: Option<GeoIp>,
+    /// Admission of component requests, if concurrency is limited.
+    scheduler: Option<Arc<Scheduler>>,
+    /// Per-component concurrency limits, if limits are adaptive.
+    limiter: Option<Arc<AdaptiveLimiter>>,
+    /// Stored responses of idempotent routes.
+    idempotency: Idempotency,
+    /// Cached responses of routes with a cache.
+    response_cache: ResponseCache,
+    /// The path metrics are served at, if any.
+    metrics_path: Option<String>,
+    /// The profile recording invocations of each route.
+    profile: Arc<RouteProfile>,
+    /// The devices wasi-nn computations are scheduled on, if wasi-nn is
+    /// available.
+    wasi_nn_devices: Option<Arc<spin_wasi_nn::Devices>>,
+    /// The meter of the outbound traffic of components, if the default host
+    /// components are available.
+    egress: Option<Arc<spin_engine::EgressMeter>>,
+    /// The health of the host services, reported by the health endpoint.
+    health: Arc<spin_engine::ServiceHealth>,
+    /// The address the trigger listens on, which components reach as `self`.
+    self_address: Arc<spin_engine::SelfAddress>,
+    /// Bounds on the bodies held in memory.
+    body_config: HttpBodyConfig,
+    /// Strict checking of requests, if enabled.
+    strict: Option<StrictHttp>,
+    /// The signal on which to stop accepting connections and drain those
+    /// open.
+    shutdown: ShutdownSignal,
+    /// The rules applied to requests before routing them, if configured.
+    rules: Option<Arc<RequestRules>>,
+    /// The OpenTelemetry metrics of the requests routed to components.
+    request_metrics: RequestMetrics,
 }
 
 #[derive(Args)]
 pub struct CliArgs {
-    /// IP address and port to listen on
+    /// IP address and port to listen on, unix:PATH to listen on a Unix domain
+    /// socket, or systemd to accept connections on the socket passed by
+    /// systemd socket activation
     #[clap(long = "listen", default_value = "127.0.0.1:3000")]
     pub address: String,
 
+    /// The permissions of the Unix domain socket, in octal, such as 660
+    #[clap(
+        long = "unix-socket-mode",
+        value_name = "MODE",
+        parse(try_from_str = listener::parse_socket_mode)
+    )]
+    pub unix_socket_mode: Option<u32>,
+
     /// The path to the certificate to use for https, if this is not set, normal http will be used. The cert should be in PEM format
     #[clap(long, env = "SPIN_TLS_CERT", requires = "tls-key")]
     pub tls_cert: Option<PathBuf>,
@@ -68,9 +158,33 @@ pub struct CliArgs {
     /// The path to the certificate key to use for https, if this is not set, normal http will be used. The key should be in PKCS#8 format
     #[clap(long, env = "SPIN_TLS_KEY", requires = "tls-cert")]
     pub tls_key: Option<PathBuf>,
+
+    /// Directory in which to record requests for routes that have auditing enabled
+    #[clap(long, env = "SPIN_AUDIT_DIR", conflicts_with = "audit-url")]
+    pub audit_dir: Option<PathBuf>,
+
+    /// S3-compatible object storage URL, of the form `<ENDPOINT>/<BUCKET>/<PREFIX>`, to which requests for routes that have auditing enabled are uploaded
+    #[clap(long, env = "SPIN_AUDIT_URL", conflicts_with = "audit-dir")]
+    pub audit_url: Option<String>,
+
+    /// The region of the audit object storage bucket
+    #[clap(long, env = "SPIN_AUDIT_REGION", default_value = "us-east-1")]
+    pub audit_region: String,
+
+    /// Reject requests with ambiguous framing, oversized headers or invalid header characters, rather than passing them to components
+    #[clap(long = "strict-http", env = "SPIN_STRICT_HTTP")]
+    pub strict_http: bool,
 }
 
 impl CliArgs {
+    fn audit_sink(&self) -> Result<Option<AuditSink>> {
+        Ok(match (&self.audit_dir, &self.audit_url) {
+            (Some(dir), _) => Some(AuditSink::Directory(dir.clone())),
+            (None, Some(url)) => Some(AuditSink::object_store(url, &self.audit_region)?),
+            (None, None) => None,
+        })
+    }
+
     fn into_tls_config(self) -> Option<TlsConfig> {
         match (self.tls_cert, self.tls_key) {
             (Some(cert_path), Some(key_path)) => Some(TlsConfig {
@@ -107,10 +221,37 @@ impl TriggerExecutor for HttpTrigger {
     ) -> Result<Self> {
         let component_triggers: ComponentMap<HttpConfig> = trigger_configs
             .into_iter()
-            .map(|config| (config.0, config.1))
+            .map(|HttpTriggerConfig(id, mut config)| {
+                // Components without CORS configuration use the trigger's.
+                if config.cors.is_none() {
+                    config.cors = global_config.cors.clone();
+                }
+                (id, config)
+            })
             .collect();
 
         let router = Router::build(&global_config.base, &component_triggers)?;
+        let middlewares = Middlewares::new(&component_triggers)?;
+        let native_routes = NativeRoutes::build(
+            &global_config.base,
+            &global_config.routes,
+            execution_context.config.config_resolver.as_deref(),
+        )?;
+        // Native routes are matched before component routes, so a component
+        // route they overlap would be shadowed for some paths.
+        for (pattern, component) in &router.routes {
+            if let Some((native, _)) = native_routes
+                .describe()
+                .find(|(native, _)| native.overlaps(pattern))
+            {
+                bail!(
+                    "Route {} of component {} overlaps the native route {}",
+                    pattern,
+                    component,
+                    native
+                );
+            }
+        }
         log::trace!(
             "Constructed router for application {}: {:?}",
             execution_context.config.label,
@@ -121,19 +262,142 @@ impl TriggerExecutor for HttpTrigger {
             trigger_config: global_config,
             component_triggers,
             router,
+            native_routes,
             engine: execution_context,
+            auditor: None,
+            jwt: Default::default(),
+            middlewares,
+            geoip: None,
+            scheduler: None,
+            limiter: None,
+            idempotency: Idempotency::new(&Default::default())?,
+            response_cache: ResponseCache::new(&Default::default()),
+            metrics_path: None,
+            profile: Arc::new(RouteProfile::memory()),
+            wasi_nn_devices: None,
+            egress: None,
+            health: Default::default(),
+            self_address: Default::default(),
+            body_config: Default::default(),
+            strict: None,
+            shutdown: Default::default(),
+            rules: None,
+            request_metrics: RequestMetrics::new(),
         })
     }
 
-    async fn run(self, config: Self::RunConfig) -> Result<()> {
-        let listen_addr = config.address.parse()?;
-        let tls = config.into_tls_config();
+    fn configure_runtime(&mut self, runtime_config: &RuntimeConfig) -> Result<()> {
+        for (component, trigger) in &self.component_triggers {
+            if let Some(auth) = &trigger.auth {
+                if !runtime_config.jwt.validator.contains_key(&auth.jwt) {
+                    anyhow::bail!(
+                        "Component {} requires unknown JWT validator {}",
+                        component,
+                        auth.jwt
+                    );
+                }
+            }
+        }
+        for (component, validator) in self.middlewares.jwt_validators() {
+            if !runtime_config.jwt.validator.contains_key(validator) {
+                anyhow::bail!(
+                    "Component {} requires unknown JWT validator {}",
+                    component,
+                    validator
+                );
+            }
+        }
+        for upstream in self.native_routes.upstreams() {
+            if !runtime_config.proxy.is_allowed(upstream) {
+                anyhow::bail!(
+                    "Proxy upstream {} is not in the runtime config allowed_hosts",
+                    upstream
+                );
+            }
+        }
+        self.scheduler = Scheduler::new(&runtime_config.concurrency)?;
+        self.limiter = AdaptiveLimiter::new(runtime_config.concurrency.adaptive.as_ref())?;
+        self.idempotency = Idempotency::new(&runtime_config.idempotency)?;
+        self.response_cache = ResponseCache::new(&runtime_config.http_cache);
+        self.metrics_path = runtime_config.metrics.path.clone();
+        self.body_config = runtime_config.http_body.clone();
+        if runtime_config.geoip.is_enabled() {
+            self.geoip = Some(GeoIp::open(&runtime_config.geoip)?);
+        }
+        if let Some(rules) = &runtime_config.request_rules {
+            self.rules = Some(RequestRules::load(&rules.file)?);
+        }
+        Ok(())
+    }
+
+    fn configure_profile(&mut self, profile: Arc<RouteProfile>) {
+        self.profile = profile;
+    }
+
+    fn configure_wasi_nn(&mut self, devices: Arc<spin_wasi_nn::Devices>) {
+        self.wasi_nn_devices = Some(devices);
+    }
+
+    fn configure_jwt(&mut self, jwt: Arc<JwtProviders>) {
+        self.jwt = jwt;
+    }
+
+    fn configure_egress(&mut self, egress: Arc<spin_engine::EgressMeter>) {
+        self.egress = Some(egress);
+    }
+
+    fn configure_health(&mut self, health: Arc<spin_engine::ServiceHealth>) {
+        self.health = health;
+    }
+
+    fn configure_self_address(&mut self, self_address: Arc<spin_engine::SelfAddress>) {
+        self.self_address = self_address;
+    }
+
+    fn configure_shutdown(&mut self, shutdown: ShutdownSignal) {
+        self.shutdown = shutdown;
+    }
+
+    async fn run(mut self, config: Self::RunConfig) -> Result<()> {
+        let listen_addr: ListenAddress = config.address.parse()?;
+        let listener = Listener::bind(&listen_addr, config.unix_socket_mode).await?;
+        // Components reach the application as `self` only over TCP.
+        if let Some(addr) = listener.local_addr() {
+            self.self_address.set(addr);
+        }
+        if let Some(sink) = config.audit_sink()? {
+            log::info!("Recording audited requests to {:?}", sink);
+            self.auditor = Some(Auditor::new(sink)?);
+        }
+        if config.strict_http {
+            self.strict = Some(StrictHttp::default());
+        }
+        if let Some(rules) = &self.rules {
+            tokio::spawn(rules.clone().reload_periodically());
+        }
+        // Files given on the command line take precedence over the manifest.
+        let tls = config.into_tls_config().or_else(|| {
+            self.trigger_config.tls.as_ref().map(|tls| TlsConfig {
+                cert_path: tls.cert.clone(),
+                key_path: tls.key.clone(),
+            })
+        });
 
         // Print startup messages
         let scheme = if tls.is_some() { "https" } else { "http" };
-        let base_url = format!("{}://{:?}", scheme, listen_addr);
-        println!("Serving {}", base_url);
-        log::info!("Serving {}", base_url);
+        let base_url = match listener.local_addr() {
+            Some(addr) => {
+                let base_url = format!("{}://{:?}", scheme, addr);
+                println!("Serving {}", base_url);
+                log::info!("Serving {}", base_url);
+                base_url
+            }
+            None => {
+                println!("Serving {} on {}", scheme, listen_addr);
+                log::info!("Serving {} on {}", scheme, listen_addr);
+                format!("{}://localhost", scheme)
+            }
+        };
         println!("Available Routes:");
         for (route, component) in &self.router.routes {
             println!("  {}: {}{}", component, base_url, route);
@@ -143,11 +407,21 @@ impl TriggerExecutor for HttpTrigger {
                 }
             }
         }
+        for (route, handler) in self.native_routes.describe() {
+            println!("  ({}): {}{}", handler, base_url, route);
+        }
 
-        if let Some(tls) = tls {
-            self.serve_tls(listen_addr, tls).await?
-        } else {
-            self.serve(listen_addr).await?
+        match (listener, tls) {
+            (Listener::Tcp(listener), Some(tls)) => self.serve_tls(listener, tls).await?,
+            (Listener::Tcp(listener), None) => self.serve(listener).await?,
+            #[cfg(unix)]
+            (Listener::Unix(..), Some(_)) => {
+                bail!(
+                    "TLS is not supported on Unix domain sockets: terminate TLS in the proxy in front of Spin"
+                )
+            }
+            #[cfg(unix)]
+            (Listener::Unix(listener, _file), None) => self.serve_unix(listener).await?,
         };
         Ok(())
     }
@@ -161,7 +435,32 @@ impl HttpTrigger {
         scheme: Scheme,
         addr: SocketAddr,
     ) -> Result<Response<Body>> {
+        if let Some(strict) = &self.strict {
+            if let Err(violation) = strict.check(&req) {
+                log::warn!("Rejected request for {}: {:?}", req.uri(), violation);
+                return Self::rejected(violation);
+            }
+        }
         set_req_uri(&mut req, scheme)?;
+        // Headers set by the trigger itself must not be supplied by clients.
+        req.headers_mut().remove(auth::JWT_CLAIMS_HEADER);
+        req.headers_mut().remove(rules::TAGS_HEADER);
+        GeoIp::strip(req.headers_mut());
+        if let Some(geoip) = &self.geoip {
+            geoip.enrich(req.headers_mut(), addr.ip());
+        }
+        // Rules run after enrichment, so that they can match the client's
+        // location.
+        if let Some(rules) = &self.rules {
+            req = match rules.apply(req).await? {
+                Verdict::Handle(req) => req,
+                Verdict::Deny(status) => {
+                    let mut denied = Response::default();
+                    *denied.status_mut() = status;
+                    return Ok(denied);
+                }
+            };
+        }
 
         log::info!(
             "Processing request for application {} on URI {}",
@@ -170,67 +469,276 @@ impl HttpTrigger {
         );
 
         match req.uri().path() {
-            "/healthz" => Ok(Response::new(Body::from("OK"))),
+            "/healthz" => Ok(Response::new(Body::from(self.health.report()))),
+            route if self.metrics_path.as_deref() == Some(route) => {
+                let scheduler = self.scheduler.as_ref().map(|s| s.stats());
+                let limits = self.limiter.as_ref().map(|l| l.stats());
+                let devices = self
+                    .wasi_nn_devices
+                    .as_ref()
+                    .map(|d| d.stats())
+                    .unwrap_or_default();
+                let egress = self.egress.as_ref().map(|e| e.stats()).unwrap_or_default();
+                let rejected = self.strict.as_ref().map(|s| s.rejected());
+                Ok(Response::new(Body::from(metrics::render(
+                    scheduler.as_ref(),
+                    limits.as_ref(),
+                    &self.engine.load_stats(),
+                    &devices,
+                    &egress,
+                    rejected.as_ref(),
+                ))))
+            }
+            route if self.native_routes.route(route).is_some() => {
+                let (pattern, handler) = self.native_routes.route(route).unwrap();
+                match handler.handle(pattern, req, addr).await {
+                    Ok(res) => Ok(res),
+                    Err(e) => {
+                        log::error!("Error processing request: {:?}", e);
+                        Self::internal_error(None)
+                    }
+                }
+            }
             route => match self.router.route(route) {
                 Ok(component_id) => {
                     let trigger = self.component_triggers.get(component_id).unwrap();
-
-                    let executor = match &trigger.executor {
-                        Some(i) => i,
-                        None => &spin_manifest::HttpExecutor::Spin,
-                    };
-
-                    let follow = self
-                        .engine
-                        .config
-                        .follow_components
-                        .should_follow(component_id);
-
-                    let res = match executor {
-                        spin_manifest::HttpExecutor::Spin => {
-                            let executor = SpinHttpExecutor;
-                            executor
-                                .execute(
-                                    &self.engine,
-                                    component_id,
-                                    &self.trigger_config.base,
-                                    &trigger.route,
-                                    req,
-                                    addr,
-                                    follow,
-                                )
-                                .await
-                        }
-                        spin_manifest::HttpExecutor::Wagi(wagi_config) => {
-                            let executor = WagiHttpExecutor {
-                                wagi_config: wagi_config.clone(),
-                            };
-                            executor
-                                .execute(
-                                    &self.engine,
-                                    component_id,
-                                    &self.trigger_config.base,
-                                    &trigger.route,
-                                    req,
-                                    addr,
-                                    follow,
-                                )
-                                .await
-                        }
+                    self.profile.record(&trigger.route, component_id);
+
+                    // Cross-origin requests are checked before authentication,
+                    // as preflight requests carry no credentials.
+                    let origin = match &trigger.cors {
+                        Some(config) => match cors::check(config, &req) {
+                            CorsCheck::Preflight(res) => return Ok(res),
+                            CorsCheck::Forbidden => return Self::forbidden(),
+                            CorsCheck::Allowed(origin) => Some(origin),
+                            CorsCheck::NotCors => None,
+                        },
+                        None => None,
                     };
-                    match res {
-                        Ok(res) => Ok(res),
-                        Err(e) => {
-                            log::error!("Error processing request: {:?}", e);
-                            Self::internal_error(None)
-                        }
+                    let span = telemetry::request_span(component_id, &req);
+                    let started = Instant::now();
+                    let res = self
+                        .handle_component(component_id, trigger, req, addr)
+                        .instrument(span.clone())
+                        .await;
+                    self.request_metrics
+                        .record(component_id, &span, started, &res);
+                    let mut res = res?;
+                    if let (Some(config), Some(origin)) = (&trigger.cors, origin) {
+                        cors::apply(config, &origin, res.headers_mut());
                     }
+                    Ok(res)
                 }
                 Err(_) => Self::not_found(),
             },
         }
     }
 
+    /// Handles a request routed to a component, applying the policies of its
+    /// route.
+    async fn handle_component(
+        &self,
+        component_id: &str,
+        trigger: &HttpConfig,
+        mut req: Request<Body>,
+        addr: SocketAddr,
+    ) -> Result<Response<Body>> {
+        if let Some(auth) = &trigger.auth {
+            if let Some(res) = auth::authenticate(&self.jwt, auth, &mut req).await? {
+                return Ok(res);
+            }
+        }
+        if let Some(res) = self
+            .middlewares
+            .apply(component_id, &self.jwt, &mut req, addr)
+            .await?
+        {
+            return Ok(res);
+        }
+
+        let req = match (&trigger.audit, &self.auditor) {
+            (Some(audit), Some(auditor)) => auditor.tee(req, component_id, addr, audit).await?,
+            _ => req,
+        };
+
+        let cache_key = match &trigger.cache {
+            Some(cache) => match ResponseCache::key(component_id, cache, &req) {
+                Some(key) => {
+                    if let Some(res) = self.response_cache.get(&key) {
+                        return Ok(res);
+                    }
+                    Some((cache, key))
+                }
+                None => None,
+            },
+            None => None,
+        };
+
+        let (req, claim) = match &trigger.idempotency {
+            Some(idempotency) => {
+                match self
+                    .idempotency
+                    .admit(component_id, idempotency, req)
+                    .await?
+                {
+                    Admission::Handle(req, claim) => (req, claim),
+                    Admission::Respond(res) => return Ok(res),
+                }
+            }
+            None => (req, None),
+        };
+
+        // Held until the component has produced its response.
+        let _permit = match &self.scheduler {
+            Some(scheduler) => match scheduler
+                .admit_with_priority(component_id, trigger.priority)
+                .await
+            {
+                Ok(permit) => Some(permit),
+                Err(e) if e.is::<Overloaded>() => {
+                    if let Some(claim) = claim {
+                        claim.abandon().await;
+                    }
+                    return Self::overloaded();
+                }
+                Err(e) => return Err(e),
+            },
+            None => None,
+        };
+        // Measures the latency of the component once it has been
+        // admitted, so that time spent queued is not counted.
+        let _limit = match &self.limiter {
+            Some(limiter) => match limiter.admit(component_id) {
+                Ok(permit) => permit,
+                Err(e) if e.is::<Overloaded>() => {
+                    if let Some(claim) = claim {
+                        claim.abandon().await;
+                    }
+                    return Self::overloaded();
+                }
+                Err(e) => return Err(e),
+            },
+            None => None,
+        };
+
+        let res = self.execute(component_id, trigger, req, addr).await;
+        let res = match (res, cache_key) {
+            (Ok(res), Some((cache, key))) => self.response_cache.store(cache, &key, res).await,
+            (res, _) => res,
+        };
+        match (res, claim) {
+            (Ok(res), Some(claim)) => claim.complete(res).await,
+            (Ok(res), None) => Ok(res),
+            (Err(e), claim) => {
+                log::error!("Error processing request: {:?}", e);
+                if let Some(claim) = claim {
+                    claim.abandon().await;
+                }
+                Self::error_response(&e)
+            }
+        }
+    }
+
+    /// Runs a component on a request, bypassing routing and the policies of
+    /// its route, such as authentication and concurrency limits. Unlike
+    /// `handle`, failures of the component, such as traps, are returned as
+    /// errors rather than as 500 responses, for testing components in-process.
+    pub async fn execute_component(
+        &self,
+        component_id: &str,
+        mut req: Request<Body>,
+        addr: SocketAddr,
+    ) -> Result<Response<Body>> {
+        set_req_uri(&mut req, Scheme::HTTP)?;
+        let trigger = self
+            .component_triggers
+            .get(component_id)
+            .with_context(|| format!("{} is not an HTTP component", component_id))?;
+        self.execute(component_id, trigger, req, addr).await
+    }
+
+    /// Runs a component on a request with the executor of its route.
+    async fn execute(
+        &self,
+        component_id: &str,
+        trigger: &HttpConfig,
+        req: Request<Body>,
+        addr: SocketAddr,
+    ) -> Result<Response<Body>> {
+        let executor = match &trigger.executor {
+            Some(i) => i,
+            None => &spin_manifest::HttpExecutor::Spin,
+        };
+
+        let follow = self
+            .engine
+            .config
+            .follow_components
+            .should_follow(component_id);
+
+        let request_config = request_config::values(
+            &trigger.request_config,
+            req.uri(),
+            req.headers(),
+            &self.trigger_config.base,
+            &trigger.route,
+        );
+
+        match executor {
+            spin_manifest::HttpExecutor::Spin => {
+                let executor = SpinHttpExecutor {
+                    max_buffered_bytes: self.body_config.max_buffered_bytes,
+                    request_config,
+                };
+                executor
+                    .execute(
+                        &self.engine,
+                        component_id,
+                        &self.trigger_config.base,
+                        &trigger.route,
+                        req,
+                        addr,
+                        follow,
+                    )
+                    .await
+            }
+            spin_manifest::HttpExecutor::Wagi(wagi_config) => {
+                let executor = WagiHttpExecutor {
+                    wagi_config: wagi_config.clone(),
+                    body_config: self.body_config.clone(),
+                    request_config,
+                };
+                executor
+                    .execute(
+                        &self.engine,
+                        component_id,
+                        &self.trigger_config.base,
+                        &trigger.route,
+                        req,
+                        addr,
+                        follow,
+                    )
+                    .await
+            }
+            spin_manifest::HttpExecutor::Static(static_config) => {
+                let executor = StaticFileExecutor {
+                    config: static_config.clone(),
+                };
+                executor
+                    .execute(
+                        &self.engine,
+                        component_id,
+                        &self.trigger_config.base,
+                        &trigger.route,
+                        req,
+                        addr,
+                        follow,
+                    )
+                    .await
+            }
+        }
+    }
+
     /// Creates an HTTP 500 response.
     fn internal_error(body: Option<&str>) -> Result<Response<Body>> {
         let body = match body {
@@ -243,6 +751,46 @@ impl HttpTrigger {
             .body(body)?)
     }
 
+    /// Creates the response to a request its component failed to handle: a
+    /// 504 if it ran past its timeout, a 503 if no instance was available for
+    /// it, and a 500 otherwise.
+    fn error_response(e: &Error) -> Result<Response<Body>> {
+        match e.downcast_ref::<spin_engine::LimitExceeded>() {
+            Some(spin_engine::LimitExceeded::Timeout(_)) => Ok(Response::builder()
+                .status(StatusCode::GATEWAY_TIMEOUT)
+                .body(Body::empty())?),
+            Some(_) => Self::overloaded(),
+            None => Self::internal_error(None),
+        }
+    }
+
+    /// Creates an HTTP 503 response for requests shed under overload.
+    fn overloaded() -> Result<Response<Body>> {
+        Ok(Response::builder()
+            .status(StatusCode::SERVICE_UNAVAILABLE)
+            .header(http::header::RETRY_AFTER, "1")
+            .body(Body::empty())?)
+    }
+
+    /// Creates the response to a request rejected by strict checks, closing
+    /// the connection, as the framing of the requests following it on the
+    /// connection cannot be trusted.
+    fn rejected(violation: Violation) -> Result<Response<Body>> {
+        let mut rejected = Response::default();
+        *rejected.status_mut() = violation.status();
+        rejected
+            .headers_mut()
+            .insert(http::header::CONNECTION, HeaderValue::from_static("close"));
+        Ok(rejected)
+    }
+
+    /// Creates an HTTP 403 response.
+    fn forbidden() -> Result<Response<Body>> {
+        let mut forbidden = Response::default();
+        *forbidden.status_mut() = StatusCode::FORBIDDEN;
+        Ok(forbidden)
+    }
+
     /// Creates an HTTP 404 response.
     fn not_found() -> Result<Response<Body>> {
         let mut not_found = Response::default();
@@ -250,7 +798,7 @@ impl HttpTrigger {
         Ok(not_found)
     }
 
-    async fn serve(self, listen_addr: SocketAddr) -> Result<()> {
+    async fn serve(self, listener: std::net::TcpListener) -> Result<()> {
         let self_ = Arc::new(self);
         let make_service = make_service_fn(|conn: &AddrStream| {
             let self_ = self_.clone();
@@ -264,14 +812,20 @@ impl HttpTrigger {
             }
         });
 
-        Server::try_bind(&listen_addr)
-            .with_context(|| format!("Unable to listen on {}", listen_addr))?
+        let mut server = Server::from_tcp(listener)?;
+        if self_.strict.is_some() {
+            server = server.http1_max_buf_size(strict::MAX_BUFFER_BYTES);
+        }
+        // Once shutdown is requested, the server stops accepting
+        // connections, and completes once those open are idle.
+        server
             .serve(make_service)
+            .with_graceful_shutdown(self_.shutdown.clone().requested())
             .await?;
         Ok(())
     }
 
-    async fn serve_tls(self, listen_addr: SocketAddr, tls: TlsConfig) -> Result<()> {
+    async fn serve_tls(self, listener: std::net::TcpListener, tls: TlsConfig) -> Result<()> {
         let self_ = Arc::new(self);
         let make_service = make_service_fn(|conn: &TlsStream<TcpStream>| {
             let self_ = self_.clone();
@@ -297,9 +851,7 @@ impl HttpTrigger {
             }
         });
 
-        let listener = TcpListener::bind(&listen_addr)
-            .await
-            .with_context(|| format!("Unable to listen on {}", listen_addr))?;
+        let listener = TcpListener::from_std(listener)?;
 
         let incoming = accept::from_stream(
             TlsListener::new(tls.server_config()?, listener).filter(|conn| {
@@ -312,7 +864,49 @@ impl HttpTrigger {
             }),
         );
 
-        Server::builder(incoming).serve(make_service).await?;
+        let mut server = Server::builder(incoming);
+        if self_.strict.is_some() {
+            server = server.http1_max_buf_size(strict::MAX_BUFFER_BYTES);
+        }
+        server
+            .serve(make_service)
+            .with_graceful_shutdown(self_.shutdown.clone().requested())
+            .await?;
+        Ok(())
+    }
+
+    /// Serves connections on a Unix domain socket. Its clients have no IP
+    /// address, so they are seen as connecting from the loopback address:
+    /// the proxy in front of Spin forwards the address of the client, if
+    /// needed, in headers.
+    #[cfg(unix)]
+    async fn serve_unix(self, listener: tokio::net::UnixListener) -> Result<()> {
+        let self_ = Arc::new(self);
+        let addr = SocketAddr::from((std::net::Ipv4Addr::LOCALHOST, 0));
+        let make_service = make_service_fn(|_conn: &tokio::net::UnixStream| {
+            let self_ = self_.clone();
+            async move {
+                let service = service_fn(move |req| {
+                    let self_ = self_.clone();
+                    async move { self_.handle(req, Scheme::HTTP, addr).await }
+                });
+                Ok::<_, Error>(service)
+            }
+        });
+
+        let incoming = accept::poll_fn(move |cx| {
+            listener
+                .poll_accept(cx)
+                .map(|conn| Some(conn.map(|(stream, _)| stream)))
+        });
+        let mut server = Server::builder(incoming);
+        if self_.strict.is_some() {
+            server = server.http1_max_buf_size(strict::MAX_BUFFER_BYTES);
+        }
+        server
+            .serve(make_service)
+            .with_graceful_shutdown(self_.shutdown.clone().requested())
+            .await?;
         Ok(())
     }
 }
@@ -384,6 +978,25 @@ pub(crate) fn compute_default_headers<'a>(
     Ok(res)
 }
 
+/// The values of the named segments of the matched route, such as `id` in
+/// `/users/:id`, as `SPIN_PATH_PARAM_ID` and `X_PATH_PARAM_ID`.
+pub(crate) fn compute_path_params(uri: &Uri, raw: &str, base: &str) -> Vec<([String; 2], String)> {
+    RoutePattern::from(base, raw)
+        .params(uri.path())
+        .into_iter()
+        .map(|(name, value)| {
+            let name = name.to_ascii_uppercase();
+            (
+                [
+                    format!("SPIN_PATH_PARAM_{}", name),
+                    format!("X_PATH_PARAM_{}", name),
+                ],
+                value,
+            )
+        })
+        .collect()
+}
+
 /// The HTTP executor trait.
 /// All HTTP executors must implement this trait.
 #[async_trait]
@@ -405,7 +1018,7 @@ pub(crate) trait HttpExecutor: Clone + Send + Sync + 'static {
 
 #[cfg(test)]
 mod tests {
-    use std::{collections::BTreeMap, sync::Once};
+    use std::{collections::BTreeMap, sync::Once, time::Duration};
 
     use anyhow::Result;
     use spin_manifest::{HttpConfig, HttpExecutor};
@@ -496,7 +1109,6 @@ mod tests {
 
         let default_headers = crate::compute_default_headers(req.uri(), trigger_route, base, host)?;
 
-        // TODO: we currently replace the scheme with HTTP. When TLS is supported, this should be fixed.
         assert_eq!(
             search(FULL_URL, &default_headers).unwrap(),
             "https://fermyon.dev/foo/bar?key1=value1&key2=value2".to_string()
@@ -525,6 +1137,33 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_path_params() -> Result<()> {
+        let uri: Uri = "https://fermyon.dev/api/users/42/orders/a%20b?x=1".parse()?;
+        let params = crate::compute_path_params(&uri, "/users/:id/orders/:order_id", "/api");
+        assert_eq!(
+            params,
+            vec![
+                (
+                    [
+                        "SPIN_PATH_PARAM_ID".to_owned(),
+                        "X_PATH_PARAM_ID".to_owned()
+                    ],
+                    "42".to_owned()
+                ),
+                (
+                    [
+                        "SPIN_PATH_PARAM_ORDER_ID".to_owned(),
+                        "X_PATH_PARAM_ORDER_ID".to_owned()
+                    ],
+                    "a%20b".to_owned()
+                ),
+            ]
+        );
+        assert!(crate::compute_path_params(&uri, "/...", "/api").is_empty());
+        Ok(())
+    }
+
     // fn _search(key: &str, headers: &[(String, String)]) -> Option<String> {}
 
     fn search<'a>(keys: &'a [&'a str], headers: &[(&[&str], String)]) -> Option<String> {
@@ -547,6 +1186,7 @@ mod tests {
             .http_trigger(HttpConfig {
                 route: "/test".to_string(),
                 executor: Some(HttpExecutor::Spin),
+                ..Default::default()
             });
         let app = cfg.build_application();
 
@@ -569,6 +1209,159 @@ mod tests {
         Ok(())
     }
 
+    /// A config provider whose lookups wait, while it is closed, for another
+    /// task to open it.
+    #[derive(Clone, Debug, Default)]
+    struct GatedProvider(Arc<Gate>);
+
+    #[derive(Debug, Default)]
+    struct Gate {
+        open: std::sync::Mutex<bool>,
+        opened: std::sync::Condvar,
+        waiting: tokio::sync::Notify,
+    }
+
+    impl GatedProvider {
+        fn set_open(&self, open: bool) {
+            *self.0.open.lock().unwrap() = open;
+            self.0.opened.notify_all();
+        }
+    }
+
+    impl spin_config::Provider for GatedProvider {
+        fn get(&self, _key: &spin_config::Key) -> Result<Option<String>> {
+            let open = self.0.open.lock().unwrap();
+            if !*open {
+                self.0.waiting.notify_one();
+            }
+            let (_, wait) = self
+                .0
+                .opened
+                .wait_timeout_while(open, Duration::from_secs(10), |open| !*open)
+                .unwrap();
+            anyhow::ensure!(!wait.timed_out(), "the provider was not opened");
+            Ok(Some("hello".to_owned()))
+        }
+    }
+
+    /// A trigger for a component returning its `message` config value, which
+    /// comes from the provider.
+    async fn config_trigger(
+        provider: GatedProvider,
+        scheduling: Option<spin_engine::SchedulingConfig>,
+    ) -> Result<HttpTrigger> {
+        let mut cfg = spin_testing::TestConfig::default();
+        cfg.test_program("config-test.wasm")
+            .http_trigger(HttpConfig {
+                route: "/test".to_string(),
+                executor: Some(HttpExecutor::Spin),
+                ..Default::default()
+            });
+        let mut app = cfg.build_application();
+
+        let mut tree: spin_config::Tree = toml::toml! {
+            message = { required = true }
+        }
+        .try_into()?;
+        tree.merge_defaults(
+            &spin_config::TreePath::new("test-component")?,
+            [("message".to_owned(), "{{ message }}".to_owned())],
+        )?;
+        let mut resolver = spin_config::Resolver::new(tree)?;
+        resolver.add_provider(provider);
+        app.config_resolver = Some(Arc::new(resolver));
+
+        let mut builder = TriggerExecutorBuilder::new(app);
+        builder.runtime_config(RuntimeConfig {
+            scheduling,
+            ..Default::default()
+        });
+        builder.build().await
+    }
+
+    #[tokio::test]
+    async fn test_guests_read_config() -> Result<()> {
+        init();
+
+        for scheduling in [None, Some(Default::default())] {
+            let provider = GatedProvider::default();
+            provider.set_open(true);
+            let trigger = config_trigger(provider, scheduling).await?;
+
+            let req =
+                http::Request::get("https://myservice.fermyon.dev/test").body(Body::empty())?;
+            let res = trigger
+                .handle(req, Scheme::HTTPS, test_socket_addr())
+                .await?;
+            assert_eq!(res.status(), StatusCode::OK);
+            assert_eq!(hyper::body::to_bytes(res.into_body()).await?, "hello");
+        }
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_host_calls_yield_to_other_tasks() -> Result<()> {
+        init();
+
+        let provider = GatedProvider::default();
+        // Values are also resolved while the application starts.
+        provider.set_open(true);
+        let trigger = config_trigger(provider.clone(), Some(Default::default())).await?;
+        provider.set_open(false);
+
+        // The runtime has a single thread, so the provider is only opened if
+        // the guest yields while its host call waits.
+        let opener = provider.clone();
+        tokio::spawn(async move {
+            opener.0.waiting.notified().await;
+            opener.set_open(true);
+        });
+        let req = http::Request::get("https://myservice.fermyon.dev/test").body(Body::empty())?;
+        let res = trigger
+            .handle(req, Scheme::HTTPS, test_socket_addr())
+            .await?;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(hyper::body::to_bytes(res.into_body()).await?, "hello");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_strict_http_rejects_ambiguous_requests() -> Result<()> {
+        init();
+
+        let mut cfg = spin_testing::TestConfig::default();
+        cfg.test_program("rust-http-test.wasm")
+            .http_trigger(HttpConfig {
+                route: "/test".to_string(),
+                executor: Some(HttpExecutor::Spin),
+                ..Default::default()
+            });
+        let app = cfg.build_application();
+
+        let mut trigger: HttpTrigger = TriggerExecutorBuilder::new(app).build().await?;
+        trigger.strict = Some(StrictHttp::default());
+
+        let req = http::Request::post("https://myservice.fermyon.dev/test")
+            .header("content-length", "7")
+            .header("transfer-encoding", "chunked")
+            .body(Body::from("Fermyon"))
+            .unwrap();
+        let res = trigger
+            .handle(req, Scheme::HTTPS, test_socket_addr())
+            .await?;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(res.headers()["connection"], "close");
+
+        let req = http::Request::post("https://myservice.fermyon.dev/test")
+            .body(Body::from("Fermyon"))
+            .unwrap();
+        let res = trigger
+            .handle(req, Scheme::HTTPS, test_socket_addr())
+            .await?;
+        assert_eq!(res.status(), StatusCode::OK);
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_wagi_http() -> Result<()> {
         init();
@@ -577,6 +1370,7 @@ mod tests {
         cfg.test_program("wagi-test.wasm").http_trigger(HttpConfig {
             route: "/test".to_string(),
             executor: Some(HttpExecutor::Wagi(Default::default())),
+            ..Default::default()
         });
         let app = cfg.build_application();
 
@@ -0,0 +1,266 @@
+//! The sockets the HTTP trigger listens on: a TCP address, a Unix domain
+//! socket, or a socket passed by systemd socket activation.
+
+use std::{fmt, net::SocketAddr, path::PathBuf, str::FromStr};
+
+use anyhow::{bail, Context, Result};
+use tracing::log;
+
+/// The prefix of listen addresses naming a Unix domain socket.
+const UNIX_PREFIX: &str = "unix:";
+
+/// The listen address of the sockets passed by systemd.
+const SYSTEMD: &str = "systemd";
+
+/// Where the HTTP trigger listens, as given to `--listen`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum ListenAddress {
+    /// An IP address and port.
+    Tcp(SocketAddr),
+    /// The path of a Unix domain socket, created by the trigger.
+    Unix(PathBuf),
+    /// The socket passed by systemd with the `LISTEN_FDS` protocol.
+    Systemd,
+}
+
+impl FromStr for ListenAddress {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if let Some(path) = s.strip_prefix(UNIX_PREFIX) {
+            if path.is_empty() {
+                bail!("Invalid listen address {}: expected unix:PATH", s);
+            }
+            return Ok(Self::Unix(PathBuf::from(path)));
+        }
+        if s == SYSTEMD {
+            return Ok(Self::Systemd);
+        }
+        s.parse().map(Self::Tcp).with_context(|| {
+            format!(
+                "Invalid listen address {}: expected IP:PORT, unix:PATH or systemd",
+                s
+            )
+        })
+    }
+}
+
+impl fmt::Display for ListenAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tcp(addr) => write!(f, "{}", addr),
+            Self::Unix(path) => write!(f, "{}{}", UNIX_PREFIX, path.display()),
+            Self::Systemd => f.write_str(SYSTEMD),
+        }
+    }
+}
+
+/// Parses the permissions of a Unix domain socket, in octal.
+pub(crate) fn parse_socket_mode(s: &str) -> Result<u32> {
+    match u32::from_str_radix(s, 8) {
+        Ok(mode) if mode <= 0o777 => Ok(mode),
+        _ => bail!(
+            "Invalid socket mode {}: expected octal permissions, such as 660",
+            s
+        ),
+    }
+}
+
+/// A socket the HTTP trigger accepts connections on.
+pub(crate) enum Listener {
+    /// A TCP socket, in non-blocking mode.
+    Tcp(std::net::TcpListener),
+    /// A Unix domain socket, and the file of the socket if the trigger
+    /// created it.
+    #[cfg(unix)]
+    Unix(tokio::net::UnixListener, Option<SocketFile>),
+}
+
+impl Listener {
+    /// Binds the socket of a listen address. Unix domain sockets get the
+    /// given permissions, if any.
+    pub async fn bind(address: &ListenAddress, socket_mode: Option<u32>) -> Result<Self> {
+        match address {
+            ListenAddress::Tcp(addr) => {
+                let listener = std::net::TcpListener::bind(addr)
+                    .with_context(|| format!("Unable to listen on {}", addr))?;
+                listener.set_nonblocking(true)?;
+                Ok(Self::Tcp(listener))
+            }
+            #[cfg(unix)]
+            ListenAddress::Unix(path) => unix::bind(path, socket_mode),
+            #[cfg(unix)]
+            ListenAddress::Systemd => unix::inherited(),
+            #[cfg(not(unix))]
+            _ => {
+                let _ = socket_mode;
+                bail!("Listening on {} is only supported on Unix", address)
+            }
+        }
+    }
+
+    /// The address of a TCP socket.
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        match self {
+            Self::Tcp(listener) => listener.local_addr().ok(),
+            #[cfg(unix)]
+            Self::Unix(..) => None,
+        }
+    }
+}
+
+/// The file of a Unix domain socket, removed when the listener is dropped,
+/// once the trigger stops serving.
+#[cfg(unix)]
+pub(crate) struct SocketFile(PathBuf);
+
+#[cfg(unix)]
+impl Drop for SocketFile {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_file(&self.0) {
+            log::warn!("Failed to remove socket {}: {}", self.0.display(), e);
+        }
+    }
+}
+
+#[cfg(unix)]
+mod unix {
+    use std::{
+        os::unix::{
+            fs::{FileTypeExt, PermissionsExt},
+            io::{FromRawFd, IntoRawFd, RawFd},
+            net::UnixStream,
+        },
+        path::Path,
+    };
+
+    use super::*;
+
+    /// The first of the file descriptors passed by systemd.
+    const SD_LISTEN_FDS_START: RawFd = 3;
+
+    pub(super) fn bind(path: &Path, socket_mode: Option<u32>) -> Result<Listener> {
+        // A socket left behind by a trigger that did not shut down cleanly
+        // is replaced, but not one another process still listens on.
+        if let Ok(metadata) = std::fs::symlink_metadata(path) {
+            if !metadata.file_type().is_socket() {
+                bail!("Unable to listen on {}: it is not a socket", path.display());
+            }
+            if UnixStream::connect(path).is_ok() {
+                bail!(
+                    "Unable to listen on {}: another process is listening on it",
+                    path.display()
+                );
+            }
+            std::fs::remove_file(path)
+                .with_context(|| format!("Unable to remove stale socket {}", path.display()))?;
+        }
+        let listener = tokio::net::UnixListener::bind(path)
+            .with_context(|| format!("Unable to listen on {}", path.display()))?;
+        let file = SocketFile(path.to_owned());
+        if let Some(mode) = socket_mode {
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+                .with_context(|| format!("Unable to set the mode of {}", path.display()))?;
+        }
+        Ok(Listener::Unix(listener, Some(file)))
+    }
+
+    /// Takes the socket passed by systemd. The sockets are passed to the
+    /// process `LISTEN_PID` names, which is `spin up` when it is the
+    /// activated service, so the trigger it runs accepts them too.
+    pub(super) fn inherited() -> Result<Listener> {
+        let pid = std::env::var("LISTEN_PID")
+            .ok()
+            .and_then(|pid| pid.parse::<u32>().ok());
+        let count = std::env::var("LISTEN_FDS")
+            .ok()
+            .and_then(|count| count.parse::<u32>().ok())
+            .unwrap_or(0);
+        let ours =
+            pid == Some(std::process::id()) || pid == Some(std::os::unix::process::parent_id());
+        if !ours || count == 0 {
+            bail!("Unable to listen on systemd: no socket was passed with LISTEN_FDS");
+        }
+        if count > 1 {
+            log::warn!("systemd passed {} sockets: only the first is used", count);
+        }
+        // Processes the trigger starts must not take them again.
+        for var in ["LISTEN_PID", "LISTEN_FDS", "LISTEN_FDNAMES"] {
+            std::env::remove_var(var);
+        }
+
+        let socket = unsafe { socket2::Socket::from_raw_fd(SD_LISTEN_FDS_START) };
+        socket.set_nonblocking(true)?;
+        let addr = socket
+            .local_addr()
+            .context("Unable to listen on systemd: the passed socket is not a socket")?;
+        if addr.as_socket().is_some() {
+            return Ok(Listener::Tcp(socket.into()));
+        }
+        let listener =
+            unsafe { std::os::unix::net::UnixListener::from_raw_fd(socket.into_raw_fd()) };
+        // The socket file belongs to systemd, which removes it.
+        Ok(Listener::Unix(
+            tokio::net::UnixListener::from_std(listener)?,
+            None,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_listen_addresses() {
+        assert_eq!(
+            "127.0.0.1:3000".parse::<ListenAddress>().unwrap(),
+            ListenAddress::Tcp(([127, 0, 0, 1], 3000).into())
+        );
+        assert_eq!(
+            "unix:/run/spin.sock".parse::<ListenAddress>().unwrap(),
+            ListenAddress::Unix(PathBuf::from("/run/spin.sock"))
+        );
+        assert_eq!(
+            "systemd".parse::<ListenAddress>().unwrap(),
+            ListenAddress::Systemd
+        );
+        assert!("unix:".parse::<ListenAddress>().is_err());
+        assert!("localhost".parse::<ListenAddress>().is_err());
+        assert_eq!(
+            ListenAddress::Unix("/a.sock".into()).to_string(),
+            "unix:/a.sock"
+        );
+
+        assert_eq!(parse_socket_mode("660").unwrap(), 0o660);
+        assert!(parse_socket_mode("999").is_err());
+        assert!(parse_socket_mode("1777").is_err());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_unix_sockets_are_cleaned_up() -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("spin.sock");
+        let address = ListenAddress::Unix(path.clone());
+
+        let listener = Listener::bind(&address, Some(0o600)).await?;
+        assert_eq!(
+            std::fs::metadata(&path)?.permissions().mode() & 0o777,
+            0o600
+        );
+        // The socket is in use, so it is not replaced.
+        assert!(Listener::bind(&address, None).await.is_err());
+        drop(listener);
+        assert!(!path.exists());
+
+        // A stale socket, left by a listener that was not dropped, is.
+        let stale = std::os::unix::net::UnixListener::bind(&path)?;
+        drop(stale);
+        let listener = Listener::bind(&address, None).await?;
+        assert!(listener.local_addr().is_none());
+        Ok(())
+    }
+}
@@ -0,0 +1,494 @@
+//! Metrics served by the HTTP trigger, in the Prometheus text format.
+
+use std::fmt::Write;
+
+use std::collections::BTreeMap;
+
+use spin_engine::{EgressStats, LoadStats};
+use spin_manifest::LoadPolicy;
+use spin_trigger::{LimitStats, SchedulerStats};
+use spin_wasi_nn::DeviceStats;
+
+/// Renders the metrics of the request scheduler, if requests are limited,
+/// of the adaptive limits of components, if limits are adaptive, of the
+/// loading of components, of the wasi-nn devices, if any, of the outbound
+/// traffic of components, and of the requests rejected by strict checks, if
+/// enabled.
+pub(crate) fn render(
+    scheduler: Option<&SchedulerStats>,
+    limits: Option<&BTreeMap<String, LimitStats>>,
+    loads: &BTreeMap<String, LoadStats>,
+    devices: &BTreeMap<String, DeviceStats>,
+    egress: &BTreeMap<String, EgressStats>,
+    rejected: Option<&BTreeMap<&'static str, u64>>,
+) -> String {
+    let mut out = String::new();
+    if let Some(stats) = scheduler {
+        gauge(
+            &mut out,
+            "spin_requests_max",
+            "The maximum number of requests executing at once.",
+        );
+        writeln!(out, "spin_requests_max {}", stats.max_requests).unwrap();
+        gauge(
+            &mut out,
+            "spin_requests_executing",
+            "Requests executing, by component.",
+        );
+        for (component, n) in &stats.executing {
+            writeln!(
+                out,
+                "spin_requests_executing{{component=\"{}\"}} {}",
+                component, n
+            )
+            .unwrap();
+        }
+        gauge(
+            &mut out,
+            "spin_requests_queued",
+            "Requests waiting to execute, by component.",
+        );
+        for (component, n) in &stats.queued {
+            writeln!(
+                out,
+                "spin_requests_queued{{component=\"{}\"}} {}",
+                component, n
+            )
+            .unwrap();
+        }
+        header(
+            &mut out,
+            "spin_requests_shed_total",
+            "Requests shed because the runtime was overloaded, by component.",
+            "counter",
+        );
+        for (component, n) in &stats.shed {
+            writeln!(
+                out,
+                "spin_requests_shed_total{{component=\"{}\"}} {}",
+                component, n
+            )
+            .unwrap();
+        }
+    }
+    if let Some(limits) = limits {
+        gauge(
+            &mut out,
+            "spin_component_concurrency_limit",
+            "The adaptive limit on requests executing at once, by component.",
+        );
+        for (component, stats) in limits {
+            writeln!(
+                out,
+                "spin_component_concurrency_limit{{component=\"{}\"}} {}",
+                component, stats.limit
+            )
+            .unwrap();
+        }
+        gauge(
+            &mut out,
+            "spin_component_in_flight",
+            "Requests executing within the adaptive limit, by component.",
+        );
+        for (component, stats) in limits {
+            writeln!(
+                out,
+                "spin_component_in_flight{{component=\"{}\"}} {}",
+                component, stats.in_flight
+            )
+            .unwrap();
+        }
+        header(
+            &mut out,
+            "spin_component_limited_total",
+            "Requests rejected at the adaptive limit, by component.",
+            "counter",
+        );
+        for (component, stats) in limits {
+            writeln!(
+                out,
+                "spin_component_limited_total{{component=\"{}\"}} {}",
+                component, stats.rejected
+            )
+            .unwrap();
+        }
+    }
+    if !loads.is_empty() {
+        gauge(
+            &mut out,
+            "spin_component_load_seconds",
+            "The time taken to compile and link each loaded component, by load policy.",
+        );
+        for (component, stats) in loads {
+            let policy = match stats.policy {
+                LoadPolicy::Eager => "eager",
+                LoadPolicy::Lazy => "lazy",
+            };
+            if let Some(duration) = stats.duration {
+                writeln!(
+                    out,
+                    "spin_component_load_seconds{{component=\"{}\",load=\"{}\"}} {}",
+                    component,
+                    policy,
+                    duration.as_secs_f64()
+                )
+                .unwrap();
+            }
+        }
+        gauge(
+            &mut out,
+            "spin_component_loaded",
+            "Whether each component is loaded, by load policy.",
+        );
+        for (component, stats) in loads {
+            let policy = match stats.policy {
+                LoadPolicy::Eager => "eager",
+                LoadPolicy::Lazy => "lazy",
+            };
+            writeln!(
+                out,
+                "spin_component_loaded{{component=\"{}\",load=\"{}\"}} {}",
+                component,
+                policy,
+                stats.duration.is_some() as u8
+            )
+            .unwrap();
+        }
+    }
+    if !devices.is_empty() {
+        gauge(
+            &mut out,
+            "spin_nn_device_slots",
+            "The number of wasi-nn computations each device runs at once.",
+        );
+        for (device, stats) in devices {
+            writeln!(
+                out,
+                "spin_nn_device_slots{{device=\"{}\",target=\"{}\"}} {}",
+                device, stats.target, stats.slots
+            )
+            .unwrap();
+        }
+        header(
+            &mut out,
+            "spin_nn_device_busy_seconds_total",
+            "The time wasi-nn computations have run on each device.",
+            "counter",
+        );
+        for (device, stats) in devices {
+            writeln!(
+                out,
+                "spin_nn_device_busy_seconds_total{{device=\"{}\"}} {}",
+                device,
+                stats.busy.as_secs_f64()
+            )
+            .unwrap();
+        }
+        device_gauge(
+            &mut out,
+            devices,
+            "spin_nn_executing",
+            "wasi-nn computations running, by device and component.",
+            |s| &s.executing,
+        );
+        device_gauge(
+            &mut out,
+            devices,
+            "spin_nn_queued",
+            "wasi-nn computations waiting for a device, by device and component.",
+            |s| &s.queued,
+        );
+        device_counter(
+            &mut out,
+            devices,
+            "spin_nn_completed_total",
+            "wasi-nn computations completed, by device and component.",
+            |s| &s.completed,
+        );
+        device_counter(
+            &mut out,
+            devices,
+            "spin_nn_rejected_total",
+            "wasi-nn computations rejected because a device was overloaded, by device and component.",
+            |s| &s.rejected,
+        );
+    }
+    if !egress.is_empty() {
+        egress_counter(
+            &mut out,
+            egress,
+            "spin_egress_requests_total",
+            "Outbound requests, by component and kind.",
+            |s| &s.requests,
+        );
+        egress_counter(
+            &mut out,
+            egress,
+            "spin_egress_bytes_total",
+            "Bytes sent and received by outbound requests, by component and kind.",
+            |s| &s.bytes,
+        );
+        egress_counter(
+            &mut out,
+            egress,
+            "spin_egress_rejected_total",
+            "Outbound requests rejected by a quota, by component and kind.",
+            |s| &s.rejected,
+        );
+    }
+    if let Some(rejected) = rejected {
+        header(
+            &mut out,
+            "spin_http_rejected_total",
+            "Requests rejected by strict HTTP checks, by reason.",
+            "counter",
+        );
+        for (reason, n) in rejected {
+            writeln!(
+                out,
+                "spin_http_rejected_total{{reason=\"{}\"}} {}",
+                reason, n
+            )
+            .unwrap();
+        }
+    }
+    out
+}
+
+fn device_gauge(
+    out: &mut String,
+    devices: &BTreeMap<String, DeviceStats>,
+    name: &str,
+    help: &str,
+    values: fn(&DeviceStats) -> &BTreeMap<String, usize>,
+) {
+    gauge(out, name, help);
+    for (device, stats) in devices {
+        for (component, n) in values(stats) {
+            writeln!(
+                out,
+                "{}{{device=\"{}\",component=\"{}\"}} {}",
+                name, device, component, n
+            )
+            .unwrap();
+        }
+    }
+}
+
+fn device_counter(
+    out: &mut String,
+    devices: &BTreeMap<String, DeviceStats>,
+    name: &str,
+    help: &str,
+    values: fn(&DeviceStats) -> &BTreeMap<String, u64>,
+) {
+    header(out, name, help, "counter");
+    for (device, stats) in devices {
+        for (component, n) in values(stats) {
+            writeln!(
+                out,
+                "{}{{device=\"{}\",component=\"{}\"}} {}",
+                name, device, component, n
+            )
+            .unwrap();
+        }
+    }
+}
+
+fn egress_counter(
+    out: &mut String,
+    egress: &BTreeMap<String, EgressStats>,
+    name: &str,
+    help: &str,
+    values: fn(&EgressStats) -> &BTreeMap<&'static str, u64>,
+) {
+    header(out, name, help, "counter");
+    for (component, stats) in egress {
+        for (kind, n) in values(stats) {
+            writeln!(
+                out,
+                "{}{{component=\"{}\",kind=\"{}\"}} {}",
+                name, component, kind, n
+            )
+            .unwrap();
+        }
+    }
+}
+
+fn gauge(out: &mut String, name: &str, help: &str) {
+    header(out, name, help, "gauge")
+}
+
+fn header(out: &mut String, name: &str, help: &str, kind: &str) {
+    writeln!(out, "# HELP {} {}", name, help).unwrap();
+    writeln!(out, "# TYPE {} {}", name, kind).unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_scheduler_metrics() {
+        let stats = SchedulerStats {
+            max_requests: 4,
+            executing: [("checkout".to_string(), 4)].into_iter().collect(),
+            queued: [("checkout".to_string(), 2)].into_iter().collect(),
+            shed: [("reports".to_string(), 7)].into_iter().collect(),
+        };
+        let metrics = render(
+            Some(&stats),
+            None,
+            &BTreeMap::new(),
+            &BTreeMap::new(),
+            &BTreeMap::new(),
+            None,
+        );
+        assert!(metrics.contains("spin_requests_max 4\n"));
+        assert!(metrics.contains("spin_requests_queued{component=\"checkout\"} 2\n"));
+        assert!(metrics.contains("# TYPE spin_requests_shed_total counter\n"));
+        assert!(metrics.contains("spin_requests_shed_total{component=\"reports\"} 7\n"));
+        assert!(render(
+            None,
+            None,
+            &BTreeMap::new(),
+            &BTreeMap::new(),
+            &BTreeMap::new(),
+            None
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn test_render_adaptive_limits() {
+        let limits = [(
+            "orders".to_string(),
+            LimitStats {
+                limit: 12,
+                in_flight: 3,
+                rejected: 5,
+            },
+        )]
+        .into_iter()
+        .collect();
+        let metrics = render(
+            None,
+            Some(&limits),
+            &BTreeMap::new(),
+            &BTreeMap::new(),
+            &BTreeMap::new(),
+            None,
+        );
+        assert!(metrics.contains("spin_component_concurrency_limit{component=\"orders\"} 12\n"));
+        assert!(metrics.contains("spin_component_in_flight{component=\"orders\"} 3\n"));
+        assert!(metrics.contains("spin_component_limited_total{component=\"orders\"} 5\n"));
+        assert!(!metrics.contains("spin_requests_max"));
+    }
+
+    #[test]
+    fn test_render_load_metrics() {
+        let loads = [
+            (
+                "admin".to_string(),
+                LoadStats {
+                    policy: LoadPolicy::Lazy,
+                    duration: None,
+                },
+            ),
+            (
+                "api".to_string(),
+                LoadStats {
+                    policy: LoadPolicy::Eager,
+                    duration: Some(std::time::Duration::from_millis(250)),
+                },
+            ),
+        ]
+        .into_iter()
+        .collect();
+        let metrics = render(None, None, &loads, &BTreeMap::new(), &BTreeMap::new(), None);
+        assert!(metrics
+            .contains("spin_component_load_seconds{component=\"api\",load=\"eager\"} 0.25\n"));
+        assert!(!metrics.contains("spin_component_load_seconds{component=\"admin\""));
+        assert!(metrics.contains("spin_component_loaded{component=\"admin\",load=\"lazy\"} 0\n"));
+    }
+
+    #[test]
+    fn test_render_device_metrics() {
+        let devices = [(
+            "cpu0".to_string(),
+            DeviceStats {
+                target: "cpu",
+                slots: 2,
+                busy: std::time::Duration::from_millis(1500),
+                executing: [("chat".to_string(), 1)].into_iter().collect(),
+                queued: [("chat".to_string(), 3)].into_iter().collect(),
+                completed: [("chat".to_string(), 40)].into_iter().collect(),
+                rejected: [("chat".to_string(), 2)].into_iter().collect(),
+            },
+        )]
+        .into_iter()
+        .collect();
+        let metrics = render(
+            None,
+            None,
+            &BTreeMap::new(),
+            &devices,
+            &BTreeMap::new(),
+            None,
+        );
+        assert!(metrics.contains("spin_nn_device_slots{device=\"cpu0\",target=\"cpu\"} 2\n"));
+        assert!(metrics.contains("spin_nn_device_busy_seconds_total{device=\"cpu0\"} 1.5\n"));
+        assert!(metrics.contains("spin_nn_queued{device=\"cpu0\",component=\"chat\"} 3\n"));
+        assert!(metrics.contains("# TYPE spin_nn_rejected_total counter\n"));
+        assert!(metrics.contains("spin_nn_rejected_total{device=\"cpu0\",component=\"chat\"} 2\n"));
+    }
+
+    #[test]
+    fn test_render_rejected_requests() {
+        let rejected = [("ambiguous_framing", 3), ("invalid_header", 0)]
+            .into_iter()
+            .collect();
+        let metrics = render(
+            None,
+            None,
+            &BTreeMap::new(),
+            &BTreeMap::new(),
+            &BTreeMap::new(),
+            Some(&rejected),
+        );
+        assert!(metrics.contains("# TYPE spin_http_rejected_total counter\n"));
+        assert!(metrics.contains("spin_http_rejected_total{reason=\"ambiguous_framing\"} 3\n"));
+        assert!(metrics.contains("spin_http_rejected_total{reason=\"invalid_header\"} 0\n"));
+    }
+
+    #[test]
+    fn test_render_egress_metrics() {
+        let egress = [(
+            "reports".to_string(),
+            EgressStats {
+                requests: [("http", 12), ("postgres", 3)].into_iter().collect(),
+                bytes: [("http", 2048)].into_iter().collect(),
+                rejected: [("http", 1)].into_iter().collect(),
+            },
+        )]
+        .into_iter()
+        .collect();
+        let metrics = render(
+            None,
+            None,
+            &BTreeMap::new(),
+            &BTreeMap::new(),
+            &egress,
+            None,
+        );
+        assert!(metrics.contains("# TYPE spin_egress_bytes_total counter\n"));
+        assert!(metrics
+            .contains("spin_egress_requests_total{component=\"reports\",kind=\"postgres\"} 3\n"));
+        assert!(
+            metrics.contains("spin_egress_bytes_total{component=\"reports\",kind=\"http\"} 2048\n")
+        );
+        assert!(
+            metrics.contains("spin_egress_rejected_total{component=\"reports\",kind=\"http\"} 1\n")
+        );
+    }
+}
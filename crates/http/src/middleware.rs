@@ -0,0 +1,444 @@
+//! The middlewares routes apply to their requests, in order, before their
+//! component is invoked.
+
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context, Result};
+use http::{header::HeaderName, HeaderValue, StatusCode};
+use hyper::{Body, Request, Response};
+use serde_json::{Map, Value};
+use spin_jwt::JwtProviders;
+use spin_manifest::{
+    ComponentMap, HttpConfig, HttpJwtMiddlewareConfig, HttpMiddleware, HttpRateLimitConfig,
+};
+use tracing::log;
+
+use crate::auth;
+
+/// The number of clients a rate limiter tracks before it forgets those whose
+/// bucket has refilled. If too few have, it tracks twice as many before
+/// trying again.
+const MAX_IDLE_BUCKETS: usize = 10_000;
+
+/// The middlewares of the routes of an application, by component.
+pub(crate) struct Middlewares {
+    chains: HashMap<String, Vec<Middleware>>,
+}
+
+enum Middleware {
+    Jwt(JwtMiddleware),
+    RateLimit(RateLimiter),
+}
+
+impl Middlewares {
+    /// Creates the middlewares of the routes with a `middleware`.
+    pub(crate) fn new(component_triggers: &ComponentMap<HttpConfig>) -> Result<Self> {
+        let mut chains = HashMap::new();
+        for (component, trigger) in component_triggers {
+            if trigger.middleware.is_empty() {
+                continue;
+            }
+            let mut authenticated = trigger.auth.is_some();
+            let chain = trigger
+                .middleware
+                .iter()
+                .map(|middleware| match middleware {
+                    HttpMiddleware::Jwt(config) => {
+                        authenticated = true;
+                        JwtMiddleware::new(config).map(Middleware::Jwt)
+                    }
+                    HttpMiddleware::RateLimit(config) => {
+                        if config.key_claim.is_some() && !authenticated {
+                            bail!(
+                                "a rate limit with a `key_claim` must come after `auth` or a `jwt` middleware"
+                            );
+                        }
+                        RateLimiter::new(config).map(Middleware::RateLimit)
+                    }
+                })
+                .collect::<Result<_>>()
+                .with_context(|| format!("Invalid middleware for component {}", component))?;
+            chains.insert(component.clone(), chain);
+        }
+        Ok(Self { chains })
+    }
+
+    /// The names of the JWT validators the middlewares use, by component.
+    pub(crate) fn jwt_validators(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.chains.iter().flat_map(|(component, chain)| {
+            chain.iter().filter_map(move |middleware| match middleware {
+                Middleware::Jwt(jwt) => Some((component.as_str(), jwt.validator.as_str())),
+                Middleware::RateLimit(_) => None,
+            })
+        })
+    }
+
+    /// Applies the middlewares of a component's route to a request.
+    ///
+    /// Middlewares may add headers to the request. If one rejects it, the
+    /// response to send instead of invoking the component is returned, and
+    /// the middlewares after it are not applied.
+    pub(crate) async fn apply(
+        &self,
+        component: &str,
+        jwt: &JwtProviders,
+        req: &mut Request<Body>,
+        addr: SocketAddr,
+    ) -> Result<Option<Response<Body>>> {
+        let chain = match self.chains.get(component) {
+            Some(chain) => chain,
+            None => return Ok(None),
+        };
+        for middleware in chain {
+            let res = match middleware {
+                Middleware::Jwt(middleware) => middleware.apply(jwt, req).await?,
+                Middleware::RateLimit(limiter) => limiter.apply(req, addr)?,
+            };
+            if res.is_some() {
+                return Ok(res);
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Validates bearer tokens with a validator of the runtime configuration.
+struct JwtMiddleware {
+    validator: String,
+    /// The headers set from claims, by claim name.
+    claim_headers: Vec<(String, HeaderName)>,
+}
+
+impl JwtMiddleware {
+    fn new(config: &HttpJwtMiddlewareConfig) -> Result<Self> {
+        let claim_headers = config
+            .claim_headers
+            .iter()
+            .map(|(claim, header)| {
+                let name = HeaderName::from_bytes(header.as_bytes())
+                    .with_context(|| format!("Invalid header name {:?}", header))?;
+                Ok((claim.clone(), name))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            validator: config.validator.clone(),
+            claim_headers,
+        })
+    }
+
+    async fn apply(
+        &self,
+        jwt: &JwtProviders,
+        req: &mut Request<Body>,
+    ) -> Result<Option<Response<Body>>> {
+        // The claim headers must come from the token, not the client.
+        for (_, name) in &self.claim_headers {
+            req.headers_mut().remove(name);
+        }
+        let token = match auth::bearer_token(req) {
+            Some(token) => token,
+            None => return Ok(Some(auth::unauthorized())),
+        };
+
+        let claims = match jwt.validator(&self.validator) {
+            Some(validator) => validator.validate(&token).await,
+            None => Err(anyhow::anyhow!("Unknown JWT validator {}", self.validator)),
+        };
+        let claims = match claims {
+            Ok(claims) => claims,
+            Err(e) => {
+                log::info!("Rejecting request with invalid token: {:#}", e);
+                return Ok(Some(auth::unauthorized()));
+            }
+        };
+
+        for (claim, name) in &self.claim_headers {
+            let value = match claims.get(claim) {
+                Some(value) => claim_value(value),
+                None => continue,
+            };
+            match HeaderValue::from_str(&value) {
+                Ok(value) => {
+                    req.headers_mut().insert(name, value);
+                }
+                Err(_) => log::info!(
+                    "Not setting header {} from claim {}, which a header cannot hold",
+                    name,
+                    claim
+                ),
+            }
+        }
+        req.headers_mut()
+            .insert(auth::JWT_CLAIMS_HEADER, auth::claims_header(&claims)?);
+        Ok(None)
+    }
+}
+
+/// The value of a claim, as a header or key: strings are used as is, and
+/// other claims as JSON.
+fn claim_value(value: &Value) -> String {
+    match value {
+        Value::String(value) => value.clone(),
+        value => value.to_string(),
+    }
+}
+
+/// Limits the rate of the requests of each client with a token bucket.
+struct RateLimiter {
+    capacity: f64,
+    /// The tokens added to a bucket per second.
+    refill_rate: f64,
+    key_claim: Option<String>,
+    buckets: Mutex<Buckets>,
+}
+
+struct Buckets {
+    buckets: HashMap<String, Bucket>,
+    /// The number of buckets at which those that have refilled are forgotten.
+    prune_at: usize,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl RateLimiter {
+    fn new(config: &HttpRateLimitConfig) -> Result<Self> {
+        if config.requests == 0 || config.period_secs == 0 {
+            bail!("a rate limit must allow at least one request per period");
+        }
+        Ok(Self {
+            capacity: config.burst.unwrap_or(config.requests).max(1) as f64,
+            refill_rate: config.requests as f64 / config.period_secs as f64,
+            key_claim: config.key_claim.clone(),
+            buckets: Mutex::new(Buckets {
+                buckets: HashMap::new(),
+                prune_at: MAX_IDLE_BUCKETS,
+            }),
+        })
+    }
+
+    fn apply(&self, req: &Request<Body>, addr: SocketAddr) -> Result<Option<Response<Body>>> {
+        let key = self
+            .client_claim(req)
+            .map(|claim| format!("claim:{}", claim))
+            .unwrap_or_else(|| format!("ip:{}", addr.ip()));
+        match self.take(&key, Instant::now()) {
+            Ok(()) => Ok(None),
+            Err(retry_after) => {
+                // The key may be personal data, such as an email address.
+                log::info!("Rejecting request over the rate limit from {}", addr);
+                Ok(Some(
+                    Response::builder()
+                        .status(StatusCode::TOO_MANY_REQUESTS)
+                        .header(
+                            http::header::RETRY_AFTER,
+                            retry_after.as_secs_f64().ceil() as u64,
+                        )
+                        .body(Body::empty())?,
+                ))
+            }
+        }
+    }
+
+    /// The key claim of the validated token of a request, if any. The claims
+    /// header is only set by the trigger, never by clients.
+    fn client_claim(&self, req: &Request<Body>) -> Option<String> {
+        let claim = self.key_claim.as_ref()?;
+        let claims = req.headers().get(auth::JWT_CLAIMS_HEADER)?;
+        let claims: Map<String, Value> = serde_json::from_slice(claims.as_bytes()).ok()?;
+        claims.get(claim).map(claim_value)
+    }
+
+    /// Takes a token from the bucket of a client, or returns how long until
+    /// the bucket has one.
+    fn take(&self, key: &str, now: Instant) -> Result<(), Duration> {
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.buckets.len() >= buckets.prune_at && !buckets.buckets.contains_key(key) {
+            buckets
+                .buckets
+                .retain(|_, bucket| self.refill(bucket, now) < self.capacity);
+            // Pruning again before as many clients are added keeps adding
+            // clients constant time on average.
+            buckets.prune_at = MAX_IDLE_BUCKETS.max(2 * buckets.buckets.len());
+        }
+        let capacity = self.capacity;
+        let bucket = buckets.buckets.entry(key.to_owned()).or_insert(Bucket {
+            tokens: capacity,
+            updated: now,
+        });
+        bucket.tokens = self.refill(bucket, now);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - bucket.tokens) / self.refill_rate,
+            ))
+        }
+    }
+
+    /// The tokens of a bucket, refilled up to now.
+    fn refill(&self, bucket: &Bucket, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        (bucket.tokens + elapsed * self.refill_rate).min(self.capacity)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(requests: u32, period_secs: u64, burst: Option<u32>) -> RateLimiter {
+        RateLimiter::new(&HttpRateLimitConfig {
+            requests,
+            period_secs,
+            burst,
+            key_claim: Some("sub".to_owned()),
+        })
+        .unwrap()
+    }
+
+    fn middlewares(trigger: &str) -> Result<Middlewares> {
+        let trigger: HttpConfig = toml::from_str(trigger)?;
+        Middlewares::new(&[("hello".to_owned(), trigger)].into_iter().collect())
+    }
+
+    #[test]
+    fn test_buckets_refill_at_the_rate() {
+        let limiter = limiter(2, 10, Some(3));
+        let start = Instant::now();
+
+        for _ in 0..3 {
+            assert!(limiter.take("a", start).is_ok());
+        }
+        let retry_after = limiter.take("a", start).unwrap_err();
+        assert_eq!(retry_after.as_secs_f64().round(), 5.0);
+        // Clients have buckets of their own.
+        assert!(limiter.take("b", start).is_ok());
+
+        // A token is added every five seconds.
+        assert!(limiter.take("a", start + Duration::from_secs(6)).is_ok());
+        assert!(limiter.take("a", start + Duration::from_secs(7)).is_err());
+        // Buckets hold at most the burst.
+        let later = start + Duration::from_secs(3600);
+        for _ in 0..3 {
+            assert!(limiter.take("a", later).is_ok());
+        }
+        assert!(limiter.take("a", later).is_err());
+    }
+
+    #[test]
+    fn test_clients_are_identified_by_claim_or_ip() -> Result<()> {
+        let limiter = limiter(1, 60, None);
+        let addr: SocketAddr = "10.0.0.1:1234".parse()?;
+        let with_sub = |sub: &str| {
+            Request::get("/")
+                .header(auth::JWT_CLAIMS_HEADER, format!(r#"{{"sub":"{}"}}"#, sub))
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        assert!(limiter.apply(&with_sub("u1"), addr)?.is_none());
+        assert!(limiter.apply(&with_sub("u2"), addr)?.is_none());
+        let rejected = limiter
+            .apply(&with_sub("u1"), addr)?
+            .expect("the second request of u1 should be rejected");
+        assert_eq!(rejected.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert!(rejected.headers().contains_key(http::header::RETRY_AFTER));
+
+        // Requests without a token are limited by IP, whatever their headers.
+        let anonymous = Request::get("/")
+            .header("x-api-key", "k1")
+            .body(Body::empty())?;
+        assert!(limiter.apply(&anonymous, addr)?.is_none());
+        assert!(limiter.apply(&anonymous, addr)?.is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn test_key_claims_require_authentication() -> Result<()> {
+        let rate_limit = r#"{ type = "rate_limit", requests = 1, key_claim = "sub" }"#;
+        let jwt = r#"{ type = "jwt", validator = "users" }"#;
+        assert!(middlewares(&format!("route = \"/\"\nmiddleware = [{}]", rate_limit)).is_err());
+        assert!(middlewares(&format!(
+            "route = \"/\"\nmiddleware = [{}, {}]",
+            rate_limit, jwt
+        ))
+        .is_err());
+
+        let middlewares = middlewares(&format!(
+            "route = \"/\"\nmiddleware = [{}, {}]",
+            jwt, rate_limit
+        ))?;
+        assert_eq!(
+            middlewares.jwt_validators().collect::<Vec<_>>(),
+            [("hello", "users")]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_active_clients_are_pruned_less_often() {
+        let limiter = limiter(1, 60, None);
+        let start = Instant::now();
+        for i in 0..MAX_IDLE_BUCKETS {
+            assert!(limiter.take(&i.to_string(), start).is_ok());
+        }
+
+        // No bucket has refilled, so none is forgotten, and the next prune
+        // waits for twice as many clients.
+        assert!(limiter.take("new", start).is_ok());
+        let buckets = limiter.buckets.lock().unwrap();
+        assert_eq!(buckets.buckets.len(), MAX_IDLE_BUCKETS + 1);
+        assert_eq!(buckets.prune_at, 2 * MAX_IDLE_BUCKETS);
+        drop(buckets);
+
+        // Once they have refilled, they are.
+        let later = start + Duration::from_secs(60);
+        for i in 0..MAX_IDLE_BUCKETS {
+            assert!(limiter.take(&format!("later-{}", i), later).is_ok());
+        }
+        let buckets = limiter.buckets.lock().unwrap();
+        assert_eq!(buckets.buckets.len(), MAX_IDLE_BUCKETS);
+        assert!(!buckets.buckets.contains_key("new"));
+    }
+
+    #[test]
+    fn test_empty_rate_limits_are_rejected() {
+        assert!(RateLimiter::new(&HttpRateLimitConfig {
+            requests: 0,
+            period_secs: 1,
+            burst: None,
+            key_claim: None,
+        })
+        .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_tokens_are_required() -> Result<()> {
+        let jwt = JwtMiddleware::new(&HttpJwtMiddlewareConfig {
+            validator: "users".to_owned(),
+            claim_headers: [("sub".to_owned(), "x-user-id".to_owned())].into(),
+        })?;
+        let mut req = Request::get("/")
+            .header("x-user-id", "admin")
+            .body(Body::empty())?;
+
+        let res = jwt
+            .apply(&JwtProviders::default(), &mut req)
+            .await?
+            .expect("should be rejected");
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        // Clients cannot set the claim headers themselves.
+        assert!(req.headers().get("x-user-id").is_none());
+        Ok(())
+    }
+}
@@ -0,0 +1,350 @@
+//! Handlers for routes served natively by the HTTP trigger.
+
+use std::{net::SocketAddr, path::Path, time::Duration};
+
+use anyhow::{anyhow, bail, Context, Result};
+use http::{
+    header::{self, HeaderName, CONTENT_TYPE, HOST},
+    HeaderMap, HeaderValue, StatusCode, Uri,
+};
+use hyper::{client::HttpConnector, Body, Client, Request, Response};
+use hyper_rustls::HttpsConnector;
+use spin_config::{Resolver, TreePath};
+use spin_manifest::{HttpHandler, HttpNativeRoute, ProxyHandler, TemplateHandler};
+use tracing::log;
+
+use crate::routes::RoutePattern;
+
+/// The default timeout for upstream responses.
+const DEFAULT_PROXY_TIMEOUT: Duration = Duration::from_secs(30);
+
+type ProxyClient = Client<HttpsConnector<HttpConnector>, Body>;
+
+/// The routes handled natively by the trigger.
+#[derive(Default)]
+pub(crate) struct NativeRoutes {
+    routes: Vec<(RoutePattern, NativeHandler)>,
+}
+
+impl NativeRoutes {
+    /// Prepares the handlers for the given routes.
+    pub(crate) fn build(
+        base: &str,
+        routes: &[HttpNativeRoute],
+        resolver: Option<&Resolver>,
+    ) -> Result<Self> {
+        let routes = routes
+            .iter()
+            .map(|route| {
+                let handler = match &route.handler {
+                    HttpHandler::Template(template) => NativeHandler::template(template, resolver),
+                    HttpHandler::Proxy(proxy) => NativeHandler::proxy(proxy),
+                }
+                .with_context(|| format!("Invalid handler for route {}", route.route))?;
+                Ok((RoutePattern::from(base, &route.route), handler))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { routes })
+    }
+
+    /// Returns the route patterns, with a description of their handlers.
+    pub(crate) fn describe(&self) -> impl Iterator<Item = (&RoutePattern, &'static str)> {
+        self.routes.iter().map(|(pattern, handler)| {
+            let description = match handler {
+                NativeHandler::Template { .. } => "template",
+                NativeHandler::Proxy { .. } => "proxy",
+            };
+            (pattern, description)
+        })
+    }
+
+    /// Returns the upstream URLs of proxy routes.
+    pub(crate) fn upstreams(&self) -> impl Iterator<Item = &Uri> {
+        self.routes.iter().filter_map(|(_, handler)| match handler {
+            NativeHandler::Proxy { upstream, .. } => Some(upstream),
+            _ => None,
+        })
+    }
+
+    /// Returns the matched route pattern and handler for the given path, if
+    /// any. As for component routes, the last matching route wins.
+    pub(crate) fn route(&self, path: &str) -> Option<(&RoutePattern, &NativeHandler)> {
+        self.routes
+            .iter()
+            .rfind(|(pattern, _)| pattern.matches(path))
+            .map(|(pattern, handler)| (pattern, handler))
+    }
+}
+
+/// A native handler, ready to serve requests.
+pub(crate) enum NativeHandler {
+    /// A pre-rendered template.
+    Template {
+        status: StatusCode,
+        content_type: HeaderValue,
+        body: bytes::Bytes,
+    },
+    /// A reverse proxy to an upstream service.
+    Proxy {
+        upstream: Uri,
+        preserve_path: bool,
+        headers: HeaderMap,
+        timeout: Duration,
+        client: ProxyClient,
+    },
+}
+
+impl NativeHandler {
+    fn template(config: &TemplateHandler, resolver: Option<&Resolver>) -> Result<Self> {
+        let template = std::fs::read_to_string(&config.template)
+            .with_context(|| format!("Cannot read template {}", config.template.display()))?;
+        let body = render(&template, |name| {
+            let resolver = resolver.ok_or_else(|| anyhow!("no variables are defined"))?;
+            Ok(resolver.resolve(&TreePath::new(name)?)?)
+        })?;
+        let content_type = match &config.content_type {
+            Some(content_type) => content_type.as_str(),
+            None => infer_content_type(&config.template),
+        };
+        Ok(Self::Template {
+            status: StatusCode::from_u16(config.status)?,
+            content_type: HeaderValue::from_str(content_type)?,
+            body: body.into(),
+        })
+    }
+
+    fn proxy(config: &ProxyHandler) -> Result<Self> {
+        let upstream: Uri = config
+            .proxy
+            .parse()
+            .with_context(|| format!("Invalid upstream URL {}", config.proxy))?;
+        if upstream.scheme().is_none() || upstream.authority().is_none() {
+            bail!("Upstream URL {} must be absolute", config.proxy);
+        }
+        let headers = config
+            .headers
+            .iter()
+            .map(|(name, value)| {
+                Ok((
+                    HeaderName::from_bytes(name.as_bytes())?,
+                    HeaderValue::from_str(value)?,
+                ))
+            })
+            .collect::<Result<_>>()?;
+        let connector = hyper_rustls::HttpsConnectorBuilder::new()
+            .with_native_roots()
+            .https_or_http()
+            .enable_http1()
+            .build();
+        Ok(Self::Proxy {
+            upstream,
+            preserve_path: config.preserve_path,
+            headers,
+            timeout: config
+                .timeout_ms
+                .map(Duration::from_millis)
+                .unwrap_or(DEFAULT_PROXY_TIMEOUT),
+            client: Client::builder().build(connector),
+        })
+    }
+
+    /// Handles a request matched by the given route pattern.
+    pub(crate) async fn handle(
+        &self,
+        pattern: &RoutePattern,
+        req: Request<Body>,
+        addr: SocketAddr,
+    ) -> Result<Response<Body>> {
+        match self {
+            Self::Template {
+                status,
+                content_type,
+                body,
+            } => Ok(Response::builder()
+                .status(*status)
+                .header(CONTENT_TYPE, content_type)
+                .body(Body::from(body.clone()))?),
+            Self::Proxy {
+                upstream,
+                preserve_path,
+                headers,
+                timeout,
+                client,
+            } => {
+                let (mut parts, body) = req.into_parts();
+                let path = if *preserve_path {
+                    parts.uri.path().to_string()
+                } else {
+                    pattern.relative(&parts.uri.to_string())?
+                };
+                parts.uri = upstream_uri(upstream, &path, parts.uri.query())?;
+                remove_hop_by_hop_headers(&mut parts.headers);
+                parts.headers.remove(HOST);
+                parts
+                    .headers
+                    .append("x-forwarded-for", HeaderValue::from_str(&addr.ip().to_string())?);
+                for (name, value) in headers {
+                    parts.headers.insert(name, value.clone());
+                }
+
+                let req = Request::from_parts(parts, body);
+                match tokio::time::timeout(*timeout, client.request(req)).await {
+                    Ok(Ok(mut res)) => {
+                        remove_hop_by_hop_headers(res.headers_mut());
+                        Ok(res)
+                    }
+                    Ok(Err(e)) => {
+                        log::info!("Error forwarding request to {}: {}", upstream, e);
+                        status_response(StatusCode::BAD_GATEWAY)
+                    }
+                    Err(_) => {
+                        log::info!("Timed out forwarding request to {}", upstream);
+                        status_response(StatusCode::GATEWAY_TIMEOUT)
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Removes the headers that apply to a single connection, which proxies do
+/// not forward either way.
+pub fn remove_hop_by_hop_headers(headers: &mut HeaderMap) {
+    let listed: Vec<HeaderName> = headers
+        .get_all(header::CONNECTION)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|name| HeaderName::from_bytes(name.trim().as_bytes()).ok())
+        .collect();
+    for name in listed {
+        headers.remove(name);
+    }
+    for name in [
+        header::CONNECTION,
+        HeaderName::from_static("keep-alive"),
+        HeaderName::from_static("proxy-connection"),
+        header::PROXY_AUTHENTICATE,
+        header::PROXY_AUTHORIZATION,
+        header::TE,
+        header::TRAILER,
+        header::TRANSFER_ENCODING,
+        header::UPGRADE,
+    ] {
+        headers.remove(name);
+    }
+}
+
+/// Builds the URI of a forwarded request from the upstream URL and the
+/// request path and query.
+fn upstream_uri(upstream: &Uri, path: &str, query: Option<&str>) -> Result<Uri> {
+    let base = upstream.path().trim_end_matches('/');
+    let mut path_and_query = format!("{}{}", base, path);
+    if path_and_query.is_empty() {
+        path_and_query.push('/');
+    }
+    if let Some(query) = query {
+        path_and_query.push('?');
+        path_and_query.push_str(query);
+    }
+    let mut parts = upstream.clone().into_parts();
+    parts.path_and_query = Some(path_and_query.parse()?);
+    Ok(Uri::from_parts(parts)?)
+}
+
+fn status_response(status: StatusCode) -> Result<Response<Body>> {
+    Ok(Response::builder().status(status).body(Body::empty())?)
+}
+
+/// Replaces `{{ name }}` expressions in the template with the value of the
+/// named application variable.
+fn render(template: &str, resolve: impl Fn(&str) -> Result<String>) -> Result<String> {
+    let mut rendered = String::with_capacity(template.len());
+    let mut remainder = template;
+    while let Some(start) = remainder.find("{{") {
+        rendered.push_str(&remainder[..start]);
+        let (expr, rest) = remainder[start + 2..]
+            .split_once("}}")
+            .ok_or_else(|| anyhow!("unmatched '{{{{' in template"))?;
+        let name = expr.trim();
+        if name.is_empty() {
+            bail!("empty expression in template");
+        }
+        rendered.push_str(
+            &resolve(name).with_context(|| format!("Cannot resolve variable {}", name))?,
+        );
+        remainder = rest;
+    }
+    rendered.push_str(remainder);
+    Ok(rendered)
+}
+
+fn infer_content_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("html" | "htm") => "text/html; charset=utf-8",
+        Some("json") => "application/json",
+        Some("xml") => "application/xml",
+        Some("css") => "text/css",
+        Some("js") => "application/javascript",
+        _ => "text/plain; charset=utf-8",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolve(name: &str) -> Result<String> {
+        match name {
+            "app_name" => Ok("Spin".to_string()),
+            _ => bail!("unknown variable"),
+        }
+    }
+
+    #[test]
+    fn test_upstream_uri() {
+        let upstream: Uri = "http://localhost:8080/api/".parse().unwrap();
+        assert_eq!(
+            upstream_uri(&upstream, "/users", Some("page=2")).unwrap(),
+            "http://localhost:8080/api/users?page=2"
+        );
+        assert_eq!(
+            upstream_uri(&upstream, "", None).unwrap(),
+            "http://localhost:8080/api"
+        );
+
+        let upstream: Uri = "https://example.com".parse().unwrap();
+        assert_eq!(
+            upstream_uri(&upstream, "", None).unwrap(),
+            "https://example.com/"
+        );
+    }
+
+    #[test]
+    fn test_hop_by_hop_headers_are_not_forwarded() {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::CONNECTION, "keep-alive, x-session".parse().unwrap());
+        headers.insert("keep-alive", "timeout=5".parse().unwrap());
+        headers.insert("proxy-connection", "keep-alive".parse().unwrap());
+        headers.insert("x-session", "abc".parse().unwrap());
+        headers.insert(header::PROXY_AUTHORIZATION, "Basic abc".parse().unwrap());
+        headers.insert(header::UPGRADE, "websocket".parse().unwrap());
+        headers.insert(header::ACCEPT, "text/html".parse().unwrap());
+
+        remove_hop_by_hop_headers(&mut headers);
+        assert_eq!(headers.len(), 1);
+        assert_eq!(headers[header::ACCEPT], "text/html");
+    }
+
+    #[test]
+    fn test_render() {
+        assert_eq!(
+            render("<h1>{{ app_name }} is down</h1>", resolve).unwrap(),
+            "<h1>Spin is down</h1>"
+        );
+        assert_eq!(render("{{app_name}}{{app_name}}", resolve).unwrap(), "SpinSpin");
+        assert_eq!(render("no variables", resolve).unwrap(), "no variables");
+        assert!(render("{{ missing }}", resolve).is_err());
+        assert!(render("{{ app_name", resolve).is_err());
+    }
+}
@@ -0,0 +1,110 @@
+//! Configuration values taken from requests, for components whose route maps
+//! route parameters, query parameters or headers to configuration keys.
+
+use std::{collections::BTreeMap, sync::Arc};
+
+use anyhow::Result;
+use http::{HeaderMap, Uri};
+use spin_config::{host_component::ComponentConfig, Resolver};
+use spin_manifest::RequestConfigSource;
+use wasmtime::Store;
+
+use crate::{routes::RoutePattern, RuntimeContext};
+
+/// The configuration values the request has, by key. Keys whose source is
+/// not in the request are left to the configuration of the component.
+pub(crate) fn values(
+    sources: &BTreeMap<String, RequestConfigSource>,
+    uri: &Uri,
+    headers: &HeaderMap,
+    base: &str,
+    raw_route: &str,
+) -> Vec<(String, String)> {
+    if sources.is_empty() {
+        return vec![];
+    }
+    let path_params = RoutePattern::from(base, raw_route).params(uri.path());
+    let query = uri
+        .query()
+        .map(|q| {
+            url::form_urlencoded::parse(q.as_bytes())
+                .into_owned()
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    sources
+        .iter()
+        .filter_map(|(key, source)| {
+            let value = match source {
+                RequestConfigSource::PathParam(name) => path_params
+                    .iter()
+                    .find(|(param, _)| param == name)
+                    .map(|(_, value)| value.clone()),
+                RequestConfigSource::Query(name) => query
+                    .iter()
+                    .find(|(param, _)| param == name)
+                    .map(|(_, value)| value.clone()),
+                RequestConfigSource::Header(name) => headers
+                    .get(name)
+                    .and_then(|value| value.to_str().ok())
+                    .map(str::to_owned),
+            };
+            value.map(|value| (key.clone(), value))
+        })
+        .collect()
+}
+
+/// Makes the values visible to the component through the configuration
+/// interface of its instance.
+pub(crate) fn apply(
+    store: &mut Store<RuntimeContext>,
+    component: &str,
+    values: Vec<(String, String)>,
+) -> Result<()> {
+    if values.is_empty() {
+        return Ok(());
+    }
+    let ctx = store.data_mut();
+    if ctx.component_config.is_none() {
+        // Applications without configuration still get the request values.
+        let resolver = Arc::new(Resolver::new(Default::default())?);
+        ctx.component_config = Some(ComponentConfig::new(component, resolver)?);
+    }
+    if let Some(config) = &mut ctx.component_config {
+        config.set_request_values(values);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_config_values() -> Result<()> {
+        let sources = [
+            ("user_id", "path.id"),
+            ("page", "query.page"),
+            ("tenant", "header.X-Tenant"),
+            ("region", "query.region"),
+        ]
+        .into_iter()
+        .map(|(key, source)| Ok((key.to_owned(), source.to_owned().try_into()?)))
+        .collect::<Result<BTreeMap<_, RequestConfigSource>, String>>()
+        .map_err(anyhow::Error::msg)?;
+
+        let req = http::Request::get("http://localhost/api/users/42?page=2&page=3")
+            .header("x-tenant", "acme")
+            .body(())?;
+        let values = values(&sources, req.uri(), req.headers(), "/api", "/users/:id");
+        assert_eq!(
+            values,
+            vec![
+                ("page".to_owned(), "2".to_owned()),
+                ("tenant".to_owned(), "acme".to_owned()),
+                ("user_id".to_owned(), "42".to_owned()),
+            ]
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,368 @@
+//! Caching the responses of routes with a `cache` for the HTTP trigger.
+
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use bytes::Bytes;
+use futures::StreamExt;
+use http::{header, HeaderMap, HeaderValue, Method, StatusCode};
+use hyper::{Body, Request, Response};
+use sha2::{Digest, Sha256};
+use spin_cache::{CacheConfig, CacheValue, MemoryCache};
+use spin_manifest::HttpCacheConfig;
+
+/// The header set on responses served from the cache.
+pub(crate) const CACHE_HEADER: &str = "spin-cache";
+
+/// The responses of the routes with a `cache`, shared by all of them.
+pub(crate) struct ResponseCache {
+    cache: MemoryCache<CachedResponse>,
+}
+
+/// The key a response is cached under.
+pub(crate) struct CacheKey {
+    key: String,
+    /// Whether the request carries credentials that are not part of the key,
+    /// in which case only responses marked `public` may be cached.
+    credentialed: bool,
+}
+
+#[derive(Clone)]
+struct CachedResponse {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+    stored: Instant,
+}
+
+impl CacheValue for CachedResponse {
+    fn size(&self) -> usize {
+        let headers: usize = self
+            .headers
+            .iter()
+            .map(|(name, value)| name.as_str().len() + value.len())
+            .sum();
+        headers + self.body.len()
+    }
+}
+
+impl ResponseCache {
+    pub(crate) fn new(config: &CacheConfig) -> Self {
+        Self {
+            cache: MemoryCache::new(config.clone()),
+        }
+    }
+
+    /// The cache key of a request to a component, if its response may be
+    /// cached: only GET requests are. The key is made of the URL of the
+    /// request and the values of the headers the route names.
+    pub(crate) fn key(
+        component: &str,
+        config: &HttpCacheConfig,
+        req: &Request<Body>,
+    ) -> Option<CacheKey> {
+        if req.method() != Method::GET {
+            return None;
+        }
+        let mut hasher = Sha256::new();
+        hasher.update(req.uri().to_string());
+        for name in &config.key_headers {
+            hasher.update(b"\n");
+            hasher.update(name.to_ascii_lowercase());
+            for value in req.headers().get_all(name.as_str()) {
+                hasher.update(b"\0");
+                hasher.update(value.as_bytes());
+            }
+        }
+        let credentialed = [header::AUTHORIZATION, header::COOKIE]
+            .iter()
+            .any(|name| req.headers().contains_key(name) && !is_key_header(config, name.as_str()));
+        Some(CacheKey {
+            key: format!("{}:{:x}", component, hasher.finalize()),
+            credentialed,
+        })
+    }
+
+    /// The cached response for a key, if any.
+    pub(crate) fn get(&self, key: &CacheKey) -> Option<Response<Body>> {
+        let cached = self.cache.get(&key.key)?;
+        let mut res = Response::new(Body::from(cached.body));
+        *res.status_mut() = cached.status;
+        *res.headers_mut() = cached.headers;
+        let age = cached.stored.elapsed().as_secs();
+        res.headers_mut()
+            .insert(header::AGE, HeaderValue::from(age));
+        res.headers_mut()
+            .insert(CACHE_HEADER, HeaderValue::from_static("hit"));
+        Some(res)
+    }
+
+    /// Caches the response of a component under a key, if it may be cached,
+    /// and returns it.
+    pub(crate) async fn store(
+        &self,
+        config: &HttpCacheConfig,
+        key: &CacheKey,
+        res: Response<Body>,
+    ) -> Result<Response<Body>> {
+        let ttl = match cacheable_ttl(config, key.credentialed, &res) {
+            Some(ttl) => ttl,
+            None => return Ok(res),
+        };
+        let (parts, mut body) = res.into_parts();
+        let mut chunks = vec![];
+        let mut size = 0;
+        while let Some(chunk) = body.next().await {
+            let chunk = chunk?;
+            size += chunk.len();
+            chunks.push(chunk);
+            if size > config.max_entry_bytes {
+                // Too large to cache: the body streams on from what was read.
+                let read = chunks.into_iter().map(Ok::<_, hyper::Error>);
+                let body = futures::stream::iter(read).chain(body);
+                return Ok(Response::from_parts(parts, Body::wrap_stream(body)));
+            }
+        }
+        let body = Bytes::from(chunks.concat());
+        self.cache.set(
+            &key.key,
+            CachedResponse {
+                status: parts.status,
+                headers: parts.headers.clone(),
+                body: body.clone(),
+                stored: Instant::now(),
+            },
+            Some(ttl),
+        );
+        Ok(Response::from_parts(parts, Body::from(body)))
+    }
+}
+
+/// How long a response may be cached for, if it may be. The route's TTL is
+/// shortened by a lower `max-age` or `s-maxage` from the component, and
+/// responses the component marks `no-store`, `no-cache` or `private` are not
+/// cached, nor are those that set cookies, vary on headers that are not part
+/// of the key, or are not successful. The responses to requests with
+/// credentials that are not part of the key are only cached if the component
+/// marks them `public`, as they may be for that client alone.
+fn cacheable_ttl(
+    config: &HttpCacheConfig,
+    credentialed: bool,
+    res: &Response<Body>,
+) -> Option<Duration> {
+    if !res.status().is_success() || res.headers().contains_key(header::SET_COOKIE) {
+        return None;
+    }
+    if let Some(len) = res
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|len| len.to_str().ok()?.parse::<usize>().ok())
+    {
+        if len > config.max_entry_bytes {
+            return None;
+        }
+    }
+    for vary in res.headers().get_all(header::VARY) {
+        let vary = vary.to_str().ok()?;
+        for name in vary.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+            if !is_key_header(config, name) {
+                return None;
+            }
+        }
+    }
+
+    let mut ttl = config.ttl_secs;
+    let mut public = false;
+    for directives in res.headers().get_all(header::CACHE_CONTROL) {
+        let directives = directives.to_str().ok()?;
+        for directive in directives.split(',').map(str::trim) {
+            let (name, value) = match directive.split_once('=') {
+                Some((name, value)) => (name.trim(), Some(value.trim().trim_matches('"'))),
+                None => (directive, None),
+            };
+            match name.to_ascii_lowercase().as_str() {
+                "no-store" | "no-cache" | "private" => return None,
+                "public" => public = true,
+                "max-age" | "s-maxage" => ttl = ttl.min(value?.parse().ok()?),
+                _ => {}
+            }
+        }
+    }
+    if credentialed && !public {
+        return None;
+    }
+    (ttl > 0).then(|| Duration::from_secs(ttl))
+}
+
+/// Whether the values of the header are part of the cache keys of the route.
+fn is_key_header(config: &HttpCacheConfig, name: &str) -> bool {
+    config
+        .key_headers
+        .iter()
+        .any(|key| key.eq_ignore_ascii_case(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(uri: &str, language: &str) -> Request<Body> {
+        Request::get(uri)
+            .header("accept-language", language)
+            .header("user-agent", "test")
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    fn response(cache_control: Option<&str>, body: &'static str) -> Response<Body> {
+        let mut res = Response::builder().status(200);
+        if let Some(cache_control) = cache_control {
+            res = res.header(header::CACHE_CONTROL, cache_control);
+        }
+        res.body(Body::from(body)).unwrap()
+    }
+
+    fn cache_key(key: &str) -> CacheKey {
+        CacheKey {
+            key: key.to_owned(),
+            credentialed: false,
+        }
+    }
+
+    fn config() -> HttpCacheConfig {
+        HttpCacheConfig {
+            key_headers: vec!["Accept-Language".to_owned()],
+            max_entry_bytes: 16,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_keys_include_the_key_headers() {
+        let config = config();
+        let key = |uri, language| {
+            ResponseCache::key("hello", &config, &request(uri, language)).map(|key| key.key)
+        };
+
+        assert_eq!(key("/a?x=1", "en"), key("/a?x=1", "en"));
+        assert_ne!(key("/a?x=1", "en"), key("/a?x=2", "en"));
+        assert_ne!(key("/a?x=1", "en"), key("/a?x=1", "fr"));
+        assert_ne!(
+            key("/a?x=1", "en"),
+            ResponseCache::key("other", &config, &request("/a?x=1", "en")).map(|key| key.key)
+        );
+
+        let post = Request::post("/a").body(Body::empty()).unwrap();
+        assert!(ResponseCache::key("hello", &config, &post).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_responses_are_cached() -> Result<()> {
+        let cache = ResponseCache::new(&Default::default());
+        let config = config();
+
+        let res = cache
+            .store(&config, &cache_key("key"), response(None, "hello"))
+            .await?;
+        assert_eq!(hyper::body::to_bytes(res.into_body()).await?, "hello");
+
+        let hit = cache
+            .get(&cache_key("key"))
+            .expect("the response should be cached");
+        assert_eq!(hit.headers()[CACHE_HEADER], "hit");
+        assert_eq!(hit.headers()[header::AGE], "0");
+        assert_eq!(hyper::body::to_bytes(hit.into_body()).await?, "hello");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_uncacheable_responses_are_passed_through() -> Result<()> {
+        let cache = ResponseCache::new(&Default::default());
+        let config = config();
+
+        for (key, res) in [
+            ("no-store", response(Some("no-store"), "hello")),
+            ("private", response(Some("private, max-age=60"), "hello")),
+            ("max-age=0", response(Some("max-age=0"), "hello")),
+            ("too large", response(None, "a body longer than the limit")),
+        ] {
+            let res = cache.store(&config, &cache_key(key), res).await?;
+            assert!(!hyper::body::to_bytes(res.into_body()).await?.is_empty());
+            assert!(
+                cache.get(&cache_key(key)).is_none(),
+                "{} should not be cached",
+                key
+            );
+        }
+
+        let error = Response::builder().status(500).body(Body::empty())?;
+        cache.store(&config, &cache_key("error"), error).await?;
+        assert!(cache.get(&cache_key("error")).is_none());
+
+        let vary = response(None, "hello");
+        let (mut parts, body) = vary.into_parts();
+        parts.headers.insert(header::VARY, "user-agent".parse()?);
+        cache
+            .store(
+                &config,
+                &cache_key("vary"),
+                Response::from_parts(parts, body),
+            )
+            .await?;
+        assert!(cache.get(&cache_key("vary")).is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_max_age_shortens_the_ttl() {
+        let config = config();
+        assert_eq!(
+            cacheable_ttl(&config, false, &response(Some("public, max-age=10"), "")),
+            Some(Duration::from_secs(10))
+        );
+        assert_eq!(
+            cacheable_ttl(&config, false, &response(Some("max-age=3600"), "")),
+            Some(Duration::from_secs(60))
+        );
+    }
+
+    #[test]
+    fn test_credentialed_responses_must_be_public() {
+        let config = config();
+        let with = |name, value| {
+            let req = Request::get("/a")
+                .header(name, value)
+                .body(Body::empty())
+                .unwrap();
+            ResponseCache::key("hello", &config, &req).unwrap()
+        };
+
+        let authorized = with(header::AUTHORIZATION, "Bearer token");
+        assert!(authorized.credentialed);
+        assert!(with(header::COOKIE, "session=abc").credentialed);
+        assert!(!with(header::ACCEPT_LANGUAGE, "en").credentialed);
+        assert_eq!(
+            cacheable_ttl(&config, true, &response(Some("max-age=10"), "")),
+            None
+        );
+        assert_eq!(
+            cacheable_ttl(&config, true, &response(Some("public, max-age=10"), "")),
+            Some(Duration::from_secs(10))
+        );
+
+        // Credentials that are part of the key only get their own responses.
+        let config = HttpCacheConfig {
+            key_headers: vec!["Authorization".to_owned()],
+            ..config
+        };
+        let req = Request::get("/a")
+            .header(header::AUTHORIZATION, "Bearer token")
+            .body(Body::empty())
+            .unwrap();
+        assert!(
+            !ResponseCache::key("hello", &config, &req)
+                .unwrap()
+                .credentialed
+        );
+    }
+}
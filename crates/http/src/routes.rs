@@ -39,8 +39,9 @@ impl Router {
     // is correct.
     /// Returns the component ID that should handle the given path, or an error
     /// if no component matches.
-    /// If there are multiple possible components registered for the same route or
-    /// wildcard, this returns the last entry in the component map.
+    /// If there are multiple possible components registered for the same route,
+    /// wildcard or parameterized route, this returns the last entry in the
+    /// component map.
     pub(crate) fn route(&self, p: &str) -> Result<&str> {
         self.routes
             .iter()
@@ -57,6 +58,15 @@ pub enum RoutePattern {
     Exact(String),
     /// A route pattern that matches any path starting with the given string.
     Wildcard(String),
+    /// A route pattern with named segments, such as `/users/:id`, that match
+    /// any single non-empty segment. If `wildcard` is set, the pattern also
+    /// matches any path under it.
+    Parameterized {
+        /// The pattern, without the trailing wildcard.
+        pattern: String,
+        /// Whether the pattern ends with a wildcard.
+        wildcard: bool,
+    },
 }
 
 impl RoutePattern {
@@ -67,9 +77,19 @@ impl RoutePattern {
             Self::sanitize(base.into()),
             Self::sanitize(path.into())
         );
-        match path.strip_suffix("/...") {
-            Some(p) => Self::Wildcard(p.to_owned()),
-            None => Self::Exact(path),
+        let (path, wildcard) = match path.strip_suffix("/...") {
+            Some(p) => (p.to_owned(), true),
+            None => (path, false),
+        };
+        if path.split('/').any(|s| param_name(s).is_some()) {
+            Self::Parameterized {
+                pattern: path,
+                wildcard,
+            }
+        } else if wildcard {
+            Self::Wildcard(path)
+        } else {
+            Self::Exact(path)
         }
     }
 
@@ -82,21 +102,91 @@ impl RoutePattern {
             RoutePattern::Wildcard(pattern) => {
                 &p == pattern || p.starts_with(&format!("{}/", pattern))
             }
+            RoutePattern::Parameterized { .. } => self.match_segments(&p).is_some(),
+        }
+    }
+
+    /// Returns true if some path is matched by both route patterns.
+    pub(crate) fn overlaps(&self, other: &RoutePattern) -> bool {
+        let (segments, wildcard) = self.segments();
+        let (other_segments, other_wildcard) = other.segments();
+        let compatible = segments
+            .iter()
+            .zip(&other_segments)
+            .all(|(a, b)| a == b || param_name(a).is_some() || param_name(b).is_some());
+        let lengths = match (wildcard, other_wildcard) {
+            (false, false) => segments.len() == other_segments.len(),
+            (true, false) => segments.len() <= other_segments.len(),
+            (false, true) => other_segments.len() <= segments.len(),
+            (true, true) => true,
+        };
+        compatible && lengths
+    }
+
+    /// The segments of the route pattern, and whether it matches any path
+    /// under them.
+    fn segments(&self) -> (Vec<&str>, bool) {
+        let (path, wildcard) = match self {
+            Self::Exact(path) => (path, false),
+            Self::Wildcard(path) => (path, true),
+            Self::Parameterized { pattern, wildcard } => (pattern, *wildcard),
+        };
+        // Patterns start with `/`, so the first segment is empty.
+        (path.split('/').skip(1).collect(), wildcard)
+    }
+
+    /// Returns the values of the named segments of the route pattern in the
+    /// given path, in order, as they appear in the path.
+    pub(crate) fn params(&self, p: &str) -> Vec<(String, String)> {
+        let p = Self::sanitize(p);
+        self.match_segments(&p)
+            .map(|(params, _)| params)
+            .unwrap_or_default()
+    }
+
+    /// Matches the segments of a parameterized pattern against a path,
+    /// returning the named segments and the length of the matched prefix.
+    fn match_segments(&self, p: &str) -> Option<(Vec<(String, String)>, usize)> {
+        let (pattern, wildcard) = match self {
+            Self::Parameterized { pattern, wildcard } => (pattern, *wildcard),
+            _ => return None,
+        };
+        let mut params = vec![];
+        let mut rest = p;
+        // Both start with `/`, so the first segments are empty.
+        for expected in pattern.split('/').skip(1) {
+            let segment = rest.strip_prefix('/')?;
+            let (segment, tail) = segment.split_at(segment.find('/').unwrap_or(segment.len()));
+            match param_name(expected) {
+                Some(name) if !segment.is_empty() => {
+                    params.push((name.to_owned(), segment.to_owned()))
+                }
+                None if segment == expected => {}
+                _ => return None,
+            }
+            rest = tail;
+        }
+        if rest.is_empty() || rest == "/" || (wildcard && rest.starts_with('/')) {
+            Some((params, p.len() - rest.len()))
+        } else {
+            None
         }
     }
 
     /// Resolves a relative path from the end of the matched path to the end of the string.
     pub(crate) fn relative(&self, uri: &str) -> Result<String> {
+        let path = uri.parse::<Uri>()?.path().to_owned();
         let base = match self {
             Self::Exact(path) => path,
             Self::Wildcard(prefix) => prefix,
+            Self::Parameterized { .. } => {
+                return Ok(match self.match_segments(&path) {
+                    Some((_, len)) => path[len..].to_owned(),
+                    None => String::new(),
+                })
+            }
         };
-        Ok(uri
-            .parse::<Uri>()?
-            .path()
-            .strip_prefix(base)
-            .unwrap_or_default()
-            .to_owned())
+        Ok(path.strip_prefix(base).unwrap_or_default().to_owned())
     }
 
     /// Sanitizes the base and path and return a formed path.
@@ -126,10 +216,30 @@ impl fmt::Display for RoutePattern {
         match &self {
             RoutePattern::Exact(path) => write!(f, "{}", path),
             RoutePattern::Wildcard(pattern) => write!(f, "{} (wildcard)", pattern),
+            RoutePattern::Parameterized {
+                pattern,
+                wildcard: false,
+            } => write!(f, "{}", pattern),
+            RoutePattern::Parameterized {
+                pattern,
+                wildcard: true,
+            } => write!(f, "{} (wildcard)", pattern),
         }
     }
 }
 
+/// The name of a named segment such as `:id`. Names may only contain ASCII
+/// letters, digits and underscores, so that they can be passed as headers and
+/// environment variables.
+fn param_name(segment: &str) -> Option<&str> {
+    let name = segment.strip_prefix(':')?;
+    if !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        Some(name)
+    } else {
+        None
+    }
+}
+
 #[cfg(test)]
 mod route_tests {
     use super::*;
@@ -189,6 +299,49 @@ mod route_tests {
         assert!(rp.matches("/base"));
     }
 
+    #[test]
+    fn test_parameterized_route() {
+        let rp = RoutePattern::from("/", "/users/:id/orders/:order_id");
+        assert!(matches!(
+            rp,
+            RoutePattern::Parameterized {
+                wildcard: false,
+                ..
+            }
+        ));
+        assert!(rp.matches("/users/1/orders/2"));
+        assert!(rp.matches("/users/1/orders/2/"));
+        assert!(!rp.matches("/users/1/orders"));
+        assert!(!rp.matches("/users//orders/2"));
+        assert!(!rp.matches("/users/1/orders/2/items"));
+        assert!(!rp.matches("/accounts/1/orders/2"));
+        assert_eq!(
+            rp.params("/users/1/orders/2"),
+            vec![
+                ("id".to_owned(), "1".to_owned()),
+                ("order_id".to_owned(), "2".to_owned())
+            ]
+        );
+        assert!(rp.params("/users/1").is_empty());
+
+        let rp = RoutePattern::from("/base", "/files/:bucket/...");
+        assert!(rp.matches("/base/files/images"));
+        assert!(rp.matches("/base/files/images/2022/cat.png"));
+        assert!(!rp.matches("/base/files"));
+        assert!(!rp.matches("/files/images"));
+        assert_eq!(
+            rp.params("/base/files/images/cat.png"),
+            vec![("bucket".to_owned(), "images".to_owned())]
+        );
+        assert_eq!(rp.to_string(), "/base/files/:bucket (wildcard)");
+
+        // Only valid names make a segment a parameter.
+        assert_eq!(
+            RoutePattern::from("/", "/a/:/b:c/:d-e"),
+            RoutePattern::Exact("/a/:/b:c/:d-e".to_owned())
+        );
+    }
+
     #[test]
     fn test_relative() -> Result<()> {
         assert_eq!(
@@ -224,6 +377,15 @@ mod route_tests {
             "/images/abc.png".to_string()
         );
 
+        assert_eq!(
+            RoutePattern::from("/", "/users/:id/...").relative("/users/1/avatar.png?s=64")?,
+            "/avatar.png".to_string()
+        );
+        assert_eq!(
+            RoutePattern::from("/base", "/users/:id").relative("/base/users/1")?,
+            "".to_string()
+        );
+
         Ok(())
     }
 
@@ -309,4 +471,54 @@ mod route_tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_router_order() -> Result<()> {
+        let mut routes = IndexMap::new();
+
+        routes.insert(RoutePattern::from("/", "/users/me"), "me".to_string());
+        routes.insert(RoutePattern::from("/", "/users/:id"), "user".to_string());
+        routes.insert(RoutePattern::from("/", "/..."), "all".to_string());
+
+        let r = Router { routes };
+
+        // Whatever their kind, the last matching route wins.
+        assert_eq!(r.route("/users/me")?, "all".to_string());
+        assert_eq!(r.route("/users/42")?, "all".to_string());
+
+        let mut routes = IndexMap::new();
+
+        routes.insert(RoutePattern::from("/", "/..."), "all".to_string());
+        routes.insert(RoutePattern::from("/", "/users/..."), "users".to_string());
+        routes.insert(RoutePattern::from("/", "/users/:id"), "user".to_string());
+        routes.insert(RoutePattern::from("/", "/users/me"), "me".to_string());
+
+        let r = Router { routes };
+
+        assert_eq!(r.route("/users/me")?, "me".to_string());
+        assert_eq!(r.route("/users/42")?, "user".to_string());
+        assert_eq!(r.route("/users/42/orders")?, "users".to_string());
+        assert_eq!(r.route("/users")?, "users".to_string());
+        assert_eq!(r.route("/about")?, "all".to_string());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_overlaps() {
+        let route = |path| RoutePattern::from("/", path);
+
+        assert!(route("/...").overlaps(&route("/users/me")));
+        assert!(route("/users/...").overlaps(&route("/users")));
+        assert!(route("/users/:id").overlaps(&route("/users/me")));
+        assert!(route("/:kind/me").overlaps(&route("/users/:id")));
+        assert!(route("/users/:id/...").overlaps(&route("/users/42/orders")));
+        assert!(route("/users/...").overlaps(&route("/:kind/...")));
+
+        assert!(!route("/users/me").overlaps(&route("/users/you")));
+        assert!(!route("/users/:id").overlaps(&route("/users")));
+        assert!(!route("/users/:id").overlaps(&route("/users/42/orders")));
+        assert!(!route("/users/...").overlaps(&route("/teams/:id")));
+        assert!(!route("/users/:id/...").overlaps(&route("/users")));
+    }
 }
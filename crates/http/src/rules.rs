@@ -0,0 +1,395 @@
+//! Request rules, letting operators allow, deny, rewrite or tag requests
+//! before they are routed, from a file reloaded as it changes.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{Context, Result};
+use http::{header::HeaderName, HeaderValue, Method, Request, StatusCode, Uri};
+use hyper::Body;
+use regex::{bytes, Regex};
+use serde::Deserialize;
+use tracing::log;
+
+/// The request header listing the tags given to a request by rules.
+pub(crate) const TAGS_HEADER: &str = "spin-rule-tags";
+
+/// The largest body matched by rules. Rules with a body pattern do not match
+/// requests with larger bodies, or whose length is not known in advance.
+const MAX_BODY_BYTES: u64 = 1024 * 1024;
+
+/// How often the rules file is checked for changes.
+const RELOAD_INTERVAL: Duration = Duration::from_secs(2);
+
+/// The rules file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RulesFile {
+    #[serde(default)]
+    rule: Vec<RawRule>,
+}
+
+/// A rule, as written in the rules file.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+struct RawRule {
+    name: String,
+    #[serde(default)]
+    methods: Vec<String>,
+    path: Option<String>,
+    #[serde(default)]
+    headers: HashMap<String, String>,
+    body: Option<String>,
+    action: Action,
+}
+
+/// What a rule does to the requests it matches.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields, rename_all = "snake_case", tag = "type")]
+enum Action {
+    /// Handle the request, skipping the rules after this one.
+    Allow,
+    /// Respond with the given status, without handling the request.
+    Deny {
+        #[serde(default = "default_deny_status")]
+        status: u16,
+    },
+    /// Replace the path of the request, substituting the groups captured by
+    /// the path pattern, such as `$1`, and go on with the following rules.
+    Rewrite { path: String },
+    /// Add a tag to the request, in the `spin-rule-tags` header, and go on
+    /// with the following rules.
+    Tag { tag: String },
+}
+
+fn default_deny_status() -> u16 {
+    StatusCode::FORBIDDEN.as_u16()
+}
+
+/// A compiled rule. A request matches a rule if it matches all its patterns.
+#[derive(Debug)]
+struct Rule {
+    name: String,
+    methods: Vec<Method>,
+    path: Option<Regex>,
+    headers: Vec<(HeaderName, Regex)>,
+    body: Option<bytes::Regex>,
+    action: Action,
+}
+
+impl Rule {
+    fn compile(raw: RawRule) -> Result<Self> {
+        let context = || format!("Invalid rule {}", raw.name);
+        let methods = raw
+            .methods
+            .iter()
+            .map(|m| Method::from_bytes(m.to_ascii_uppercase().as_bytes()))
+            .collect::<Result<_, _>>()
+            .with_context(context)?;
+        let path = raw
+            .path
+            .as_deref()
+            .map(Regex::new)
+            .transpose()
+            .with_context(context)?;
+        let headers = raw
+            .headers
+            .iter()
+            .map(|(name, pattern)| {
+                Ok((
+                    HeaderName::from_bytes(name.as_bytes())?,
+                    Regex::new(pattern)?,
+                ))
+            })
+            .collect::<Result<_>>()
+            .with_context(context)?;
+        let body = raw
+            .body
+            .as_deref()
+            .map(bytes::Regex::new)
+            .transpose()
+            .with_context(context)?;
+        match &raw.action {
+            Action::Deny { status } => {
+                StatusCode::from_u16(*status).with_context(context)?;
+            }
+            Action::Rewrite { .. } if path.is_none() => {
+                anyhow::bail!(
+                    "Rule {} rewrites the path, but has no path pattern",
+                    raw.name
+                )
+            }
+            Action::Tag { tag } => {
+                HeaderValue::from_str(tag).with_context(context)?;
+            }
+            _ => {}
+        }
+        Ok(Self {
+            name: raw.name,
+            methods,
+            path,
+            headers,
+            body,
+            action: raw.action,
+        })
+    }
+
+    fn matches(&self, req: &Request<Body>, body: Option<&[u8]>) -> bool {
+        (self.methods.is_empty() || self.methods.contains(req.method()))
+            && self
+                .path
+                .as_ref()
+                .map_or(true, |path| path.is_match(req.uri().path()))
+            && self.headers.iter().all(|(name, pattern)| {
+                req.headers()
+                    .get_all(name)
+                    .iter()
+                    .any(|v| v.to_str().map_or(false, |v| pattern.is_match(v)))
+            })
+            && self.body.as_ref().map_or(true, |pattern| match body {
+                Some(body) => pattern.is_match(body),
+                None => false,
+            })
+    }
+}
+
+/// The outcome of applying the rules to a request.
+pub(crate) enum Verdict {
+    /// The request, possibly rewritten or tagged, goes on to be routed.
+    Handle(Request<Body>),
+    /// The request is denied with the given status.
+    Deny(StatusCode),
+}
+
+/// The rules applied to requests, reloaded as their file changes.
+pub(crate) struct RequestRules {
+    path: PathBuf,
+    rules: RwLock<Arc<Vec<Rule>>>,
+}
+
+impl RequestRules {
+    /// Loads the rules from a file, failing if it is not a valid rules file.
+    pub(crate) fn load(path: &Path) -> Result<Arc<Self>> {
+        Ok(Arc::new(Self {
+            path: path.to_owned(),
+            rules: RwLock::new(Arc::new(load_rules(path)?)),
+        }))
+    }
+
+    /// Applies the rules to a request, in order.
+    pub(crate) async fn apply(&self, mut req: Request<Body>) -> Result<Verdict> {
+        let rules = self.rules.read().unwrap().clone();
+
+        // Bodies are only read if a rule needs them.
+        let mut body = None;
+        if rules.iter().any(|r| r.body.is_some()) && body_fits(&req) {
+            let (parts, b) = req.into_parts();
+            let bytes = hyper::body::to_bytes(b).await?;
+            body = Some(bytes.clone());
+            req = Request::from_parts(parts, Body::from(bytes));
+        }
+
+        let mut tags = vec![];
+        for rule in rules.iter() {
+            if !rule.matches(&req, body.as_deref()) {
+                continue;
+            }
+            match &rule.action {
+                Action::Allow => break,
+                Action::Deny { status } => {
+                    log::info!("Rule {} denied request for {}", rule.name, req.uri());
+                    return Ok(Verdict::Deny(StatusCode::from_u16(*status)?));
+                }
+                Action::Rewrite { path } => {
+                    // Rewrite rules always have a path pattern.
+                    let pattern = rule.path.as_ref().unwrap();
+                    let rewritten = pattern
+                        .replace(req.uri().path(), path.as_str())
+                        .into_owned();
+                    let uri = rewrite_path(req.uri(), &rewritten)?;
+                    *req.uri_mut() = uri;
+                }
+                Action::Tag { tag } => tags.push(tag.as_str()),
+            }
+        }
+        if !tags.is_empty() {
+            req.headers_mut()
+                .insert(TAGS_HEADER, HeaderValue::from_str(&tags.join(", "))?);
+        }
+        Ok(Verdict::Handle(req))
+    }
+
+    /// Reloads the rules whenever their file changes. Invalid rules files
+    /// are logged, and the previous rules kept.
+    pub(crate) async fn reload_periodically(self: Arc<Self>) {
+        let mut seen = stamp(&self.path);
+        loop {
+            tokio::time::sleep(RELOAD_INTERVAL).await;
+            let current = stamp(&self.path);
+            if current == seen {
+                continue;
+            }
+            seen = current;
+            match load_rules(&self.path) {
+                Ok(rules) => {
+                    log::info!(
+                        "Reloaded {} request rules from {}",
+                        rules.len(),
+                        self.path.display()
+                    );
+                    *self.rules.write().unwrap() = Arc::new(rules);
+                }
+                Err(e) => log::warn!("Failed to reload request rules: {:#}", e),
+            }
+        }
+    }
+}
+
+fn load_rules(path: &Path) -> Result<Vec<Rule>> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Cannot read request rules file {}", path.display()))?;
+    let file: RulesFile = toml::from_str(&contents)
+        .with_context(|| format!("Invalid request rules file {}", path.display()))?;
+    file.rule.into_iter().map(Rule::compile).collect()
+}
+
+/// The size and modification time of a file, which change when it is
+/// rewritten.
+fn stamp(path: &Path) -> Option<(u64, SystemTime)> {
+    let metadata = std::fs::metadata(path).ok()?;
+    Some((metadata.len(), metadata.modified().ok()?))
+}
+
+/// Whether the body of the request has a known length small enough to match.
+fn body_fits(req: &Request<Body>) -> bool {
+    req.headers()
+        .get(http::header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok())
+        .map_or(false, |len| len <= MAX_BODY_BYTES)
+}
+
+/// Replaces the path of a URI, keeping its query.
+fn rewrite_path(uri: &Uri, path: &str) -> Result<Uri> {
+    let path_and_query = match uri.query() {
+        Some(query) => format!("{}?{}", path, query),
+        None => path.to_owned(),
+    };
+    let mut parts = uri.clone().into_parts();
+    parts.path_and_query = Some(path_and_query.parse()?);
+    Ok(Uri::from_parts(parts)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RULES: &str = r#"
+[[rule]]
+name = "health"
+path = "^/healthz$"
+action = { type = "allow" }
+
+[[rule]]
+name = "scanners"
+headers = { "user-agent" = "(?i)sqlmap" }
+action = { type = "deny" }
+
+[[rule]]
+name = "injection"
+methods = ["post"]
+body = "DROP TABLE"
+action = { type = "deny", status = 400 }
+
+[[rule]]
+name = "legacy"
+path = "^/v1/(.*)$"
+action = { type = "rewrite", path = "/api/$1" }
+
+[[rule]]
+name = "api"
+path = "^/api/"
+action = { type = "tag", tag = "api" }
+"#;
+
+    fn rules(contents: &str) -> Result<(tempfile::TempDir, Arc<RequestRules>)> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("rules.toml");
+        std::fs::write(&path, contents)?;
+        let rules = RequestRules::load(&path)?;
+        Ok((dir, rules))
+    }
+
+    async fn apply(rules: &RequestRules, req: Request<Body>) -> Verdict {
+        rules.apply(req).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_rules() -> Result<()> {
+        let (_dir, rules) = rules(RULES)?;
+
+        let req = Request::get("https://example.com/healthz")
+            .header("user-agent", "sqlmap/1.6")
+            .body(Body::empty())?;
+        assert!(matches!(apply(&rules, req).await, Verdict::Handle(_)));
+
+        let req = Request::get("https://example.com/")
+            .header("user-agent", "SQLMap/1.6")
+            .body(Body::empty())?;
+        assert!(matches!(
+            apply(&rules, req).await,
+            Verdict::Deny(status) if status == StatusCode::FORBIDDEN
+        ));
+
+        let req = Request::post("https://example.com/items")
+            .header("content-length", "16")
+            .body(Body::from("1; DROP TABLE x;"))?;
+        assert!(matches!(
+            apply(&rules, req).await,
+            Verdict::Deny(status) if status == StatusCode::BAD_REQUEST
+        ));
+
+        let req = Request::get("https://example.com/v1/items?page=2").body(Body::empty())?;
+        match apply(&rules, req).await {
+            Verdict::Handle(req) => {
+                assert_eq!(req.uri().path(), "/api/items");
+                assert_eq!(req.uri().query(), Some("page=2"));
+                assert_eq!(req.headers()[TAGS_HEADER], "api");
+            }
+            Verdict::Deny(_) => panic!("expected the request to be handled"),
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_body_read_by_rules_is_kept() -> Result<()> {
+        let (_dir, rules) = rules(RULES)?;
+        let req = Request::post("https://example.com/items")
+            .header("content-length", "7")
+            .body(Body::from("Fermyon"))?;
+        match apply(&rules, req).await {
+            Verdict::Handle(req) => {
+                let body = hyper::body::to_bytes(req.into_body()).await?;
+                assert_eq!(&body[..], b"Fermyon");
+            }
+            Verdict::Deny(_) => panic!("expected the request to be handled"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_rules() {
+        for contents in [
+            "[[rule]]\nname = \"a\"\npath = \"(\"\naction = { type = \"allow\" }",
+            "[[rule]]\nname = \"a\"\naction = { type = \"deny\", status = 1000 }",
+            "[[rule]]\nname = \"a\"\naction = { type = \"rewrite\", path = \"/b\" }",
+            "[[rule]]\nname = \"a\"\naction = { type = \"block\" }",
+        ] {
+            assert!(rules(contents).is_err(), "{}", contents);
+        }
+    }
+}
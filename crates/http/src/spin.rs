@@ -8,12 +8,17 @@ use http::Uri;
 use hyper::{Body, Request, Response};
 use spin_engine::io::ModuleIoRedirects;
 use std::{net::SocketAddr, str, str::FromStr};
-use tokio::task::spawn_blocking;
 use tracing::log;
 use wasmtime::{Instance, Store};
 
 #[derive(Clone)]
-pub struct SpinHttpExecutor;
+pub struct SpinHttpExecutor {
+    /// The largest request body passed to components, which receive whole
+    /// bodies.
+    pub max_buffered_bytes: u64,
+    /// The configuration values taken from the request, by key.
+    pub request_config: Vec<(String, String)>,
+}
 
 #[async_trait]
 impl HttpExecutor for SpinHttpExecutor {
@@ -32,14 +37,25 @@ impl HttpExecutor for SpinHttpExecutor {
             component
         );
 
-        let mior = ModuleIoRedirects::new(follow);
+        // Components receive whole bodies, so larger bodies are rejected
+        // before the component is instantiated.
+        let (parts, body) = req.into_parts();
+        let req = match crate::body::read_to_limit(body, self.max_buffered_bytes).await? {
+            Some(bytes) => Request::from_parts(parts, Body::from(bytes)),
+            None => return crate::body::too_large(),
+        };
+
+        let mior = ModuleIoRedirects::for_component(follow, &engine.logger(component));
 
-        let (store, instance) =
-            engine.prepare_component(component, None, Some(mior.pipes), None, None)?;
+        let (mut store, instance) = engine
+            .prepare_component(component, None, Some(mior.pipes), None, None)
+            .await?;
+        crate::request_config::apply(&mut store, component, self.request_config.clone())?;
 
-        let resp_result = Self::execute_impl(store, instance, base, raw_route, req)
-            .await
-            .map_err(contextualise_err);
+        let resp_result =
+            Self::execute_impl(engine, component, store, instance, base, raw_route, req)
+                .await
+                .map_err(contextualise_err);
 
         let log_result =
             engine.save_output_to_logs(mior.read_handles.read(), component, true, true);
@@ -51,6 +67,11 @@ impl HttpExecutor for SpinHttpExecutor {
         let resp = resp_result?;
         log_result?;
 
+        let resp = match engine.components.get(component) {
+            Some(c) => crate::file_body::resolve(resp, &c.core.wasm.mounts).await?,
+            None => resp,
+        };
+
         log::info!(
             "Request finished, sending response with status code {}",
             resp.status()
@@ -61,6 +82,8 @@ impl HttpExecutor for SpinHttpExecutor {
 
 impl SpinHttpExecutor {
     pub async fn execute_impl(
+        engine: &ExecutionContext,
+        component: &str,
         mut store: Store<RuntimeContext>,
         instance: Instance,
         base: &str,
@@ -73,41 +96,42 @@ impl SpinHttpExecutor {
             headers = Self::headers(&mut req, raw_route, base)?;
         }
 
-        let engine = SpinHttp::new(&mut store, &instance, |host| host.data.as_mut().unwrap())?;
+        let spin_http = SpinHttp::new(&mut store, &instance, |host| host.data.as_mut().unwrap())?;
         let (parts, bytes) = req.into_parts();
         let bytes = hyper::body::to_bytes(bytes).await?.to_vec();
 
-        let res = spawn_blocking(move || -> Result<crate::spin_http::Response> {
-            let method = Self::method(&parts.method);
-
-            let headers: Vec<(&str, &str)> = headers
-                .iter()
-                .map(|(k, v)| (k.as_str(), v.as_str()))
-                .collect();
-
-            let params = &Self::params(&parts.uri)?;
-            let params: Vec<(&str, &str)> = params
-                .iter()
-                .map(|(k, v)| (k.as_str(), v.as_str()))
-                .collect();
-
-            let body = Some(&bytes[..]);
-            let uri = match parts.uri.path_and_query() {
-                Some(u) => u.to_string(),
-                None => parts.uri.to_string(),
-            };
-
-            let req = crate::spin_http::Request {
-                method,
-                uri: &uri,
-                headers: &headers,
-                params: &params,
-                body,
-            };
-
-            Ok(engine.handle_http_request(&mut store, req)?)
-        })
-        .await??;
+        let res = engine
+            .run_guest(component, async move {
+                let method = Self::method(&parts.method);
+
+                let headers: Vec<(&str, &str)> = headers
+                    .iter()
+                    .map(|(k, v)| (k.as_str(), v.as_str()))
+                    .collect();
+
+                let params = &Self::params(&parts.uri)?;
+                let params: Vec<(&str, &str)> = params
+                    .iter()
+                    .map(|(k, v)| (k.as_str(), v.as_str()))
+                    .collect();
+
+                let body = Some(&bytes[..]);
+                let uri = match parts.uri.path_and_query() {
+                    Some(u) => u.to_string(),
+                    None => parts.uri.to_string(),
+                };
+
+                let req = crate::spin_http::Request {
+                    method,
+                    uri: &uri,
+                    headers: &headers,
+                    params: &params,
+                    body,
+                };
+
+                Ok::<_, anyhow::Error>(spin_http.handle_http_request(&mut store, req).await?)
+            })
+            .await??;
 
         if res.status < 100 || res.status > 600 {
             log::error!("malformed HTTP status code");
@@ -167,6 +191,9 @@ impl SpinHttpExecutor {
         for (keys, val) in crate::compute_default_headers(req.uri(), raw, base, host)? {
             res.push((Self::prepare_header_key(keys[0]), val));
         }
+        for (keys, val) in crate::compute_path_params(req.uri(), raw, base) {
+            res.push((Self::prepare_header_key(&keys[0]), val));
+        }
 
         Ok(res)
     }
@@ -0,0 +1,286 @@
+//! The static executor, serving the files mounted in a component from the
+//! host, without instantiating the component.
+
+use std::{io::SeekFrom, net::SocketAddr, path::Path, time::UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use http::{
+    header::{
+        ACCEPT_RANGES, ALLOW, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, ETAG, IF_NONE_MATCH,
+        RANGE,
+    },
+    HeaderValue, Method, StatusCode,
+};
+use hyper::{Body, Request, Response};
+use spin_manifest::StaticConfig;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio_util::io::ReaderStream;
+use tracing::log;
+
+use crate::{file_body, routes::RoutePattern, ExecutionContext, HttpExecutor};
+
+/// Serves the files of a mounted directory of a component.
+#[derive(Clone)]
+pub(crate) struct StaticFileExecutor {
+    pub(crate) config: StaticConfig,
+}
+
+#[async_trait]
+impl HttpExecutor for StaticFileExecutor {
+    async fn execute(
+        &self,
+        engine: &ExecutionContext,
+        component: &str,
+        base: &str,
+        raw_route: &str,
+        req: Request<Body>,
+        _client_addr: SocketAddr,
+        _follow: bool,
+    ) -> Result<Response<Body>> {
+        if req.method() != Method::GET && req.method() != Method::HEAD {
+            return Ok(Response::builder()
+                .status(StatusCode::METHOD_NOT_ALLOWED)
+                .header(ALLOW, "GET, HEAD")
+                .body(Body::empty())?);
+        }
+
+        let mounts = &engine
+            .components
+            .get(component)
+            .with_context(|| format!("Cannot find component {}", component))?
+            .core
+            .wasm
+            .mounts;
+        let relative = RoutePattern::from(base, raw_route).relative(&req.uri().to_string())?;
+        let guest_path = guest_path(&self.config.dir, &relative)?;
+        // Paths that cannot be resolved, including those escaping the
+        // mounted directories, are not found.
+        let mut host_path = match file_body::host_path(mounts, &guest_path) {
+            Ok(path) => path,
+            Err(e) => {
+                log::trace!("Cannot serve {}: {:#}", guest_path, e);
+                return not_found();
+            }
+        };
+        if host_path.is_dir() {
+            host_path = host_path.join(&self.config.index);
+        }
+        match tokio::fs::metadata(&host_path).await {
+            Ok(metadata) if metadata.is_file() => serve(&req, &host_path, metadata).await,
+            _ => not_found(),
+        }
+    }
+}
+
+/// Joins the percent-encoded path of a request, relative to its route, to the
+/// guest path of the served directory.
+fn guest_path(dir: &str, relative: &str) -> Result<String> {
+    let relative = percent_encoding::percent_decode_str(relative)
+        .decode_utf8()
+        .context("Request path is not valid UTF-8")?;
+    let dir = dir.trim_end_matches('/');
+    let dir = if dir.starts_with('/') {
+        dir.to_owned()
+    } else {
+        format!("/{}", dir)
+    };
+    Ok(format!("{}/{}", dir, relative.trim_start_matches('/')))
+}
+
+async fn serve(
+    req: &Request<Body>,
+    path: &Path,
+    metadata: std::fs::Metadata,
+) -> Result<Response<Body>> {
+    let len = metadata.len();
+    let etag = etag(&metadata);
+    let content_type = mime_guess::from_path(path).first_or_octet_stream();
+
+    let builder = Response::builder()
+        .header(CONTENT_TYPE, content_type.as_ref())
+        .header(ETAG, &etag)
+        .header(ACCEPT_RANGES, "bytes");
+    if let Some(if_none_match) = req.headers().get(IF_NONE_MATCH) {
+        if matches_etag(if_none_match, &etag) {
+            return Ok(builder
+                .status(StatusCode::NOT_MODIFIED)
+                .body(Body::empty())?);
+        }
+    }
+
+    let (builder, start, count) = match req.headers().get(RANGE).map(|r| parse_range(r, len)) {
+        Some(Range::Satisfiable(start, end)) => (
+            builder
+                .status(StatusCode::PARTIAL_CONTENT)
+                .header(CONTENT_RANGE, format!("bytes {}-{}/{}", start, end, len)),
+            start,
+            end - start + 1,
+        ),
+        Some(Range::Unsatisfiable) => {
+            return Ok(builder
+                .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                .header(CONTENT_RANGE, format!("bytes */{}", len))
+                .body(Body::empty())?)
+        }
+        Some(Range::Ignored) | None => (builder.status(StatusCode::OK), 0, len),
+    };
+    let builder = builder.header(CONTENT_LENGTH, count);
+    if req.method() == Method::HEAD {
+        return Ok(builder.body(Body::empty())?);
+    }
+
+    let mut file = tokio::fs::File::open(path)
+        .await
+        .with_context(|| format!("Cannot open {}", path.display()))?;
+    file.seek(SeekFrom::Start(start)).await?;
+    Ok(builder.body(Body::wrap_stream(ReaderStream::new(file.take(count))))?)
+}
+
+/// A weak validator from the size and modification time of the file.
+fn etag(metadata: &std::fs::Metadata) -> String {
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|m| m.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+    format!("W/\"{:x}-{:x}\"", metadata.len(), modified)
+}
+
+fn matches_etag(if_none_match: &HeaderValue, etag: &str) -> bool {
+    // Weak comparison, as for `If-None-Match`.
+    let opaque = |tag: &str| tag.trim().trim_start_matches("W/").to_owned();
+    match if_none_match.to_str() {
+        Ok(value) => value
+            .split(',')
+            .any(|tag| tag.trim() == "*" || opaque(tag) == opaque(etag)),
+        Err(_) => false,
+    }
+}
+
+/// A byte range request, as handled.
+#[derive(Debug, PartialEq, Eq)]
+enum Range {
+    /// The inclusive range of bytes to serve.
+    Satisfiable(u64, u64),
+    /// The range is outside the file.
+    Unsatisfiable,
+    /// The range is invalid, or has several parts: the whole file is served.
+    Ignored,
+}
+
+fn parse_range(header: &HeaderValue, len: u64) -> Range {
+    let spec = match header.to_str().ok().and_then(|h| h.strip_prefix("bytes=")) {
+        Some(spec) if !spec.contains(',') => spec.trim(),
+        _ => return Range::Ignored,
+    };
+    let (start, end) = match spec.split_once('-') {
+        Some(bounds) => bounds,
+        None => return Range::Ignored,
+    };
+    let range = match (start.parse::<u64>(), end.parse::<u64>()) {
+        // The last `end` bytes.
+        (Err(_), Ok(suffix)) if start.is_empty() => {
+            if suffix == 0 {
+                return Range::Unsatisfiable;
+            }
+            (len.saturating_sub(suffix), len.wrapping_sub(1))
+        }
+        (Ok(start), Err(_)) if end.is_empty() => (start, len.wrapping_sub(1)),
+        (Ok(start), Ok(end)) if start <= end => (start, end.min(len.wrapping_sub(1))),
+        _ => return Range::Ignored,
+    };
+    if len == 0 || range.0 >= len {
+        return Range::Unsatisfiable;
+    }
+    Range::Satisfiable(range.0, range.1)
+}
+
+fn not_found() -> Result<Response<Body>> {
+    Ok(Response::builder()
+        .status(StatusCode::NOT_FOUND)
+        .body(Body::empty())?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(value: &str, len: u64) -> Range {
+        parse_range(&HeaderValue::from_str(value).unwrap(), len)
+    }
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(range("bytes=0-9", 100), Range::Satisfiable(0, 9));
+        assert_eq!(range("bytes=90-", 100), Range::Satisfiable(90, 99));
+        assert_eq!(range("bytes=-10", 100), Range::Satisfiable(90, 99));
+        assert_eq!(range("bytes=-200", 100), Range::Satisfiable(0, 99));
+        assert_eq!(range("bytes=50-500", 100), Range::Satisfiable(50, 99));
+        assert_eq!(range("bytes=100-", 100), Range::Unsatisfiable);
+        assert_eq!(range("bytes=-0", 100), Range::Unsatisfiable);
+        assert_eq!(range("bytes=0-0", 0), Range::Unsatisfiable);
+        assert_eq!(range("bytes=0-1,5-6", 100), Range::Ignored);
+        assert_eq!(range("bytes=9-0", 100), Range::Ignored);
+        assert_eq!(range("items=0-9", 100), Range::Ignored);
+    }
+
+    #[test]
+    fn test_guest_path() {
+        assert_eq!(
+            guest_path("public", "/css/site%20main.css").unwrap(),
+            "/public/css/site main.css"
+        );
+        assert_eq!(guest_path("/", "").unwrap(), "/");
+        assert_eq!(guest_path("/public/", "/").unwrap(), "/public/");
+    }
+
+    #[test]
+    fn test_etag() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("index.html");
+        std::fs::write(&path, "<h1>Spin</h1>")?;
+        let etag = etag(&std::fs::metadata(&path)?);
+        assert!(etag.starts_with("W/\"d-"));
+        let strong = etag.trim_start_matches("W/");
+        assert!(matches_etag(&HeaderValue::from_str(strong)?, &etag));
+        assert!(matches_etag(
+            &HeaderValue::from_str(&format!("\"other\", {}", etag))?,
+            &etag
+        ));
+        assert!(!matches_etag(&HeaderValue::from_static("\"other\""), &etag));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_serve() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("site.css");
+        std::fs::write(&path, "body { color: red; }")?;
+        let metadata = || std::fs::metadata(&path).unwrap();
+
+        let req = Request::get("/site.css").body(Body::empty())?;
+        let res = serve(&req, &path, metadata()).await?;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()[CONTENT_TYPE], "text/css");
+        assert_eq!(res.headers()[CONTENT_LENGTH], "20");
+        let etag = res.headers()[ETAG].clone();
+
+        let req = Request::get("/site.css")
+            .header(RANGE, "bytes=7-11")
+            .body(Body::empty())?;
+        let res = serve(&req, &path, metadata()).await?;
+        assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(res.headers()[CONTENT_RANGE], "bytes 7-11/20");
+        let body = hyper::body::to_bytes(res.into_body()).await?;
+        assert_eq!(&body[..], b"color");
+
+        let req = Request::get("/site.css")
+            .header(IF_NONE_MATCH, etag)
+            .body(Body::empty())?;
+        let res = serve(&req, &path, metadata()).await?;
+        assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+        Ok(())
+    }
+}
@@ -0,0 +1,200 @@
+//! Strict checks of the framing and headers of requests, rejecting those
+//! that servers and proxies in front of Spin may interpret differently.
+
+use std::{
+    collections::BTreeMap,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use http::{header, HeaderMap, Request, StatusCode};
+
+/// The largest total size of the names and values of the headers of a
+/// request.
+pub(crate) const MAX_HEADER_BYTES: usize = 32 * 1024;
+
+/// The largest number of headers of a request.
+pub(crate) const MAX_HEADERS: usize = 100;
+
+/// The size of the buffer connections are read into, bounding the size of
+/// header blocks before they are parsed.
+pub(crate) const MAX_BUFFER_BYTES: usize = 64 * 1024;
+
+/// Why a request was rejected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Violation {
+    /// The length of the body is ambiguous: both `Content-Length` and
+    /// `Transfer-Encoding` are set, `Content-Length` is repeated, or the
+    /// transfer coding is not exactly `chunked`.
+    AmbiguousFraming,
+    /// The headers are too many or too large.
+    HeadersTooLarge,
+    /// A header is repeated where it must not be, or holds control or
+    /// non-ASCII characters.
+    InvalidHeader,
+}
+
+impl Violation {
+    const ALL: [Violation; 3] = [
+        Violation::AmbiguousFraming,
+        Violation::HeadersTooLarge,
+        Violation::InvalidHeader,
+    ];
+
+    /// The label of the violation in metrics.
+    pub(crate) fn reason(&self) -> &'static str {
+        match self {
+            Self::AmbiguousFraming => "ambiguous_framing",
+            Self::HeadersTooLarge => "headers_too_large",
+            Self::InvalidHeader => "invalid_header",
+        }
+    }
+
+    /// The status of the response to a request rejected for the violation.
+    pub(crate) fn status(&self) -> StatusCode {
+        match self {
+            Self::HeadersTooLarge => StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE,
+            Self::AmbiguousFraming | Self::InvalidHeader => StatusCode::BAD_REQUEST,
+        }
+    }
+
+    fn index(&self) -> usize {
+        Self::ALL.iter().position(|v| v == self).unwrap()
+    }
+}
+
+/// Strict checking of requests, counting those rejected.
+#[derive(Default)]
+pub(crate) struct StrictHttp {
+    rejected: [AtomicU64; 3],
+}
+
+impl StrictHttp {
+    /// Checks a request, counting it if it is rejected.
+    pub(crate) fn check<B>(&self, req: &Request<B>) -> Result<(), Violation> {
+        let result = check(req.headers());
+        if let Err(violation) = result {
+            self.rejected[violation.index()].fetch_add(1, Ordering::Relaxed);
+        }
+        result
+    }
+
+    /// The number of requests rejected, by reason.
+    pub(crate) fn rejected(&self) -> BTreeMap<&'static str, u64> {
+        Violation::ALL
+            .iter()
+            .map(|v| (v.reason(), self.rejected[v.index()].load(Ordering::Relaxed)))
+            .collect()
+    }
+}
+
+fn check(headers: &HeaderMap) -> Result<(), Violation> {
+    if headers.len() > MAX_HEADERS {
+        return Err(Violation::HeadersTooLarge);
+    }
+    let size: usize = headers
+        .iter()
+        .map(|(name, value)| name.as_str().len() + value.len())
+        .sum();
+    if size > MAX_HEADER_BYTES {
+        return Err(Violation::HeadersTooLarge);
+    }
+
+    let content_lengths = headers.get_all(header::CONTENT_LENGTH).iter().count();
+    let transfer_encodings: Vec<_> = headers.get_all(header::TRANSFER_ENCODING).iter().collect();
+    if content_lengths > 1
+        || (content_lengths == 1 && !transfer_encodings.is_empty())
+        || transfer_encodings.len() > 1
+        || transfer_encodings
+            .iter()
+            .any(|te| te.as_bytes() != b"chunked")
+    {
+        return Err(Violation::AmbiguousFraming);
+    }
+
+    if headers.get_all(header::HOST).iter().count() > 1 {
+        return Err(Violation::InvalidHeader);
+    }
+    let invalid = |b: &u8| (*b < 0x20 && *b != b'\t') || *b >= 0x7f;
+    if headers
+        .values()
+        .any(|value| value.as_bytes().iter().any(invalid))
+    {
+        return Err(Violation::InvalidHeader);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::HeaderValue;
+
+    fn request(headers: &[(&str, &[u8])]) -> Request<()> {
+        let mut req = Request::new(());
+        for (name, value) in headers {
+            req.headers_mut().append(
+                header::HeaderName::from_bytes(name.as_bytes()).unwrap(),
+                HeaderValue::from_bytes(value).unwrap(),
+            );
+        }
+        req
+    }
+
+    #[test]
+    fn test_ambiguous_framing_is_rejected() {
+        let strict = StrictHttp::default();
+        let ok = [
+            request(&[("content-length", b"5")]),
+            request(&[("transfer-encoding", b"chunked")]),
+            request(&[("host", b"example.com")]),
+        ];
+        for req in &ok {
+            assert_eq!(strict.check(req), Ok(()));
+        }
+
+        let ambiguous = [
+            request(&[("content-length", b"5"), ("transfer-encoding", b"chunked")]),
+            request(&[("content-length", b"5"), ("content-length", b"5")]),
+            request(&[("transfer-encoding", b"chunked, identity")]),
+            request(&[
+                ("transfer-encoding", b"chunked"),
+                ("transfer-encoding", b"chunked"),
+            ]),
+            request(&[("transfer-encoding", b" chunked")]),
+        ];
+        for req in &ambiguous {
+            assert_eq!(strict.check(req), Err(Violation::AmbiguousFraming));
+        }
+        assert_eq!(strict.rejected()["ambiguous_framing"], 5);
+        assert_eq!(strict.rejected()["invalid_header"], 0);
+    }
+
+    #[test]
+    fn test_invalid_and_oversized_headers_are_rejected() {
+        let strict = StrictHttp::default();
+        let invalid = [
+            request(&[("x-name", "caf\u{e9}".as_bytes())]),
+            request(&[("host", b"a.example.com"), ("host", b"b.example.com")]),
+        ];
+        for req in &invalid {
+            assert_eq!(strict.check(req), Err(Violation::InvalidHeader));
+        }
+        assert_eq!(strict.check(&request(&[("x-name", b"a\tb")])), Ok(()));
+
+        let large = vec![b'a'; MAX_HEADER_BYTES];
+        let req = request(&[("x-large", &large)]);
+        assert_eq!(strict.check(&req), Err(Violation::HeadersTooLarge));
+        assert_eq!(
+            Violation::HeadersTooLarge.status(),
+            StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE
+        );
+
+        let names: Vec<String> = (0..=MAX_HEADERS).map(|i| format!("x-{}", i)).collect();
+        let many: Vec<(&str, &[u8])> = names.iter().map(|n| (n.as_str(), &b"1"[..])).collect();
+        assert_eq!(
+            strict.check(&request(&many)),
+            Err(Violation::HeadersTooLarge)
+        );
+        assert_eq!(strict.rejected()["headers_too_large"], 2);
+    }
+}
@@ -0,0 +1,83 @@
+//! Spans and OpenTelemetry metrics of the requests handled by components.
+
+use std::time::Instant;
+
+use anyhow::Result;
+use hyper::{Body, Request, Response};
+use opentelemetry::{
+    global,
+    metrics::{Counter, ValueRecorder},
+    KeyValue,
+};
+use tracing::{field, Span};
+
+/// The metrics of the requests routed to components, recorded with the
+/// global meter, which exports them if an OTLP endpoint was given.
+pub(crate) struct RequestMetrics {
+    requests: Counter<u64>,
+    failures: Counter<u64>,
+    duration: ValueRecorder<f64>,
+}
+
+impl RequestMetrics {
+    pub fn new() -> Self {
+        let meter = global::meter("spin");
+        Self {
+            requests: meter
+                .u64_counter("spin.http.requests")
+                .with_description("Requests handled, by component.")
+                .init(),
+            failures: meter
+                .u64_counter("spin.http.request.failures")
+                .with_description(
+                    "Requests that failed or got a server error response, by component.",
+                )
+                .init(),
+            duration: meter
+                .f64_value_recorder("spin.http.request.duration")
+                .with_description("The time taken to handle requests, in seconds, by component.")
+                .init(),
+        }
+    }
+
+    /// Records a request handled by a component, and its status on its span.
+    pub fn record(
+        &self,
+        component: &str,
+        span: &Span,
+        started: Instant,
+        res: &Result<Response<Body>>,
+    ) {
+        let attributes = [KeyValue::new("component", component.to_owned())];
+        self.requests.add(1, &attributes);
+        self.duration
+            .record(started.elapsed().as_secs_f64(), &attributes);
+        let failed = match res {
+            Ok(res) => {
+                span.record("http.status_code", &res.status().as_u16());
+                res.status().is_server_error()
+            }
+            Err(_) => true,
+        };
+        if failed {
+            span.record("otel.status_code", &"ERROR");
+            self.failures.add(1, &attributes);
+        }
+    }
+}
+
+/// The span of a request routed to a component, continuing the trace of the
+/// client if the request carries a trace context.
+pub(crate) fn request_span(component: &str, req: &Request<Body>) -> Span {
+    let span = tracing::info_span!(
+        "http.request",
+        otel.kind = "server",
+        http.method = %req.method(),
+        http.target = %req.uri().path(),
+        component = %component,
+        http.status_code = field::Empty,
+        otel.status_code = field::Empty,
+    );
+    spin_engine::telemetry::continue_trace(&span, req.headers());
+    span
+}
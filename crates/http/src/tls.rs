@@ -1,4 +1,5 @@
-use rustls_pemfile::{certs, pkcs8_private_keys};
+use anyhow::{bail, Context};
+use rustls_pemfile::{certs, pkcs8_private_keys, rsa_private_keys};
 use std::{
     fs, io,
     path::{Path, PathBuf},
@@ -18,13 +19,15 @@ pub struct TlsConfig {
 impl TlsConfig {
     // Creates a TLS acceptor from server config.
     pub(super) fn server_config(&self) -> anyhow::Result<TlsAcceptor> {
-        let certs = load_certs(&self.cert_path)?;
-        let mut keys = load_keys(&self.key_path)?;
+        let certs = load_certs(&self.cert_path)
+            .with_context(|| format!("Invalid TLS certificate {}", self.cert_path.display()))?;
+        let key = load_key(&self.key_path)
+            .with_context(|| format!("Invalid TLS key {}", self.key_path.display()))?;
 
         let cfg = rustls::ServerConfig::builder()
             .with_safe_defaults()
             .with_no_client_auth()
-            .with_single_cert(certs, keys.remove(0))
+            .with_single_cert(certs, key)
             .map_err(|e| anyhow::anyhow!("{}", e))?;
 
         Ok(Arc::new(cfg).into())
@@ -32,15 +35,47 @@ impl TlsConfig {
 }
 
 // Loads public certificate from file.
-fn load_certs(path: impl AsRef<Path>) -> io::Result<Vec<rustls::Certificate>> {
-    certs(&mut io::BufReader::new(fs::File::open(path)?))
-        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "invalid cert"))
-        .map(|mut certs| certs.drain(..).map(rustls::Certificate).collect())
+fn load_certs(path: impl AsRef<Path>) -> anyhow::Result<Vec<rustls::Certificate>> {
+    let certs = certs(&mut io::BufReader::new(fs::File::open(path)?))
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "invalid cert"))?;
+    if certs.is_empty() {
+        bail!("no certificates found");
+    }
+    Ok(certs.into_iter().map(rustls::Certificate).collect())
+}
+
+// Loads the private key from file, in either PKCS#8 or PKCS#1 (RSA) format.
+fn load_key(path: impl AsRef<Path>) -> anyhow::Result<rustls::PrivateKey> {
+    let path = path.as_ref();
+    let mut keys = pkcs8_private_keys(&mut io::BufReader::new(fs::File::open(path)?))
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "invalid key"))?;
+    if keys.is_empty() {
+        keys = rsa_private_keys(&mut io::BufReader::new(fs::File::open(path)?))
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "invalid key"))?;
+    }
+    match keys.into_iter().next() {
+        Some(key) => Ok(rustls::PrivateKey(key)),
+        None => bail!("no PKCS#8 or RSA private key found"),
+    }
 }
 
-// Loads private key from file.
-fn load_keys(path: impl AsRef<Path>) -> io::Result<Vec<rustls::PrivateKey>> {
-    pkcs8_private_keys(&mut io::BufReader::new(fs::File::open(path)?))
-        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "invalid key"))
-        .map(|mut keys| keys.drain(..).map(rustls::PrivateKey).collect())
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_server_config() {
+        let tls = TlsConfig {
+            cert_path: "tests/local.crt.pem".into(),
+            key_path: "tests/local.key.pem".into(),
+        };
+        tls.server_config().unwrap();
+
+        let swapped = TlsConfig {
+            cert_path: "tests/local.key.pem".into(),
+            key_path: "tests/local.crt.pem".into(),
+        };
+        let err = swapped.server_config().err().unwrap();
+        assert!(err.to_string().contains("local.key.pem"));
+    }
 }
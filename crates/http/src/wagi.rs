@@ -1,23 +1,34 @@
-use crate::{routes::RoutePattern, ExecutionContext, HttpExecutor};
+use crate::{
+    body::{SpoolWriter, Spooled},
+    routes::RoutePattern,
+    ExecutionContext, HttpExecutor,
+};
 use anyhow::Result;
 use async_trait::async_trait;
-use hyper::{body, Body, Request, Response};
-use spin_engine::io::{
-    redirect_to_mem_buffer, Follow, OutputBuffers, RedirectPipes, WriteDestinations,
+use hyper::{Body, Request, Response};
+use spin_engine::{
+    io::{redirect_to_mem_buffer, Follow, OutputBuffers, RedirectPipes, WriteDestinations},
+    logs::ComponentLogger,
 };
 use spin_manifest::WagiConfig;
+use spin_trigger::HttpBodyConfig;
 use std::{
     collections::HashMap,
     net::SocketAddr,
     sync::{Arc, RwLock, RwLockReadGuard},
 };
-use tokio::task::spawn_blocking;
 use tracing::log;
-use wasi_common::pipe::{ReadPipe, WritePipe};
+use wasi_common::pipe::WritePipe;
 
 #[derive(Clone)]
 pub struct WagiHttpExecutor {
     pub wagi_config: WagiConfig,
+    /// Bounds on the bodies held in memory. Larger request bodies are
+    /// spooled to the module's standard input, and larger responses from
+    /// its standard output, through temporary files.
+    pub body_config: HttpBodyConfig,
+    /// The configuration values taken from the request, by key.
+    pub request_config: Vec<(String, String)>,
 }
 
 #[async_trait]
@@ -52,11 +63,22 @@ impl HttpExecutor for WagiHttpExecutor {
 
         let (parts, body) = req.into_parts();
 
-        let body = body::to_bytes(body).await?.to_vec();
-        let len = body.len();
-        let (redirects, outputs) = Self::streams_from_body(body, follow);
-        // TODO
-        // The default host and TLS fields are currently hard-coded.
+        let body = Spooled::read(body, &self.body_config).await?;
+        let len = body.len() as usize;
+        let logger = engine.logger(component);
+        let (redirects, outputs) =
+            Self::streams_from_body(body, &self.body_config, follow, &logger);
+        let default_host = http::HeaderValue::from_str("localhost")?;
+        let host = std::str::from_utf8(
+            parts
+                .headers
+                .get("host")
+                .unwrap_or(&default_host)
+                .as_bytes(),
+        )?;
+        // The URI of the request carries the scheme it was received with,
+        // so Wagi modules see `HTTPS` set for requests served over TLS.
+        let is_tls = parts.uri.scheme() == Some(&http::uri::Scheme::HTTPS);
         let mut headers = wagi::http_util::build_headers(
             &wagi::dispatcher::RoutePattern::parse(&RoutePattern::sanitize_with_base(
                 base, raw_route,
@@ -64,20 +86,11 @@ impl HttpExecutor for WagiHttpExecutor {
             &parts,
             len,
             client_addr,
-            "default_host",
-            false,
+            host,
+            is_tls,
             &HashMap::new(),
         );
 
-        let default_host = http::HeaderValue::from_str("localhost")?;
-        let host = std::str::from_utf8(
-            parts
-                .headers
-                .get("host")
-                .unwrap_or(&default_host)
-                .as_bytes(),
-        )?;
-
         // Add the default Spin headers.
         // This sets the current environment variables Wagi expects (such as
         // `PATH_INFO`, or `X_FULL_URL`).
@@ -85,14 +98,20 @@ impl HttpExecutor for WagiHttpExecutor {
         for (keys, val) in crate::compute_default_headers(&parts.uri, raw_route, base, host)? {
             headers.insert(keys[1].to_string(), val);
         }
+        for ([_, key], val) in crate::compute_path_params(&parts.uri, raw_route, base) {
+            headers.insert(key, val);
+        }
 
-        let (mut store, instance) = engine.prepare_component(
-            component,
-            None,
-            Some(redirects),
-            Some(headers),
-            Some(argv.split(' ').map(|s| s.to_owned()).collect()),
-        )?;
+        let (mut store, instance) = engine
+            .prepare_component(
+                component,
+                None,
+                Some(redirects),
+                Some(headers),
+                Some(argv.split(' ').map(|s| s.to_owned()).collect()),
+            )
+            .await?;
+        crate::request_config::apply(&mut store, component, self.request_config.clone())?;
 
         let start = instance
             .get_func(&mut store, &self.wagi_config.entrypoint)
@@ -104,7 +123,11 @@ impl HttpExecutor for WagiHttpExecutor {
                 )
             })?;
         tracing::trace!("Calling Wasm entry point");
-        let guest_result = spawn_blocking(move || start.call(&mut store, &[], &mut [])).await;
+        let guest_result = engine
+            .run_guest(component, async move {
+                start.call_async(&mut store, &[], &mut []).await
+            })
+            .await;
         tracing::info!("Module execution complete");
 
         let log_result = engine.save_output_to_logs(outputs.read(), component, false, true);
@@ -116,28 +139,27 @@ impl HttpExecutor for WagiHttpExecutor {
         guest_result?.or_else(ignore_successful_proc_exit_trap)?;
         log_result?;
 
-        wagi::handlers::compose_response(outputs.stdout)
+        let mut stdout = outputs.stdout.write().unwrap();
+        stdout.compose_response()
     }
 }
 
 impl WagiHttpExecutor {
     fn streams_from_body(
-        body: Vec<u8>,
+        body: Spooled,
+        body_config: &HttpBodyConfig,
         follow_on_stderr: bool,
+        logger: &ComponentLogger,
     ) -> (RedirectPipes, WagiRedirectReadHandles) {
-        let stdin = ReadPipe::from(body);
+        let stdin = body.into_pipe();
 
-        let stdout_buf = vec![];
-        let stdout_lock = Arc::new(RwLock::new(stdout_buf));
+        let stdout_lock = Arc::new(RwLock::new(SpoolWriter::new(body_config)));
         let stdout_pipe = WritePipe::from_shared(stdout_lock.clone());
 
-        let (stderr_pipe, stderr_lock) = redirect_to_mem_buffer(Follow::stderr(follow_on_stderr));
+        let (stderr_pipe, stderr_lock) =
+            redirect_to_mem_buffer(Follow::stderr(follow_on_stderr), logger);
 
-        let rd = RedirectPipes::new(
-            Box::new(stdin),
-            Box::new(stdout_pipe),
-            Box::new(stderr_pipe),
-        );
+        let rd = RedirectPipes::new(stdin, Box::new(stdout_pipe), Box::new(stderr_pipe));
 
         let h = WagiRedirectReadHandles {
             stdout: stdout_lock,
@@ -149,7 +171,7 @@ impl WagiHttpExecutor {
 }
 
 struct WagiRedirectReadHandles {
-    stdout: Arc<RwLock<Vec<u8>>>,
+    stdout: Arc<RwLock<SpoolWriter>>,
     stderr: Arc<RwLock<WriteDestinations>>,
 }
 
@@ -163,13 +185,13 @@ impl WagiRedirectReadHandles {
 }
 
 struct WagiRedirectReadHandlesLock<'a> {
-    stdout: RwLockReadGuard<'a, Vec<u8>>,
+    stdout: RwLockReadGuard<'a, SpoolWriter>,
     stderr: RwLockReadGuard<'a, WriteDestinations>,
 }
 
 impl<'a> OutputBuffers for WagiRedirectReadHandlesLock<'a> {
     fn stdout(&self) -> &[u8] {
-        &self.stdout
+        self.stdout.head()
     }
     fn stderr(&self) -> &[u8] {
         self.stderr.buffer()
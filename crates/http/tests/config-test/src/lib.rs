@@ -0,0 +1,23 @@
+use spin_http::{Request, Response};
+
+wit_bindgen_rust::export!("../../../../wit/ephemeral/spin-http.wit");
+wit_bindgen_rust::import!("../../../../wit/ephemeral/spin-config.wit");
+
+struct SpinHttp {}
+
+impl spin_http::SpinHttp for SpinHttp {
+    fn handle_http_request(_req: Request) -> Response {
+        match spin_config::get_config("message") {
+            Ok(message) => Response {
+                status: 200,
+                headers: None,
+                body: Some(message.into_bytes()),
+            },
+            Err(_) => Response {
+                status: 500,
+                headers: None,
+                body: None,
+            },
+        }
+    }
+}
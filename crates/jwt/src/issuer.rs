@@ -0,0 +1,110 @@
+use std::{
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{bail, Context, Result};
+use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+fn default_ttl_secs() -> u64 {
+    3600
+}
+
+/// Runtime configuration for a JWT issuer.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub struct IssuerConfig {
+    /// The signing algorithm, for example `HS256`, `RS256` or `ES256`.
+    pub algorithm: Algorithm,
+    /// Shared secret, for HMAC algorithms.
+    pub secret: Option<String>,
+    /// PEM file with the private key, for other algorithms.
+    pub private_key_file: Option<PathBuf>,
+    /// The `iss` claim of issued tokens.
+    pub issuer: String,
+    /// The `aud` claim of issued tokens.
+    pub audience: Option<String>,
+    /// Lifetime of issued tokens, in seconds.
+    #[serde(default = "default_ttl_secs")]
+    pub ttl_secs: u64,
+}
+
+/// Issues signed tokens.
+pub struct Issuer {
+    config: IssuerConfig,
+    key: EncodingKey,
+}
+
+impl Issuer {
+    pub(crate) fn new(config: IssuerConfig) -> Result<Self> {
+        let key = match (&config.secret, &config.private_key_file) {
+            (Some(secret), None) => EncodingKey::from_secret(secret.as_bytes()),
+            (None, Some(file)) => {
+                let pem = std::fs::read(file)
+                    .with_context(|| format!("Cannot read private key {}", file.display()))?;
+                match config.algorithm {
+                    Algorithm::ES256 | Algorithm::ES384 => EncodingKey::from_ec_pem(&pem)?,
+                    Algorithm::EdDSA => EncodingKey::from_ed_pem(&pem)?,
+                    _ => EncodingKey::from_rsa_pem(&pem)?,
+                }
+            }
+            _ => bail!("exactly one of `secret` or `private_key_file` must be set"),
+        };
+        Ok(Self { config, key })
+    }
+
+    /// Issues a token for the given subject. Registered claims set by the
+    /// issuer take precedence over the given extra claims.
+    pub fn issue(&self, subject: &str, mut claims: Map<String, Value>) -> Result<String> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        claims.insert("sub".into(), subject.into());
+        claims.insert("iss".into(), self.config.issuer.clone().into());
+        claims.insert("iat".into(), now.into());
+        claims.insert("exp".into(), (now + self.config.ttl_secs).into());
+        if let Some(audience) = &self.config.audience {
+            claims.insert("aud".into(), audience.clone().into());
+        }
+        Ok(encode(&Header::new(self.config.algorithm), &claims, &self.key)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::validator::{Validator, ValidatorConfig};
+
+    #[tokio::test]
+    async fn test_issued_tokens_validate() -> Result<()> {
+        let issuer = Issuer::new(IssuerConfig {
+            algorithm: Algorithm::HS256,
+            secret: Some("s3cr3t".to_string()),
+            private_key_file: None,
+            issuer: "spin".to_string(),
+            audience: Some("api".to_string()),
+            ttl_secs: 60,
+        })?;
+        let token = issuer.issue("component", Map::new())?;
+
+        let validator_config = |audience: &str| ValidatorConfig {
+            jwks_url: None,
+            secret: Some("s3cr3t".to_string()),
+            public_key_file: None,
+            issuer: Some("spin".to_string()),
+            audience: vec![audience.to_string()],
+            leeway_secs: 0,
+            algorithms: vec![],
+        };
+
+        let claims = Validator::new(validator_config("api"))?
+            .validate(&token)
+            .await?;
+        assert_eq!(claims["sub"], "component");
+        assert!(Validator::new(validator_config("other"))?
+            .validate(&token)
+            .await
+            .is_err());
+        Ok(())
+    }
+}
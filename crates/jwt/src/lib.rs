@@ -0,0 +1,204 @@
+//! JWT validation and issuance for Spin applications.
+//!
+//! Validators are used by the HTTP trigger to authenticate requests to routes
+//! with `auth` configuration, and both validators and issuers are available to
+//! guests through the `spin-jwt` interface.
+
+mod issuer;
+mod validator;
+
+use std::{collections::HashMap, sync::Arc};
+
+use anyhow::Context;
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use spin_engine::{
+    host_component::{HostComponent, HostComponentsStateHandle},
+    RuntimeContext,
+};
+use spin_jwt::*;
+use spin_manifest::CoreComponent;
+use wit_bindgen_wasmtime::wasmtime::Linker;
+
+pub use issuer::{Issuer, IssuerConfig};
+pub use spin_jwt::add_to_linker;
+pub use validator::{Validator, ValidatorConfig};
+
+wit_bindgen_wasmtime::export!({
+    paths: ["../../wit/ephemeral/spin-jwt.wit"],
+    async: *,
+});
+
+/// Runtime configuration for JWT validators and issuers.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub struct JwtConfig {
+    /// Validators, by name.
+    #[serde(default)]
+    pub validator: HashMap<String, ValidatorConfig>,
+    /// Issuers, by name.
+    #[serde(default)]
+    pub issuer: HashMap<String, IssuerConfig>,
+}
+
+/// The configured JWT validators and issuers.
+#[derive(Default)]
+pub struct JwtProviders {
+    validators: HashMap<String, Validator>,
+    issuers: HashMap<String, Issuer>,
+}
+
+impl JwtProviders {
+    /// Creates the validators and issuers described by the given configuration.
+    pub fn new(config: &JwtConfig) -> anyhow::Result<Self> {
+        let validators = config
+            .validator
+            .iter()
+            .map(|(name, c)| {
+                let validator = Validator::new(c.clone())
+                    .with_context(|| format!("Invalid JWT validator {}", name))?;
+                Ok((name.clone(), validator))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        let issuers = config
+            .issuer
+            .iter()
+            .map(|(name, c)| {
+                let issuer = Issuer::new(c.clone())
+                    .with_context(|| format!("Invalid JWT issuer {}", name))?;
+                Ok((name.clone(), issuer))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Self {
+            validators,
+            issuers,
+        })
+    }
+
+    /// Returns the named validator.
+    pub fn validator(&self, name: &str) -> Option<&Validator> {
+        self.validators.get(name)
+    }
+
+    /// Returns the named issuer.
+    pub fn issuer(&self, name: &str) -> Option<&Issuer> {
+        self.issuers.get(name)
+    }
+}
+
+/// The JWT host component.
+#[derive(Clone)]
+pub struct JwtComponent {
+    providers: Arc<JwtProviders>,
+}
+
+impl JwtComponent {
+    /// Creates a JWT host component exposing the given validators and issuers.
+    pub fn new(providers: Arc<JwtProviders>) -> Self {
+        Self { providers }
+    }
+}
+
+impl HostComponent for JwtComponent {
+    type State = Jwt;
+
+    fn add_to_linker<T: Send>(
+        linker: &mut Linker<RuntimeContext<T>>,
+        state_handle: HostComponentsStateHandle<Self::State>,
+    ) -> anyhow::Result<()> {
+        add_to_linker(linker, move |ctx| state_handle.get_mut(ctx))
+    }
+
+    fn build_state(&self, component: &CoreComponent) -> anyhow::Result<Self::State> {
+        Ok(Jwt {
+            providers: self.providers.clone(),
+            allowed_issuers: component.wasm.allowed_jwt_issuers.clone(),
+        })
+    }
+}
+
+/// Per-component JWT state.
+pub struct Jwt {
+    providers: Arc<JwtProviders>,
+    /// The issuers the component may issue tokens with.
+    allowed_issuers: Vec<String>,
+}
+
+#[async_trait]
+impl spin_jwt::SpinJwt for Jwt {
+    async fn validate(&mut self, validator: &str, token: &str) -> Result<String, Error> {
+        let validator = self
+            .providers
+            .validator(validator)
+            .ok_or_else(|| Error::UnknownValidator(validator.to_string()))?;
+        let claims = validator
+            .validate(token)
+            .await
+            .map_err(|e| Error::InvalidToken(e.to_string()))?;
+        serde_json::to_string(&claims).map_err(|e| Error::Other(e.to_string()))
+    }
+
+    async fn issue(
+        &mut self,
+        issuer: &str,
+        subject: &str,
+        claims: Option<&str>,
+    ) -> Result<String, Error> {
+        if !self.allowed_issuers.iter().any(|name| name == issuer) {
+            return Err(Error::Other(format!(
+                "issuer {:?} is not in allowed_jwt_issuers",
+                issuer
+            )));
+        }
+        let issuer = self
+            .providers
+            .issuer(issuer)
+            .ok_or_else(|| Error::UnknownIssuer(issuer.to_string()))?;
+        let claims: Map<String, Value> = match claims {
+            Some(claims) => {
+                serde_json::from_str(claims).map_err(|e| Error::Other(e.to_string()))?
+            }
+            None => Map::new(),
+        };
+        issuer
+            .issue(subject, claims)
+            .map_err(|e| Error::Other(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jsonwebtoken::Algorithm;
+
+    #[tokio::test]
+    async fn test_components_only_use_allowed_issuers() -> anyhow::Result<()> {
+        let issuer = IssuerConfig {
+            algorithm: Algorithm::HS256,
+            secret: Some("s3cr3t".to_string()),
+            private_key_file: None,
+            issuer: "spin".to_string(),
+            audience: None,
+            ttl_secs: 60,
+        };
+        let providers = Arc::new(JwtProviders::new(&JwtConfig {
+            issuer: [("internal".to_string(), issuer)].into(),
+            ..Default::default()
+        })?);
+        let jwt = |allowed_issuers: Vec<String>| Jwt {
+            providers: providers.clone(),
+            allowed_issuers,
+        };
+
+        assert!(jwt(vec![])
+            .issue("internal", "component", None)
+            .await
+            .is_err());
+        assert!(jwt(vec!["internal".to_string()])
+            .issue("internal", "component", None)
+            .await
+            .is_ok());
+        Ok(())
+    }
+}
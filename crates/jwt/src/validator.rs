@@ -0,0 +1,242 @@
+use std::{
+    path::PathBuf,
+    sync::RwLock,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use jsonwebtoken::{
+    decode, decode_header,
+    jwk::{AlgorithmParameters, JwkSet},
+    Algorithm, DecodingKey, Validation,
+};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+/// How long fetched JWKS documents are cached for.
+const JWKS_CACHE_TTL: Duration = Duration::from_secs(600);
+
+/// How long after a JWKS document is fetched before a token signed with a key
+/// it does not have fetches it again, so that such tokens cannot make every
+/// request fetch it.
+const JWKS_MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Runtime configuration for a JWT validator.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub struct ValidatorConfig {
+    /// URL of the JWKS document with the keys tokens may be signed with.
+    pub jwks_url: Option<String>,
+    /// Shared secret for HMAC-signed tokens.
+    pub secret: Option<String>,
+    /// PEM file with the public key tokens are signed with.
+    pub public_key_file: Option<PathBuf>,
+    /// Required `iss` claim.
+    pub issuer: Option<String>,
+    /// Accepted `aud` claims.
+    #[serde(default)]
+    pub audience: Vec<String>,
+    /// Leeway, in seconds, when checking `exp` and `nbf`.
+    #[serde(default)]
+    pub leeway_secs: u64,
+    /// Algorithms tokens may be signed with. Defaults to HS256 with a shared
+    /// secret, and to RS256 with public keys.
+    #[serde(default)]
+    pub algorithms: Vec<Algorithm>,
+}
+
+enum KeySource {
+    Jwks {
+        url: String,
+        cache: RwLock<Option<(Instant, JwkSet)>>,
+    },
+    Secret(Vec<u8>),
+    PublicKey(Vec<u8>),
+}
+
+/// Validates tokens against a configured key source and claim requirements.
+pub struct Validator {
+    config: ValidatorConfig,
+    keys: KeySource,
+    algorithms: Vec<Algorithm>,
+}
+
+impl Validator {
+    /// Creates a validator. Keys are fetched from a JWKS URL when tokens are
+    /// first validated, not here.
+    pub fn new(config: ValidatorConfig) -> Result<Self> {
+        let keys = match (&config.jwks_url, &config.secret, &config.public_key_file) {
+            (Some(url), None, None) => KeySource::Jwks {
+                url: url.clone(),
+                cache: RwLock::new(None),
+            },
+            (None, Some(secret), None) => KeySource::Secret(secret.as_bytes().to_vec()),
+            (None, None, Some(file)) => KeySource::PublicKey(
+                std::fs::read(file)
+                    .with_context(|| format!("Cannot read public key {}", file.display()))?,
+            ),
+            _ => bail!("exactly one of `jwks_url`, `secret` or `public_key_file` must be set"),
+        };
+        let secret = matches!(keys, KeySource::Secret(_));
+        let algorithms = match (config.algorithms.is_empty(), secret) {
+            (false, _) => config.algorithms.clone(),
+            (true, true) => vec![Algorithm::HS256],
+            (true, false) => vec![Algorithm::RS256],
+        };
+        // A public key used as an HMAC secret would let anyone sign tokens.
+        if let Some(algorithm) = algorithms.iter().find(|a| is_hmac(**a) != secret) {
+            bail!(
+                "algorithm {:?} cannot be used with {}",
+                algorithm,
+                if secret { "a secret" } else { "public keys" }
+            );
+        }
+        Ok(Self {
+            config,
+            keys,
+            algorithms,
+        })
+    }
+
+    /// Validates a token, returning its claims. The JWKS document is fetched
+    /// if the key of the token is not cached.
+    pub async fn validate(&self, token: &str) -> Result<Map<String, Value>> {
+        let header = decode_header(token).context("malformed token header")?;
+        if !self.algorithms.contains(&header.alg) {
+            bail!("token algorithm {:?} is not allowed", header.alg);
+        }
+        let key = match &self.keys {
+            KeySource::Secret(secret) => DecodingKey::from_secret(secret),
+            KeySource::PublicKey(pem) => match header.alg {
+                Algorithm::ES256 | Algorithm::ES384 => DecodingKey::from_ec_pem(pem)?,
+                Algorithm::EdDSA => DecodingKey::from_ed_pem(pem)?,
+                _ => DecodingKey::from_rsa_pem(pem)?,
+            },
+            KeySource::Jwks { url, cache } => {
+                let kid = header.kid.as_deref().context("token has no key ID")?;
+                self.jwks_key(url, cache, kid).await?
+            }
+        };
+
+        let mut validation = Validation::new(header.alg);
+        validation.algorithms = self.algorithms.clone();
+        validation.leeway = self.config.leeway_secs;
+        if let Some(issuer) = &self.config.issuer {
+            validation.set_issuer(&[issuer]);
+        }
+        if self.config.audience.is_empty() {
+            validation.validate_aud = false;
+        } else {
+            validation.set_audience(&self.config.audience);
+        }
+
+        Ok(decode::<Map<String, Value>>(token, &key, &validation)?.claims)
+    }
+
+    async fn jwks_key(
+        &self,
+        url: &str,
+        cache: &RwLock<Option<(Instant, JwkSet)>>,
+        kid: &str,
+    ) -> Result<DecodingKey> {
+        let (cached, refreshable) = match cache.read().unwrap().as_ref() {
+            Some((fetched, jwks)) if fetched.elapsed() < JWKS_CACHE_TTL => (
+                jwks.find(kid).cloned(),
+                fetched.elapsed() >= JWKS_MIN_REFRESH_INTERVAL,
+            ),
+            _ => (None, true),
+        };
+        let jwk = match cached {
+            Some(jwk) => jwk,
+            None if !refreshable => bail!("no key {} in JWKS", kid),
+            None => {
+                // Either the cache expired, or the signing keys were rotated.
+                tracing::log::debug!("Fetching JWKS from {}", url);
+                let jwks: JwkSet = reqwest::get(url).await?.error_for_status()?.json().await?;
+                let jwk = jwks.find(kid).cloned();
+                *cache.write().unwrap() = Some((Instant::now(), jwks));
+                jwk.ok_or_else(|| anyhow!("no key {} in JWKS", kid))?
+            }
+        };
+        Ok(match &jwk.algorithm {
+            AlgorithmParameters::RSA(rsa) => DecodingKey::from_rsa_components(&rsa.n, &rsa.e)?,
+            AlgorithmParameters::EllipticCurve(ec) => {
+                DecodingKey::from_ec_components(&ec.x, &ec.y)?
+            }
+            _ => bail!("unsupported key type for key {}", kid),
+        })
+    }
+}
+
+fn is_hmac(algorithm: Algorithm) -> bool {
+    matches!(
+        algorithm,
+        Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use jsonwebtoken::{encode, EncodingKey, Header};
+
+    use super::*;
+
+    fn config(algorithms: Vec<Algorithm>) -> ValidatorConfig {
+        ValidatorConfig {
+            jwks_url: None,
+            secret: Some("s3cr3t".to_string()),
+            public_key_file: None,
+            issuer: None,
+            audience: vec![],
+            leeway_secs: 0,
+            algorithms,
+        }
+    }
+
+    fn token(algorithm: Algorithm) -> String {
+        let claims = serde_json::json!({ "sub": "user", "exp": u32::MAX });
+        let key = EncodingKey::from_secret(b"s3cr3t");
+        encode(&Header::new(algorithm), &claims, &key).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_only_allowed_algorithms_validate() -> Result<()> {
+        let validator = Validator::new(config(vec![]))?;
+        assert!(validator.validate(&token(Algorithm::HS256)).await.is_ok());
+        assert!(validator.validate(&token(Algorithm::HS512)).await.is_err());
+
+        let validator = Validator::new(config(vec![Algorithm::HS512]))?;
+        assert!(validator.validate(&token(Algorithm::HS512)).await.is_ok());
+        assert!(validator.validate(&token(Algorithm::HS256)).await.is_err());
+
+        // Secrets are only for HMAC, and public keys never are.
+        assert!(Validator::new(config(vec![Algorithm::RS256])).is_err());
+        let jwks = ValidatorConfig {
+            jwks_url: Some("https://spin.invalid/jwks.json".to_string()),
+            secret: None,
+            ..config(vec![Algorithm::HS256])
+        };
+        assert!(Validator::new(jwks).is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_unknown_keys_do_not_refetch_fresh_jwks() {
+        let cache = RwLock::new(Some((Instant::now(), JwkSet { keys: vec![] })));
+        let validator = Validator::new(ValidatorConfig {
+            jwks_url: Some("https://spin.invalid/jwks.json".to_string()),
+            secret: None,
+            ..config(vec![])
+        })
+        .unwrap();
+
+        // The document was just fetched, so the key is missing rather than
+        // the document fetched again.
+        let err = validator
+            .jwks_key("https://spin.invalid/jwks.json", &cache, "rotated")
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(err.to_string(), "no key rotated in JWKS");
+    }
+}
@@ -0,0 +1,291 @@
+//! A key-value store host interface for Spin components.
+
+mod sqlite;
+
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::Context;
+use async_trait::async_trait;
+use serde::Deserialize;
+use spin_engine::{
+    host_component::{HostComponent, HostComponentsStateHandle},
+    DataDir, QuotaExceeded, RuntimeContext, ServiceUnavailable,
+};
+use spin_key_value::*;
+use spin_manifest::CoreComponent;
+use wit_bindgen_wasmtime::wasmtime::Linker;
+
+pub use spin_key_value::add_to_linker;
+
+wit_bindgen_wasmtime::export!({
+    paths: ["../../wit/ephemeral/spin-key-value.wit"],
+    async: *,
+});
+
+/// The directory, relative to the data directory of the application or else
+/// to the working directory of the runtime, in which stores without explicit
+/// runtime configuration are kept.
+const DEFAULT_KEY_VALUE_DIR: &str = ".spin/key_value";
+
+/// The maximum length of a key, in bytes.
+const MAX_KEY_BYTES: usize = 255;
+
+/// Runtime configuration for a key-value store.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "snake_case", tag = "type")]
+pub enum StoreConfig {
+    /// Keep the store in a SQLite database file.
+    Sqlite {
+        /// The path of the database file.
+        path: PathBuf,
+    },
+}
+
+/// A backing store for a single key-value store. Stores other than SQLite,
+/// such as Redis, implement this trait.
+pub(crate) trait Store: Send + Sync {
+    fn get(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>>;
+    fn set(&self, key: &str, value: &[u8]) -> anyhow::Result<()>;
+    fn delete(&self, key: &str) -> anyhow::Result<()>;
+    fn exists(&self, key: &str) -> anyhow::Result<bool>;
+    fn list(&self, prefix: &str) -> anyhow::Result<Vec<String>>;
+}
+
+/// A store of a key-value component started without its backend, returning
+/// `unavailable` errors.
+struct UnavailableStore(ServiceUnavailable);
+
+impl Store for UnavailableStore {
+    fn get(&self, _key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        Err(self.0.clone().into())
+    }
+
+    fn set(&self, _key: &str, _value: &[u8]) -> anyhow::Result<()> {
+        Err(self.0.clone().into())
+    }
+
+    fn delete(&self, _key: &str) -> anyhow::Result<()> {
+        Err(self.0.clone().into())
+    }
+
+    fn exists(&self, _key: &str) -> anyhow::Result<bool> {
+        Err(self.0.clone().into())
+    }
+
+    fn list(&self, _prefix: &str) -> anyhow::Result<Vec<String>> {
+        Err(self.0.clone().into())
+    }
+}
+
+/// The key-value host component.
+#[derive(Clone, Default)]
+pub struct KeyValueComponent {
+    stores: HashMap<String, StoreConfig>,
+    data_dir: Option<DataDir>,
+    unavailable: Option<ServiceUnavailable>,
+    /// The stores opened so far, shared by the instances of all components.
+    opened: Arc<Mutex<HashMap<String, Arc<dyn Store>>>>,
+}
+
+impl KeyValueComponent {
+    /// Creates a key-value host component with the given store configuration.
+    /// Stores that are not configured are kept in SQLite databases on the
+    /// local filesystem.
+    pub fn new(stores: HashMap<String, StoreConfig>) -> Self {
+        Self {
+            stores,
+            ..Default::default()
+        }
+    }
+
+    /// Keeps stores that are not configured in the data directory of the
+    /// application, within its quota.
+    pub fn with_data_dir(mut self, data_dir: Option<DataDir>) -> Self {
+        self.data_dir = data_dir;
+        self
+    }
+
+    /// Returns `unavailable` errors for all stores, as the backing store was
+    /// unavailable when the application started.
+    pub fn unavailable(mut self, error: ServiceUnavailable) -> Self {
+        self.unavailable = Some(error);
+        self
+    }
+
+    /// Checks that the configured stores can be opened.
+    pub fn check(&self) -> anyhow::Result<()> {
+        for name in self.stores.keys() {
+            self.open(name)
+                .with_context(|| format!("Cannot open key-value store {:?}", name))?;
+        }
+        Ok(())
+    }
+
+    fn open(&self, name: &str) -> anyhow::Result<Arc<dyn Store>> {
+        if let Some(error) = &self.unavailable {
+            return Ok(Arc::new(UnavailableStore(error.clone())));
+        }
+        let mut opened = self.opened.lock().unwrap();
+        if let Some(store) = opened.get(name) {
+            return Ok(store.clone());
+        }
+        let file = format!("{}.db", name);
+        let store: Arc<dyn Store> = match (self.stores.get(name), &self.data_dir) {
+            (Some(StoreConfig::Sqlite { path }), _) => {
+                Arc::new(sqlite::SqliteStore::open(path.clone(), None)?)
+            }
+            (None, Some(data_dir)) => Arc::new(sqlite::SqliteStore::open(
+                data_dir.path().join(DEFAULT_KEY_VALUE_DIR).join(file),
+                Some(data_dir.clone()),
+            )?),
+            (None, None) => Arc::new(sqlite::SqliteStore::open(
+                PathBuf::from(DEFAULT_KEY_VALUE_DIR).join(file),
+                None,
+            )?),
+        };
+        opened.insert(name.to_owned(), store.clone());
+        Ok(store)
+    }
+}
+
+impl HostComponent for KeyValueComponent {
+    type State = KeyValue;
+
+    fn add_to_linker<T: Send>(
+        linker: &mut Linker<RuntimeContext<T>>,
+        state_handle: HostComponentsStateHandle<Self::State>,
+    ) -> anyhow::Result<()> {
+        add_to_linker(linker, move |ctx| state_handle.get_mut(ctx))
+    }
+
+    fn build_state(&self, component: &CoreComponent) -> anyhow::Result<Self::State> {
+        let stores = component
+            .wasm
+            .key_value_stores
+            .iter()
+            .map(|name| Ok((name.clone(), self.open(name)?)))
+            .collect::<anyhow::Result<_>>()?;
+        Ok(KeyValue { stores })
+    }
+}
+
+/// Per-component key-value state, holding the stores the component may access.
+pub struct KeyValue {
+    stores: HashMap<String, Arc<dyn Store>>,
+}
+
+impl KeyValue {
+    fn store(&self, name: &str) -> Result<&Arc<dyn Store>, Error> {
+        self.stores.get(name).ok_or_else(|| {
+            Error::AccessDenied(format!("store {:?} is not in key_value_stores", name))
+        })
+    }
+}
+
+#[async_trait]
+impl spin_key_value::SpinKeyValue for KeyValue {
+    async fn get(&mut self, store: &str, key: &str) -> Result<Vec<u8>, Error> {
+        validate_key(key)?;
+        self.store(store)?
+            .get(key)
+            .map_err(to_error)?
+            .ok_or_else(|| Error::NotFound(key.to_owned()))
+    }
+
+    async fn set(&mut self, store: &str, key: &str, value: &[u8]) -> Result<(), Error> {
+        validate_key(key)?;
+        self.store(store)?.set(key, value).map_err(to_error)
+    }
+
+    async fn delete(&mut self, store: &str, key: &str) -> Result<(), Error> {
+        validate_key(key)?;
+        self.store(store)?.delete(key).map_err(to_error)
+    }
+
+    async fn exists(&mut self, store: &str, key: &str) -> Result<bool, Error> {
+        validate_key(key)?;
+        self.store(store)?.exists(key).map_err(to_error)
+    }
+
+    async fn list(&mut self, store: &str, prefix: Option<&str>) -> Result<Vec<String>, Error> {
+        self.store(store)?
+            .list(prefix.unwrap_or_default())
+            .map_err(to_error)
+    }
+}
+
+/// Keys are non-empty strings of at most `MAX_KEY_BYTES` bytes.
+fn validate_key(key: &str) -> Result<(), Error> {
+    if key.is_empty() || key.len() > MAX_KEY_BYTES {
+        return Err(Error::InvalidKey(key.to_owned()));
+    }
+    Ok(())
+}
+
+fn to_error(e: anyhow::Error) -> Error {
+    if e.is::<ServiceUnavailable>() {
+        Error::Unavailable(e.to_string())
+    } else if e.downcast_ref::<QuotaExceeded>().is_some() {
+        // Exceeding the quota is the guest's doing, not a host failure.
+        Error::Other(e.to_string())
+    } else {
+        tracing::log::error!("Key-value store error: {:?}", e);
+        Error::Other(e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sqlite_store() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let config = StoreConfig::Sqlite {
+            path: dir.path().join("cart.db"),
+        };
+        let component = KeyValueComponent::new([("cart".to_owned(), config)].into_iter().collect());
+        let store = component.open("cart")?;
+
+        assert_eq!(store.get("items/1")?, None);
+        assert!(!store.exists("items/1")?);
+        store.set("items/1", b"apple")?;
+        store.set("items/2", b"pear")?;
+        store.set("total", b"2")?;
+        store.set("items/2", b"plum")?;
+        assert_eq!(store.get("items/2")?, Some(b"plum".to_vec()));
+        assert!(store.exists("items/1")?);
+        assert_eq!(store.list("items/")?, ["items/1", "items/2"]);
+        assert_eq!(store.list("")?.len(), 3);
+
+        store.delete("items/1")?;
+        store.delete("items/1")?;
+        assert!(!store.exists("items/1")?);
+
+        // Stores are opened once and shared.
+        assert_eq!(component.open("cart")?.get("total")?, Some(b"2".to_vec()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_key() {
+        assert!(validate_key("a").is_ok());
+        assert!(validate_key(&"k".repeat(MAX_KEY_BYTES)).is_ok());
+        assert!(validate_key("").is_err());
+        assert!(validate_key(&"k".repeat(MAX_KEY_BYTES + 1)).is_err());
+    }
+
+    #[test]
+    fn test_unavailable_store() {
+        let component = KeyValueComponent::default().unavailable(ServiceUnavailable {
+            service: "key_value".to_owned(),
+            reason: "disk full".to_owned(),
+        });
+        let err = component.open("cart").unwrap().get("total").unwrap_err();
+        assert!(matches!(to_error(err), Error::Unavailable(_)));
+    }
+}
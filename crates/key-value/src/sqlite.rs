@@ -0,0 +1,93 @@
+use std::{path::PathBuf, sync::Mutex, time::Duration};
+
+use anyhow::{Context, Result};
+use rusqlite::{params, Connection, OptionalExtension};
+use spin_engine::DataDir;
+
+use crate::Store;
+
+/// How long to wait for the database when another process is writing to it.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A store kept in a SQLite database file, optionally bounded by the quota of
+/// the data directory it is in.
+pub(crate) struct SqliteStore {
+    connection: Mutex<Connection>,
+    quota: Option<DataDir>,
+}
+
+impl SqliteStore {
+    pub(crate) fn open(path: PathBuf, quota: Option<DataDir>) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Cannot create directory {}", parent.display()))?;
+        }
+        let connection = Connection::open(&path)
+            .with_context(|| format!("Cannot open key-value store {}", path.display()))?;
+        connection.busy_timeout(BUSY_TIMEOUT)?;
+        connection
+            .execute(
+                "CREATE TABLE IF NOT EXISTS spin_key_value (key TEXT PRIMARY KEY, value BLOB NOT NULL)",
+                [],
+            )
+            .with_context(|| format!("Cannot initialize key-value store {}", path.display()))?;
+        Ok(Self {
+            connection: Mutex::new(connection),
+            quota,
+        })
+    }
+}
+
+impl Store for SqliteStore {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        Ok(self
+            .connection
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT value FROM spin_key_value WHERE key = ?1",
+                [key],
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
+    fn set(&self, key: &str, value: &[u8]) -> Result<()> {
+        if let Some(quota) = &self.quota {
+            quota.reserve((key.len() + value.len()) as u64)?;
+        }
+        self.connection.lock().unwrap().execute(
+            "INSERT INTO spin_key_value (key, value) VALUES (?1, ?2)
+             ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+            params![key, value],
+        )?;
+        Ok(())
+    }
+
+    fn delete(&self, key: &str) -> Result<()> {
+        self.connection
+            .lock()
+            .unwrap()
+            .execute("DELETE FROM spin_key_value WHERE key = ?1", [key])?;
+        Ok(())
+    }
+
+    fn exists(&self, key: &str) -> Result<bool> {
+        Ok(self.connection.lock().unwrap().query_row(
+            "SELECT EXISTS(SELECT 1 FROM spin_key_value WHERE key = ?1)",
+            [key],
+            |row| row.get(0),
+        )?)
+    }
+
+    fn list(&self, prefix: &str) -> Result<Vec<String>> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare(
+            "SELECT key FROM spin_key_value WHERE substr(key, 1, length(?1)) = ?1 ORDER BY key",
+        )?;
+        let keys = statement
+            .query_map([prefix], |row| row.get(0))?
+            .collect::<Result<_, _>>()?;
+        Ok(keys)
+    }
+}
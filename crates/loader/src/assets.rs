@@ -2,7 +2,7 @@
 
 use anyhow::{anyhow, bail, Context, Result};
 use sha2::{Digest, Sha256};
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
 use tokio::fs;
 
 /// Create the temporary directory for a component.
@@ -16,22 +16,45 @@ pub(crate) async fn create_dir(base: impl AsRef<Path>, id: &str) -> Result<PathB
     Ok(dir)
 }
 
-/// Get the path of a file relative to a given directory.
+/// Get the path of a file relative to a given directory, with '/' separators.
 pub(crate) fn to_relative(path: impl AsRef<Path>, relative_to: impl AsRef<Path>) -> Result<String> {
-    let rel = path.as_ref().strip_prefix(&relative_to).with_context(|| {
+    // Either path may be in the extended-length form on Windows (`\\?\C:\...`)
+    // while the other is not, which would prevent matching prefixes.
+    let path = dunce::simplified(path.as_ref());
+    let relative_to = dunce::simplified(relative_to.as_ref());
+    let rel = path.strip_prefix(relative_to).with_context(|| {
         format!(
             "Copied path '{}' did not belong with expected prefix '{}'",
-            path.as_ref().display(),
-            relative_to.as_ref().display()
+            path.display(),
+            relative_to.display()
         )
     })?;
 
-    Ok(rel
-        .to_str()
-        .ok_or_else(|| anyhow!("Can't convert '{}' back to relative path", rel.display()))?
-        .to_owned()
-        // TODO: a better way
-        .replace('\\', "/"))
+    to_guest_path(rel)
+}
+
+/// Convert a relative host path to a guest path, which always uses '/'
+/// separators whatever the host platform.
+pub(crate) fn to_guest_path(path: impl AsRef<Path>) -> Result<String> {
+    let segments = path
+        .as_ref()
+        .components()
+        .map(|c| match c {
+            Component::Normal(segment) => segment.to_str().ok_or_else(|| {
+                anyhow!(
+                    "Can't convert '{}' to a guest path",
+                    path.as_ref().display()
+                )
+            }),
+            Component::CurDir => Ok("."),
+            Component::ParentDir => Ok(".."),
+            Component::Prefix(_) | Component::RootDir => bail!(
+                "Can't convert absolute path '{}' to a relative guest path",
+                path.as_ref().display()
+            ),
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(segments.join("/"))
 }
 
 /// Ensure all paths are under a given directory.
@@ -61,9 +84,12 @@ pub(crate) fn ensure_under(desired: impl AsRef<Path>, actual: impl AsRef<Path>)
 
 // Check whether a path is under a given directory.
 pub(crate) fn is_under(desired: impl AsRef<Path>, actual: impl AsRef<Path>) -> bool {
-    // TODO: There should be a more robust check here.
-    actual.as_ref().strip_prefix(desired.as_ref()).is_ok()
-        && !(actual.as_ref().display().to_string().contains(".."))
+    let desired = dunce::simplified(desired.as_ref());
+    let actual = dunce::simplified(actual.as_ref());
+    actual.starts_with(desired)
+        && !actual
+            .components()
+            .any(|c| matches!(c, Component::ParentDir))
 }
 
 lazy_static::lazy_static! {
@@ -112,5 +138,36 @@ mod test {
         assert!(is_under("/foo", "/foo/bar"));
         assert!(!is_under("/foo", "/bar/baz"));
         assert!(!is_under("/foo", "/foo/../bar/baz"));
+        assert!(is_under("/foo", "/foo/bar..baz"));
+    }
+
+    #[test]
+    fn test_to_relative() -> Result<()> {
+        assert_eq!(to_relative("/foo/bar/baz.txt", "/foo")?, "bar/baz.txt");
+        assert!(to_relative("/bar/baz.txt", "/foo").is_err());
+        Ok(())
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_windows_paths() -> Result<()> {
+        assert_eq!(
+            to_relative(r"C:\app\static\a.txt", r"C:\app")?,
+            "static/a.txt"
+        );
+        assert_eq!(
+            to_relative(r"\\?\C:\app\static\a.txt", r"C:\app")?,
+            "static/a.txt"
+        );
+        assert!(is_under(r"C:\app", r"\\?\C:\app\static"));
+        assert!(!is_under(r"C:\app", r"C:\app\..\secrets"));
+
+        // Paths longer than the legacy 260 character limit stay in the
+        // extended-length form.
+        let long_dir = "d".repeat(100);
+        let base = format!(r"\\?\C:\app\{0}\{0}", long_dir);
+        let file = format!(r"{0}\{1}\{1}.txt", base, long_dir);
+        assert_eq!(to_relative(&file, &base)?, format!("{0}/{0}.txt", long_dir));
+        Ok(())
     }
 }
@@ -115,6 +115,18 @@ impl Copier {
             to.display()
         );
         fs::create_dir_all(to.parent().expect("Cannot copy to file '/'")).await?;
+        if let Some(cached) = self.reader.get_cached_parcel(&p.sha256).await? {
+            fs::copy(&cached, &to).await.with_context(|| {
+                anyhow!(
+                    "Failed to copy cached asset parcel '{}@{}' to {}",
+                    self.id,
+                    p.sha256,
+                    to.display()
+                )
+            })?;
+            change_file_permission(&to, allow_transient_write).await?;
+            return Ok(());
+        }
         let mut stream = self
             .reader
             .get_parcel_stream(&p.sha256)
@@ -0,0 +1,181 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{bail, Context, Result};
+use futures::{Stream, TryStreamExt};
+use sha2::{Digest, Sha256};
+use tokio::io::AsyncWriteExt;
+
+/// The default directory pulled parcels are cached in.
+pub fn default_dir() -> Option<PathBuf> {
+    dirs::cache_dir().map(|dir| dir.join("spin").join("parcels"))
+}
+
+/// Parcels pulled from Bindle servers, persisted in a directory and keyed by
+/// their SHA256 digest, so that they are pulled once across runs and
+/// applications.
+#[derive(Clone, Debug)]
+pub struct ParcelCache {
+    path: PathBuf,
+}
+
+/// A parcel in the cache.
+#[derive(Clone, Debug)]
+pub struct ParcelCacheEntry {
+    /// The SHA256 digest of the parcel.
+    pub sha256: String,
+    /// The size of the parcel, in bytes.
+    pub bytes: u64,
+    /// When the parcel was pulled into the cache.
+    pub cached_at: SystemTime,
+}
+
+impl ParcelCache {
+    /// Creates a cache persisting parcels in the given directory.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// The directory parcels are persisted in.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the path of the cached parcel with the given digest, if it is
+    /// in the cache.
+    pub(crate) fn get(&self, sha256: &str) -> Option<PathBuf> {
+        let path = self.entry(sha256).ok()?;
+        path.is_file().then(|| path)
+    }
+
+    /// Persists the content of a parcel from a stream, and returns its path.
+    /// The content is checked against the digest, so that the cache never
+    /// holds a parcel under another digest than its own.
+    pub(crate) async fn put(
+        &self,
+        sha256: &str,
+        mut content: impl Stream<Item = Result<bytes::Bytes>> + Unpin,
+    ) -> Result<PathBuf> {
+        let path = self.entry(sha256)?;
+        tokio::fs::create_dir_all(&self.path)
+            .await
+            .with_context(|| format!("Cannot create parcel cache directory {:?}", self.path))?;
+        // Written to a temporary file first, so that other processes never
+        // read a partial entry.
+        let temp = tempfile::NamedTempFile::new_in(&self.path)?;
+        let mut file = tokio::fs::File::from_std(temp.reopen()?);
+        let mut hasher = Sha256::new();
+        while let Some(chunk) = content.try_next().await? {
+            hasher.update(&chunk);
+            file.write_all(&chunk).await?;
+        }
+        file.flush().await?;
+        drop(file);
+        let digest = format!("{:x}", hasher.finalize());
+        if digest != sha256 {
+            bail!(
+                "Parcel {} has content with digest {}: it was not cached",
+                sha256,
+                digest
+            );
+        }
+        temp.persist(&path)
+            .with_context(|| format!("Cannot write parcel to cache {:?}", path))?;
+        Ok(path)
+    }
+
+    /// Lists the cached parcels.
+    pub fn list(&self) -> Result<Vec<ParcelCacheEntry>> {
+        let entries = match std::fs::read_dir(&self.path) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("Cannot read parcel cache directory {:?}", self.path))
+            }
+        };
+        let mut parcels = vec![];
+        for entry in entries {
+            let entry = entry?;
+            let sha256 = entry.file_name().to_string_lossy().into_owned();
+            // Temporary files of entries being written are not parcels yet.
+            if !is_sha256(&sha256) {
+                continue;
+            }
+            let metadata = entry.metadata()?;
+            parcels.push(ParcelCacheEntry {
+                sha256,
+                bytes: metadata.len(),
+                cached_at: metadata.modified()?,
+            });
+        }
+        parcels.sort_by(|a, b| a.cached_at.cmp(&b.cached_at));
+        Ok(parcels)
+    }
+
+    /// Removes the parcels cached longer ago than the given age, or all of
+    /// them, and returns the removed parcels.
+    pub fn prune(&self, older_than: Option<Duration>) -> Result<Vec<ParcelCacheEntry>> {
+        let now = SystemTime::now();
+        let mut removed = vec![];
+        for parcel in self.list()? {
+            let age = now.duration_since(parcel.cached_at).unwrap_or_default();
+            if older_than.map_or(true, |older_than| age > older_than) {
+                let path = self.path.join(&parcel.sha256);
+                std::fs::remove_file(&path)
+                    .with_context(|| format!("Cannot remove cached parcel {:?}", path))?;
+                removed.push(parcel);
+            }
+        }
+        Ok(removed)
+    }
+
+    fn entry(&self, sha256: &str) -> Result<PathBuf> {
+        // The digest comes from the invoice, so it must not be able to
+        // name a file outside the cache.
+        if !is_sha256(sha256) {
+            bail!("Invalid parcel digest {:?}", sha256);
+        }
+        Ok(self.path.join(sha256))
+    }
+}
+
+fn is_sha256(s: &str) -> bool {
+    s.len() == 64 && s.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stream(content: &'static [u8]) -> impl Stream<Item = Result<bytes::Bytes>> + Unpin {
+        futures::stream::iter(vec![Ok(bytes::Bytes::from_static(content))])
+    }
+
+    #[tokio::test]
+    async fn test_parcels_are_cached_by_digest() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let cache = ParcelCache::new(dir.path().join("parcels"));
+        assert!(cache.list()?.is_empty());
+
+        let sha256 = format!("{:x}", Sha256::digest(b"parcel"));
+        assert!(cache.get(&sha256).is_none());
+        let path = cache.put(&sha256, stream(b"parcel")).await?;
+        assert_eq!(cache.get(&sha256), Some(path.clone()));
+        assert_eq!(std::fs::read(&path)?, b"parcel");
+        assert_eq!(cache.list()?.len(), 1);
+
+        // Content that does not match its digest is not cached.
+        let other = format!("{:x}", Sha256::digest(b"other"));
+        assert!(cache.put(&other, stream(b"tampered")).await.is_err());
+        assert!(cache.get(&other).is_none());
+        assert!(cache.get("../../etc/passwd").is_none());
+
+        assert!(cache.prune(Some(Duration::from_secs(3600)))?.is_empty());
+        assert_eq!(cache.prune(None)?.len(), 1);
+        assert!(cache.get(&sha256).is_none());
+        Ok(())
+    }
+}
@@ -45,6 +45,24 @@ pub struct RawWasmConfig {
     pub files: Option<String>,
     /// Optional list of HTTP hosts the component is allowed to connect.
     pub allowed_http_hosts: Option<Vec<String>>,
+    /// Optional list of blob store containers the component is allowed to access.
+    pub allowed_blob_containers: Option<Vec<String>>,
+    /// Optional list of database hosts the component is allowed to connect to.
+    pub allowed_database_hosts: Option<Vec<String>>,
+    /// Optional list of key-value stores the component is allowed to access.
+    pub key_value_stores: Option<Vec<String>>,
+    /// Optional list of JWT issuers the component is allowed to issue tokens with.
+    pub allowed_jwt_issuers: Option<Vec<String>>,
+    /// Optional list of cryptography keys the component is allowed to use.
+    pub allowed_crypto_keys: Option<Vec<String>>,
+    /// When the module of the component is compiled. Defaults to eager.
+    pub load: Option<spin_manifest::LoadPolicy>,
+    /// Limits on the resources the component uses.
+    pub limits: Option<spin_manifest::ComponentLimits>,
+    /// The least severe output of the component that is logged.
+    pub log_level: Option<spin_manifest::LogLevel>,
+    /// Configuration for host components, by host component name.
+    pub host_config: Option<HashMap<String, toml::Value>>,
     /// Environment variables to be mapped inside the Wasm module at runtime.
     pub environment: Option<HashMap<String, String>>,
 }
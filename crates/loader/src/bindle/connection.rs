@@ -5,6 +5,18 @@ use bindle::client::{
     Client, ClientBuilder,
 };
 
+/// The environment variable holding the username for basic http auth to the
+/// Bindle server.
+pub const BINDLE_USERNAME_ENV: &str = "BINDLE_USERNAME";
+/// The environment variable holding the password for basic http auth to the
+/// Bindle server.
+pub const BINDLE_PASSWORD_ENV: &str = "BINDLE_PASSWORD";
+/// The environment variable holding a bearer token for the Bindle server.
+pub const BINDLE_TOKEN_ENV: &str = "BINDLE_TOKEN";
+/// The environment variable which, set to `true`, ignores certificate errors
+/// from the Bindle server.
+pub const BINDLE_INSECURE_ENV: &str = "SPIN_BINDLE_INSECURE";
+
 /// BindleConnectionInfo holds the details of a connection to a
 /// Bindle server, including url, insecure configuration and an
 /// auth token manager
@@ -39,6 +51,44 @@ impl BindleConnectionInfo {
         }
     }
 
+    /// Generates a new BindleConnectionInfo instance using the provided
+    /// base_url, allow_insecure setting and a bearer token, such as one
+    /// issued by the identity provider of the Bindle server
+    pub fn with_token<I: Into<String>>(base_url: I, allow_insecure: bool, token: String) -> Self {
+        let token_manager: Box<dyn TokenManager + Send + Sync> = Box::new(BearerToken { token });
+        Self {
+            base_url: base_url.into(),
+            allow_insecure,
+            token_manager: AnyAuth {
+                token_manager: Arc::new(token_manager),
+            },
+        }
+    }
+
+    /// Generates a new BindleConnectionInfo instance for the given base_url,
+    /// with the credentials and insecure setting in the `BINDLE_TOKEN`, or
+    /// `BINDLE_USERNAME` and `BINDLE_PASSWORD`, and `SPIN_BINDLE_INSECURE`
+    /// environment variables
+    pub fn from_env<I: Into<String>>(base_url: I) -> Self {
+        let allow_insecure = std::env::var(BINDLE_INSECURE_ENV)
+            .map(|v| v.trim() == "true")
+            .unwrap_or(false);
+        match std::env::var(BINDLE_TOKEN_ENV) {
+            Ok(token) if !token.is_empty() => Self::with_token(base_url, allow_insecure, token),
+            _ => Self::new(
+                base_url,
+                allow_insecure,
+                std::env::var(BINDLE_USERNAME_ENV).ok(),
+                std::env::var(BINDLE_PASSWORD_ENV).ok(),
+            ),
+        }
+    }
+
+    /// The URL of the Bindle server
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
     /// Returns a client based on this instance's configuration
     pub fn client(&self) -> bindle::client::Result<Client<AnyAuth>> {
         let builder = ClientBuilder::default()
@@ -64,3 +114,18 @@ impl TokenManager for AnyAuth {
         self.token_manager.apply_auth_header(builder).await
     }
 }
+
+/// Applies a bearer token to requests
+struct BearerToken {
+    token: String,
+}
+
+#[async_trait::async_trait]
+impl TokenManager for BearerToken {
+    async fn apply_auth_header(
+        &self,
+        builder: reqwest::RequestBuilder,
+    ) -> bindle::client::Result<reqwest::RequestBuilder> {
+        Ok(builder.bearer_auth(&self.token))
+    }
+}
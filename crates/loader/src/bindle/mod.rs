@@ -4,9 +4,13 @@
 
 /// Module to prepare the assets for the components of an application.
 mod assets;
+/// Local cache of the parcels pulled from Bindle servers.
+pub mod cache;
 /// Configuration representation for a Spin application in Bindle.
 pub mod config;
 mod connection;
+/// Verification of the signatures of invoices.
+pub mod signature;
 /// Bindle helper functions.
 mod utils;
 
@@ -15,38 +19,56 @@ use crate::{
         config::{RawAppManifest, RawComponentManifest},
         utils::{find_manifest, parcels_in_group},
     },
-    validation::validate_allowed_http_hosts,
+    validation::{
+        validate_allowed_database_hosts, validate_allowed_http_hosts, validate_key_value_stores,
+    },
 };
 use anyhow::{anyhow, Context, Result};
 use bindle::Invoice;
-pub use connection::BindleConnectionInfo;
+pub use cache::ParcelCache;
+pub use connection::{
+    BindleConnectionInfo, BINDLE_INSECURE_ENV, BINDLE_PASSWORD_ENV, BINDLE_TOKEN_ENV,
+    BINDLE_USERNAME_ENV,
+};
 use futures::future;
+pub use signature::SignaturePolicy;
 use spin_manifest::{
-    Application, ApplicationInformation, ApplicationOrigin, CoreComponent, ModuleSource,
-    SpinVersion, WasmConfig,
+    Application, ApplicationInformation, ApplicationOrigin, ApplicationTrigger, CoreComponent,
+    HttpHandler, ModuleSource, SpinVersion, WasmConfig,
 };
 use std::{path::Path, sync::Arc};
 use tracing::log;
 pub(crate) use utils::BindleReader;
 pub use utils::SPIN_MANIFEST_MEDIA_TYPE;
 
-/// Given a Bindle server URL and reference, pull it, expand its assets locally, and get a
+/// Given a Bindle server connection and reference, pull it, expand its assets locally, and get a
 /// prepared application configuration consumable by a Spin execution context.
 /// If a directory is provided, use it as the base directory to expand the assets,
-/// otherwise create a new temporary directory.
+/// otherwise create a new temporary directory. The signatures of the invoice are
+/// checked according to the given policy. If a parcel cache is provided, parcels
+/// are read from it when present, and added to it when pulled.
 pub async fn from_bindle(
     id: &str,
-    url: &str,
+    connection_info: &BindleConnectionInfo,
     base_dst: impl AsRef<Path>,
     allow_transient_write: bool,
+    signatures: &SignaturePolicy,
+    parcel_cache: Option<ParcelCache>,
 ) -> Result<Application> {
-    // TODO
-    // Handle Bindle authentication.
-    let connection_info = BindleConnectionInfo::new(url, false, None, None);
+    let url = connection_info.base_url();
+    crate::offline::ensure_online(format!("load application {} from {}", id, url))?;
     let client = connection_info.client()?;
-    let reader = BindleReader::remote(&client, &id.parse()?);
+    let reader = BindleReader::remote(&client, &id.parse()?).with_cache(parcel_cache);
 
-    prepare(id, url, &reader, base_dst, allow_transient_write).await
+    prepare(
+        id,
+        url,
+        &reader,
+        base_dst,
+        allow_transient_write,
+        signatures,
+    )
+    .await
 }
 
 /// Converts a Bindle invoice into Spin configuration.
@@ -56,12 +78,15 @@ async fn prepare(
     reader: &BindleReader,
     base_dst: impl AsRef<Path>,
     allow_transient_write: bool,
+    signatures: &SignaturePolicy,
 ) -> Result<Application> {
-    // First, get the invoice from the Bindle server.
+    // First, get the invoice from the Bindle server, and check it is signed
+    // as required before trusting its parcels.
     let invoice = reader
         .get_invoice()
         .await
         .with_context(|| anyhow!("Failed to load invoice '{}' from '{}'", id, url))?;
+    signatures.check(&invoice).await?;
 
     // Then, reconstruct the application manifest from the parcels.
     let mut raw: RawAppManifest =
@@ -71,6 +96,8 @@ async fn prepare(
     let mut config_root = raw.config.take().unwrap_or_default();
     for component in &mut raw.components {
         validate_allowed_http_hosts(&component.wasm.allowed_http_hosts)?;
+        validate_allowed_database_hosts(&component.wasm.allowed_database_hosts)?;
+        validate_key_value_stores(&component.wasm.key_value_stores)?;
         if let Some(config) = component.config.take() {
             let path = component.id.clone().try_into().with_context(|| {
                 format!("component ID {:?} not a valid config path", component.id)
@@ -80,7 +107,8 @@ async fn prepare(
     }
     let config_resolver = Some(Arc::new(spin_config::Resolver::new(config_root)?));
 
-    let info = info(&raw, &invoice, url);
+    let mut info = info(&raw, &invoice, url);
+    prepare_templates(&mut info, &invoice, reader, &base_dst).await?;
     log::trace!("Application information from bindle: {:?}", info);
     let component_triggers = raw
         .components
@@ -141,10 +169,27 @@ async fn core(
     };
     let environment = raw.wasm.environment.unwrap_or_default();
     let allowed_http_hosts = raw.wasm.allowed_http_hosts.unwrap_or_default();
+    let allowed_blob_containers = raw.wasm.allowed_blob_containers.unwrap_or_default();
+    let allowed_database_hosts = raw.wasm.allowed_database_hosts.unwrap_or_default();
+    let key_value_stores = raw.wasm.key_value_stores.unwrap_or_default();
+    let allowed_jwt_issuers = raw.wasm.allowed_jwt_issuers.unwrap_or_default();
+    let allowed_crypto_keys = raw.wasm.allowed_crypto_keys.unwrap_or_default();
+    let load = raw.wasm.load.unwrap_or_default();
+    let limits = raw.wasm.limits.unwrap_or_default();
+    let host_config = raw.wasm.host_config.unwrap_or_default();
     let wasm = WasmConfig {
         environment,
         mounts,
         allowed_http_hosts,
+        allowed_blob_containers,
+        allowed_database_hosts,
+        key_value_stores,
+        allowed_jwt_issuers,
+        allowed_crypto_keys,
+        load,
+        limits,
+        log_level: raw.wasm.log_level,
+        host_config,
     };
     Ok(CoreComponent {
         source,
@@ -154,6 +199,51 @@ async fn core(
     })
 }
 
+/// Writes the templates of native routes, published as parcels, to the base
+/// directory, and points the routes at them.
+async fn prepare_templates(
+    info: &mut ApplicationInformation,
+    invoice: &Invoice,
+    reader: &BindleReader,
+    base_dst: impl AsRef<Path>,
+) -> Result<()> {
+    let http = match &mut info.trigger {
+        ApplicationTrigger::Http(http) => http,
+        _ => return Ok(()),
+    };
+    for route in &mut http.routes {
+        let template = match &mut route.handler {
+            HttpHandler::Template(template) => template,
+            _ => continue,
+        };
+        let id = template.template.to_string_lossy().into_owned();
+        let label = invoice
+            .parcel
+            .iter()
+            .flatten()
+            .map(|parcel| &parcel.label)
+            .find(|label| label.sha256 == id)
+            .with_context(|| {
+                format!("Template parcel {} of route {} not found", id, route.route)
+            })?;
+        // The template keeps its file name, from which its content type is
+        // inferred.
+        let name = Path::new(&label.name)
+            .file_name()
+            .with_context(|| format!("Invalid template parcel name {:?}", label.name))?;
+        let dir = base_dst.as_ref().join("templates").join(&id);
+        tokio::fs::create_dir_all(&dir)
+            .await
+            .with_context(|| format!("Error creating template directory {}", dir.display()))?;
+        let path = dir.join(name);
+        tokio::fs::write(&path, reader.get_parcel(&id).await?)
+            .await
+            .with_context(|| format!("Error writing template {}", path.display()))?;
+        template.template = path;
+    }
+    Ok(())
+}
+
 /// Converts the raw application manifest from the bindle invoice into the
 /// standard application configuration.
 fn info(raw: &RawAppManifest, invoice: &Invoice, url: &str) -> ApplicationInformation {
@@ -0,0 +1,172 @@
+use anyhow::{anyhow, bail, Context, Result};
+use bindle::{
+    invoice::{signature::KeyRing, VerificationStrategy},
+    Invoice,
+};
+use std::path::{Path, PathBuf};
+use tracing::log;
+
+const KEYRING_FILE: &str = "keyring.toml";
+
+/// Environment variable set to `true` to require the invoices of bindles
+/// loaded by the process and its children to be signed by a trusted key.
+pub const REQUIRE_SIGNED_ENV: &str = "SPIN_REQUIRE_SIGNED";
+
+/// The path of the keyring of the keys trusted to sign bindles.
+pub fn keyring_path() -> Result<PathBuf> {
+    let dir = dirs::config_dir().ok_or_else(|| anyhow!("Cannot find the config directory"))?;
+    Ok(dir.join("spin").join(KEYRING_FILE))
+}
+
+/// Loads a keyring, which is empty if it does not exist.
+pub async fn load_keyring(path: &Path) -> Result<KeyRing> {
+    match tokio::fs::read_to_string(path).await {
+        Ok(contents) => {
+            toml::from_str(&contents).with_context(|| format!("Invalid keyring {}", path.display()))
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(KeyRing::default()),
+        Err(e) => Err(e).with_context(|| format!("Cannot read keyring {}", path.display())),
+    }
+}
+
+/// Saves a keyring.
+pub async fn save_keyring(path: &Path, keyring: &KeyRing) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+    let contents = toml::to_string_pretty(keyring)?;
+    tokio::fs::write(path, contents)
+        .await
+        .with_context(|| format!("Cannot write keyring {}", path.display()))
+}
+
+/// How the signatures of the invoices of loaded bindles are checked.
+#[derive(Clone, Debug, Default)]
+pub struct SignaturePolicy {
+    /// The keyring of trusted keys, defaulting to the one of the local user.
+    pub keyring: Option<PathBuf>,
+    /// Fail on invoices that are not signed by a trusted key, rather than
+    /// only warning about them.
+    pub require_signed: bool,
+}
+
+impl SignaturePolicy {
+    /// The policy of the current process, requiring signatures if
+    /// `SPIN_REQUIRE_SIGNED` is set.
+    pub fn from_env() -> Self {
+        let require_signed = std::env::var(REQUIRE_SIGNED_ENV)
+            .map(|v| v == "true" || v == "1")
+            .unwrap_or_default();
+        Self {
+            keyring: None,
+            require_signed,
+        }
+    }
+
+    /// Checks the signatures of an invoice. Invoices signed by a trusted key
+    /// must verify, whatever the policy; unsigned invoices, and invoices
+    /// signed only by unknown keys, fail if signatures are required.
+    pub async fn check(&self, invoice: &Invoice) -> Result<()> {
+        let id = &invoice.bindle.id;
+        let signatures = invoice.signature.as_deref().unwrap_or_default();
+        if signatures.is_empty() {
+            if self.require_signed {
+                bail!("Bindle {} is not signed, and signatures are required", id);
+            }
+            log::warn!("Bindle {} is not signed", id);
+            return Ok(());
+        }
+
+        let keyring_path = match &self.keyring {
+            Some(path) => path.clone(),
+            None => keyring_path()?,
+        };
+        let keyring = load_keyring(&keyring_path).await?;
+        let trusted = signatures
+            .iter()
+            .any(|s| keyring.key.iter().any(|k| k.key == s.key));
+        if !trusted {
+            if self.require_signed {
+                bail!(
+                    "Bindle {} is not signed by a key in {}, and signatures are required",
+                    id,
+                    keyring_path.display()
+                );
+            }
+            log::warn!(
+                "Bindle {} is signed by keys that are not in {}",
+                id,
+                keyring_path.display()
+            );
+            return Ok(());
+        }
+
+        VerificationStrategy::CreativeIntegrity
+            .verify(invoice.clone(), &keyring)
+            .with_context(|| {
+                format!(
+                    "The signature of bindle {} does not verify: it may have been tampered with",
+                    id
+                )
+            })?;
+        log::trace!("Verified the signature of bindle {}", id);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bindle::invoice::{
+        signature::{KeyEntry, SecretKeyEntry, SignatureRole},
+        Signed,
+    };
+
+    const INVOICE: &str = r#"
+bindleVersion = "1.0.0"
+
+[bindle]
+name = "spin-hello-world"
+version = "1.0.0"
+"#;
+
+    #[tokio::test]
+    async fn test_check() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let keyring = dir.path().join("keyring.toml");
+        let key = SecretKeyEntry::new("test".to_owned(), vec![SignatureRole::Creator]);
+        save_keyring(
+            &keyring,
+            &KeyRing::new(vec![KeyEntry::try_from(key.clone())?]),
+        )
+        .await?;
+        let lenient = SignaturePolicy {
+            keyring: Some(keyring.clone()),
+            require_signed: false,
+        };
+        let strict = SignaturePolicy {
+            require_signed: true,
+            ..lenient.clone()
+        };
+
+        let unsigned: Invoice = toml::from_str(INVOICE)?;
+        lenient.check(&unsigned).await?;
+        assert!(strict.check(&unsigned).await.is_err());
+
+        let signed =
+            bindle::invoice::sign(unsigned.clone(), vec![(SignatureRole::Creator, &key)])?.signed();
+        lenient.check(&signed).await?;
+        strict.check(&signed).await?;
+
+        let mut tampered = signed.clone();
+        tampered.bindle.id = "spin-hello-world/1.0.1".parse()?;
+        assert!(lenient.check(&tampered).await.is_err());
+
+        let other = SecretKeyEntry::new("other".to_owned(), vec![SignatureRole::Creator]);
+        let unknown =
+            bindle::invoice::sign(unsigned, vec![(SignatureRole::Creator, &other)])?.signed();
+        lenient.check(&unknown).await?;
+        assert!(strict.check(&unknown).await.is_err());
+        Ok(())
+    }
+}
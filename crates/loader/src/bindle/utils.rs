@@ -4,11 +4,16 @@ use anyhow::{anyhow, bail, Context, Error, Result};
 use bindle::{client::Client, standalone::StandaloneRead, Id, Invoice, Label, Parcel};
 use futures::{Stream, StreamExt, TryStreamExt};
 use itertools::Itertools;
-use std::{fmt::Debug, path::Path, sync::Arc};
+use std::{
+    fmt::Debug,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 use tokio::fs;
 use tokio_util::codec::{BytesCodec, FramedRead};
+use tracing::log;
 
-use super::connection::AnyAuth;
+use super::{cache::ParcelCache, connection::AnyAuth};
 
 static EMPTY: &Vec<bindle::Parcel> = &vec![];
 
@@ -97,11 +102,17 @@ trigger     = "http"
 #[derive(Clone, Debug)]
 pub(crate) struct BindleReader {
     inner: BindleReaderInner,
+    cache: Option<ParcelCache>,
 }
 
 impl BindleReader {
     /// Gets the content of a parcel from the bindle source.
     pub(crate) async fn get_parcel(&self, id: &str) -> Result<Vec<u8>> {
+        if let Some(path) = self.get_cached_parcel(id).await? {
+            return fs::read(&path)
+                .await
+                .with_context(|| anyhow!("Error reading cached parcel {}", path.display()));
+        }
         match &self.inner {
             BindleReaderInner::Remote(c, bindle_id) => c
                 .get_parcel(bindle_id, id)
@@ -150,6 +161,29 @@ impl BindleReader {
         }
     }
 
+    /// Gets the path of a parcel in the local parcel cache, pulling it into
+    /// the cache first if needed. Returns `None` if parcels are not cached
+    /// for this source.
+    pub(crate) async fn get_cached_parcel(&self, id: &str) -> Result<Option<PathBuf>> {
+        let (cache, client, bindle_id) = match (&self.cache, &self.inner) {
+            (Some(cache), BindleReaderInner::Remote(client, bindle_id)) => {
+                (cache, client, bindle_id)
+            }
+            _ => return Ok(None),
+        };
+        if let Some(path) = cache.get(id) {
+            log::trace!("Using cached parcel {}", id);
+            return Ok(Some(path));
+        }
+        let stream = client
+            .get_parcel_stream(bindle_id, id)
+            .await
+            .with_context(|| anyhow!("Error fetching remote parcel {}@{}", bindle_id, id))?
+            .map_err(Error::from);
+        let path = cache.put(id, stream.boxed()).await?;
+        Ok(Some(path))
+    }
+
     /// Get the invoice from the bindle source
     pub(crate) async fn get_invoice(&self) -> Result<Invoice> {
         match &self.inner {
@@ -178,14 +212,21 @@ impl BindleReader {
     pub(crate) fn remote(c: &Client<AnyAuth>, id: &Id) -> Self {
         Self {
             inner: BindleReaderInner::Remote(c.clone(), id.clone()),
+            cache: None,
         }
     }
 
+    /// Caches the parcels pulled from a remote source in the given cache.
+    pub(crate) fn with_cache(self, cache: Option<ParcelCache>) -> Self {
+        Self { cache, ..self }
+    }
+
     #[allow(dead_code)]
     pub(crate) async fn standalone(base_path: impl AsRef<Path>, id: &Id) -> Result<Self> {
         let s = StandaloneRead::new(&base_path, id).await?;
         Ok(Self {
             inner: BindleReaderInner::Standalone(Arc::new(s)),
+            cache: None,
         })
     }
 }
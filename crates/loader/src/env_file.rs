@@ -0,0 +1,132 @@
+//! Files of variables in the dotenv format: one `KEY=VALUE` per line.
+
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+
+/// Reads the variables of a file in the dotenv format, in file order.
+pub async fn load(path: &Path) -> Result<Vec<(String, String)>> {
+    let contents = tokio::fs::read_to_string(path)
+        .await
+        .with_context(|| format!("Cannot read variables file {}", path.display()))?;
+    parse(&contents).with_context(|| format!("Invalid variables file {}", path.display()))
+}
+
+/// Sets the given variables in a list of variables, replacing the values of
+/// those already in it.
+pub fn merge(
+    variables: &mut Vec<(String, String)>,
+    updates: impl IntoIterator<Item = (String, String)>,
+) {
+    for (key, value) in updates {
+        match variables.iter_mut().find(|(k, _)| *k == key) {
+            Some(existing) => existing.1 = value,
+            None => variables.push((key, value)),
+        }
+    }
+}
+
+/// Parses variables in the dotenv format. Blank lines and lines starting
+/// with `#` are ignored, and a leading `export ` is allowed. Values may be
+/// quoted: single-quoted values are taken literally, and double-quoted values
+/// may contain `\n`, `\"` and `\\` escapes. Unquoted values end at a ` #`
+/// comment and are trimmed.
+pub fn parse(contents: &str) -> Result<Vec<(String, String)>> {
+    let mut variables = vec![];
+    for (index, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let variable = parse_line(line.strip_prefix("export ").unwrap_or(line))
+            .with_context(|| format!("Invalid variable on line {}", index + 1))?;
+        variables.push(variable);
+    }
+    Ok(variables)
+}
+
+fn parse_line(line: &str) -> Result<(String, String)> {
+    let (key, value) = match line.split_once('=') {
+        Some((key, value)) => (key.trim(), value.trim()),
+        None => bail!("expected KEY=VALUE"),
+    };
+    if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        bail!("invalid name '{}'", key);
+    }
+    let value = if let Some(quoted) = value.strip_prefix('\'') {
+        match quoted.split_once('\'') {
+            Some((value, _)) => value.to_owned(),
+            None => bail!("unterminated quote"),
+        }
+    } else if let Some(quoted) = value.strip_prefix('"') {
+        unescape(quoted)?
+    } else {
+        match value.find(" #") {
+            Some(comment) => value[..comment].trim_end().to_owned(),
+            None => value.to_owned(),
+        }
+    };
+    Ok((key.to_owned(), value))
+}
+
+/// Unescapes a double-quoted value, up to its closing quote.
+fn unescape(quoted: &str) -> Result<String> {
+    let mut value = String::with_capacity(quoted.len());
+    let mut chars = quoted.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => return Ok(value),
+            '\\' => match chars.next() {
+                Some('n') => value.push('\n'),
+                Some(c @ ('"' | '\\')) => value.push(c),
+                Some(c) => {
+                    value.push('\\');
+                    value.push(c);
+                }
+                None => break,
+            },
+            c => value.push(c),
+        }
+    }
+    bail!("unterminated quote")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() -> Result<()> {
+        let variables = parse(
+            r#"
+            # Database settings
+            DB_HOST=db.example.com
+            export DB_PORT = 5432
+            GREETING="Hello,\n\"world\""
+            PATTERN='^\d+$'
+            MODE=debug # not for production
+            EMPTY=
+            "#,
+        )?;
+        let expected = [
+            ("DB_HOST", "db.example.com"),
+            ("DB_PORT", "5432"),
+            ("GREETING", "Hello,\n\"world\""),
+            ("PATTERN", r"^\d+$"),
+            ("MODE", "debug"),
+            ("EMPTY", ""),
+        ];
+        assert_eq!(
+            variables,
+            expected
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect::<Vec<_>>()
+        );
+
+        assert!(parse("NO_VALUE").is_err());
+        assert!(parse("BAD KEY=1").is_err());
+        assert!(parse("OPEN=\"unterminated").is_err());
+        Ok(())
+    }
+}
@@ -12,7 +12,14 @@
 
 mod assets;
 pub mod bindle;
+pub mod env_file;
 pub mod local;
+pub mod locked;
+pub mod offline;
+mod progress;
+pub mod schema;
+pub mod staging;
+pub mod url_source;
 mod validation;
 
 /// Load a Spin application configuration from a spin.toml manifest file.
@@ -25,3 +32,6 @@ pub use crate::assets::file_sha256_digest_string;
 
 /// Maximum number of assets to process in parallel
 pub(crate) const MAX_PARALLEL_ASSET_PROCESSING: usize = 16;
+
+/// Maximum number of components to prepare in parallel
+pub(crate) const MAX_PARALLEL_COMPONENT_PROCESSING: usize = 8;
@@ -1,12 +1,13 @@
 #![deny(missing_docs)]
 
 use crate::assets::{
-    change_file_permission, create_dir, ensure_all_under, ensure_under, to_relative,
+    change_file_permission, create_dir, ensure_all_under, ensure_under, to_guest_path, to_relative,
 };
 use anyhow::{anyhow, bail, ensure, Context, Result};
 use futures::{future, stream, StreamExt};
-use spin_manifest::DirectoryMount;
+use spin_manifest::{ComponentLimits, DirectoryMount};
 use std::{
+    collections::HashMap,
     path::{Path, PathBuf},
     vec,
 };
@@ -15,15 +16,27 @@ use walkdir::WalkDir;
 
 use super::config::{RawDirectoryPlacement, RawFileMount};
 
+/// The number of files a component may mount if its limits set none.
+pub(crate) const DEFAULT_MAX_ASSET_FILES: usize = 10_000;
+
+/// The total size of the files a component may mount if its limits set none.
+pub(crate) const DEFAULT_MAX_ASSET_BYTES: u64 = 1 << 30;
+
 /// Prepare all local assets given a component ID and its file patterns.
 /// This file will copy all assets into a temporary directory as read-only.
+/// With `direct_mounts`, directories placed whole are instead mounted from
+/// the application directory, where the other mounts allow it. The files
+/// must be within the asset limits of the component.
+#[allow(clippy::too_many_arguments)]
 pub(crate) async fn prepare_component(
     raw_mounts: &[RawFileMount],
     src: impl AsRef<Path>,
     base_dst: impl AsRef<Path>,
     id: &str,
     allow_transient_write: bool,
+    direct_mounts: bool,
     exclude_files: &[String],
+    limits: &ComponentLimits,
 ) -> Result<Vec<DirectoryMount>> {
     log::info!(
         "Mounting files from '{}' to '{}'",
@@ -31,12 +44,159 @@ pub(crate) async fn prepare_component(
         base_dst.as_ref().display()
     );
 
-    let files = collect(raw_mounts, exclude_files, src)?;
+    // Walking directories is blocking, so it runs on a blocking thread to let
+    // other components be prepared meanwhile.
+    let (files, direct) = {
+        let (raw_mounts, exclude_files) = (raw_mounts.to_vec(), exclude_files.to_vec());
+        let (src, limits) = (src.as_ref().to_path_buf(), limits.clone());
+        tokio::task::spawn_blocking(move || {
+            plan(&raw_mounts, &exclude_files, &src, direct_mounts, &limits)
+        })
+        .await?
+        .with_context(|| format!("Cannot mount the files of component {}", id))?
+    };
     let host = create_dir(&base_dst, id).await?;
     let guest = "/".to_string();
     copy_all(&files, &host, allow_transient_write).await?;
 
-    Ok(vec![DirectoryMount { guest, host }])
+    let mut mounts = vec![DirectoryMount { guest, host }];
+    for mount in direct {
+        log::info!(
+            "Mounting '{}' directly at '{}'",
+            mount.host.display(),
+            mount.guest
+        );
+        mounts.push(mount);
+    }
+    Ok(mounts)
+}
+
+/// Splits the mounts of a component into the files to copy into its mount
+/// directory and, with `direct_mounts`, the directories to mount directly,
+/// once the files are checked against the limits of the component.
+fn plan(
+    raw_mounts: &[RawFileMount],
+    exclude_files: &[String],
+    rel: &Path,
+    direct_mounts: bool,
+    limits: &ComponentLimits,
+) -> Result<(Vec<FileMount>, Vec<DirectoryMount>)> {
+    if !direct_mounts {
+        let files = collect(raw_mounts, exclude_files, rel)?;
+        check_limits(&files, limits)?;
+        return Ok((files, vec![]));
+    }
+
+    let (patterns, placements) = uncase(raw_mounts);
+    let exclude_patterns = convert_strings_to_glob_patterns(exclude_files)?;
+    let mut staged = get_included_files(collect_patterns(&patterns, rel)?, &exclude_patterns, rel);
+
+    // Mounting a directory directly would expose the files it excludes.
+    let mut direct = vec![];
+    for placement in placements {
+        let files = collect_placements(&[placement.clone()], rel)?;
+        let included = get_included_files(files.clone(), &exclude_patterns, rel);
+        if included.len() == files.len() {
+            direct.push((placement, files));
+        } else {
+            log::info!(
+                "Copying files from '{}' as some are excluded",
+                placement.source.display()
+            );
+            staged.extend(included);
+        }
+    }
+
+    // A directory mounted directly hides the copied files under its guest
+    // path, so such directories are copied too.
+    while let Some(index) = direct.iter().position(|(placement, _)| {
+        let guest = placement
+            .destination
+            .strip_prefix("/")
+            .unwrap_or(&placement.destination);
+        staged
+            .iter()
+            .any(|f| Path::new(&f.relative_dst).starts_with(guest))
+    }) {
+        let (placement, files) = direct.remove(index);
+        log::info!(
+            "Copying files from '{}' as other files are mounted under {}",
+            placement.source.display(),
+            placement.destination.display()
+        );
+        staged.extend(files);
+    }
+
+    check_limits(
+        staged
+            .iter()
+            .chain(direct.iter().flat_map(|(_, files)| files)),
+        limits,
+    )?;
+    let direct = direct
+        .into_iter()
+        .map(|(placement, _)| DirectoryMount {
+            guest: placement.destination.to_string_lossy().to_string(),
+            host: rel.join(&placement.source),
+        })
+        .collect();
+    Ok((staged, direct))
+}
+
+/// Checks that the files mounted in a component are within its limits, so
+/// that patterns matching far more than intended, such as `**/*` in a
+/// directory with `node_modules`, fail before the files are staged. The error
+/// names the top-level directory holding the most files.
+fn check_limits<'a>(
+    files: impl IntoIterator<Item = &'a FileMount>,
+    limits: &ComponentLimits,
+) -> Result<()> {
+    let max_files = limits.max_asset_files.unwrap_or(DEFAULT_MAX_ASSET_FILES);
+    let max_bytes = limits.max_asset_bytes.unwrap_or(DEFAULT_MAX_ASSET_BYTES);
+
+    let (mut count, mut bytes) = (0usize, 0u64);
+    let mut dirs: HashMap<&str, (usize, u64)> = HashMap::new();
+    for file in files {
+        let size = std::fs::metadata(&file.src)
+            .with_context(|| format!("Failed to read metadata of {}", file.src.display()))?
+            .len();
+        count += 1;
+        bytes += size;
+        if let Some((dir, _)) = file.relative_dst.split_once('/') {
+            let entry = dirs.entry(dir).or_default();
+            entry.0 += 1;
+            entry.1 += size;
+        }
+    }
+
+    let hint = |by_count: bool| {
+        dirs.iter()
+            .max_by_key(|(_, (n, size))| if by_count { *n as u64 } else { *size })
+            .map(|(dir, (n, size))| {
+                format!(
+                    "; the largest directory is {}/ with {} files ({} bytes)",
+                    dir, n, size
+                )
+            })
+            .unwrap_or_default()
+    };
+    if count > max_files {
+        bail!(
+            "The files patterns match {} files, more than the limit of {}{}. Exclude files with `exclude_files`, or raise `max_asset_files` in the component `limits`",
+            count,
+            max_files,
+            hint(true)
+        );
+    }
+    if bytes > max_bytes {
+        bail!(
+            "The files patterns match {} bytes, more than the limit of {}{}. Exclude files with `exclude_files`, or raise `max_asset_bytes` in the component `limits`",
+            bytes,
+            max_bytes,
+            hint(false)
+        );
+    }
+    Ok(())
 }
 
 /// A file that a component requires to be present at runtime.
@@ -57,25 +217,108 @@ impl FileMount {
 
     fn from_exact(src: impl AsRef<Path>, dest: impl AsRef<Path>) -> Result<Self> {
         let src = src.as_ref().to_path_buf();
-        let relative_dst = dest.as_ref().to_string_lossy().to_string();
+        let relative_dst = to_guest_path(dest)?;
         Ok(Self { src, relative_dst })
     }
 }
 
+/// The number of files above which a single file pattern is reported as
+/// matching more than was likely intended.
+pub const MANY_FILES: usize = 1_000;
+
+/// The files matched by one entry of the `files` of a component.
+#[derive(Debug, Clone)]
+pub struct MountMatches {
+    /// The entry: a file pattern, or `source -> destination` for a placed
+    /// directory.
+    pub mount: String,
+    /// The matched files that are mounted.
+    pub included: Vec<FileMount>,
+    /// The matched files that are excluded, with the `exclude_files`
+    /// pattern that excludes each.
+    pub excluded: Vec<(FileMount, String)>,
+}
+
+impl MountMatches {
+    fn new(
+        mount: String,
+        files: Vec<FileMount>,
+        exclude_patterns: &[glob::Pattern],
+        rel: impl AsRef<Path>,
+    ) -> Self {
+        let (mut included, mut excluded) = (vec![], vec![]);
+        for file in files {
+            match exclusion(&file, exclude_patterns, &rel) {
+                Some(pattern) => excluded.push((file, pattern.to_string())),
+                None => included.push(file),
+            }
+        }
+        Self {
+            mount,
+            included,
+            excluded,
+        }
+    }
+
+    /// A warning if the entry matches no files, only excluded files, or more
+    /// than `MANY_FILES` files.
+    pub fn warning(&self) -> Option<String> {
+        if self.included.is_empty() && self.excluded.is_empty() {
+            Some(format!("'{}' matches no files", self.mount))
+        } else if self.included.is_empty() {
+            Some(format!(
+                "'{}' only matches files excluded by exclude_files",
+                self.mount
+            ))
+        } else if self.included.len() > MANY_FILES {
+            Some(format!(
+                "'{}' matches {} files: check that it does not include dependencies or build output, such as node_modules, or exclude them with exclude_files",
+                self.mount,
+                self.included.len()
+            ))
+        } else {
+            None
+        }
+    }
+}
+
 /// Generate a vector of file mounts for a component given all its file patterns.
 pub fn collect(
     raw_mounts: &[RawFileMount],
     exclude_files: &[String],
     rel: impl AsRef<Path>,
 ) -> Result<Vec<FileMount>> {
-    let (patterns, placements) = uncase(raw_mounts);
+    Ok(collect_matches(raw_mounts, exclude_files, rel)?
+        .into_iter()
+        .flat_map(|m| m.included)
+        .collect())
+}
 
-    let pattern_files = collect_patterns(&patterns, &rel)?;
-    let placement_files = collect_placements(&placements, &rel)?;
-    let all_files = [pattern_files, placement_files].concat();
+/// Match the file patterns of a component, reporting the files that each
+/// entry matches, patterns first and then placed directories.
+pub fn collect_matches(
+    raw_mounts: &[RawFileMount],
+    exclude_files: &[String],
+    rel: impl AsRef<Path>,
+) -> Result<Vec<MountMatches>> {
+    let (patterns, placements) = uncase(raw_mounts);
+    let exclude_patterns = convert_strings_to_glob_patterns(exclude_files)?;
 
-    let exclude_patterns = convert_strings_to_glob_patterns(exclude_files, &rel)?;
-    Ok(get_included_files(all_files, &exclude_patterns))
+    let mut matches = vec![];
+    for pattern in patterns {
+        let files = collect_patterns(std::slice::from_ref(&pattern), &rel)?;
+        matches.push(MountMatches::new(pattern, files, &exclude_patterns, &rel));
+    }
+    for placement in placements {
+        let files = collect_placements(std::slice::from_ref(&placement), &rel)?;
+        let mount = format!(
+            "{} -> {}",
+            placement.source.display(),
+            placement.destination.display()
+        );
+        matches.push(MountMatches::new(mount, files, &exclude_patterns, &rel));
+    }
+    Ok(matches)
 }
 
 fn collect_placements(
@@ -171,20 +414,83 @@ fn collect_placement(
 }
 
 /// Generate a vector of file mounts given a file pattern.
+///
+/// Rather than globbing the absolute pattern, which fails for application
+/// directories containing glob characters or in the extended-length form on
+/// Windows, this walks the directory the pattern is rooted at and matches
+/// the relative, '/'-separated path of each file.
 fn collect_pattern(pattern: &str, rel: impl AsRef<Path>) -> Result<Vec<FileMount>> {
-    let abs = rel.as_ref().join(pattern);
-    log::trace!("Resolving asset file pattern '{:?}'", abs);
+    log::trace!(
+        "Resolving asset file pattern '{}' in '{}'",
+        pattern,
+        rel.as_ref().display()
+    );
 
-    let matches = glob::glob(&abs.to_string_lossy())?;
-    let specifiers = matches
-        .into_iter()
-        .map(|path| FileMount::from(path?, &rel))
-        .collect::<Result<Vec<_>>>()?;
-    let files: Vec<_> = specifiers.into_iter().filter(|s| s.src.is_file()).collect();
+    if is_absolute_guest_path(pattern) || Path::new(pattern).is_absolute() {
+        bail!("Cannot mount {}: file patterns must be relative", pattern);
+    }
+    let segments = pattern_segments(pattern);
+    if segments.contains(&"..") {
+        // Files matched by patterns are mounted at their path in the
+        // application directory, which files outside it do not have.
+        bail!(
+            "Cannot mount {}: file patterns must be inside the application directory; place directories outside it with {{ source = \"...\", destination = \"/...\" }}",
+            pattern
+        );
+    }
+    let matcher = glob::Pattern::new(&segments.join("/"))?;
+    let options = glob::MatchOptions {
+        require_literal_separator: true,
+        ..glob::MatchOptions::new()
+    };
+    let literal = segments
+        .iter()
+        .take_while(|s| !s.contains(&['*', '?', '['][..]))
+        .count();
+    let root = rel.as_ref().join(segments[..literal].join("/"));
+    let mut walker = WalkDir::new(&root).follow_links(true);
+    if !segments[literal..].contains(&"**") {
+        walker = walker.max_depth(segments.len() - literal);
+    }
+
+    let mut files = vec![];
+    for entry in walker {
+        let entry = match entry {
+            Ok(entry) => entry,
+            // A pattern rooted at a missing directory matches nothing.
+            Err(e)
+                if e.path() == Some(root.as_path())
+                    && e.io_error().map(|e| e.kind()) == Some(std::io::ErrorKind::NotFound) =>
+            {
+                break
+            }
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("Failed to walk directory under {}", root.display()))
+            }
+        };
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let file = FileMount::from(entry.path(), &rel)?;
+        if matcher.matches_with(&file.relative_dst, options) {
+            files.push(file);
+        }
+    }
     ensure_all_under(&rel, files.iter().map(|s| &s.src))?;
     Ok(files)
 }
 
+/// Split a file pattern into its path segments, accepting backslash
+/// separators on Windows.
+fn pattern_segments(pattern: &str) -> Vec<&str> {
+    let is_separator = |c: char| c == '/' || (cfg!(windows) && c == '\\');
+    pattern
+        .split(is_separator)
+        .filter(|s| !s.is_empty() && *s != ".")
+        .collect()
+}
+
 /// Copy all files to the mount directory.
 async fn copy_all(
     files: &[FileMount],
@@ -227,7 +533,7 @@ async fn copy(file: &FileMount, dir: impl AsRef<Path>, allow_transient_write: bo
         change_file_permission(&to, true).await?;
     }
 
-    let _ = tokio::fs::copy(&from, &to)
+    crate::staging::copy_file(&from, &to)
         .await
         .with_context(|| anyhow!("Error copying asset file  '{}'", from.display()))?;
 
@@ -267,46 +573,45 @@ fn is_absolute_guest_path(path: impl AsRef<Path>) -> bool {
     path.as_ref().to_string_lossy().starts_with('/')
 }
 
-/// Convert strings to glob patterns
-fn convert_strings_to_glob_patterns<T: AsRef<str>>(
-    files: &[T],
-    rel: impl AsRef<Path>,
-) -> Result<Vec<glob::Pattern>> {
-    let file_paths = files
-        .iter()
-        .map(|f| match rel.as_ref().join(f.as_ref()).to_str() {
-            Some(abs) => Ok(abs.to_owned()),
-            None => Err(anyhow!(
-                "Can't join {} and {}",
-                rel.as_ref().display(),
-                f.as_ref()
-            )),
-        })
-        .collect::<Result<Vec<_>>>()?;
-    file_paths
+/// Convert strings to glob patterns, matching paths relative to the
+/// application directory.
+fn convert_strings_to_glob_patterns<T: AsRef<str>>(files: &[T]) -> Result<Vec<glob::Pattern>> {
+    files
         .iter()
         .map(|f| {
-            glob::Pattern::new(f).with_context(|| format!("can't convert {} to glob pattern", f))
+            let pattern = pattern_segments(f.as_ref()).join("/");
+            glob::Pattern::new(&pattern)
+                .with_context(|| format!("can't convert {} to glob pattern", f.as_ref()))
         })
         .collect::<Result<Vec<glob::Pattern>>>()
 }
 
 /// Remove files which match excluded patterns
-fn get_included_files(files: Vec<FileMount>, exclude_patterns: &[glob::Pattern]) -> Vec<FileMount> {
+fn get_included_files(
+    files: Vec<FileMount>,
+    exclude_patterns: &[glob::Pattern],
+    rel: impl AsRef<Path>,
+) -> Vec<FileMount> {
     files
         .into_iter()
-        .filter(|f| {
-            for exclude_pattern in exclude_patterns {
-                if exclude_pattern.matches_path(Path::new(&f.src)) {
-                    tracing::info!(
-                        "file: {} is excluded by pattern {}",
-                        f.src.display(),
-                        exclude_pattern
-                    );
-                    return false;
-                }
-            }
-            true
-        })
+        .filter(|f| exclusion(f, exclude_patterns, &rel).is_none())
         .collect::<Vec<_>>()
 }
+
+/// The first of the excluded patterns that matches the file, if any.
+fn exclusion<'a>(
+    file: &FileMount,
+    exclude_patterns: &'a [glob::Pattern],
+    rel: impl AsRef<Path>,
+) -> Option<&'a glob::Pattern> {
+    // Files are matched by their path relative to the application directory,
+    // which is always '/'-separated.
+    let relative = to_relative(&file.src, &rel).ok()?;
+    let pattern = exclude_patterns.iter().find(|p| p.matches(&relative))?;
+    tracing::info!(
+        "file: {} is excluded by pattern {}",
+        file.src.display(),
+        pattern
+    );
+    Some(pattern)
+}
@@ -0,0 +1,415 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context, Result};
+use path_absolutize::Absolutize;
+use spin_manifest::{QueueConfig, RedisConfig, TriggerConfig};
+
+use super::{
+    assets,
+    config::{RawAppManifestAnyVersion, RawComponentManifest, RawFileMount, RawModuleSource},
+    config_tree, external_source_dirs, include_components, origin_name, validate_component_fields,
+    validate_component_source_paths, validate_scale,
+};
+
+/// A problem found in a manifest, with where it was found.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Problem {
+    /// The manifest, or the file the component was configured in.
+    pub file: PathBuf,
+    /// The line of the file the problem was found at, starting at 1, if known.
+    pub line: Option<usize>,
+    /// The ID of the component the problem was found in, if any.
+    pub component: Option<String>,
+    /// The field the problem was found in, such as `trigger.route`, if known.
+    pub field: Option<String>,
+    /// What the problem is.
+    pub message: String,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.file.display())?;
+        if let Some(line) = self.line {
+            write!(f, ":{}", line)?;
+        }
+        write!(f, ": ")?;
+        if let Some(component) = &self.component {
+            write!(f, "component {}: ", component)?;
+        }
+        if let Some(field) = &self.field {
+            write!(f, "{}: ", field)?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Checks a manifest and the files it references without running anything,
+/// returning all the problems found, in manifest order. Only failing to read
+/// the manifest is an error.
+pub async fn check(app: &Path) -> Result<Vec<Problem>> {
+    let text = tokio::fs::read_to_string(app)
+        .await
+        .with_context(|| format!("Cannot read manifest file from {:?}", app))?;
+    let manifest = Source::new(app, &text);
+    let RawAppManifestAnyVersion::V1(mut raw) = match toml::from_str(&text) {
+        Ok(raw) => raw,
+        // The manifest cannot be checked further.
+        Err(e) => {
+            return Ok(vec![Problem {
+                file: app.to_owned(),
+                line: e.line_col().map(|(line, _)| line + 1),
+                component: None,
+                field: None,
+                message: e.to_string(),
+            }])
+        }
+    };
+
+    let mut problems = vec![];
+    if let Err(e) = include_components(&mut raw, app).await {
+        problems.push(manifest.app_problem("components_from", e));
+    }
+    let app_dir = app
+        .parent()
+        .unwrap_or_else(|| Path::new("/"))
+        .absolutize()?
+        .into_owned();
+    let allowed = external_source_dirs(&raw, &app_dir).unwrap_or_else(|e| {
+        problems.push(manifest.app_problem("external_source_dirs", e));
+        vec![]
+    });
+
+    let origins = raw
+        .components
+        .iter()
+        .filter_map(|c| c.origin.as_ref())
+        .map(|path| {
+            let text = std::fs::read_to_string(path).unwrap_or_default();
+            (path.clone(), Source::new(path, &text))
+        })
+        .collect::<HashMap<_, _>>();
+    let mut conflicts = Conflicts::default();
+    let mut manifest_components = manifest.component_sections().into_iter();
+    for c in &raw.components {
+        // Components from other files are configured at the top level of
+        // their file, after those of the manifest.
+        let (source, lines) = match &c.origin {
+            Some(path) => {
+                let source = &origins[path];
+                (source, 0..source.lines.len())
+            }
+            None => (&manifest, manifest_components.next().unwrap_or(0..0)),
+        };
+        let mut report = |field: &str, e: anyhow::Error| {
+            problems.push(Problem {
+                file: source.path.clone(),
+                line: source.field_line(&lines, field),
+                component: Some(c.id.clone()),
+                field: Some(field.to_owned()),
+                message: format!("{:#}", e),
+            })
+        };
+        if let Some(other) = conflicts.id(c) {
+            report(
+                "id",
+                anyhow!(
+                    "Duplicate component ID: also used by a component configured in {}",
+                    origin_name(&other.origin)
+                ),
+            );
+        }
+        if let Some((field, other)) = conflicts.trigger(c) {
+            report(
+                field,
+                anyhow!(
+                    "Same {} as component {}, which will never be invoked",
+                    field.trim_start_matches("trigger."),
+                    other.id
+                ),
+            );
+        }
+        for (field, result) in validate_component_fields(&raw, c) {
+            if let Err(e) = result {
+                report(field, e);
+            }
+        }
+        for (field, result) in check_component_files(c, &app_dir, &allowed) {
+            if let Err(e) = result {
+                report(field, e);
+            }
+        }
+    }
+
+    if let Err(e) = config_tree(&raw) {
+        problems.push(manifest.app_problem("config", e));
+    }
+    if let Some(scale) = raw.deploy.as_ref().and_then(|d| d.scale.as_ref()) {
+        if let Err(e) = validate_scale(scale) {
+            problems.push(manifest.app_problem("deploy.scale", e));
+        }
+    }
+    Ok(problems)
+}
+
+/// Checks the files a component references: its module, the files it
+/// mounts, and the payload schema of its trigger.
+fn check_component_files(
+    c: &RawComponentManifest,
+    app_dir: &Path,
+    allowed: &[PathBuf],
+) -> Vec<(&'static str, Result<()>)> {
+    let mut checks = vec![(
+        "source",
+        validate_component_source_paths(c, app_dir, allowed),
+    )];
+    if let RawModuleSource::FileReference(path) = &c.source {
+        if !app_dir.join(path).is_file() {
+            let hint = match &c.build {
+                Some(_) => "; run `spin build` to build it",
+                None => "",
+            };
+            checks.push((
+                "source",
+                Err(anyhow!("Module {} does not exist{}", path.display(), hint)),
+            ));
+        }
+    }
+    if let Some(path) = &c.wasm.environment_file {
+        let path = app_dir.join(path);
+        let loaded = std::fs::read_to_string(&path)
+            .with_context(|| format!("Cannot read variables file {}", path.display()))
+            .and_then(|contents| crate::env_file::parse(&contents));
+        checks.push(("environment_file", loaded.map(|_| ())));
+    }
+    if let Some(files) = &c.wasm.files {
+        let missing = files
+            .iter()
+            .filter_map(|f| match f {
+                RawFileMount::Placement(placement) => Some(&placement.source),
+                RawFileMount::Pattern(_) => None,
+            })
+            .filter(|source| !app_dir.join(source).is_dir())
+            .map(|source| anyhow!("Directory {} does not exist", source.display()))
+            .collect::<Vec<_>>();
+        if missing.is_empty() {
+            let exclude_files = c.wasm.exclude_files.clone().unwrap_or_default();
+            let matched = assets::collect_matches(files, &exclude_files, app_dir);
+            checks.push(("files", matched.map(|_| ())));
+        }
+        checks.extend(missing.into_iter().map(|e| ("files", Err(e))));
+    }
+    if let TriggerConfig::Redis(RedisConfig {
+        schema: Some(schema),
+        ..
+    }) = &c.trigger
+    {
+        let loaded = crate::schema::load_payload_schema(&app_dir.join(schema)).map(|_| ());
+        checks.push(("trigger.schema", loaded));
+    }
+    checks
+}
+
+/// The IDs and triggers of the components checked so far.
+#[derive(Default)]
+struct Conflicts<'a> {
+    ids: HashMap<&'a str, &'a RawComponentManifest>,
+    triggers: HashMap<(&'static str, String), &'a RawComponentManifest>,
+}
+
+impl<'a> Conflicts<'a> {
+    /// The component checked before with the ID of the component, if any.
+    fn id(&mut self, c: &'a RawComponentManifest) -> Option<&'a RawComponentManifest> {
+        self.ids.insert(&c.id, c)
+    }
+
+    /// The component checked before that is invoked for the same route,
+    /// channel or topic as the component, if any, with the field they share.
+    /// Triggers invoke the last of those components.
+    fn trigger(
+        &mut self,
+        c: &'a RawComponentManifest,
+    ) -> Option<(&'static str, &'a RawComponentManifest)> {
+        let (field, key) = match &c.trigger {
+            TriggerConfig::Http(http) => (
+                "trigger.route",
+                http.route
+                    .strip_suffix('/')
+                    .unwrap_or(&http.route)
+                    .to_owned(),
+            ),
+            TriggerConfig::Redis(RedisConfig { channel, .. }) => {
+                ("trigger.channel", channel.clone())
+            }
+            TriggerConfig::Queue(QueueConfig { topic, .. }) => ("trigger.topic", topic.clone()),
+            TriggerConfig::External(_) => return None,
+        };
+        self.triggers
+            .insert((field, key), c)
+            .map(|other| (field, other))
+    }
+}
+
+/// The lines of a manifest or component file, to locate problems in.
+struct Source {
+    path: PathBuf,
+    lines: Vec<String>,
+}
+
+impl Source {
+    fn new(path: &Path, text: &str) -> Self {
+        Self {
+            path: path.to_owned(),
+            lines: text.lines().map(str::to_owned).collect(),
+        }
+    }
+
+    /// The lines of each `[[component]]` table, with its subtables, in order.
+    fn component_sections(&self) -> Vec<Range<usize>> {
+        let mut sections = vec![];
+        let mut start = None;
+        for (idx, line) in self.lines.iter().enumerate() {
+            let line = line.trim();
+            if !line.starts_with('[') {
+                continue;
+            }
+            let is_component = line.trim_matches(['[', ']', ' ']) == "component";
+            let is_subtable = line.trim_start_matches('[').starts_with("component.");
+            if is_component || !is_subtable {
+                if let Some(start) = start.take() {
+                    sections.push(start..idx);
+                }
+            }
+            if is_component {
+                start = Some(idx);
+            }
+        }
+        if let Some(start) = start {
+            sections.push(start..self.lines.len());
+        }
+        sections
+    }
+
+    /// A problem with a field of the application, outside the components.
+    fn app_problem(&self, field: &str, e: anyhow::Error) -> Problem {
+        let sections = self.component_sections();
+        let line = (0..self.lines.len())
+            .filter(|idx| !sections.iter().any(|section| section.contains(idx)))
+            .find(|idx| mentions(&self.lines[*idx], field));
+        Problem {
+            file: self.path.clone(),
+            line: line.map(|idx| idx + 1),
+            component: None,
+            field: Some(field.to_owned()),
+            message: format!("{:#}", e),
+        }
+    }
+
+    /// The line of the given lines the field is set on, starting at 1. If
+    /// the field isn't found, this is the line its closest parent is set on.
+    fn field_line(&self, lines: &Range<usize>, field: &str) -> Option<usize> {
+        let segments = field.split('.').collect::<Vec<_>>();
+        (1..=segments.len())
+            .rev()
+            .find_map(|len| {
+                let field = segments[..len].join(".");
+                lines
+                    .clone()
+                    .find(|idx| mentions(&self.lines[*idx], &field))
+            })
+            .or_else(|| (!lines.is_empty()).then(|| lines.start))
+            .map(|idx| idx + 1)
+    }
+}
+
+/// Whether a line sets the field, or the last key of it: as a key, the
+/// header of a table, or a key of an inline table.
+fn mentions(line: &str, field: &str) -> bool {
+    let key = field.rsplit('.').next().unwrap_or(field);
+    let line = line.trim();
+    let sets = |rest: &str| rest.trim_start().starts_with('=');
+    if let Some(rest) = line.strip_prefix(key) {
+        return sets(rest);
+    }
+    if line.starts_with('[') {
+        let header = line.trim_matches(['[', ']', ' ']);
+        return header == field || header.ends_with(&format!(".{}", field));
+    }
+    line.contains('{')
+        && line
+            .match_indices(key)
+            .any(|(idx, _)| sets(&line[idx + key.len()..]) && !line[..idx].ends_with('_'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MANIFEST: &str = r#"spin_version = "1"
+name = "check-test"
+version = "1.0.0"
+trigger = { type = "http", base = "/" }
+
+[[component]]
+id = "api"
+source = "api.wasm"
+allowed_http_hosts = ["not a host"]
+[component.trigger]
+route = "/users/:id/:id"
+
+[[component]]
+id = "api"
+source = "missing.wasm"
+[component.build]
+command = "cargo build"
+[component.trigger]
+route = "/users/:id/:id/"
+
+[deploy.scale]
+min_replicas = 3
+max_replicas = 2
+"#;
+
+    #[tokio::test]
+    async fn test_check_reports_all_problems() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("api.wasm"), b"").unwrap();
+        let app = dir.path().join("spin.toml");
+        std::fs::write(&app, MANIFEST).unwrap();
+
+        let problems = check(&app).await.unwrap();
+        let found = problems
+            .iter()
+            .map(|p| (p.line, p.field.as_deref().unwrap_or_default()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            found,
+            vec![
+                (Some(9), "allowed_http_hosts"),
+                (Some(11), "trigger.route"),
+                (Some(14), "id"),
+                (Some(19), "trigger.route"),
+                (Some(19), "trigger.route"),
+                (Some(15), "source"),
+                (Some(21), "deploy.scale"),
+            ],
+            "{:#?}",
+            problems
+        );
+        assert!(problems[5].message.contains("spin build"));
+    }
+
+    #[tokio::test]
+    async fn test_check_reports_parse_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let app = dir.path().join("spin.toml");
+        std::fs::write(&app, "name = \"oops\nversion = 1\n").unwrap();
+        let problems = check(&app).await.unwrap();
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].line, Some(1));
+    }
+}
@@ -4,12 +4,38 @@
 
 #![deny(missing_docs)]
 
+use schemars::{
+    schema::{InstanceType, RootSchema, Schema, SchemaObject},
+    JsonSchema,
+};
 use serde::{Deserialize, Serialize};
-use spin_manifest::{ApplicationTrigger, TriggerConfig};
+use spin_manifest::{ApplicationTrigger, ComponentLimits, LoadPolicy, LogLevel, TriggerConfig};
 use std::{collections::HashMap, path::PathBuf};
 
+/// The JSON Schema of spin.toml, generated from the types it deserializes
+/// into, for editors and linters to check manifests with.
+pub fn json_schema() -> RootSchema {
+    // The version tag is added by hand, as the manifest does not allow
+    // unknown fields.
+    let mut schema = schemars::gen::SchemaSettings::draft07()
+        .into_generator()
+        .into_root_schema_for::<RawAppManifest>();
+    let object = schema.schema.object();
+    object.properties.insert(
+        "spin_version".to_owned(),
+        Schema::Object(SchemaObject {
+            instance_type: Some(InstanceType::String.into()),
+            const_value: Some("1".into()),
+            ..Default::default()
+        }),
+    );
+    object.required.insert("spin_version".to_owned());
+    schema.schema.metadata().title = Some("Spin application manifest".to_owned());
+    schema
+}
+
 /// Container for any version of the manifest.
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
 #[serde(tag = "spin_version")]
 pub enum RawAppManifestAnyVersion {
     /// A manifest with API version 1.
@@ -19,7 +45,7 @@ pub enum RawAppManifestAnyVersion {
 
 /// Application configuration local file format.
 /// This is the main structure spin.toml deserializes into.
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
 #[serde(deny_unknown_fields, rename_all = "snake_case")]
 pub struct RawAppManifest {
     /// General application information.
@@ -30,13 +56,67 @@ pub struct RawAppManifest {
     #[serde(rename = "variables")]
     pub config: Option<spin_config::Tree>,
 
+    /// Glob patterns, relative to spin.toml, matching files that each
+    /// configure one further component.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub components_from: Vec<String>,
+
+    /// Directories outside the application directory, relative to spin.toml,
+    /// that components may load modules and place directories from, for
+    /// example build outputs shared by the applications of a monorepo.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub external_source_dirs: Vec<PathBuf>,
+
     /// Configuration for the application components.
-    #[serde(rename = "component")]
+    #[serde(rename = "component", default)]
     pub components: Vec<RawComponentManifest>,
+
+    /// Settings for deploying the application.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deploy: Option<RawDeployConfig>,
+}
+
+/// Settings for deploying the application.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub struct RawDeployConfig {
+    /// Environments the application is deployed to, by name.
+    #[serde(default)]
+    pub environments: HashMap<String, RawDeployEnvironment>,
+    /// Scaling settings applied to the channel on every deploy, on platforms
+    /// that support them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scale: Option<RawScaleConfig>,
+}
+
+/// Scaling settings of the channel an application is deployed to. Settings
+/// left out are those of the platform, or as last set with `spin scale`.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq, JsonSchema)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub struct RawScaleConfig {
+    /// The fewest replicas of the application to run.
+    pub min_replicas: Option<u32>,
+    /// The most replicas of the application to run.
+    pub max_replicas: Option<u32>,
+    /// The memory class of each replica, as named by the platform.
+    pub memory_class: Option<String>,
+}
+
+/// The servers and channel of an environment the application is deployed
+/// to.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq, JsonSchema)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub struct RawDeployEnvironment {
+    /// URL of the Hippo server.
+    pub hippo_server: Option<String>,
+    /// URL of the bindle server.
+    pub bindle_server: Option<String>,
+    /// Name of the Hippo channel the application is deployed to.
+    pub channel: Option<String>,
 }
 
 /// General application information.
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
 #[serde(deny_unknown_fields, rename_all = "snake_case")]
 pub struct RawAppInformation {
     /// Name of the application.
@@ -54,7 +134,7 @@ pub struct RawAppInformation {
 }
 
 /// Core component configuration.
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
 #[serde(deny_unknown_fields, rename_all = "snake_case")]
 pub struct RawComponentManifest {
     /// The module source.
@@ -73,25 +153,42 @@ pub struct RawComponentManifest {
     pub config: Option<HashMap<String, String>>,
     /// Build configuration for the component.
     pub build: Option<RawBuildConfig>,
+    /// The file the component was configured in, if not spin.toml.
+    #[serde(skip)]
+    pub origin: Option<PathBuf>,
 }
 
 /// Build configuration for the component.
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
 #[serde(deny_unknown_fields, rename_all = "snake_case")]
 pub struct RawBuildConfig {
     /// Build command.
     pub command: String,
-    /// Working directory in which the build command is executed. It must be
-    /// relative to the directory in which `spin.toml` is located.
+    /// Commands executed in order before the build command, such as code
+    /// generators.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pre: Vec<String>,
+    /// Commands executed in order after the build command, such as
+    /// optimizers.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub post: Vec<String>,
+    /// Working directory in which the build commands are executed. It must
+    /// be relative to the directory in which `spin.toml` is located.
     pub workdir: Option<PathBuf>,
+    /// Components whose build commands must complete before this one runs.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub depends_on: Vec<String>,
 }
 
 /// WebAssembly configuration.
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
 #[serde(deny_unknown_fields, rename_all = "snake_case")]
 pub struct RawWasmConfig {
     /// Environment variables to be mapped inside the Wasm module at runtime.
     pub environment: Option<HashMap<String, String>>,
+    /// File of environment variables, in the dotenv format and relative to
+    /// the spin.toml file, merged into `environment`.
+    pub environment_file: Option<PathBuf>,
     /// Files to be mapped inside the Wasm module at runtime.
     ///
     /// In the local configuration file, this is a vector, each element of which
@@ -103,11 +200,30 @@ pub struct RawWasmConfig {
     pub exclude_files: Option<Vec<String>>,
     /// Optional list of HTTP hosts the component is allowed to connect.
     pub allowed_http_hosts: Option<Vec<String>>,
+    /// Optional list of blob store containers the component is allowed to access.
+    pub allowed_blob_containers: Option<Vec<String>>,
+    /// Optional list of database hosts the component is allowed to connect to.
+    pub allowed_database_hosts: Option<Vec<String>>,
+    /// Optional list of key-value stores the component is allowed to access.
+    pub key_value_stores: Option<Vec<String>>,
+    /// Optional list of JWT issuers the component is allowed to issue tokens with.
+    pub allowed_jwt_issuers: Option<Vec<String>>,
+    /// Optional list of cryptography keys the component is allowed to use.
+    pub allowed_crypto_keys: Option<Vec<String>>,
+    /// When the module of the component is compiled. Defaults to eager.
+    pub load: Option<LoadPolicy>,
+    /// Limits on the resources the component uses.
+    pub limits: Option<ComponentLimits>,
+    /// The least severe output of the component that is logged.
+    pub log_level: Option<LogLevel>,
+    /// Configuration for host components, by host component name.
+    #[schemars(with = "Option<HashMap<String, serde_json::Value>>")]
+    pub host_config: Option<HashMap<String, toml::Value>>,
 }
 
 /// An entry in the `files` list mapping a source path to an absolute
 /// mount path in the guest.
-#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize, JsonSchema)]
 #[serde(deny_unknown_fields, rename_all = "snake_case")]
 pub struct RawDirectoryPlacement {
     /// The source to mount.
@@ -118,7 +234,7 @@ pub struct RawDirectoryPlacement {
 
 /// A specification for a file or set of files to mount in the
 /// Wasm module.
-#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize, JsonSchema)]
 #[serde(deny_unknown_fields, rename_all = "snake_case", untagged)]
 pub enum RawFileMount {
     /// Mount a specified directory at a specified location.
@@ -128,20 +244,22 @@ pub enum RawFileMount {
 }
 
 /// Source for the module.
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
 #[serde(deny_unknown_fields, rename_all = "snake_case", untagged)]
 pub enum RawModuleSource {
     /// Local path or parcel reference to a module that needs to be linked.
     FileReference(PathBuf),
     /// Reference to a remote bindle
     Bindle(FileComponentBindleSource),
+    /// Module downloaded from an HTTP(S) URL
+    Url(FileComponentUrlSource),
 }
 
 /// A component source from Bindle.
 /// TODO
 /// The component and its entrypoint should be pulled from Bindle.
 /// This assumes access to the Bindle server.
-#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize, JsonSchema)]
 #[serde(deny_unknown_fields, rename_all = "snake_case")]
 pub struct FileComponentBindleSource {
     /// Reference to the bindle (name/version)
@@ -149,3 +267,14 @@ pub struct FileComponentBindleSource {
     /// Parcel to use from the bindle.
     pub parcel: String,
 }
+
+/// A component source downloaded from an HTTP(S) URL, and verified against
+/// its digest.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub struct FileComponentUrlSource {
+    /// The URL of the module.
+    pub url: String,
+    /// The digest of the module, as `sha256:<hex>`.
+    pub digest: String,
+}
@@ -4,6 +4,8 @@
 
 /// Module to prepare the assets for the components of an application.
 pub mod assets;
+/// Module to check a manifest and the files it references without running it.
+pub mod check;
 /// Configuration representation for a Spin application as a local spin.toml file.
 pub mod config;
 
@@ -11,27 +13,45 @@ pub mod config;
 mod tests;
 
 use anyhow::{anyhow, bail, Context, Result};
-use config::{RawAppInformation, RawAppManifest, RawAppManifestAnyVersion, RawComponentManifest};
-use futures::future;
+use config::{
+    RawAppInformation, RawAppManifest, RawAppManifestAnyVersion, RawComponentManifest,
+    RawFileMount, RawModuleSource, RawScaleConfig,
+};
+use futures::{stream, StreamExt};
 use path_absolutize::Absolutize;
 use spin_manifest::{
-    Application, ApplicationInformation, ApplicationOrigin, CoreComponent, ModuleSource,
-    SpinVersion, WasmConfig,
+    Application, ApplicationInformation, ApplicationOrigin, ApplicationTrigger, CoreComponent,
+    HttpConfig, HttpHandler, ModuleSource, QueueConfig, RedisConfig, RequestConfigSource,
+    SpinVersion, TriggerConfig, WasmConfig,
+};
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
 };
-use std::{path::Path, str::FromStr, sync::Arc};
 use tokio::{fs::File, io::AsyncReadExt};
 
-use crate::{bindle::BindleConnectionInfo, validation::validate_allowed_http_hosts};
+use crate::{
+    assets::is_under,
+    bindle::BindleConnectionInfo,
+    progress::Progress,
+    url_source::UrlSource,
+    validation::{
+        validate_allowed_database_hosts, validate_allowed_http_hosts, validate_key_value_stores,
+    },
+};
 
 /// Given the path to a spin.toml manifest file, prepare its assets locally and
 /// get a prepared application configuration consumable by a Spin execution context.
 /// If a directory is provided, use it as the base directory to expand the assets,
-/// otherwise create a new temporary directory.
+/// otherwise create a new temporary directory. With `direct_mounts`, directories
+/// placed whole are mounted from the application directory rather than copied.
 pub async fn from_file(
     app: impl AsRef<Path>,
     base_dst: impl AsRef<Path>,
     bindle_connection: &Option<BindleConnectionInfo>,
     allow_transient_write: bool,
+    direct_mounts: bool,
 ) -> Result<Application> {
     let app = app
         .as_ref()
@@ -39,6 +59,8 @@ pub async fn from_file(
         .context("Failed to resolve absolute path to manifest file")?;
     let manifest = raw_manifest_from_file(&app).await?;
     validate_raw_app_manifest(&manifest)?;
+    validate_source_paths(&manifest, app.parent().unwrap_or_else(|| Path::new("/")))?;
+    validate_payload_schemas(&manifest, app.parent().unwrap_or_else(|| Path::new("/")))?;
 
     prepare_any_version(
         manifest,
@@ -46,11 +68,13 @@ pub async fn from_file(
         base_dst,
         bindle_connection,
         allow_transient_write,
+        direct_mounts,
     )
     .await
 }
 
-/// Reads the spin.toml file as a raw manifest.
+/// Reads the spin.toml file as a raw manifest, including the components
+/// configured in the files matched by its `components_from` patterns.
 pub async fn raw_manifest_from_file(app: &impl AsRef<Path>) -> Result<RawAppManifestAnyVersion> {
     let mut buf = vec![];
     File::open(app.as_ref())
@@ -60,8 +84,34 @@ pub async fn raw_manifest_from_file(app: &impl AsRef<Path>) -> Result<RawAppMani
         .await
         .with_context(|| anyhow!("Cannot read manifest file from {:?}", app.as_ref()))?;
 
-    let manifest: RawAppManifestAnyVersion = toml::from_slice(&buf)?;
-    Ok(manifest)
+    let RawAppManifestAnyVersion::V1(mut manifest) = toml::from_slice(&buf)?;
+    include_components(&mut manifest, app.as_ref()).await?;
+    Ok(RawAppManifestAnyVersion::V1(manifest))
+}
+
+/// Appends the components configured in the files matched by the
+/// manifest's `components_from` patterns, in file name order.
+async fn include_components(raw: &mut RawAppManifest, app: &Path) -> Result<()> {
+    let manifest_dir = app.parent().unwrap_or_else(|| Path::new(""));
+    for pattern in std::mem::take(&mut raw.components_from) {
+        let abs = manifest_dir.join(&pattern);
+        let paths = glob::glob(&abs.to_string_lossy())
+            .with_context(|| format!("Invalid components_from pattern '{}'", pattern))?
+            .collect::<Result<Vec<_>, _>>()?;
+        if paths.is_empty() {
+            bail!("components_from pattern '{}' matches no files", pattern);
+        }
+        for path in paths {
+            let contents = tokio::fs::read(&path)
+                .await
+                .with_context(|| format!("Cannot read component file {}", path.display()))?;
+            let mut component: RawComponentManifest = toml::from_slice(&contents)
+                .with_context(|| format!("Cannot parse component file {}", path.display()))?;
+            component.origin = Some(path);
+            raw.components.push(component);
+        }
+    }
+    Ok(())
 }
 
 /// Converts a raw application manifest into Spin configuration while handling
@@ -72,83 +122,417 @@ async fn prepare_any_version(
     base_dst: impl AsRef<Path>,
     bindle_connection: &Option<BindleConnectionInfo>,
     allow_transient_write: bool,
+    direct_mounts: bool,
 ) -> Result<Application> {
     match raw {
         RawAppManifestAnyVersion::V1(raw) => {
-            prepare(raw, src, base_dst, bindle_connection, allow_transient_write).await
+            prepare(
+                raw,
+                src,
+                base_dst,
+                bindle_connection,
+                allow_transient_write,
+                direct_mounts,
+            )
+            .await
         }
     }
 }
 
 /// Iterates over a vector of RawComponentManifest structs and throws an error if any component ids are duplicated
 fn error_on_duplicate_ids(components: Vec<RawComponentManifest>) -> Result<()> {
-    let mut ids: Vec<String> = Vec::new();
+    let mut ids: Vec<(String, Option<PathBuf>)> = Vec::new();
     for c in components {
         let id = c.id;
-        if ids.contains(&id) {
-            bail!("cannot have duplicate component IDs: {}", id);
-        } else {
-            ids.push(id);
+        match ids.iter().find(|(other, _)| *other == id) {
+            Some((_, other_origin)) if other_origin.is_some() || c.origin.is_some() => bail!(
+                "cannot have duplicate component IDs: {} (configured in {} and {})",
+                id,
+                origin_name(other_origin),
+                origin_name(&c.origin)
+            ),
+            Some(_) => bail!("cannot have duplicate component IDs: {}", id),
+            None => ids.push((id, c.origin)),
         }
     }
     Ok(())
 }
 
+/// Adds the file a component was configured in to its errors, if it was
+/// not configured in spin.toml.
+fn in_origin<T>(id: &str, origin: &Option<PathBuf>, result: Result<T>) -> Result<T> {
+    match origin {
+        Some(path) => result
+            .with_context(|| format!("Invalid component {} configured in {}", id, path.display())),
+        None => result,
+    }
+}
+
+fn origin_name(origin: &Option<PathBuf>) -> String {
+    match origin {
+        Some(path) => path.display().to_string(),
+        None => "spin.toml".to_owned(),
+    }
+}
+
 /// Validate fields in raw app manifest
 pub fn validate_raw_app_manifest(raw: &RawAppManifestAnyVersion) -> Result<()> {
     match raw {
         RawAppManifestAnyVersion::V1(raw) => {
-            let _ = raw
-                .components
-                .iter()
-                .map(|c| validate_allowed_http_hosts(&c.wasm.allowed_http_hosts))
-                .collect::<Result<Vec<_>>>()?;
+            for c in &raw.components {
+                for (_, validated) in validate_component_fields(raw, c) {
+                    in_origin(&c.id, &c.origin, validated)?;
+                }
+            }
+            if let Some(scale) = raw.deploy.as_ref().and_then(|d| d.scale.as_ref()) {
+                validate_scale(scale)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Validates the fields of a component that can be checked without reading
+/// files, returning the outcome of each check with the field it checks.
+fn validate_component_fields(
+    raw: &RawAppManifest,
+    c: &RawComponentManifest,
+) -> Vec<(&'static str, Result<()>)> {
+    vec![
+        (
+            "allowed_http_hosts",
+            validate_allowed_http_hosts(&c.wasm.allowed_http_hosts),
+        ),
+        (
+            "allowed_database_hosts",
+            validate_allowed_database_hosts(&c.wasm.allowed_database_hosts),
+        ),
+        (
+            "key_value_stores",
+            validate_key_value_stores(&c.wasm.key_value_stores),
+        ),
+        (
+            "trigger",
+            validate_component_trigger(&raw.info.trigger, &c.trigger),
+        ),
+        ("source", validate_module_source(&c.source)),
+        ("trigger.route", validate_route(&c.trigger)),
+        (
+            "trigger.dead_letter_topic",
+            validate_queue_trigger(&c.trigger),
+        ),
+        (
+            "trigger.request_config",
+            validate_request_config(&c.trigger),
+        ),
+    ]
+}
+
+/// Checks that the URL and digest of a module downloaded over HTTP are valid.
+fn validate_module_source(source: &RawModuleSource) -> Result<()> {
+    if let RawModuleSource::Url(u) = source {
+        UrlSource::parse(&u.url, &u.digest)?;
+    }
+    Ok(())
+}
+
+/// Checks that the trigger of a component is of the type of the application
+/// trigger. Component triggers that are not of a built-in type are read as
+/// the configuration of an external trigger, so for applications with a
+/// built-in trigger, they are read again to report why they are invalid.
+fn validate_component_trigger(
+    app_trigger: &ApplicationTrigger,
+    trigger: &TriggerConfig,
+) -> Result<()> {
+    let expected = app_trigger.trigger_type();
+    let reparsed = match (app_trigger, trigger) {
+        (ApplicationTrigger::External(_), _)
+        | (ApplicationTrigger::Http(_), TriggerConfig::Http(_))
+        | (ApplicationTrigger::Redis(_), TriggerConfig::Redis(_))
+        | (ApplicationTrigger::Queue(_), TriggerConfig::Queue(_)) => return Ok(()),
+        (_, TriggerConfig::External(table)) => {
+            let value = toml::Value::Table(table.clone());
+            match app_trigger {
+                ApplicationTrigger::Http(_) => value.try_into::<HttpConfig>().map(|_| ()),
+                ApplicationTrigger::Redis(_) => value.try_into::<RedisConfig>().map(|_| ()),
+                _ => value.try_into::<QueueConfig>().map(|_| ()),
+            }
+        }
+        _ => bail!("The component trigger is not a {} trigger", expected),
+    };
+    reparsed.with_context(|| format!("Invalid {} component trigger", expected))
+}
+
+/// Checks the syntax of the route of an HTTP component: `...` may only be
+/// the last segment, and route parameters must be named, once each, with
+/// ASCII letters, digits and `_`.
+fn validate_route(trigger: &TriggerConfig) -> Result<()> {
+    let route = match trigger {
+        TriggerConfig::Http(http) => &http.route,
+        _ => return Ok(()),
+    };
+    if let Some(c) = route
+        .chars()
+        .find(|c| c.is_whitespace() || *c == '?' || *c == '#')
+    {
+        bail!("Route {} cannot contain {:?}", route, c);
+    }
+    let segments = route.split('/').collect::<Vec<_>>();
+    let mut params = vec![];
+    for (idx, segment) in segments.iter().enumerate() {
+        if segment.contains("...") && (*segment != "..." || idx != segments.len() - 1) {
+            bail!(
+                "Route {} can only end with /... to match the paths under it",
+                route
+            );
+        }
+        if let Some(name) = segment.strip_prefix(':') {
+            if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                bail!(
+                    "Route {} has an invalid parameter {:?}: parameter names may only contain ASCII letters, digits and '_'",
+                    route,
+                    segment
+                );
+            }
+            if params.contains(&name) {
+                bail!("Route {} has the parameter :{} twice", route, name);
+            }
+            params.push(name);
         }
     }
     Ok(())
 }
 
+fn validate_queue_trigger(trigger: &TriggerConfig) -> Result<()> {
+    if let TriggerConfig::Queue(QueueConfig {
+        topic,
+        dead_letter_topic: Some(dead_letter_topic),
+        ..
+    }) = trigger
+    {
+        if topic == dead_letter_topic {
+            bail!(
+                "The dead-letter topic must differ from the topic {}, or rejected messages would be handled again",
+                topic
+            );
+        }
+    }
+    Ok(())
+}
+
+fn validate_request_config(trigger: &TriggerConfig) -> Result<()> {
+    let http = match trigger {
+        TriggerConfig::Http(http) => http,
+        _ => return Ok(()),
+    };
+    for (key, source) in &http.request_config {
+        spin_config::Key::new(key)
+            .with_context(|| format!("Invalid request_config key {:?}", key))?;
+        if let RequestConfigSource::PathParam(name) = source {
+            let param = format!(":{}", name);
+            if !http.route.split('/').any(|segment| segment == param) {
+                bail!(
+                    "request_config key {:?} takes the route parameter {:?}, which route {} does not have",
+                    key,
+                    name,
+                    http.route
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+fn validate_scale(scale: &RawScaleConfig) -> Result<()> {
+    match (scale.min_replicas, scale.max_replicas) {
+        (_, Some(0)) => bail!("deploy.scale.max_replicas must be at least 1"),
+        (Some(min), Some(max)) if min > max => bail!(
+            "deploy.scale.min_replicas ({}) is more than deploy.scale.max_replicas ({})",
+            min,
+            max
+        ),
+        _ => Ok(()),
+    }
+}
+
+/// Matches the file patterns of the components that mount files, in manifest
+/// order, reporting the files that each entry of their `files` matches.
+pub fn match_component_files(
+    raw: &RawAppManifestAnyVersion,
+    app_dir: &Path,
+) -> Result<Vec<(String, Vec<assets::MountMatches>)>> {
+    let RawAppManifestAnyVersion::V1(raw) = raw;
+    raw.components
+        .iter()
+        .filter_map(|c| {
+            let files = c.wasm.files.as_ref()?;
+            let exclude_files = c.wasm.exclude_files.clone().unwrap_or_default();
+            let matches = assets::collect_matches(files, &exclude_files, app_dir);
+            Some(in_origin(&c.id, &c.origin, matches).map(|m| (c.id.clone(), m)))
+        })
+        .collect()
+}
+
+/// Checks that the module sources and placed directories of the components
+/// are inside the application directory, or inside one of the
+/// `external_source_dirs` of the manifest.
+pub fn validate_source_paths(raw: &RawAppManifestAnyVersion, app_dir: &Path) -> Result<()> {
+    let RawAppManifestAnyVersion::V1(raw) = raw;
+    let app_dir = app_dir
+        .absolutize()
+        .context("Failed to resolve absolute path to application directory")?;
+    let allowed = external_source_dirs(raw, &app_dir)?;
+    for c in &raw.components {
+        in_origin(
+            &c.id,
+            &c.origin,
+            validate_component_source_paths(c, &app_dir, &allowed),
+        )?;
+    }
+    Ok(())
+}
+
+/// The absolute paths of the `external_source_dirs` of the manifest.
+fn external_source_dirs(raw: &RawAppManifest, app_dir: &Path) -> Result<Vec<PathBuf>> {
+    raw.external_source_dirs
+        .iter()
+        .map(|dir| {
+            if dir.is_absolute() {
+                bail!(
+                    "external_source_dirs entry {} must be relative to spin.toml",
+                    dir.display()
+                );
+            }
+            Ok(app_dir.join(dir).absolutize()?.into_owned())
+        })
+        .collect()
+}
+
+/// The relative module source and placed directories of a component.
+fn component_source_paths(c: &RawComponentManifest) -> impl Iterator<Item = &PathBuf> {
+    let module = match &c.source {
+        RawModuleSource::FileReference(path) => Some(path),
+        RawModuleSource::Bindle(_) | RawModuleSource::Url(_) => None,
+    };
+    let placements = c.wasm.files.iter().flatten().filter_map(|f| match f {
+        RawFileMount::Placement(placement) => Some(&placement.source),
+        RawFileMount::Pattern(_) => None,
+    });
+    module.into_iter().chain(placements)
+}
+
+fn validate_component_source_paths(
+    c: &RawComponentManifest,
+    app_dir: &Path,
+    allowed: &[PathBuf],
+) -> Result<()> {
+    // Absolute paths are explicit, so only relative ones need allowing.
+    for path in component_source_paths(c).filter(|path| !path.is_absolute()) {
+        let full = app_dir.join(path).absolutize()?.into_owned();
+        if !is_under(app_dir, &full) && !allowed.iter().any(|dir| is_under(dir, &full)) {
+            bail!(
+                "Component {} uses {}, which is outside the application directory; add its directory to external_source_dirs in spin.toml to allow it",
+                c.id,
+                path.display()
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Checks that the payload schemas declared by the triggers of the
+/// components are valid JSON schemas.
+pub fn validate_payload_schemas(raw: &RawAppManifestAnyVersion, app_dir: &Path) -> Result<()> {
+    let RawAppManifestAnyVersion::V1(raw) = raw;
+    for c in &raw.components {
+        if let TriggerConfig::Redis(RedisConfig {
+            schema: Some(schema),
+            ..
+        }) = &c.trigger
+        {
+            let loaded = crate::schema::load_payload_schema(&app_dir.join(schema)).map(|_| ());
+            in_origin(&c.id, &c.origin, loaded)?;
+        }
+    }
+    Ok(())
+}
+
+/// The configuration tree of an application: its variables, and the config
+/// of each component under its ID.
+pub fn config_tree(raw: &RawAppManifest) -> Result<spin_config::Tree> {
+    let mut config_root = raw.config.clone().unwrap_or_default();
+    for component in &raw.components {
+        if let Some(config) = component.config.clone() {
+            let merged = spin_config::TreePath::try_from(component.id.clone())
+                .with_context(|| format!("component ID {:?} not a valid config path", component.id))
+                .and_then(|path| Ok(config_root.merge_defaults(&path, config)?));
+            in_origin(&component.id, &component.origin, merged)?;
+        }
+    }
+    Ok(config_root)
+}
+
 /// Converts a raw application manifest into Spin configuration.
 async fn prepare(
-    mut raw: RawAppManifest,
+    raw: RawAppManifest,
     src: impl AsRef<Path>,
     base_dst: impl AsRef<Path>,
     bindle_connection: &Option<BindleConnectionInfo>,
     allow_transient_write: bool,
+    direct_mounts: bool,
 ) -> Result<Application> {
     let info = info(raw.info, &src);
 
     error_on_duplicate_ids(raw.components.clone())?;
 
-    let mut config_root = raw.config.unwrap_or_default();
-    for component in &mut raw.components {
-        if let Some(config) = component.config.take() {
-            let path = component.id.clone().try_into().with_context(|| {
-                format!("component ID {:?} not a valid config path", component.id)
-            })?;
-            config_root.merge_defaults(&path, config)?;
-        }
-    }
+    let config_root = config_tree(&raw)?;
     let config_resolver = Some(Arc::new(spin_config::Resolver::new(config_root)?));
 
+    // Payload schemas are resolved relative to the directory of the manifest.
+    let dir = src.as_ref().parent().unwrap_or_else(|| Path::new("."));
     let component_triggers = raw
         .components
         .iter()
-        .map(|c| (c.id.clone(), c.trigger.clone()))
+        .map(|c| {
+            let mut trigger = c.trigger.clone();
+            if let TriggerConfig::Redis(RedisConfig {
+                schema: Some(schema),
+                ..
+            }) = &mut trigger
+            {
+                *schema = dir.join(&schema);
+            }
+            (c.id.clone(), trigger)
+        })
         .collect();
 
-    let components = future::join_all(
-        raw.components
-            .into_iter()
-            .map(|c| async {
-                core(c, &src, &base_dst, bindle_connection, allow_transient_write).await
-            })
-            .collect::<Vec<_>>(),
-    )
-    .await
-    .into_iter()
-    .collect::<Result<Vec<_>>>()
-    .context("Failed to prepare configuration")?;
+    let progress = Progress::new(raw.components.len());
+    let components = stream::iter(raw.components)
+        .map(|c| {
+            let (src, base_dst, progress) = (&src, &base_dst, &progress);
+            async move {
+                let (id, origin) = (c.id.clone(), c.origin.clone());
+                let component = core(
+                    c,
+                    src,
+                    base_dst,
+                    bindle_connection,
+                    allow_transient_write,
+                    direct_mounts,
+                )
+                .await;
+                let component = in_origin(&id, &origin, component)?;
+                progress.completed(&id);
+                Ok::<_, anyhow::Error>(component)
+            }
+        })
+        // Components are kept in manifest order.
+        .buffered(crate::MAX_PARALLEL_COMPONENT_PROCESSING)
+        .collect::<Vec<_>>()
+        .await
+        .into_iter()
+        .collect::<Result<Vec<_>>>()
+        .context("Failed to prepare configuration")?;
 
     Ok(Application {
         info,
@@ -165,6 +549,7 @@ async fn core(
     base_dst: impl AsRef<Path>,
     bindle_connection: &Option<BindleConnectionInfo>,
     allow_transient_write: bool,
+    direct_mounts: bool,
 ) -> Result<CoreComponent> {
     let id = raw.id;
 
@@ -186,6 +571,10 @@ async fn core(
                 format!("Invalid bindle ID {} in component {}", b.reference, id)
             })?;
             let parcel_sha = &b.parcel;
+            crate::offline::ensure_online(format!(
+                "download the source of component {} from bindle {}",
+                id, bindle_id
+            ))?;
             let client = match bindle_connection {
                 None => anyhow::bail!(
                     "Component {} requires a Bindle connection but none was specified",
@@ -206,9 +595,17 @@ async fn core(
             let name = format!("{}@{}", bindle_id, parcel_sha);
             ModuleSource::Buffer(bytes, name)
         }
+        config::RawModuleSource::Url(u) => {
+            let path = UrlSource::parse(&u.url, &u.digest)
+                .with_context(|| format!("Invalid source for component {}", id))?
+                .fetch(&id)
+                .await?;
+            ModuleSource::FileReference(path)
+        }
     };
 
     let description = raw.description;
+    let limits = raw.wasm.limits.unwrap_or_default();
     let mounts = match raw.wasm.files {
         Some(f) => {
             let exclude_files = raw.wasm.exclude_files.unwrap_or_default();
@@ -218,18 +615,42 @@ async fn core(
                 &base_dst,
                 &id,
                 allow_transient_write,
+                direct_mounts,
                 &exclude_files,
+                &limits,
             )
             .await?
         }
         None => vec![],
     };
-    let environment = raw.wasm.environment.unwrap_or_default();
+    let mut environment = raw.wasm.environment.unwrap_or_default();
+    if let Some(file) = &raw.wasm.environment_file {
+        let variables = crate::env_file::load(&src.join(file))
+            .await
+            .with_context(|| format!("Cannot load the environment_file of component {}", id))?;
+        environment.extend(variables);
+    }
     let allowed_http_hosts = raw.wasm.allowed_http_hosts.unwrap_or_default();
+    let allowed_blob_containers = raw.wasm.allowed_blob_containers.unwrap_or_default();
+    let allowed_database_hosts = raw.wasm.allowed_database_hosts.unwrap_or_default();
+    let key_value_stores = raw.wasm.key_value_stores.unwrap_or_default();
+    let allowed_jwt_issuers = raw.wasm.allowed_jwt_issuers.unwrap_or_default();
+    let allowed_crypto_keys = raw.wasm.allowed_crypto_keys.unwrap_or_default();
+    let load = raw.wasm.load.unwrap_or_default();
+    let host_config = raw.wasm.host_config.unwrap_or_default();
     let wasm = WasmConfig {
         environment,
         mounts,
         allowed_http_hosts,
+        allowed_blob_containers,
+        allowed_database_hosts,
+        key_value_stores,
+        allowed_jwt_issuers,
+        allowed_crypto_keys,
+        load,
+        limits,
+        log_level: raw.wasm.log_level,
+        host_config,
     };
     Ok(CoreComponent {
         source,
@@ -241,13 +662,29 @@ async fn core(
 
 /// Converts the raw application information from the spin.toml manifest to the standard configuration.
 fn info(raw: RawAppInformation, src: impl AsRef<Path>) -> ApplicationInformation {
+    let mut trigger = raw.trigger;
+    if let ApplicationTrigger::Http(http) = &mut trigger {
+        // Resolve template and TLS paths relative to the directory of the
+        // manifest.
+        let dir = src.as_ref().parent().unwrap_or_else(|| Path::new("."));
+        for route in &mut http.routes {
+            if let HttpHandler::Template(template) = &mut route.handler {
+                template.template = dir.join(&template.template);
+            }
+        }
+        if let Some(tls) = &mut http.tls {
+            tls.cert = dir.join(&tls.cert);
+            tls.key = dir.join(&tls.key);
+        }
+    }
+
     ApplicationInformation {
         spin_version: SpinVersion::V1,
         name: raw.name,
         version: raw.version,
         description: raw.description,
         authors: raw.authors.unwrap_or_default(),
-        trigger: raw.trigger,
+        trigger,
         namespace: raw.namespace,
         origin: ApplicationOrigin::File(src.as_ref().to_path_buf()),
     }
@@ -11,7 +11,7 @@ async fn test_from_local_source() -> Result<()> {
 
     let temp_dir = tempfile::tempdir()?;
     let dir = temp_dir.path();
-    let app = from_file(MANIFEST, dir, &None, false).await?;
+    let app = from_file(MANIFEST, dir, &None, false, false).await?;
 
     assert_eq!(app.info.name, "spin-local-source-test");
     assert_eq!(app.info.version, "1.0.0");
@@ -90,12 +90,150 @@ fn test_manifest() -> Result<()> {
 
     let b = match cfg.components[1].source.clone() {
         RawModuleSource::Bindle(b) => b,
-        RawModuleSource::FileReference(_) => panic!("expected bindle source"),
+        _ => panic!("expected bindle source"),
     };
 
     assert_eq!(b.reference, "bindle reference".to_string());
     assert_eq!(b.parcel, "parcel".to_string());
 
+    let limits = cfg.components[1].wasm.limits.clone().unwrap();
+    assert_eq!(limits.max_memory_bytes, Some(64 * 1024 * 1024));
+    assert_eq!(limits.timeout_ms, Some(5000));
+    assert_eq!(limits.max_concurrent, None);
+    assert!(cfg.components[0].wasm.limits.is_none());
+
+    Ok(())
+}
+
+#[test]
+fn test_deploy_environments() -> Result<()> {
+    let RawAppManifestAnyVersion::V1(cfg) = toml::from_str(
+        r#"
+        spin_version = "1"
+        name = "app"
+        version = "1.0.0"
+        trigger = { type = "http", base = "/" }
+
+        [deploy.environments.staging]
+        hippo_server = "https://hippo.staging.example.com"
+        channel = "staging"
+        "#,
+    )?;
+    let staging = &cfg.deploy.unwrap().environments["staging"];
+    assert_eq!(
+        staging.hippo_server.as_deref(),
+        Some("https://hippo.staging.example.com")
+    );
+    assert_eq!(staging.bindle_server, None);
+    assert_eq!(staging.channel.as_deref(), Some("staging"));
+    Ok(())
+}
+
+#[test]
+fn test_url_source() -> Result<()> {
+    let manifest = |digest: &str| {
+        toml::from_str::<RawAppManifestAnyVersion>(&format!(
+            r#"
+            spin_version = "1"
+            name = "app"
+            version = "1.0.0"
+            trigger = {{ type = "http", base = "/" }}
+
+            [[component]]
+            id = "hello"
+            source = {{ url = "https://example.com/hello.wasm", digest = "{}" }}
+            [component.trigger]
+            route = "/hello"
+            "#,
+            digest
+        ))
+    };
+
+    let digest = format!("sha256:{}", "a".repeat(64));
+    let cfg = manifest(&digest)?;
+    validate_raw_app_manifest(&cfg)?;
+    let RawAppManifestAnyVersion::V1(raw) = cfg;
+    match &raw.components[0].source {
+        RawModuleSource::Url(u) => {
+            assert_eq!(u.url, "https://example.com/hello.wasm");
+            assert_eq!(u.digest, digest);
+        }
+        _ => panic!("expected URL source"),
+    }
+
+    assert!(validate_raw_app_manifest(&manifest("md5:abc")?).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_deploy_scale() -> Result<()> {
+    let manifest = |scale: &str| {
+        toml::from_str::<RawAppManifestAnyVersion>(&format!(
+            r#"
+            spin_version = "1"
+            name = "app"
+            version = "1.0.0"
+            trigger = {{ type = "http", base = "/" }}
+
+            [deploy.scale]
+            {}
+            "#,
+            scale
+        ))
+    };
+
+    let cfg = manifest("min_replicas = 2\nmax_replicas = 5\nmemory_class = \"large\"")?;
+    validate_raw_app_manifest(&cfg)?;
+    let RawAppManifestAnyVersion::V1(raw) = cfg;
+    let scale = raw.deploy.unwrap().scale.unwrap();
+    assert_eq!(scale.min_replicas, Some(2));
+    assert_eq!(scale.max_replicas, Some(5));
+    assert_eq!(scale.memory_class.as_deref(), Some("large"));
+
+    assert!(validate_raw_app_manifest(&manifest("min_replicas = 3\nmax_replicas = 2")?).is_err());
+    assert!(validate_raw_app_manifest(&manifest("max_replicas = 0")?).is_err());
+    assert!(manifest("replicas = 2").is_err());
+    Ok(())
+}
+
+#[test]
+fn test_queue_trigger() -> Result<()> {
+    let manifest = |component_trigger: &str| {
+        toml::from_str::<RawAppManifestAnyVersion>(&format!(
+            r#"
+            spin_version = "1"
+            name = "app"
+            version = "1.0.0"
+            trigger = {{ type = "queue", broker = "kafka", address = "localhost:9092", consumer_group = "orders" }}
+
+            [[component]]
+            id = "orders"
+            source = "orders.wasm"
+            [component.trigger]
+            {}
+            "#,
+            component_trigger
+        ))
+    };
+
+    let cfg = manifest("topic = \"orders\"\ndead_letter_topic = \"orders-dead\"")?;
+    validate_raw_app_manifest(&cfg)?;
+    let RawAppManifestAnyVersion::V1(raw) = cfg;
+    match raw.info.trigger {
+        ApplicationTrigger::Queue(queue) => {
+            assert_eq!(queue.broker, spin_manifest::QueueBroker::Kafka);
+            assert_eq!(queue.consumer_group.as_deref(), Some("orders"));
+        }
+        _ => panic!("Expected a queue trigger"),
+    }
+    let queue = QueueConfig::try_from(raw.components[0].trigger.clone())?;
+    assert_eq!(queue.topic, "orders");
+    assert_eq!(queue.max_retries, 3);
+    assert_eq!(queue.retry_delay_ms, 1000);
+    assert_eq!(queue.dead_letter_topic.as_deref(), Some("orders-dead"));
+
+    let looping = manifest("topic = \"orders\"\ndead_letter_topic = \"orders\"")?;
+    assert!(validate_raw_app_manifest(&looping).is_err());
     Ok(())
 }
 
@@ -129,7 +267,7 @@ fn test_wagi_executor_with_custom_entrypoint() -> Result<()> {
     let http_config: HttpConfig = cfg.components[0].trigger.clone().try_into()?;
 
     match http_config.executor.as_ref().unwrap() {
-        HttpExecutor::Spin => panic!("expected wagi http executor"),
+        HttpExecutor::Spin | HttpExecutor::Static(_) => panic!("expected wagi http executor"),
         HttpExecutor::Wagi(spin_manifest::WagiConfig { entrypoint, argv }) => {
             assert_eq!(entrypoint, EXPECTED_CUSTOM_ENTRYPOINT);
             assert_eq!(argv, EXPECTED_DEFAULT_ARGV);
@@ -145,7 +283,7 @@ async fn test_duplicate_component_id_is_rejected() -> Result<()> {
 
     let temp_dir = tempfile::tempdir()?;
     let dir = temp_dir.path();
-    let app = from_file(MANIFEST, dir, &None, false).await;
+    let app = from_file(MANIFEST, dir, &None, false, false).await;
 
     assert!(
         app.is_err(),
@@ -167,7 +305,7 @@ async fn test_insecure_allow_all_with_invalid_url() -> Result<()> {
 
     let temp_dir = tempfile::tempdir()?;
     let dir = temp_dir.path();
-    let app = from_file(MANIFEST, dir, &None, false).await;
+    let app = from_file(MANIFEST, dir, &None, false, false).await;
 
     assert!(
         app.is_ok(),
@@ -183,7 +321,7 @@ async fn test_invalid_url_in_allowed_http_hosts_is_rejected() -> Result<()> {
 
     let temp_dir = tempfile::tempdir()?;
     let dir = temp_dir.path();
-    let app = from_file(MANIFEST, dir, &None, false).await;
+    let app = from_file(MANIFEST, dir, &None, false, false).await;
 
     assert!(
         app.is_err(),
@@ -198,3 +336,669 @@ async fn test_invalid_url_in_allowed_http_hosts_is_rejected() -> Result<()> {
 
     Ok(())
 }
+
+#[tokio::test]
+async fn test_direct_mounts_respect_excluded_files() -> Result<()> {
+    let src = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/valid-with-files");
+    let placement = |source: &str, destination: &str| {
+        RawFileMount::Placement(RawDirectoryPlacement {
+            source: PathBuf::from(source),
+            destination: PathBuf::from(destination),
+        })
+    };
+    let raw_mounts = vec![
+        placement("static/alphabet", "/alphabet"),
+        placement("static/numbers", "/numbers"),
+    ];
+    let exclude_files = vec!["static/numbers/2".to_owned()];
+
+    let temp_dir = tempfile::tempdir()?;
+    let mounts = assets::prepare_component(
+        &raw_mounts,
+        &src,
+        temp_dir.path(),
+        "fs",
+        false,
+        true,
+        &exclude_files,
+        &Default::default(),
+    )
+    .await?;
+
+    assert_eq!(mounts.len(), 2);
+    assert_eq!(mounts[1].guest, "/alphabet");
+    assert_eq!(mounts[1].host, src.join("static/alphabet"));
+    let staged = &mounts[0].host;
+    assert!(staged.join("numbers/1").exists());
+    assert!(!staged.join("numbers/2").exists());
+    assert!(!staged.join("alphabet").exists());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_components_from_included_files() -> Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let dir = temp_dir.path();
+    std::fs::create_dir(dir.join("components"))?;
+    std::fs::write(
+        dir.join("spin.toml"),
+        r#"
+        spin_version = "1"
+        name = "included"
+        version = "1.0.0"
+        trigger = { type = "http", base = "/" }
+        components_from = ["components/*.toml"]
+
+        [[component]]
+        id = "main"
+        source = "main.wasm"
+        [component.trigger]
+        route = "/main"
+        "#,
+    )?;
+    std::fs::write(
+        dir.join("components/included.toml"),
+        r#"
+        id = "included"
+        source = "included.wasm"
+        [trigger]
+        route = "/included"
+        "#,
+    )?;
+
+    let RawAppManifestAnyVersion::V1(cfg) = raw_manifest_from_file(&dir.join("spin.toml")).await?;
+    let ids: Vec<_> = cfg.components.iter().map(|c| c.id.as_str()).collect();
+    assert_eq!(ids, ["main", "included"]);
+    assert_eq!(
+        cfg.components[1].origin,
+        Some(dir.join("components/included.toml"))
+    );
+
+    std::fs::write(
+        dir.join("components/duplicate.toml"),
+        r#"
+        id = "main"
+        source = "duplicate.wasm"
+        [trigger]
+        route = "/duplicate"
+        "#,
+    )?;
+    let app = from_file(
+        dir.join("spin.toml"),
+        dir.join("assets"),
+        &None,
+        false,
+        false,
+    )
+    .await;
+    let e = format!("{:#}", app.unwrap_err());
+    assert!(
+        e.contains("duplicate.toml"),
+        "Expected error to name the file of the duplicate component: {}",
+        e
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_external_sources_must_be_allowed() -> Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let (app_dir, shared_dir) = (temp_dir.path().join("app"), temp_dir.path().join("shared"));
+    std::fs::create_dir_all(&app_dir)?;
+    std::fs::create_dir_all(shared_dir.join("static"))?;
+    std::fs::write(shared_dir.join("auth.wasm"), b"\0asm")?;
+    std::fs::write(shared_dir.join("static/logo.svg"), "<svg/>")?;
+    let manifest = |external_source_dirs: &str| {
+        format!(
+            r#"
+            spin_version = "1"
+            name = "monorepo"
+            version = "1.0.0"
+            trigger = {{ type = "http", base = "/" }}
+            {}
+
+            [[component]]
+            id = "auth"
+            source = "../shared/auth.wasm"
+            files = [ {{ source = "../shared/static", destination = "/static" }} ]
+            [component.trigger]
+            route = "/auth"
+            "#,
+            external_source_dirs
+        )
+    };
+
+    std::fs::write(app_dir.join("spin.toml"), manifest(""))?;
+    let app = from_file(
+        app_dir.join("spin.toml"),
+        temp_dir.path().join("assets"),
+        &None,
+        false,
+        false,
+    )
+    .await;
+    let e = format!("{:#}", app.unwrap_err());
+    assert!(
+        e.contains("outside the application directory"),
+        "Expected error to reject the external source: {}",
+        e
+    );
+
+    std::fs::write(
+        app_dir.join("spin.toml"),
+        manifest(r#"external_source_dirs = ["../shared"]"#),
+    )?;
+    let app = from_file(
+        app_dir.join("spin.toml"),
+        temp_dir.path().join("assets"),
+        &None,
+        false,
+        false,
+    )
+    .await?;
+    let component = &app.components[0];
+    match &component.source {
+        ModuleSource::FileReference(path) => assert!(path.exists()),
+        _ => panic!("Expected a file reference"),
+    }
+    assert!(component.wasm.mounts[0]
+        .host
+        .join("static/logo.svg")
+        .exists());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_environment_file() -> Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let dir = temp_dir.path();
+    std::fs::write(dir.join("hello.wasm"), b"\0asm")?;
+    std::fs::write(
+        dir.join("hello.env"),
+        "GREETING=hi\nDATABASE_URL='sqlite://dev.db'\n",
+    )?;
+    std::fs::write(
+        dir.join("spin.toml"),
+        r#"
+        spin_version = "1"
+        name = "app"
+        version = "1.0.0"
+        trigger = { type = "http", base = "/" }
+
+        [[component]]
+        id = "hello"
+        source = "hello.wasm"
+        environment = { GREETING = "hello", MODE = "debug" }
+        environment_file = "hello.env"
+        [component.trigger]
+        route = "/hello"
+        "#,
+    )?;
+    let app = from_file(
+        dir.join("spin.toml"),
+        dir.join("assets"),
+        &None,
+        false,
+        false,
+    )
+    .await?;
+    let environment = &app.components[0].wasm.environment;
+    assert_eq!(environment["GREETING"], "hi");
+    assert_eq!(environment["MODE"], "debug");
+    assert_eq!(environment["DATABASE_URL"], "sqlite://dev.db");
+
+    std::fs::remove_file(dir.join("hello.env"))?;
+    let app = from_file(
+        dir.join("spin.toml"),
+        dir.join("assets"),
+        &None,
+        false,
+        false,
+    )
+    .await;
+    let e = format!("{:#}", app.unwrap_err());
+    assert!(
+        e.contains("environment_file of component hello"),
+        "Expected error to name the component: {}",
+        e
+    );
+    Ok(())
+}
+
+#[test]
+fn test_collect_patterns_in_dir_with_glob_characters() -> Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let dir = temp_dir.path().join("app [1]");
+    std::fs::create_dir_all(dir.join("static/nested"))?;
+    std::fs::write(dir.join("static/a.txt"), "a")?;
+    std::fs::write(dir.join("static/b.md"), "b")?;
+    std::fs::write(dir.join("static/nested/c.txt"), "c")?;
+
+    let raw_mounts = vec![RawFileMount::Pattern("static/*.txt".to_owned())];
+    let files = assets::collect(&raw_mounts, &[], &dir)?;
+    let dsts: Vec<_> = files.iter().map(|f| f.relative_dst.as_str()).collect();
+    assert_eq!(dsts, ["static/a.txt"]);
+
+    let raw_mounts = vec![RawFileMount::Pattern("static/**/*.txt".to_owned())];
+    let mut files = assets::collect(&raw_mounts, &["static/a.txt".to_owned()], &dir)?;
+    files.sort_by(|a, b| a.relative_dst.cmp(&b.relative_dst));
+    let dsts: Vec<_> = files.iter().map(|f| f.relative_dst.as_str()).collect();
+    assert_eq!(dsts, ["static/nested/c.txt"]);
+
+    Ok(())
+}
+
+#[cfg(windows)]
+#[tokio::test]
+async fn test_collect_windows_patterns_and_long_paths() -> Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    // Longer than the legacy 260 character limit, so only usable in the
+    // extended-length form.
+    let long_dir = "d".repeat(120);
+    let dir = temp_dir
+        .path()
+        .canonicalize()?
+        .join(&long_dir)
+        .join(&long_dir);
+    assert!(dir.to_string_lossy().starts_with(r"\\?\"));
+    std::fs::create_dir_all(dir.join("static"))?;
+    std::fs::write(dir.join("static").join("a.txt"), "a")?;
+
+    let raw_mounts = vec![
+        RawFileMount::Pattern(r"static\*.txt".to_owned()),
+        RawFileMount::Placement(RawDirectoryPlacement {
+            source: PathBuf::from("static"),
+            destination: PathBuf::from("/assets"),
+        }),
+    ];
+    let files = assets::collect(&raw_mounts, &[], &dir)?;
+    let dsts: Vec<_> = files.iter().map(|f| f.relative_dst.as_str()).collect();
+    assert_eq!(dsts, ["static/a.txt", "assets/a.txt"]);
+
+    let temp_dst = tempfile::tempdir()?;
+    let mounts = assets::prepare_component(
+        &raw_mounts,
+        &dir,
+        temp_dst.path(),
+        "long",
+        false,
+        false,
+        &[],
+        &Default::default(),
+    )
+    .await?;
+    assert!(mounts[0].host.join("assets").join("a.txt").exists());
+
+    Ok(())
+}
+
+#[test]
+fn test_external_trigger() -> Result<()> {
+    let manifest = |trigger: &str, component_trigger: &str| {
+        toml::from_str::<RawAppManifestAnyVersion>(&format!(
+            r#"
+            spin_version = "1"
+            name = "app"
+            version = "1.0.0"
+            trigger = {}
+
+            [[component]]
+            id = "ticker"
+            source = "ticker.wasm"
+            [component.trigger]
+            {}
+            "#,
+            trigger, component_trigger
+        ))
+    };
+
+    let cfg = manifest(
+        r#"{ type = "command:timer", speedup = 2 }"#,
+        r#"interval_secs = 5"#,
+    )?;
+    validate_raw_app_manifest(&cfg)?;
+    let RawAppManifestAnyVersion::V1(raw) = cfg;
+    let external = match &raw.info.trigger {
+        ApplicationTrigger::External(external) => external,
+        _ => panic!("Expected an external trigger"),
+    };
+    assert_eq!(external.command, "timer");
+    assert_eq!(external.config["speedup"], toml::Value::Integer(2));
+    match &raw.components[0].trigger {
+        TriggerConfig::External(table) => {
+            assert_eq!(table["interval_secs"], toml::Value::Integer(5))
+        }
+        _ => panic!("Expected an external component trigger"),
+    }
+
+    // The trigger type survives serialization, as in lock files.
+    let value = toml::Value::try_from(&raw.info.trigger)?;
+    assert_eq!(value["type"].as_str(), Some("command:timer"));
+    assert_eq!(value.try_into::<ApplicationTrigger>()?, raw.info.trigger);
+
+    // A component trigger that is not an HTTP trigger is reported as such.
+    let invalid = manifest(r#"{ type = "http", base = "/" }"#, r#"rout = "/""#)?;
+    let err = validate_raw_app_manifest(&invalid).unwrap_err();
+    assert!(format!("{:#}", err).contains("route"));
+
+    assert!(manifest(r#"{ type = "command:" }"#, r#"route = "/""#).is_err());
+    assert!(manifest(r#"{ type = "mqtt" }"#, r#"route = "/""#).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_request_config() -> Result<()> {
+    let manifest = |request_config: &str| {
+        toml::from_str::<RawAppManifestAnyVersion>(&format!(
+            r#"
+            spin_version = "1"
+            name = "app"
+            version = "1.0.0"
+            trigger = {{ type = "http", base = "/" }}
+
+            [[component]]
+            id = "users"
+            source = "users.wasm"
+            [component.trigger]
+            route = "/users/:id"
+            [component.trigger.request_config]
+            {}
+            "#,
+            request_config
+        ))
+    };
+
+    let cfg = manifest("user_id = \"path.id\"\ntenant = \"header.X-Tenant\"")?;
+    validate_raw_app_manifest(&cfg)?;
+    let RawAppManifestAnyVersion::V1(raw) = cfg;
+    let http = HttpConfig::try_from(raw.components[0].trigger.clone())?;
+    assert_eq!(
+        http.request_config["user_id"],
+        spin_manifest::RequestConfigSource::PathParam("id".to_owned())
+    );
+    assert_eq!(
+        http.request_config["tenant"],
+        spin_manifest::RequestConfigSource::Header("x-tenant".to_owned())
+    );
+
+    let unknown_param = manifest("user_id = \"path.user\"")?;
+    assert!(validate_raw_app_manifest(&unknown_param).is_err());
+    let invalid_key = manifest("UserId = \"path.id\"")?;
+    assert!(validate_raw_app_manifest(&invalid_key).is_err());
+    // Read as an external trigger table, and reported when validated.
+    let invalid_source = manifest("user_id = \"cookie.id\"")?;
+    assert!(validate_raw_app_manifest(&invalid_source).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_route_syntax() -> Result<()> {
+    let manifest = |route: &str| {
+        toml::from_str::<RawAppManifestAnyVersion>(&format!(
+            r#"
+            spin_version = "1"
+            name = "app"
+            version = "1.0.0"
+            trigger = {{ type = "http", base = "/" }}
+
+            [[component]]
+            id = "users"
+            source = "users.wasm"
+            [component.trigger]
+            route = "{}"
+            "#,
+            route
+        ))
+    };
+
+    for valid in ["/", "/...", "/users/:id", "/users/:id/posts/:post_id/..."] {
+        validate_raw_app_manifest(&manifest(valid)?)?;
+    }
+    for invalid in [
+        "/.../users",
+        "/users...",
+        "/users/:",
+        "/users/:user-id",
+        "/users/:id/:id",
+        "/users?id=1",
+        "/user s",
+    ] {
+        assert!(
+            validate_raw_app_manifest(&manifest(invalid)?).is_err(),
+            "{} should be rejected",
+            invalid
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn test_allowed_database_hosts() -> Result<()> {
+    let manifest = |hosts: &str| {
+        toml::from_str::<RawAppManifestAnyVersion>(&format!(
+            r#"
+            spin_version = "1"
+            name = "app"
+            version = "1.0.0"
+            trigger = {{ type = "http", base = "/" }}
+
+            [[component]]
+            id = "orders"
+            source = "orders.wasm"
+            allowed_database_hosts = {}
+            [component.trigger]
+            route = "/orders"
+            "#,
+            hosts
+        ))
+    };
+
+    let cfg = manifest(r#"["db.example.com:5432", "mysql.internal", "[::1]:3306"]"#)?;
+    validate_raw_app_manifest(&cfg)?;
+
+    let allowed = [
+        "db.example.com:5432".to_owned(),
+        "mysql.internal".to_owned(),
+        "[::1]:3306".to_owned(),
+    ];
+    let allows = |host, port| spin_manifest::allows_database_host(&allowed, host, port);
+    assert!(allows("db.example.com", 5432));
+    assert!(allows("DB.example.com", 5432));
+    assert!(!allows("db.example.com", 5433));
+    assert!(allows("mysql.internal", 3306));
+    assert!(allows("mysql.internal", 13306));
+    assert!(allows("::1", 3306));
+    assert!(!allows("localhost", 5432));
+
+    for invalid in [
+        r#"["postgres://db.example.com"]"#,
+        r#"["db.example.com:port"]"#,
+        r#"["user@db.example.com"]"#,
+        r#"[""]"#,
+    ] {
+        assert!(validate_raw_app_manifest(&manifest(invalid)?).is_err());
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_asset_limits() -> Result<()> {
+    let src = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/valid-with-files");
+    let raw_mounts = vec![RawFileMount::Pattern("static/**/*".to_owned())];
+    let prepare = |limits: spin_manifest::ComponentLimits| {
+        let (raw_mounts, src) = (raw_mounts.clone(), src.clone());
+        async move {
+            let temp_dir = tempfile::tempdir()?;
+            assets::prepare_component(
+                &raw_mounts,
+                &src,
+                temp_dir.path(),
+                "fs",
+                false,
+                false,
+                &[],
+                &limits,
+            )
+            .await
+        }
+    };
+
+    prepare(Default::default()).await?;
+
+    let err = prepare(spin_manifest::ComponentLimits {
+        max_asset_files: Some(1),
+        ..Default::default()
+    })
+    .await
+    .unwrap_err();
+    let message = format!("{:#}", err);
+    assert!(message.contains("more than the limit of 1"), "{}", message);
+    assert!(message.contains("static/"), "{}", message);
+    assert!(message.contains("max_asset_files"), "{}", message);
+
+    let err = prepare(spin_manifest::ComponentLimits {
+        max_asset_bytes: Some(0),
+        ..Default::default()
+    })
+    .await
+    .unwrap_err();
+    assert!(format!("{:#}", err).contains("max_asset_bytes"));
+
+    Ok(())
+}
+
+#[test]
+fn test_collect_matches() -> Result<()> {
+    let src = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/valid-with-files");
+    let raw_mounts = vec![
+        RawFileMount::Pattern("static/alphabet/*".to_owned()),
+        RawFileMount::Pattern("static/missing/*".to_owned()),
+        RawFileMount::Pattern("static/numbers/*".to_owned()),
+        RawFileMount::Placement(RawDirectoryPlacement {
+            source: PathBuf::from("static/alphabet"),
+            destination: PathBuf::from("/letters"),
+        }),
+    ];
+    let exclude_files = vec![
+        "static/numbers/*".to_owned(),
+        "static/alphabet/c".to_owned(),
+    ];
+
+    let matches = assets::collect_matches(&raw_mounts, &exclude_files, &src)?;
+    assert_eq!(matches.len(), 4);
+
+    assert_eq!(matches[0].mount, "static/alphabet/*");
+    assert_eq!(matches[0].included.len(), 2);
+    assert_eq!(matches[0].excluded.len(), 1);
+    assert_eq!(matches[0].excluded[0].1, "static/alphabet/c");
+    assert!(matches[0].warning().is_none());
+
+    assert!(matches[1].warning().unwrap().contains("matches no files"));
+    assert!(matches[2]
+        .warning()
+        .unwrap()
+        .contains("only matches files excluded"));
+
+    assert_eq!(matches[3].mount, "static/alphabet -> /letters");
+    let mut dsts: Vec<_> = matches[3]
+        .included
+        .iter()
+        .map(|f| f.relative_dst.as_str())
+        .collect();
+    dsts.sort_unstable();
+    assert_eq!(dsts, ["letters/a", "letters/b"]);
+
+    let collected = assets::collect(&raw_mounts, &exclude_files, &src)?;
+    assert_eq!(collected.len(), 4);
+    Ok(())
+}
+
+#[test]
+fn test_json_schema() -> Result<()> {
+    let schema = serde_json::to_value(config::json_schema())?;
+    let schema = jsonschema::JSONSchema::compile(&schema).expect("invalid manifest schema");
+    let validate = |path: &str| -> Result<bool> {
+        let manifest: toml::Value = toml::from_str(&std::fs::read_to_string(path)?)?;
+        Ok(schema.is_valid(&serde_json::to_value(manifest)?))
+    };
+
+    for valid in [
+        "tests/valid-manifest.toml",
+        "tests/valid-with-files/spin.toml",
+        "tests/wagi-custom-entrypoint.toml",
+    ] {
+        assert!(validate(valid)?, "{} should match the schema", valid);
+    }
+    assert!(!validate("tests/invalid-version.toml")?);
+
+    let unknown_field: toml::Value = toml::from_str(
+        r#"
+        spin_version = "1"
+        name = "app"
+        version = "1.0.0"
+        trigger = { type = "http", base = "/" }
+
+        [[component]]
+        id = "hello"
+        source = "hello.wasm"
+        sauce = "hot"
+        [component.trigger]
+        route = "/hello"
+        "#,
+    )?;
+    assert!(!schema.is_valid(&serde_json::to_value(unknown_field)?));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_components_are_prepared_in_manifest_order() -> Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let src = temp_dir.path().join("app");
+    std::fs::create_dir_all(src.join("static"))?;
+    std::fs::write(src.join("static/index.html"), "<h1>Spin</h1>")?;
+    std::fs::write(src.join("hello.wasm"), "")?;
+
+    // More components than are prepared at once.
+    let ids: Vec<_> = (0..crate::MAX_PARALLEL_COMPONENT_PROCESSING * 2 + 1)
+        .map(|i| format!("component-{}", i))
+        .collect();
+    let mut manifest = r#"
+spin_version = "1"
+name = "many-components"
+trigger = {type = "http", base = "/"}
+version = "1.0.0"
+"#
+    .to_owned();
+    for id in &ids {
+        manifest.push_str(&format!(
+            r#"
+[[component]]
+id = "{id}"
+source = "hello.wasm"
+files = ["static/*"]
+[component.trigger]
+route = "/{id}"
+"#,
+            id = id
+        ));
+    }
+    std::fs::write(src.join("spin.toml"), manifest)?;
+
+    let staging = temp_dir.path().join("staging");
+    let app = from_file(src.join("spin.toml"), &staging, &None, false, false).await?;
+
+    let prepared: Vec<_> = app.components.iter().map(|c| c.id.clone()).collect();
+    assert_eq!(prepared, ids);
+    for component in &app.components {
+        assert_eq!(component.wasm.mounts.len(), 1);
+        assert!(component.wasm.mounts[0]
+            .host
+            .join("static/index.html")
+            .exists());
+    }
+
+    Ok(())
+}
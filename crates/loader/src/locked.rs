@@ -0,0 +1,674 @@
+//! Locked Spin applications: applications prepared ahead of time, for example
+//! when building a container image, and written to a lock file that a trigger
+//! runs without loading a manifest.
+//!
+//! A lock file records a content hash of its canonical serialization, and the
+//! digests of the modules, mounted directories and trigger files it references,
+//! so that it can be verified to describe exactly the application reviewed.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::{bail, Context, Result};
+use path_absolutize::Absolutize;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use spin_config::Tree;
+use spin_manifest::{
+    Application, ApplicationInformation, ApplicationOrigin, ApplicationTrigger, ComponentLimits,
+    CoreComponent, DirectoryMount, HttpHandler, LoadPolicy, LogLevel, ModuleSource, SpinVersion,
+    TriggerConfig, WasmConfig,
+};
+
+/// The version of the lock file format.
+pub const LOCK_VERSION: u32 = 1;
+
+/// A prepared application, as written to a lock file. Paths are relative to
+/// the directory of the lock file, or absolute if outside it.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub struct LockedApp {
+    /// The version of the lock file format.
+    pub spin_lock_version: u32,
+    /// Name of the application.
+    pub name: String,
+    /// Version of the application.
+    pub version: String,
+    /// Description of the application.
+    pub description: Option<String>,
+    /// Authors of the application.
+    #[serde(default)]
+    pub authors: Vec<String>,
+    /// Namespace for grouping applications.
+    pub namespace: Option<String>,
+    /// Trigger for the application.
+    pub trigger: ApplicationTrigger,
+    /// Application configuration, with the defaults of the components.
+    #[serde(default)]
+    pub config: Tree,
+    /// The components of the application.
+    #[serde(default, rename = "component")]
+    pub components: Vec<LockedComponent>,
+    /// Digests of the files of the trigger, by their path in the trigger.
+    #[serde(default)]
+    pub trigger_digests: BTreeMap<PathBuf, String>,
+    /// The hash of the canonical serialization of the lock file, without
+    /// this field.
+    pub content_hash: Option<String>,
+}
+
+/// A prepared component, as written to a lock file.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub struct LockedComponent {
+    /// ID of the component.
+    pub id: String,
+    /// Description of the component.
+    pub description: Option<String>,
+    /// Path to the Wasm module of the component.
+    pub source: PathBuf,
+    /// Digest of the Wasm module of the component.
+    pub source_digest: Option<String>,
+    /// Environment variables of the component.
+    #[serde(default)]
+    pub environment: HashMap<String, String>,
+    /// Directories mounted in the component.
+    #[serde(default)]
+    pub files: Vec<LockedMount>,
+    /// Hosts the component is allowed to make HTTP requests to.
+    #[serde(default)]
+    pub allowed_http_hosts: Vec<String>,
+    /// Blob store containers the component is allowed to access.
+    #[serde(default)]
+    pub allowed_blob_containers: Vec<String>,
+    /// Database hosts the component is allowed to connect to.
+    #[serde(default)]
+    pub allowed_database_hosts: Vec<String>,
+    /// Key-value stores the component is allowed to access.
+    #[serde(default)]
+    pub key_value_stores: Vec<String>,
+    /// JWT issuers the component is allowed to issue tokens with.
+    #[serde(default)]
+    pub allowed_jwt_issuers: Vec<String>,
+    /// Cryptography keys the component is allowed to use.
+    #[serde(default)]
+    pub allowed_crypto_keys: Vec<String>,
+    /// When the module of the component is compiled.
+    #[serde(default)]
+    pub load: LoadPolicy,
+    /// Limits on the resources the component uses.
+    #[serde(default)]
+    pub limits: ComponentLimits,
+    /// The least severe output of the component that is logged.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub log_level: Option<LogLevel>,
+    /// Configuration for host components, by host component name.
+    #[serde(default)]
+    pub host_config: HashMap<String, toml::Value>,
+    /// Trigger configuration of the component.
+    pub trigger: TriggerConfig,
+}
+
+/// A directory mounted in a locked component.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub struct LockedMount {
+    /// Path of the directory in the component.
+    pub guest: String,
+    /// Path of the directory on the host.
+    pub host: PathBuf,
+    /// Digest of the files of the directory.
+    pub digest: Option<String>,
+}
+
+/// An artifact referenced by a lock file whose content does not match the
+/// lock file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LockMismatch {
+    /// The artifact, such as the module of a component.
+    pub artifact: String,
+    /// The digest recorded in the lock file.
+    pub expected: Option<String>,
+    /// The digest of the artifact, or why it cannot be computed.
+    pub actual: Result<String, String>,
+}
+
+impl std::fmt::Display for LockMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: ", self.artifact)?;
+        match &self.expected {
+            Some(expected) => write!(f, "expected {}, ", expected)?,
+            None => write!(f, "no digest recorded, ")?,
+        }
+        match &self.actual {
+            Ok(actual) => write!(f, "found {}", actual),
+            Err(e) => write!(f, "cannot be read: {}", e),
+        }
+    }
+}
+
+/// The result of verifying a lock file against the artifacts it references.
+#[derive(Clone, Debug)]
+pub struct Verification {
+    /// The content hash recorded in the lock file.
+    pub content_hash: String,
+    /// The number of artifacts whose digests were verified.
+    pub artifacts: usize,
+    /// The content hash and artifacts that do not match the lock file.
+    pub mismatches: Vec<LockMismatch>,
+}
+
+/// Writes a prepared application to the given lock file. The modules and
+/// mounted directories of its components must outlive the lock file, so must
+/// not be in a temporary directory.
+pub async fn write(app: &Application, lock_file: impl AsRef<Path>) -> Result<()> {
+    let lock_file = lock_file.as_ref();
+    let dir = lock_dir(lock_file)?;
+    let mut locked = LockedApp::new(app, &dir)?;
+    locked.seal(&dir)?;
+    let text = toml::Value::try_from(&locked)
+        .and_then(|value| toml::to_string_pretty(&value))
+        .context("Failed to serialize the locked application")?;
+    tokio::fs::write(lock_file, text)
+        .await
+        .with_context(|| format!("Failed to write lock file {}", lock_file.display()))
+}
+
+/// Loads the application written to the given lock file.
+pub async fn from_file(lock_file: impl AsRef<Path>) -> Result<Application> {
+    let lock_file = lock_file.as_ref();
+    let text = tokio::fs::read_to_string(lock_file)
+        .await
+        .with_context(|| format!("Failed to read lock file {}", lock_file.display()))?;
+    let locked: LockedApp = toml::from_str(&text)
+        .with_context(|| format!("Invalid lock file {}", lock_file.display()))?;
+    locked.into_application(&lock_dir(lock_file)?, lock_file.absolutize()?.into_owned())
+}
+
+/// Recomputes the content hash of the given lock file and the digests of the
+/// artifacts it references, and compares them with those it records.
+pub async fn verify(lock_file: impl AsRef<Path>) -> Result<Verification> {
+    let lock_file = lock_file.as_ref();
+    let text = tokio::fs::read_to_string(lock_file)
+        .await
+        .with_context(|| format!("Failed to read lock file {}", lock_file.display()))?;
+    let locked: LockedApp = toml::from_str(&text)
+        .with_context(|| format!("Invalid lock file {}", lock_file.display()))?;
+    let dir = lock_dir(lock_file)?;
+    tokio::task::spawn_blocking(move || locked.verify(&dir)).await?
+}
+
+impl LockedApp {
+    /// Locks a prepared application, with paths relative to the given
+    /// absolute directory where they are inside it.
+    pub fn new(app: &Application, dir: &Path) -> Result<Self> {
+        let relative = |path: &Path| -> Result<PathBuf> {
+            let path = path.absolutize()?;
+            Ok(path.strip_prefix(dir).unwrap_or(&path).to_path_buf())
+        };
+
+        let mut trigger = app.info.trigger.clone();
+        for file in trigger_files(&mut trigger) {
+            *file = relative(file)?;
+        }
+
+        let components = app
+            .components
+            .iter()
+            .map(|c| {
+                let source = match &c.source {
+                    ModuleSource::FileReference(path) => relative(path)?,
+                    ModuleSource::Buffer(_, _) => bail!(
+                        "Component {} is not loaded from a local file, so cannot be locked",
+                        c.id
+                    ),
+                };
+                let files = c
+                    .wasm
+                    .mounts
+                    .iter()
+                    .map(|m| {
+                        Ok(LockedMount {
+                            guest: m.guest.clone(),
+                            host: relative(&m.host)?,
+                            digest: None,
+                        })
+                    })
+                    .collect::<Result<_>>()?;
+                Ok(LockedComponent {
+                    id: c.id.clone(),
+                    description: c.description.clone(),
+                    source,
+                    source_digest: None,
+                    environment: c.wasm.environment.clone(),
+                    files,
+                    allowed_http_hosts: c.wasm.allowed_http_hosts.clone(),
+                    allowed_blob_containers: c.wasm.allowed_blob_containers.clone(),
+                    allowed_database_hosts: c.wasm.allowed_database_hosts.clone(),
+                    key_value_stores: c.wasm.key_value_stores.clone(),
+                    allowed_jwt_issuers: c.wasm.allowed_jwt_issuers.clone(),
+                    allowed_crypto_keys: c.wasm.allowed_crypto_keys.clone(),
+                    load: c.wasm.load,
+                    limits: c.wasm.limits.clone(),
+                    log_level: c.wasm.log_level,
+                    host_config: c.wasm.host_config.clone(),
+                    trigger: app
+                        .component_triggers
+                        .get(&c.id)
+                        .cloned()
+                        .with_context(|| format!("Component {} has no trigger", c.id))?,
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self {
+            spin_lock_version: LOCK_VERSION,
+            name: app.info.name.clone(),
+            version: app.info.version.clone(),
+            description: app.info.description.clone(),
+            authors: app.info.authors.clone(),
+            namespace: app.info.namespace.clone(),
+            trigger,
+            config: app
+                .config_resolver
+                .as_ref()
+                .map(|resolver| resolver.tree().clone())
+                .unwrap_or_default(),
+            components,
+            trigger_digests: BTreeMap::new(),
+            content_hash: None,
+        })
+    }
+
+    /// Records the digests of the artifacts referenced by the locked
+    /// application, with relative paths resolved against the given directory,
+    /// then its content hash.
+    pub fn seal(&mut self, dir: &Path) -> Result<()> {
+        for c in &mut self.components {
+            c.source_digest =
+                Some(file_digest(&dir.join(&c.source)).with_context(|| {
+                    format!("Failed to digest the module of component {}", c.id)
+                })?);
+            for m in &mut c.files {
+                m.digest = Some(dir_digest(&dir.join(&m.host)).with_context(|| {
+                    format!(
+                        "Failed to digest directory {} of component {}",
+                        m.guest, c.id
+                    )
+                })?);
+            }
+        }
+        let mut trigger = self.trigger.clone();
+        self.trigger_digests = trigger_files(&mut trigger)
+            .into_iter()
+            .map(|file| {
+                let digest = file_digest(&dir.join(&file))
+                    .with_context(|| format!("Failed to digest {}", file.display()))?;
+                Ok((file.clone(), digest))
+            })
+            .collect::<Result<_>>()?;
+        self.content_hash = Some(self.compute_content_hash()?);
+        Ok(())
+    }
+
+    /// Compares the content hash and artifact digests recorded in the locked
+    /// application with those computed, with relative paths resolved against
+    /// the given directory.
+    pub fn verify(&self, dir: &Path) -> Result<Verification> {
+        let content_hash = self
+            .content_hash
+            .clone()
+            .context("The lock file has no content hash: write it again to record one")?;
+        let mut mismatches = vec![];
+        let actual = self.compute_content_hash()?;
+        if actual != content_hash {
+            mismatches.push(LockMismatch {
+                artifact: "lock file content".into(),
+                expected: Some(content_hash.clone()),
+                actual: Ok(actual),
+            });
+        }
+
+        let mut artifacts = 0;
+        let mut check = |artifact: String, expected: &Option<String>, actual: Result<String>| {
+            artifacts += 1;
+            let actual = actual.map_err(|e| format!("{:#}", e));
+            if expected.is_none() || actual.as_ref().ok() != expected.as_ref() {
+                mismatches.push(LockMismatch {
+                    artifact,
+                    expected: expected.clone(),
+                    actual,
+                });
+            }
+        };
+
+        for c in &self.components {
+            check(
+                format!("module of component {}", c.id),
+                &c.source_digest,
+                file_digest(&dir.join(&c.source)),
+            );
+            for m in &c.files {
+                check(
+                    format!("directory {} of component {}", m.guest, c.id),
+                    &m.digest,
+                    dir_digest(&dir.join(&m.host)),
+                );
+            }
+        }
+        let mut trigger = self.trigger.clone();
+        for file in trigger_files(&mut trigger) {
+            check(
+                format!("trigger file {}", file.display()),
+                &self.trigger_digests.get(file.as_path()).cloned(),
+                file_digest(&dir.join(&file)),
+            );
+        }
+
+        Ok(Verification {
+            content_hash,
+            artifacts,
+            mismatches,
+        })
+    }
+
+    /// The hash of the canonical serialization of the locked application:
+    /// JSON with the keys of every object sorted, without the content hash.
+    pub fn compute_content_hash(&self) -> Result<String> {
+        let mut value =
+            serde_json::to_value(self).context("Failed to serialize the locked application")?;
+        if let serde_json::Value::Object(map) = &mut value {
+            map.remove("content_hash");
+        }
+        Ok(format!(
+            "sha256:{:x}",
+            Sha256::digest(sort_keys(value).to_string())
+        ))
+    }
+
+    /// The locked application, with relative paths resolved against the
+    /// given directory, as loaded from the given lock file.
+    pub fn into_application(self, dir: &Path, lock_file: PathBuf) -> Result<Application> {
+        if self.spin_lock_version != LOCK_VERSION {
+            bail!(
+                "Unsupported lock file version {}: this version of Spin supports version {}",
+                self.spin_lock_version,
+                LOCK_VERSION
+            );
+        }
+
+        let mut trigger = self.trigger;
+        for file in trigger_files(&mut trigger) {
+            *file = dir.join(&file);
+        }
+
+        let component_triggers = self
+            .components
+            .iter()
+            .map(|c| (c.id.clone(), c.trigger.clone()))
+            .collect();
+        let components = self
+            .components
+            .into_iter()
+            .map(|c| CoreComponent {
+                source: ModuleSource::FileReference(dir.join(c.source)),
+                id: c.id,
+                description: c.description,
+                wasm: WasmConfig {
+                    environment: c.environment,
+                    mounts: c
+                        .files
+                        .into_iter()
+                        .map(|m| DirectoryMount {
+                            guest: m.guest,
+                            host: dir.join(m.host),
+                        })
+                        .collect(),
+                    allowed_http_hosts: c.allowed_http_hosts,
+                    allowed_blob_containers: c.allowed_blob_containers,
+                    allowed_database_hosts: c.allowed_database_hosts,
+                    key_value_stores: c.key_value_stores,
+                    allowed_jwt_issuers: c.allowed_jwt_issuers,
+                    allowed_crypto_keys: c.allowed_crypto_keys,
+                    load: c.load,
+                    limits: c.limits,
+                    log_level: c.log_level,
+                    host_config: c.host_config,
+                },
+            })
+            .collect();
+
+        Ok(Application {
+            info: ApplicationInformation {
+                spin_version: SpinVersion::V1,
+                name: self.name,
+                version: self.version,
+                description: self.description,
+                authors: self.authors,
+                trigger,
+                namespace: self.namespace,
+                origin: ApplicationOrigin::File(lock_file),
+            },
+            components,
+            component_triggers,
+            config_resolver: Some(Arc::new(spin_config::Resolver::new(self.config)?)),
+        })
+    }
+}
+
+/// The absolute directory of a lock file.
+fn lock_dir(lock_file: &Path) -> Result<PathBuf> {
+    let lock_file = lock_file
+        .absolutize()
+        .with_context(|| format!("Failed to resolve lock file path {}", lock_file.display()))?;
+    Ok(lock_file
+        .parent()
+        .unwrap_or_else(|| Path::new("/"))
+        .to_path_buf())
+}
+
+/// The digest of a file.
+fn file_digest(path: &Path) -> Result<String> {
+    let digest = crate::file_sha256_digest_string(path)
+        .with_context(|| format!("Cannot open file {}", path.display()))?;
+    Ok(format!("sha256:{}", digest))
+}
+
+/// The digest of the files in a directory: of their '/'-separated paths in
+/// the directory and the digests of their content, sorted by path so that it
+/// does not depend on the order in which the filesystem lists files.
+fn dir_digest(dir: &Path) -> Result<String> {
+    let mut sha256 = Sha256::new();
+    for entry in walkdir::WalkDir::new(dir)
+        .follow_links(true)
+        .sort_by_file_name()
+    {
+        let entry = entry.with_context(|| format!("Cannot read directory {}", dir.display()))?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let path = entry
+            .path()
+            .strip_prefix(dir)?
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        sha256.update(format!("{} {}\n", path, file_digest(entry.path())?).as_bytes());
+    }
+    Ok(format!("sha256:{:x}", sha256.finalize()))
+}
+
+/// Sorts the keys of every object of a JSON value, so that maps serialize the
+/// same whatever their iteration order.
+fn sort_keys(value: serde_json::Value) -> serde_json::Value {
+    match value {
+        serde_json::Value::Object(map) => serde_json::Value::Object(
+            map.into_iter()
+                .map(|(k, v)| (k, sort_keys(v)))
+                .collect::<BTreeMap<_, _>>()
+                .into_iter()
+                .collect(),
+        ),
+        serde_json::Value::Array(values) => {
+            serde_json::Value::Array(values.into_iter().map(sort_keys).collect())
+        }
+        value => value,
+    }
+}
+
+/// The paths of the files of a trigger: the templates of its native routes
+/// and its TLS certificate and key.
+fn trigger_files(trigger: &mut ApplicationTrigger) -> Vec<&mut PathBuf> {
+    match trigger {
+        ApplicationTrigger::Http(http) => {
+            let mut files: Vec<&mut PathBuf> = http
+                .routes
+                .iter_mut()
+                .filter_map(|route| match &mut route.handler {
+                    HttpHandler::Template(template) => Some(&mut template.template),
+                    _ => None,
+                })
+                .collect();
+            if let Some(tls) = &mut http.tls {
+                files.push(&mut tls.cert);
+                files.push(&mut tls.key);
+            }
+            files
+        }
+        _ => vec![],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_lock_round_trip() -> Result<()> {
+        const MANIFEST: &str = "tests/valid-with-files/spin.toml";
+
+        let temp_dir = tempfile::tempdir()?;
+        let dir = temp_dir.path();
+        let app = crate::from_file(MANIFEST, dir.join("assets"), &None, false, false).await?;
+
+        let lock_file = dir.join("spin.lock");
+        write(&app, &lock_file).await?;
+        let locked: LockedApp = toml::from_str(&std::fs::read_to_string(&lock_file)?)?;
+        // Files prepared next to the lock file are referenced relative to it.
+        assert!(locked.components[0].files[0].host.is_relative());
+
+        let loaded = from_file(&lock_file).await?;
+        assert_eq!(loaded.info.name, app.info.name);
+        assert_eq!(loaded.info.trigger, app.info.trigger);
+        assert_eq!(loaded.info.origin, ApplicationOrigin::File(lock_file));
+        assert_eq!(loaded.components.len(), app.components.len());
+        let (component, expected) = (&loaded.components[0], &app.components[0]);
+        assert_eq!(component.id, expected.id);
+        assert_eq!(component.wasm.mounts[0].host, expected.wasm.mounts[0].host);
+        match (&component.source, &expected.source) {
+            (ModuleSource::FileReference(path), ModuleSource::FileReference(expected)) => {
+                assert_eq!(path, &expected.absolutize()?.into_owned())
+            }
+            _ => panic!("Expected a file reference"),
+        }
+        assert!(loaded.component_triggers.contains_key(&component.id));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_verify_lock() -> Result<()> {
+        const MANIFEST: &str = "tests/valid-with-files/spin.toml";
+
+        let temp_dir = tempfile::tempdir()?;
+        let dir = temp_dir.path();
+        let app = crate::from_file(MANIFEST, dir.join("assets"), &None, false, false).await?;
+        let lock_file = dir.join("spin.lock");
+        write(&app, &lock_file).await?;
+
+        let verification = verify(&lock_file).await?;
+        assert!(verification.content_hash.starts_with("sha256:"));
+        assert_eq!(verification.artifacts, 2);
+        assert_eq!(verification.mismatches, vec![]);
+
+        // Adding a file to a mounted directory changes its digest.
+        let locked: LockedApp = toml::from_str(&std::fs::read_to_string(&lock_file)?)?;
+        std::fs::write(
+            dir.join(&locked.components[0].files[0].host).join("extra"),
+            "",
+        )?;
+        let mismatches = verify(&lock_file).await?.mismatches;
+        assert_eq!(mismatches.len(), 1);
+        assert_eq!(mismatches[0].artifact, "directory / of component fs");
+
+        // Editing the lock file changes its content hash.
+        let mut edited = locked;
+        edited.components[0]
+            .environment
+            .insert("DEBUG".into(), "1".into());
+        assert_ne!(edited.compute_content_hash()?, verification.content_hash);
+        Ok(())
+    }
+
+    #[test]
+    fn test_content_hash_is_canonical() -> Result<()> {
+        let locked: LockedApp = toml::from_str(
+            r#"
+spin_lock_version = 1
+name = "app"
+version = "1.0.0"
+
+[trigger]
+type = "http"
+base = "/"
+
+[[component]]
+id = "api"
+source = "api.wasm"
+environment = { A = "1", B = "2", C = "3" }
+
+[component.trigger]
+route = "/..."
+executor = { type = "spin" }
+"#,
+        )?;
+        let hash = locked.compute_content_hash()?;
+        let mut reordered = locked.clone();
+        reordered.components[0].environment = ["C", "B", "A"]
+            .iter()
+            .zip(["3", "2", "1"])
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        assert_eq!(reordered.compute_content_hash()?, hash);
+
+        // The recorded hash is not part of the content hashed.
+        reordered.content_hash = Some(hash.clone());
+        assert_eq!(reordered.compute_content_hash()?, hash);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_unsupported_lock_version() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let lock_file = temp_dir.path().join("spin.lock");
+        std::fs::write(
+            &lock_file,
+            r#"
+spin_lock_version = 2
+name = "future"
+version = "1.0.0"
+
+[trigger]
+type = "http"
+base = "/"
+"#,
+        )?;
+        let err = from_file(&lock_file).await.unwrap_err();
+        assert!(err.to_string().contains("Unsupported lock file version 2"));
+        Ok(())
+    }
+}
@@ -0,0 +1,49 @@
+//! Offline mode, in which operations that would use the network fail
+//! immediately rather than being attempted.
+
+use anyhow::{bail, Result};
+
+/// The environment variable enabling offline mode. It is set by
+/// `spin --offline`, so that processes started by Spin are offline too.
+pub const OFFLINE_ENV: &str = "SPIN_OFFLINE";
+
+/// Whether Spin is running in offline mode.
+pub fn is_offline() -> bool {
+    match std::env::var(OFFLINE_ENV) {
+        Ok(value) => enables_offline_mode(&value),
+        Err(_) => false,
+    }
+}
+
+/// Whether the value of the offline mode variable enables it.
+fn enables_offline_mode(value: &str) -> bool {
+    !matches!(value.to_lowercase().as_str(), "" | "0" | "false" | "no")
+}
+
+/// Fails if Spin is running in offline mode. The operation describes what
+/// would have used the network, for example "push bindle to server".
+pub fn ensure_online(operation: impl AsRef<str>) -> Result<()> {
+    if is_offline() {
+        bail!(
+            "Cannot {}: this requires network access, and Spin is running in offline mode (--offline or {})",
+            operation.as_ref(),
+            OFFLINE_ENV
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_offline_mode_values() {
+        for value in ["true", "1", "yes", "TRUE"] {
+            assert!(enables_offline_mode(value), "{}", value);
+        }
+        for value in ["", "0", "false", "No"] {
+            assert!(!enables_offline_mode(value), "{}", value);
+        }
+    }
+}
@@ -0,0 +1,58 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use tracing::log;
+
+/// Reports the progress of preparing the components of an application.
+pub(crate) struct Progress {
+    total: usize,
+    done: AtomicUsize,
+    // Whether progress is shown on the terminal, rather than only logged.
+    interactive: bool,
+}
+
+impl Progress {
+    pub(crate) fn new(total: usize) -> Self {
+        Self {
+            total,
+            done: AtomicUsize::new(0),
+            interactive: total > 1 && atty::is(atty::Stream::Stderr),
+        }
+    }
+
+    /// Records that the given component is prepared.
+    pub(crate) fn completed(&self, id: &str) {
+        let done = self.done.fetch_add(1, Ordering::Relaxed) + 1;
+        log::info!("Prepared component {} ({}/{})", id, done, self.total);
+        if self.interactive {
+            eprint!("\rPreparing components: {}/{}", done, self.total);
+            if done == self.total {
+                eprintln!();
+            }
+        }
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        // Ends the progress line if preparing a component failed.
+        if self.interactive && *self.done.get_mut() < self.total {
+            eprintln!();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_completed_components_are_counted() {
+        let progress = Progress::new(2);
+        progress.completed("a");
+        progress.completed("b");
+        assert_eq!(progress.done.load(Ordering::Relaxed), 2);
+
+        // A single component is not worth a progress line.
+        assert!(!Progress::new(1).interactive);
+    }
+}
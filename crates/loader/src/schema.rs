@@ -0,0 +1,59 @@
+//! JSON schemas of the payloads of message triggers.
+
+#![deny(missing_docs)]
+
+use anyhow::{anyhow, Context, Result};
+use jsonschema::JSONSchema;
+use std::path::Path;
+
+/// Loads a JSON schema file, failing if it is not valid JSON or not a valid
+/// schema.
+pub fn load_payload_schema(path: &Path) -> Result<JSONSchema> {
+    let contents = std::fs::read(path)
+        .with_context(|| format!("Cannot read payload schema {}", path.display()))?;
+    let schema: serde_json::Value = serde_json::from_slice(&contents)
+        .with_context(|| format!("Payload schema {} is not valid JSON", path.display()))?;
+    JSONSchema::compile(&schema)
+        .map_err(|e| anyhow!("Invalid payload schema {}: {}", path.display(), e))
+}
+
+/// Checks a payload against a schema, returning the reasons it does not
+/// match, which are empty if it does.
+pub fn payload_errors(schema: &JSONSchema, payload: &[u8]) -> Vec<String> {
+    let payload: serde_json::Value = match serde_json::from_slice(payload) {
+        Ok(payload) => payload,
+        Err(e) => return vec![format!("payload is not valid JSON: {}", e)],
+    };
+    let errors = match schema.validate(&payload) {
+        Ok(()) => return vec![],
+        Err(errors) => errors,
+    };
+    errors
+        .map(|e| format!("{}: {}", e.instance_path, e))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_payload_errors() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("order.json");
+        std::fs::write(
+            &path,
+            r#"{"type": "object", "required": ["id"], "properties": {"id": {"type": "integer"}}}"#,
+        )?;
+        let schema = load_payload_schema(&path)?;
+        assert!(payload_errors(&schema, br#"{"id": 1}"#).is_empty());
+        assert_eq!(payload_errors(&schema, br#"{"id": "1"}"#).len(), 1);
+        assert_eq!(payload_errors(&schema, b"id=1").len(), 1);
+
+        std::fs::write(&path, r#"{"type": "nonsense"}"#)?;
+        assert!(load_payload_schema(&path).is_err());
+        std::fs::write(&path, r#"{"type": "#)?;
+        assert!(load_payload_schema(&path).is_err());
+        Ok(())
+    }
+}
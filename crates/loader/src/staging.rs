@@ -0,0 +1,84 @@
+//! Staging of files into working directories, sharing storage with the
+//! source files where possible rather than copying them.
+
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use tracing::log;
+
+/// The environment variable forcing staged files to be copied. It is set by
+/// `spin --copy`, so that processes started by Spin copy files too.
+pub const COPY_ENV: &str = "SPIN_COPY_ASSETS";
+
+/// Whether staged files must be copied, rather than linked.
+pub fn always_copy() -> bool {
+    match std::env::var(COPY_ENV) {
+        Ok(value) => !matches!(value.to_lowercase().as_str(), "" | "0" | "false" | "no"),
+        Err(_) => false,
+    }
+}
+
+/// Copies a file, as a copy-on-write clone (reflink) where the filesystem
+/// supports it. The copy can be modified, or have its permissions changed,
+/// without affecting the source.
+pub async fn copy_file(from: impl AsRef<Path>, to: impl AsRef<Path>) -> Result<()> {
+    let (from, to) = (from.as_ref().to_owned(), to.as_ref().to_owned());
+    if always_copy() {
+        tokio::fs::copy(&from, &to).await?;
+        return Ok(());
+    }
+    remove_existing(&to).await?;
+    tokio::task::spawn_blocking(move || reflink_copy::reflink_or_copy(&from, &to)).await??;
+    Ok(())
+}
+
+/// Stages a file as a hard link to the source where both are on the same
+/// filesystem, falling back to `copy_file`. As the staged file shares the
+/// source's storage and permissions, it must not be modified.
+pub async fn link_or_copy_file(from: impl AsRef<Path>, to: impl AsRef<Path>) -> Result<()> {
+    let (from, to) = (from.as_ref(), to.as_ref());
+    if !always_copy() {
+        remove_existing(to).await?;
+        match tokio::fs::hard_link(from, to).await {
+            Ok(()) => return Ok(()),
+            // For example, the files are on different filesystems.
+            Err(e) => log::trace!(
+                "Cannot link {} to {}, copying instead: {}",
+                to.display(),
+                from.display(),
+                e
+            ),
+        }
+    }
+    copy_file(from, to).await
+}
+
+async fn remove_existing(path: &Path) -> Result<()> {
+    match tokio::fs::remove_file(path).await {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e).with_context(|| format!("Cannot replace {}", path.display())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_copy_is_independent_of_source() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let (from, to) = (dir.path().join("from.txt"), dir.path().join("to.txt"));
+        std::fs::write(&from, "source")?;
+        std::fs::write(&to, "stale")?;
+
+        copy_file(&from, &to).await?;
+        assert_eq!(std::fs::read_to_string(&to)?, "source");
+        std::fs::write(&to, "changed")?;
+        assert_eq!(std::fs::read_to_string(&from)?, "source");
+
+        link_or_copy_file(&from, &to).await?;
+        assert_eq!(std::fs::read_to_string(&to)?, "source");
+        Ok(())
+    }
+}
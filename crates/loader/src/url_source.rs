@@ -0,0 +1,126 @@
+//! Modules downloaded from HTTP(S) URLs. The manifest declares the digest of
+//! a module along with its URL, so that the download is verified, and cached
+//! by that digest in the local parcel cache.
+
+use std::path::PathBuf;
+
+use anyhow::{bail, Context, Result};
+use futures::{StreamExt, TryStreamExt};
+use reqwest::Url;
+
+use crate::bindle::{cache, ParcelCache};
+
+/// The prefix of the digests of modules, the only algorithm supported.
+const SHA256_PREFIX: &str = "sha256:";
+
+/// A module source parsed from a component manifest.
+#[derive(Clone, Debug)]
+pub struct UrlSource {
+    /// The URL to download the module from.
+    pub url: Url,
+    /// The hex-encoded SHA256 digest of the module.
+    pub sha256: String,
+}
+
+impl UrlSource {
+    /// Parses the URL and digest of a module source, which must be an HTTP
+    /// or HTTPS URL, and a digest of the form `sha256:<hex>`.
+    pub fn parse(url: &str, digest: &str) -> Result<Self> {
+        let url = Url::parse(url).with_context(|| format!("Invalid module URL {:?}", url))?;
+        if !matches!(url.scheme(), "http" | "https") {
+            bail!(
+                "Module URL {} must use http or https, not {}",
+                url,
+                url.scheme()
+            );
+        }
+        let sha256 = match digest.strip_prefix(SHA256_PREFIX) {
+            Some(hex) if hex.len() == 64 && hex.chars().all(|c| c.is_ascii_hexdigit()) => {
+                hex.to_ascii_lowercase()
+            }
+            _ => bail!(
+                "Invalid module digest {:?}: expected {}<64 hex digits>",
+                digest,
+                SHA256_PREFIX
+            ),
+        };
+        Ok(Self { url, sha256 })
+    }
+
+    /// Returns the path of the module in the local parcel cache, downloading
+    /// it first if it is not cached yet.
+    pub async fn fetch(&self, component: &str) -> Result<PathBuf> {
+        let dir = cache::default_dir()
+            .unwrap_or_else(|| std::env::temp_dir().join("spin").join("parcels"));
+        self.fetch_into(&ParcelCache::new(dir), component).await
+    }
+
+    async fn fetch_into(&self, cache: &ParcelCache, component: &str) -> Result<PathBuf> {
+        if let Some(path) = cache.get(&self.sha256) {
+            log::trace!(
+                "Using cached module {} for component {}",
+                self.url,
+                component
+            );
+            return Ok(path);
+        }
+        crate::offline::ensure_online(format!(
+            "download the source of component {} from {}",
+            component, self.url
+        ))?;
+        let download = async {
+            let response = reqwest::get(self.url.clone()).await?.error_for_status()?;
+            let content = response.bytes_stream().map_err(anyhow::Error::from);
+            cache.put(&self.sha256, content.boxed()).await
+        };
+        download.await.with_context(|| {
+            format!(
+                "Failed to download module {} for component {}",
+                self.url, component
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sha2::{Digest, Sha256};
+
+    #[test]
+    fn test_url_sources_are_parsed() {
+        let digest = format!("sha256:{:x}", Sha256::digest(b"module"));
+        let source = UrlSource::parse("https://example.com/module.wasm", &digest).unwrap();
+        assert_eq!(source.url.as_str(), "https://example.com/module.wasm");
+        assert_eq!(source.sha256, digest[SHA256_PREFIX.len()..]);
+
+        let uppercase = digest.to_ascii_uppercase().replace("SHA256:", "sha256:");
+        assert_eq!(
+            UrlSource::parse("https://example.com/module.wasm", &uppercase)
+                .unwrap()
+                .sha256,
+            source.sha256
+        );
+        assert!(UrlSource::parse("file:///module.wasm", &digest).is_err());
+        assert!(UrlSource::parse("module.wasm", &digest).is_err());
+        assert!(UrlSource::parse("https://example.com/module.wasm", "sha256:abc").is_err());
+        assert!(UrlSource::parse("https://example.com/module.wasm", &digest[7..]).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_cached_modules_are_not_downloaded() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let cache = ParcelCache::new(dir.path());
+        let sha256 = format!("{:x}", Sha256::digest(b"module"));
+        let content = futures::stream::iter(vec![Ok(bytes::Bytes::from_static(b"module"))]);
+        let cached = cache.put(&sha256, content).await?;
+
+        // The host does not resolve, so only a cache hit succeeds.
+        let source = UrlSource::parse(
+            "https://spin.invalid/module.wasm",
+            &format!("sha256:{}", sha256),
+        )?;
+        assert_eq!(source.fetch_into(&cache, "hello").await?, cached);
+        Ok(())
+    }
+}
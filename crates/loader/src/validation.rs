@@ -1,23 +1,43 @@
 #![deny(missing_docs)]
 
-use anyhow::{Context, Result};
-use reqwest::Url;
+use anyhow::Result;
 
-// Check whether http host can be parsed by Url
+// Check whether every http host is a URL or a host pattern
 pub fn validate_allowed_http_hosts(http_hosts: &Option<Vec<String>>) -> Result<()> {
     if let Some(domains) = http_hosts.as_deref() {
-        if domains
-            .iter()
-            .any(|domain| domain == wasi_outbound_http::ALLOW_ALL_HOSTS)
+        wasi_outbound_http::AllowedHosts::parse(domains)?;
+    }
+    Ok(())
+}
+
+/// Checks that every entry of `allowed_database_hosts` is a host, optionally
+/// followed by a port.
+pub fn validate_allowed_database_hosts(database_hosts: &Option<Vec<String>>) -> Result<()> {
+    for entry in database_hosts.iter().flatten() {
+        if spin_manifest::parse_database_host(entry).is_none() {
+            anyhow::bail!(
+                "Can't parse {} in allowed_database_hosts: expected host or host:port",
+                entry
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Checks that every key-value store name is made of ASCII letters, digits,
+/// `-` and `_`, as stores are kept in files named after them.
+pub fn validate_key_value_stores(stores: &Option<Vec<String>>) -> Result<()> {
+    for name in stores.iter().flatten() {
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
         {
-            return Ok(());
+            anyhow::bail!(
+                "Invalid key-value store name {:?} in key_value_stores: names may only contain ASCII letters, digits, '-' and '_'",
+                name
+            );
         }
-        let _ = domains
-            .iter()
-            .map(|d| {
-                Url::parse(d).with_context(|| format!("Can't parse {} in allowed_http_hosts", d))
-            })
-            .collect::<Result<Vec<_>, _>>()?;
     }
     Ok(())
 }
@@ -0,0 +1,246 @@
+//! A lease-based locking host interface for Spin components.
+
+mod memory;
+mod redis_backend;
+
+use std::{sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use serde::Deserialize;
+use spin_engine::{
+    host_component::{HostComponent, HostComponentsStateHandle},
+    RuntimeContext, ServiceUnavailable,
+};
+use spin_lock::*;
+use spin_manifest::CoreComponent;
+use wit_bindgen_wasmtime::wasmtime::Linker;
+
+pub use spin_lock::add_to_linker;
+
+wit_bindgen_wasmtime::export!({
+    paths: ["../../wit/ephemeral/spin-lock.wit"],
+    async: *,
+});
+
+/// Runtime configuration for the store holding lock leases.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "snake_case", tag = "type")]
+pub enum LockConfig {
+    /// Leases are held in memory, and only coordinate components running in
+    /// the same Spin instance.
+    Memory,
+    /// Leases are held in a Redis server shared by all replicas.
+    Redis {
+        /// Address of the Redis server.
+        address: String,
+    },
+}
+
+impl Default for LockConfig {
+    fn default() -> Self {
+        Self::Memory
+    }
+}
+
+/// The outcome of a lease operation on a backend.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum LeaseResult {
+    /// The operation succeeded.
+    Ok,
+    /// The lock is leased by another holder.
+    HeldByOther,
+    /// The caller's lease is no longer held.
+    NotHeld,
+}
+
+/// A store for lock leases.
+#[async_trait]
+pub(crate) trait LeaseStore: Send + Sync {
+    async fn acquire(&self, name: &str, token: &str, ttl: Duration) -> anyhow::Result<LeaseResult>;
+    async fn renew(&self, name: &str, token: &str, ttl: Duration) -> anyhow::Result<LeaseResult>;
+    async fn release(&self, name: &str, token: &str) -> anyhow::Result<LeaseResult>;
+
+    /// Checks that the store can be reached.
+    fn check(&self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+/// The store of a lock component started without its backend, returning
+/// `unavailable` errors.
+struct UnavailableLeaseStore(ServiceUnavailable);
+
+#[async_trait]
+impl LeaseStore for UnavailableLeaseStore {
+    async fn acquire(
+        &self,
+        _name: &str,
+        _token: &str,
+        _ttl: Duration,
+    ) -> anyhow::Result<LeaseResult> {
+        Err(self.0.clone().into())
+    }
+
+    async fn renew(
+        &self,
+        _name: &str,
+        _token: &str,
+        _ttl: Duration,
+    ) -> anyhow::Result<LeaseResult> {
+        Err(self.0.clone().into())
+    }
+
+    async fn release(&self, _name: &str, _token: &str) -> anyhow::Result<LeaseResult> {
+        Err(self.0.clone().into())
+    }
+}
+
+/// The lock host component.
+#[derive(Clone)]
+pub struct LockComponent {
+    store: Arc<dyn LeaseStore>,
+    /// The prefix of the names of the application's locks in the store.
+    namespace: Arc<str>,
+}
+
+impl LockComponent {
+    /// Creates a lock host component for the named application, using the
+    /// configured lease store. Applications sharing a store have distinct
+    /// locks.
+    pub fn new(config: &LockConfig, app_name: &str) -> anyhow::Result<Self> {
+        let store: Arc<dyn LeaseStore> = match config {
+            LockConfig::Memory => Arc::new(memory::MemoryLeaseStore::default()),
+            LockConfig::Redis { address } => {
+                Arc::new(redis_backend::RedisLeaseStore::new(address)?)
+            }
+        };
+        Ok(Self {
+            store,
+            namespace: namespace(app_name).into(),
+        })
+    }
+
+    /// Creates a lock host component for a store that was unavailable when
+    /// the application started.
+    pub fn unavailable(error: ServiceUnavailable) -> Self {
+        Self {
+            store: Arc::new(UnavailableLeaseStore(error)),
+            namespace: "".into(),
+        }
+    }
+
+    /// Checks that the lease store can be reached.
+    pub fn check(&self) -> anyhow::Result<()> {
+        self.store.check()
+    }
+}
+
+impl HostComponent for LockComponent {
+    type State = Lock;
+
+    fn add_to_linker<T: Send>(
+        linker: &mut Linker<RuntimeContext<T>>,
+        state_handle: HostComponentsStateHandle<Self::State>,
+    ) -> anyhow::Result<()> {
+        add_to_linker(linker, move |ctx| state_handle.get_mut(ctx))
+    }
+
+    fn build_state(&self, _component: &CoreComponent) -> anyhow::Result<Self::State> {
+        Ok(Lock {
+            store: self.store.clone(),
+            namespace: self.namespace.clone(),
+        })
+    }
+}
+
+/// Per-component lock state.
+pub struct Lock {
+    store: Arc<dyn LeaseStore>,
+    namespace: Arc<str>,
+}
+
+impl Lock {
+    /// The name of a lock of the application in the store.
+    fn name(&self, name: &str) -> String {
+        format!("{}:{}", self.namespace, name)
+    }
+}
+
+#[async_trait]
+impl spin_lock::SpinLock for Lock {
+    async fn acquire(&mut self, name: &str, ttl_ms: u64) -> Result<String, Error> {
+        let ttl = ttl(ttl_ms)?;
+        let token = uuid::Uuid::new_v4().to_string();
+        to_result(self.store.acquire(&self.name(name), &token, ttl).await)?;
+        Ok(token)
+    }
+
+    async fn renew(&mut self, name: &str, token: &str, ttl_ms: u64) -> Result<(), Error> {
+        let ttl = ttl(ttl_ms)?;
+        to_result(self.store.renew(&self.name(name), token, ttl).await)
+    }
+
+    async fn release(&mut self, name: &str, token: &str) -> Result<(), Error> {
+        to_result(self.store.release(&self.name(name), token).await)
+    }
+}
+
+/// The prefix of the lock names of an application, in which `:` only
+/// separates it from the lock name.
+fn namespace(app_name: &str) -> String {
+    app_name.replace('%', "%25").replace(':', "%3A")
+}
+
+/// Leases must live for some time: Redis rejects a zero expiry.
+fn ttl(ttl_ms: u64) -> Result<Duration, Error> {
+    if ttl_ms == 0 {
+        return Err(Error::Other("ttl-ms must be greater than zero".to_string()));
+    }
+    Ok(Duration::from_millis(ttl_ms))
+}
+
+fn to_result(res: anyhow::Result<LeaseResult>) -> Result<(), Error> {
+    match res {
+        Ok(LeaseResult::Ok) => Ok(()),
+        Ok(LeaseResult::HeldByOther) => Err(Error::HeldByOther),
+        Ok(LeaseResult::NotHeld) => Err(Error::NotHeld),
+        Err(e) if e.is::<ServiceUnavailable>() => Err(Error::Unavailable(e.to_string())),
+        Err(e) => {
+            tracing::log::error!("Lock store error: {:?}", e);
+            Err(Error::Other(e.to_string()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_applications_have_distinct_locks() -> anyhow::Result<()> {
+        let store: Arc<dyn LeaseStore> = Arc::new(memory::MemoryLeaseStore::default());
+        let lock = |app_name| Lock {
+            store: store.clone(),
+            namespace: namespace(app_name).into(),
+        };
+
+        assert!(lock("app").acquire("job", 60_000).await.is_ok());
+        assert!(matches!(
+            lock("app").acquire("job", 60_000).await,
+            Err(Error::HeldByOther)
+        ));
+        assert!(lock("other").acquire("job", 60_000).await.is_ok());
+        assert!(lock("app:job").acquire("job", 60_000).await.is_ok());
+        assert!(lock("app").acquire("job:job", 60_000).await.is_ok());
+
+        assert!(matches!(
+            lock("app").acquire("zero", 0).await,
+            Err(Error::Other(_))
+        ));
+        assert!(matches!(
+            lock("app").acquire("x", u64::MAX).await,
+            Err(Error::Other(_))
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,147 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+
+use crate::{LeaseResult, LeaseStore};
+
+/// The number of leases below which expired leases are not pruned.
+const MIN_PRUNED_LEASES: usize = 1024;
+
+/// A lease store local to the current process.
+pub(crate) struct MemoryLeaseStore {
+    leases: Mutex<MemoryLeases>,
+}
+
+struct MemoryLeases {
+    leases: HashMap<String, (String, Instant)>,
+    /// The number of leases at which those that expired are removed.
+    prune_at: usize,
+}
+
+impl Default for MemoryLeaseStore {
+    fn default() -> Self {
+        Self {
+            leases: Mutex::new(MemoryLeases {
+                leases: HashMap::new(),
+                prune_at: MIN_PRUNED_LEASES,
+            }),
+        }
+    }
+}
+
+#[async_trait]
+impl LeaseStore for MemoryLeaseStore {
+    async fn acquire(&self, name: &str, token: &str, ttl: Duration) -> Result<LeaseResult> {
+        let mut leases = self.leases.lock().unwrap();
+        let now = Instant::now();
+        let expiry = expiry(now, ttl)?;
+        if leases.leases.len() >= leases.prune_at {
+            leases.leases.retain(|_, (_, expiry)| *expiry > now);
+            // Pruning again before as many leases are added keeps acquiring
+            // constant time on average.
+            leases.prune_at = MIN_PRUNED_LEASES.max(2 * leases.leases.len());
+        }
+        match leases.leases.get(name) {
+            Some((_, expiry)) if *expiry > now => Ok(LeaseResult::HeldByOther),
+            _ => {
+                leases
+                    .leases
+                    .insert(name.to_string(), (token.to_string(), expiry));
+                Ok(LeaseResult::Ok)
+            }
+        }
+    }
+
+    async fn renew(&self, name: &str, token: &str, ttl: Duration) -> Result<LeaseResult> {
+        let mut leases = self.leases.lock().unwrap();
+        let now = Instant::now();
+        let new_expiry = expiry(now, ttl)?;
+        match leases.leases.get_mut(name) {
+            Some((holder, expiry)) if holder == token && *expiry > now => {
+                *expiry = new_expiry;
+                Ok(LeaseResult::Ok)
+            }
+            _ => Ok(LeaseResult::NotHeld),
+        }
+    }
+
+    async fn release(&self, name: &str, token: &str) -> Result<LeaseResult> {
+        let mut leases = self.leases.lock().unwrap();
+        match leases.leases.get(name) {
+            Some((holder, expiry)) if holder == token && *expiry > Instant::now() => {
+                leases.leases.remove(name);
+                Ok(LeaseResult::Ok)
+            }
+            _ => Ok(LeaseResult::NotHeld),
+        }
+    }
+}
+
+/// The time at which a lease taken now expires.
+fn expiry(now: Instant, ttl: Duration) -> Result<Instant> {
+    now.checked_add(ttl)
+        .with_context(|| format!("Lease TTL of {:?} is too long", ttl))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_memory_leases() -> Result<()> {
+        let store = MemoryLeaseStore::default();
+        let ttl = Duration::from_secs(60);
+
+        assert_eq!(store.acquire("job", "a", ttl).await?, LeaseResult::Ok);
+        assert_eq!(
+            store.acquire("job", "b", ttl).await?,
+            LeaseResult::HeldByOther
+        );
+        assert_eq!(store.renew("job", "b", ttl).await?, LeaseResult::NotHeld);
+        assert_eq!(store.renew("job", "a", ttl).await?, LeaseResult::Ok);
+        assert_eq!(store.release("job", "a").await?, LeaseResult::Ok);
+        assert_eq!(store.release("job", "a").await?, LeaseResult::NotHeld);
+        assert_eq!(store.acquire("job", "b", ttl).await?, LeaseResult::Ok);
+
+        assert_eq!(
+            store.acquire("expiring", "a", Duration::ZERO).await?,
+            LeaseResult::Ok
+        );
+        assert_eq!(store.acquire("expiring", "b", ttl).await?, LeaseResult::Ok);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_expired_leases_are_pruned() -> Result<()> {
+        let store = MemoryLeaseStore::default();
+        for i in 0..MIN_PRUNED_LEASES {
+            store
+                .acquire(&format!("expired-{}", i), "a", Duration::ZERO)
+                .await?;
+        }
+        store.acquire("held", "a", Duration::from_secs(60)).await?;
+        store.acquire("other", "a", Duration::from_secs(60)).await?;
+
+        let leases = store.leases.lock().unwrap();
+        assert_eq!(leases.leases.len(), 2);
+        assert_eq!(leases.prune_at, MIN_PRUNED_LEASES);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_overlong_ttls_are_rejected() -> Result<()> {
+        let store = MemoryLeaseStore::default();
+        let ttl = Duration::from_millis(u64::MAX);
+
+        assert!(store.acquire("x", "a", ttl).await.is_err());
+        store.acquire("x", "a", Duration::from_secs(60)).await?;
+        assert!(store.renew("x", "a", ttl).await.is_err());
+        assert_eq!(store.release("x", "a").await?, LeaseResult::Ok);
+        Ok(())
+    }
+}
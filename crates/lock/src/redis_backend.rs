@@ -0,0 +1,94 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use redis::{Client, Script};
+
+use crate::{LeaseResult, LeaseStore};
+
+const KEY_PREFIX: &str = "spin:lock:";
+
+// Only extend or delete the lease if it is still held with the caller's token.
+const RENEW_SCRIPT: &str = r#"
+if redis.call("GET", KEYS[1]) == ARGV[1] then
+    return redis.call("PEXPIRE", KEYS[1], ARGV[2])
+else
+    return 0
+end
+"#;
+const RELEASE_SCRIPT: &str = r#"
+if redis.call("GET", KEYS[1]) == ARGV[1] then
+    return redis.call("DEL", KEYS[1])
+else
+    return 0
+end
+"#;
+
+/// A lease store backed by a Redis server, shared by all Spin instances using it.
+pub(crate) struct RedisLeaseStore {
+    client: Client,
+}
+
+impl RedisLeaseStore {
+    pub(crate) fn new(address: &str) -> Result<Self> {
+        Ok(Self {
+            client: Client::open(address)?,
+        })
+    }
+
+    fn key(name: &str) -> String {
+        format!("{}{}", KEY_PREFIX, name)
+    }
+}
+
+#[async_trait]
+impl LeaseStore for RedisLeaseStore {
+    async fn acquire(&self, name: &str, token: &str, ttl: Duration) -> Result<LeaseResult> {
+        let mut conn = self.client.get_async_connection().await?;
+        let set: Option<String> = redis::cmd("SET")
+            .arg(Self::key(name))
+            .arg(token)
+            .arg("NX")
+            .arg("PX")
+            .arg(ttl.as_millis() as u64)
+            .query_async(&mut conn)
+            .await?;
+        Ok(match set {
+            Some(_) => LeaseResult::Ok,
+            None => LeaseResult::HeldByOther,
+        })
+    }
+
+    async fn renew(&self, name: &str, token: &str, ttl: Duration) -> Result<LeaseResult> {
+        let mut conn = self.client.get_async_connection().await?;
+        let renewed: i64 = Script::new(RENEW_SCRIPT)
+            .key(Self::key(name))
+            .arg(token)
+            .arg(ttl.as_millis() as u64)
+            .invoke_async(&mut conn)
+            .await?;
+        Ok(match renewed {
+            0 => LeaseResult::NotHeld,
+            _ => LeaseResult::Ok,
+        })
+    }
+
+    async fn release(&self, name: &str, token: &str) -> Result<LeaseResult> {
+        let mut conn = self.client.get_async_connection().await?;
+        let released: i64 = Script::new(RELEASE_SCRIPT)
+            .key(Self::key(name))
+            .arg(token)
+            .invoke_async(&mut conn)
+            .await?;
+        Ok(match released {
+            0 => LeaseResult::NotHeld,
+            _ => LeaseResult::Ok,
+        })
+    }
+
+    fn check(&self) -> Result<()> {
+        let mut conn = self.client.get_connection()?;
+        redis::cmd("PING").query::<String>(&mut conn)?;
+        Ok(())
+    }
+}
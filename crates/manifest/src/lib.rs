@@ -3,10 +3,15 @@
 #![deny(missing_docs)]
 
 use indexmap::IndexMap;
+use schemars::{
+    gen::SchemaGenerator,
+    schema::{InstanceType, Schema, SchemaObject, StringValidation, SubschemaValidation},
+    JsonSchema,
+};
 use serde::{Deserialize, Serialize};
 use spin_config::Resolver;
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     fmt::{Debug, Formatter},
     path::PathBuf,
     sync::Arc,
@@ -98,28 +103,279 @@ pub enum ApplicationOrigin {
 }
 
 /// The trigger type.
-#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize)]
-#[serde(deny_unknown_fields, rename_all = "camelCase", tag = "type")]
+///
+/// The type is given by the `type` field of the trigger table: `http`,
+/// `redis`, `queue`, or `command:<name>` for a trigger run by an external
+/// executable.
+#[derive(Clone, Debug, PartialEq)]
 pub enum ApplicationTrigger {
     /// HTTP trigger type.
     Http(HttpTriggerConfiguration),
     /// Redis trigger type.
     Redis(RedisTriggerConfiguration),
+    /// Message queue trigger type.
+    Queue(QueueTriggerConfiguration),
+    /// Trigger type implemented by an external executable.
+    External(ExternalTriggerConfiguration),
+}
+
+impl ApplicationTrigger {
+    /// The value of the `type` field of the trigger.
+    pub fn trigger_type(&self) -> String {
+        match self {
+            Self::Http(_) => "http".to_owned(),
+            Self::Redis(_) => "redis".to_owned(),
+            Self::Queue(_) => "queue".to_owned(),
+            Self::External(external) => {
+                format!("{}{}", EXTERNAL_TRIGGER_PREFIX, external.command)
+            }
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for ApplicationTrigger {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+
+        let mut table = toml::value::Table::deserialize(deserializer)?;
+        let trigger_type = match table.remove("type") {
+            Some(toml::Value::String(trigger_type)) => trigger_type,
+            Some(_) => return Err(D::Error::custom("the trigger type must be a string")),
+            None => return Err(D::Error::missing_field("type")),
+        };
+        if let Some(command) = trigger_type.strip_prefix(EXTERNAL_TRIGGER_PREFIX) {
+            if command.is_empty() {
+                return Err(D::Error::custom(
+                    "the trigger type `command:` names no command",
+                ));
+            }
+            return Ok(Self::External(ExternalTriggerConfiguration {
+                command: command.to_owned(),
+                config: table,
+            }));
+        }
+        let config = toml::Value::Table(table);
+        let trigger = match trigger_type.as_str() {
+            "http" => config.try_into().map(Self::Http),
+            "redis" => config.try_into().map(Self::Redis),
+            "queue" => config.try_into().map(Self::Queue),
+            other => {
+                return Err(D::Error::unknown_variant(
+                    other,
+                    &["http", "redis", "queue", "command:<name>"],
+                ))
+            }
+        };
+        trigger.map_err(D::Error::custom)
+    }
+}
+
+impl Serialize for ApplicationTrigger {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::Error;
+
+        let config = match self {
+            Self::Http(config) => toml::Value::try_from(config),
+            Self::Redis(config) => toml::Value::try_from(config),
+            Self::Queue(config) => toml::Value::try_from(config),
+            Self::External(external) => Ok(toml::Value::Table(external.config.clone())),
+        };
+        let mut table = match config.map_err(S::Error::custom)? {
+            toml::Value::Table(table) => table,
+            _ => {
+                return Err(S::Error::custom(
+                    "the trigger configuration must be a table",
+                ))
+            }
+        };
+        table.insert("type".to_owned(), toml::Value::String(self.trigger_type()));
+        table.serialize(serializer)
+    }
+}
+
+impl JsonSchema for ApplicationTrigger {
+    fn schema_name() -> String {
+        "ApplicationTrigger".to_owned()
+    }
+
+    // One schema for each trigger type, with the `type` field its
+    // configuration is read along with.
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        let typed = |mut schema: SchemaObject, trigger_type: Schema| {
+            let object = schema.object();
+            object.properties.insert("type".to_owned(), trigger_type);
+            object.required.insert("type".to_owned());
+            Schema::Object(schema)
+        };
+        let constant = |trigger_type: &str| {
+            Schema::Object(SchemaObject {
+                instance_type: Some(InstanceType::String.into()),
+                const_value: Some(trigger_type.into()),
+                ..Default::default()
+            })
+        };
+        let external = Schema::Object(SchemaObject {
+            instance_type: Some(InstanceType::String.into()),
+            string: Some(Box::new(StringValidation {
+                pattern: Some(format!("^{}.+", EXTERNAL_TRIGGER_PREFIX)),
+                ..Default::default()
+            })),
+            ..Default::default()
+        });
+        let variants = vec![
+            typed(
+                HttpTriggerConfiguration::json_schema(gen).into_object(),
+                constant("http"),
+            ),
+            typed(
+                RedisTriggerConfiguration::json_schema(gen).into_object(),
+                constant("redis"),
+            ),
+            typed(
+                QueueTriggerConfiguration::json_schema(gen).into_object(),
+                constant("queue"),
+            ),
+            typed(free_form_table(gen).into_object(), external),
+        ];
+        Schema::Object(SchemaObject {
+            subschemas: Some(Box::new(SubschemaValidation {
+                one_of: Some(variants),
+                ..Default::default()
+            })),
+            ..Default::default()
+        })
+    }
+}
+
+/// The schema of a table whose fields are not checked, such as the
+/// configuration of an external trigger.
+pub fn free_form_table(_: &mut SchemaGenerator) -> Schema {
+    Schema::Object(SchemaObject {
+        instance_type: Some(InstanceType::Object.into()),
+        ..Default::default()
+    })
+}
+
+/// The prefix of the types of triggers implemented by external executables.
+pub const EXTERNAL_TRIGGER_PREFIX: &str = "command:";
+
+/// Configuration of a trigger implemented by an external executable.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExternalTriggerConfiguration {
+    /// The command running the trigger: the name of a `spin-trigger-<name>`
+    /// executable on the `PATH`, or a path to the executable.
+    pub command: String,
+    /// The other fields of the trigger table, passed to the trigger as is.
+    pub config: toml::value::Table,
+}
+
+impl TryFrom<ApplicationTrigger> for ExternalTriggerConfiguration {
+    type Error = Error;
+
+    fn try_from(trigger: ApplicationTrigger) -> Result<Self, Self::Error> {
+        match trigger {
+            ApplicationTrigger::External(external) => Ok(external),
+            _ => Err(Error::InvalidTriggerType),
+        }
+    }
 }
 
 /// HTTP trigger configuration.
-#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize, JsonSchema)]
 pub struct HttpTriggerConfiguration {
     /// Base path for the HTTP application.
     pub base: String,
+    /// Routes handled natively by the trigger, without a component.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub routes: Vec<HttpNativeRoute>,
+    /// The certificate and key to serve HTTPS with, unless others are given
+    /// on the command line.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls: Option<HttpTlsFiles>,
+    /// CORS configuration for the components that do not set their own.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cors: Option<HttpCorsConfig>,
 }
 
 impl Default for HttpTriggerConfiguration {
     fn default() -> Self {
-        Self { base: "/".into() }
+        Self {
+            base: "/".into(),
+            routes: vec![],
+            tls: None,
+            cors: None,
+        }
     }
 }
 
+/// The certificate and key the HTTP trigger serves HTTPS with.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub struct HttpTlsFiles {
+    /// Path to the certificate chain, in PEM format. Relative paths are
+    /// resolved against the directory of the application manifest.
+    pub cert: PathBuf,
+    /// Path to the private key, in PEM format, either PKCS#8 or PKCS#1
+    /// (RSA). Relative paths are resolved like `cert`.
+    pub key: PathBuf,
+}
+
+/// A route handled natively by the HTTP trigger.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub struct HttpNativeRoute {
+    /// HTTP route the handler will be invoked for.
+    pub route: String,
+    /// The handler for the route.
+    pub handler: HttpHandler,
+}
+
+/// A native HTTP handler.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(untagged)]
+pub enum HttpHandler {
+    /// Responds with a rendered template.
+    Template(TemplateHandler),
+    /// Forwards requests to an upstream service.
+    Proxy(ProxyHandler),
+}
+
+/// Configuration for a handler responding with a rendered template.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub struct TemplateHandler {
+    /// Path to the template. Relative paths are resolved against the
+    /// directory of the application manifest.
+    pub template: PathBuf,
+    /// The response status code.
+    #[serde(default = "default_template_status")]
+    pub status: u16,
+    /// The response content type. If not set, it is inferred from the
+    /// template file extension.
+    pub content_type: Option<String>,
+}
+
+fn default_template_status() -> u16 {
+    200
+}
+
+/// Configuration for a handler forwarding requests to an upstream service.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub struct ProxyHandler {
+    /// The upstream URL. The part of the request path matched by the route
+    /// is replaced with the path of this URL.
+    pub proxy: String,
+    /// Forward the full request path instead, appended to the upstream URL.
+    #[serde(default)]
+    pub preserve_path: bool,
+    /// Headers to set on forwarded requests.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub headers: HashMap<String, String>,
+    /// Timeout for upstream responses, in milliseconds.
+    pub timeout_ms: Option<u64>,
+}
+
 impl TryFrom<ApplicationTrigger> for HttpTriggerConfiguration {
     type Error = Error;
 
@@ -132,7 +388,7 @@ impl TryFrom<ApplicationTrigger> for HttpTriggerConfiguration {
 }
 
 /// Redis trigger configuration.
-#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize, JsonSchema)]
 pub struct RedisTriggerConfiguration {
     /// Address of Redis server.
     pub address: String,
@@ -149,6 +405,44 @@ impl TryFrom<ApplicationTrigger> for RedisTriggerConfiguration {
     }
 }
 
+/// Message queue trigger configuration.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub struct QueueTriggerConfiguration {
+    /// The kind of message broker.
+    pub broker: QueueBroker,
+    /// Address of the broker: a comma-separated list of bootstrap servers
+    /// for Kafka, or a server URL for NATS.
+    pub address: String,
+    /// The consumer group the application consumes messages in, so that the
+    /// instances of the application share the messages between them. For
+    /// NATS, the name of the durable JetStream consumer. If not set, the
+    /// name of the application.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub consumer_group: Option<String>,
+}
+
+impl TryFrom<ApplicationTrigger> for QueueTriggerConfiguration {
+    type Error = Error;
+
+    fn try_from(trigger: ApplicationTrigger) -> Result<Self, Self::Error> {
+        match trigger {
+            ApplicationTrigger::Queue(queue) => Ok(queue),
+            _ => Err(Error::InvalidTriggerType),
+        }
+    }
+}
+
+/// A kind of message broker the queue trigger consumes messages from.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum QueueBroker {
+    /// Apache Kafka topics.
+    Kafka,
+    /// NATS JetStream subjects.
+    Nats,
+}
+
 /// WebAssembly configuration.
 #[derive(Clone, Debug, Default)]
 pub struct WasmConfig {
@@ -158,6 +452,162 @@ pub struct WasmConfig {
     pub mounts: Vec<DirectoryMount>,
     /// Optional list of HTTP hosts the component is allowed to connect.
     pub allowed_http_hosts: Vec<String>,
+    /// List of blob store containers the component is allowed to access.
+    pub allowed_blob_containers: Vec<String>,
+    /// List of database hosts, as `host` or `host:port`, the component is
+    /// allowed to connect to.
+    pub allowed_database_hosts: Vec<String>,
+    /// List of key-value stores the component is allowed to access.
+    pub key_value_stores: Vec<String>,
+    /// List of JWT issuers the component is allowed to issue tokens with.
+    pub allowed_jwt_issuers: Vec<String>,
+    /// List of cryptography keys the component is allowed to use.
+    pub allowed_crypto_keys: Vec<String>,
+    /// When the module of the component is compiled.
+    pub load: LoadPolicy,
+    /// Limits on the resources the component uses.
+    pub limits: ComponentLimits,
+    /// The least severe output of the component that is logged. All output
+    /// is logged if not set.
+    pub log_level: Option<LogLevel>,
+    /// Configuration for host components, by host component name.
+    pub host_config: HashMap<String, toml::Value>,
+}
+
+/// Whether a binary is a component of the WebAssembly component model rather
+/// than a core module. Both start with the magic number, followed by a
+/// version, which is 1 for core modules, and a layer, which is 1 for
+/// components.
+///
+/// Spin only runs core modules: Wasmtime 0.35 cannot compile components, so
+/// they are rejected with this when loaded or published.
+pub fn is_wasm_component(bytes: &[u8]) -> bool {
+    bytes.starts_with(b"\0asm") && bytes.get(6..8) == Some(&[1, 0])
+}
+
+/// Whether a component with the given `allowed_database_hosts` is allowed to
+/// connect to the database at the given host and port.
+pub fn allows_database_host(allowed_hosts: &[String], host: &str, port: u16) -> bool {
+    allowed_hosts
+        .iter()
+        .filter_map(|entry| parse_database_host(entry))
+        .any(|(allowed_host, allowed_port)| {
+            allowed_host.eq_ignore_ascii_case(host)
+                && allowed_port.map_or(true, |allowed_port| allowed_port == port)
+        })
+}
+
+/// Parses an entry of `allowed_database_hosts`, either `host` or `host:port`,
+/// into the host and the optional port. IPv6 addresses are enclosed in
+/// brackets, as in `[::1]:5432`, and returned without them.
+pub fn parse_database_host(entry: &str) -> Option<(&str, Option<u16>)> {
+    let (host, port) = match entry.strip_prefix('[') {
+        Some(rest) => {
+            let (host, rest) = rest.split_once(']')?;
+            match rest {
+                "" => (host, None),
+                rest => (host, Some(rest.strip_prefix(':')?)),
+            }
+        }
+        None => {
+            let (host, port) = match entry.split_once(':') {
+                Some((host, port)) => (host, Some(port)),
+                None => (entry, None),
+            };
+            if host.contains(|c| c == '/' || c == '@') {
+                return None;
+            }
+            (host, port)
+        }
+    };
+    if host.is_empty() {
+        return None;
+    }
+    let port = match port {
+        Some(port) => Some(port.parse().ok()?),
+        None => None,
+    };
+    Some((host, port))
+}
+
+/// Limits on the resources a component uses, so that one misbehaving
+/// component cannot starve the rest of the application.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub struct ComponentLimits {
+    /// The maximum size of the linear memory of an instance, in bytes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_memory_bytes: Option<u64>,
+    /// How long an invocation may run for, in milliseconds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_ms: Option<u64>,
+    /// The maximum number of instances running at once.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_concurrent: Option<usize>,
+    /// The maximum number of invocations waiting for an instance once
+    /// `max_concurrent` instances are running. If not set, invocations past
+    /// `max_concurrent` are rejected rather than queued.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_queued: Option<usize>,
+    /// The number of slots of the instance pool reserved for the component,
+    /// which other components cannot use.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_instances: Option<usize>,
+    /// The maximum number of files mounted in the component, checked when
+    /// the application is loaded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_asset_files: Option<usize>,
+    /// The maximum total size of the files mounted in the component, in
+    /// bytes, checked when the application is loaded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_asset_bytes: Option<u64>,
+}
+
+/// When the module of a component is compiled.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum LoadPolicy {
+    /// The module is compiled when the application starts.
+    Eager,
+    /// The module is compiled when the component is first invoked.
+    Lazy,
+}
+
+impl Default for LoadPolicy {
+    fn default() -> Self {
+        Self::Eager
+    }
+}
+
+/// The severity of a line of component output.
+#[derive(
+    Clone, Copy, Debug, Deserialize, PartialEq, Eq, PartialOrd, Ord, Serialize, JsonSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum LogLevel {
+    /// Tracing output.
+    Trace,
+    /// Debugging output.
+    Debug,
+    /// Informational output.
+    Info,
+    /// Warnings.
+    Warn,
+    /// Errors.
+    Error,
+}
+
+impl LogLevel {
+    /// The name of the level, as in the manifest.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Trace => "trace",
+            Self::Debug => "debug",
+            Self::Info => "info",
+            Self::Warn => "warn",
+            Self::Error => "error",
+        }
+    }
 }
 
 /// Directory mount for the assets of a component.
@@ -195,12 +645,51 @@ impl Debug for ModuleSource {
     }
 }
 /// Configuration for the HTTP trigger.
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
 pub struct HttpConfig {
     /// HTTP route the component will be invoked for.
     pub route: String,
     /// The HTTP executor the component requires.
     pub executor: Option<HttpExecutor>,
+    /// Audit configuration for requests handled by this route.
+    /// If set, request metadata and bodies are copied to the audit sink
+    /// configured for the trigger.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audit: Option<HttpAuditConfig>,
+    /// Authentication configuration for requests handled by this route.
+    /// If set, requests without a valid token are rejected before the
+    /// component is invoked.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth: Option<HttpAuthConfig>,
+    /// Middlewares applied, in order, to requests handled by this route.
+    /// Each can reject a request before the component is invoked.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub middleware: Vec<HttpMiddleware>,
+    /// Idempotency configuration for requests handled by this route.
+    /// If set, the responses to requests with an idempotency key are
+    /// stored, and replayed for later requests with the same key.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idempotency: Option<HttpIdempotencyConfig>,
+    /// Response caching configuration for requests handled by this route.
+    /// If set, the responses to GET requests are cached in memory, and
+    /// repeated requests are answered without invoking the component.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache: Option<HttpCacheConfig>,
+    /// Priority of requests handled by this route when concurrency is
+    /// limited: requests to higher priority routes are admitted first, and
+    /// requests to lower priority routes are shed first.
+    #[serde(default)]
+    pub priority: RoutePriority,
+    /// CORS configuration for requests handled by this route. If set,
+    /// preflight requests are answered without invoking the component, and
+    /// requests from origins that are not allowed are rejected.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cors: Option<HttpCorsConfig>,
+    /// Configuration values taken from each request, by configuration key.
+    /// They take precedence over the configuration of the component, whose
+    /// value is used when the request has no value for the key.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub request_config: BTreeMap<String, RequestConfigSource>,
 }
 
 impl Default for HttpConfig {
@@ -208,22 +697,281 @@ impl Default for HttpConfig {
         Self {
             route: "/".to_string(),
             executor: Default::default(),
+            audit: None,
+            auth: None,
+            middleware: vec![],
+            idempotency: None,
+            cache: None,
+            priority: Default::default(),
+            cors: None,
+            request_config: BTreeMap::new(),
+        }
+    }
+}
+
+/// The part of a request a request-scoped configuration value is taken from,
+/// written `path.<name>`, `query.<name>` or `header.<name>`.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum RequestConfigSource {
+    /// A named segment of the route, such as `id` in `/users/:id`.
+    PathParam(String),
+    /// A query parameter. If it is repeated, its first value is used.
+    Query(String),
+    /// A request header, matched case-insensitively.
+    Header(String),
+}
+
+impl TryFrom<String> for RequestConfigSource {
+    type Error = String;
+
+    fn try_from(source: String) -> Result<Self, Self::Error> {
+        let (kind, name) = source.split_once('.').ok_or_else(|| {
+            format!(
+                "invalid request config source {:?}: expected `path.<name>`, `query.<name>` or `header.<name>`",
+                source
+            )
+        })?;
+        if name.is_empty() {
+            return Err(format!("request config source {:?} names nothing", source));
+        }
+        match kind {
+            "path" => Ok(Self::PathParam(name.to_owned())),
+            "query" => Ok(Self::Query(name.to_owned())),
+            "header" => Ok(Self::Header(name.to_ascii_lowercase())),
+            _ => Err(format!(
+                "invalid request config source {:?}: the source must be `path`, `query` or `header`",
+                source
+            )),
+        }
+    }
+}
+
+impl JsonSchema for RequestConfigSource {
+    fn schema_name() -> String {
+        "RequestConfigSource".to_owned()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        Schema::Object(SchemaObject {
+            instance_type: Some(InstanceType::String.into()),
+            string: Some(Box::new(StringValidation {
+                pattern: Some(r"^(path|query|header)\..+$".to_owned()),
+                ..Default::default()
+            })),
+            ..Default::default()
+        })
+    }
+}
+
+impl From<RequestConfigSource> for String {
+    fn from(source: RequestConfigSource) -> Self {
+        match source {
+            RequestConfigSource::PathParam(name) => format!("path.{}", name),
+            RequestConfigSource::Query(name) => format!("query.{}", name),
+            RequestConfigSource::Header(name) => format!("header.{}", name),
+        }
+    }
+}
+
+/// Priority of the requests to a route, from highest to lowest.
+#[derive(
+    Clone, Copy, Debug, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize, JsonSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum RoutePriority {
+    /// Requests that must stay responsive under load, such as health checks.
+    High,
+    /// Requests to most routes.
+    Normal,
+    /// Requests that can wait, or be shed, under load.
+    Low,
+}
+
+impl Default for RoutePriority {
+    fn default() -> Self {
+        Self::Normal
+    }
+}
+
+/// Audit configuration for an HTTP route.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(default, deny_unknown_fields, rename_all = "snake_case")]
+pub struct HttpAuditConfig {
+    /// Whether the request body should be included in the audit record.
+    pub include_body: bool,
+    /// Maximum number of body bytes to record, 1 MiB by default. Longer
+    /// bodies are truncated.
+    pub max_body_bytes: Option<usize>,
+    /// Names of request headers whose values must be redacted.
+    pub redact_headers: Vec<String>,
+}
+
+impl Default for HttpAuditConfig {
+    fn default() -> Self {
+        Self {
+            include_body: true,
+            max_body_bytes: None,
+            redact_headers: vec!["authorization".to_string(), "cookie".to_string()],
+        }
+    }
+}
+
+/// Authentication configuration for an HTTP route.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub struct HttpAuthConfig {
+    /// The name of the JWT validator, from the runtime configuration,
+    /// that bearer tokens must be accepted by.
+    pub jwt: String,
+}
+
+/// A middleware applied to the requests of an HTTP route.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, rename_all = "snake_case", tag = "type")]
+pub enum HttpMiddleware {
+    /// Rejects requests without a valid bearer JWT, and passes the claims of
+    /// valid tokens to the component.
+    Jwt(HttpJwtMiddlewareConfig),
+    /// Rejects requests from clients that exceed a rate.
+    RateLimit(HttpRateLimitConfig),
+}
+
+/// Configuration of the JWT validation middleware.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub struct HttpJwtMiddlewareConfig {
+    /// The name of the JWT validator, from the runtime configuration,
+    /// that bearer tokens must be accepted by.
+    pub validator: String,
+    /// Request headers set to the value of a claim of the token, by claim
+    /// name.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub claim_headers: BTreeMap<String, String>,
+}
+
+/// Configuration of the rate limiting middleware: each client gets a bucket
+/// of `burst` tokens, refilled at `requests` per `period_secs`, and each
+/// request takes a token.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub struct HttpRateLimitConfig {
+    /// The number of requests a client may make per period.
+    pub requests: u32,
+    /// The length of the period, in seconds.
+    #[serde(default = "default_rate_limit_period_secs")]
+    pub period_secs: u64,
+    /// The number of requests a client may make at once. Defaults to
+    /// `requests`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub burst: Option<u32>,
+    /// The claim of the validated token of a request identifying clients,
+    /// such as `sub`. The token must be validated by the route's `auth` or
+    /// an earlier `jwt` middleware. Clients are identified by their IP
+    /// address if it is not set, or if the token does not have the claim.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_claim: Option<String>,
+}
+
+fn default_rate_limit_period_secs() -> u64 {
+    1
+}
+
+/// Idempotency configuration for an HTTP route.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(default, deny_unknown_fields, rename_all = "snake_case")]
+pub struct HttpIdempotencyConfig {
+    /// Name of the request header carrying the idempotency key.
+    pub header: String,
+    /// How long, in seconds, responses are stored for.
+    pub ttl_secs: u64,
+    /// Whether requests without an idempotency key are rejected.
+    pub required: bool,
+}
+
+impl Default for HttpIdempotencyConfig {
+    fn default() -> Self {
+        Self {
+            header: "idempotency-key".to_string(),
+            ttl_secs: 24 * 60 * 60,
+            required: false,
+        }
+    }
+}
+
+/// Response caching configuration for an HTTP route.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(default, deny_unknown_fields, rename_all = "snake_case")]
+pub struct HttpCacheConfig {
+    /// How long, in seconds, responses are cached for.
+    pub ttl_secs: u64,
+    /// Names of the request headers whose values are part of the cache key,
+    /// along with the method, path and query string. Requests differing in
+    /// other headers get the same cached response.
+    pub key_headers: Vec<String>,
+    /// The largest response body cached, in bytes.
+    pub max_entry_bytes: usize,
+}
+
+impl Default for HttpCacheConfig {
+    fn default() -> Self {
+        Self {
+            ttl_secs: 60,
+            key_headers: vec![],
+            max_entry_bytes: 1024 * 1024,
+        }
+    }
+}
+
+/// CORS configuration for an HTTP route.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(default, deny_unknown_fields, rename_all = "snake_case")]
+pub struct HttpCorsConfig {
+    /// Origins allowed to make cross-origin requests, such as
+    /// `https://app.example.com`, or `*` for any origin.
+    pub allowed_origins: Vec<String>,
+    /// Methods allowed in cross-origin requests, or `*` for any method.
+    pub allowed_methods: Vec<String>,
+    /// Request headers allowed in cross-origin requests, or `*` for any
+    /// header.
+    pub allowed_headers: Vec<String>,
+    /// Response headers exposed to the scripts of other origins.
+    pub expose_headers: Vec<String>,
+    /// Whether cross-origin requests may include credentials, such as
+    /// cookies.
+    pub allow_credentials: bool,
+    /// How long, in seconds, browsers may cache preflight responses.
+    pub max_age_secs: Option<u64>,
+}
+
+impl Default for HttpCorsConfig {
+    fn default() -> Self {
+        Self {
+            allowed_origins: vec![],
+            allowed_methods: vec!["GET".to_string(), "HEAD".to_string(), "POST".to_string()],
+            allowed_headers: vec![],
+            expose_headers: vec![],
+            allow_credentials: false,
+            max_age_secs: None,
         }
     }
 }
 
 /// The executor for the HTTP component.
 /// The component can either implement the Spin HTTP interface,
-/// or the Wagi CGI interface.
+/// or the Wagi CGI interface, or have its mounted files served.
 ///
 /// If an executor is not specified, the inferred default is `HttpExecutor::Spin`.
-#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
 #[serde(deny_unknown_fields, rename_all = "camelCase", tag = "type")]
 pub enum HttpExecutor {
     /// The component implements the Spin HTTP interface.
     Spin,
     /// The component implements the Wagi CGI interface.
     Wagi(WagiConfig),
+    /// The files mounted in the component are served by the host, without
+    /// instantiating the component.
+    Static(StaticConfig),
 }
 
 impl Default for HttpExecutor {
@@ -233,7 +981,7 @@ impl Default for HttpExecutor {
 }
 
 /// Wagi specific configuration for the http executor.
-#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
 #[serde(default, deny_unknown_fields, rename_all = "camelCase")]
 pub struct WagiConfig {
     /// The name of the entrypoint.
@@ -263,19 +1011,46 @@ impl Default for WagiConfig {
     }
 }
 
+/// Configuration for the static file executor.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(default, deny_unknown_fields, rename_all = "camelCase")]
+pub struct StaticConfig {
+    /// The guest path of the mounted directory to serve.
+    pub dir: String,
+    /// The file served for requests to a directory.
+    pub index: String,
+}
+
+impl Default for StaticConfig {
+    fn default() -> StaticConfig {
+        StaticConfig {
+            dir: "/".to_owned(),
+            index: "index.html".to_owned(),
+        }
+    }
+}
+
 /// Configuration for the Redis trigger.
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
 pub struct RedisConfig {
     /// Redis channel to subscribe.
     pub channel: String,
     /// The Redis executor the component requires.
     pub executor: Option<RedisExecutor>,
+    /// Path of the JSON schema payloads must match, relative to the
+    /// manifest when loaded from a file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema: Option<PathBuf>,
+    /// Redis channel payloads that do not match the schema are published to,
+    /// rather than being dropped.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dead_letter_channel: Option<String>,
 }
 
 /// The executor for the Redis component.
 ///
 /// If an executor is not specified, the inferred default is `RedisExecutor::Spin`.
-#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
 #[serde(deny_unknown_fields, rename_all = "camelCase", tag = "type")]
 pub enum RedisExecutor {
     /// The component implements the Spin Redis interface.
@@ -288,14 +1063,65 @@ impl Default for RedisExecutor {
     }
 }
 
+/// Configuration for the message queue trigger.
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub struct QueueConfig {
+    /// Kafka topic or NATS subject to consume messages from.
+    pub topic: String,
+    /// The queue executor the component requires.
+    pub executor: Option<QueueExecutor>,
+    /// How many times a message the component fails to handle is retried,
+    /// after the first attempt.
+    #[serde(default = "default_queue_max_retries")]
+    pub max_retries: u32,
+    /// How long to wait before the first retry, in milliseconds. The delay
+    /// doubles for each later retry.
+    #[serde(default = "default_queue_retry_delay_ms")]
+    pub retry_delay_ms: u64,
+    /// Topic messages are published to once the component rejects them, or
+    /// fails to handle them after all retries, rather than being dropped.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dead_letter_topic: Option<String>,
+}
+
+fn default_queue_max_retries() -> u32 {
+    3
+}
+
+fn default_queue_retry_delay_ms() -> u64 {
+    1000
+}
+
+/// The executor for the queue component.
+///
+/// If an executor is not specified, the inferred default is `QueueExecutor::Spin`.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, rename_all = "camelCase", tag = "type")]
+pub enum QueueExecutor {
+    /// The component implements the Spin queue interface.
+    Spin,
+}
+
+impl Default for QueueExecutor {
+    fn default() -> Self {
+        Self::Spin
+    }
+}
+
 /// Trigger configuration.
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
 #[serde(deny_unknown_fields, rename_all = "camelCase", untagged)]
 pub enum TriggerConfig {
     /// HTTP trigger configuration
     Http(HttpConfig),
     /// Redis trigger configuration
     Redis(RedisConfig),
+    /// Message queue trigger configuration
+    Queue(QueueConfig),
+    /// Configuration for a trigger implemented by an external executable,
+    /// passed to the trigger as is
+    External(#[schemars(schema_with = "free_form_table")] toml::value::Table),
 }
 
 impl Default for TriggerConfig {
@@ -325,3 +1151,14 @@ impl TryFrom<TriggerConfig> for RedisConfig {
         }
     }
 }
+
+impl TryFrom<TriggerConfig> for QueueConfig {
+    type Error = Error;
+
+    fn try_from(trigger: TriggerConfig) -> Result<Self, Self::Error> {
+        match trigger {
+            TriggerConfig::Queue(queue) => Ok(queue),
+            _ => Err(Error::InvalidTriggerType),
+        }
+    }
+}
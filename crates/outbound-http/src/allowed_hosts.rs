@@ -0,0 +1,303 @@
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use anyhow::{bail, Context, Result};
+use spin_engine::SelfAddress;
+use url::{Host, Url};
+
+use crate::ALLOW_ALL_HOSTS;
+
+/// The entry of `allowed_http_hosts` allowing requests to the application itself.
+pub const SELF_HOST: &str = "self";
+
+/// The hosts a component may send requests to, parsed from `allowed_http_hosts`.
+///
+/// Entries are either URLs such as `https://api.example.com`, whose path is
+/// ignored, or a host and port such as `api.example.com:443`. The leftmost
+/// label of a host may be `*`, matching any subdomain. Ports may be `*` or a
+/// range such as `8000-8999`, and URLs without a port match any port.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AllowedHosts {
+    all: bool,
+    patterns: Vec<HostPattern>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum HostPattern {
+    /// The address the application is served on.
+    SelfHost,
+    Host {
+        host: HostMatch,
+        ports: (u16, u16),
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum HostMatch {
+    Exact(String),
+    /// Any subdomain of the domain, but not the domain itself.
+    Subdomains(String),
+}
+
+impl AllowedHosts {
+    /// Parses the entries of `allowed_http_hosts`. If one of them is
+    /// `insecure:allow-all`, all hosts are allowed and the others are not
+    /// checked.
+    pub fn parse(entries: &[String]) -> Result<Self> {
+        if entries.iter().any(|entry| entry == ALLOW_ALL_HOSTS) {
+            return Ok(Self {
+                all: true,
+                patterns: vec![],
+            });
+        }
+        let patterns = entries
+            .iter()
+            .map(|entry| {
+                parse_pattern(entry)
+                    .with_context(|| format!("Can't parse {} in allowed_http_hosts", entry))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            all: false,
+            patterns,
+        })
+    }
+
+    /// Whether a request to the URL is allowed, given the address the
+    /// application itself is served on.
+    pub fn allows(&self, url: &Url, self_address: &SelfAddress) -> bool {
+        if self.all {
+            return true;
+        }
+        let (host, port) = match (url.host(), url.port_or_known_default()) {
+            (Some(host), Some(port)) => (host_string(host), port),
+            _ => return false,
+        };
+        self.patterns.iter().any(|pattern| match pattern {
+            HostPattern::SelfHost => self_address.matches(&host, port),
+            HostPattern::Host {
+                host: pattern,
+                ports: (first, last),
+            } => {
+                let host_matches = match pattern {
+                    HostMatch::Exact(exact) => *exact == host,
+                    HostMatch::Subdomains(domain) => host
+                        .strip_suffix(domain.as_str())
+                        .map_or(false, |sub| sub.len() > 1 && sub.ends_with('.')),
+                };
+                host_matches && (*first..=*last).contains(&port)
+            }
+        })
+    }
+}
+
+fn parse_pattern(entry: &str) -> Result<HostPattern> {
+    if entry == SELF_HOST {
+        return Ok(HostPattern::SelfHost);
+    }
+    let (has_scheme, authority) = match entry.split_once("://") {
+        Some((scheme, rest)) => {
+            if !scheme.eq_ignore_ascii_case("http") && !scheme.eq_ignore_ascii_case("https") {
+                bail!("Unsupported scheme {:?}: expected http or https", scheme);
+            }
+            (true, rest.split(['/', '?', '#']).next().unwrap_or_default())
+        }
+        None if entry.contains('/') => bail!(
+            "Expected a URL such as https://example.com, or a host and port such as \
+             example.com:443"
+        ),
+        None => (false, entry),
+    };
+    if authority.contains('@') {
+        bail!("User information is not allowed");
+    }
+
+    let (host, port) = split_port(authority)?;
+    let ports = match port {
+        None if !has_scheme => bail!(
+            "Expected a URL such as https://{}, or a host and port such as {}:443",
+            host,
+            host
+        ),
+        None | Some("*") => (0, u16::MAX),
+        Some(port) => match port.split_once('-') {
+            Some((first, last)) => {
+                let (first, last) = (parse_port(first)?, parse_port(last)?);
+                if first > last {
+                    bail!(
+                        "Invalid port range {}: the first port is greater than the last",
+                        port
+                    );
+                }
+                (first, last)
+            }
+            None => {
+                let port = parse_port(port)?;
+                (port, port)
+            }
+        },
+    };
+    Ok(HostPattern::Host {
+        host: parse_host(host)?,
+        ports,
+    })
+}
+
+/// Splits an authority into its host and port, if any. IPv6 addresses must be
+/// enclosed in brackets.
+fn split_port(authority: &str) -> Result<(&str, Option<&str>)> {
+    if let Some(rest) = authority.strip_prefix('[') {
+        let (host, rest) = rest
+            .split_once(']')
+            .context("Unclosed bracket in IPv6 address")?;
+        return match rest {
+            "" => Ok((host, None)),
+            _ => match rest.strip_prefix(':') {
+                Some(port) => Ok((host, Some(port))),
+                None => bail!("Unexpected {:?} after IPv6 address", rest),
+            },
+        };
+    }
+    Ok(match authority.rsplit_once(':') {
+        Some((host, port)) => (host, Some(port)),
+        None => (authority, None),
+    })
+}
+
+fn parse_port(port: &str) -> Result<u16> {
+    match port.parse() {
+        Ok(port) if port > 0 => Ok(port),
+        _ => bail!("Invalid port {:?}: expected 1-65535, a range or *", port),
+    }
+}
+
+fn parse_host(host: &str) -> Result<HostMatch> {
+    if host.is_empty() {
+        bail!("Missing host");
+    }
+    if host == "*" {
+        bail!(
+            "A wildcard must be followed by a domain, as in *.example.com; to allow any \
+             host, use {}",
+            ALLOW_ALL_HOSTS
+        );
+    }
+    if let Ok(ip) = host.parse::<Ipv4Addr>() {
+        return Ok(HostMatch::Exact(ip.to_string()));
+    }
+    if let Ok(ip) = host.parse::<Ipv6Addr>() {
+        return Ok(HostMatch::Exact(ip.to_string()));
+    }
+    let (subdomains, domain) = match host.strip_prefix("*.") {
+        Some(domain) => (true, domain),
+        None => (false, host),
+    };
+    let valid_label = |label: &str| {
+        !label.is_empty()
+            && !label.starts_with('-')
+            && label
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    };
+    if !domain.split('.').all(valid_label) {
+        if domain.contains('*') {
+            bail!("Wildcards are only allowed as the leftmost label, as in *.example.com");
+        }
+        bail!("Invalid host {:?}", host);
+    }
+    let domain = domain.to_ascii_lowercase();
+    Ok(match subdomains {
+        true => HostMatch::Subdomains(domain),
+        false => HostMatch::Exact(domain),
+    })
+}
+
+/// The host of a URL, as patterns are matched against it.
+fn host_string(host: Host<&str>) -> String {
+    match host {
+        Host::Domain(domain) => domain.to_ascii_lowercase(),
+        Host::Ipv4(ip) => ip.to_string(),
+        Host::Ipv6(ip) => ip.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn allowed(entries: &[&str]) -> AllowedHosts {
+        let entries = entries.iter().map(|e| e.to_string()).collect::<Vec<_>>();
+        AllowedHosts::parse(&entries).unwrap()
+    }
+
+    fn allows(allowed: &AllowedHosts, url: &str) -> bool {
+        allowed.allows(&url.parse().unwrap(), &SelfAddress::default())
+    }
+
+    #[test]
+    fn test_urls() {
+        let hosts = allowed(&["https://api.example.com", "http://10.0.0.1:8080/v1"]);
+        assert!(allows(&hosts, "https://api.example.com/users"));
+        assert!(allows(&hosts, "http://API.example.com:9000"));
+        assert!(allows(&hosts, "http://10.0.0.1:8080"));
+        assert!(!allows(&hosts, "http://10.0.0.1:8081"));
+        assert!(!allows(&hosts, "https://example.com"));
+        assert!(!allows(&hosts, "https://v2.api.example.com"));
+    }
+
+    #[test]
+    fn test_wildcards() {
+        let hosts = allowed(&["*.internal.corp:443", "https://*.example.com:8000-8999"]);
+        assert!(allows(&hosts, "https://billing.internal.corp"));
+        assert!(allows(&hosts, "https://a.b.internal.corp:443"));
+        assert!(!allows(&hosts, "https://internal.corp"));
+        assert!(!allows(&hosts, "https://evilinternal.corp"));
+        assert!(!allows(&hosts, "http://billing.internal.corp"));
+        assert!(allows(&hosts, "http://api.example.com:8500"));
+        assert!(!allows(&hosts, "http://api.example.com:9000"));
+
+        let hosts = allowed(&["[::1]:*"]);
+        assert!(allows(&hosts, "http://[::1]:1234"));
+    }
+
+    #[test]
+    fn test_self() {
+        let hosts = allowed(&["self"]);
+        let address = SelfAddress::default();
+        let url = "http://localhost:3000/api".parse().unwrap();
+        assert!(!hosts.allows(&url, &address));
+        address.set("127.0.0.1:3000".parse().unwrap());
+        assert!(hosts.allows(&url, &address));
+        assert!(!hosts.allows(&"http://localhost:3001".parse().unwrap(), &address));
+    }
+
+    #[test]
+    fn test_allow_all() {
+        let hosts = allowed(&["insecure:allow-all", "not a host"]);
+        assert!(allows(&hosts, "https://anything.example.com"));
+    }
+
+    #[test]
+    fn test_invalid_patterns() {
+        for entry in [
+            "example.com",
+            "ftp://example.com",
+            "example.com/path:443",
+            "https://user@example.com",
+            "*:443",
+            "https://*",
+            "api.*.example.com:443",
+            "https://example.com:0",
+            "https://example.com:9000-8000",
+            "https://example.com:http",
+            "https://exa mple.com",
+            "https://[::1",
+            "https://",
+        ] {
+            assert!(
+                AllowedHosts::parse(&[entry.to_owned()]).is_err(),
+                "{} should be rejected",
+                entry
+            );
+        }
+    }
+}
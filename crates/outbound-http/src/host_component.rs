@@ -1,20 +1,52 @@
+use std::sync::Arc;
+
 use anyhow::Result;
 use wit_bindgen_wasmtime::wasmtime::Linker;
 
 use spin_engine::{
     host_component::{HostComponent, HostComponentsStateHandle},
-    RuntimeContext,
+    EgressMeter, RuntimeContext, SelfAddress,
 };
 use spin_manifest::CoreComponent;
 
-use crate::OutboundHttp;
+use crate::{OutboundHttp, WorkloadIdentity};
+
+#[derive(Default)]
+pub struct OutboundHttpComponent {
+    identity: Option<Arc<WorkloadIdentity>>,
+    egress: Arc<EgressMeter>,
+    self_address: Arc<SelfAddress>,
+}
+
+impl OutboundHttpComponent {
+    /// Creates the component, presenting the given identity to the services
+    /// it is configured for.
+    pub fn new(identity: Option<Arc<WorkloadIdentity>>) -> Self {
+        Self {
+            identity,
+            egress: Default::default(),
+            self_address: Default::default(),
+        }
+    }
 
-pub struct OutboundHttpComponent;
+    /// Meters the requests of components with the given meter.
+    pub fn with_egress(mut self, egress: Arc<EgressMeter>) -> Self {
+        self.egress = egress;
+        self
+    }
+
+    /// Allows requests to the given address, once set, through the `self`
+    /// entry of `allowed_http_hosts`.
+    pub fn with_self_address(mut self, self_address: Arc<SelfAddress>) -> Self {
+        self.self_address = self_address;
+        self
+    }
+}
 
 impl HostComponent for OutboundHttpComponent {
     type State = OutboundHttp;
 
-    fn add_to_linker<T>(
+    fn add_to_linker<T: Send>(
         linker: &mut Linker<RuntimeContext<T>>,
         data_handle: HostComponentsStateHandle<Self::State>,
     ) -> Result<()> {
@@ -24,7 +56,11 @@ impl HostComponent for OutboundHttpComponent {
 
     fn build_state(&self, component: &CoreComponent) -> Result<Self::State> {
         Ok(OutboundHttp {
-            allowed_hosts: Some(component.wasm.allowed_http_hosts.clone()),
+            identity: self.identity.clone(),
+            egress: self.egress.clone(),
+            component: component.id.clone(),
+            self_address: self.self_address.clone(),
+            ..OutboundHttp::new(Some(component.wasm.allowed_http_hosts.clone()))
         })
     }
 }
@@ -0,0 +1,284 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use reqwest::{Certificate, Identity, Url};
+use serde::Deserialize;
+
+/// Environment variable the SPIFFE Workload API socket is read from when the
+/// configuration does not name one, as set for workloads by SPIRE.
+pub const SPIFFE_ENDPOINT_SOCKET_ENV: &str = "SPIFFE_ENDPOINT_SOCKET";
+
+/// How often credentials are reloaded, to pick up rotated certificates.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Runtime configuration for the workload identity presented by outbound
+/// HTTP requests to internal services, for mutual TLS.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "snake_case", tag = "type")]
+pub enum WorkloadIdentityConfig {
+    /// Certificates read from files, such as those written by SPIFFE Helper
+    /// or cert-manager.
+    File {
+        /// PEM file of the certificate chain, leaf first.
+        cert_file: PathBuf,
+        /// PEM file of the private key of the certificate.
+        key_file: PathBuf,
+        /// PEM file of the certificates trusted to sign the certificates of
+        /// the services. Defaults to the system's trusted certificates.
+        bundle_file: Option<PathBuf>,
+        /// URLs of the services presented with the certificate.
+        hosts: Vec<String>,
+    },
+    /// X.509 SVIDs fetched from the SPIFFE Workload API, for example from the
+    /// SPIRE agent.
+    Spiffe {
+        /// Address of the Workload API, such as
+        /// `unix:///run/spire/sockets/agent.sock`. Defaults to
+        /// `SPIFFE_ENDPOINT_SOCKET`.
+        socket: Option<String>,
+        /// URLs of the services presented with the SVID.
+        hosts: Vec<String>,
+    },
+}
+
+impl WorkloadIdentityConfig {
+    fn hosts(&self) -> &[String] {
+        match self {
+            Self::File { hosts, .. } | Self::Spiffe { hosts, .. } => hosts,
+        }
+    }
+}
+
+/// The credentials presented to services.
+#[derive(Clone)]
+pub(crate) struct Credentials {
+    pub(crate) identity: Identity,
+    /// The certificates trusted to sign the certificates of services, in
+    /// addition to the system's.
+    pub(crate) roots: Vec<Certificate>,
+}
+
+/// The workload identity of the application, kept up to date as its
+/// certificates rotate. Only the host holds the keys: guests make requests,
+/// and the host presents the certificate.
+pub struct WorkloadIdentity {
+    config: WorkloadIdentityConfig,
+    hosts: Vec<String>,
+    credentials: RwLock<Credentials>,
+}
+
+impl WorkloadIdentity {
+    /// Loads the credentials, failing if they cannot be loaded, and keeps
+    /// reloading them in the background. Reloads that fail keep the previous
+    /// credentials.
+    pub async fn start(config: &WorkloadIdentityConfig) -> Result<Arc<Self>> {
+        let hosts = config
+            .hosts()
+            .iter()
+            .map(|h| {
+                let url = Url::parse(h)
+                    .with_context(|| format!("Invalid workload identity host {}", h))?;
+                url.host_str()
+                    .map(str::to_owned)
+                    .with_context(|| format!("Workload identity host {} has no host name", h))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let credentials = load(config).await?;
+        let identity = Arc::new(Self {
+            config: config.clone(),
+            hosts,
+            credentials: RwLock::new(credentials),
+        });
+        tokio::spawn(refresh(Arc::downgrade(&identity)));
+        Ok(identity)
+    }
+
+    /// The credentials to present to the service at the URL, if it is one
+    /// of the services the identity is presented to. Only HTTPS requests
+    /// present credentials.
+    pub(crate) fn credentials_for(&self, url: &Url) -> Option<Credentials> {
+        if url.scheme() != "https" {
+            return None;
+        }
+        let host = url.host_str()?;
+        if !self.hosts.iter().any(|h| h == host) {
+            return None;
+        }
+        Some(self.credentials.read().unwrap().clone())
+    }
+}
+
+/// Reloads the credentials of the identity until it is dropped.
+async fn refresh(identity: std::sync::Weak<WorkloadIdentity>) {
+    loop {
+        tokio::time::sleep(REFRESH_INTERVAL).await;
+        let identity = match identity.upgrade() {
+            Some(identity) => identity,
+            None => return,
+        };
+        match load(&identity.config).await {
+            Ok(credentials) => *identity.credentials.write().unwrap() = credentials,
+            Err(e) => tracing::warn!("Failed to reload workload identity: {:#}", e),
+        }
+    }
+}
+
+async fn load(config: &WorkloadIdentityConfig) -> Result<Credentials> {
+    match config {
+        WorkloadIdentityConfig::File {
+            cert_file,
+            key_file,
+            bundle_file,
+            ..
+        } => load_files(cert_file, key_file, bundle_file.as_deref()),
+        WorkloadIdentityConfig::Spiffe { socket, .. } => {
+            let socket = match socket {
+                Some(socket) => socket.clone(),
+                None => std::env::var(SPIFFE_ENDPOINT_SOCKET_ENV).with_context(|| {
+                    format!(
+                        "No SPIFFE Workload API socket configured, and {} is not set",
+                        SPIFFE_ENDPOINT_SOCKET_ENV
+                    )
+                })?,
+            };
+            fetch_svid(&socket).await
+        }
+    }
+}
+
+fn load_files(
+    cert_file: &Path,
+    key_file: &Path,
+    bundle_file: Option<&Path>,
+) -> Result<Credentials> {
+    let read = |path: &Path| {
+        std::fs::read(path).with_context(|| format!("Cannot read {}", path.display()))
+    };
+    // reqwest expects the key and the certificates in a single PEM buffer.
+    let mut pem = read(key_file)?;
+    pem.push(b'\n');
+    pem.extend(read(cert_file)?);
+    let identity = Identity::from_pem(&pem).with_context(|| {
+        format!(
+            "Invalid certificate {} or key {}",
+            cert_file.display(),
+            key_file.display()
+        )
+    })?;
+    let roots = match bundle_file {
+        Some(path) => pem_certificates(&read(path)?)
+            .with_context(|| format!("Invalid certificate bundle {}", path.display()))?,
+        None => vec![],
+    };
+    Ok(Credentials { identity, roots })
+}
+
+/// Fetches the default X.509 SVID of the workload, and the bundle of its
+/// trust domain, from the Workload API.
+async fn fetch_svid(socket: &str) -> Result<Credentials> {
+    let mut client = spiffe::workload_api::client::WorkloadApiClient::new_from_path(socket)
+        .await
+        .with_context(|| format!("Cannot connect to the SPIFFE Workload API at {}", socket))?;
+    let context = client
+        .fetch_x509_context()
+        .await
+        .context("Cannot fetch X.509 SVIDs from the SPIFFE Workload API")?;
+    let svid = context
+        .default_svid()
+        .context("The SPIFFE Workload API returned no X.509 SVID")?;
+
+    let mut pem = to_pem("PRIVATE KEY", svid.private_key().content());
+    for cert in svid.cert_chain() {
+        pem.push_str(&to_pem("CERTIFICATE", cert.content()));
+    }
+    let identity = Identity::from_pem(pem.as_bytes()).context("Invalid X.509 SVID")?;
+    let trust_domain = svid.spiffe_id().trust_domain();
+    let roots = match context.bundle_set().get_bundle(trust_domain) {
+        Some(bundle) => bundle
+            .authorities()
+            .iter()
+            .map(|c| Certificate::from_der(c.content()))
+            .collect::<Result<_, _>>()
+            .context("Invalid X.509 bundle")?,
+        None => vec![],
+    };
+    Ok(Credentials { identity, roots })
+}
+
+/// Splits a PEM bundle into its certificates.
+fn pem_certificates(pem: &[u8]) -> Result<Vec<Certificate>> {
+    const END: &str = "-----END CERTIFICATE-----";
+    let pem = std::str::from_utf8(pem)?;
+    pem.split_inclusive(END)
+        .filter(|block| block.contains("-----BEGIN CERTIFICATE-----"))
+        .map(|block| Ok(Certificate::from_pem(block.trim().as_bytes())?))
+        .collect()
+}
+
+/// Encodes DER bytes as a PEM block.
+fn to_pem(label: &str, der: &[u8]) -> String {
+    let encoded = base64::encode(der);
+    let mut pem = format!("-----BEGIN {}-----\n", label);
+    for line in encoded.as_bytes().chunks(64) {
+        // Base64 is ASCII, so every chunk is valid UTF-8.
+        pem.push_str(std::str::from_utf8(line).unwrap());
+        pem.push('\n');
+    }
+    pem.push_str(&format!("-----END {}-----\n", label));
+    pem
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CERT: &str = "../http/tests/local.crt.pem";
+    const KEY: &str = "../http/tests/local.key.pem";
+
+    #[tokio::test]
+    async fn test_credentials_only_for_configured_hosts() -> Result<()> {
+        let config = WorkloadIdentityConfig::File {
+            cert_file: CERT.into(),
+            key_file: KEY.into(),
+            bundle_file: Some(CERT.into()),
+            hosts: vec!["https://payments.internal:8443".to_owned()],
+        };
+        let identity = WorkloadIdentity::start(&config).await?;
+
+        let presented = identity
+            .credentials_for(&Url::parse("https://payments.internal:8443/charge")?)
+            .unwrap();
+        assert_eq!(presented.roots.len(), 1);
+        assert!(identity
+            .credentials_for(&Url::parse("http://payments.internal/charge")?)
+            .is_none());
+        assert!(identity
+            .credentials_for(&Url::parse("https://example.com/")?)
+            .is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_missing_files_fail_to_start() {
+        let config = WorkloadIdentityConfig::File {
+            cert_file: "missing.pem".into(),
+            key_file: KEY.into(),
+            bundle_file: None,
+            hosts: vec![],
+        };
+        assert!(WorkloadIdentity::start(&config).await.is_err());
+    }
+
+    #[test]
+    fn test_to_pem() {
+        let pem = to_pem("CERTIFICATE", &[0u8; 60]);
+        let lines: Vec<&str> = pem.lines().collect();
+        assert_eq!(lines[0], "-----BEGIN CERTIFICATE-----");
+        assert_eq!(lines[1].len(), 64);
+        assert_eq!(lines[3], "-----END CERTIFICATE-----");
+    }
+}
@@ -1,16 +1,25 @@
+mod allowed_hosts;
 mod host_component;
+mod identity;
 
-use futures::executor::block_on;
+use async_trait::async_trait;
 use http::HeaderMap;
+use identity::Credentials;
 use reqwest::{Client, Url};
-use std::str::FromStr;
-use tokio::runtime::Handle;
+use spin_engine::{EgressKind, EgressMeter, SelfAddress};
+use std::{str::FromStr, sync::Arc};
+use tracing::{field, Instrument};
 use wasi_outbound_http::*;
 
+pub use allowed_hosts::{AllowedHosts, SELF_HOST};
 pub use host_component::OutboundHttpComponent;
+pub use identity::{WorkloadIdentity, WorkloadIdentityConfig, SPIFFE_ENDPOINT_SOCKET_ENV};
 pub use wasi_outbound_http::add_to_linker;
 
-wit_bindgen_wasmtime::export!("../../wit/ephemeral/wasi-outbound-http.wit");
+wit_bindgen_wasmtime::export!({
+    paths: ["../../wit/ephemeral/wasi-outbound-http.wit"],
+    async: *,
+});
 
 pub const ALLOW_ALL_HOSTS: &str = "insecure:allow-all";
 
@@ -19,88 +28,128 @@ pub const ALLOW_ALL_HOSTS: &str = "insecure:allow-all";
 pub struct OutboundHttp {
     /// List of hosts guest modules are allowed to make requests to.
     pub allowed_hosts: Option<Vec<String>>,
+    /// The identity presented to the services it is configured for.
+    pub identity: Option<Arc<WorkloadIdentity>>,
+    /// Meters the requests of components.
+    pub egress: Arc<EgressMeter>,
+    /// The component sending the requests.
+    pub component: String,
+    /// The address the application is served on, allowed by `self`.
+    pub self_address: Arc<SelfAddress>,
 }
 
 impl OutboundHttp {
     pub fn new(allowed_hosts: Option<Vec<String>>) -> Self {
-        Self { allowed_hosts }
+        Self {
+            allowed_hosts,
+            identity: None,
+            egress: Default::default(),
+            component: String::new(),
+            self_address: Default::default(),
+        }
     }
 
     /// Check if guest module is allowed to send request to URL, based on the list of
     /// allowed hosts defined by the runtime. If the list of allowed hosts contains
-    /// `insecure:allow-all`, then all hosts are allowed.
+    /// `insecure:allow-all`, then all hosts are allowed, and `self` allows the
+    /// address the application is served on.
     /// If `None` is passed, the guest module is not allowed to send the request.
-    fn is_allowed(url: &str, allowed_hosts: Option<Vec<String>>) -> Result<bool, HttpError> {
-        let url_host = Url::parse(url)
-            .map_err(|_| HttpError::InvalidUrl)?
-            .host_str()
-            .ok_or(HttpError::InvalidUrl)?
-            .to_owned();
-        match allowed_hosts.as_deref() {
+    fn is_allowed(&self, url: &str) -> Result<bool, HttpError> {
+        let url = Url::parse(url).map_err(|_| HttpError::InvalidUrl)?;
+        if url.host().is_none() {
+            return Err(HttpError::InvalidUrl);
+        }
+        match self.allowed_hosts.as_deref() {
             Some(domains) => {
                 tracing::info!("Allowed hosts: {:?}", domains);
-                // check domains has any "insecure:allow-all" wildcard
-                if domains.iter().any(|domain| domain == ALLOW_ALL_HOSTS) {
-                    Ok(true)
-                } else {
-                    let allowed: Result<Vec<_>, _> =
-                        domains.iter().map(|d| Url::parse(d)).collect();
-                    let allowed = allowed.map_err(|_| HttpError::InvalidUrl)?;
-
-                    Ok(allowed
-                        .iter()
-                        .map(|u| u.host_str().unwrap())
-                        .any(|x| x == url_host.as_str()))
-                }
+                let allowed = AllowedHosts::parse(domains).map_err(|_| HttpError::InvalidUrl)?;
+                Ok(allowed.allows(&url, &self.self_address))
             }
             None => Ok(false),
         }
     }
-}
 
-impl wasi_outbound_http::WasiOutboundHttp for OutboundHttp {
-    fn request(&mut self, req: Request) -> Result<Response, HttpError> {
-        if !Self::is_allowed(req.uri, self.allowed_hosts.clone())? {
+    /// Counts the body bytes sent or received by a request of the component.
+    fn record(&self, bytes: usize) {
+        self.egress
+            .record(&self.component, EgressKind::Http, bytes as u64);
+    }
+
+    /// Sends a request of the component.
+    async fn send(&mut self, req: Request<'_>) -> Result<Response, HttpError> {
+        if !self.is_allowed(req.uri)? {
             tracing::log::info!("Destination not allowed: {}", req.uri);
             return Err(HttpError::DestinationNotAllowed);
         }
+        if let Err(e) = self.egress.admit(&self.component, EgressKind::Http) {
+            tracing::log::info!("Request of {} would exceed {}", self.component, e);
+            return Err(HttpError::DestinationNotAllowed);
+        }
 
         let method = http::Method::from(req.method);
         let url = Url::parse(req.uri).map_err(|_| HttpError::InvalidUrl)?;
-        let headers = request_headers(req.headers)?;
+        let mut headers = request_headers(req.headers)?;
+        // The service continues the trace of the request of the component.
+        spin_engine::telemetry::inject_trace_context(&mut headers);
         let body = req.body.unwrap_or_default().to_vec();
+        self.record(body.len());
+        let credentials = self
+            .identity
+            .as_ref()
+            .and_then(|identity| identity.credentials_for(&url));
+
+        let client = match credentials {
+            Some(credentials) => with_credentials(Client::builder(), credentials)
+                .build()
+                .map_err(|_| HttpError::RuntimeError)?,
+            None => Client::new(),
+        };
+        let res = client
+            .request(method, url)
+            .headers(headers)
+            .body(body)
+            .send()
+            .await?;
+        let res = Response::from_reqwest(res).await?;
+        self.record(res.body.as_ref().map(Vec::len).unwrap_or_default());
+        Ok(res)
+    }
+}
 
-        match Handle::try_current() {
-            // If running in a Tokio runtime, spawn a new blocking executor
-            // that will send the HTTP request, and block on its execution.
-            // This attempts to avoid any deadlocks from other operations
-            // already executing on the same executor (compared with just
-            // blocking on the current one).
-            Ok(r) => block_on(r.spawn_blocking(move || -> Result<Response, HttpError> {
-                let client = Client::builder().build().unwrap();
-                let res = block_on(
-                    client
-                        .request(method, url)
-                        .headers(headers)
-                        .body(body)
-                        .send(),
-                )?;
-
-                Response::try_from(res)
-            }))
-            .map_err(|_| HttpError::RuntimeError)?,
-            Err(_) => {
-                let res = reqwest::blocking::Client::new()
-                    .request(method, url)
-                    .headers(headers)
-                    .body(body)
-                    .send()?;
-                Ok(Response::try_from(res)?)
-            }
-        }
+#[async_trait]
+impl wasi_outbound_http::WasiOutboundHttp for OutboundHttp {
+    async fn request(&mut self, req: Request<'_>) -> Result<Response, HttpError> {
+        let span = tracing::info_span!(
+            "outbound_http.request",
+            otel.kind = "client",
+            component = %self.component,
+            http.method = %http::Method::from(req.method),
+            http.url = %req.uri,
+            http.status_code = field::Empty,
+            otel.status_code = field::Empty,
+        );
+        let res = self.send(req).instrument(span.clone()).await;
+        match &res {
+            Ok(res) => span.record("http.status_code", &res.status),
+            Err(_) => span.record("otel.status_code", &"ERROR"),
+        };
+        res
     }
 }
 
+/// Presents the workload identity to the service, and trusts the
+/// certificates of its bundle.
+fn with_credentials(
+    builder: reqwest::ClientBuilder,
+    credentials: Credentials,
+) -> reqwest::ClientBuilder {
+    let mut builder = builder.use_rustls_tls().identity(credentials.identity);
+    for root in credentials.roots {
+        builder = builder.add_root_certificate(root);
+    }
+    builder
+}
+
 impl From<Method> for http::Method {
     fn from(m: Method) -> Self {
         match m {
@@ -115,30 +164,11 @@ impl From<Method> for http::Method {
     }
 }
 
-impl TryFrom<reqwest::Response> for Response {
-    type Error = HttpError;
-
-    fn try_from(res: reqwest::Response) -> Result<Self, Self::Error> {
-        let status = res.status().as_u16();
-        let headers = response_headers(res.headers())?;
-
-        let body = Some(block_on(res.bytes())?.to_vec());
-
-        Ok(Response {
-            status,
-            headers,
-            body,
-        })
-    }
-}
-
-impl TryFrom<reqwest::blocking::Response> for Response {
-    type Error = HttpError;
-
-    fn try_from(res: reqwest::blocking::Response) -> Result<Self, Self::Error> {
+impl Response {
+    async fn from_reqwest(res: reqwest::Response) -> Result<Self, HttpError> {
         let status = res.status().as_u16();
         let headers = response_headers(res.headers())?;
-        let body = Some(res.bytes()?.to_vec());
+        let body = Some(res.bytes().await?.to_vec());
 
         Ok(Response {
             status,
@@ -0,0 +1,325 @@
+use std::{mem::size_of_val, sync::Arc};
+
+use async_trait::async_trait;
+use mysql::{
+    consts::{ColumnFlags, ColumnType},
+    from_value_opt,
+    prelude::Queryable,
+    Conn, FromValueError, Opts, Params, Row, Value,
+};
+use outbound_mysql::*;
+
+pub use outbound_mysql::add_to_linker;
+use spin_engine::{
+    host_component::{HostComponent, HostComponentsStateHandle},
+    EgressKind, EgressMeter, RuntimeContext,
+};
+use wit_bindgen_wasmtime::wasmtime::Linker;
+
+wit_bindgen_wasmtime::export!({
+    paths: ["../../wit/ephemeral/outbound-mysql.wit"],
+    async: *,
+});
+
+/// The character set MySQL reports for binary columns.
+const BINARY_CHARSET: u16 = 63;
+
+/// A simple implementation to support outbound MySQL connection
+#[derive(Default, Clone)]
+pub struct OutboundMysql {
+    /// Meters the statements of components.
+    egress: Arc<EgressMeter>,
+    /// The component executing the statements.
+    component: String,
+    /// The database hosts the component is allowed to connect to.
+    allowed_hosts: Vec<String>,
+}
+
+impl OutboundMysql {
+    /// Creates the component, metering statements with the given meter.
+    pub fn new(egress: Arc<EgressMeter>) -> Self {
+        Self {
+            egress,
+            component: String::new(),
+            allowed_hosts: vec![],
+        }
+    }
+
+    /// The options to connect to the database at the address, if its host is
+    /// in the `allowed_database_hosts` of the component.
+    fn connect_opts(&self, address: &str) -> Result<Opts, MysqlError> {
+        let opts = Opts::from_url(address)
+            .map_err(|e| MysqlError::ConnectionFailed(format!("{:?}", e)))?;
+        let host: &str = &opts.get_ip_or_hostname();
+        let port = opts.get_tcp_port();
+        let allowed = opts.get_socket().is_none()
+            && spin_manifest::allows_database_host(&self.allowed_hosts, host, port);
+        if !allowed {
+            tracing::info!(
+                "Component {} is not allowed to connect to {}:{}",
+                self.component,
+                host,
+                port
+            );
+            return Err(MysqlError::ConnectionFailed(format!(
+                "host {:?} is not in allowed_database_hosts",
+                host
+            )));
+        }
+        Ok(opts)
+    }
+
+    /// Counts a statement of the component, and the bytes of the statement
+    /// and its parameters, unless it would exceed the quotas of the
+    /// component.
+    fn admit(&self, statement: &str, params: &[ParameterValue<'_>]) -> Result<(), MysqlError> {
+        self.egress
+            .admit(&self.component, EgressKind::Mysql)
+            .map_err(|e| {
+                tracing::info!("Statement of {} would exceed {}", self.component, e);
+                MysqlError::OtherError(format!("Statement would exceed {}", e))
+            })?;
+        let bytes = statement.len() + params.iter().map(parameter_size).sum::<usize>();
+        self.record(bytes);
+        Ok(())
+    }
+
+    fn record(&self, bytes: usize) {
+        self.egress
+            .record(&self.component, EgressKind::Mysql, bytes as u64);
+    }
+}
+
+impl HostComponent for OutboundMysql {
+    type State = Self;
+
+    fn add_to_linker<T: Send>(
+        linker: &mut Linker<RuntimeContext<T>>,
+        state_handle: HostComponentsStateHandle<Self::State>,
+    ) -> anyhow::Result<()> {
+        add_to_linker(linker, move |ctx| state_handle.get_mut(ctx))
+    }
+
+    fn build_state(&self, component: &spin_manifest::CoreComponent) -> anyhow::Result<Self::State> {
+        Ok(Self {
+            egress: self.egress.clone(),
+            component: component.id.clone(),
+            allowed_hosts: component.wasm.allowed_database_hosts.clone(),
+        })
+    }
+}
+
+#[async_trait]
+impl outbound_mysql::OutboundMysql for OutboundMysql {
+    async fn execute(
+        &mut self,
+        address: &str,
+        statement: &str,
+        params: Vec<ParameterValue<'_>>,
+    ) -> Result<u64, MysqlError> {
+        self.admit(statement, &params)?;
+        let opts = self.connect_opts(address)?;
+        let statement = statement.to_owned();
+        let params = to_params(&params);
+
+        run_blocking(move || {
+            let mut conn = connect(opts)?;
+            conn.exec_drop(statement, params)
+                .map_err(|e| MysqlError::QueryFailed(format!("{:?}", e)))?;
+            Ok(conn.affected_rows())
+        })
+        .await
+    }
+
+    async fn query(
+        &mut self,
+        address: &str,
+        statement: &str,
+        params: Vec<ParameterValue<'_>>,
+    ) -> Result<RowSet, MysqlError> {
+        self.admit(statement, &params)?;
+        let opts = self.connect_opts(address)?;
+        let statement = statement.to_owned();
+        let params = to_params(&params);
+
+        let row_set = run_blocking(move || {
+            let mut conn = connect(opts)?;
+            let mut results = conn
+                .exec_iter(statement, params)
+                .map_err(|e| MysqlError::QueryFailed(format!("{:?}", e)))?;
+
+            let columns = results
+                .columns()
+                .as_ref()
+                .iter()
+                .map(convert_column)
+                .collect::<Vec<_>>();
+            let rows = results
+                .by_ref()
+                .map(|row| {
+                    let row = row.map_err(|e| MysqlError::QueryFailed(format!("{:?}", e)))?;
+                    convert_row(row, &columns)
+                        .map_err(|e| MysqlError::ValueConversionFailed(format!("{:?}", e)))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(RowSet { columns, rows })
+        })
+        .await?;
+        self.record(row_set.rows.iter().flatten().map(value_size).sum());
+
+        Ok(row_set)
+    }
+}
+
+fn connect(opts: Opts) -> Result<Conn, MysqlError> {
+    Conn::new(opts).map_err(|e| MysqlError::ConnectionFailed(format!("{:?}", e)))
+}
+
+/// Runs work with the MySQL client, which blocks on the network, on the
+/// blocking thread pool rather than the thread running the guest.
+async fn run_blocking<T: Send + 'static>(
+    work: impl FnOnce() -> Result<T, MysqlError> + Send + 'static,
+) -> Result<T, MysqlError> {
+    tokio::task::spawn_blocking(work)
+        .await
+        .map_err(|e| MysqlError::OtherError(format!("{:?}", e)))?
+}
+
+/// The positional parameters of a statement, bound to its `?` placeholders.
+fn to_params(params: &[ParameterValue<'_>]) -> Params {
+    if params.is_empty() {
+        return Params::Empty;
+    }
+    Params::Positional(params.iter().map(to_sql_parameter).collect())
+}
+
+fn to_sql_parameter(value: &ParameterValue) -> Value {
+    match value {
+        ParameterValue::Boolean(v) => Value::Int(*v as i64),
+        ParameterValue::Int8(v) => Value::Int(*v as i64),
+        ParameterValue::Int16(v) => Value::Int(*v as i64),
+        ParameterValue::Int32(v) => Value::Int(*v as i64),
+        ParameterValue::Int64(v) => Value::Int(*v),
+        ParameterValue::Uint8(v) => Value::UInt(*v as u64),
+        ParameterValue::Uint16(v) => Value::UInt(*v as u64),
+        ParameterValue::Uint32(v) => Value::UInt(*v as u64),
+        ParameterValue::Uint64(v) => Value::UInt(*v),
+        ParameterValue::Floating32(v) => Value::Float(*v),
+        ParameterValue::Floating64(v) => Value::Double(*v),
+        ParameterValue::Str(v) => Value::Bytes(v.as_bytes().to_vec()),
+        ParameterValue::Binary(v) => Value::Bytes(v.to_vec()),
+        ParameterValue::DbNull => Value::NULL,
+    }
+}
+
+/// The size of a parameter sent to the database.
+fn parameter_size(value: &ParameterValue) -> usize {
+    match value {
+        ParameterValue::Boolean(v) => size_of_val(v),
+        ParameterValue::Int8(v) => size_of_val(v),
+        ParameterValue::Int16(v) => size_of_val(v),
+        ParameterValue::Int32(v) => size_of_val(v),
+        ParameterValue::Int64(v) => size_of_val(v),
+        ParameterValue::Uint8(v) => size_of_val(v),
+        ParameterValue::Uint16(v) => size_of_val(v),
+        ParameterValue::Uint32(v) => size_of_val(v),
+        ParameterValue::Uint64(v) => size_of_val(v),
+        ParameterValue::Floating32(v) => size_of_val(v),
+        ParameterValue::Floating64(v) => size_of_val(v),
+        ParameterValue::Str(v) => v.len(),
+        ParameterValue::Binary(v) => v.len(),
+        ParameterValue::DbNull => 0,
+    }
+}
+
+/// The size of a value received from the database.
+fn value_size(value: &DbValue) -> usize {
+    match value {
+        DbValue::Boolean(v) => size_of_val(v),
+        DbValue::Int8(v) => size_of_val(v),
+        DbValue::Int16(v) => size_of_val(v),
+        DbValue::Int32(v) => size_of_val(v),
+        DbValue::Int64(v) => size_of_val(v),
+        DbValue::Uint8(v) => size_of_val(v),
+        DbValue::Uint16(v) => size_of_val(v),
+        DbValue::Uint32(v) => size_of_val(v),
+        DbValue::Uint64(v) => size_of_val(v),
+        DbValue::Floating32(v) => size_of_val(v),
+        DbValue::Floating64(v) => size_of_val(v),
+        DbValue::Str(v) => v.len(),
+        DbValue::Binary(v) => v.len(),
+        DbValue::DbNull | DbValue::Unsupported => 0,
+    }
+}
+
+fn convert_column(column: &mysql::Column) -> Column {
+    Column {
+        name: column.name_str().into_owned(),
+        data_type: convert_data_type(column),
+    }
+}
+
+fn convert_data_type(column: &mysql::Column) -> DbDataType {
+    let unsigned = column.flags().contains(ColumnFlags::UNSIGNED_FLAG);
+    match (column.column_type(), unsigned) {
+        (ColumnType::MYSQL_TYPE_TINY, false) => DbDataType::Int8,
+        (ColumnType::MYSQL_TYPE_TINY, true) => DbDataType::Uint8,
+        (ColumnType::MYSQL_TYPE_SHORT, false) => DbDataType::Int16,
+        (ColumnType::MYSQL_TYPE_SHORT, true) => DbDataType::Uint16,
+        (ColumnType::MYSQL_TYPE_INT24 | ColumnType::MYSQL_TYPE_LONG, false) => DbDataType::Int32,
+        (ColumnType::MYSQL_TYPE_INT24 | ColumnType::MYSQL_TYPE_LONG, true) => DbDataType::Uint32,
+        (ColumnType::MYSQL_TYPE_LONGLONG, false) => DbDataType::Int64,
+        (ColumnType::MYSQL_TYPE_LONGLONG, true) => DbDataType::Uint64,
+        (ColumnType::MYSQL_TYPE_FLOAT, _) => DbDataType::Floating32,
+        (ColumnType::MYSQL_TYPE_DOUBLE, _) => DbDataType::Floating64,
+        (
+            ColumnType::MYSQL_TYPE_VARCHAR
+            | ColumnType::MYSQL_TYPE_VAR_STRING
+            | ColumnType::MYSQL_TYPE_STRING
+            | ColumnType::MYSQL_TYPE_TINY_BLOB
+            | ColumnType::MYSQL_TYPE_MEDIUM_BLOB
+            | ColumnType::MYSQL_TYPE_LONG_BLOB
+            | ColumnType::MYSQL_TYPE_BLOB,
+            _,
+        ) => {
+            if column.character_set() == BINARY_CHARSET {
+                DbDataType::Binary
+            } else {
+                DbDataType::Str
+            }
+        }
+        (ColumnType::MYSQL_TYPE_DECIMAL | ColumnType::MYSQL_TYPE_NEWDECIMAL, _) => DbDataType::Str,
+        (column_type, _) => {
+            tracing::debug!("Couldn't convert MySQL type {:?} to WIT", column_type);
+            DbDataType::Other
+        }
+    }
+}
+
+fn convert_row(row: Row, columns: &[Column]) -> Result<Vec<DbValue>, FromValueError> {
+    row.unwrap()
+        .into_iter()
+        .zip(columns)
+        .map(|(value, column)| convert_value(value, column.data_type))
+        .collect()
+}
+
+fn convert_value(value: Value, data_type: DbDataType) -> Result<DbValue, FromValueError> {
+    let value = match (value, data_type) {
+        (Value::NULL, _) => DbValue::DbNull,
+        (v, DbDataType::Int8) => DbValue::Int8(from_value_opt(v)?),
+        (v, DbDataType::Int16) => DbValue::Int16(from_value_opt(v)?),
+        (v, DbDataType::Int32) => DbValue::Int32(from_value_opt(v)?),
+        (v, DbDataType::Int64) => DbValue::Int64(from_value_opt(v)?),
+        (v, DbDataType::Uint8) => DbValue::Uint8(from_value_opt(v)?),
+        (v, DbDataType::Uint16) => DbValue::Uint16(from_value_opt(v)?),
+        (v, DbDataType::Uint32) => DbValue::Uint32(from_value_opt(v)?),
+        (v, DbDataType::Uint64) => DbValue::Uint64(from_value_opt(v)?),
+        (v, DbDataType::Floating32) => DbValue::Floating32(from_value_opt(v)?),
+        (v, DbDataType::Floating64) => DbValue::Floating64(from_value_opt(v)?),
+        (v, DbDataType::Str) => DbValue::Str(from_value_opt(v)?),
+        (v, DbDataType::Binary) => DbValue::Binary(from_value_opt(v)?),
+        (_, DbDataType::Boolean | DbDataType::Other) => DbValue::Unsupported,
+    };
+    Ok(value)
+}
@@ -1,47 +1,141 @@
+use std::{mem::size_of_val, sync::Arc};
+
 use anyhow::anyhow;
+use async_trait::async_trait;
 use outbound_pg::*;
-use postgres::{types::ToSql, types::Type, Client, NoTls, Row};
+use tokio_postgres::{config::Host, types::ToSql, types::Type, Client, Config, NoTls, Row};
 
 pub use outbound_pg::add_to_linker;
 use spin_engine::{
     host_component::{HostComponent, HostComponentsStateHandle},
-    RuntimeContext,
+    EgressKind, EgressMeter, RuntimeContext,
 };
 use wit_bindgen_wasmtime::wasmtime::Linker;
 
-wit_bindgen_wasmtime::export!("../../wit/ephemeral/outbound-pg.wit");
+wit_bindgen_wasmtime::export!({
+    paths: ["../../wit/ephemeral/outbound-pg.wit"],
+    async: *,
+});
 
 /// A simple implementation to support outbound pg connection
 #[derive(Default, Clone)]
-pub struct OutboundPg;
+pub struct OutboundPg {
+    /// Meters the statements of components.
+    egress: Arc<EgressMeter>,
+    /// The component executing the statements.
+    component: String,
+    /// The database hosts the component is allowed to connect to.
+    allowed_hosts: Vec<String>,
+}
+
+impl OutboundPg {
+    /// Creates the component, metering statements with the given meter.
+    pub fn new(egress: Arc<EgressMeter>) -> Self {
+        Self {
+            egress,
+            component: String::new(),
+            allowed_hosts: vec![],
+        }
+    }
+
+    /// Connects to the database at the address, if each of its hosts is in
+    /// the `allowed_database_hosts` of the component.
+    async fn connect(&self, address: &str) -> Result<Client, PgError> {
+        let config: Config = address
+            .parse()
+            .map_err(|e| PgError::ConnectionFailed(format!("{:?}", e)))?;
+        if config.get_hosts().is_empty() {
+            return Err(PgError::ConnectionFailed(
+                "the address has no host".to_owned(),
+            ));
+        }
+        // A single port applies to every host.
+        let ports = config.get_ports();
+        for (idx, host) in config.get_hosts().iter().enumerate() {
+            let port = ports.get(idx).or_else(|| ports.first()).copied();
+            let allowed = match host {
+                Host::Tcp(host) => spin_manifest::allows_database_host(
+                    &self.allowed_hosts,
+                    host,
+                    port.unwrap_or(DEFAULT_PORT),
+                ),
+                Host::Unix(_) => false,
+            };
+            if !allowed {
+                tracing::info!(
+                    "Component {} is not allowed to connect to {:?}",
+                    self.component,
+                    host
+                );
+                return Err(PgError::ConnectionFailed(format!(
+                    "host {:?} is not in allowed_database_hosts",
+                    host
+                )));
+            }
+        }
+        let (client, connection) = config
+            .connect(NoTls)
+            .await
+            .map_err(|e| PgError::ConnectionFailed(format!("{:?}", e)))?;
+        // The connection runs until the client is dropped.
+        tokio::spawn(async move {
+            if let Err(e) = connection.await {
+                tracing::warn!("Postgres connection error: {}", e);
+            }
+        });
+        Ok(client)
+    }
+
+    /// Counts a statement of the component, and the bytes of the statement
+    /// and its parameters, unless it would exceed the quotas of the
+    /// component.
+    fn admit(&self, statement: &str, params: &[ParameterValue<'_>]) -> Result<(), PgError> {
+        self.egress
+            .admit(&self.component, EgressKind::Postgres)
+            .map_err(|e| {
+                tracing::info!("Statement of {} would exceed {}", self.component, e);
+                PgError::OtherError(format!("Statement would exceed {}", e))
+            })?;
+        let bytes = statement.len() + params.iter().map(parameter_size).sum::<usize>();
+        self.record(bytes);
+        Ok(())
+    }
+
+    fn record(&self, bytes: usize) {
+        self.egress
+            .record(&self.component, EgressKind::Postgres, bytes as u64);
+    }
+}
 
 impl HostComponent for OutboundPg {
     type State = Self;
 
-    fn add_to_linker<T>(
+    fn add_to_linker<T: Send>(
         linker: &mut Linker<RuntimeContext<T>>,
         state_handle: HostComponentsStateHandle<Self::State>,
     ) -> anyhow::Result<()> {
         add_to_linker(linker, move |ctx| state_handle.get_mut(ctx))
     }
 
-    fn build_state(
-        &self,
-        _component: &spin_manifest::CoreComponent,
-    ) -> anyhow::Result<Self::State> {
-        Ok(Self)
+    fn build_state(&self, component: &spin_manifest::CoreComponent) -> anyhow::Result<Self::State> {
+        Ok(Self {
+            egress: self.egress.clone(),
+            component: component.id.clone(),
+            allowed_hosts: component.wasm.allowed_database_hosts.clone(),
+        })
     }
 }
 
+#[async_trait]
 impl outbound_pg::OutboundPg for OutboundPg {
-    fn execute(
+    async fn execute(
         &mut self,
         address: &str,
         statement: &str,
         params: Vec<ParameterValue<'_>>,
     ) -> Result<u64, PgError> {
-        let mut client = Client::connect(address, NoTls)
-            .map_err(|e| PgError::ConnectionFailed(format!("{:?}", e)))?;
+        self.admit(statement, &params)?;
+        let client = self.connect(address).await?;
 
         let params: Vec<&(dyn ToSql + Sync)> = params
             .iter()
@@ -51,19 +145,20 @@ impl outbound_pg::OutboundPg for OutboundPg {
 
         let nrow = client
             .execute(statement, params.as_slice())
+            .await
             .map_err(|e| PgError::ValueConversionFailed(format!("{:?}", e)))?;
 
         Ok(nrow)
     }
 
-    fn query(
+    async fn query(
         &mut self,
         address: &str,
         statement: &str,
         params: Vec<ParameterValue<'_>>,
     ) -> Result<RowSet, PgError> {
-        let mut client = Client::connect(address, NoTls)
-            .map_err(|e| PgError::ConnectionFailed(format!("{:?}", e)))?;
+        self.admit(statement, &params)?;
+        let client = self.connect(address).await?;
 
         let params: Vec<&(dyn ToSql + Sync)> = params
             .iter()
@@ -73,6 +168,7 @@ impl outbound_pg::OutboundPg for OutboundPg {
 
         let results = client
             .query(statement, params.as_slice())
+            .await
             .map_err(|e| PgError::QueryFailed(format!("{:?}", e)))?;
 
         if results.is_empty() {
@@ -88,6 +184,7 @@ impl outbound_pg::OutboundPg for OutboundPg {
             .map(convert_row)
             .collect::<Result<Vec<_>, _>>()
             .map_err(|e| PgError::QueryFailed(format!("{:?}", e)))?;
+        self.record(rows.iter().flatten().map(value_size).sum());
 
         Ok(RowSet { columns, rows })
     }
@@ -95,6 +192,9 @@ impl outbound_pg::OutboundPg for OutboundPg {
 
 const DB_NULL: Option<i32> = None;
 
+/// The port of a host that the address gives none for.
+const DEFAULT_PORT: u16 = 5432;
+
 fn to_sql_parameter<'a>(value: &'a ParameterValue) -> anyhow::Result<&'a (dyn ToSql + Sync)> {
     match value {
         ParameterValue::Boolean(v) => Ok(v),
@@ -114,6 +214,46 @@ fn to_sql_parameter<'a>(value: &'a ParameterValue) -> anyhow::Result<&'a (dyn To
     }
 }
 
+/// The size of a parameter sent to the database.
+fn parameter_size(value: &ParameterValue) -> usize {
+    match value {
+        ParameterValue::Boolean(v) => size_of_val(v),
+        ParameterValue::Int8(v) => size_of_val(v),
+        ParameterValue::Int16(v) => size_of_val(v),
+        ParameterValue::Int32(v) => size_of_val(v),
+        ParameterValue::Int64(v) => size_of_val(v),
+        ParameterValue::Uint8(v) => size_of_val(v),
+        ParameterValue::Uint16(v) => size_of_val(v),
+        ParameterValue::Uint32(v) => size_of_val(v),
+        ParameterValue::Uint64(v) => size_of_val(v),
+        ParameterValue::Floating32(v) => size_of_val(v),
+        ParameterValue::Floating64(v) => size_of_val(v),
+        ParameterValue::Str(v) => v.len(),
+        ParameterValue::Binary(v) => v.len(),
+        ParameterValue::DbNull => 0,
+    }
+}
+
+/// The size of a value received from the database.
+fn value_size(value: &DbValue) -> usize {
+    match value {
+        DbValue::Boolean(v) => size_of_val(v),
+        DbValue::Int8(v) => size_of_val(v),
+        DbValue::Int16(v) => size_of_val(v),
+        DbValue::Int32(v) => size_of_val(v),
+        DbValue::Int64(v) => size_of_val(v),
+        DbValue::Uint8(v) => size_of_val(v),
+        DbValue::Uint16(v) => size_of_val(v),
+        DbValue::Uint32(v) => size_of_val(v),
+        DbValue::Uint64(v) => size_of_val(v),
+        DbValue::Floating32(v) => size_of_val(v),
+        DbValue::Floating64(v) => size_of_val(v),
+        DbValue::Str(v) => v.len(),
+        DbValue::Binary(v) => v.len(),
+        DbValue::DbNull | DbValue::Unsupported => 0,
+    }
+}
+
 fn infer_columns(row: &Row) -> Vec<Column> {
     let mut result = Vec::with_capacity(row.len());
     for index in 0..row.len() {
@@ -147,7 +287,7 @@ fn convert_data_type(pg_type: &Type) -> DbDataType {
     }
 }
 
-fn convert_row(row: &Row) -> Result<Vec<DbValue>, postgres::Error> {
+fn convert_row(row: &Row) -> Result<Vec<DbValue>, tokio_postgres::Error> {
     let mut result = Vec::with_capacity(row.len());
     for index in 0..row.len() {
         result.push(convert_entry(row, index)?);
@@ -155,7 +295,7 @@ fn convert_row(row: &Row) -> Result<Vec<DbValue>, postgres::Error> {
     Ok(result)
 }
 
-fn convert_entry(row: &Row, index: usize) -> Result<DbValue, postgres::Error> {
+fn convert_entry(row: &Row, index: usize) -> Result<DbValue, tokio_postgres::Error> {
     let column = &row.columns()[index];
     let value = match column.type_() {
         &Type::BOOL => {
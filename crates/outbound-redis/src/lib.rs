@@ -1,65 +1,129 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
 use outbound_redis::*;
-use redis::Commands;
+use redis::AsyncCommands;
 
 pub use outbound_redis::add_to_linker;
 use spin_engine::{
     host_component::{HostComponent, HostComponentsStateHandle},
-    RuntimeContext,
+    EgressKind, EgressMeter, RuntimeContext,
 };
 use wit_bindgen_wasmtime::wasmtime::Linker;
 
-wit_bindgen_wasmtime::export!("../../wit/ephemeral/outbound-redis.wit");
+wit_bindgen_wasmtime::export!({
+    paths: ["../../wit/ephemeral/outbound-redis.wit"],
+    async: *,
+});
 
 /// A simple implementation to support outbound Redis commands.
 #[derive(Default, Clone)]
-pub struct OutboundRedis;
+pub struct OutboundRedis {
+    /// Meters the commands of components.
+    egress: Arc<EgressMeter>,
+    /// The component sending the commands.
+    component: String,
+}
+
+impl OutboundRedis {
+    /// Creates the component, metering commands with the given meter.
+    pub fn new(egress: Arc<EgressMeter>) -> Self {
+        Self {
+            egress,
+            component: String::new(),
+        }
+    }
+
+    /// Counts a command of the component, unless it would exceed the
+    /// quotas of the component.
+    fn admit(&self) -> Result<(), Error> {
+        self.egress
+            .admit(&self.component, EgressKind::Redis)
+            .map_err(|e| {
+                tracing::log::info!("Redis command of {} would exceed {}", self.component, e);
+                Error::Error
+            })
+    }
+
+    fn record(&self, bytes: usize) {
+        self.egress
+            .record(&self.component, EgressKind::Redis, bytes as u64);
+    }
+}
 
 impl HostComponent for OutboundRedis {
     type State = Self;
 
-    fn add_to_linker<T>(
+    fn add_to_linker<T: Send>(
         linker: &mut Linker<RuntimeContext<T>>,
         state_handle: HostComponentsStateHandle<Self::State>,
     ) -> anyhow::Result<()> {
         add_to_linker(linker, move |ctx| state_handle.get_mut(ctx))
     }
 
-    fn build_state(
-        &self,
-        _component: &spin_manifest::CoreComponent,
-    ) -> anyhow::Result<Self::State> {
-        Ok(Self)
+    fn build_state(&self, component: &spin_manifest::CoreComponent) -> anyhow::Result<Self::State> {
+        Ok(Self {
+            egress: self.egress.clone(),
+            component: component.id.clone(),
+        })
     }
 }
 
+#[async_trait]
 impl outbound_redis::OutboundRedis for OutboundRedis {
-    fn publish(&mut self, address: &str, channel: &str, payload: &[u8]) -> Result<(), Error> {
+    #[tracing::instrument(name = "outbound_redis.publish", skip_all, fields(otel.kind = "client", component = %self.component))]
+    async fn publish(&mut self, address: &str, channel: &str, payload: &[u8]) -> Result<(), Error> {
+        self.admit()?;
+        self.record(channel.len() + payload.len());
         let client = redis::Client::open(address).map_err(|_| Error::Error)?;
-        let mut pubsub_conn = client.get_connection().map_err(|_| Error::Error)?;
+        let mut pubsub_conn = client
+            .get_async_connection()
+            .await
+            .map_err(|_| Error::Error)?;
         pubsub_conn
             .publish(channel, payload)
+            .await
             .map_err(|_| Error::Error)?;
         Ok(())
     }
 
-    fn get(&mut self, address: &str, key: &str) -> Result<Vec<u8>, Error> {
+    #[tracing::instrument(name = "outbound_redis.get", skip_all, fields(otel.kind = "client", component = %self.component))]
+    async fn get(&mut self, address: &str, key: &str) -> Result<Vec<u8>, Error> {
+        self.admit()?;
+        self.record(key.len());
         let client = redis::Client::open(address).map_err(|_| Error::Error)?;
-        let mut conn = client.get_connection().map_err(|_| Error::Error)?;
-        let value = conn.get(key).map_err(|_| Error::Error)?;
+        let mut conn = client
+            .get_async_connection()
+            .await
+            .map_err(|_| Error::Error)?;
+        let value: Vec<u8> = conn.get(key).await.map_err(|_| Error::Error)?;
+        self.record(value.len());
         Ok(value)
     }
 
-    fn set(&mut self, address: &str, key: &str, value: &[u8]) -> Result<(), Error> {
+    #[tracing::instrument(name = "outbound_redis.set", skip_all, fields(otel.kind = "client", component = %self.component))]
+    async fn set(&mut self, address: &str, key: &str, value: &[u8]) -> Result<(), Error> {
+        self.admit()?;
+        self.record(key.len() + value.len());
         let client = redis::Client::open(address).map_err(|_| Error::Error)?;
-        let mut conn = client.get_connection().map_err(|_| Error::Error)?;
-        conn.set(key, value).map_err(|_| Error::Error)?;
+        let mut conn = client
+            .get_async_connection()
+            .await
+            .map_err(|_| Error::Error)?;
+        conn.set(key, value).await.map_err(|_| Error::Error)?;
         Ok(())
     }
 
-    fn incr(&mut self, address: &str, key: &str) -> Result<i64, Error> {
+    #[tracing::instrument(name = "outbound_redis.incr", skip_all, fields(otel.kind = "client", component = %self.component))]
+    async fn incr(&mut self, address: &str, key: &str) -> Result<i64, Error> {
+        self.admit()?;
+        self.record(key.len());
         let client = redis::Client::open(address).map_err(|_| Error::Error)?;
-        let mut conn = client.get_connection().map_err(|_| Error::Error)?;
-        let value = conn.incr(key, 1).map_err(|_| Error::Error)?;
+        let mut conn = client
+            .get_async_connection()
+            .await
+            .map_err(|_| Error::Error)?;
+        let value = conn.incr(key, 1).await.map_err(|_| Error::Error)?;
         Ok(value)
     }
 }
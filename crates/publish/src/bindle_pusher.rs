@@ -1,16 +1,89 @@
 #![deny(missing_docs)]
 
 use anyhow::{Context, Result};
-use bindle::{standalone::StandaloneRead, Id};
-use std::path::Path;
+use bindle::{client::ClientError, Id, Invoice, Label, Parcel};
+use futures::{Stream, StreamExt, TryStreamExt};
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+use tokio::io::AsyncReadExt;
+
+use crate::{
+    bindle_writer::{invoice_file, parcel_file, upload_state_file},
+    progress::UploadProgress,
+    upload_state::UploadState,
+    RetryPolicy,
+};
+
+/// The number of parcels uploaded at once by default.
+pub const DEFAULT_PUSH_CONCURRENCY: usize = 4;
+
+/// The size of the chunks parcel files are read and streamed in, in bytes.
+const PARCEL_CHUNK_SIZE: usize = 8 * 1024 * 1024;
+
+/// How a bindle is pushed.
+#[derive(Clone, Copy, Debug)]
+pub struct PushOptions {
+    /// The maximum number of parcels uploaded at once.
+    pub concurrency: usize,
+    /// How requests failing with transient errors are retried.
+    pub retry: RetryPolicy,
+}
+
+impl Default for PushOptions {
+    fn default() -> Self {
+        Self {
+            concurrency: DEFAULT_PUSH_CONCURRENCY,
+            retry: RetryPolicy::default(),
+        }
+    }
+}
+
+/// What pushing a bindle uploaded, and what the server already had.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PushSummary {
+    /// The number of parcels uploaded.
+    pub uploaded: usize,
+    /// The total size of the parcels uploaded, in bytes.
+    pub uploaded_bytes: u64,
+    /// The number of parcels the server already had, which were not
+    /// uploaded.
+    pub skipped: usize,
+    /// The total size of the parcels the server already had, in bytes.
+    pub skipped_bytes: u64,
+    /// Whether the push resumed an earlier push of the bindle that did not
+    /// finish.
+    pub resumed: bool,
+}
 
 /// Pushes a standalone bindle to a Bindle server.
+///
+/// Parcels are stored by their SHA, so the server only asks for those it does
+/// not have; parcels unchanged since an earlier version, or shared with
+/// another bindle, are not uploaded again. Parcels are uploaded concurrently,
+/// streamed from their files in chunks, with their progress shown on the
+/// terminal, and requests failing with transient errors are retried, as the
+/// options say.
+///
+/// The parcels uploaded are recorded in the bindle's directory, so that
+/// pushing the bindle again from the same directory after a failed push
+/// resumes it, uploading only the parcels the server is missing, instead of
+/// failing because the invoice already exists.
 pub async fn push_all(
     path: impl AsRef<Path>,
     bindle_id: &Id,
     bindle_connection_info: crate::BindleConnectionInfo,
-) -> Result<()> {
-    let reader = StandaloneRead::new(&path, bindle_id).await?;
+    options: PushOptions,
+) -> Result<PushSummary> {
+    let path = path.as_ref();
+    let invoice_path = invoice_file(path, bindle_id);
+    let invoice_text = tokio::fs::read(&invoice_path)
+        .await
+        .with_context(|| format!("Failed to read invoice '{}'", invoice_path.display()))?;
+    let invoice: Invoice = toml::from_slice(&invoice_text)
+        .with_context(|| format!("Invalid invoice '{}'", invoice_path.display()))?;
+
     let client = &bindle_connection_info.client().with_context(|| {
         format!(
             "Failed to create a bindle client for server '{}'",
@@ -18,14 +91,151 @@ pub async fn push_all(
         )
     })?;
 
-    if client.get_yanked_invoice(bindle_id).await.is_ok() {
-        anyhow::bail!("Bindle {} already exists on the server", bindle_id);
-    }
+    let retry = options.retry;
+    let state_path = upload_state_file(path, bindle_id);
+    let (invoice, state, missing, resumed) = match client.get_yanked_invoice(bindle_id).await {
+        Ok(existing) => match UploadState::load(state_path, &bindle_id.to_string()) {
+            // The server's invoice lists the parcels to upload, in case the
+            // bindle was staged again since.
+            Some(state) => {
+                let missing = existing
+                    .parcel
+                    .iter()
+                    .flatten()
+                    .filter(|parcel| !state.is_uploaded(&parcel.label.sha256))
+                    .map(|parcel| parcel.label.clone())
+                    .collect::<Vec<_>>();
+                (existing, state, missing, true)
+            }
+            None => anyhow::bail!("Bindle {} already exists on the server", bindle_id),
+        },
+        Err(_) => {
+            let invoice = &invoice;
+            let missing = retry
+                .run_checked(
+                    "Creating the invoice",
+                    || async move {
+                        let created = client.create_invoice(invoice.clone()).await?;
+                        Ok(created.missing.unwrap_or_default())
+                    },
+                    // A request failing in transit may have created the
+                    // invoice. All its parcels are then uploaded, the server
+                    // rejecting those it already has.
+                    || async move {
+                        match client.get_yanked_invoice(bindle_id).await {
+                            Ok(_) => Ok(Some(
+                                invoice
+                                    .parcel
+                                    .iter()
+                                    .flatten()
+                                    .map(|parcel| parcel.label.clone())
+                                    .collect(),
+                            )),
+                            Err(ClientError::InvoiceNotFound) => Ok(None),
+                            Err(e) => Err(e.into()),
+                        }
+                    },
+                )
+                .await
+                .with_context(|| push_failed_msg(path, &bindle_connection_info.base_url))?;
+            let missing_shas: HashSet<_> = missing.iter().map(|label| &label.sha256).collect();
+            let present = invoice
+                .parcel
+                .iter()
+                .flatten()
+                .map(|parcel| &parcel.label.sha256)
+                .filter(|sha256| !missing_shas.contains(sha256))
+                .cloned()
+                .collect::<Vec<_>>();
+            let state = UploadState::start(state_path, &bindle_id.to_string(), present)?;
+            (invoice.clone(), state, missing, false)
+        }
+    };
+    let (missing, mut summary) =
+        parcels_to_upload(invoice.parcel.as_deref().unwrap_or_default(), &missing);
 
-    reader
-        .push(client)
+    let (progress, uploads) = (&UploadProgress::new(&missing), &state);
+    futures::stream::iter(missing)
+        .map(|parcel| async move {
+            let sha256 = &parcel.label.sha256;
+            progress.started(parcel);
+            retry
+                .run(
+                    &format!("Uploading parcel '{}'", parcel.label.name),
+                    || async move {
+                        let file = parcel_file(path, bindle_id, sha256);
+                        let chunks = parcel_chunks(file, PARCEL_CHUNK_SIZE).await?;
+                        match client
+                            .create_parcel_from_stream(bindle_id, sha256, chunks)
+                            .await
+                        {
+                            // The parcel was uploaded by an interrupted push,
+                            // which did not get to record it.
+                            Err(ClientError::ParcelAlreadyExists) => Ok(()),
+                            result => Ok(result?),
+                        }
+                    },
+                )
+                .await
+                .with_context(|| format!("Failed to upload parcel '{}'", parcel.label.name))?;
+            uploads.record(sha256)?;
+            progress.finished(parcel);
+            Ok::<_, anyhow::Error>(())
+        })
+        .buffer_unordered(options.concurrency.max(1))
+        .try_collect::<Vec<_>>()
         .await
-        .with_context(|| push_failed_msg(path, &bindle_connection_info.base_url))
+        .with_context(|| push_failed_msg(path, &bindle_connection_info.base_url))?;
+
+    state.finish()?;
+    summary.resumed = resumed;
+    Ok(summary)
+}
+
+/// Streams the content of a parcel file in chunks of at most the given size.
+async fn parcel_chunks(
+    file: PathBuf,
+    chunk_size: usize,
+) -> Result<impl Stream<Item = std::io::Result<bytes::Bytes>> + Unpin + Send + Sync + 'static> {
+    let file = tokio::fs::File::open(&file)
+        .await
+        .with_context(|| format!("Failed to read parcel file '{}'", file.display()))?;
+    let chunk_size = chunk_size.max(1) as u64;
+    let chunks = futures::stream::try_unfold(file, move |mut file| async move {
+        let mut chunk = vec![];
+        (&mut file).take(chunk_size).read_to_end(&mut chunk).await?;
+        if chunk.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some((bytes::Bytes::from(chunk), file)))
+    });
+    Ok(Box::pin(chunks))
+}
+
+/// The parcels of an invoice the server reported missing, each once, and
+/// the summary of uploading them.
+fn parcels_to_upload<'a>(
+    parcels: &'a [Parcel],
+    missing: &[Label],
+) -> (Vec<&'a Parcel>, PushSummary) {
+    let missing: HashSet<_> = missing.iter().map(|label| &label.sha256).collect();
+    let mut seen = HashSet::new();
+    let mut to_upload = vec![];
+    let mut summary = PushSummary::default();
+    for parcel in parcels {
+        if !seen.insert(&parcel.label.sha256) {
+            continue;
+        }
+        if missing.contains(&parcel.label.sha256) {
+            summary.uploaded += 1;
+            summary.uploaded_bytes += parcel.label.size;
+            to_upload.push(parcel);
+        } else {
+            summary.skipped += 1;
+            summary.skipped_bytes += parcel.label.size;
+        }
+    }
+    (to_upload, summary)
 }
 
 fn push_failed_msg(path: impl AsRef<Path>, server_url: &str) -> String {
@@ -35,3 +245,49 @@ fn push_failed_msg(path: impl AsRef<Path>, server_url: &str) -> String {
         server_url
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parcel(name: &str, sha256: &str, size: u64) -> Parcel {
+        Parcel {
+            label: Label {
+                name: name.to_owned(),
+                sha256: sha256.to_owned(),
+                size,
+                media_type: "application/octet-stream".to_owned(),
+                annotations: None,
+                feature: None,
+                origin: None,
+            },
+            conditions: None,
+        }
+    }
+
+    #[test]
+    fn test_only_missing_parcels_are_uploaded() {
+        let parcels = vec![
+            parcel("app.wasm", "aaa", 100),
+            parcel("static/index.html", "bbb", 20),
+            parcel("static/logo.png", "ccc", 3000),
+            // The same content mounted twice is one parcel on the server.
+            parcel("other/logo.png", "ccc", 3000),
+        ];
+        let missing = vec![parcels[0].label.clone()];
+
+        let (to_upload, summary) = parcels_to_upload(&parcels, &missing);
+        let names: Vec<_> = to_upload.iter().map(|p| p.label.name.as_str()).collect();
+        assert_eq!(names, vec!["app.wasm"]);
+        assert_eq!(
+            summary,
+            PushSummary {
+                uploaded: 1,
+                uploaded_bytes: 100,
+                skipped: 2,
+                skipped_bytes: 3020,
+                resumed: false,
+            }
+        );
+    }
+}
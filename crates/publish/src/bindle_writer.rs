@@ -1,7 +1,7 @@
 #![deny(missing_docs)]
 
 use anyhow::{Context, Result};
-use bindle::{Invoice, Parcel};
+use bindle::{invoice::signature::SecretKeyEntry, Id, Invoice, Parcel};
 use std::{
     collections::BTreeMap,
     path::{Path, PathBuf},
@@ -14,17 +14,23 @@ struct BindleWriter {
     parcel_sources: ParcelSources,
 }
 
-/// Writes an invoice and supporting parcels out as a standalone bindle.
+/// Writes an invoice and supporting parcels out as a standalone bindle,
+/// signing the invoice as its creator if a signing key is given.
 pub async fn write(
     source_dir: impl AsRef<Path>,
     dest_dir: impl AsRef<Path>,
     invoice: &Invoice,
     parcel_sources: &ParcelSources,
+    signing_key: Option<&SecretKeyEntry>,
 ) -> Result<()> {
+    let invoice = match signing_key {
+        Some(key) => crate::sign_invoice(invoice, key)?,
+        None => invoice.clone(),
+    };
     let writer = BindleWriter {
         source_dir: source_dir.as_ref().to_owned(),
         dest_dir: dest_dir.as_ref().to_owned(),
-        invoice: invoice.clone(),
+        invoice,
         parcel_sources: parcel_sources.clone(),
     };
     writer.write().await
@@ -33,8 +39,7 @@ pub async fn write(
 impl BindleWriter {
     async fn write(&self) -> Result<()> {
         // This is very similar to bindle::StandaloneWrite::write but... not quite the same
-        let bindle_id_hash = self.invoice.bindle.id.sha();
-        let bindle_dir = self.dest_dir.join(bindle_id_hash);
+        let bindle_dir = bindle_dir(&self.dest_dir, &self.invoice.bindle.id);
         let parcels_dir = bindle_dir.join("parcels");
         tokio::fs::create_dir_all(&parcels_dir).await?;
 
@@ -45,7 +50,7 @@ impl BindleWriter {
 
     async fn write_invoice_file(&self, bindle_dir: &Path) -> Result<()> {
         let invoice_text = toml::to_string_pretty(&self.invoice)?;
-        let invoice_file = bindle_dir.join("invoice.toml");
+        let invoice_file = bindle_dir.join(INVOICE_FILE);
         tokio::fs::write(&invoice_file, &invoice_text)
             .await
             .with_context(|| format!("Failed to write invoice to '{}'", invoice_file.display()))?;
@@ -74,8 +79,9 @@ impl BindleWriter {
             None => self.source_dir.join(&parcel.label.name),
         };
         let hash = &parcel.label.sha256;
-        let dest_file = parcels_dir.join(format!("{}.dat", hash));
-        tokio::fs::copy(&source_file, &dest_file)
+        let dest_file = parcels_dir.join(parcel_file_name(hash));
+        // Parcels are only read once written, so can share the source's storage.
+        spin_loader::staging::link_or_copy_file(&source_file, &dest_file)
             .await
             .with_context(|| copy_parcel_failed_msg(&source_file, &dest_file))?;
 
@@ -87,6 +93,39 @@ impl BindleWriter {
     }
 }
 
+const INVOICE_FILE: &str = "invoice.toml";
+const UPLOAD_STATE_FILE: &str = "upload-state.toml";
+
+/// The directory a standalone bindle is written to in the destination
+/// directory.
+fn bindle_dir(dest_dir: &Path, bindle_id: &Id) -> PathBuf {
+    dest_dir.join(bindle_id.sha())
+}
+
+fn parcel_file_name(sha256: &str) -> String {
+    format!("{}.dat", sha256)
+}
+
+/// The invoice file of a standalone bindle written to the destination
+/// directory.
+pub(crate) fn invoice_file(dest_dir: &Path, bindle_id: &Id) -> PathBuf {
+    bindle_dir(dest_dir, bindle_id).join(INVOICE_FILE)
+}
+
+/// The file recording the progress of pushing a standalone bindle written to
+/// the destination directory.
+pub(crate) fn upload_state_file(dest_dir: &Path, bindle_id: &Id) -> PathBuf {
+    bindle_dir(dest_dir, bindle_id).join(UPLOAD_STATE_FILE)
+}
+
+/// The file of a parcel of a standalone bindle written to the destination
+/// directory.
+pub(crate) fn parcel_file(dest_dir: &Path, bindle_id: &Id, sha256: &str) -> PathBuf {
+    bindle_dir(dest_dir, bindle_id)
+        .join("parcels")
+        .join(parcel_file_name(sha256))
+}
+
 #[derive(Debug, Clone)]
 pub struct ParcelSource {
     digest: String,
@@ -4,17 +4,24 @@ use crate::bindle_writer::{self, ParcelSources};
 use anyhow::{Context, Result};
 use bindle::{BindleSpec, Condition, Group, Invoice, Label, Parcel};
 use path_absolutize::Absolutize;
-use semver::BuildMetadata;
+use semver::{BuildMetadata, Version};
 use sha2::{Digest, Sha256};
 use spin_loader::{
     bindle::config as bindle_schema,
-    local::{config as local_schema, validate_raw_app_manifest},
+    local::{config as local_schema, validate_raw_app_manifest, validate_source_paths},
+    url_source::UrlSource,
+};
+use std::{
+    io::Read,
+    path::{Path, PathBuf},
 };
-use std::path::{Path, PathBuf};
 
-/// Expands a file-based application manifest to a Bindle invoice.
+/// Expands a file-based application manifest to a Bindle invoice. If a
+/// name or version is given, it is used instead of the manifest one.
 pub async fn expand_manifest(
     app_file: impl AsRef<Path>,
+    name: Option<String>,
+    version: Option<Version>,
     buildinfo: Option<BuildMetadata>,
     scratch_dir: impl AsRef<Path>,
 ) -> Result<(Invoice, ParcelSources)> {
@@ -24,8 +31,9 @@ pub async fn expand_manifest(
         .context("Failed to resolve absolute path to manifest file")?;
     let manifest = spin_loader::local::raw_manifest_from_file(&app_file).await?;
     validate_raw_app_manifest(&manifest)?;
-    let local_schema::RawAppManifestAnyVersion::V1(manifest) = manifest;
     let app_dir = app_dir(&app_file)?;
+    validate_source_paths(&manifest, &app_dir)?;
+    let local_schema::RawAppManifestAnyVersion::V1(manifest) = manifest;
 
     // * create a new spin.toml-like document where
     //   - each component changes its `files` entry to a group name
@@ -39,6 +47,7 @@ pub async fn expand_manifest(
     //   - there is a parcel for each module source
     //   - if a component refers to an asset then the asset is in the component's group
     //     - the source and manifest parcels should NOT be group members
+    //   - there is a parcel for each template of a native route
     //   - there is a parcel for the spin.toml-a-like and it has the magic media type
 
     // - n parcels for the Wasm modules at their locations
@@ -51,13 +60,22 @@ pub async fn expand_manifest(
         .await
         .context("Failed to collect asset files")?;
     let asset_parcels = consolidate_asset_parcels(asset_parcels);
+    // - n parcels for the templates of native routes
+    let template_parcels = template_parcels(&manifest, &app_dir)
+        .await
+        .context("Failed to collect templates")?;
     // - one parcel to rule them all, and in the Spin app bind them
     let manifest_parcel = manifest_parcel(&dest_manifest, &scratch_dir).await?;
 
-    let sourced_parcels = itertools::concat([vec![manifest_parcel], wasm_parcels, asset_parcels]);
+    let sourced_parcels = itertools::concat([
+        vec![manifest_parcel],
+        wasm_parcels,
+        asset_parcels,
+        template_parcels,
+    ]);
     let (parcels, sources) = split_sources(sourced_parcels);
 
-    let bindle_id = bindle_id(&manifest.info, buildinfo)?;
+    let bindle_id = bindle_id(&manifest.info, name, version, buildinfo)?;
     let groups = build_groups(&manifest);
 
     let invoice = Invoice {
@@ -88,7 +106,19 @@ fn bindle_manifest(
         .map(|c| bindle_component_manifest(c, base_dir))
         .collect::<Result<Vec<_>>>()
         .context("Failed to convert components to Bindle format")?;
-    let trigger = local.info.trigger.clone();
+    let mut trigger = local.info.trigger.clone();
+    if let spin_manifest::ApplicationTrigger::Http(http) = &mut trigger {
+        // Templates are published as parcels, and referred to by their SHA.
+        for route in &mut http.routes {
+            if let spin_manifest::HttpHandler::Template(template) = &mut route.handler {
+                let full_path = base_dir.join(&template.template);
+                let digest = file_digest_string(&full_path).with_context(|| {
+                    format!("Failed to get parcel id for '{}'", full_path.display())
+                })?;
+                template.template = digest.into();
+            }
+        }
+    }
     let config = local.config.clone();
 
     Ok(bindle_schema::RawAppManifest {
@@ -105,6 +135,7 @@ fn bindle_component_manifest(
     let source_digest = match &local.source {
         local_schema::RawModuleSource::FileReference(path) => {
             let full_path = base_dir.join(path);
+            ensure_core_module(&local.id, &full_path)?;
             file_digest_string(&full_path)
                 .with_context(|| format!("Failed to get parcel id for '{}'", full_path.display()))?
         }
@@ -113,7 +144,21 @@ fn bindle_component_manifest(
                 "This version of Spin can't publish components whose sources are already bindles"
             )
         }
+        local_schema::RawModuleSource::Url(u) => {
+            UrlSource::parse(&u.url, &u.digest)
+                .with_context(|| format!("Invalid source for component {}", local.id))?
+                .sha256
+        }
     };
+    if let spin_manifest::TriggerConfig::Redis(spin_manifest::RedisConfig {
+        schema: Some(_), ..
+    }) = &local.trigger
+    {
+        anyhow::bail!(
+            "This version of Spin can't publish components with payload schemas, declared by component {}",
+            local.id
+        )
+    }
     let asset_group = local.wasm.files.as_ref().map(|_| group_name_for(&local.id));
     Ok(bindle_schema::RawComponentManifest {
         id: local.id.clone(),
@@ -123,6 +168,15 @@ fn bindle_component_manifest(
             environment: local.wasm.environment.clone(),
             files: asset_group,
             allowed_http_hosts: local.wasm.allowed_http_hosts.clone(),
+            allowed_blob_containers: local.wasm.allowed_blob_containers.clone(),
+            allowed_database_hosts: local.wasm.allowed_database_hosts.clone(),
+            key_value_stores: local.wasm.key_value_stores.clone(),
+            allowed_jwt_issuers: local.wasm.allowed_jwt_issuers.clone(),
+            allowed_crypto_keys: local.wasm.allowed_crypto_keys.clone(),
+            load: local.wasm.load,
+            limits: local.wasm.limits.clone(),
+            log_level: local.wasm.log_level,
+            host_config: local.wasm.host_config.clone(),
         },
         trigger: local.trigger.clone(),
         config: local.config.clone(),
@@ -142,17 +196,32 @@ async fn wasm_parcel(
     component: &local_schema::RawComponentManifest,
     base_dir: &Path,
 ) -> Result<SourcedParcel> {
-    let wasm_file = match &component.source {
-        local_schema::RawModuleSource::FileReference(path) => path,
+    match &component.source {
+        local_schema::RawModuleSource::FileReference(path) => {
+            let absolute_wasm_file = base_dir.join(path);
+            file_parcel(&absolute_wasm_file, path, None, "application/wasm").await
+        }
         local_schema::RawModuleSource::Bindle(_) => {
             anyhow::bail!(
                 "This version of Spin can't publish components whose sources are already bindles"
             )
         }
-    };
-    let absolute_wasm_file = base_dir.join(wasm_file);
-
-    file_parcel(&absolute_wasm_file, wasm_file, None, "application/wasm").await
+        local_schema::RawModuleSource::Url(u) => {
+            // The module is published as a parcel, so that the application
+            // does not depend on the URL remaining available.
+            let source = UrlSource::parse(&u.url, &u.digest)
+                .with_context(|| format!("Invalid source for component {}", component.id))?;
+            let cached = source.fetch(&component.id).await?;
+            let name = source
+                .url
+                .path_segments()
+                .and_then(|mut segments| segments.next_back())
+                .filter(|name| !name.is_empty())
+                .map(|name| name.to_owned())
+                .unwrap_or_else(|| format!("{}.wasm", component.id));
+            file_parcel(&cached, name, None, "application/wasm").await
+        }
+    }
 }
 
 async fn asset_parcels(
@@ -173,6 +242,37 @@ async fn asset_parcels(
     Ok(parcels)
 }
 
+async fn template_parcels(
+    manifest: &local_schema::RawAppManifest,
+    base_dir: &Path,
+) -> Result<Vec<SourcedParcel>> {
+    let templates = match &manifest.info.trigger {
+        spin_manifest::ApplicationTrigger::Http(http) => http
+            .routes
+            .iter()
+            .filter_map(|route| match &route.handler {
+                spin_manifest::HttpHandler::Template(template) => Some(&template.template),
+                _ => None,
+            })
+            .collect(),
+        _ => vec![],
+    };
+    let parcel_futures = templates.into_iter().map(|path| async move {
+        let media_type = mime_guess::from_path(path)
+            .first_or_octet_stream()
+            .to_string();
+        file_parcel(&base_dir.join(path), path, None, media_type)
+            .await
+            .with_context(|| format!("Failed to assemble parcel from '{}'", path.display()))
+    });
+    let parcels = futures::future::join_all(parcel_futures)
+        .await
+        .into_iter()
+        .collect::<Result<Vec<_>>>()?;
+    // Like Wasm modules, templates are referred to by their content.
+    Ok(consolidate_wasm_parcels(parcels))
+}
+
 fn collect_assets(
     component: &local_schema::RawComponentManifest,
     base_dir: impl AsRef<Path>,
@@ -223,7 +323,7 @@ async fn file_parcel(
     let parcel = Parcel {
         label: Label {
             sha256: digest,
-            name: dest_relative_path.as_ref().display().to_string(),
+            name: parcel_name(dest_relative_path),
             size,
             media_type: media_type.into(),
             annotations: None,
@@ -242,6 +342,25 @@ async fn file_parcel(
     })
 }
 
+/// The name of a parcel at a path relative to the application directory.
+/// Names always use '/' separators, so that a bindle pushed from Windows is
+/// the same as one pushed from other platforms. Modules outside the
+/// application directory are named without their leading `..` segments.
+fn parcel_name(relative_path: impl AsRef<Path>) -> String {
+    relative_path
+        .as_ref()
+        .components()
+        .filter(|c| {
+            !matches!(
+                c,
+                std::path::Component::CurDir | std::path::Component::ParentDir
+            )
+        })
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
 async fn manifest_parcel(
     manifest: &bindle_schema::RawAppManifest,
     scratch_dir: impl AsRef<Path>,
@@ -367,6 +486,22 @@ fn group_for(component_id: &str) -> Group {
     }
 }
 
+/// Fails if the module of a component is a component of the WebAssembly
+/// component model, which Spin cannot run, rather than publishing it.
+fn ensure_core_module(component_id: &str, path: &Path) -> Result<()> {
+    let mut preamble = [0; 8];
+    let read = std::fs::File::open(path)
+        .and_then(|mut file| file.read_exact(&mut preamble))
+        .is_ok();
+    if read && spin_manifest::is_wasm_component(&preamble) {
+        anyhow::bail!(
+            "The module of component {} is a WebAssembly component. This version of Spin only runs core WebAssembly modules: build it without a component adapter, such as for the wasm32-wasi target",
+            component_id
+        );
+    }
+    Ok(())
+}
+
 fn file_digest_string(path: impl AsRef<Path>) -> Result<String> {
     let mut file = std::fs::File::open(&path)?;
     let mut sha = Sha256::new();
@@ -384,11 +519,18 @@ fn bytes_digest_string(bytes: &[u8]) -> String {
 
 fn bindle_id(
     app_info: &local_schema::RawAppInformation,
+    name: Option<String>,
+    version: Option<Version>,
     buildinfo: Option<BuildMetadata>,
 ) -> Result<bindle::Id> {
+    let name = name.unwrap_or_else(|| app_info.name.clone());
+    let version = match version {
+        Some(version) => version.to_string(),
+        None => app_info.version.clone(),
+    };
     let text = match buildinfo {
-        None => format!("{}/{}", app_info.name, app_info.version),
-        Some(buildinfo) => format!("{}/{}+{}", app_info.name, app_info.version, buildinfo),
+        None => format!("{}/{}", name, version),
+        Some(buildinfo) => format!("{}/{}+{}", name, version, buildinfo),
     };
     bindle::Id::try_from(&text)
         .with_context(|| format!("App name and version '{}' do not form a bindle ID", text))
@@ -422,3 +564,28 @@ fn split_sources(sourced_parcels: Vec<SourcedParcel>) -> (Vec<Parcel>, ParcelSou
 
     (parcels.collect(), parcel_sources)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parcel_name() {
+        assert_eq!(parcel_name("static/a.txt"), "static/a.txt");
+        assert_eq!(parcel_name("./static//a.txt"), "static/a.txt");
+        assert_eq!(
+            parcel_name("../shared/target/auth.wasm"),
+            "shared/target/auth.wasm"
+        );
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_parcel_name_on_windows() {
+        assert_eq!(
+            parcel_name(r"target\wasm32-wasi\release\app.wasm"),
+            "target/wasm32-wasi/release/app.wasm"
+        );
+        assert_eq!(parcel_name(r"static/nested\a.txt"), "static/nested/a.txt");
+    }
+}
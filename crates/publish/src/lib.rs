@@ -1,14 +1,27 @@
 #![deny(missing_docs)]
 
-//! Functions for publishing Spin applications to Bindle.
+//! Functions for publishing Spin applications to Bindle or OCI registries.
 
 mod bindle_pusher;
 mod bindle_writer;
 mod expander;
+mod oci;
+mod progress;
+mod retry;
+mod signing;
+mod upload_state;
+mod version;
 
-pub use bindle_pusher::push_all;
+pub use bindle_pusher::{push_all, PushOptions, PushSummary, DEFAULT_PUSH_CONCURRENCY};
 pub use bindle_writer::write;
 pub use expander::expand_manifest;
+pub use oci::{push_oci, tag_for_version, OciRepository};
+pub use retry::RetryPolicy;
+pub use signing::{
+    find_signing_key, generate_signing_key, load_signing_keys, public_key, save_signing_keys,
+    sign_invoice, signing_keys_path,
+};
+pub use version::{date_version, next_patch_version};
 
 use bindle::client::{
     tokens::{HttpBasic, NoToken, TokenManager},
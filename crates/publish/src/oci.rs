@@ -0,0 +1,188 @@
+#![deny(missing_docs)]
+
+use anyhow::{bail, Context, Result};
+use bindle::Invoice;
+use docker_credential::{CredentialRetrievalError, DockerCredential};
+use oci_distribution::{
+    client::{ClientConfig, Config, ImageLayer},
+    secrets::RegistryAuth,
+    Client, Reference,
+};
+use std::{collections::HashMap, path::Path, str::FromStr};
+
+use crate::bindle_writer::{invoice_file, parcel_file};
+
+/// The media type of the config of a Spin application artifact: the invoice
+/// of its bindle.
+pub const INVOICE_MEDIA_TYPE: &str = "application/vnd.fermyon.spin.invoice.v1+toml";
+
+/// The media type of the layers of a Spin application artifact: the parcels
+/// of its bindle, that is its modules and assets.
+pub const PARCEL_MEDIA_TYPE: &str = "application/vnd.fermyon.spin.parcel.v1";
+
+const TITLE_ANNOTATION: &str = "org.opencontainers.image.title";
+const PARCEL_MEDIA_TYPE_ANNOTATION: &str = "dev.fermyon.spin.parcel.media_type";
+
+/// An OCI repository applications are pushed to, given as
+/// `oci://<registry>/<repository>`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OciRepository {
+    registry: String,
+    repository: String,
+}
+
+impl OciRepository {
+    /// The registry holding the repository, such as `ghcr.io`.
+    pub fn registry(&self) -> &str {
+        &self.registry
+    }
+
+    /// The repository, including its registry, such as `ghcr.io/org/app`.
+    pub fn name(&self) -> String {
+        format!("{}/{}", self.registry, self.repository)
+    }
+
+    /// The reference of the given tag of the repository.
+    pub fn reference(&self, tag: &str) -> String {
+        format!("{}:{}", self.name(), tag)
+    }
+}
+
+impl FromStr for OciRepository {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let rest = s
+            .strip_prefix("oci://")
+            .with_context(|| format!("Registry '{}' must start with oci://", s))?;
+        let (registry, repository) = match rest.trim_end_matches('/').split_once('/') {
+            Some((registry, repository)) if !registry.is_empty() && !repository.is_empty() => {
+                (registry, repository)
+            }
+            _ => bail!(
+                "Registry '{}' must be of the form oci://<registry>/<repository>",
+                s
+            ),
+        };
+        if repository.contains(['@', ':']) {
+            bail!(
+                "Registry '{}' must not include a tag or digest: the tag is the application version",
+                s
+            );
+        }
+        Ok(Self {
+            registry: registry.to_owned(),
+            repository: repository.to_owned(),
+        })
+    }
+}
+
+/// The tag an application version is pushed as. OCI tags cannot contain
+/// `+`, so the build metadata of the version is separated by `_` instead.
+pub fn tag_for_version(version: &str) -> String {
+    version.replace('+', "_")
+}
+
+/// Pushes a standalone bindle, written to the given directory, to an OCI
+/// repository as an artifact whose config is the invoice and whose layers
+/// are the parcels. Credentials for the registry are read from the Docker
+/// configuration and its credential helpers. Returns the URL of the pushed
+/// manifest.
+pub async fn push_oci(
+    dest_dir: impl AsRef<Path>,
+    invoice: &Invoice,
+    repository: &OciRepository,
+    tag: &str,
+    allow_insecure: bool,
+) -> Result<String> {
+    let dest_dir = dest_dir.as_ref();
+    let invoice_path = invoice_file(dest_dir, &invoice.bindle.id);
+    let invoice_data = tokio::fs::read(&invoice_path)
+        .await
+        .with_context(|| format!("Failed to read invoice '{}'", invoice_path.display()))?;
+    let config = Config::new(invoice_data, INVOICE_MEDIA_TYPE.to_owned(), None);
+
+    let mut layers = vec![];
+    for parcel in invoice.parcel.iter().flatten() {
+        let path = parcel_file(dest_dir, &invoice.bindle.id, &parcel.label.sha256);
+        let data = tokio::fs::read(&path)
+            .await
+            .with_context(|| format!("Failed to read parcel '{}'", path.display()))?;
+        let annotations = HashMap::from([
+            (TITLE_ANNOTATION.to_owned(), parcel.label.name.clone()),
+            (
+                PARCEL_MEDIA_TYPE_ANNOTATION.to_owned(),
+                parcel.label.media_type.clone(),
+            ),
+        ]);
+        layers.push(ImageLayer::new(
+            data,
+            PARCEL_MEDIA_TYPE.to_owned(),
+            Some(annotations),
+        ));
+    }
+
+    let reference: Reference = repository
+        .reference(tag)
+        .parse()
+        .with_context(|| format!("Invalid OCI reference '{}'", repository.reference(tag)))?;
+    let auth = registry_auth(repository.registry())?;
+    let mut client = Client::new(ClientConfig {
+        accept_invalid_certificates: allow_insecure,
+        ..Default::default()
+    });
+    let response = client
+        .push(&reference, &layers, config, &auth, None)
+        .await
+        .with_context(|| format!("Failed to push to '{}'", repository.reference(tag)))?;
+    Ok(response.manifest_url)
+}
+
+/// The credentials for a registry, from the Docker configuration, or none if
+/// it has none for the registry.
+fn registry_auth(registry: &str) -> Result<RegistryAuth> {
+    match docker_credential::get_credential(registry) {
+        Ok(DockerCredential::UsernamePassword(username, password)) => {
+            Ok(RegistryAuth::Basic(username, password))
+        }
+        Ok(DockerCredential::IdentityToken(_)) => bail!(
+            "The Docker credentials for {} are an identity token, which is not supported: log in with a username and password or access token",
+            registry
+        ),
+        Err(
+            CredentialRetrievalError::ConfigNotFound
+            | CredentialRetrievalError::NoCredentialConfigured,
+        ) => Ok(RegistryAuth::Anonymous),
+        Err(e) => Err(e)
+            .with_context(|| format!("Failed to get the Docker credentials for {}", registry)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_repository() {
+        let repository: OciRepository = "oci://ghcr.io/org/app".parse().unwrap();
+        assert_eq!(repository.registry(), "ghcr.io");
+        assert_eq!(repository.name(), "ghcr.io/org/app");
+        assert_eq!(
+            repository.reference(&tag_for_version("1.0.0+q4f2a1b0")),
+            "ghcr.io/org/app:1.0.0_q4f2a1b0"
+        );
+
+        let local: OciRepository = "oci://localhost:5000/app/".parse().unwrap();
+        assert_eq!(local.registry(), "localhost:5000");
+        assert_eq!(local.name(), "localhost:5000/app");
+
+        assert!("ghcr.io/org/app".parse::<OciRepository>().is_err());
+        assert!("oci://ghcr.io".parse::<OciRepository>().is_err());
+        assert!("oci://ghcr.io/org/app:1.0.0"
+            .parse::<OciRepository>()
+            .is_err());
+        assert!("oci://ghcr.io/org/app@sha256:abc"
+            .parse::<OciRepository>()
+            .is_err());
+    }
+}
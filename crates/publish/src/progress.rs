@@ -0,0 +1,133 @@
+use std::{io::Write, sync::Mutex};
+
+use bindle::Parcel;
+
+const BAR_WIDTH: usize = 30;
+
+/// Shows the progress of uploading the parcels of a bindle as a bar on the
+/// terminal: the parcels and bytes uploaded, and the parcels in flight.
+pub(crate) struct UploadProgress {
+    total: usize,
+    total_bytes: u64,
+    state: Mutex<State>,
+    // Whether there is a terminal to show progress on.
+    interactive: bool,
+}
+
+#[derive(Default)]
+struct State {
+    done: usize,
+    done_bytes: u64,
+    uploading: Vec<String>,
+}
+
+impl UploadProgress {
+    pub(crate) fn new(parcels: &[&Parcel]) -> Self {
+        Self {
+            total: parcels.len(),
+            total_bytes: parcels.iter().map(|p| p.label.size).sum(),
+            state: Mutex::default(),
+            interactive: !parcels.is_empty() && atty::is(atty::Stream::Stderr),
+        }
+    }
+
+    /// Records that the given parcel started uploading.
+    pub(crate) fn started(&self, parcel: &Parcel) {
+        let mut state = self.state.lock().unwrap();
+        state.uploading.push(parcel.label.name.clone());
+        self.draw(&state);
+    }
+
+    /// Records that the given parcel is uploaded.
+    pub(crate) fn finished(&self, parcel: &Parcel) {
+        let mut state = self.state.lock().unwrap();
+        state.done += 1;
+        state.done_bytes += parcel.label.size;
+        if let Some(index) = state.uploading.iter().position(|n| n == &parcel.label.name) {
+            state.uploading.remove(index);
+        }
+        self.draw(&state);
+        if self.interactive && state.done == self.total {
+            eprintln!();
+        }
+    }
+
+    fn draw(&self, state: &State) {
+        if self.interactive {
+            // Clears the rest of the line, which may be shorter than the last.
+            eprint!("\r{}\x1b[K", self.line(state));
+            let _ = std::io::stderr().flush();
+        }
+    }
+
+    fn line(&self, state: &State) -> String {
+        let fraction = if self.total_bytes == 0 {
+            state.done as f64 / self.total.max(1) as f64
+        } else {
+            state.done_bytes as f64 / self.total_bytes as f64
+        };
+        let filled = (fraction * BAR_WIDTH as f64).round() as usize;
+        let mut line = format!(
+            "Uploading [{}{}] {:>3}% {}/{} parcels",
+            "=".repeat(filled),
+            " ".repeat(BAR_WIDTH - filled),
+            (fraction * 100.0).round(),
+            state.done,
+            self.total
+        );
+        if !state.uploading.is_empty() {
+            line.push_str(": ");
+            line.push_str(&state.uploading.join(", "));
+        }
+        line
+    }
+}
+
+impl Drop for UploadProgress {
+    fn drop(&mut self) {
+        // Ends the progress line if uploading a parcel failed.
+        if self.interactive && self.state.get_mut().unwrap().done < self.total {
+            eprintln!();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bindle::Label;
+
+    fn parcel(name: &str, size: u64) -> Parcel {
+        Parcel {
+            label: Label {
+                name: name.to_owned(),
+                sha256: name.to_owned(),
+                size,
+                media_type: "application/octet-stream".to_owned(),
+                annotations: None,
+                feature: None,
+                origin: None,
+            },
+            conditions: None,
+        }
+    }
+
+    #[test]
+    fn test_progress_line() {
+        let (wasm, logo) = (parcel("app.wasm", 300), parcel("logo.png", 100));
+        let progress = UploadProgress::new(&[&wasm, &logo]);
+        progress.started(&wasm);
+        progress.started(&logo);
+        progress.finished(&wasm);
+
+        let state = progress.state.lock().unwrap();
+        assert_eq!(
+            progress.line(&state),
+            format!(
+                "Uploading [{}{}]  75% 1/2 parcels: logo.png",
+                "=".repeat(23),
+                " ".repeat(7)
+            )
+        );
+    }
+}
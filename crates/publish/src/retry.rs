@@ -0,0 +1,288 @@
+use std::{future::Future, io::ErrorKind, time::Duration};
+
+use anyhow::Result;
+use bindle::client::ClientError;
+use reqwest::StatusCode;
+
+/// The longest wait between two attempts.
+const MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// How operations failing with transient network errors are retried.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// The number of times an operation is retried after its first attempt.
+    pub retries: u32,
+    /// How long to wait before the first retry. Each later retry waits twice
+    /// as long as the one before.
+    pub delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            retries: 3,
+            delay: Duration::from_secs(1),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A policy never retrying operations.
+    pub fn none() -> Self {
+        Self {
+            retries: 0,
+            delay: Duration::ZERO,
+        }
+    }
+
+    /// Runs the given operation, retrying it while it fails with a transient
+    /// error, such as a timeout or a 5xx response, and attempts remain.
+    /// Other errors, such as authentication failures or conflicts, are
+    /// returned immediately. The operation must be idempotent, such as a
+    /// lookup or the upload of content-addressed data: a request that failed
+    /// in transit may have been handled by the server.
+    pub async fn run<T, F, Fut>(&self, description: &str, operation: F) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        self.run_checked(description, operation, || async { Ok(None) })
+            .await
+    }
+
+    /// Runs the given operation as [`run`](Self::run) does, but checks with
+    /// `applied` whether a failed attempt took effect anyway before retrying
+    /// it, for operations that must not be applied twice, such as creating
+    /// a resource. If `applied` returns a value, the operation is not
+    /// retried and the value is returned as its result. If the check fails,
+    /// the operation is not retried either, as its effect is unknown.
+    pub async fn run_checked<T, F, Fut, A, AFut>(
+        &self,
+        description: &str,
+        mut operation: F,
+        mut applied: A,
+    ) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T>>,
+        A: FnMut() -> AFut,
+        AFut: Future<Output = Result<Option<T>>>,
+    {
+        let mut attempt = 0;
+        loop {
+            let e = match operation().await {
+                Err(e) if attempt < self.retries && is_retryable(&e) => e,
+                result => return result,
+            };
+            match applied().await {
+                Ok(Some(result)) => return Ok(result),
+                Ok(None) => (),
+                Err(check) => {
+                    return Err(e.context(format!(
+                        "{} failed, and checking whether it took effect failed too: {:#}",
+                        description, check
+                    )))
+                }
+            }
+            let delay = self.backoff(attempt);
+            eprintln!(
+                "{} failed, retrying in {}s ({}/{}): {:#}",
+                description,
+                delay.as_secs_f64(),
+                attempt + 1,
+                self.retries,
+                e
+            );
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
+    /// How long to wait before the given retry, counting from zero.
+    fn backoff(&self, attempt: u32) -> Duration {
+        self.delay
+            .checked_mul(2u32.saturating_pow(attempt))
+            .map_or(MAX_DELAY, |delay| delay.min(MAX_DELAY))
+    }
+}
+
+/// Whether an error is transient, so that the failed operation may succeed
+/// if retried.
+pub(crate) fn is_retryable(err: &anyhow::Error) -> bool {
+    for cause in err.chain() {
+        if let Some(e) = cause.downcast_ref::<ClientError>() {
+            return match e {
+                ClientError::ServerError(_) => true,
+                ClientError::HttpClientError(e) => is_retryable_request(e),
+                _ => false,
+            };
+        }
+        if let Some(e) = cause.downcast_ref::<reqwest::Error>() {
+            return is_retryable_request(e);
+        }
+        if let Some(e) = cause.downcast_ref::<std::io::Error>() {
+            return matches!(
+                e.kind(),
+                ErrorKind::TimedOut
+                    | ErrorKind::ConnectionReset
+                    | ErrorKind::ConnectionAborted
+                    | ErrorKind::BrokenPipe
+                    | ErrorKind::UnexpectedEof
+            );
+        }
+    }
+    // Some clients, such as Hippo's, only report the status of failed
+    // responses in their messages.
+    let message = format!("{:#}", err);
+    retryable_statuses().any(|status| {
+        status
+            .canonical_reason()
+            .map_or(false, |reason| message.contains(reason))
+    })
+}
+
+fn is_retryable_request(e: &reqwest::Error) -> bool {
+    e.is_timeout()
+        || e.is_connect()
+        || e.status()
+            .map_or(false, |status| retryable_statuses().any(|s| s == status))
+}
+
+fn retryable_statuses() -> impl Iterator<Item = StatusCode> {
+    [
+        StatusCode::TOO_MANY_REQUESTS,
+        StatusCode::INTERNAL_SERVER_ERROR,
+        StatusCode::BAD_GATEWAY,
+        StatusCode::SERVICE_UNAVAILABLE,
+        StatusCode::GATEWAY_TIMEOUT,
+    ]
+    .into_iter()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::{anyhow, Context};
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    #[test]
+    fn test_retryable_errors() {
+        let timeout = anyhow::Error::new(std::io::Error::from(ErrorKind::TimedOut))
+            .context("Failed to upload parcel");
+        assert!(is_retryable(&timeout));
+        assert!(is_retryable(&anyhow!("Bindle error: 502 Bad Gateway")));
+        assert!(is_retryable(&anyhow!(ClientError::ServerError(None))));
+
+        assert!(!is_retryable(&anyhow!(ClientError::Unauthorized)));
+        assert!(!is_retryable(
+            &anyhow!("409 Conflict").context("Unable to create Hippo app")
+        ));
+        assert!(!is_retryable(&anyhow::Error::new(std::io::Error::from(
+            ErrorKind::NotFound
+        ))));
+    }
+
+    #[test]
+    fn test_backoff() {
+        let policy = RetryPolicy {
+            retries: 10,
+            delay: Duration::from_millis(500),
+        };
+        assert_eq!(policy.backoff(0), Duration::from_millis(500));
+        assert_eq!(policy.backoff(3), Duration::from_secs(4));
+        assert_eq!(policy.backoff(9), MAX_DELAY);
+    }
+
+    #[tokio::test]
+    async fn test_run_retries_transient_errors() {
+        let policy = RetryPolicy {
+            retries: 2,
+            delay: Duration::ZERO,
+        };
+        let attempts = &AtomicU32::new(0);
+        let result = policy
+            .run("Uploading", || async move {
+                match attempts.fetch_add(1, Ordering::SeqCst) {
+                    0 => Err(anyhow!("503 Service Unavailable")),
+                    n => Ok(n),
+                }
+            })
+            .await;
+        assert_eq!(result.unwrap(), 1);
+
+        let attempts = &AtomicU32::new(0);
+        let result: Result<()> = policy
+            .run("Uploading", || async move {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                Err(anyhow!("401 Unauthorized")).context("Unable to log in")
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+
+        let attempts = &AtomicU32::new(0);
+        let result: Result<()> = policy
+            .run("Uploading", || async move {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                Err(anyhow!("504 Gateway Timeout"))
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_run_checked_does_not_repeat_applied_operations() {
+        let policy = RetryPolicy {
+            retries: 2,
+            delay: Duration::ZERO,
+        };
+        let created = &AtomicU32::new(0);
+        // The first request is handled, but its response times out.
+        let result = policy
+            .run_checked(
+                "Creating",
+                || async move {
+                    match created.fetch_add(1, Ordering::SeqCst) {
+                        0 => Err(anyhow!("504 Gateway Timeout")),
+                        _ => Err(anyhow!("409 Conflict")),
+                    }
+                },
+                || async move { Ok((created.load(Ordering::SeqCst) > 0).then(|| 42)) },
+            )
+            .await;
+        assert_eq!(result.unwrap(), 42);
+        assert_eq!(created.load(Ordering::SeqCst), 1);
+
+        // Requests that were not handled are retried.
+        let attempts = &AtomicU32::new(0);
+        let result = policy
+            .run_checked(
+                "Creating",
+                || async move {
+                    match attempts.fetch_add(1, Ordering::SeqCst) {
+                        0 => Err(anyhow!("503 Service Unavailable")),
+                        n => Ok(n),
+                    }
+                },
+                || async { Ok(None) },
+            )
+            .await;
+        assert_eq!(result.unwrap(), 1);
+
+        // Operations whose effect is unknown are not retried.
+        let attempts = &AtomicU32::new(0);
+        let result: Result<()> = policy
+            .run_checked(
+                "Creating",
+                || async move {
+                    attempts.fetch_add(1, Ordering::SeqCst);
+                    Err(anyhow!("503 Service Unavailable"))
+                },
+                || async { Err(anyhow!("401 Unauthorized")) },
+            )
+            .await;
+        assert!(result.is_err());
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,117 @@
+use anyhow::{anyhow, bail, Context, Result};
+use bindle::{
+    invoice::{
+        signature::{KeyEntry, SecretKeyEntry, SecretKeyFile, SignatureRole},
+        Signed,
+    },
+    Invoice,
+};
+use std::path::{Path, PathBuf};
+use tokio::io::AsyncWriteExt;
+
+const SIGNING_KEYS_FILE: &str = "signing-keys.toml";
+
+/// The path of the file the signing keys of the local user are kept in.
+pub fn signing_keys_path() -> Result<PathBuf> {
+    let dir = dirs::config_dir().ok_or_else(|| anyhow!("Cannot find the config directory"))?;
+    Ok(dir.join("spin").join(SIGNING_KEYS_FILE))
+}
+
+/// Loads a file of signing keys, which is empty if it does not exist.
+pub async fn load_signing_keys(path: &Path) -> Result<SecretKeyFile> {
+    match tokio::fs::read_to_string(path).await {
+        Ok(contents) => toml::from_str(&contents)
+            .with_context(|| format!("Invalid signing keys file {}", path.display())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(SecretKeyFile::default()),
+        Err(e) => {
+            Err(e).with_context(|| format!("Cannot read signing keys file {}", path.display()))
+        }
+    }
+}
+
+/// Saves a file of signing keys, readable only by the current user where
+/// the platform allows it.
+pub async fn save_signing_keys(path: &Path, keys: &SecretKeyFile) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+    let contents = toml::to_string_pretty(keys)?;
+    // Written aside and renamed, so that the keys are never readable by
+    // others, even while they are written.
+    let temp_path = path.with_extension("toml.tmp");
+    let write = async {
+        match tokio::fs::remove_file(&temp_path).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+        let mut options = tokio::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        options.mode(0o600);
+        let mut file = options.open(&temp_path).await?;
+        file.write_all(contents.as_bytes()).await?;
+        file.sync_all().await?;
+        tokio::fs::rename(&temp_path, path).await
+    };
+    write
+        .await
+        .with_context(|| format!("Cannot write signing keys file {}", path.display()))
+}
+
+/// Generates a new key for signing invoices as their creator.
+pub fn generate_signing_key(label: &str) -> SecretKeyEntry {
+    SecretKeyEntry::new(label.to_owned(), vec![SignatureRole::Creator])
+}
+
+/// The key with the given label, or the first key if no label is given.
+/// Returns `None` if there are no keys and no label is given.
+pub fn find_signing_key<'a>(
+    keys: &'a SecretKeyFile,
+    label: Option<&str>,
+) -> Result<Option<&'a SecretKeyEntry>> {
+    match label {
+        Some(label) => match keys.key.iter().find(|k| k.label == label) {
+            Some(key) => Ok(Some(key)),
+            None => bail!("No signing key labelled {}", label),
+        },
+        None => Ok(keys.key.first()),
+    }
+}
+
+/// The public part of a signing key, to add to the keyrings of those
+/// verifying the invoices it signs.
+pub fn public_key(key: &SecretKeyEntry) -> Result<KeyEntry> {
+    KeyEntry::try_from(key.clone()).with_context(|| format!("Invalid signing key {}", key.label))
+}
+
+/// Signs an invoice as its creator.
+pub fn sign_invoice(invoice: &Invoice, key: &SecretKeyEntry) -> Result<Invoice> {
+    let signed = bindle::invoice::sign(invoice.clone(), vec![(SignatureRole::Creator, key)])
+        .with_context(|| format!("Cannot sign {} with key {}", invoice.bindle.id, key.label))?;
+    Ok(signed.signed())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_signing_keys_round_trip() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("spin").join(SIGNING_KEYS_FILE);
+        let mut keys = SecretKeyFile::default();
+        keys.key.push(generate_signing_key("me@example.com"));
+        save_signing_keys(&path, &keys).await?;
+        save_signing_keys(&path, &keys).await?;
+
+        let loaded = load_signing_keys(&path).await?;
+        assert!(find_signing_key(&loaded, Some("me@example.com"))?.is_some());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path)?.permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,108 @@
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+/// The parcels of a bindle known to be on the server, recorded in the staging
+/// directory as they are uploaded, so that a push that was interrupted, or
+/// that failed after its retries, resumes where it stopped.
+pub(crate) struct UploadState {
+    path: PathBuf,
+    recorded: Mutex<Recorded>,
+}
+
+#[derive(Debug, Default, Deserialize, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+struct Recorded {
+    /// The bindle whose invoice was created on the server.
+    bindle: String,
+    /// The SHA256 of the parcels on the server.
+    uploaded: BTreeSet<String>,
+}
+
+impl UploadState {
+    /// Loads the state of an earlier push of the bindle, if one created its
+    /// invoice and did not finish.
+    pub(crate) fn load(path: PathBuf, bindle: &str) -> Option<Self> {
+        let text = std::fs::read(&path).ok()?;
+        let recorded: Recorded = toml::from_slice(&text).ok()?;
+        (recorded.bindle == bindle).then(|| Self {
+            path,
+            recorded: Mutex::new(recorded),
+        })
+    }
+
+    /// Records that the invoice of the bindle was created, and the parcels
+    /// the server already had.
+    pub(crate) fn start(
+        path: PathBuf,
+        bindle: &str,
+        uploaded: impl IntoIterator<Item = String>,
+    ) -> Result<Self> {
+        let recorded = Recorded {
+            bindle: bindle.to_owned(),
+            uploaded: uploaded.into_iter().collect(),
+        };
+        save(&path, &recorded)?;
+        Ok(Self {
+            path,
+            recorded: Mutex::new(recorded),
+        })
+    }
+
+    /// Whether the parcel is on the server.
+    pub(crate) fn is_uploaded(&self, sha256: &str) -> bool {
+        self.recorded.lock().unwrap().uploaded.contains(sha256)
+    }
+
+    /// Records that the parcel is on the server.
+    pub(crate) fn record(&self, sha256: &str) -> Result<()> {
+        let mut recorded = self.recorded.lock().unwrap();
+        recorded.uploaded.insert(sha256.to_owned());
+        save(&self.path, &recorded)
+    }
+
+    /// Removes the state once every parcel is uploaded.
+    pub(crate) fn finish(self) -> Result<()> {
+        std::fs::remove_file(&self.path)
+            .with_context(|| format!("Failed to remove upload state '{}'", self.path.display()))
+    }
+}
+
+/// Writes the state through a temporary file, so that an interrupted write
+/// leaves the previous state.
+fn save(path: &Path, recorded: &Recorded) -> Result<()> {
+    let temp_path = path.with_extension("toml.tmp");
+    std::fs::write(&temp_path, toml::to_string(recorded)?)
+        .and_then(|()| std::fs::rename(&temp_path, path))
+        .with_context(|| format!("Failed to write upload state '{}'", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_upload_state_is_resumed() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("upload-state.toml");
+
+        let state = UploadState::start(path.clone(), "app/1.0.0", ["aaa".to_owned()])?;
+        state.record("bbb")?;
+        drop(state);
+
+        assert!(UploadState::load(path.clone(), "app/1.0.1").is_none());
+        let state = UploadState::load(path.clone(), "app/1.0.0").expect("state should load");
+        assert!(state.is_uploaded("aaa"));
+        assert!(state.is_uploaded("bbb"));
+        assert!(!state.is_uploaded("ccc"));
+
+        state.finish()?;
+        assert!(!path.exists());
+        Ok(())
+    }
+}
@@ -0,0 +1,86 @@
+use anyhow::{Context, Result};
+use bindle::QueryOptions;
+use chrono::{DateTime, Utc};
+use semver::Version;
+
+use crate::BindleConnectionInfo;
+
+/// Returns the version following the latest version of the named bindle
+/// on the server, by incrementing its patch number. If no version of the
+/// bindle exists, or the given manifest version is greater, the manifest
+/// version is returned.
+pub async fn next_patch_version(
+    bindle_connection_info: &BindleConnectionInfo,
+    name: &str,
+    manifest_version: &Version,
+) -> Result<Version> {
+    let client = bindle_connection_info.client()?;
+    let matches = client
+        .query_invoices(QueryOptions {
+            query: Some(name.to_owned()),
+            ..Default::default()
+        })
+        .await
+        .with_context(|| format!("Failed to query versions of bindle '{}'", name))?;
+    let latest = matches
+        .invoices
+        .iter()
+        .filter(|invoice| invoice.bindle.id.name() == name)
+        .filter_map(|invoice| Version::parse(&invoice.bindle.id.version_string()).ok())
+        .max();
+    Ok(bump(latest, manifest_version))
+}
+
+fn bump(latest: Option<Version>, manifest_version: &Version) -> Version {
+    match latest {
+        Some(latest) => {
+            let next = Version::new(latest.major, latest.minor, latest.patch + 1);
+            next.max(manifest_version.clone())
+        }
+        None => manifest_version.clone(),
+    }
+}
+
+/// Returns a version derived from the given time, of the form
+/// `YYYYMMDD.HHMMSS.0` in UTC, so that later versions sort higher.
+pub fn date_version(time: DateTime<Utc>) -> Version {
+    let date = time.format("%Y%m%d").to_string();
+    let time_of_day = time.format("%H%M%S").to_string();
+    // Both are formatted from digits only, and leading zeros are not
+    // allowed in semver numbers, so they are parsed rather than used as is.
+    Version::new(
+        date.parse().unwrap_or_default(),
+        time_of_day.parse().unwrap_or_default(),
+        0,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_bump() {
+        let manifest = Version::new(1, 0, 0);
+        assert_eq!(bump(None, &manifest), manifest);
+        assert_eq!(
+            bump(Some(Version::new(1, 2, 3)), &manifest),
+            Version::new(1, 2, 4)
+        );
+        assert_eq!(
+            bump(Some(Version::parse("1.2.3+q1234").unwrap()), &manifest),
+            Version::new(1, 2, 4)
+        );
+        assert_eq!(
+            bump(Some(Version::new(0, 9, 0)), &manifest),
+            Version::new(1, 0, 0)
+        );
+    }
+
+    #[test]
+    fn test_date_version() {
+        let time = Utc.ymd(2022, 1, 5).and_hms(7, 3, 9);
+        assert_eq!(date_version(time), Version::new(20220105, 70309, 0));
+    }
+}
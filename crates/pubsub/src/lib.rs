@@ -0,0 +1,203 @@
+//! A broker-agnostic publish/subscribe host interface for Spin components.
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use redis::AsyncCommands;
+use serde::Deserialize;
+use spin_engine::{
+    host_component::{HostComponent, HostComponentsStateHandle},
+    RuntimeContext, ServiceUnavailable,
+};
+use spin_manifest::CoreComponent;
+use spin_pubsub::*;
+use wit_bindgen_wasmtime::wasmtime::Linker;
+
+pub use spin_pubsub::add_to_linker;
+
+wit_bindgen_wasmtime::export!({
+    paths: ["../../wit/ephemeral/spin-pubsub.wit"],
+    async: *,
+});
+
+/// Runtime configuration for the message broker.
+///
+/// There is no in-memory broker: no trigger subscribes to one, so the
+/// messages published to it would never be delivered to a component.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "snake_case", tag = "type")]
+pub enum PubSubConfig {
+    /// Messages are published to Redis channels.
+    Redis {
+        /// Address of the Redis server.
+        address: String,
+    },
+    /// Messages are published to NATS subjects.
+    Nats {
+        /// Address of the NATS server.
+        address: String,
+    },
+}
+
+/// A message broker.
+#[async_trait]
+pub trait Broker: Send + Sync {
+    /// Publishes a message to a topic.
+    async fn publish(&self, topic: &str, payload: &[u8]) -> anyhow::Result<()>;
+
+    /// Checks that the broker can be reached.
+    fn check(&self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+struct RedisBroker {
+    client: redis::Client,
+}
+
+#[async_trait]
+impl Broker for RedisBroker {
+    async fn publish(&self, topic: &str, payload: &[u8]) -> anyhow::Result<()> {
+        let mut conn = self.client.get_async_connection().await?;
+        conn.publish(topic, payload).await?;
+        Ok(())
+    }
+
+    fn check(&self) -> anyhow::Result<()> {
+        let mut conn = self.client.get_connection()?;
+        redis::cmd("PING").query::<String>(&mut conn)?;
+        Ok(())
+    }
+}
+
+struct NatsBroker {
+    connection: nats::Connection,
+}
+
+#[async_trait]
+impl Broker for NatsBroker {
+    async fn publish(&self, topic: &str, payload: &[u8]) -> anyhow::Result<()> {
+        // The NATS client blocks while its buffer is full.
+        let connection = self.connection.clone();
+        let (topic, payload) = (topic.to_owned(), payload.to_vec());
+        tokio::task::spawn_blocking(move || connection.publish(&topic, payload)).await??;
+        Ok(())
+    }
+
+    fn check(&self) -> anyhow::Result<()> {
+        self.connection.flush()?;
+        Ok(())
+    }
+}
+
+/// The broker of a pub/sub component started without its backend, returning
+/// `unavailable` errors.
+struct UnavailableBroker(ServiceUnavailable);
+
+#[async_trait]
+impl Broker for UnavailableBroker {
+    async fn publish(&self, _topic: &str, _payload: &[u8]) -> anyhow::Result<()> {
+        Err(self.0.clone().into())
+    }
+}
+
+/// The pub/sub host component.
+#[derive(Clone)]
+pub struct PubSubComponent {
+    broker: Arc<dyn Broker>,
+}
+
+impl PubSubComponent {
+    /// Creates a pub/sub host component using the configured broker.
+    pub fn new(config: &PubSubConfig) -> anyhow::Result<Self> {
+        let broker: Arc<dyn Broker> = match config {
+            PubSubConfig::Redis { address } => Arc::new(RedisBroker {
+                client: redis::Client::open(address.as_str())?,
+            }),
+            PubSubConfig::Nats { address } => Arc::new(NatsBroker {
+                connection: nats::connect(address)?,
+            }),
+        };
+        Ok(Self { broker })
+    }
+
+    /// Creates a pub/sub host component publishing to the given broker.
+    pub fn with_broker(broker: Arc<dyn Broker>) -> Self {
+        Self { broker }
+    }
+
+    /// Creates a pub/sub host component for a broker that was unavailable
+    /// when the application started.
+    pub fn unavailable(error: ServiceUnavailable) -> Self {
+        Self::with_broker(Arc::new(UnavailableBroker(error)))
+    }
+
+    /// Creates a pub/sub host component for an application without a
+    /// broker, which components cannot publish with.
+    pub fn unconfigured() -> Self {
+        Self::unavailable(ServiceUnavailable {
+            service: "pubsub".to_owned(),
+            reason: "no broker is configured in the runtime configuration".to_owned(),
+        })
+    }
+
+    /// Checks that the broker can be reached.
+    pub fn check(&self) -> anyhow::Result<()> {
+        self.broker.check()
+    }
+}
+
+impl HostComponent for PubSubComponent {
+    type State = PubSub;
+
+    fn add_to_linker<T: Send>(
+        linker: &mut Linker<RuntimeContext<T>>,
+        state_handle: HostComponentsStateHandle<Self::State>,
+    ) -> anyhow::Result<()> {
+        add_to_linker(linker, move |ctx| state_handle.get_mut(ctx))
+    }
+
+    fn build_state(&self, _component: &CoreComponent) -> anyhow::Result<Self::State> {
+        Ok(PubSub {
+            broker: self.broker.clone(),
+        })
+    }
+}
+
+/// Per-component pub/sub state.
+pub struct PubSub {
+    broker: Arc<dyn Broker>,
+}
+
+#[async_trait]
+impl spin_pubsub::SpinPubsub for PubSub {
+    async fn publish(&mut self, topic: &str, payload: &[u8]) -> Result<(), Error> {
+        if topic.is_empty() || topic.chars().any(|c| c.is_whitespace()) {
+            return Err(Error::InvalidTopic(topic.to_string()));
+        }
+        self.broker.publish(topic, payload).await.map_err(|e| {
+            if e.is::<ServiceUnavailable>() {
+                return Error::Unavailable(e.to_string());
+            }
+            tracing::log::error!("Failed to publish to topic {}: {:?}", topic, e);
+            Error::Other(e.to_string())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_publishing_requires_a_broker() {
+        let component = PubSubComponent::unconfigured();
+        let mut pubsub = PubSub {
+            broker: component.broker.clone(),
+        };
+        assert!(matches!(
+            pubsub.publish("orders", b"order-1").await,
+            Err(Error::Unavailable(_))
+        ));
+    }
+}
@@ -0,0 +1,276 @@
+//! The message brokers the queue trigger consumes messages from.
+
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use rdkafka::{
+    consumer::{CommitMode, Consumer, StreamConsumer},
+    message::{Headers, Message, OwnedHeaders},
+    producer::{FutureProducer, FutureRecord},
+    ClientConfig, Offset, TopicPartitionList,
+};
+use spin_manifest::{QueueBroker, QueueTriggerConfiguration};
+use tokio::sync::mpsc;
+
+/// How long to wait for Kafka to seek back to or publish a message.
+const KAFKA_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A message received from a broker, as handed to components.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct QueueMessage {
+    pub topic: String,
+    pub key: Option<Vec<u8>>,
+    pub payload: Vec<u8>,
+    pub headers: Vec<(String, String)>,
+}
+
+/// A message received from a broker, to be acknowledged once handled.
+pub(crate) struct Delivery {
+    /// The topic or subject the component subscribed to, which for NATS may
+    /// be a wildcard matching the subject of the message.
+    pub subscription: String,
+    pub message: QueueMessage,
+    receipt: Receipt,
+}
+
+/// What a broker needs to acknowledge a message.
+enum Receipt {
+    Kafka { partition: i32, offset: i64 },
+    Nats(nats::Message),
+}
+
+/// A connection to a message broker.
+#[async_trait]
+pub(crate) trait Broker: Send + Sync {
+    /// Receives the next message on the subscribed topics, or none if the
+    /// connection to the broker closed.
+    async fn receive(&mut self) -> Result<Option<Delivery>>;
+
+    /// Acknowledges a message, so that it is not delivered again.
+    async fn ack(&self, delivery: &Delivery) -> Result<()>;
+
+    /// Negatively acknowledges a message, so that it is delivered again.
+    async fn nack(&self, delivery: &Delivery) -> Result<()>;
+
+    /// Publishes a message to a topic.
+    async fn publish(&self, topic: &str, message: &QueueMessage) -> Result<()>;
+}
+
+/// Connects to the broker of the trigger and subscribes to the given topics.
+pub(crate) async fn connect(
+    config: &QueueTriggerConfiguration,
+    consumer_group: &str,
+    topics: &[&str],
+) -> Result<Box<dyn Broker>> {
+    Ok(match config.broker {
+        QueueBroker::Kafka => Box::new(KafkaBroker::connect(
+            &config.address,
+            consumer_group,
+            topics,
+        )?),
+        QueueBroker::Nats => {
+            Box::new(NatsBroker::connect(&config.address, consumer_group, topics).await?)
+        }
+    })
+}
+
+/// Kafka topics, consumed in a consumer group whose offsets are committed
+/// once messages are handled.
+struct KafkaBroker {
+    consumer: StreamConsumer,
+    producer: FutureProducer,
+}
+
+impl KafkaBroker {
+    fn connect(address: &str, consumer_group: &str, topics: &[&str]) -> Result<Self> {
+        let consumer: StreamConsumer = ClientConfig::new()
+            .set("bootstrap.servers", address)
+            .set("group.id", consumer_group)
+            .set("enable.auto.commit", "false")
+            .set("auto.offset.reset", "earliest")
+            .create()
+            .with_context(|| format!("Cannot connect to Kafka at {}", address))?;
+        consumer
+            .subscribe(topics)
+            .context("Cannot subscribe to the Kafka topics")?;
+        let producer = ClientConfig::new()
+            .set("bootstrap.servers", address)
+            .create()
+            .with_context(|| format!("Cannot connect to Kafka at {}", address))?;
+        Ok(Self { consumer, producer })
+    }
+}
+
+#[async_trait]
+impl Broker for KafkaBroker {
+    async fn receive(&mut self) -> Result<Option<Delivery>> {
+        let msg = self.consumer.recv().await?;
+        let headers = msg
+            .headers()
+            .map(|headers| {
+                (0..headers.count())
+                    .filter_map(|idx| headers.get(idx))
+                    .map(|(name, value)| {
+                        (name.to_owned(), String::from_utf8_lossy(value).into_owned())
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        Ok(Some(Delivery {
+            subscription: msg.topic().to_owned(),
+            message: QueueMessage {
+                topic: msg.topic().to_owned(),
+                key: msg.key().map(<[u8]>::to_vec),
+                payload: msg.payload().map(<[u8]>::to_vec).unwrap_or_default(),
+                headers,
+            },
+            receipt: Receipt::Kafka {
+                partition: msg.partition(),
+                offset: msg.offset(),
+            },
+        }))
+    }
+
+    async fn ack(&self, delivery: &Delivery) -> Result<()> {
+        if let Receipt::Kafka { partition, offset } = delivery.receipt {
+            // The committed offset is that of the next message to consume.
+            let mut offsets = TopicPartitionList::new();
+            offsets.add_partition_offset(
+                &delivery.message.topic,
+                partition,
+                Offset::Offset(offset + 1),
+            )?;
+            self.consumer.commit(&offsets, CommitMode::Async)?;
+        }
+        Ok(())
+    }
+
+    async fn nack(&self, delivery: &Delivery) -> Result<()> {
+        if let Receipt::Kafka { partition, offset } = delivery.receipt {
+            self.consumer.seek(
+                &delivery.message.topic,
+                partition,
+                Offset::Offset(offset),
+                KAFKA_TIMEOUT,
+            )?;
+        }
+        Ok(())
+    }
+
+    async fn publish(&self, topic: &str, message: &QueueMessage) -> Result<()> {
+        let headers = message
+            .headers
+            .iter()
+            .fold(OwnedHeaders::new(), |headers, (name, value)| {
+                headers.add(name, value)
+            });
+        let mut record = FutureRecord::to(topic)
+            .payload(&message.payload)
+            .headers(headers);
+        if let Some(key) = &message.key {
+            record = record.key(key);
+        }
+        self.producer
+            .send(record, KAFKA_TIMEOUT)
+            .await
+            .map_err(|(e, _)| e)?;
+        Ok(())
+    }
+}
+
+/// NATS JetStream subjects, consumed by a durable queue group. The subjects
+/// must be captured by a JetStream stream.
+struct NatsBroker {
+    connection: nats::Connection,
+    deliveries: mpsc::Receiver<(String, nats::Message)>,
+}
+
+impl NatsBroker {
+    async fn connect(address: &str, consumer_group: &str, topics: &[&str]) -> Result<Self> {
+        let address = address.to_owned();
+        let connection = tokio::task::spawn_blocking(move || nats::connect(&address))
+            .await?
+            .context("Cannot connect to NATS")?;
+        let jetstream = nats::jetstream::new(connection.clone());
+
+        // The NATS client blocks, so each subscription is read on its own
+        // thread until the trigger stops receiving messages.
+        let (sender, deliveries) = mpsc::channel(1);
+        for topic in topics {
+            let subscription = jetstream
+                .queue_subscribe(topic, consumer_group)
+                .with_context(|| format!("Cannot subscribe to NATS subject {}", topic))?;
+            let (topic, sender) = (topic.to_string(), sender.clone());
+            tokio::task::spawn_blocking(move || {
+                while let Some(msg) = subscription.next() {
+                    if sender.blocking_send((topic.clone(), msg)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        Ok(Self {
+            connection,
+            deliveries,
+        })
+    }
+}
+
+#[async_trait]
+impl Broker for NatsBroker {
+    async fn receive(&mut self) -> Result<Option<Delivery>> {
+        let (subscription, msg) = match self.deliveries.recv().await {
+            Some(delivery) => delivery,
+            None => return Ok(None),
+        };
+        let headers = msg
+            .headers
+            .iter()
+            .flat_map(|headers| headers.iter())
+            .flat_map(|(name, values)| {
+                values
+                    .iter()
+                    .map(move |value| (name.clone(), value.clone()))
+            })
+            .collect();
+        Ok(Some(Delivery {
+            subscription,
+            message: QueueMessage {
+                topic: msg.subject.clone(),
+                key: None,
+                payload: msg.data.clone(),
+                headers,
+            },
+            receipt: Receipt::Nats(msg),
+        }))
+    }
+
+    async fn ack(&self, delivery: &Delivery) -> Result<()> {
+        if let Receipt::Nats(msg) = &delivery.receipt {
+            msg.ack()?;
+        }
+        Ok(())
+    }
+
+    async fn nack(&self, delivery: &Delivery) -> Result<()> {
+        if let Receipt::Nats(msg) = &delivery.receipt {
+            msg.ack_kind(nats::jetstream::AckKind::Nak)?;
+        }
+        Ok(())
+    }
+
+    async fn publish(&self, topic: &str, message: &QueueMessage) -> Result<()> {
+        let mut headers = nats::header::HeaderMap::new();
+        for (name, value) in &message.headers {
+            headers.insert(name.as_str(), value.clone());
+        }
+        self.connection.publish_with_reply_or_headers(
+            topic,
+            None,
+            Some(&headers),
+            &message.payload,
+        )?;
+        Ok(())
+    }
+}
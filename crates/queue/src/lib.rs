@@ -0,0 +1,300 @@
+//! Implementation for the Spin message queue engine, consuming Kafka topics
+//! and NATS JetStream subjects.
+
+mod broker;
+mod spin;
+
+use crate::{
+    broker::{Broker, Delivery, QueueMessage},
+    spin::SpinQueueExecutor,
+};
+use anyhow::Result;
+use async_trait::async_trait;
+use spin_manifest::{ComponentMap, QueueConfig, QueueTriggerConfiguration, TriggerConfig};
+use spin_queue::SpinQueueData;
+use spin_trigger::{cli::NoArgs, ShutdownSignal, TriggerExecutor};
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+wit_bindgen_wasmtime::import!({
+    paths: ["../../wit/ephemeral/spin-queue.wit"],
+    async: *,
+});
+
+type ExecutionContext = spin_engine::ExecutionContext<SpinQueueData>;
+type RuntimeContext = spin_engine::RuntimeContext<SpinQueueData>;
+
+/// The Spin message queue trigger.
+#[derive(Clone)]
+pub struct QueueTrigger {
+    /// Trigger configuration.
+    trigger_config: QueueTriggerConfiguration,
+    /// Component trigger configurations.
+    component_triggers: ComponentMap<QueueConfig>,
+    /// Spin execution context.
+    engine: Arc<ExecutionContext>,
+    /// Map from topic to component index.
+    subscriptions: HashMap<String, usize>,
+    /// The signal on which to stop receiving messages.
+    shutdown: ShutdownSignal,
+}
+
+pub struct QueueTriggerConfig(String, QueueConfig);
+
+impl TryFrom<(String, TriggerConfig)> for QueueTriggerConfig {
+    type Error = spin_manifest::Error;
+
+    fn try_from((component, config): (String, TriggerConfig)) -> Result<Self, Self::Error> {
+        Ok(QueueTriggerConfig(component, config.try_into()?))
+    }
+}
+
+#[async_trait]
+impl TriggerExecutor for QueueTrigger {
+    type GlobalConfig = QueueTriggerConfiguration;
+    type TriggerConfig = QueueTriggerConfig;
+    type RunConfig = NoArgs;
+    type RuntimeContext = SpinQueueData;
+
+    fn new(
+        execution_context: ExecutionContext,
+        global_config: Self::GlobalConfig,
+        trigger_configs: impl IntoIterator<Item = Self::TriggerConfig>,
+    ) -> Result<Self> {
+        let component_triggers: ComponentMap<QueueConfig> = trigger_configs
+            .into_iter()
+            .map(|config| (config.0, config.1))
+            .collect();
+        let subscriptions = execution_context
+            .config
+            .components
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, component)| {
+                component_triggers
+                    .get(&component.id)
+                    .map(|queue_config| (queue_config.topic.clone(), idx))
+            })
+            .collect();
+
+        Ok(Self {
+            trigger_config: global_config,
+            component_triggers,
+            engine: Arc::new(execution_context),
+            subscriptions,
+            shutdown: Default::default(),
+        })
+    }
+
+    fn configure_shutdown(&mut self, shutdown: ShutdownSignal) {
+        self.shutdown = shutdown;
+    }
+
+    /// Run the queue trigger indefinitely.
+    async fn run(self, _config: Self::RunConfig) -> Result<()> {
+        let consumer_group = self
+            .trigger_config
+            .consumer_group
+            .as_deref()
+            .unwrap_or(&self.engine.config.label);
+        log::info!(
+            "Connecting to {:?} broker at {} in consumer group {}",
+            self.trigger_config.broker,
+            self.trigger_config.address,
+            consumer_group
+        );
+        let topics = self
+            .subscriptions
+            .keys()
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        let mut broker = broker::connect(&self.trigger_config, consumer_group, &topics).await?;
+
+        for (topic, idx) in self.subscriptions.iter() {
+            let name = &self.engine.config.components[*idx].id;
+            log::info!(
+                "Subscribed component #{} ({}) to topic: {}",
+                idx,
+                name,
+                topic
+            );
+        }
+
+        // Messages are handled one at a time, so once shutdown is requested,
+        // the message being handled completes before the trigger stops.
+        let shutdown = self.shutdown.clone().requested();
+        tokio::pin!(shutdown);
+        loop {
+            let delivery = tokio::select! {
+                delivery = broker.receive() => delivery?,
+                _ = &mut shutdown => {
+                    log::info!("Shutdown requested: unsubscribing");
+                    break Ok(());
+                }
+            };
+            match delivery {
+                Some(delivery) => {
+                    if let Err(e) = self.handle(broker.as_ref(), delivery).await {
+                        log::error!("Error acknowledging the message: {:#}", e);
+                    }
+                }
+                None => {
+                    log::info!("The connection to the broker closed");
+                    break Ok(());
+                }
+            }
+        }
+    }
+}
+
+impl QueueTrigger {
+    // Handle the message, retrying it as configured for its component, and
+    // acknowledge it once it is handled, dead-lettered or dropped.
+    async fn handle(&self, broker: &dyn Broker, delivery: Delivery) -> Result<()> {
+        let topic = delivery.message.topic.as_str();
+        log::info!("Received message on topic {:?}", topic);
+
+        let idx = match self.subscriptions.get(&delivery.subscription).copied() {
+            Some(idx) => idx,
+            None => {
+                log::debug!("No subscription found for {:?}", topic);
+                return broker.ack(&delivery).await;
+            }
+        };
+        let component = &self.engine.config.components[idx];
+        let config = &self.component_triggers[&component.id];
+        let follow = self
+            .engine
+            .config
+            .follow_components
+            .should_follow(&component.id);
+
+        let mut attempt = 1;
+        loop {
+            let outcome = match config.executor.clone().unwrap_or_default() {
+                spin_manifest::QueueExecutor::Spin => {
+                    log::trace!("Executing Spin queue component {}", component.id);
+                    SpinQueueExecutor
+                        .execute(
+                            &self.engine,
+                            &component.id,
+                            &delivery.message,
+                            attempt,
+                            follow,
+                        )
+                        .await
+                }
+            };
+            let outcome = outcome.unwrap_or_else(|e| {
+                log::warn!("Component {} failed: {:#}", component.id, e);
+                Outcome::Failed
+            });
+
+            match next_step(outcome, attempt, config) {
+                Step::Ack => return broker.ack(&delivery).await,
+                Step::Retry(delay) => {
+                    log::info!(
+                        "Retrying the message on topic {:?} in {:?} (retry {} of {})",
+                        topic,
+                        delay,
+                        attempt,
+                        config.max_retries
+                    );
+                    // Once shutdown is requested, the message is left for
+                    // the broker to deliver again rather than retried.
+                    tokio::select! {
+                        _ = tokio::time::sleep(delay) => attempt += 1,
+                        _ = self.shutdown.clone().requested() => {
+                            log::info!("Shutdown requested: returning the message to the broker");
+                            return broker.nack(&delivery).await;
+                        }
+                    }
+                }
+                Step::DeadLetter(dead_letter_topic) => {
+                    return match broker.publish(dead_letter_topic, &delivery.message).await {
+                        Ok(()) => {
+                            log::info!(
+                                "Published the message to dead-letter topic {}",
+                                dead_letter_topic
+                            );
+                            broker.ack(&delivery).await
+                        }
+                        Err(e) => {
+                            log::error!(
+                                "Cannot publish the message to dead-letter topic {}: {:#}",
+                                dead_letter_topic,
+                                e
+                            );
+                            broker.nack(&delivery).await
+                        }
+                    };
+                }
+                Step::Drop => {
+                    log::warn!(
+                        "Dropping the message, as component {} has no dead-letter topic",
+                        component.id
+                    );
+                    return broker.ack(&delivery).await;
+                }
+            }
+        }
+    }
+}
+
+/// The outcome of an attempt at handling a message.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Outcome {
+    /// The component handled the message.
+    Handled,
+    /// The component failed to handle the message, and it may be retried.
+    Failed,
+    /// The component rejected the message, which must not be retried.
+    Rejected,
+}
+
+/// What to do with a message after an attempt at handling it.
+#[derive(Debug, PartialEq, Eq)]
+enum Step<'a> {
+    /// Acknowledge the message.
+    Ack,
+    /// Handle the message again after the delay.
+    Retry(Duration),
+    /// Publish the message to the dead-letter topic, then acknowledge it.
+    DeadLetter(&'a str),
+    /// Acknowledge the message without handling it.
+    Drop,
+}
+
+/// What to do with a message after the given attempt at handling it, where
+/// the first attempt is 1. The retry delay doubles for each retry.
+fn next_step(outcome: Outcome, attempt: u32, config: &QueueConfig) -> Step<'_> {
+    match outcome {
+        Outcome::Handled => Step::Ack,
+        Outcome::Failed if attempt <= config.max_retries => {
+            let factor = 2u64.saturating_pow(attempt - 1);
+            Step::Retry(Duration::from_millis(
+                config.retry_delay_ms.saturating_mul(factor),
+            ))
+        }
+        _ => match &config.dead_letter_topic {
+            Some(topic) => Step::DeadLetter(topic),
+            None => Step::Drop,
+        },
+    }
+}
+
+/// The queue executor trait.
+/// All queue executors must implement this trait.
+#[async_trait]
+pub(crate) trait QueueExecutor: Clone + Send + Sync + 'static {
+    async fn execute(
+        &self,
+        engine: &ExecutionContext,
+        component: &str,
+        message: &QueueMessage,
+        attempt: u32,
+        follow: bool,
+    ) -> Result<Outcome>;
+}
+
+#[cfg(test)]
+mod tests;
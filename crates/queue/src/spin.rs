@@ -0,0 +1,93 @@
+use crate::{
+    broker::QueueMessage,
+    spin_queue::{self, SpinQueue},
+    ExecutionContext, Outcome, QueueExecutor, RuntimeContext,
+};
+use anyhow::Result;
+use async_trait::async_trait;
+use spin_engine::io::ModuleIoRedirects;
+use wasmtime::{Instance, Store};
+
+#[derive(Clone)]
+pub struct SpinQueueExecutor;
+
+#[async_trait]
+impl QueueExecutor for SpinQueueExecutor {
+    async fn execute(
+        &self,
+        engine: &ExecutionContext,
+        component: &str,
+        message: &QueueMessage,
+        attempt: u32,
+        follow: bool,
+    ) -> Result<Outcome> {
+        log::trace!(
+            "Executing request using the Spin executor for component {}",
+            component
+        );
+
+        let mior = ModuleIoRedirects::for_component(follow, &engine.logger(component));
+
+        let (store, instance) = engine
+            .prepare_component(component, None, Some(mior.pipes), None, None)
+            .await?;
+
+        let result =
+            match Self::execute_impl(engine, store, instance, component, message.clone(), attempt)
+                .await
+            {
+                Ok(outcome) => {
+                    log::trace!("Request finished with {:?}", outcome);
+                    Ok(outcome)
+                }
+                Err(e) => {
+                    log::trace!("Request finished with error {}", e);
+                    Err(e)
+                }
+            };
+
+        let log_result =
+            engine.save_output_to_logs(mior.read_handles.read(), component, true, true);
+
+        log_result.and(result)
+    }
+}
+
+impl SpinQueueExecutor {
+    pub async fn execute_impl(
+        engine: &ExecutionContext,
+        mut store: Store<RuntimeContext>,
+        instance: Instance,
+        component: &str,
+        message: QueueMessage,
+        attempt: u32,
+    ) -> Result<Outcome> {
+        let spin_queue = SpinQueue::new(&mut store, &instance, |host| host.data.as_mut().unwrap())?;
+
+        engine
+            .run_guest(component, async move {
+                let headers = message
+                    .headers
+                    .iter()
+                    .map(|(name, value)| (name.as_str(), value.as_str()))
+                    .collect::<Vec<_>>();
+                let guest_message = spin_queue::Message {
+                    topic: &message.topic,
+                    key: message.key.as_deref(),
+                    payload: &message.payload,
+                    headers: &headers,
+                    attempt,
+                };
+                match spin_queue
+                    .handle_queue_message(&mut store, guest_message)
+                    .await
+                {
+                    Ok(Ok(())) => Ok(Outcome::Handled),
+                    Ok(Err(spin_queue::Error::Retry)) => Ok(Outcome::Failed),
+                    Ok(Err(spin_queue::Error::Reject)) => Ok(Outcome::Rejected),
+                    Err(trap) => Err(trap.into()),
+                }
+            })
+            .await?
+    }
+}
@@ -0,0 +1,85 @@
+use super::*;
+use anyhow::Result;
+use spin_manifest::{QueueConfig, QueueExecutor};
+use spin_testing::TestConfig;
+use spin_trigger::TriggerExecutorBuilder;
+use std::sync::Once;
+
+static LOGGER: Once = Once::new();
+
+/// We can only initialize the tracing subscriber once per crate.
+pub(crate) fn init() {
+    LOGGER.call_once(|| {
+        tracing_subscriber::fmt()
+            .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
+            .init();
+    });
+}
+
+fn queue_config(dead_letter_topic: Option<&str>) -> QueueConfig {
+    QueueConfig {
+        topic: "orders".to_string(),
+        executor: Some(QueueExecutor::Spin),
+        max_retries: 2,
+        retry_delay_ms: 500,
+        dead_letter_topic: dead_letter_topic.map(str::to_owned),
+    }
+}
+
+#[test]
+fn test_next_step_retries_with_backoff() {
+    let config = queue_config(Some("orders-dead"));
+    assert_eq!(next_step(Outcome::Handled, 1, &config), Step::Ack);
+    assert_eq!(
+        next_step(Outcome::Failed, 1, &config),
+        Step::Retry(Duration::from_millis(500))
+    );
+    assert_eq!(
+        next_step(Outcome::Failed, 2, &config),
+        Step::Retry(Duration::from_millis(1000))
+    );
+    assert_eq!(
+        next_step(Outcome::Failed, 3, &config),
+        Step::DeadLetter("orders-dead")
+    );
+    assert_eq!(next_step(Outcome::Handled, 3, &config), Step::Ack);
+}
+
+#[test]
+fn test_next_step_rejected_is_not_retried() {
+    let config = queue_config(Some("orders-dead"));
+    assert_eq!(
+        next_step(Outcome::Rejected, 1, &config),
+        Step::DeadLetter("orders-dead")
+    );
+
+    let config = queue_config(None);
+    assert_eq!(next_step(Outcome::Rejected, 1, &config), Step::Drop);
+    assert_eq!(next_step(Outcome::Failed, 3, &config), Step::Drop);
+}
+
+#[ignore]
+#[tokio::test]
+async fn test_queue_message() -> Result<()> {
+    init();
+
+    let mut cfg = TestConfig::default();
+    cfg.test_program("queue-rust.wasm")
+        .queue_trigger(queue_config(None));
+    let app = cfg.build_application();
+
+    let trigger: QueueTrigger = TriggerExecutorBuilder::new(app).build().await?;
+
+    let message = QueueMessage {
+        topic: "orders".to_string(),
+        key: Some(b"42".to_vec()),
+        payload: b"hello".to_vec(),
+        headers: vec![("content-type".to_string(), "text/plain".to_string())],
+    };
+    let outcome = SpinQueueExecutor
+        .execute(&trigger.engine, "test-component", &message, 1, false)
+        .await?;
+    assert_eq!(outcome, Outcome::Handled);
+
+    Ok(())
+}
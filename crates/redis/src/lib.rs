@@ -3,16 +3,20 @@
 mod spin;
 
 use crate::spin::SpinRedisExecutor;
-use anyhow::Result;
+use anyhow::{Context, Result};
 use async_trait::async_trait;
 use futures::StreamExt;
+use jsonschema::JSONSchema;
 use redis::{Client, ConnectionLike};
 use spin_manifest::{ComponentMap, RedisConfig, RedisTriggerConfiguration, TriggerConfig};
 use spin_redis::SpinRedisData;
-use spin_trigger::{cli::NoArgs, TriggerExecutor};
+use spin_trigger::{cli::NoArgs, ShutdownSignal, TriggerExecutor};
 use std::{collections::HashMap, sync::Arc};
 
-wit_bindgen_wasmtime::import!("../../wit/ephemeral/spin-redis.wit");
+wit_bindgen_wasmtime::import!({
+    paths: ["../../wit/ephemeral/spin-redis.wit"],
+    async: *,
+});
 
 type ExecutionContext = spin_engine::ExecutionContext<SpinRedisData>;
 type RuntimeContext = spin_engine::RuntimeContext<SpinRedisData>;
@@ -28,6 +32,10 @@ pub struct RedisTrigger {
     engine: Arc<ExecutionContext>,
     /// Map from channel name to tuple of component name & index.
     subscriptions: HashMap<String, usize>,
+    /// Map from component ID to the schema its payloads must match.
+    schemas: Arc<HashMap<String, JSONSchema>>,
+    /// The signal on which to stop receiving messages.
+    shutdown: ShutdownSignal,
 }
 
 pub struct RedisTriggerConfig(String, RedisConfig);
@@ -67,15 +75,30 @@ impl TriggerExecutor for RedisTrigger {
                     .map(|redis_config| (redis_config.channel.clone(), idx))
             })
             .collect();
+        let schemas = component_triggers
+            .iter()
+            .filter_map(|(id, config)| {
+                let schema = config.schema.as_ref()?;
+                let schema = spin_loader::schema::load_payload_schema(schema)
+                    .with_context(|| format!("Cannot load payload schema of component {}", id));
+                Some(schema.map(|schema| (id.clone(), schema)))
+            })
+            .collect::<Result<_>>()?;
 
         Ok(Self {
             trigger_config: global_config,
             component_triggers,
             engine: Arc::new(execution_context),
             subscriptions,
+            schemas: Arc::new(schemas),
+            shutdown: Default::default(),
         })
     }
 
+    fn configure_shutdown(&mut self, shutdown: ShutdownSignal) {
+        self.shutdown = shutdown;
+    }
+
     /// Run the Redis trigger indefinitely.
     async fn run(self, _config: Self::RunConfig) -> Result<()> {
         let address = self.trigger_config.address.as_str();
@@ -96,9 +119,20 @@ impl TriggerExecutor for RedisTrigger {
             pubsub.subscribe(subscription).await?;
         }
 
+        // Messages are handled one at a time, so once shutdown is requested,
+        // the message being handled completes before the trigger stops.
         let mut stream = pubsub.on_message();
+        let shutdown = self.shutdown.clone().requested();
+        tokio::pin!(shutdown);
         loop {
-            match stream.next().await {
+            let msg = tokio::select! {
+                msg = stream.next() => msg,
+                _ = &mut shutdown => {
+                    log::info!("Shutdown requested: unsubscribing");
+                    break Ok(());
+                }
+            };
+            match msg {
                 Some(msg) => drop(self.handle(msg).await),
                 None => {
                     log::trace!("Empty message");
@@ -120,6 +154,20 @@ impl RedisTrigger {
 
         if let Some(idx) = self.subscriptions.get(channel).copied() {
             let component = &self.engine.config.components[idx];
+            if let Some(schema) = self.schemas.get(&component.id) {
+                let errors = spin_loader::schema::payload_errors(schema, msg.get_payload_bytes());
+                if !errors.is_empty() {
+                    log::warn!(
+                        "Message on channel {:?} does not match the payload schema of component {}: {}",
+                        channel,
+                        component.id,
+                        errors.join("; ")
+                    );
+                    return self
+                        .dead_letter(&component.id, msg.get_payload_bytes())
+                        .await;
+                }
+            }
             let executor = self
                 .component_triggers
                 .get(&component.id)
@@ -153,6 +201,40 @@ impl RedisTrigger {
 
         Ok(())
     }
+
+    // Publish a message that did not match the payload schema of a component
+    // to its dead-letter channel, if it has one, or drop it.
+    async fn dead_letter(&self, component: &str, payload: &[u8]) -> Result<()> {
+        let channel = match self
+            .component_triggers
+            .get(component)
+            .and_then(|t| t.dead_letter_channel.as_deref())
+        {
+            Some(channel) => channel,
+            None => {
+                log::warn!(
+                    "Dropping the message, as component {} has no dead-letter channel",
+                    component
+                );
+                return Ok(());
+            }
+        };
+        let client = Client::open(self.trigger_config.address.as_str())?;
+        let mut conn = client.get_async_connection().await?;
+        redis::cmd("PUBLISH")
+            .arg(channel)
+            .arg(payload)
+            .query_async::<_, ()>(&mut conn)
+            .await
+            .with_context(|| {
+                format!(
+                    "Cannot publish the message to dead-letter channel {}",
+                    channel
+                )
+            })?;
+        log::info!("Published the message to dead-letter channel {}", channel);
+        Ok(())
+    }
 }
 
 /// The Redis executor trait.
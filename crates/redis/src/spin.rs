@@ -1,8 +1,7 @@
 use crate::{spin_redis::SpinRedis, ExecutionContext, RedisExecutor, RuntimeContext};
 use anyhow::Result;
 use async_trait::async_trait;
-use spin_engine::io::ModuleIoRedirects;
-use tokio::task::spawn_blocking;
+use spin_engine::{io::ModuleIoRedirects, InvocationOutcome};
 use wasmtime::{Instance, Store};
 
 #[derive(Clone)]
@@ -14,7 +13,7 @@ impl RedisExecutor for SpinRedisExecutor {
         &self,
         engine: &ExecutionContext,
         component: &str,
-        channel: &str,
+        _channel: &str,
         payload: &[u8],
         follow: bool,
     ) -> Result<()> {
@@ -23,21 +22,23 @@ impl RedisExecutor for SpinRedisExecutor {
             component
         );
 
-        let mior = ModuleIoRedirects::new(follow);
+        let mior = ModuleIoRedirects::for_component(follow, &engine.logger(component));
 
-        let (store, instance) =
-            engine.prepare_component(component, None, Some(mior.pipes), None, None)?;
+        let (store, instance) = engine
+            .prepare_component(component, None, Some(mior.pipes), None, None)
+            .await?;
 
-        let result = match Self::execute_impl(store, instance, channel, payload.to_vec()).await {
-            Ok(()) => {
-                log::trace!("Request finished OK");
-                Ok(())
-            }
-            Err(e) => {
-                log::trace!("Request finished with error {}", e);
-                Err(e)
-            }
-        };
+        let result =
+            match Self::execute_impl(engine, store, instance, component, payload.to_vec()).await {
+                Ok(()) => {
+                    log::trace!("Request finished OK");
+                    Ok(())
+                }
+                Err(e) => {
+                    log::trace!("Request finished with error {}", e);
+                    Err(e)
+                }
+            };
 
         let log_result =
             engine.save_output_to_logs(mior.read_handles.read(), component, true, true);
@@ -48,21 +49,30 @@ impl RedisExecutor for SpinRedisExecutor {
 
 impl SpinRedisExecutor {
     pub async fn execute_impl(
+        engine: &ExecutionContext,
         mut store: Store<RuntimeContext>,
         instance: Instance,
-        _channel: &str,
+        component: &str,
         payload: Vec<u8>,
     ) -> Result<()> {
-        let engine = SpinRedis::new(&mut store, &instance, |host| host.data.as_mut().unwrap())?;
+        let spin_redis = SpinRedis::new(&mut store, &instance, |host| host.data.as_mut().unwrap())?;
 
-        let _res = spawn_blocking(move || -> Result<crate::spin_redis::Error> {
-            match engine.handle_redis_message(&mut store, &payload) {
-                Ok(_) => Ok(crate::spin_redis::Error::Success),
-                Err(_) => Ok(crate::spin_redis::Error::Error),
-            }
-        })
-        .await??;
+        let _res = engine
+            .run_guest(component, async move {
+                match spin_redis.handle_redis_message(&mut store, &payload).await {
+                    Ok(Ok(())) => crate::spin_redis::Error::Success,
+                    Ok(Err(e)) => e,
+                    Err(_) => crate::spin_redis::Error::Error,
+                }
+            })
+            .await?;
 
         Ok(())
     }
 }
+
+impl InvocationOutcome for crate::spin_redis::Error {
+    fn is_failure(&self) -> bool {
+        !matches!(self, Self::Success)
+    }
+}
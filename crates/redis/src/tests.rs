@@ -36,6 +36,8 @@ async fn test_pubsub() -> Result<()> {
         .redis_trigger(RedisConfig {
             channel: "messages".to_string(),
             executor: Some(RedisExecutor::Spin),
+            schema: None,
+            dead_letter_channel: None,
         });
     let app = cfg.build_application();
 
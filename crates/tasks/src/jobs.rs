@@ -0,0 +1,213 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Result};
+use chrono::{TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+
+/// The file, relative to the application directory, holding the jobs of an
+/// application run from a local spin.toml.
+pub const JOBS_FILE: &str = ".spin/jobs.json";
+
+/// A delayed or recurring task, kept until it runs for the last time.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize)]
+pub struct Job {
+    /// The ID of the job.
+    pub id: String,
+    /// The component to run the job.
+    pub component: String,
+    /// The payload passed to the component.
+    pub payload: Vec<u8>,
+    /// When the job next runs, in milliseconds since the Unix epoch.
+    pub next_run_ms: u64,
+    /// The cron expression of a recurring job.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cron: Option<String>,
+}
+
+/// Jobs by ID, persisted in a JSON file, or in memory if there is none.
+///
+/// The file is read and written on every access, so that jobs cancelled
+/// by `spin jobs cancel` while the application is running do not run.
+pub struct JobStore {
+    path: Option<PathBuf>,
+    memory: Mutex<BTreeMap<String, Job>>,
+}
+
+impl JobStore {
+    /// Creates a store persisting jobs in the given file.
+    pub fn file(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: Some(path.into()),
+            memory: Default::default(),
+        }
+    }
+
+    /// Creates a store keeping jobs in memory.
+    pub fn memory() -> Self {
+        Self {
+            path: None,
+            memory: Default::default(),
+        }
+    }
+
+    /// The file jobs are persisted in, if any.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Lists the jobs, in the order they next run.
+    pub fn list(&self) -> Result<Vec<Job>> {
+        let mut jobs: Vec<_> = self.update(|_| ())?.into_values().collect();
+        jobs.sort_by_key(|job| job.next_run_ms);
+        Ok(jobs)
+    }
+
+    /// Adds a job.
+    pub fn insert(&self, job: Job) -> Result<()> {
+        self.update(|jobs| {
+            jobs.insert(job.id.clone(), job);
+        })?;
+        Ok(())
+    }
+
+    /// Removes a job, returning it if it existed.
+    pub fn remove(&self, id: &str) -> Result<Option<Job>> {
+        let mut removed = None;
+        self.update(|jobs| removed = jobs.remove(id))?;
+        Ok(removed)
+    }
+
+    /// Records that a job runs now: a recurring job is moved to its next
+    /// run, and any other job is removed. Returns the job, with its next run
+    /// if it recurs, or `None` if it was cancelled.
+    pub fn advance(&self, id: &str, now_ms: u64) -> Result<Option<Job>> {
+        let mut advanced = None;
+        self.update(|jobs| {
+            if let Some(job) = jobs.get_mut(id) {
+                let next = job
+                    .cron
+                    .as_deref()
+                    .and_then(|cron| next_run_ms(cron, now_ms).ok().flatten());
+                match next {
+                    Some(next) => {
+                        job.next_run_ms = next;
+                        advanced = Some(job.clone());
+                    }
+                    None => {
+                        advanced = jobs.remove(id).map(|job| Job { cron: None, ..job });
+                    }
+                }
+            }
+        })?;
+        Ok(advanced)
+    }
+
+    fn update(&self, f: impl FnOnce(&mut BTreeMap<String, Job>)) -> Result<BTreeMap<String, Job>> {
+        let mut memory = self.memory.lock().unwrap();
+        let path = match &self.path {
+            Some(path) => path,
+            None => {
+                f(&mut memory);
+                return Ok(memory.clone());
+            }
+        };
+
+        let mut jobs = load_jobs(path)?;
+        let before = jobs.clone();
+        f(&mut jobs);
+        if jobs != before {
+            save_jobs(path, &jobs)?;
+        }
+        Ok(jobs)
+    }
+}
+
+/// Returns when a job with the cron expression next runs after the given
+/// time, or `None` if it never runs again.
+pub fn next_run_ms(cron: &str, after_ms: u64) -> Result<Option<u64>> {
+    let schedule = cron::Schedule::from_str(cron)
+        .with_context(|| format!("Invalid cron expression '{}'", cron))?;
+    let after = Utc
+        .timestamp_millis_opt(after_ms as i64)
+        .single()
+        .with_context(|| format!("Invalid time {}", after_ms))?;
+    Ok(schedule
+        .after(&after)
+        .next()
+        .map(|next| next.timestamp_millis() as u64))
+}
+
+/// The current time, in milliseconds since the Unix epoch.
+pub fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+fn load_jobs(path: &Path) -> Result<BTreeMap<String, Job>> {
+    match std::fs::read(path) {
+        Ok(contents) => serde_json::from_slice(&contents)
+            .with_context(|| format!("Cannot parse jobs file {}", path.display())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+        Err(e) => Err(e).with_context(|| format!("Cannot read jobs file {}", path.display())),
+    }
+}
+
+fn save_jobs(path: &Path, jobs: &BTreeMap<String, Job>) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path, serde_json::to_vec_pretty(jobs)?)
+        .with_context(|| format!("Cannot write jobs file {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn job(id: &str, next_run_ms: u64, cron: Option<&str>) -> Job {
+        Job {
+            id: id.to_owned(),
+            component: "worker".to_owned(),
+            payload: b"payload".to_vec(),
+            next_run_ms,
+            cron: cron.map(str::to_owned),
+        }
+    }
+
+    #[test]
+    fn test_jobs_are_persisted_and_advanced() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join(JOBS_FILE);
+        let store = JobStore::file(&path);
+        store.insert(job("once", 2_000, None))?;
+        store.insert(job("every-minute", 1_000, Some("0 * * * * *")))?;
+
+        // Another store on the same file, as after a restart, sees the jobs.
+        let restarted = JobStore::file(&path);
+        let ids: Vec<_> = restarted.list()?.into_iter().map(|j| j.id).collect();
+        assert_eq!(ids, ["every-minute", "once"]);
+
+        let recurring = restarted.advance("every-minute", 1_000)?.unwrap();
+        assert_eq!(recurring.next_run_ms, 60_000);
+        assert_eq!(store.list()?[1], recurring);
+
+        assert!(restarted.advance("once", 2_000)?.is_some());
+        assert!(store.advance("once", 2_000)?.is_none());
+        assert!(store.remove("every-minute")?.is_some());
+        assert!(restarted.list()?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_cron_expression_is_an_error() {
+        assert!(next_run_ms("every minute", 0).is_err());
+    }
+}
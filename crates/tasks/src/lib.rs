@@ -0,0 +1,335 @@
+//! A host interface allowing components to enqueue background tasks, run by
+//! the runtime once the invocation enqueuing them completes, and to schedule
+//! recurring jobs.
+
+mod jobs;
+mod runner;
+
+use std::{
+    collections::HashSet,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use serde::Deserialize;
+use spin_engine::{
+    host_component::{HostComponent, HostComponentsStateHandle},
+    RuntimeContext,
+};
+use spin_manifest::CoreComponent;
+use spin_tasks::*;
+use tokio::sync::mpsc;
+use wit_bindgen_wasmtime::wasmtime::Linker;
+
+pub use jobs::{next_run_ms, now_ms, Job, JobStore, JOBS_FILE};
+pub use runner::run_tasks;
+pub use spin_tasks::add_to_linker;
+
+wit_bindgen_wasmtime::export!({
+    paths: ["../../wit/ephemeral/spin-tasks.wit"],
+    async: *,
+});
+
+/// The function components export to run the tasks enqueued for them.
+pub const HANDLE_TASK_EXPORT: &str = "handle-task";
+
+/// Runtime configuration for background tasks.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub struct TasksConfig {
+    /// The maximum number of tasks waiting to run, across all components.
+    #[serde(default = "default_max_pending")]
+    pub max_pending: usize,
+    /// The maximum delay, in seconds, a task may be enqueued with.
+    #[serde(default = "default_max_delay_secs")]
+    pub max_delay_secs: u64,
+    /// The file delayed tasks and recurring jobs are persisted in. Defaults
+    /// to `.spin/jobs.json` in the directory of a local application.
+    pub store: Option<PathBuf>,
+}
+
+impl Default for TasksConfig {
+    fn default() -> Self {
+        Self {
+            max_pending: default_max_pending(),
+            max_delay_secs: default_max_delay_secs(),
+            store: None,
+        }
+    }
+}
+
+fn default_max_pending() -> usize {
+    1024
+}
+
+fn default_max_delay_secs() -> u64 {
+    24 * 60 * 60
+}
+
+/// A task enqueued by a component.
+pub struct Task {
+    /// The ID of the task, identifying the job it is kept as.
+    pub id: String,
+    /// The component to run the task.
+    pub component: String,
+    /// The payload passed to the component.
+    pub payload: Vec<u8>,
+    /// The time to wait before running the task.
+    pub delay: Duration,
+    /// The cron expression of a recurring task.
+    pub cron: Option<String>,
+    _pending: Pending,
+}
+
+/// Counts a task as pending until it is dropped.
+struct Pending(Arc<AtomicUsize>);
+
+impl Drop for Pending {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Receives the tasks enqueued by components, once they are ready to run.
+pub struct TaskReceiver {
+    receiver: mpsc::UnboundedReceiver<Task>,
+    jobs: Arc<JobStore>,
+}
+
+/// The background tasks host component.
+#[derive(Clone)]
+pub struct TasksComponent {
+    config: TasksConfig,
+    components: Arc<HashSet<String>>,
+    sender: mpsc::UnboundedSender<Task>,
+    pending: Arc<AtomicUsize>,
+    jobs: Arc<JobStore>,
+}
+
+impl TasksComponent {
+    /// Creates a background tasks host component for an application with the
+    /// given components, keeping jobs in the given store, along with the
+    /// receiver of the tasks they enqueue, to be passed to `run_tasks`.
+    pub fn new(
+        config: &TasksConfig,
+        components: impl IntoIterator<Item = String>,
+        jobs: JobStore,
+    ) -> (Self, TaskReceiver) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let jobs = Arc::new(jobs);
+        let component = Self {
+            config: config.clone(),
+            components: Arc::new(components.into_iter().collect()),
+            sender,
+            pending: Default::default(),
+            jobs: jobs.clone(),
+        };
+        (component, TaskReceiver { receiver, jobs })
+    }
+
+    fn tasks_for(&self, caller: &str) -> Tasks {
+        Tasks {
+            caller: caller.to_owned(),
+            host: self.clone(),
+            enqueued: vec![],
+        }
+    }
+}
+
+impl HostComponent for TasksComponent {
+    type State = Tasks;
+
+    fn add_to_linker<T: Send>(
+        linker: &mut Linker<RuntimeContext<T>>,
+        state_handle: HostComponentsStateHandle<Self::State>,
+    ) -> anyhow::Result<()> {
+        add_to_linker(linker, move |ctx| state_handle.get_mut(ctx))
+    }
+
+    fn build_state(&self, component: &CoreComponent) -> anyhow::Result<Self::State> {
+        Ok(self.tasks_for(&component.id))
+    }
+}
+
+/// Per-instance background tasks state. Tasks enqueued by an instance are
+/// held until the instance is dropped, so that they run after the
+/// invocation, and its response, completes.
+pub struct Tasks {
+    caller: String,
+    host: TasksComponent,
+    enqueued: Vec<Task>,
+}
+
+impl Tasks {
+    fn push(
+        &mut self,
+        component: Option<&str>,
+        payload: &[u8],
+        delay: Duration,
+        cron: Option<String>,
+    ) -> Result<String, Error> {
+        let component = component.unwrap_or(&self.caller);
+        if !self.host.components.contains(component) {
+            return Err(Error::NoSuchComponent(component.to_owned()));
+        }
+        let pending = &self.host.pending;
+        if pending.fetch_add(1, Ordering::SeqCst) >= self.host.config.max_pending {
+            pending.fetch_sub(1, Ordering::SeqCst);
+            return Err(Error::QueueFull);
+        }
+        let id = uuid::Uuid::new_v4().to_string();
+        self.enqueued.push(Task {
+            id: id.clone(),
+            component: component.to_owned(),
+            payload: payload.to_vec(),
+            delay,
+            cron,
+            _pending: Pending(pending.clone()),
+        });
+        Ok(id)
+    }
+}
+
+#[async_trait]
+impl spin_tasks::SpinTasks for Tasks {
+    async fn enqueue(
+        &mut self,
+        component: Option<&str>,
+        payload: &[u8],
+        delay_ms: Option<u64>,
+    ) -> Result<(), Error> {
+        let delay = Duration::from_millis(delay_ms.unwrap_or_default());
+        if delay > Duration::from_secs(self.host.config.max_delay_secs) {
+            return Err(Error::InvalidDelay(delay_ms.unwrap_or_default()));
+        }
+        self.push(component, payload, delay, None)?;
+        Ok(())
+    }
+
+    async fn schedule(
+        &mut self,
+        component: Option<&str>,
+        payload: &[u8],
+        cron: &str,
+    ) -> Result<String, Error> {
+        match jobs::next_run_ms(cron, now_ms()) {
+            Ok(Some(_)) => {}
+            Ok(None) => return Err(Error::InvalidSchedule(cron.to_owned())),
+            Err(e) => return Err(Error::InvalidSchedule(format!("{:#}", e))),
+        }
+        self.push(component, payload, Duration::ZERO, Some(cron.to_owned()))
+    }
+
+    async fn cancel(&mut self, id: &str) -> Result<(), Error> {
+        let enqueued = self.enqueued.len();
+        self.enqueued.retain(|task| task.id != id);
+        if self.enqueued.len() < enqueued {
+            return Ok(());
+        }
+        match self.host.jobs.remove(id) {
+            Ok(Some(_)) => Ok(()),
+            Ok(None) => Err(Error::NoSuchJob(id.to_owned())),
+            Err(e) => {
+                tracing::error!("Failed to cancel job {}: {:?}", id, e);
+                Err(Error::Other(e.to_string()))
+            }
+        }
+    }
+}
+
+impl Drop for Tasks {
+    fn drop(&mut self) {
+        for task in self.enqueued.drain(..) {
+            if self.host.sender.send(task).is_err() {
+                tracing::warn!(
+                    "Dropping task enqueued by component {}: tasks are no longer running",
+                    self.caller
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spin_tasks::SpinTasks;
+
+    #[tokio::test]
+    async fn test_tasks_are_sent_once_the_instance_is_dropped() {
+        let config = TasksConfig {
+            max_pending: 1,
+            ..Default::default()
+        };
+        let components = ["webhook".to_owned(), "worker".to_owned()];
+        let (host, mut receiver) = TasksComponent::new(&config, components, JobStore::memory());
+
+        let mut tasks = host.tasks_for("webhook");
+        assert!(matches!(
+            tasks.enqueue(Some("missing"), b"", None).await,
+            Err(Error::NoSuchComponent(_))
+        ));
+        assert!(matches!(
+            tasks.enqueue(None, b"", Some(u64::MAX)).await,
+            Err(Error::InvalidDelay(_))
+        ));
+        tasks
+            .enqueue(Some("worker"), b"job-1", Some(500))
+            .await
+            .unwrap();
+        assert!(matches!(
+            tasks.enqueue(None, b"job-2", None).await,
+            Err(Error::QueueFull)
+        ));
+        assert!(receiver.receiver.try_recv().is_err());
+
+        drop(tasks);
+        let task = receiver.receiver.try_recv().unwrap();
+        assert_eq!(task.component, "worker");
+        assert_eq!(task.payload, b"job-1");
+        assert_eq!(task.delay, Duration::from_millis(500));
+
+        // Running the task frees its place in the queue.
+        drop(task);
+        let mut tasks = host.tasks_for("webhook");
+        tasks.enqueue(None, b"job-2", None).await.unwrap();
+        drop(tasks);
+        assert_eq!(receiver.receiver.try_recv().unwrap().component, "webhook");
+    }
+
+    #[tokio::test]
+    async fn test_recurring_jobs_can_be_cancelled() {
+        let (host, mut receiver) = TasksComponent::new(
+            &Default::default(),
+            ["reports".to_owned()],
+            JobStore::memory(),
+        );
+
+        let mut tasks = host.tasks_for("reports");
+        assert!(matches!(
+            tasks.schedule(None, b"", "every day").await,
+            Err(Error::InvalidSchedule(_))
+        ));
+        let daily = tasks.schedule(None, b"daily", "0 0 0 * * *").await.unwrap();
+        let hourly = tasks
+            .schedule(None, b"hourly", "0 0 * * * *")
+            .await
+            .unwrap();
+        tasks.cancel(&daily).await.unwrap();
+        assert!(matches!(
+            tasks.cancel(&daily).await,
+            Err(Error::NoSuchJob(_))
+        ));
+        drop(tasks);
+
+        let task = receiver.receiver.try_recv().unwrap();
+        assert_eq!(task.id, hourly);
+        assert_eq!(task.cron.as_deref(), Some("0 0 * * * *"));
+        assert!(receiver.receiver.try_recv().is_err());
+    }
+}
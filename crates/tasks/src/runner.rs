@@ -0,0 +1,172 @@
+use std::{sync::Arc, time::Duration};
+
+use anyhow::{Context, Result};
+use spin_engine::{io::ModuleIoRedirects, ExecutionContext};
+use wasmtime::{AsContextMut, Instance};
+
+use crate::{
+    jobs::{next_run_ms, now_ms},
+    Job, JobStore, TaskReceiver, HANDLE_TASK_EXPORT,
+};
+
+/// Runs the tasks enqueued by components, and the jobs kept in the job
+/// store, including those kept before the application restarted. Tasks
+/// without a delay run immediately; delayed and recurring tasks are kept as
+/// jobs until they run for the last time. Failed tasks are logged, and not
+/// retried.
+pub async fn run_tasks<T: Default + Send + 'static>(
+    receiver: TaskReceiver,
+    execution_context: ExecutionContext<T>,
+) {
+    let TaskReceiver { mut receiver, jobs } = receiver;
+
+    match jobs.list() {
+        Ok(stored) => {
+            for job in stored {
+                tokio::spawn(run_job(
+                    execution_context.clone(),
+                    jobs.clone(),
+                    job.id,
+                    job.next_run_ms,
+                ));
+            }
+        }
+        Err(e) => tracing::error!("Failed to load stored jobs: {:#}", e),
+    }
+
+    while let Some(task) = receiver.recv().await {
+        if task.delay.is_zero() && task.cron.is_none() {
+            let execution_context = execution_context.clone();
+            tokio::spawn(async move {
+                let (component, payload) = (task.component.clone(), task.payload.clone());
+                run_and_log(execution_context, component, payload).await;
+                // The task counts as pending until it has run.
+                drop(task);
+            });
+            continue;
+        }
+
+        let now = now_ms();
+        let next_run_ms = match &task.cron {
+            Some(cron) => match next_run_ms(cron, now) {
+                Ok(Some(next)) => next,
+                _ => continue,
+            },
+            None => now + task.delay.as_millis() as u64,
+        };
+        let job = Job {
+            id: task.id.clone(),
+            component: task.component.clone(),
+            payload: task.payload.clone(),
+            next_run_ms,
+            cron: task.cron.clone(),
+        };
+        if let Err(e) = jobs.insert(job) {
+            tracing::error!(
+                "Failed to store job for component {}, dropping it: {:#}",
+                task.component,
+                e
+            );
+            continue;
+        }
+        tokio::spawn(run_job(
+            execution_context.clone(),
+            jobs.clone(),
+            task.id,
+            next_run_ms,
+        ));
+    }
+}
+
+/// Runs a stored job when it is due, for as long as it recurs and is not
+/// cancelled.
+async fn run_job<T: Default + Send + 'static>(
+    execution_context: ExecutionContext<T>,
+    jobs: Arc<JobStore>,
+    id: String,
+    mut next_run_ms: u64,
+) {
+    loop {
+        let wait = next_run_ms.saturating_sub(now_ms());
+        tokio::time::sleep(Duration::from_millis(wait)).await;
+        let job = match jobs.advance(&id, now_ms()) {
+            Ok(Some(job)) => job,
+            Ok(None) => {
+                tracing::trace!("Job {} was cancelled", id);
+                return;
+            }
+            Err(e) => {
+                tracing::error!("Failed to update job {}, not running it: {:#}", id, e);
+                return;
+            }
+        };
+        tokio::spawn(run_and_log(
+            execution_context.clone(),
+            job.component,
+            job.payload,
+        ));
+        match job.cron {
+            Some(_) => next_run_ms = job.next_run_ms,
+            None => return,
+        }
+    }
+}
+
+async fn run_and_log<T: Default + Send + 'static>(
+    execution_context: ExecutionContext<T>,
+    component: String,
+    payload: Vec<u8>,
+) {
+    if let Err(e) = run_task(&execution_context, &component, payload).await {
+        tracing::error!("Task for component {} failed: {:#}", component, e);
+    }
+}
+
+async fn run_task<T: Default + Send + 'static>(
+    execution_context: &ExecutionContext<T>,
+    component: &str,
+    payload: Vec<u8>,
+) -> Result<()> {
+    tracing::trace!("Running task for component {}", component);
+    let follow = execution_context
+        .config
+        .follow_components
+        .should_follow(component);
+    let mior = ModuleIoRedirects::for_component(follow, &execution_context.logger(component));
+    let (mut store, instance) = execution_context
+        .prepare_component(component, None, Some(mior.pipes), None, None)
+        .await?;
+
+    let result = execution_context
+        .run_guest(component, async move {
+            call_handle_task(&mut store, instance, &payload).await
+        })
+        .await?;
+
+    let log_result =
+        execution_context.save_output_to_logs(mior.read_handles.read(), component, true, true);
+    result.and(log_result)
+}
+
+/// Calls the task export of an instance, passing the payload as a list in
+/// the canonical ABI: allocated in guest memory, and owned by the guest.
+async fn call_handle_task<T: Send>(
+    mut store: impl AsContextMut<Data = T> + Send,
+    instance: Instance,
+    payload: &[u8],
+) -> Result<()> {
+    let handle_task = instance
+        .get_typed_func::<(i32, i32), (), _>(&mut store, HANDLE_TASK_EXPORT)
+        .with_context(|| format!("Component must export a {} function", HANDLE_TASK_EXPORT))?;
+    let realloc = instance
+        .get_typed_func::<(i32, i32, i32, i32), i32, _>(&mut store, "canonical_abi_realloc")?;
+    let memory = instance
+        .get_memory(&mut store, "memory")
+        .context("Component must export its memory")?;
+
+    let len = i32::try_from(payload.len()).context("Task payload is too large")?;
+    let ptr = realloc.call_async(&mut store, (0, 0, 1, len)).await?;
+    memory.write(&mut store, ptr as u32 as usize, payload)?;
+    handle_task.call_async(&mut store, (ptr, len)).await?;
+    Ok(())
+}
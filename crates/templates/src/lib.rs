@@ -15,6 +15,6 @@ mod store;
 mod template;
 
 pub use manager::*;
-pub use run::{Run, RunOptions, TemplatePreparationResult};
-pub use source::TemplateSource;
+pub use run::{Run, RunOptions, TemplatePreparationResult, TemplateVariantInfo};
+pub use source::{GitAuth, TemplateSource};
 pub use template::Template;
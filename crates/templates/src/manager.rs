@@ -1,9 +1,9 @@
 use std::path::Path;
 
-use anyhow::Context;
+use anyhow::{anyhow, Context};
 
 use crate::{
-    source::TemplateSource,
+    source::{GitAuth, RecordedSource, TemplateSource},
     store::{TemplateLayout, TemplateStore},
     template::Template,
 };
@@ -67,6 +67,12 @@ pub enum SkippedReason {
     AlreadyExists,
     /// The template was skipped because its manifest was missing or invalid.
     InvalidManifest(String),
+    /// The template was not upgraded because it was installed without
+    /// recording its source.
+    UnknownSource,
+    /// The template was not upgraded because it is no longer in the source it
+    /// was installed from.
+    NotInSource,
 }
 
 /// The results of installing a set of templates.
@@ -111,6 +117,7 @@ impl TemplateManager {
             reporter.report("Copying remote template source");
         }
 
+        let record = source.record()?;
         let local_source = source
             .get_local()
             .await
@@ -125,7 +132,7 @@ impl TemplateManager {
 
         for template_dir in template_dirs {
             let install_result = self
-                .install_one(&template_dir, options, reporter)
+                .install_one(&template_dir, options, reporter, &record)
                 .await
                 .with_context(|| {
                     format!("Failed to install template from {}", template_dir.display())
@@ -142,11 +149,91 @@ impl TemplateManager {
         Ok(InstallationResults { installed, skipped })
     }
 
+    /// Upgrades installed templates from the sources they were installed
+    /// from, or all installed templates if no IDs are given.
+    ///
+    /// If the source of a template is a Git repository with no recorded
+    /// branch, the templates are installed from the tag matching the Spin
+    /// version, as for a new installation.
+    pub async fn upgrade(
+        &self,
+        ids: &[String],
+        spin_version: &str,
+        git_auth: Option<GitAuth>,
+        reporter: &impl ProgressReporter,
+    ) -> anyhow::Result<InstallationResults> {
+        let mut installed = vec![];
+        let mut skipped = vec![];
+
+        // The templates to upgrade from each source.
+        let mut sources: Vec<(RecordedSource, Vec<String>)> = vec![];
+        let mut found = vec![];
+        for layout in self.store.list_layouts().await? {
+            let id = match Template::load_from(&layout) {
+                Ok(template) => template.id().to_owned(),
+                Err(_) => continue,
+            };
+            if !ids.is_empty() && !ids.contains(&id) {
+                continue;
+            }
+            found.push(id.clone());
+            match read_source_record(&layout)? {
+                Some(record) => match sources.iter_mut().find(|(r, _)| *r == record) {
+                    Some((_, ids)) => ids.push(id),
+                    None => sources.push((record, vec![id])),
+                },
+                None => skipped.push((id, SkippedReason::UnknownSource)),
+            }
+        }
+        if let Some(missing) = ids.iter().find(|id| !found.contains(id)) {
+            return Err(anyhow!("Template {} is not installed", missing));
+        }
+
+        let options = InstallOptions::default().update(true);
+        for (record, template_ids) in sources {
+            reporter.report(format!("Upgrading templates from {}", record));
+            let source = TemplateSource::from_record(&record, spin_version, git_auth.clone());
+            let local_source = source
+                .get_local()
+                .await
+                .with_context(|| format!("Failed to get template source {}", record))?;
+            let mut upgraded = vec![];
+            for template_dir in local_source.template_directories().await? {
+                // Other templates of the source are not installed by upgrades.
+                let id = match Template::load_from(&TemplateLayout::new(&template_dir)) {
+                    Ok(template) if template_ids.contains(&template.id().to_owned()) => {
+                        template.id().to_owned()
+                    }
+                    _ => continue,
+                };
+                upgraded.push(id);
+                match self
+                    .install_one(&template_dir, &options, reporter, &record)
+                    .await?
+                {
+                    InstallationResult::Installed(template) => installed.push(template),
+                    InstallationResult::Skipped(id, reason) => skipped.push((id, reason)),
+                }
+            }
+            for id in template_ids {
+                if !upgraded.contains(&id) {
+                    skipped.push((id, SkippedReason::NotInSource));
+                }
+            }
+        }
+
+        installed.sort_by_key(|t| t.id().to_owned());
+        skipped.sort_by_key(|(id, _)| id.clone());
+
+        Ok(InstallationResults { installed, skipped })
+    }
+
     async fn install_one(
         &self,
         source_dir: &Path,
         options: &InstallOptions,
         reporter: &impl ProgressReporter,
+        record: &RecordedSource,
     ) -> anyhow::Result<InstallationResult> {
         let layout = TemplateLayout::new(source_dir);
         let template = match Template::load_from(&layout) {
@@ -185,6 +272,7 @@ impl TemplateManager {
         } else {
             copy_template_into(id, source_dir, &dest_dir).await?
         };
+        write_source_record(&TemplateLayout::new(&dest_dir), record).await?;
 
         Ok(InstallationResult::Installed(template))
     }
@@ -354,6 +442,28 @@ fn load_template_from(id: &str, dest_dir: &Path) -> anyhow::Result<Template> {
     })
 }
 
+async fn write_source_record(
+    layout: &TemplateLayout,
+    record: &RecordedSource,
+) -> anyhow::Result<()> {
+    let path = layout.source_path();
+    tokio::fs::write(&path, toml::to_string(record)?)
+        .await
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+fn read_source_record(layout: &TemplateLayout) -> anyhow::Result<Option<RecordedSource>> {
+    let path = layout.source_path();
+    if !path.exists() {
+        return Ok(None);
+    }
+    let text = std::fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let record = toml::from_str(&text)
+        .with_context(|| format!("Invalid source record {}", path.display()))?;
+    Ok(Some(record))
+}
+
 fn copy_content() -> fs_extra::dir::CopyOptions {
     let mut options = fs_extra::dir::CopyOptions::new();
     options.content_only = true;
@@ -391,7 +501,7 @@ mod tests {
 
     use tempfile::tempdir;
 
-    use crate::RunOptions;
+    use crate::{RunOptions, TemplateVariantInfo};
 
     use super::*;
 
@@ -577,6 +687,58 @@ mod tests {
         assert!(installed.iter().any(|t| t.id() == "http-go"));
     }
 
+    #[tokio::test]
+    async fn can_upgrade_from_recorded_source() {
+        let temp_dir = tempdir().unwrap();
+        let store = TemplateStore::new(temp_dir.path());
+        let manager = TemplateManager { store };
+        let source = TemplateSource::File(project_root());
+
+        manager
+            .install(&source, &InstallOptions::default(), &DiscardingReporter)
+            .await
+            .unwrap();
+
+        // Templates installed before sources were recorded can't be upgraded.
+        let layout = manager.store.get_layout("http-go").unwrap();
+        fs::remove_file(layout.source_path()).unwrap();
+        // Changes to installed templates are replaced by upgrades.
+        let layout = manager.store.get_layout("http-rust").unwrap();
+        fs::remove_dir_all(layout.content_dir()).unwrap();
+
+        let upgrade_result = manager
+            .upgrade(&[], "0.4.0", None, &DiscardingReporter)
+            .await
+            .unwrap();
+        assert_eq!(TPLS_IN_THIS - 1, upgrade_result.installed.len());
+        assert_eq!(1, upgrade_result.skipped.len());
+        assert!(matches!(
+            upgrade_result.skipped[0],
+            (ref id, SkippedReason::UnknownSource) if id == "http-go"
+        ));
+        assert!(layout.content_dir().exists());
+
+        let upgrade_result = manager
+            .upgrade(
+                &["http-rust".to_owned()],
+                "0.4.0",
+                None,
+                &DiscardingReporter,
+            )
+            .await
+            .unwrap();
+        assert_eq!(1, upgrade_result.installed.len());
+        assert!(manager
+            .upgrade(
+                &["not-installed".to_owned()],
+                "0.4.0",
+                None,
+                &DiscardingReporter
+            )
+            .await
+            .is_err());
+    }
+
     #[tokio::test]
     async fn can_read_installed_template() {
         let temp_dir = tempdir().unwrap();
@@ -630,6 +792,8 @@ mod tests {
             name: "my project".to_owned(),
             values,
             accept_defaults: false,
+            accept_hooks: false,
+            variant: TemplateVariantInfo::NewApplication,
         };
 
         template
@@ -668,6 +832,8 @@ mod tests {
             name: "my project".to_owned(),
             values,
             accept_defaults: true,
+            accept_hooks: false,
+            variant: TemplateVariantInfo::NewApplication,
         };
 
         template
@@ -687,4 +853,230 @@ mod tests {
             .unwrap();
         assert!(spin_toml.contains("route = \"/...\""));
     }
+
+    #[tokio::test]
+    async fn silent_run_lists_all_missing_values() {
+        let temp_dir = tempdir().unwrap();
+        let store = TemplateStore::new(temp_dir.path());
+        let manager = TemplateManager { store };
+        let source = TemplateSource::File(project_root());
+
+        manager
+            .install(&source, &InstallOptions::default(), &DiscardingReporter)
+            .await
+            .unwrap();
+
+        let template = manager.get("http-rust").unwrap().unwrap();
+
+        let dest_temp_dir = tempdir().unwrap();
+        let output_dir = dest_temp_dir.path().join("myproj");
+        let values = [("http-base".to_owned(), "/base".to_owned())]
+            .into_iter()
+            .collect();
+        let options = RunOptions {
+            output_path: output_dir.clone(),
+            name: "my project".to_owned(),
+            values,
+            accept_defaults: false,
+            accept_hooks: false,
+            variant: TemplateVariantInfo::NewApplication,
+        };
+
+        let err = template
+            .run(options)
+            .silent()
+            .await
+            .execute()
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("project-description"), "{}", err);
+        assert!(err.contains("http-path"), "{}", err);
+        assert!(!err.contains("http-base"), "{}", err);
+        assert!(!output_dir.exists());
+    }
+
+    #[tokio::test]
+    async fn can_add_component_from_template() {
+        let temp_dir = tempdir().unwrap();
+        let store = TemplateStore::new(temp_dir.path());
+        let manager = TemplateManager { store };
+        let source = TemplateSource::File(project_root());
+
+        manager
+            .install(&source, &InstallOptions::default(), &DiscardingReporter)
+            .await
+            .unwrap();
+
+        let app_dir = tempdir().unwrap();
+        let manifest_path = app_dir.path().join("spin.toml");
+        let app_manifest = r#"# My application
+spin_version = "1"
+name = "my-app"
+trigger = { type = "http", base = "/" }
+version = "1.0.0"
+
+[[component]]
+id = "existing"
+source = "existing.wasm"
+[component.trigger]
+route = "/existing"
+"#;
+        std::fs::write(&manifest_path, app_manifest).unwrap();
+
+        let add = |name: &str, path: &str| RunOptions {
+            output_path: app_dir.path().join(name),
+            name: name.to_owned(),
+            values: [("http-path".to_owned(), path.to_owned())]
+                .into_iter()
+                .collect(),
+            accept_defaults: false,
+            accept_hooks: false,
+            variant: TemplateVariantInfo::AddComponent {
+                manifest_path: manifest_path.clone(),
+            },
+        };
+
+        let template = manager.get("http-rust").unwrap().unwrap();
+        template
+            .run(add("api", "/api/..."))
+            .silent()
+            .await
+            .execute()
+            .await
+            .unwrap();
+
+        let manifest = std::fs::read_to_string(&manifest_path).unwrap();
+        assert!(
+            manifest.starts_with(app_manifest.trim_end()),
+            "{}",
+            manifest
+        );
+        let manifest: toml::Value = toml::from_str(&manifest).unwrap();
+        let components = manifest["component"].as_array().unwrap();
+        assert_eq!(2, components.len());
+        let added = &components[1];
+        assert_eq!("api", added["id"].as_str().unwrap());
+        assert_eq!(
+            "api/target/wasm32-wasi/release/api.wasm",
+            added["source"].as_str().unwrap()
+        );
+        assert_eq!("api", added["build"]["workdir"].as_str().unwrap());
+        assert_eq!("/api/...", added["trigger"]["route"].as_str().unwrap());
+        assert!(app_dir.path().join("api").join("Cargo.toml").exists());
+        assert!(!app_dir.path().join("api").join("spin.toml").exists());
+
+        // Components are not added twice, nor to applications with another trigger.
+        let template = manager.get("http-rust").unwrap().unwrap();
+        let mut options = add("api", "/other");
+        options.output_path = app_dir.path().join("api2");
+        assert!(template
+            .run(options)
+            .silent()
+            .await
+            .execute()
+            .await
+            .is_err());
+        let template = manager.get("redis-rust").unwrap().unwrap();
+        let mut options = add("queue", "/unused");
+        options.values = [("redis-channel".to_owned(), "messages".to_owned())]
+            .into_iter()
+            .collect();
+        assert!(template
+            .run(options)
+            .silent()
+            .await
+            .execute()
+            .await
+            .is_err());
+        assert!(!app_dir.path().join("queue").exists());
+    }
+
+    #[tokio::test]
+    async fn can_run_conditionals_file_name_templates_and_hooks() {
+        let temp_dir = tempdir().unwrap();
+        let store = TemplateStore::new(temp_dir.path());
+        let manager = TemplateManager { store };
+
+        let source_dir = tempdir().unwrap();
+        let tpl_dir = source_dir.path().join("templates").join("conditional");
+        let content_dir = tpl_dir.join("content");
+        fs::create_dir_all(tpl_dir.join("metadata")).unwrap();
+        fs::create_dir_all(content_dir.join("ci")).unwrap();
+        fs::create_dir_all(content_dir.join("src")).unwrap();
+        fs::write(
+            tpl_dir.join("metadata").join("spin-template.toml"),
+            r#"manifest_version = "1"
+id = "conditional"
+
+[parameters]
+ci = { type = "string", prompt = "Add CI workflow? (yes/no)", default = "no", pattern = "^(yes|no)$" }
+
+[[conditional]]
+condition = { parameter = "ci", equals = "yes" }
+files = ["ci"]
+
+[post_generate]
+command = "echo {{project-name | kebab_case}} > hook-ran.txt"
+"#,
+        )
+        .unwrap();
+        fs::write(content_dir.join("ci").join("workflow.yml"), "on: push").unwrap();
+        fs::write(
+            content_dir
+                .join("src")
+                .join("{{project-name | snake_case}}.rs"),
+            "// {{project-name | pascal_case}}",
+        )
+        .unwrap();
+
+        manager
+            .install(
+                &TemplateSource::File(source_dir.path().to_owned()),
+                &InstallOptions::default(),
+                &DiscardingReporter,
+            )
+            .await
+            .unwrap();
+
+        let dest_temp_dir = tempdir().unwrap();
+        let run = |dir: &str, ci: &str, accept_hooks: bool| RunOptions {
+            output_path: dest_temp_dir.path().join(dir),
+            name: "my project".to_owned(),
+            values: [("ci".to_owned(), ci.to_owned())].into_iter().collect(),
+            accept_defaults: false,
+            accept_hooks,
+            variant: TemplateVariantInfo::NewApplication,
+        };
+
+        let template = manager.get("conditional").unwrap().unwrap();
+        template
+            .run(run("without", "no", false))
+            .silent()
+            .await
+            .execute()
+            .await
+            .unwrap();
+        let without = dest_temp_dir.path().join("without");
+        assert!(!without.join("ci").exists());
+        assert_eq!(
+            "// MyProject",
+            fs::read_to_string(without.join("src").join("my_project.rs")).unwrap()
+        );
+        // Silent runs do not run hooks unless they are accepted.
+        assert!(!without.join("hook-ran.txt").exists());
+
+        let template = manager.get("conditional").unwrap().unwrap();
+        template
+            .run(run("with", "yes", true))
+            .silent()
+            .await
+            .execute()
+            .await
+            .unwrap();
+        let with = dest_temp_dir.path().join("with");
+        assert!(with.join("ci").join("workflow.yml").exists());
+        let hook_output = fs::read_to_string(with.join("hook-ran.txt")).unwrap();
+        assert_eq!("my-project", hook_output.trim());
+    }
 }
@@ -16,6 +16,36 @@ pub(crate) struct RawTemplateManifestV1 {
     pub id: String,
     pub description: Option<String>,
     pub parameters: Option<IndexMap<String, RawParameter>>,
+    pub add_component: Option<RawAddComponent>,
+    pub conditional: Option<Vec<RawConditional>>,
+    pub post_generate: Option<RawPostGenerate>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub(crate) struct RawAddComponent {
+    pub skip_files: Option<Vec<String>>,
+    pub skip_parameters: Option<Vec<String>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub(crate) struct RawConditional {
+    pub condition: RawCondition,
+    pub files: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub(crate) struct RawCondition {
+    pub parameter: String,
+    pub equals: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub(crate) struct RawPostGenerate {
+    pub command: String,
 }
 
 #[derive(Debug, Deserialize)]
@@ -10,6 +10,9 @@ use walkdir::WalkDir;
 
 use crate::template::{Template, TemplateParameter};
 
+/// The manifest of the applications generated by templates.
+const SPIN_MANIFEST_FILE_NAME: &str = "spin.toml";
+
 /// Executes a template to the point where it is ready to generate
 /// artefacts.
 pub struct Run {
@@ -27,6 +30,26 @@ pub struct RunOptions {
     pub values: HashMap<String, String>,
     /// If true accept default values where available
     pub accept_defaults: bool,
+    /// If true run the template's post-generation command without asking.
+    /// Otherwise, interactive runs ask before running it, and silent runs
+    /// do not run it.
+    pub accept_hooks: bool,
+    /// Whether to create a new application or add a component to one.
+    pub variant: TemplateVariantInfo,
+}
+
+/// What running a template creates.
+#[derive(Clone, Debug)]
+pub enum TemplateVariantInfo {
+    /// A new application, in the output path.
+    NewApplication,
+    /// A component of an existing application, whose files are generated in
+    /// the output path and whose `[[component]]` entries are appended to the
+    /// manifest of the application.
+    AddComponent {
+        /// The path of the `spin.toml` of the application.
+        manifest_path: PathBuf,
+    },
 }
 
 enum Cancellable<T, E> {
@@ -58,6 +81,34 @@ struct PreparedTemplate {
     files: HashMap<PathBuf, TemplateContent>,
     special_values: HashMap<String, String>,
     parameter_values: HashMap<String, String>,
+    add_component: Option<AddComponent>,
+    target_dir: PathBuf,
+    post_generate: Option<PostGenerate>,
+}
+
+/// A command run in the generated directory once the files are written.
+struct PostGenerate {
+    /// The command, which may contain parameter placeholders.
+    command: String,
+    policy: HookPolicy,
+}
+
+#[derive(Clone, Copy)]
+enum HookPolicy {
+    Run,
+    Confirm,
+    Skip,
+}
+
+/// Where the component generated by a template is added.
+struct AddComponent {
+    /// The generated `spin.toml`, whose components are added.
+    template_manifest: PathBuf,
+    /// The manifest of the application the component is added to.
+    manifest_path: PathBuf,
+    /// The '/'-separated path of the component directory, relative to the
+    /// manifest.
+    relative_dir: String,
 }
 
 enum TemplateContent {
@@ -67,6 +118,11 @@ enum TemplateContent {
 
 struct TemplateOutputs {
     files: HashMap<PathBuf, Vec<u8>>,
+    /// The manifest to update, and its updated content.
+    manifest: Option<(PathBuf, String)>,
+    target_dir: PathBuf,
+    /// The post-generation command, with its placeholders rendered.
+    post_generate: Option<(String, HookPolicy)>,
 }
 
 impl Run {
@@ -86,6 +142,7 @@ impl Run {
             .run_inner(
                 |path| self.check_allow_generate_interactive(path),
                 || self.populate_parameters_interactive(),
+                HookPolicy::Confirm,
             )
             .await;
         let inner = Cancellable::from_result_option(raw_prepared);
@@ -105,6 +162,7 @@ impl Run {
             .run_inner(
                 |path| self.check_allow_generate_silent(path),
                 || self.populate_parameters_silent(),
+                HookPolicy::Skip,
             )
             .await;
         let inner = Cancellable::from_result_option(raw_prepared);
@@ -115,6 +173,7 @@ impl Run {
         &self,
         allow_generate: impl Fn(&Path) -> Cancellable<(), anyhow::Error>,
         populate_parameters: impl Fn() -> anyhow::Result<Option<HashMap<String, String>>>,
+        hook_policy: HookPolicy,
     ) -> anyhow::Result<Option<PreparedTemplate>> {
         // TODO: rationalise `path` and `dir`
         let to = self.target_dir();
@@ -129,8 +188,9 @@ impl Run {
         // nicely with the Rust ? operator - is there a better way?
 
         self.validate_provided_values()?;
+        let add_component = self.add_component_target(to)?;
 
-        let outputs = match self.template.content_dir() {
+        let mut outputs = match self.template.content_dir() {
             None => HashMap::new(),
             Some(path) => {
                 let from = path
@@ -144,14 +204,50 @@ impl Run {
                 Self::to_output_paths(&from, to, template_contents)
             }
         };
+        if let Some(add_component) = &add_component {
+            for skipped in &self.template.add_component().skip_files {
+                outputs.remove(&to.join(skipped));
+            }
+            if !outputs.contains_key(&add_component.template_manifest) {
+                return Err(anyhow!(
+                    "Template {} cannot add a component: it has no {}",
+                    self.template.id(),
+                    SPIN_MANIFEST_FILE_NAME
+                ));
+            }
+        }
 
         match populate_parameters()? {
             Some(parameter_values) => {
-                // let outputs = Self::render_all(output_templates, &parameter_values)?;
+                let special_values = self.special_values().await;
+                let all_values = special_values
+                    .iter()
+                    .chain(&parameter_values)
+                    .map(|(k, v)| (k.clone(), v.clone()))
+                    .collect();
+                for excluded in self.template.excluded_files(&all_values) {
+                    let excluded = to.join(excluded);
+                    outputs.retain(|path, _| !path.starts_with(&excluded));
+                }
+                let post_generate =
+                    self.template
+                        .post_generate()
+                        .as_ref()
+                        .map(|command| PostGenerate {
+                            command: command.clone(),
+                            policy: if self.options.accept_hooks {
+                                HookPolicy::Run
+                            } else {
+                                hook_policy
+                            },
+                        });
                 let prepared_template = PreparedTemplate {
                     files: outputs,
-                    special_values: self.special_values().await,
+                    special_values,
                     parameter_values,
+                    add_component,
+                    target_dir: to.clone(),
+                    post_generate,
                 };
                 Ok(Some(prepared_template))
             }
@@ -174,6 +270,58 @@ impl Run {
         &self.options.output_path
     }
 
+    fn add_component_target(&self, to: &Path) -> anyhow::Result<Option<AddComponent>> {
+        let manifest_path = match &self.options.variant {
+            TemplateVariantInfo::NewApplication => return Ok(None),
+            TemplateVariantInfo::AddComponent { manifest_path } => manifest_path,
+        };
+        let manifest_path = manifest_path
+            .absolutize()
+            .context("Failed to get absolute path of application manifest")?
+            .into_owned();
+        let app_dir = manifest_path.parent().with_context(|| {
+            format!("Can't get directory containing {}", manifest_path.display())
+        })?;
+        let to = to
+            .absolutize()
+            .context("Failed to get absolute path of component directory")?;
+        let relative_dir = pathdiff::diff_paths(&to, app_dir)
+            .with_context(|| {
+                format!(
+                    "Can't get path of {} relative to {}",
+                    to.display(),
+                    app_dir.display()
+                )
+            })?
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy().into_owned())
+            .join("/");
+        Ok(Some(AddComponent {
+            template_manifest: self.target_dir().join(SPIN_MANIFEST_FILE_NAME),
+            manifest_path,
+            relative_dir,
+        }))
+    }
+
+    /// Whether a parameter is left out when adding a component.
+    fn is_skipped(&self, parameter: &TemplateParameter) -> bool {
+        matches!(
+            self.options.variant,
+            TemplateVariantInfo::AddComponent { .. }
+        ) && self
+            .template
+            .add_component()
+            .skip_parameters
+            .iter()
+            .any(|p| p == parameter.id())
+    }
+
+    /// The value of a skipped parameter: it only appears in files that are not
+    /// generated, but must still render.
+    fn skipped_value(parameter: &TemplateParameter) -> String {
+        parameter.default_value().clone().unwrap_or_default()
+    }
+
     fn validate_provided_values(&self) -> anyhow::Result<()> {
         let errors = self
             .options
@@ -271,6 +419,10 @@ impl Run {
     fn populate_parameters_interactive(&self) -> anyhow::Result<Option<HashMap<String, String>>> {
         let mut values = HashMap::new();
         for parameter in self.template.parameters() {
+            if self.is_skipped(parameter) {
+                values.insert(parameter.id().to_owned(), Self::skipped_value(parameter));
+                continue;
+            }
             match self.populate_parameter_interactive(parameter) {
                 Some(v) => {
                     values.insert(parameter.id().to_owned(), v);
@@ -293,19 +445,43 @@ impl Run {
 
     fn populate_parameters_silent(&self) -> anyhow::Result<Option<HashMap<String, String>>> {
         let mut values = HashMap::new();
+        let mut missing = vec![];
         for parameter in self.template.parameters() {
-            let value = self.populate_parameter_silent(parameter)?;
-            values.insert(parameter.id().to_owned(), value);
+            if self.is_skipped(parameter) {
+                values.insert(parameter.id().to_owned(), Self::skipped_value(parameter));
+                continue;
+            }
+            match self.populate_parameter_silent(parameter) {
+                Some(value) => {
+                    values.insert(parameter.id().to_owned(), value);
+                }
+                None => missing.push(parameter),
+            }
         }
-        Ok(Some(values))
+        if missing.is_empty() {
+            return Ok(Some(values));
+        }
+        // All the missing values are reported at once, so that they can be
+        // fixed in one go.
+        let missing_msg = missing
+            .iter()
+            .map(|p| match p.default_value() {
+                Some(default) => format!("- {}: {} (default: {})", p.id(), p.prompt(), default),
+                None => format!("- {}: {}", p.id(), p.prompt()),
+            })
+            .join("\n");
+        Err(anyhow!(
+            "No value was provided for the following template parameter(s):\n{}\nProvide values for them, or accept the defaults of those that have one",
+            missing_msg
+        ))
     }
 
-    fn populate_parameter_silent(&self, parameter: &TemplateParameter) -> anyhow::Result<String> {
+    fn populate_parameter_silent(&self, parameter: &TemplateParameter) -> Option<String> {
         match self.options.values.get(parameter.id()) {
-            Some(s) => Ok(s.clone()),
+            Some(s) => Some(s.clone()),
             None => match (self.options.accept_defaults, parameter.default_value()) {
-                (true, Some(v)) => Ok(v.to_string()),
-                _ => Err(anyhow!("Parameter '{}' not provided", parameter.id())),
+                (true, Some(v)) => Some(v.to_string()),
+                _ => None,
             },
         }
     }
@@ -378,13 +554,57 @@ impl TemplatePreparationResult {
 impl PreparedTemplate {
     fn render_all(self) -> anyhow::Result<TemplateOutputs> {
         let globals = self.renderer_globals();
+        let parser = Run::template_parser();
+        let target_dir = self.target_dir;
         let rendered = self
             .files
             .into_iter()
-            .map(|(path, content)| Self::render_one(path, content, &globals))
+            .map(|(path, content)| {
+                let path = render_path(&parser, &target_dir, path, &globals)?;
+                Self::render_one(path, content, &globals)
+            })
             .collect::<anyhow::Result<Vec<_>>>()?;
-        let outputs = HashMap::from_iter(rendered);
-        Ok(TemplateOutputs { files: outputs })
+        let mut outputs = HashMap::from_iter(rendered);
+        let manifest = match self.add_component {
+            None => None,
+            Some(add) => {
+                // The components of the generated manifest go into the
+                // application manifest, rather than into a file of their own.
+                let template_manifest = outputs
+                    .remove(&add.template_manifest)
+                    .context("Template has no manifest")?;
+                let template_manifest = String::from_utf8(template_manifest)
+                    .context("Template manifest is not valid UTF-8")?;
+                let app_manifest = std::fs::read_to_string(&add.manifest_path)
+                    .with_context(|| format!("Failed to read {}", add.manifest_path.display()))?;
+                let merged = merge_component(&app_manifest, &template_manifest, &add.relative_dir)
+                    .with_context(|| {
+                        format!(
+                            "Failed to add the component to {}",
+                            add.manifest_path.display()
+                        )
+                    })?;
+                Some((add.manifest_path, merged))
+            }
+        };
+        let post_generate = match self.post_generate {
+            None => None,
+            Some(hook) => {
+                let command = parser
+                    .parse(&hook.command)
+                    .and_then(|t| t.render(&globals))
+                    .with_context(|| {
+                        format!("Invalid post-generation command '{}'", hook.command)
+                    })?;
+                Some((command, hook.policy))
+            }
+        };
+        Ok(TemplateOutputs {
+            files: outputs,
+            manifest,
+            target_dir,
+            post_generate,
+        })
     }
 
     fn render_one(
@@ -441,6 +661,29 @@ impl TemplateContent {
     }
 }
 
+/// Renders the placeholders in the path of a generated file, such as
+/// `src/{{project-name | snake_case}}.rs`, relative to the directory it is
+/// generated in.
+fn render_path(
+    parser: &liquid::Parser,
+    target_dir: &Path,
+    path: PathBuf,
+    globals: &liquid::Object,
+) -> anyhow::Result<PathBuf> {
+    let relative = match path.strip_prefix(target_dir) {
+        Ok(relative) => relative.to_string_lossy(),
+        Err(_) => return Ok(path),
+    };
+    if !relative.contains("{{") {
+        return Ok(path);
+    }
+    let rendered = parser
+        .parse(&relative)
+        .and_then(|t| t.render(globals))
+        .with_context(|| format!("Invalid file name template '{}'", relative))?;
+    Ok(target_dir.join(rendered))
+}
+
 fn string_from_bytes(bytes: &[u8]) -> Option<String> {
     match std::str::from_utf8(bytes) {
         Ok(s) => Some(s.to_owned()),
@@ -461,6 +704,174 @@ impl TemplateOutputs {
                 .await
                 .with_context(|| format!("Failed to write file {}", path.display()))?;
         }
+        if let Some((path, contents)) = &self.manifest {
+            tokio::fs::write(&path, &contents)
+                .await
+                .with_context(|| format!("Failed to write file {}", path.display()))?;
+        }
+        if let Some((command, policy)) = &self.post_generate {
+            self.run_post_generate(command, *policy).await?;
+        }
         Ok(())
     }
+
+    async fn run_post_generate(&self, command: &str, policy: HookPolicy) -> anyhow::Result<()> {
+        let dir = &self.target_dir;
+        match policy {
+            HookPolicy::Run => (),
+            HookPolicy::Confirm => {
+                let prompt = format!(
+                    "The template runs `{}` in {} to complete the setup. Run it now?",
+                    command,
+                    dir.display()
+                );
+                if !crate::interaction::confirm(&prompt)? {
+                    return Ok(());
+                }
+            }
+            HookPolicy::Skip => {
+                println!(
+                    "The template did not run `{}`, as it was not confirmed: run it in {} to complete the setup",
+                    command,
+                    dir.display()
+                );
+                return Ok(());
+            }
+        }
+
+        let mut shell = if cfg!(windows) {
+            let mut shell = tokio::process::Command::new("cmd");
+            shell.arg("/C");
+            shell
+        } else {
+            let mut shell = tokio::process::Command::new("sh");
+            shell.arg("-c");
+            shell
+        };
+        let status = shell
+            .arg(command)
+            .current_dir(dir)
+            .status()
+            .await
+            .with_context(|| format!("Failed to run post-generation command `{}`", command))?;
+        if !status.success() {
+            return Err(anyhow!(
+                "The files were generated, but the post-generation command `{}` failed ({})",
+                command,
+                status
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Appends the components of a generated manifest to the manifest of an
+/// application, leaving its existing content as it is.
+///
+/// The paths of the components are relative to the application manifest
+/// rather than to the directory they were generated in, and their build
+/// commands run in that directory.
+fn merge_component(
+    app_manifest: &str,
+    template_manifest: &str,
+    relative_dir: &str,
+) -> anyhow::Result<String> {
+    let app: toml::Value = toml::from_str(app_manifest).context("Invalid application manifest")?;
+    let template: toml::Value =
+        toml::from_str(template_manifest).context("Invalid template manifest")?;
+
+    let trigger_type = |manifest: &toml::Value| {
+        manifest
+            .get("trigger")?
+            .get("type")?
+            .as_str()
+            .map(str::to_owned)
+    };
+    if let (Some(app_trigger), Some(template_trigger)) =
+        (trigger_type(&app), trigger_type(&template))
+    {
+        if app_trigger != template_trigger {
+            return Err(anyhow!(
+                "The template is for {} applications, but the application has a {} trigger",
+                template_trigger,
+                app_trigger
+            ));
+        }
+    }
+
+    let existing_ids = components(&app)
+        .iter()
+        .filter_map(|c| c.get("id")?.as_str())
+        .collect_vec();
+    let mut added = components(&template).to_vec();
+    if added.is_empty() {
+        return Err(anyhow!("The template manifest has no components"));
+    }
+    for component in &mut added {
+        let table = component
+            .as_table_mut()
+            .context("Template component is not a table")?;
+        if let Some(id) = table.get("id").and_then(|id| id.as_str()) {
+            if existing_ids.contains(&id) {
+                return Err(anyhow!(
+                    "The application already has a component with ID {}",
+                    id
+                ));
+            }
+        }
+        relocate_component(table, relative_dir);
+    }
+
+    let mut entries = toml::value::Table::new();
+    entries.insert("component".to_owned(), toml::Value::Array(added));
+    Ok(format!(
+        "{}\n\n{}",
+        app_manifest.trim_end(),
+        toml::to_string(&entries)?
+    ))
+}
+
+fn components(manifest: &toml::Value) -> &[toml::Value] {
+    manifest
+        .get("component")
+        .and_then(|c| c.as_array())
+        .map(|c| c.as_slice())
+        .unwrap_or_default()
+}
+
+/// Makes the paths of a component generated in a directory relative to the
+/// application manifest.
+fn relocate_component(component: &mut toml::value::Table, relative_dir: &str) {
+    if relative_dir.is_empty() {
+        return;
+    }
+    let relocate = |value: &mut toml::Value| {
+        if let toml::Value::String(path) = value {
+            *path = format!("{}/{}", relative_dir, path.trim_start_matches("./"));
+        }
+    };
+    if let Some(source) = component.get_mut("source") {
+        relocate(source);
+    }
+    for key in ["files", "exclude_files"] {
+        if let Some(toml::Value::Array(patterns)) = component.get_mut(key) {
+            for pattern in patterns {
+                match pattern {
+                    toml::Value::Table(mapping) => {
+                        if let Some(source) = mapping.get_mut("source") {
+                            relocate(source);
+                        }
+                    }
+                    pattern => relocate(pattern),
+                }
+            }
+        }
+    }
+    if let Some(toml::Value::Table(build)) = component.get_mut("build") {
+        let workdir = match build.get("workdir").and_then(|w| w.as_str()) {
+            Some(workdir) => format!("{}/{}", relative_dir, workdir),
+            None => relative_dir.to_owned(),
+        };
+        build.insert("workdir".to_owned(), toml::Value::String(workdir));
+    }
 }
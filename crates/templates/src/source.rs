@@ -1,6 +1,7 @@
 use std::path::{Path, PathBuf};
 
 use anyhow::{anyhow, Context};
+use serde::{Deserialize, Serialize};
 use tempfile::{tempdir, TempDir};
 use tokio::process::Command;
 use url::Url;
@@ -30,13 +31,55 @@ pub enum TemplateSource {
 /// Settings for installing templates from a Git repository.
 #[derive(Debug)]
 pub struct GitTemplateSource {
-    /// The URL of the Git repository from which to install templates.
-    url: Url,
+    /// The URL of the Git repository from which to install templates: a URL,
+    /// or an SSH location such as `git@github.com:org/repo.git`.
+    url: String,
     /// The branch or tag from which to install templates; inferred if omitted.
     branch: Option<String>,
     /// The version of the Spin client, used for branch inference.
     // We have to pass this through because vergen is only on the root bin
     spin_version: String,
+    /// The credentials for HTTPS repositories, if any.
+    auth: Option<GitAuth>,
+}
+
+/// A token authenticating to an HTTPS Git repository, such as a personal
+/// access token.
+///
+/// The token is passed to Git in its environment, so that it does not
+/// appear in the command line of Git or in its configuration, and it is
+/// not recorded with the installed templates.
+#[derive(Clone)]
+pub struct GitAuth {
+    /// The user name the token is sent with. Most Git hosts accept any
+    /// name with a token.
+    pub username: String,
+    /// The token.
+    pub token: String,
+}
+
+impl std::fmt::Debug for GitAuth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GitAuth")
+            .field("username", &self.username)
+            .field("token", &"<redacted>")
+            .finish()
+    }
+}
+
+/// Where installed templates came from, recorded so that they can be
+/// upgraded from the same place.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub(crate) enum RecordedSource {
+    Git {
+        url: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        branch: Option<String>,
+    },
+    Dir {
+        path: PathBuf,
+    },
 }
 
 impl TemplateSource {
@@ -47,15 +90,84 @@ impl TemplateSource {
         branch: &Option<String>,
         spin_version: &str,
     ) -> anyhow::Result<Self> {
-        let url_str = git_url.as_ref();
-        let url =
-            Url::parse(url_str).with_context(|| format!("Failed to parse {} as URL", url_str))?;
+        let url = git_url.as_ref();
+        if !is_scp_location(url) {
+            Url::parse(url).with_context(|| format!("Failed to parse {} as URL", url))?;
+        }
         Ok(Self::Git(GitTemplateSource {
-            url,
+            url: url.to_owned(),
             branch: branch.clone(),
             spin_version: spin_version.to_owned(),
+            auth: None,
         }))
     }
+
+    /// Authenticates to the Git repository of the source with a token. This
+    /// has no effect on other sources.
+    pub fn with_git_auth(self, auth: Option<GitAuth>) -> Self {
+        match self {
+            Self::Git(git_source) => Self::Git(GitTemplateSource { auth, ..git_source }),
+            source => source,
+        }
+    }
+
+    /// The source as it is recorded with the templates installed from it.
+    pub(crate) fn record(&self) -> anyhow::Result<RecordedSource> {
+        match self {
+            Self::Git(git_source) => Ok(RecordedSource::Git {
+                url: git_source.url.clone(),
+                branch: git_source.branch.clone(),
+            }),
+            Self::File(path) => Ok(RecordedSource::Dir {
+                path: path
+                    .canonicalize()
+                    .with_context(|| format!("Path not found: {}", path.display()))?,
+            }),
+        }
+    }
+
+    /// The source a record refers to.
+    pub(crate) fn from_record(
+        record: &RecordedSource,
+        spin_version: &str,
+        auth: Option<GitAuth>,
+    ) -> Self {
+        match record {
+            RecordedSource::Git { url, branch } => Self::Git(GitTemplateSource {
+                url: url.clone(),
+                branch: branch.clone(),
+                spin_version: spin_version.to_owned(),
+                auth,
+            }),
+            RecordedSource::Dir { path } => Self::File(path.clone()),
+        }
+    }
+}
+
+impl std::fmt::Display for RecordedSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Git { url, branch: None } => write!(f, "{}", url),
+            Self::Git {
+                url,
+                branch: Some(branch),
+            } => write!(f, "{} ({})", url, branch),
+            Self::Dir { path } => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+/// Whether a Git location is in the SSH form `[user@]host:path`, which Git
+/// accepts besides URLs.
+fn is_scp_location(location: &str) -> bool {
+    match location.split_once(':') {
+        // A colon after a slash is part of a path, and a single letter
+        // before a colon is a Windows drive.
+        Some((host, path)) => {
+            !host.contains('/') && host.len() > 1 && !path.starts_with("//") && !path.is_empty()
+        }
+        None => false,
+    }
 }
 
 pub(crate) struct LocalTemplateSource {
@@ -107,10 +219,10 @@ async fn clone_local(git_source: &GitTemplateSource) -> anyhow::Result<LocalTemp
 
     let actual_branch = match &git_source.branch {
         Some(b) => Some(b.clone()),
-        None => version_matched_tag(url_str, &git_source.spin_version).await,
+        None => version_matched_tag(git_source).await,
     };
 
-    let mut git = Command::new("git");
+    let mut git = git_command(git_source);
     git.arg("clone");
     git.arg("--depth").arg("1");
 
@@ -135,13 +247,31 @@ async fn clone_local(git_source: &GitTemplateSource) -> anyhow::Result<LocalTemp
     }
 }
 
-async fn version_matched_tag(url: &str, spin_version: &str) -> Option<String> {
-    let preferred_tag = version_preferred_tag(spin_version);
-
+/// A Git command for a repository, which fails rather than prompts for
+/// credentials it doesn't have.
+fn git_command(git_source: &GitTemplateSource) -> Command {
     let mut git = Command::new("git");
+    git.env("GIT_TERMINAL_PROMPT", "0");
+    if let Some(auth) = &git_source.auth {
+        let credentials = base64::encode(format!("{}:{}", auth.username, auth.token));
+        // Configuration from the environment requires Git 2.31 or later.
+        git.env("GIT_CONFIG_COUNT", "1")
+            .env("GIT_CONFIG_KEY_0", "http.extraHeader")
+            .env(
+                "GIT_CONFIG_VALUE_0",
+                format!("Authorization: Basic {}", credentials),
+            );
+    }
+    git
+}
+
+async fn version_matched_tag(git_source: &GitTemplateSource) -> Option<String> {
+    let preferred_tag = version_preferred_tag(&git_source.spin_version);
+
+    let mut git = git_command(git_source);
     git.arg("ls-remote");
     git.arg("--exit-code");
-    git.arg(&url);
+    git.arg(&git_source.url);
     git.arg(&preferred_tag);
 
     git.output().await.ok().and_then(|output| {
@@ -176,6 +306,24 @@ async fn check_local(path: &Path) -> anyhow::Result<LocalTemplateSource> {
 mod test {
     use super::*;
 
+    #[test]
+    fn accepts_ssh_locations() {
+        for url in [
+            "git@github.com:fermyon/spin.git",
+            "ssh://git@github.com/fermyon/spin.git",
+            "https://github.com/fermyon/spin",
+        ] {
+            assert!(
+                TemplateSource::try_from_git(url, &None, "0.4.0").is_ok(),
+                "{}",
+                url
+            );
+        }
+        assert!(!is_scp_location("https://github.com/fermyon/spin"));
+        assert!(!is_scp_location("C:/templates"));
+        assert!(TemplateSource::try_from_git("not a repo", &None, "0.4.0").is_err());
+    }
+
     #[test]
     fn preferred_tag_excludes_patch_version() {
         assert_eq!("spin/templates/v1.2", version_preferred_tag("1.2.3"));
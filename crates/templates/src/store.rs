@@ -74,6 +74,7 @@ const METADATA_DIR_NAME: &str = "metadata";
 const CONTENT_DIR_NAME: &str = "content";
 
 const MANIFEST_FILE_NAME: &str = "spin-template.toml";
+const SOURCE_FILE_NAME: &str = "source.toml";
 
 impl TemplateLayout {
     pub fn new(template_dir: impl AsRef<Path>) -> Self {
@@ -93,4 +94,9 @@ impl TemplateLayout {
     pub fn content_dir(&self) -> PathBuf {
         self.template_dir.join(CONTENT_DIR_NAME)
     }
+
+    /// The record of where an installed template was installed from.
+    pub fn source_path(&self) -> PathBuf {
+        self.metadata_dir().join(SOURCE_FILE_NAME)
+    }
 }
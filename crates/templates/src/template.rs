@@ -1,4 +1,4 @@
-use std::path::PathBuf;
+use std::{collections::HashMap, path::PathBuf};
 
 use anyhow::{anyhow, Context};
 use indexmap::IndexMap;
@@ -6,7 +6,7 @@ use regex::Regex;
 
 use crate::{
     constraints::StringConstraints,
-    reader::{RawParameter, RawTemplateManifest},
+    reader::{RawConditional, RawParameter, RawTemplateManifest},
     run::{Run, RunOptions},
     store::TemplateLayout,
 };
@@ -18,6 +18,30 @@ pub struct Template {
     description: Option<String>,
     parameters: Vec<TemplateParameter>,
     content_dir: Option<PathBuf>, // TODO: maybe always need a spin.toml file in there?
+    add_component: AddComponentInfo,
+    conditionals: Vec<Conditional>,
+    post_generate: Option<String>,
+}
+
+/// How a template is used to add a component to an existing application.
+#[derive(Debug, Default)]
+pub(crate) struct AddComponentInfo {
+    /// Content files that are only generated for new applications.
+    pub skip_files: Vec<String>,
+    /// Parameters that only apply to new applications, and are not prompted
+    /// for when adding a component.
+    pub skip_parameters: Vec<String>,
+}
+
+/// Content files that are only generated when a parameter has a given value.
+#[derive(Debug)]
+pub(crate) struct Conditional {
+    /// The parameter the files depend on.
+    pub parameter: String,
+    /// The value the parameter must have for the files to be generated.
+    pub equals: String,
+    /// The files, or directories of files, relative to the content directory.
+    pub files: Vec<String>,
 }
 
 #[derive(Clone, Debug)]
@@ -57,12 +81,25 @@ impl Template {
         };
 
         let template = match raw {
-            RawTemplateManifest::V1(raw) => Self {
-                id: raw.id.clone(),
-                description: raw.description.clone(),
-                parameters: Self::parse_parameters(&raw.parameters)?,
-                content_dir,
-            },
+            RawTemplateManifest::V1(raw) => {
+                let parameters = Self::parse_parameters(&raw.parameters)?;
+                let conditionals = Self::parse_conditionals(raw.conditional, &parameters)?;
+                Self {
+                    id: raw.id.clone(),
+                    description: raw.description.clone(),
+                    parameters,
+                    content_dir,
+                    add_component: raw
+                        .add_component
+                        .map(|add| AddComponentInfo {
+                            skip_files: add.skip_files.unwrap_or_default(),
+                            skip_parameters: add.skip_parameters.unwrap_or_default(),
+                        })
+                        .unwrap_or_default(),
+                    conditionals,
+                    post_generate: raw.post_generate.map(|hook| hook.command),
+                }
+            }
         };
         Ok(template)
     }
@@ -101,6 +138,26 @@ impl Template {
         &self.content_dir
     }
 
+    pub(crate) fn add_component(&self) -> &AddComponentInfo {
+        &self.add_component
+    }
+
+    /// The content files that are not generated with the given parameter
+    /// values.
+    pub(crate) fn excluded_files(&self, values: &HashMap<String, String>) -> Vec<&str> {
+        self.conditionals
+            .iter()
+            .filter(|c| values.get(&c.parameter) != Some(&c.equals))
+            .flat_map(|c| c.files.iter().map(|f| f.as_str()))
+            .collect()
+    }
+
+    /// The command to run in the generated directory once the files are
+    /// written, if any. It may contain parameter placeholders.
+    pub(crate) fn post_generate(&self) -> &Option<String> {
+        &self.post_generate
+    }
+
     /// Creates a runner for the template, governed by the given options. Call
     /// the relevant associated function of the `Run` to execute the template
     /// as appropriate to your application (e.g. `interactive()` to prompt the user
@@ -120,6 +177,31 @@ impl Template {
                 .collect(),
         }
     }
+
+    fn parse_conditionals(
+        raw: Option<Vec<RawConditional>>,
+        parameters: &[TemplateParameter],
+    ) -> anyhow::Result<Vec<Conditional>> {
+        raw.unwrap_or_default()
+            .into_iter()
+            .map(|c| {
+                let parameter = c.condition.parameter;
+                // Conditions can also depend on values every template has,
+                // such as the project name.
+                if parameter != "project-name" && !parameters.iter().any(|p| p.id == parameter) {
+                    return Err(anyhow!(
+                        "Condition refers to parameter '{}', which the template does not have",
+                        parameter
+                    ));
+                }
+                Ok(Conditional {
+                    parameter,
+                    equals: c.condition.equals,
+                    files: c.files,
+                })
+            })
+            .collect()
+    }
 }
 
 impl TemplateParameter {
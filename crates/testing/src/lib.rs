@@ -12,7 +12,8 @@ use spin_engine::{Builder, ExecutionContextConfiguration};
 use spin_http_engine::HttpTrigger;
 use spin_manifest::{
     Application, ApplicationInformation, ApplicationOrigin, ApplicationTrigger, CoreComponent,
-    HttpConfig, ModuleSource, RedisConfig, RedisTriggerConfiguration, SpinVersion, TriggerConfig,
+    HttpConfig, ModuleSource, QueueBroker, QueueConfig, QueueTriggerConfiguration, RedisConfig,
+    RedisTriggerConfiguration, SpinVersion, TriggerConfig,
 };
 use spin_trigger::TriggerExecutorBuilder;
 
@@ -51,6 +52,16 @@ impl TestConfig {
         self
     }
 
+    pub fn queue_trigger(&mut self, config: QueueConfig) -> &mut Self {
+        self.application_trigger = Some(ApplicationTrigger::Queue(QueueTriggerConfiguration {
+            broker: QueueBroker::Nats,
+            address: "nats://localhost:4222".to_owned(),
+            consumer_group: None,
+        }));
+        self.trigger_config = Some(TriggerConfig::Queue(config));
+        self
+    }
+
     pub fn build_application_information(&self) -> ApplicationInformation {
         ApplicationInformation {
             spin_version: SpinVersion::V1,
@@ -98,7 +109,10 @@ impl TestConfig {
         }
     }
 
-    pub async fn prepare_builder<T: Default + 'static>(&self, app: Application) -> Builder<T> {
+    pub async fn prepare_builder<T: Default + Send + 'static>(
+        &self,
+        app: Application,
+    ) -> Builder<T> {
         let config = ExecutionContextConfiguration {
             components: app.components,
             label: app.info.name,
@@ -0,0 +1,291 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Result};
+use serde::Deserialize;
+
+use crate::Overloaded;
+
+/// Number of latency samples after which the baseline latency of a
+/// component is measured afresh, so that it follows lasting changes.
+const BASELINE_SAMPLES: u32 = 1000;
+
+/// Runtime configuration for adaptive concurrency limits.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub struct AdaptiveConfig {
+    /// The components whose concurrency is limited. If empty, all
+    /// components are.
+    #[serde(default)]
+    pub components: Vec<String>,
+    /// The limit of each component before any request completes.
+    #[serde(default = "default_initial_limit")]
+    pub initial_limit: usize,
+    /// The lowest limit of each component.
+    #[serde(default = "default_min_limit")]
+    pub min_limit: usize,
+    /// The highest limit of each component.
+    #[serde(default = "default_max_limit")]
+    pub max_limit: usize,
+    /// The latency, in milliseconds, above which a component is considered
+    /// overloaded. If not set, a component is considered overloaded when its
+    /// latency exceeds `tolerance` times the lowest latency observed.
+    pub target_latency_ms: Option<u64>,
+    /// The ratio to the lowest observed latency above which a component is
+    /// considered overloaded, if `target_latency_ms` is not set.
+    #[serde(default = "default_tolerance")]
+    pub tolerance: f64,
+    /// The factor the limit is multiplied by when a component is overloaded.
+    #[serde(default = "default_backoff")]
+    pub backoff: f64,
+}
+
+impl Default for AdaptiveConfig {
+    fn default() -> Self {
+        Self {
+            components: vec![],
+            initial_limit: default_initial_limit(),
+            min_limit: default_min_limit(),
+            max_limit: default_max_limit(),
+            target_latency_ms: None,
+            tolerance: default_tolerance(),
+            backoff: default_backoff(),
+        }
+    }
+}
+
+fn default_initial_limit() -> usize {
+    16
+}
+
+fn default_min_limit() -> usize {
+    1
+}
+
+fn default_max_limit() -> usize {
+    512
+}
+
+fn default_tolerance() -> f64 {
+    2.0
+}
+
+fn default_backoff() -> f64 {
+    0.9
+}
+
+/// The limit and usage of a component's concurrency.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LimitStats {
+    /// Requests the component may execute at once.
+    pub limit: usize,
+    /// Requests the component is executing.
+    pub in_flight: usize,
+    /// Requests rejected because the component was at its limit.
+    pub rejected: u64,
+}
+
+/// Limits the requests each component executes at once, adjusting the
+/// limit from the latency of completed requests: it increases additively
+/// while latency stays low, and decreases multiplicatively when latency
+/// rises, so that components back off before overloading the resources
+/// they depend on. Requests beyond the limit are rejected immediately.
+pub struct AdaptiveLimiter {
+    config: AdaptiveConfig,
+    state: Mutex<HashMap<String, ComponentLimit>>,
+}
+
+struct ComponentLimit {
+    limit: f64,
+    in_flight: usize,
+    rejected: u64,
+    baseline: Option<Duration>,
+    samples: u32,
+}
+
+impl AdaptiveLimiter {
+    /// Creates a limiter, or returns `None` if limits are not adaptive.
+    pub fn new(config: Option<&AdaptiveConfig>) -> Result<Option<Arc<Self>>> {
+        let config = match config {
+            Some(config) => config.clone(),
+            None => return Ok(None),
+        };
+        if config.min_limit == 0 || config.min_limit > config.max_limit {
+            bail!("adaptive min_limit must be greater than zero and at most max_limit");
+        }
+        if !(config.min_limit..=config.max_limit).contains(&config.initial_limit) {
+            bail!("adaptive initial_limit must be between min_limit and max_limit");
+        }
+        if config.tolerance <= 1.0 {
+            bail!("adaptive tolerance must be greater than 1");
+        }
+        if !(config.backoff > 0.0 && config.backoff < 1.0) {
+            bail!("adaptive backoff must be between 0 and 1");
+        }
+        Ok(Some(Arc::new(Self {
+            config,
+            state: Default::default(),
+        })))
+    }
+
+    /// Admits a request to the component, if it is below its limit. The
+    /// latency of the request is measured until the permit is dropped.
+    /// Fails with `Overloaded` if the component is at its limit.
+    pub fn admit(self: &Arc<Self>, component: &str) -> Result<Option<AdaptivePermit>> {
+        if !self.config.components.is_empty()
+            && !self.config.components.iter().any(|c| c == component)
+        {
+            return Ok(None);
+        }
+        let mut state = self.state.lock().unwrap();
+        let limit = state
+            .entry(component.to_string())
+            .or_insert_with(|| ComponentLimit {
+                limit: self.config.initial_limit as f64,
+                in_flight: 0,
+                rejected: 0,
+                baseline: None,
+                samples: 0,
+            });
+        if limit.in_flight >= limit.limit as usize {
+            limit.rejected += 1;
+            return Err(Overloaded.into());
+        }
+        limit.in_flight += 1;
+        Ok(Some(AdaptivePermit {
+            limiter: self.clone(),
+            component: component.to_string(),
+            started: Instant::now(),
+            in_flight: limit.in_flight,
+        }))
+    }
+
+    /// Returns the limits of the components that have received requests.
+    pub fn stats(&self) -> BTreeMap<String, LimitStats> {
+        self.state
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(component, limit)| {
+                let stats = LimitStats {
+                    limit: limit.limit as usize,
+                    in_flight: limit.in_flight,
+                    rejected: limit.rejected,
+                };
+                (component.clone(), stats)
+            })
+            .collect()
+    }
+
+    fn complete(&self, component: &str, latency: Duration, in_flight: usize) {
+        let mut state = self.state.lock().unwrap();
+        let limit = match state.get_mut(component) {
+            Some(limit) => limit,
+            None => return,
+        };
+        limit.in_flight -= 1;
+
+        limit.samples += 1;
+        if limit.samples >= BASELINE_SAMPLES {
+            limit.samples = 0;
+            limit.baseline = None;
+        }
+        let baseline = *limit.baseline.get_or_insert(latency);
+        limit.baseline = Some(baseline.min(latency));
+
+        let overloaded = match self.config.target_latency_ms {
+            Some(target) => latency > Duration::from_millis(target),
+            None => latency.as_secs_f64() > baseline.as_secs_f64() * self.config.tolerance,
+        };
+        let (min, max) = (self.config.min_limit as f64, self.config.max_limit as f64);
+        if overloaded {
+            limit.limit = (limit.limit * self.config.backoff).max(min);
+        } else if in_flight as f64 * 2.0 >= limit.limit {
+            // Only grow a limit the component is making use of.
+            limit.limit = (limit.limit + 1.0 / limit.limit).min(max);
+        }
+    }
+}
+
+/// A request executing within its component's adaptive limit.
+pub struct AdaptivePermit {
+    limiter: Arc<AdaptiveLimiter>,
+    component: String,
+    started: Instant,
+    /// The requests the component was executing when this one started.
+    in_flight: usize,
+}
+
+impl Drop for AdaptivePermit {
+    fn drop(&mut self) {
+        self.limiter
+            .complete(&self.component, self.started.elapsed(), self.in_flight);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(initial_limit: usize) -> Arc<AdaptiveLimiter> {
+        let config = AdaptiveConfig {
+            initial_limit,
+            min_limit: 1,
+            target_latency_ms: Some(100),
+            ..Default::default()
+        };
+        AdaptiveLimiter::new(Some(&config)).unwrap().unwrap()
+    }
+
+    #[test]
+    fn test_requests_beyond_the_limit_are_rejected() {
+        let limiter = limiter(2);
+        let _a1 = limiter.admit("a").unwrap();
+        let _a2 = limiter.admit("a").unwrap();
+        let err = limiter.admit("a").err().unwrap();
+        assert!(err.is::<Overloaded>());
+        assert!(limiter.admit("b").is_ok());
+
+        let stats = limiter.stats();
+        assert_eq!(stats["a"].in_flight, 2);
+        assert_eq!(stats["a"].rejected, 1);
+    }
+
+    fn complete(limiter: &Arc<AdaptiveLimiter>, latency_ms: u64, in_flight: usize) {
+        let mut permit = limiter.admit("a").unwrap().unwrap();
+        permit.started -= Duration::from_millis(latency_ms);
+        permit.in_flight = in_flight;
+    }
+
+    #[test]
+    fn test_limit_follows_latency() {
+        let limiter = limiter(10);
+        for _ in 0..20 {
+            complete(&limiter, 10, 10);
+        }
+        let grown = limiter.stats()["a"].limit;
+        assert!(grown > 10);
+
+        for _ in 0..5 {
+            complete(&limiter, 500, 1);
+        }
+        assert!(limiter.stats()["a"].limit < grown);
+        assert_eq!(limiter.stats()["a"].in_flight, 0);
+    }
+
+    #[test]
+    fn test_limits_apply_to_listed_components() {
+        let config = AdaptiveConfig {
+            components: vec!["db-writer".to_string()],
+            initial_limit: 1,
+            ..Default::default()
+        };
+        let limiter = AdaptiveLimiter::new(Some(&config)).unwrap().unwrap();
+        assert!(limiter.admit("db-writer").unwrap().is_some());
+        assert!(limiter.admit("db-writer").is_err());
+        assert!(limiter.admit("static").unwrap().is_none());
+    }
+}
@@ -0,0 +1,353 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use http::{header::CONTENT_TYPE, Method, Request, Response, StatusCode};
+use hyper::{
+    service::{make_service_fn, service_fn},
+    Body, Server,
+};
+use serde::Deserialize;
+use spin_engine::{InvocationMeter, InvocationStats, ServiceHealth, DURATION_BUCKETS};
+use tracing::log;
+
+/// How long CPU profiles run, unless requested otherwise.
+const DEFAULT_PROFILE_SECONDS: u64 = 30;
+/// The longest CPU profile that can be requested.
+const MAX_PROFILE_SECONDS: u64 = 300;
+/// How often CPU profiles sample the stacks of the process.
+const PROFILE_FREQUENCY: i32 = 100;
+
+/// Only one CPU profile can run at a time in a process.
+static PROFILING: AtomicBool = AtomicBool::new(false);
+
+/// Runtime configuration for the admin listener, serving operators rather
+/// than the application's clients.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub struct AdminConfig {
+    /// The address the admin listener binds to. It should not be reachable
+    /// by the application's clients.
+    pub listen: SocketAddr,
+    /// Whether to serve the profiling endpoints of the host process.
+    #[serde(default)]
+    pub profiling: bool,
+}
+
+/// What the admin listener reports on.
+#[derive(Clone, Default)]
+pub(crate) struct AdminState {
+    /// The health of the host services.
+    pub health: Arc<ServiceHealth>,
+    /// Set once the components are loaded and the trigger is built.
+    pub ready: Arc<AtomicBool>,
+    /// The invocations of components.
+    pub invocations: Arc<InvocationMeter>,
+}
+
+/// Binds the admin listener, returning the future serving it.
+pub(crate) fn serve(
+    config: &AdminConfig,
+    state: AdminState,
+) -> Result<impl std::future::Future<Output = ()>> {
+    let profiling = config.profiling;
+    let make_service = make_service_fn(move |_| {
+        let state = state.clone();
+        async move {
+            Ok::<_, hyper::Error>(service_fn(move |req| {
+                let state = state.clone();
+                async move {
+                    Ok::<_, hyper::Error>(handle(req, profiling, &state).await.unwrap_or_else(
+                        |e| {
+                            log::error!("Admin request failed: {:?}", e);
+                            text(StatusCode::INTERNAL_SERVER_ERROR, format!("{:#}\n", e))
+                        },
+                    ))
+                }
+            }))
+        }
+    });
+    let server = Server::try_bind(&config.listen)
+        .with_context(|| format!("Cannot bind the admin listener to {}", config.listen))?
+        .serve(make_service);
+    log::info!("Serving the admin listener on {}", config.listen);
+    Ok(async move {
+        if let Err(e) = server.await {
+            log::error!("Admin listener failed: {:?}", e);
+        }
+    })
+}
+
+async fn handle(req: Request<Body>, profiling: bool, state: &AdminState) -> Result<Response<Body>> {
+    if req.method() != Method::GET {
+        return Ok(text(StatusCode::METHOD_NOT_ALLOWED, ""));
+    }
+    match req.uri().path() {
+        "/healthz" | "/.well-known/spin/health" => Ok(text(StatusCode::OK, state.health.report())),
+        "/.well-known/spin/ready" => match state.ready.load(Ordering::Acquire) {
+            true => Ok(text(StatusCode::OK, "READY\n")),
+            false => Ok(text(StatusCode::SERVICE_UNAVAILABLE, "NOT READY\n")),
+        },
+        "/.well-known/spin/metrics" => Ok(Response::builder()
+            .header(CONTENT_TYPE, "text/plain; version=0.0.4")
+            .body(Body::from(metrics(&state.invocations.stats())))?),
+        "/debug/pprof/profile" if profiling => {
+            let seconds = match profile_seconds(req.uri().query()) {
+                Ok(seconds) => seconds,
+                Err(e) => return Ok(text(StatusCode::BAD_REQUEST, format!("{:#}\n", e))),
+            };
+            cpu_profile(Duration::from_secs(seconds)).await
+        }
+        "/debug/heap" if profiling => match memory_stats() {
+            Ok(stats) => Ok(text(StatusCode::OK, stats)),
+            Err(e) => Ok(text(StatusCode::NOT_IMPLEMENTED, format!("{:#}\n", e))),
+        },
+        "/debug/tasks" if profiling => Ok(text(StatusCode::OK, tasks())),
+        _ => Ok(text(StatusCode::NOT_FOUND, "")),
+    }
+}
+
+/// The duration of the CPU profile requested by the `seconds` parameter.
+fn profile_seconds(query: Option<&str>) -> Result<u64> {
+    let seconds = query
+        .unwrap_or_default()
+        .split('&')
+        .find_map(|param| param.strip_prefix("seconds="));
+    let seconds = match seconds {
+        Some(seconds) => seconds
+            .parse()
+            .with_context(|| format!("Invalid profile duration {:?}", seconds))?,
+        None => DEFAULT_PROFILE_SECONDS,
+    };
+    if seconds == 0 || seconds > MAX_PROFILE_SECONDS {
+        anyhow::bail!(
+            "Profile duration must be between 1 and {} seconds",
+            MAX_PROFILE_SECONDS
+        );
+    }
+    Ok(seconds)
+}
+
+/// Samples the stacks of the process for the duration, returning the profile
+/// in the pprof protobuf format.
+async fn cpu_profile(duration: Duration) -> Result<Response<Body>> {
+    if PROFILING.swap(true, Ordering::AcqRel) {
+        return Ok(text(
+            StatusCode::CONFLICT,
+            "A CPU profile is already running\n",
+        ));
+    }
+    let profile = tokio::task::spawn_blocking(move || -> Result<Vec<u8>> {
+        use pprof::protos::Message;
+
+        let guard = pprof::ProfilerGuardBuilder::default()
+            .frequency(PROFILE_FREQUENCY)
+            .blocklist(&["libc", "libgcc", "pthread", "vdso"])
+            .build()
+            .context("Cannot start the CPU profiler")?;
+        std::thread::sleep(duration);
+        let profile = guard
+            .report()
+            .build()
+            .context("Cannot build the CPU profile")?
+            .pprof()
+            .context("Cannot encode the CPU profile")?;
+        let mut body = Vec::new();
+        profile.encode(&mut body)?;
+        Ok(body)
+    })
+    .await;
+    PROFILING.store(false, Ordering::Release);
+
+    Ok(Response::builder()
+        .header(CONTENT_TYPE, "application/octet-stream")
+        .body(Body::from(profile??))?)
+}
+
+/// The memory use of the process, from procfs.
+fn memory_stats() -> Result<String> {
+    let status = std::fs::read_to_string("/proc/self/status")
+        .context("Memory statistics are only available on Linux")?;
+    Ok(parse_memory_stats(&status))
+}
+
+fn parse_memory_stats(status: &str) -> String {
+    const FIELDS: &[&str] = &[
+        "VmPeak", "VmSize", "VmHWM", "VmRSS", "RssAnon", "RssFile", "VmData", "VmSwap",
+    ];
+    let mut out = String::new();
+    for line in status.lines() {
+        if let Some((name, value)) = line.split_once(':') {
+            if FIELDS.contains(&name) {
+                writeln!(out, "{}: {}", name, value.trim()).unwrap();
+            }
+        }
+    }
+    out
+}
+
+/// The tasks running on behalf of component invocations, oldest first.
+fn tasks() -> String {
+    let tasks = spin_engine::task_dump();
+    let mut out = String::new();
+    writeln!(out, "{} tasks", tasks.len()).unwrap();
+    for task in tasks {
+        writeln!(
+            out,
+            "{}\t{}\t{}\t{:.3}s",
+            task.component,
+            task.name,
+            if task.blocking { "blocking" } else { "async" },
+            task.age.as_secs_f64()
+        )
+        .unwrap();
+    }
+    out
+}
+
+/// The invocations of each component, in the Prometheus text format.
+fn metrics(stats: &BTreeMap<String, InvocationStats>) -> String {
+    let mut out = String::new();
+    let mut header = |name: &str, help: &str, kind: &str| {
+        writeln!(out, "# HELP {} {}", name, help).unwrap();
+        writeln!(out, "# TYPE {} {}", name, kind).unwrap();
+    };
+    header(
+        "spin_component_invocations_total",
+        "Invocations of components, such as HTTP requests, by component.",
+        "counter",
+    );
+    header(
+        "spin_component_invocation_failures_total",
+        "Invocations that failed, by component.",
+        "counter",
+    );
+    header(
+        "spin_component_invocation_duration_seconds",
+        "The duration of invocations, by component.",
+        "histogram",
+    );
+    for (component, stats) in stats {
+        let label = format!("component=\"{}\"", component);
+        writeln!(
+            out,
+            "spin_component_invocations_total{{{}}} {}",
+            label, stats.invocations
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "spin_component_invocation_failures_total{{{}}} {}",
+            label, stats.failures
+        )
+        .unwrap();
+        for (bound, count) in DURATION_BUCKETS.iter().zip(stats.duration_buckets) {
+            writeln!(
+                out,
+                "spin_component_invocation_duration_seconds_bucket{{{},le=\"{}\"}} {}",
+                label, bound, count
+            )
+            .unwrap();
+        }
+        writeln!(
+            out,
+            "spin_component_invocation_duration_seconds_bucket{{{},le=\"+Inf\"}} {}",
+            label, stats.invocations
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "spin_component_invocation_duration_seconds_sum{{{}}} {}",
+            label, stats.duration_seconds
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "spin_component_invocation_duration_seconds_count{{{}}} {}",
+            label, stats.invocations
+        )
+        .unwrap();
+    }
+    out
+}
+
+fn text(status: StatusCode, body: impl Into<String>) -> Response<Body> {
+    let mut res = Response::new(Body::from(body.into()));
+    *res.status_mut() = status;
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profile_seconds() {
+        assert_eq!(profile_seconds(None).unwrap(), DEFAULT_PROFILE_SECONDS);
+        assert_eq!(profile_seconds(Some("debug=1&seconds=5")).unwrap(), 5);
+        assert!(profile_seconds(Some("seconds=0")).is_err());
+        assert!(profile_seconds(Some("seconds=3600")).is_err());
+        assert!(profile_seconds(Some("seconds=soon")).is_err());
+    }
+
+    #[test]
+    fn test_parse_memory_stats() {
+        let status = "Name:\tspin\nVmPeak:\t  204800 kB\nVmRSS:\t   51200 kB\nThreads:\t12\n";
+        assert_eq!(
+            parse_memory_stats(status),
+            "VmPeak: 204800 kB\nVmRSS: 51200 kB\n"
+        );
+    }
+
+    fn get(path: &str) -> Request<Body> {
+        Request::get(path).body(Body::empty()).unwrap()
+    }
+
+    #[test]
+    fn test_profiling_disabled() {
+        let state = AdminState::default();
+        let res = futures::executor::block_on(handle(get("/debug/tasks"), false, &state)).unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        let res = futures::executor::block_on(handle(get("/debug/tasks"), true, &state)).unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn test_readiness() {
+        let state = AdminState::default();
+        let ready = || {
+            futures::executor::block_on(handle(get("/.well-known/spin/ready"), false, &state))
+                .unwrap()
+                .status()
+        };
+        assert_eq!(ready(), StatusCode::SERVICE_UNAVAILABLE);
+        state.ready.store(true, Ordering::Release);
+        assert_eq!(ready(), StatusCode::OK);
+    }
+
+    #[test]
+    fn test_metrics() {
+        let meter = InvocationMeter::default();
+        meter.record("hello", Duration::from_millis(200), false);
+        meter.record("hello", Duration::from_secs(20), true);
+        let metrics = metrics(&meter.stats());
+        assert!(metrics.contains("spin_component_invocations_total{component=\"hello\"} 2\n"));
+        assert!(
+            metrics.contains("spin_component_invocation_failures_total{component=\"hello\"} 1\n")
+        );
+        assert!(metrics.contains(
+            "spin_component_invocation_duration_seconds_bucket{component=\"hello\",le=\"0.25\"} 1\n"
+        ));
+        assert!(metrics.contains(
+            "spin_component_invocation_duration_seconds_bucket{component=\"hello\",le=\"+Inf\"} 2\n"
+        ));
+    }
+}
@@ -1,16 +1,35 @@
-use std::{error::Error, path::PathBuf, sync::Arc};
+use std::{
+    error::Error,
+    path::PathBuf,
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
 
 use anyhow::{bail, Context, Result};
 use clap::{Args, IntoApp, Parser};
-use spin_engine::io::FollowComponents;
-use spin_loader::bindle::BindleConnectionInfo;
+use futures::future::Either;
+use spin_engine::{io::FollowComponents, logs::LogFormat};
+use spin_loader::{
+    bindle::{cache as parcel_cache, BindleConnectionInfo, ParcelCache, SignaturePolicy},
+    env_file,
+};
 use spin_manifest::{Application, ApplicationTrigger, TriggerConfig};
 
-use crate::{TriggerExecutor, TriggerExecutorBuilder};
+use crate::{
+    telemetry::TelemetryOpts, RuntimeConfig, ShutdownSignal, TriggerExecutor,
+    TriggerExecutorBuilder, DEFAULT_SHUTDOWN_TIMEOUT,
+};
 
+pub const ADMIN_PORT: &str = "SPIN_ADMIN_PORT";
 pub const APP_LOG_DIR: &str = "APP_LOG_DIR";
+pub const APP_LOG_FORMAT: &str = "SPIN_LOG_FORMAT";
 pub const DISABLE_WASMTIME_CACHE: &str = "DISABLE_WASMTIME_CACHE";
 pub const FOLLOW_LOG_OPT: &str = "FOLLOW_ID";
+pub const FROM_LOCK: &str = "SPIN_LOCK_FILE";
+pub const MAX_CONCURRENCY: &str = "SPIN_MAX_CONCURRENCY";
+pub const RUNTIME_CONFIG_FILE: &str = "RUNTIME_CONFIG_FILE";
+pub const RUNTIME_CONFIG_KEY: &str = "RUNTIME_CONFIG_KEY";
+pub const SHUTDOWN_TIMEOUT: &str = "SPIN_SHUTDOWN_TIMEOUT";
 pub const WASMTIME_CACHE_FILE: &str = "WASMTIME_CACHE_FILE";
 
 /// A command that runs a TriggerExecutor.
@@ -20,10 +39,21 @@ pub struct TriggerExecutorCommand<Executor: TriggerExecutor>
 where
     Executor::RunConfig: Args,
 {
-    /// Pass an environment variable (key=value) to all components of the application.
+    /// Pass an environment variable (key=value) to all components of the application,
+    /// overriding the value of those that declare it.
     #[clap(long = "env", short = 'e', parse(try_from_str = parse_env_var))]
     pub env: Vec<(String, String)>,
 
+    /// File of environment variables for the components, in the dotenv
+    /// format. May be repeated, later files overriding earlier ones. Variables
+    /// passed with --env take precedence.
+    #[clap(long = "env-file", value_name = "PATH")]
+    pub env_files: Vec<PathBuf>,
+
+    /// Print the environment variables the given component will see, and exit.
+    #[clap(long = "show-env", value_name = "COMPONENT")]
+    pub show_env: Option<String>,
+
     /// Log directory for the stdout and stderr of components.
     #[clap(
             name = APP_LOG_DIR,
@@ -32,6 +62,17 @@ where
             )]
     pub log: Option<PathBuf>,
 
+    /// The format of the stdout and stderr of components, in log files and
+    /// when followed: text, or json for a JSON object per line.
+    #[clap(
+        name = APP_LOG_FORMAT,
+        long = "log-format",
+        env = APP_LOG_FORMAT,
+        default_value = "text",
+        possible_values = ["text", "json"],
+    )]
+    pub log_format: LogFormat,
+
     /// Disable Wasmtime cache.
     #[clap(
         name = DISABLE_WASMTIME_CACHE,
@@ -66,6 +107,73 @@ where
         )]
     pub follow_all_components: bool,
 
+    /// Configuration file for host services such as blob storage.
+    #[clap(
+        name = RUNTIME_CONFIG_FILE,
+        long = "runtime-config-file",
+        env = RUNTIME_CONFIG_FILE,
+    )]
+    pub runtime_config_file: Option<PathBuf>,
+
+    /// Public key trusted to sign the runtime config file, as printed by
+    /// `spin signing-key list`. If set, the runtime config file is only
+    /// applied if its signature, in the file of the same name with `.sig`
+    /// appended, was made with one of these keys.
+    #[clap(
+        name = RUNTIME_CONFIG_KEY,
+        long = "runtime-config-key",
+        env = RUNTIME_CONFIG_KEY,
+        multiple_occurrences = true,
+        requires = RUNTIME_CONFIG_FILE,
+    )]
+    pub runtime_config_keys: Vec<String>,
+
+    /// Run the application written to the given lock file by `spin up
+    /// --write-lock`, without loading its manifest.
+    #[clap(
+        name = FROM_LOCK,
+        long = "from-lock",
+        env = FROM_LOCK,
+    )]
+    pub from_lock: Option<PathBuf>,
+
+    /// Reload the module of a component when its file changes, without
+    /// restarting the application. Requests in flight complete with the
+    /// previous module.
+    #[clap(long = "hot-reload", conflicts_with = FROM_LOCK)]
+    pub hot_reload: bool,
+
+    /// Compile the modules of the components into the compiled module cache,
+    /// and exit without running the application.
+    #[clap(long = "precompile", conflicts_with = DISABLE_WASMTIME_CACHE)]
+    pub precompile: bool,
+
+    /// How long, in seconds, work in flight may take to complete once
+    /// shutdown is requested, before it is aborted and the trigger exits
+    /// with an error. Shutdown functions of components run afterwards.
+    #[clap(
+        name = SHUTDOWN_TIMEOUT,
+        long = "shutdown-timeout",
+        env = SHUTDOWN_TIMEOUT,
+        default_value_t = DEFAULT_SHUTDOWN_TIMEOUT.as_secs(),
+    )]
+    pub shutdown_timeout: u64,
+
+    /// The maximum number of component instances running at once. Instances
+    /// are allocated from a pool of this size, and invocations past it wait
+    /// for a free instance. Overrides `instance_pool.max_instances` in the
+    /// runtime configuration.
+    #[clap(name = MAX_CONCURRENCY, long = "max-concurrency", env = MAX_CONCURRENCY)]
+    pub max_concurrency: Option<u32>,
+
+    /// Serve the admin listener, with the health, readiness and metrics
+    /// endpoints, on this port of all interfaces.
+    #[clap(name = ADMIN_PORT, long = "admin-port", env = ADMIN_PORT)]
+    pub admin_port: Option<u16>,
+
+    #[clap(flatten)]
+    pub telemetry: TelemetryOpts,
+
     #[clap(flatten)]
     pub run_config: Executor::RunConfig,
 
@@ -89,6 +197,15 @@ where
 {
     /// Create a new TriggerExecutorBuilder from this TriggerExecutorCommand.
     pub async fn run(self) -> Result<()> {
+        self.run_with(|_| Ok(())).await
+    }
+
+    /// Run the trigger, letting the given function configure the
+    /// TriggerExecutorBuilder first, for example to add host components.
+    pub async fn run_with(
+        self,
+        configure: impl FnOnce(&mut TriggerExecutorBuilder<Executor>) -> Result<()>,
+    ) -> Result<()> {
         if self.help_args_only {
             Self::command()
                 .disable_help_flag(true)
@@ -98,32 +215,85 @@ where
         }
 
         let app = self.build_application().await?;
+        if let Some(component) = &self.show_env {
+            return show_env(&app, component);
+        }
         let mut builder = TriggerExecutorBuilder::new(app);
         self.update_wasmtime_config(builder.wasmtime_config_mut())?;
+        if !self.disable_cache {
+            if let Some(dir) = spin_engine::module_cache::default_dir() {
+                builder.module_cache(spin_engine::module_cache::ModuleCacheDir::new(dir));
+            }
+        }
         builder.follow_components(self.follow_components());
         if let Some(log_dir) = self.log {
             builder.log_dir(log_dir);
         }
+        builder.log_format(self.log_format);
+        if let Some(runtime_config_file) = &self.runtime_config_file {
+            let runtime_config = if self.runtime_config_keys.is_empty() {
+                RuntimeConfig::from_file(runtime_config_file)?
+            } else {
+                RuntimeConfig::from_signed_file(runtime_config_file, &self.runtime_config_keys)?
+            };
+            builder.runtime_config(runtime_config);
+        }
+        if let Some(max_concurrency) = self.max_concurrency {
+            builder.max_concurrency(max_concurrency);
+        }
+        if let Some(port) = self.admin_port {
+            builder.admin_port(port);
+        }
+        if self.precompile {
+            return precompile(builder);
+        }
+        if self.hot_reload {
+            builder.hot_reload();
+        }
+        configure(&mut builder)?;
 
-        let executor: Executor = builder.build().await?;
-        let run_fut = executor.run(self.run_config);
+        let (mut executor, shutdown_hooks): (Executor, _) =
+            builder.build_with_shutdown_hooks().await?;
+        let (shutdown, request_shutdown) = ShutdownSignal::new();
+        executor.configure_shutdown(shutdown.clone());
+        let mut run_fut = executor.run(self.run_config);
 
-        let (abortable, abort_handle) = futures::future::abortable(run_fut);
-        ctrlc::set_handler(move || abort_handle.abort())?;
-        match abortable.await {
-            Ok(Ok(())) => {
+        let shutdown_requested = AtomicBool::new(false);
+        ctrlc::set_handler(move || {
+            // A second request skips draining and waiting for components to
+            // shut down.
+            if shutdown_requested.swap(true, Ordering::SeqCst) {
+                std::process::exit(1);
+            }
+            request_shutdown()
+        })?;
+        let requested = Box::pin(shutdown.requested());
+        let result = match futures::future::select(&mut run_fut, requested).await {
+            Either::Left((result, _)) => result,
+            Either::Right(((), _)) => {
+                tracing::info!("User requested shutdown: draining work in flight");
+                let timeout = Duration::from_secs(self.shutdown_timeout);
+                match tokio::time::timeout(timeout, run_fut).await {
+                    Ok(result) => result,
+                    Err(_) => Err(anyhow::anyhow!(
+                        "Work in flight did not complete within the shutdown timeout of {}s",
+                        timeout.as_secs()
+                    )),
+                }
+            }
+        };
+        let result = match result {
+            Ok(()) => {
                 tracing::info!("Trigger executor shut down: exiting");
                 Ok(())
             }
-            Ok(Err(err)) => {
+            Err(err) => {
                 tracing::error!("Trigger executor failed: {:?}", err);
                 Err(err)
             }
-            Err(_aborted) => {
-                tracing::info!("User requested shutdown: exiting");
-                Ok(())
-            }
-        }
+        };
+        shutdown_hooks.run().await;
+        result
     }
 }
 
@@ -132,50 +302,35 @@ where
     Executor::RunConfig: Args,
 {
     pub async fn build_application(&self) -> Result<Application> {
-        let working_dir = std::env::var("SPIN_WORKING_DIR").context("SPIN_WORKING_DIR")?;
-        let manifest_url = std::env::var("SPIN_MANIFEST_URL").context("SPIN_MANIFEST_URL")?;
-        let allow_transient_write: bool = std::env::var("SPIN_ALLOW_TRANSIENT_WRITE")
-            .unwrap_or_else(|_| "false".to_string())
-            .trim()
-            .parse()
-            .context("SPIN_ALLOW_TRANSIENT_WRITE")?;
-
-        // TODO(lann): Find a better home for this; spin_loader?
-        let mut app = if let Some(manifest_file) = manifest_url.strip_prefix("file://") {
-            let bindle_connection = std::env::var("BINDLE_URL")
-                .ok()
-                .map(|url| BindleConnectionInfo::new(url, false, None, None));
-            spin_loader::from_file(
-                manifest_file,
-                working_dir,
-                &bindle_connection,
-                allow_transient_write,
-            )
-            .await?
-        } else if let Some(bindle_url) = manifest_url.strip_prefix("bindle+") {
-            let (bindle_server, bindle_id) = bindle_url
-                .rsplit_once("?id=")
-                .context("invalid bindle URL")?;
-            spin_loader::from_bindle(bindle_id, bindle_server, working_dir, allow_transient_write)
-                .await?
-        } else {
-            bail!("invalid SPIN_MANIFEST_URL {}", manifest_url);
+        let mut app = match &self.from_lock {
+            Some(lock_file) => spin_loader::locked::from_file(lock_file).await?,
+            None => load_application().await?,
         };
 
-        // Apply --env to all components in the given app
-        for c in app.components.iter_mut() {
-            for (k, v) in self.env.iter().cloned() {
-                c.wasm.environment.insert(k, v);
-            }
+        // The version components see, from `spin up --version-label`.
+        if let Ok(label) = std::env::var("SPIN_VERSION_LABEL") {
+            app.info.version = label;
         }
 
-        // Add default config provider(s).
-        // TODO(lann): Make config provider(s) configurable.
-        if let Some(ref mut resolver) = app.config_resolver {
-            let resolver = Arc::get_mut(resolver)
-                .context("Internal error: app.config_resolver unexpectedly shared")?;
-            resolver.add_provider(spin_config::provider::env::EnvProvider::default());
+        // Apply the variables of `spin up --env-file`, then of --env-file,
+        // which --env overrides. Variables no component declares are
+        // skipped, as env files are often shared with other tools.
+        let mut env_file_vars = match std::env::var("SPIN_ENV_FILE_VARS") {
+            Ok(vars) => serde_json::from_str(&vars).context("invalid SPIN_ENV_FILE_VARS")?,
+            Err(_) => vec![],
+        };
+        for file in &self.env_files {
+            env_file::merge(&mut env_file_vars, env_file::load(file).await?);
         }
+        for (k, v) in env_file_vars {
+            for c in app.components.iter_mut() {
+                if let Some(value) = c.wasm.environment.get_mut(&k) {
+                    *value = v.clone();
+                }
+            }
+        }
+
+        apply_env(&mut app, &self.env);
 
         Ok(app)
     }
@@ -203,6 +358,123 @@ where
     }
 }
 
+/// Loads the application `spin up` prepared, from the manifest or bindle in
+/// the environment it runs the trigger in.
+async fn load_application() -> Result<Application> {
+    let working_dir = std::env::var("SPIN_WORKING_DIR").context("SPIN_WORKING_DIR")?;
+    let manifest_url = std::env::var("SPIN_MANIFEST_URL").context("SPIN_MANIFEST_URL")?;
+    let allow_transient_write: bool = std::env::var("SPIN_ALLOW_TRANSIENT_WRITE")
+        .unwrap_or_else(|_| "false".to_string())
+        .trim()
+        .parse()
+        .context("SPIN_ALLOW_TRANSIENT_WRITE")?;
+    let direct_mounts: bool = std::env::var("SPIN_DIRECT_MOUNTS")
+        .unwrap_or_else(|_| "false".to_string())
+        .trim()
+        .parse()
+        .context("SPIN_DIRECT_MOUNTS")?;
+
+    // TODO(lann): Find a better home for this; spin_loader?
+    let app = if let Some(manifest_file) = manifest_url.strip_prefix("file://") {
+        let bindle_connection = std::env::var("BINDLE_URL")
+            .ok()
+            .map(BindleConnectionInfo::from_env);
+        spin_loader::from_file(
+            manifest_file,
+            working_dir,
+            &bindle_connection,
+            allow_transient_write,
+            direct_mounts,
+        )
+        .await?
+    } else if let Some(bindle_url) = manifest_url.strip_prefix("bindle+") {
+        let (bindle_server, bindle_id) = bindle_url
+            .rsplit_once("?id=")
+            .context("invalid bindle URL")?;
+        spin_loader::from_bindle(
+            bindle_id,
+            &BindleConnectionInfo::from_env(bindle_server),
+            working_dir,
+            allow_transient_write,
+            &SignaturePolicy::from_env(),
+            parcel_cache::default_dir().map(ParcelCache::new),
+        )
+        .await?
+    } else {
+        bail!("invalid SPIN_MANIFEST_URL {}", manifest_url);
+    };
+    Ok(app)
+}
+
+// Compile the modules of the application into the module cache.
+fn precompile<Executor: TriggerExecutor>(builder: TriggerExecutorBuilder<Executor>) -> Result<()> {
+    let start = std::time::Instant::now();
+    let stats = builder.precompile()?;
+    println!(
+        "Compiled {} module(s) in {}ms; {} module(s) were already compiled",
+        stats.compiled,
+        start.elapsed().as_millis(),
+        stats.cached
+    );
+    Ok(())
+}
+
+/// Passes the variables given with --env to all components. Variables are
+/// given explicitly, so unlike those of env files they are passed to
+/// components that do not declare them; each is logged with the components
+/// it is added to and those whose value it overrides.
+fn apply_env(app: &mut Application, env: &[(String, String)]) {
+    for (k, v) in env {
+        let mut added = vec![];
+        let mut overridden = vec![];
+        for c in app.components.iter_mut() {
+            match c.wasm.environment.insert(k.clone(), v.clone()) {
+                Some(_) => overridden.push(c.id.as_str()),
+                None => added.push(c.id.as_str()),
+            }
+        }
+        tracing::info!(
+            "Environment variable {} from --env: added to components [{}], overriding components [{}]",
+            k,
+            added.join(", "),
+            overridden.join(", ")
+        );
+    }
+}
+
+// Print the environment of a component, as it will be passed to the guest.
+fn show_env(app: &Application, component: &str) -> Result<()> {
+    for (k, v) in component_env(app, component)? {
+        println!("{}={}", k, v);
+    }
+    Ok(())
+}
+
+/// The environment of a component, as it will be passed to the guest, sorted
+/// by name.
+fn component_env<'a>(app: &'a Application, component: &str) -> Result<Vec<(&'a str, &'a str)>> {
+    let component = app
+        .components
+        .iter()
+        .find(|c| c.id == component)
+        .with_context(|| format!("Unknown component {}", component))?;
+    let mut env: Vec<(&str, &str)> = component
+        .wasm
+        .environment
+        .iter()
+        .map(|(k, v)| (k.as_str(), v.as_str()))
+        .collect();
+    if !component
+        .wasm
+        .environment
+        .contains_key(spin_engine::APP_VERSION_ENV)
+    {
+        env.push((spin_engine::APP_VERSION_ENV, &app.info.version));
+    }
+    env.sort();
+    Ok(env)
+}
+
 // Parse the environment variables passed in `key=value` pairs.
 fn parse_env_var(s: &str) -> Result<(String, String)> {
     let parts: Vec<_> = s.splitn(2, '=').collect();
@@ -211,3 +483,97 @@ fn parse_env_var(s: &str) -> Result<(String, String)> {
     }
     Ok((parts[0].to_owned(), parts[1].to_owned()))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spin_manifest::{
+        ApplicationInformation, ApplicationOrigin, CoreComponent, ModuleSource, SpinVersion,
+    };
+
+    fn app(components: &[(&str, &[(&str, &str)])]) -> Application {
+        let components = components
+            .iter()
+            .map(|(id, env)| CoreComponent {
+                source: ModuleSource::Buffer(vec![], "test".to_owned()),
+                id: id.to_string(),
+                description: None,
+                wasm: spin_manifest::WasmConfig {
+                    environment: env
+                        .iter()
+                        .map(|(k, v)| (k.to_string(), v.to_string()))
+                        .collect(),
+                    ..Default::default()
+                },
+            })
+            .collect();
+        Application {
+            info: ApplicationInformation {
+                spin_version: SpinVersion::V1,
+                name: "test-app".to_owned(),
+                version: "1.0.0".to_owned(),
+                description: None,
+                authors: vec![],
+                trigger: ApplicationTrigger::Http(Default::default()),
+                namespace: None,
+                origin: ApplicationOrigin::File(PathBuf::from("spin.toml")),
+            },
+            components,
+            component_triggers: Default::default(),
+            config_resolver: None,
+        }
+    }
+
+    #[test]
+    fn test_env_is_added_to_every_component() -> Result<()> {
+        let mut app = app(&[("first", &[("GREETING", "hello")]), ("second", &[])]);
+        let env = [
+            parse_env_var("GREETING=bonjour")?,
+            parse_env_var("FOO=a=b")?,
+        ];
+        apply_env(&mut app, &env);
+
+        assert_eq!(
+            component_env(&app, "first")?,
+            [
+                ("FOO", "a=b"),
+                ("GREETING", "bonjour"),
+                (spin_engine::APP_VERSION_ENV, "1.0.0")
+            ]
+        );
+        assert_eq!(
+            component_env(&app, "second")?,
+            [
+                ("FOO", "a=b"),
+                ("GREETING", "bonjour"),
+                (spin_engine::APP_VERSION_ENV, "1.0.0")
+            ]
+        );
+        assert!(parse_env_var("FOO").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_component_env() -> Result<()> {
+        let app = app(&[
+            ("versioned", &[(spin_engine::APP_VERSION_ENV, "custom")]),
+            ("plain", &[("B", "2"), ("A", "1")]),
+        ]);
+
+        // Components declaring the version variable keep their own value.
+        assert_eq!(
+            component_env(&app, "versioned")?,
+            [(spin_engine::APP_VERSION_ENV, "custom")]
+        );
+        assert_eq!(
+            component_env(&app, "plain")?,
+            [
+                ("A", "1"),
+                ("B", "2"),
+                (spin_engine::APP_VERSION_ENV, "1.0.0")
+            ]
+        );
+        assert!(component_env(&app, "unknown").is_err());
+        Ok(())
+    }
+}
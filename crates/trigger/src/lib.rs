@@ -1,19 +1,55 @@
-use std::{error::Error, marker::PhantomData, path::PathBuf};
+use std::{
+    error::Error,
+    marker::PhantomData,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::{atomic::Ordering, Arc},
+};
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use async_trait::async_trait;
 use spin_engine::{
-    io::FollowComponents, Builder, Engine, ExecutionContext, ExecutionContextConfiguration,
+    host_component::HostComponent,
+    io::FollowComponents,
+    logs::LogFormat,
+    module_cache::{ModuleCacheDir, PrecompileStats},
+    Builder, DataDir, EgressMeter, Engine, ExecutionContext, ExecutionContextConfiguration,
+    SelfAddress, ServiceHealth,
+};
+use spin_manifest::{
+    Application, ApplicationOrigin, ApplicationTrigger, CoreComponent, TriggerConfig,
 };
-use spin_manifest::{Application, ApplicationTrigger, TriggerConfig};
 
+use crate::admin::AdminState;
+
+mod adaptive;
+mod admin;
 pub mod cli;
+mod lifecycle;
+mod profile;
+mod reload;
+mod runtime_config;
+mod scheduler;
+mod shutdown;
+pub mod telemetry;
+
+pub use adaptive::{AdaptiveConfig, AdaptiveLimiter, AdaptivePermit, LimitStats};
+pub use admin::AdminConfig;
+pub use lifecycle::{LifecycleConfig, ShutdownHooks, INIT_EXPORT, SHUTDOWN_EXPORT};
+pub use profile::{RouteProfile, PROFILE_FILE};
+pub use runtime_config::{
+    runtime_config_signature_path, sign_runtime_config, GeoIpConfig, HttpBodyConfig,
+    IdempotencyConfig, MetricsConfig, ProxyConfig, RequestRulesConfig, RuntimeConfig,
+};
+pub use scheduler::{ConcurrencyConfig, Overloaded, Permit, Scheduler, SchedulerStats, ShedPolicy};
+pub use shutdown::{ShutdownSignal, DEFAULT_SHUTDOWN_TIMEOUT};
+
 #[async_trait]
 pub trait TriggerExecutor: Sized {
     type GlobalConfig;
     type TriggerConfig;
     type RunConfig;
-    type RuntimeContext: Default + 'static;
+    type RuntimeContext: Default + Send + 'static;
 
     /// Create a new trigger executor.
     fn new(
@@ -29,14 +65,57 @@ pub trait TriggerExecutor: Sized {
     fn configure_execution_context(_builder: &mut Builder<Self::RuntimeContext>) -> Result<()> {
         Ok(())
     }
+
+    /// Configure the trigger executor from the application's runtime configuration.
+    fn configure_runtime(&mut self, _runtime_config: &RuntimeConfig) -> Result<()> {
+        Ok(())
+    }
+
+    /// Give the trigger executor the profile in which to record the routes
+    /// it invokes.
+    fn configure_profile(&mut self, _profile: Arc<RouteProfile>) {}
+
+    /// Give the trigger executor the devices wasi-nn computations are
+    /// scheduled on, to report their use.
+    fn configure_wasi_nn(&mut self, _devices: Arc<spin_wasi_nn::Devices>) {}
+
+    /// Give the trigger executor the JWT providers components sign and
+    /// validate tokens with, to validate the tokens of requests with the
+    /// same keys.
+    fn configure_jwt(&mut self, _jwt: Arc<spin_jwt::JwtProviders>) {}
+
+    /// Give the trigger executor the meter of the outbound traffic of
+    /// components, to report it.
+    fn configure_egress(&mut self, _egress: Arc<EgressMeter>) {}
+
+    /// Give the trigger executor the health of the application's host
+    /// services, to report it from its health endpoint.
+    fn configure_health(&mut self, _health: Arc<ServiceHealth>) {}
+
+    /// Give the trigger executor the address the application is served on,
+    /// to set once it listens, so that components can send requests to it.
+    fn configure_self_address(&mut self, _self_address: Arc<SelfAddress>) {}
+
+    /// Give the trigger executor the signal on which to stop accepting work
+    /// and return from `run` once the work in flight completes. Executors
+    /// ignoring it are stopped when the shutdown timeout elapses.
+    fn configure_shutdown(&mut self, _shutdown: ShutdownSignal) {}
 }
 
+/// Adds a host component to the builder of an execution context.
+type HostComponentRegistration<T> = Box<dyn FnOnce(&mut Builder<T>) -> Result<()> + Send>;
+
 pub struct TriggerExecutorBuilder<Executor: TriggerExecutor> {
     application: Application,
     wasmtime_config: wasmtime::Config,
     log_dir: Option<PathBuf>,
+    log_format: LogFormat,
     follow_components: FollowComponents,
     disable_default_host_components: bool,
+    runtime_config: RuntimeConfig,
+    module_cache: Option<ModuleCacheDir>,
+    hot_reload: bool,
+    host_components: Vec<HostComponentRegistration<Executor::RuntimeContext>>,
     _phantom: PhantomData<Executor>,
 }
 
@@ -47,8 +126,13 @@ impl<Executor: TriggerExecutor> TriggerExecutorBuilder<Executor> {
             application,
             wasmtime_config: Default::default(),
             log_dir: None,
+            log_format: Default::default(),
             follow_components: Default::default(),
             disable_default_host_components: false,
+            runtime_config: Default::default(),
+            module_cache: None,
+            hot_reload: false,
+            host_components: Vec::new(),
             _phantom: PhantomData,
         }
     }
@@ -65,6 +149,11 @@ impl<Executor: TriggerExecutor> TriggerExecutorBuilder<Executor> {
         self
     }
 
+    pub fn log_format(&mut self, log_format: LogFormat) -> &mut Self {
+        self.log_format = log_format;
+        self
+    }
+
     pub fn follow_components(&mut self, follow_components: FollowComponents) -> &mut Self {
         self.follow_components = follow_components;
         self
@@ -75,6 +164,78 @@ impl<Executor: TriggerExecutor> TriggerExecutorBuilder<Executor> {
         self
     }
 
+    pub fn runtime_config(&mut self, runtime_config: RuntimeConfig) -> &mut Self {
+        self.runtime_config = runtime_config;
+        self
+    }
+
+    /// Persist compiled modules in the given cache, to reuse them across runs.
+    pub fn module_cache(&mut self, module_cache: ModuleCacheDir) -> &mut Self {
+        self.module_cache = Some(module_cache);
+        self
+    }
+
+    /// Reload the modules of components when their files change, without
+    /// restarting the trigger.
+    /// Run components in an instance pool of the given number of instances,
+    /// overriding the size of the pool in the runtime configuration.
+    pub fn max_concurrency(&mut self, max_instances: u32) -> &mut Self {
+        self.runtime_config
+            .instance_pool
+            .get_or_insert_with(Default::default)
+            .max_instances = max_instances;
+        self
+    }
+
+    /// Serve the admin listener on the given port of all interfaces,
+    /// overriding the address in the runtime configuration.
+    pub fn admin_port(&mut self, port: u16) -> &mut Self {
+        let listen = SocketAddr::from(([0, 0, 0, 0], port));
+        match &mut self.runtime_config.admin {
+            Some(admin) => admin.listen = listen,
+            None => {
+                self.runtime_config.admin = Some(AdminConfig {
+                    listen,
+                    profiling: false,
+                })
+            }
+        }
+        self
+    }
+
+    pub fn hot_reload(&mut self) -> &mut Self {
+        self.hot_reload = true;
+        self
+    }
+
+    /// Add a host component to the execution context, besides the default
+    /// ones. It is added even if the default host components are disabled.
+    pub fn add_host_component(
+        &mut self,
+        host_component: impl HostComponent + 'static,
+    ) -> &mut Self {
+        self.host_components.push(Box::new(move |builder| {
+            builder.add_host_component(host_component)?;
+            Ok(())
+        }));
+        self
+    }
+
+    /// Compiles the modules of the application's components into the module
+    /// cache, with the engine configuration they run with, so that the
+    /// application starts without compiling them.
+    pub fn precompile(self) -> Result<PrecompileStats> {
+        let module_cache = self
+            .module_cache
+            .context("Cannot precompile modules with the module cache disabled")?;
+        let engine = engine(
+            self.wasmtime_config,
+            &self.runtime_config,
+            &self.application.components,
+        )?;
+        module_cache.precompile(&engine, &self.application.components)
+    }
+
     pub async fn build(self) -> Result<Executor>
     where
         Executor::GlobalConfig: TryFrom<ApplicationTrigger>,
@@ -84,24 +245,131 @@ impl<Executor: TriggerExecutor> TriggerExecutorBuilder<Executor> {
         <Executor::TriggerConfig as TryFrom<(String, TriggerConfig)>>::Error:
             Error + Send + Sync + 'static,
     {
-        let app = self.application;
+        let (executor, _) = self.build_with_shutdown_hooks().await?;
+        Ok(executor)
+    }
+
+    /// Builds the executor along with the shutdown hooks of the application's
+    /// components, to be run once the executor stops.
+    pub async fn build_with_shutdown_hooks(self) -> Result<(Executor, ShutdownHooks)>
+    where
+        Executor::GlobalConfig: TryFrom<ApplicationTrigger>,
+        <Executor::GlobalConfig as TryFrom<ApplicationTrigger>>::Error:
+            Error + Send + Sync + 'static,
+        Executor::TriggerConfig: TryFrom<(String, TriggerConfig)>,
+        <Executor::TriggerConfig as TryFrom<(String, TriggerConfig)>>::Error:
+            Error + Send + Sync + 'static,
+    {
+        let self_address = Arc::new(SelfAddress::default());
+        let health = Arc::new(ServiceHealth::new(&self.runtime_config.services));
+        health.validate(DEGRADABLE_SERVICES)?;
+        // Served before the components are loaded, so that the application
+        // is reported live, but not ready, while it starts.
+        let admin_state = AdminState {
+            health: health.clone(),
+            ..Default::default()
+        };
+        if let Some(admin) = &self.runtime_config.admin {
+            tokio::spawn(admin::serve(admin, admin_state.clone())?);
+        }
+
+        let mut app = self.application;
+        resolve_variables(&mut app, &self.runtime_config).await?;
+        let data_dir = self.runtime_config.data_dir.resolve(&app.info.name);
+        let state_dir = app_state_dir(data_dir.as_ref(), &app.info.origin);
+
+        // Prepare the components of the hottest routes first.
+        let profile = Arc::new(route_profile(state_dir.as_deref()));
+        profile.order(&mut app.components, |c| &c.id);
+
+        let (tasks, task_receiver) = spin_tasks::TasksComponent::new(
+            &self.runtime_config.tasks,
+            app.components.iter().map(|c| c.id.clone()),
+            job_store(&self.runtime_config, state_dir.as_deref()),
+        );
+
+        let mut temp_dir = self.runtime_config.temp_dir.clone();
+        if let (None, Some(data_dir)) = (&temp_dir.dir, &data_dir) {
+            temp_dir.dir = Some(data_dir.path().join("tmp"));
+        }
+
+        let app_name = app.info.name.clone();
 
         // Build ExecutionContext
         let ctx_config = ExecutionContextConfiguration {
             components: app.components,
             label: app.info.name,
             log_dir: self.log_dir,
+            log_rotation: self.runtime_config.log_rotation.clone(),
+            log_format: self.log_format,
             follow_components: self.follow_components,
             config_resolver: app.config_resolver,
+            temp_dir,
+            app_version: Some(app.info.version),
+            module_cache: self.module_cache,
+            scheduling: self.runtime_config.scheduling.clone(),
+            instance_pool: self.runtime_config.instance_pool.clone(),
         };
-        let engine = Engine::new(self.wasmtime_config)?;
+        let engine = engine(
+            self.wasmtime_config,
+            &self.runtime_config,
+            &ctx_config.components,
+        )?;
         let mut ctx_builder = Builder::with_engine(ctx_config, engine)?;
         ctx_builder.link_defaults()?;
+        ctx_builder.invocation_meter(admin_state.invocations.clone());
+        let mut wasi_nn_devices = None;
+        let mut egress = None;
+        let jwt = Arc::new(spin_jwt::JwtProviders::new(&self.runtime_config.jwt)?);
         if !self.disable_default_host_components {
-            add_default_host_components(&mut ctx_builder)?;
+            let identity = match &self.runtime_config.workload_identity {
+                Some(config) => Some(wasi_outbound_http::WorkloadIdentity::start(config).await?),
+                None => None,
+            };
+            let meter = Arc::new(EgressMeter::new(&self.runtime_config.egress));
+            add_default_host_components(
+                &mut ctx_builder,
+                &self.runtime_config,
+                &app_name,
+                identity,
+                data_dir,
+                meter.clone(),
+                jwt.clone(),
+                &health,
+                self_address.clone(),
+            )
+            .await?;
+            egress = Some(meter);
+            ctx_builder.add_host_component(tasks)?;
+            let wasi_nn = spin_wasi_nn::WasiNnComponent::new(&self.runtime_config.wasi_nn)?;
+            wasi_nn_devices = Some(wasi_nn.devices());
+            ctx_builder.add_host_component(wasi_nn)?;
+        }
+        for plugin in &self.runtime_config.host_plugin {
+            ctx_builder.add_host_component(spin_host_plugins::HostPlugin::load(&plugin.path)?)?;
+        }
+        for add_host_component in self.host_components {
+            add_host_component(&mut ctx_builder)?;
         }
         Executor::configure_execution_context(&mut ctx_builder)?;
         let execution_context = ctx_builder.build().await?;
+        tokio::spawn(spin_tasks::run_tasks(
+            task_receiver,
+            execution_context.clone(),
+        ));
+        lifecycle::run_init_hooks(&execution_context, &self.runtime_config.lifecycle).await?;
+        let shutdown_hooks = ShutdownHooks::new(
+            execution_context.clone(),
+            &self.runtime_config.lifecycle,
+            profile.clone(),
+        );
+        tokio::spawn(profile.clone().save_periodically());
+        if self.hot_reload {
+            tokio::spawn(reload::reload_changed_modules(
+                execution_context.clone(),
+                reload::RELOAD_INTERVAL,
+            ));
+        }
 
         // Build trigger configurations
         let global_config = app.info.trigger.try_into()?;
@@ -112,14 +380,167 @@ impl<Executor: TriggerExecutor> TriggerExecutorBuilder<Executor> {
             .collect::<Result<Vec<_>>>()?;
 
         // Run trigger executor
-        Executor::new(execution_context, global_config, trigger_configs)
+        let mut executor = Executor::new(execution_context, global_config, trigger_configs)?;
+        executor.configure_runtime(&self.runtime_config)?;
+        executor.configure_profile(profile);
+        executor.configure_jwt(jwt);
+        if let Some(devices) = wasi_nn_devices {
+            executor.configure_wasi_nn(devices);
+        }
+        if let Some(egress) = egress {
+            executor.configure_egress(egress);
+        }
+        executor.configure_health(health);
+        executor.configure_self_address(self_address);
+        admin_state.ready.store(true, Ordering::Release);
+        Ok((executor, shutdown_hooks))
+    }
+}
+
+/// The engine components run on. Compiled modules are only reused by engines
+/// with the same configuration, so modules compiled ahead of time must use
+/// this too.
+fn engine(
+    mut wasmtime_config: wasmtime::Config,
+    runtime_config: &RuntimeConfig,
+    components: &[CoreComponent],
+) -> Result<Engine> {
+    // Guests are interrupted to yield, or to stop at their timeout.
+    let timeouts = components
+        .iter()
+        .any(|c| c.wasm.limits.timeout_ms.is_some());
+    if runtime_config.scheduling.is_some() || timeouts {
+        wasmtime_config.epoch_interruption(true);
+    }
+    if let Some(pool) = &runtime_config.instance_pool {
+        pool.configure_engine(&mut wasmtime_config);
+    }
+    Engine::new(wasmtime_config)
+}
+
+/// The store for the delayed tasks and recurring jobs of an application: the
+/// configured file, or the jobs file in the directory of a local application.
+fn job_store(runtime_config: &RuntimeConfig, state_dir: Option<&Path>) -> spin_tasks::JobStore {
+    match (&runtime_config.tasks.store, state_dir) {
+        (Some(path), _) => spin_tasks::JobStore::file(path),
+        (None, Some(dir)) => spin_tasks::JobStore::file(dir.join(spin_tasks::JOBS_FILE)),
+        (None, None) => spin_tasks::JobStore::memory(),
+    }
+}
+
+/// The route profile of an application: the profile file in its state
+/// directory, or an unpersisted profile otherwise.
+fn route_profile(state_dir: Option<&Path>) -> RouteProfile {
+    match state_dir {
+        Some(dir) => RouteProfile::file(dir.join(PROFILE_FILE)),
+        None => RouteProfile::memory(),
+    }
+}
+
+/// Adds the configured providers to the variable resolver of the application,
+/// and checks that all variables and component config values resolve, so
+/// that a missing variable fails at startup rather than when it is read.
+async fn resolve_variables(app: &mut Application, runtime_config: &RuntimeConfig) -> Result<()> {
+    if let Some(resolver) = &mut app.config_resolver {
+        let resolver_mut = Arc::get_mut(resolver)
+            .context("Internal error: app.config_resolver unexpectedly shared")?;
+        if runtime_config.config_provider.is_empty() {
+            resolver_mut.add_providers(&[spin_config::ProviderConfig::default()]);
+        } else {
+            resolver_mut.add_providers(&runtime_config.config_provider);
+        }
+        // Secret providers such as Vault fetch values with blocking requests.
+        let resolver = resolver.clone();
+        tokio::task::spawn_blocking(move || resolver.validate())
+            .await?
+            .context("Cannot resolve the application variables")?;
     }
+    Ok(())
 }
 
-/// Add the default set of host components to the given builder.
-pub fn add_default_host_components<T: Default + 'static>(builder: &mut Builder<T>) -> Result<()> {
-    builder.add_host_component(wasi_outbound_http::OutboundHttpComponent)?;
-    builder.add_host_component(outbound_redis::OutboundRedis)?;
-    builder.add_host_component(outbound_pg::OutboundPg)?;
+/// The directory the state files of an application are kept in: its data
+/// directory if one is configured, or else the directory of a local
+/// application.
+fn app_state_dir(data_dir: Option<&DataDir>, origin: &ApplicationOrigin) -> Option<PathBuf> {
+    match (data_dir, origin) {
+        (Some(data_dir), _) => Some(data_dir.path().to_owned()),
+        (None, ApplicationOrigin::File(manifest)) => manifest.parent().map(Path::to_owned),
+        (None, _) => None,
+    }
+}
+
+/// The host services that can start without their backend, as named in the
+/// `[services]` runtime configuration.
+const DEGRADABLE_SERVICES: &[&str] = &["blob_store", "key_value", "lock", "pubsub"];
+
+/// Add the default set of host components for the named application to the
+/// given builder, with outbound HTTP requests presenting the given workload
+/// identity, state stored in the given data directory, and outbound traffic
+/// metered by the given meter. Components sign and validate tokens with the
+/// given JWT providers.
+/// Services whose backend is unavailable are started as the given health
+/// tracker's policies say, and outbound HTTP requests to the given address
+/// are allowed by `self`.
+#[allow(clippy::too_many_arguments)]
+pub async fn add_default_host_components<T: Default + Send + 'static>(
+    builder: &mut Builder<T>,
+    runtime_config: &RuntimeConfig,
+    app_name: &str,
+    identity: Option<Arc<wasi_outbound_http::WorkloadIdentity>>,
+    data_dir: Option<DataDir>,
+    egress: Arc<EgressMeter>,
+    jwt: Arc<spin_jwt::JwtProviders>,
+    health: &ServiceHealth,
+    self_address: Arc<SelfAddress>,
+) -> Result<()> {
+    builder.add_host_component(
+        wasi_outbound_http::OutboundHttpComponent::new(identity)
+            .with_egress(egress.clone())
+            .with_self_address(self_address),
+    )?;
+    builder.add_host_component(outbound_redis::OutboundRedis::new(egress.clone()))?;
+    builder.add_host_component(outbound_pg::OutboundPg::new(egress.clone()))?;
+    builder.add_host_component(outbound_mysql::OutboundMysql::new(egress))?;
+    let blob_store = spin_blobstore::BlobStoreComponent::new(runtime_config.blob_store.clone())
+        .with_data_dir(data_dir.clone());
+    let blob_store_reachable = blob_store.check().await;
+    builder.add_host_component(health.start(
+        "blob_store",
+        || blob_store_reachable.map(|_| blob_store.clone()),
+        |e| blob_store.clone().unavailable(e),
+    )?)?;
+    builder.add_host_component(spin_cache::CacheComponent::new(&runtime_config.cache))?;
+    let key_value = spin_key_value::KeyValueComponent::new(runtime_config.key_value.clone())
+        .with_data_dir(data_dir);
+    builder.add_host_component(health.start(
+        "key_value",
+        || key_value.check().map(|_| key_value.clone()),
+        |e| key_value.clone().unavailable(e),
+    )?)?;
+    builder.add_host_component(health.start(
+        "lock",
+        || {
+            let lock = spin_lock::LockComponent::new(&runtime_config.lock, app_name)?;
+            lock.check()?;
+            Ok(lock)
+        },
+        spin_lock::LockComponent::unavailable,
+    )?)?;
+    match &runtime_config.pubsub {
+        Some(pubsub) => builder.add_host_component(health.start(
+            "pubsub",
+            || {
+                let pubsub = spin_pubsub::PubSubComponent::new(pubsub)?;
+                pubsub.check()?;
+                Ok(pubsub)
+            },
+            spin_pubsub::PubSubComponent::unavailable,
+        )?)?,
+        None => builder.add_host_component(spin_pubsub::PubSubComponent::unconfigured())?,
+    };
+    builder.add_host_component(spin_crypto::CryptoComponent::new(
+        &runtime_config.crypto_key,
+    )?)?;
+    builder.add_host_component(spin_jwt::JwtComponent::new(jwt))?;
     Ok(())
 }
@@ -0,0 +1,319 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, Result};
+use futures::future::BoxFuture;
+use serde::Deserialize;
+use spin_engine::ExecutionContext;
+
+use crate::RouteProfile;
+
+/// The function components may export to be called once, when the
+/// application starts, for example to warm caches or validate their config.
+pub const INIT_EXPORT: &str = "spin-init";
+
+/// The function components may export to be called once, when the
+/// application stops, for example to flush buffers or release leases.
+pub const SHUTDOWN_EXPORT: &str = "spin-shutdown";
+
+/// Runtime configuration for component lifecycle hooks.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub struct LifecycleConfig {
+    /// The maximum time, in seconds, the init function of each component
+    /// may run for before startup fails.
+    #[serde(default = "default_init_timeout_secs")]
+    pub init_timeout_secs: u64,
+    /// The maximum time, in seconds, the application may take to stop once
+    /// shutdown is requested, including running shutdown functions.
+    #[serde(default = "default_drain_timeout_secs")]
+    pub drain_timeout_secs: u64,
+}
+
+impl Default for LifecycleConfig {
+    fn default() -> Self {
+        Self {
+            init_timeout_secs: default_init_timeout_secs(),
+            drain_timeout_secs: default_drain_timeout_secs(),
+        }
+    }
+}
+
+fn default_init_timeout_secs() -> u64 {
+    30
+}
+
+fn default_drain_timeout_secs() -> u64 {
+    10
+}
+
+/// The outcome of calling a lifecycle function of a component.
+enum HookOutcome {
+    /// The component does not export the function.
+    NoHook,
+    Succeeded(Duration),
+    Failed(anyhow::Error),
+}
+
+/// Calls the init function of every component that exports one, printing a
+/// report of the outcomes. Fails if any init function fails or times out.
+pub(crate) async fn run_init_hooks<T: Default + Send + 'static>(
+    execution_context: &ExecutionContext<T>,
+    config: &LifecycleConfig,
+) -> Result<()> {
+    let timeout = Duration::from_secs(config.init_timeout_secs);
+    let outcomes = run_hooks(execution_context, INIT_EXPORT, timeout).await;
+
+    let mut failed = 0;
+    let mut report = vec![];
+    for (id, outcome) in outcomes {
+        match outcome {
+            HookOutcome::NoHook => {}
+            HookOutcome::Succeeded(elapsed) => {
+                report.push(format!("  {}: ok ({}ms)", id, elapsed.as_millis()))
+            }
+            HookOutcome::Failed(e) => {
+                failed += 1;
+                report.push(format!("  {}: FAILED: {:#}", id, e));
+            }
+        }
+    }
+    if !report.is_empty() {
+        println!("Initializing components:");
+        for line in report {
+            println!("{}", line);
+        }
+    }
+    if failed > 0 {
+        bail!("{} component(s) failed to initialize", failed);
+    }
+    Ok(())
+}
+
+/// Calls the shutdown functions of components once the application stops.
+pub struct ShutdownHooks {
+    run: Box<dyn FnOnce() -> BoxFuture<'static, ()> + Send>,
+}
+
+impl ShutdownHooks {
+    pub(crate) fn new<T: Default + Send + 'static>(
+        execution_context: ExecutionContext<T>,
+        config: &LifecycleConfig,
+        profile: Arc<RouteProfile>,
+    ) -> Self {
+        let timeout = Duration::from_secs(config.drain_timeout_secs);
+        Self {
+            run: Box::new(move || {
+                Box::pin(async move {
+                    run_shutdown_hooks(&execution_context, timeout).await;
+                    if let Err(e) = profile.save() {
+                        tracing::warn!("Failed to save route profile: {:#}", e);
+                    }
+                })
+            }),
+        }
+    }
+
+    /// Calls the shutdown function of every component that exports one,
+    /// concurrently, waiting at most for the drain timeout, then saves the
+    /// route profile. Failures are logged, as the application is stopping
+    /// regardless.
+    pub async fn run(self) {
+        (self.run)().await
+    }
+}
+
+async fn run_shutdown_hooks<T: Default + Send + 'static>(
+    execution_context: &ExecutionContext<T>,
+    timeout: Duration,
+) {
+    for (id, outcome) in run_hooks(execution_context, SHUTDOWN_EXPORT, timeout).await {
+        match outcome {
+            HookOutcome::NoHook => {}
+            HookOutcome::Succeeded(elapsed) => {
+                tracing::info!("Component {} shut down in {}ms", id, elapsed.as_millis())
+            }
+            HookOutcome::Failed(e) => {
+                tracing::error!("Component {} failed to shut down: {:#}", id, e)
+            }
+        }
+    }
+}
+
+/// Calls a lifecycle function of every loaded component that exports it,
+/// concurrently, returning the outcomes by component ID. Components loaded
+/// lazily are skipped until they are first invoked.
+async fn run_hooks<T: Default + Send + 'static>(
+    execution_context: &ExecutionContext<T>,
+    export: &str,
+    timeout: Duration,
+) -> Vec<(String, HookOutcome)> {
+    let mut ids: Vec<_> = execution_context
+        .components
+        .keys()
+        .filter(|id| execution_context.is_loaded(id))
+        .cloned()
+        .collect();
+    ids.sort();
+    let outcomes = futures::future::join_all(
+        ids.iter()
+            .map(|id| run_hook(execution_context, id, export, timeout)),
+    )
+    .await;
+    ids.into_iter().zip(outcomes).collect()
+}
+
+async fn run_hook<T: Default + Send + 'static>(
+    execution_context: &ExecutionContext<T>,
+    component: &str,
+    export: &str,
+    timeout: Duration,
+) -> HookOutcome {
+    let (mut store, instance) = match execution_context
+        .prepare_component(component, None, None, None, None)
+        .await
+    {
+        Ok(prepared) => prepared,
+        Err(e) => return HookOutcome::Failed(e),
+    };
+    let hook = match instance.get_func(&mut store, export) {
+        Some(hook) => hook,
+        None => return HookOutcome::NoHook,
+    };
+    let hook = match hook.typed::<(), (), _>(&store) {
+        Ok(hook) => hook,
+        Err(e) => {
+            return HookOutcome::Failed(e.context(format!(
+                "{} must take no arguments and return nothing",
+                export
+            )))
+        }
+    };
+
+    tracing::trace!("Calling {} of component {}", export, component);
+    let start = Instant::now();
+    // Unless guests yield, a guest that does not return within the timeout
+    // keeps running on its blocking thread, but the application is starting
+    // up or stopping, so the process exits regardless.
+    let call = async move { hook.call_async(&mut store, ()).await };
+    let call = execution_context.run_guest(component, call);
+    match tokio::time::timeout(timeout, call).await {
+        Ok(Ok(Ok(()))) => HookOutcome::Succeeded(start.elapsed()),
+        Ok(Ok(Err(trap))) => HookOutcome::Failed(anyhow!(trap)),
+        Ok(Err(e)) => HookOutcome::Failed(e),
+        Err(_) => HookOutcome::Failed(anyhow!("timed out after {}s", timeout.as_secs())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spin_engine::{Builder, ExecutionContextConfiguration, TempDirConfig, TempDirMode};
+    use spin_manifest::{CoreComponent, LoadPolicy, ModuleSource, WasmConfig};
+
+    /// An execution context for components with the given modules, in the
+    /// WebAssembly text format, loaded as the given policies say.
+    async fn execution_context(
+        dir: &std::path::Path,
+        modules: &[(&str, &str, LoadPolicy)],
+    ) -> Result<ExecutionContext<()>> {
+        let mut components = vec![];
+        for (id, module, load) in modules {
+            let path = dir.join(format!("{}.wat", id));
+            std::fs::write(&path, module)?;
+            components.push(CoreComponent {
+                source: ModuleSource::FileReference(path),
+                id: id.to_string(),
+                description: None,
+                wasm: WasmConfig {
+                    load: *load,
+                    ..Default::default()
+                },
+            });
+        }
+        let config = ExecutionContextConfiguration {
+            components,
+            temp_dir: TempDirConfig {
+                mode: TempDirMode::Disabled,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        Builder::build_default(config).await
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_hooks() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let hook = r#"(module (func (export "spin-shutdown")))"#;
+        let ctx = execution_context(
+            dir.path(),
+            &[
+                ("a-ok", hook, LoadPolicy::Eager),
+                (
+                    "b-trap",
+                    r#"(module (func (export "spin-shutdown") unreachable))"#,
+                    LoadPolicy::Eager,
+                ),
+                (
+                    "c-signature",
+                    r#"(module (func (export "spin-shutdown") (param i32)))"#,
+                    LoadPolicy::Eager,
+                ),
+                ("d-none", "(module)", LoadPolicy::Eager),
+                ("e-lazy", hook, LoadPolicy::Lazy),
+            ],
+        )
+        .await?;
+
+        let outcomes = run_hooks(&ctx, SHUTDOWN_EXPORT, Duration::from_secs(5)).await;
+        let ids: Vec<_> = outcomes.iter().map(|(id, _)| id.as_str()).collect();
+        // Components that were never invoked are not loaded to shut down.
+        assert_eq!(ids, ["a-ok", "b-trap", "c-signature", "d-none"]);
+        assert!(matches!(outcomes[0].1, HookOutcome::Succeeded(_)));
+        assert!(matches!(outcomes[1].1, HookOutcome::Failed(_)));
+        assert!(matches!(outcomes[2].1, HookOutcome::Failed(_)));
+        assert!(matches!(outcomes[3].1, HookOutcome::NoHook));
+
+        // Failures are logged rather than failing the shutdown.
+        let profile = Arc::new(RouteProfile::memory());
+        ShutdownHooks::new(ctx, &LifecycleConfig::default(), profile)
+            .run()
+            .await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_failed_init_hooks_fail_startup() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let ok = r#"(module (func (export "spin-init")))"#;
+        let ctx = execution_context(dir.path(), &[("ok", ok, LoadPolicy::Eager)]).await?;
+        run_init_hooks(&ctx, &LifecycleConfig::default()).await?;
+
+        let trap = r#"(module (func (export "spin-init") unreachable))"#;
+        let ctx = execution_context(
+            dir.path(),
+            &[
+                ("ok", ok, LoadPolicy::Eager),
+                ("trap", trap, LoadPolicy::Eager),
+            ],
+        )
+        .await?;
+        assert!(run_init_hooks(&ctx, &LifecycleConfig::default())
+            .await
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_timeout_defaults() -> Result<()> {
+        let config: crate::RuntimeConfig = toml::from_str("[lifecycle]")?;
+        assert_eq!(config.lifecycle.init_timeout_secs, 30);
+        assert_eq!(config.lifecycle.drain_timeout_secs, 10);
+        let config: crate::RuntimeConfig = toml::from_str("[lifecycle]\ninit_timeout_secs = 5")?;
+        assert_eq!(config.lifecycle.init_timeout_secs, 5);
+        Ok(())
+    }
+}
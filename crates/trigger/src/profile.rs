@@ -0,0 +1,168 @@
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+/// The file, relative to the application directory, holding the route
+/// profile of an application run from a local spin.toml.
+pub const PROFILE_FILE: &str = ".spin/profile.json";
+
+/// How often the profile is saved while the application runs.
+const SAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The invocations of a route.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize)]
+struct RouteCount {
+    component: String,
+    count: u64,
+}
+
+/// How often each route of an application is invoked, kept across runs so
+/// that the components of the hottest routes are prepared first at startup.
+///
+/// Counts are halved each time the profile is loaded, so that the profile
+/// follows changes in traffic rather than being dominated by old runs.
+pub struct RouteProfile {
+    path: Option<PathBuf>,
+    routes: Mutex<BTreeMap<String, RouteCount>>,
+}
+
+impl RouteProfile {
+    /// Loads the profile persisted in the given file. A missing or invalid
+    /// file yields an empty profile, as the profile is only a hint.
+    pub fn file(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let mut routes = load_routes(&path).unwrap_or_else(|e| {
+            tracing::warn!("Ignoring route profile: {:#}", e);
+            BTreeMap::new()
+        });
+        routes.retain(|_, route| {
+            route.count /= 2;
+            route.count > 0
+        });
+        Self {
+            path: Some(path),
+            routes: Mutex::new(routes),
+        }
+    }
+
+    /// Creates a profile that is not persisted.
+    pub fn memory() -> Self {
+        Self {
+            path: None,
+            routes: Default::default(),
+        }
+    }
+
+    /// Records an invocation of the component handling the route.
+    pub fn record(&self, route: &str, component: &str) {
+        let mut routes = self.routes.lock().unwrap();
+        match routes.get_mut(route) {
+            Some(count) if count.component == component => count.count += 1,
+            _ => {
+                let count = RouteCount {
+                    component: component.to_string(),
+                    count: 1,
+                };
+                routes.insert(route.to_string(), count);
+            }
+        }
+    }
+
+    /// Returns the invocations of each component, across its routes.
+    pub fn invocations(&self) -> HashMap<String, u64> {
+        let mut invocations = HashMap::new();
+        for route in self.routes.lock().unwrap().values() {
+            *invocations.entry(route.component.clone()).or_default() += route.count;
+        }
+        invocations
+    }
+
+    /// Orders items from the most to the least invoked component, given the
+    /// component of each. Items with the same number of invocations keep
+    /// their order.
+    pub fn order<T>(&self, items: &mut [T], component: impl Fn(&T) -> &str) {
+        let invocations = self.invocations();
+        items.sort_by_key(|item| Reverse(invocations.get(component(item)).copied().unwrap_or(0)));
+    }
+
+    /// Saves the profile to its file, if it has one.
+    pub fn save(&self) -> Result<()> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let routes = self.routes.lock().unwrap().clone();
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, serde_json::to_vec_pretty(&routes)?)
+            .with_context(|| format!("Cannot write route profile {}", path.display()))
+    }
+
+    /// Saves the profile periodically, so that a crash loses little of it.
+    pub(crate) async fn save_periodically(self: Arc<Self>) {
+        if self.path.is_none() {
+            return;
+        }
+        loop {
+            tokio::time::sleep(SAVE_INTERVAL).await;
+            if let Err(e) = self.save() {
+                tracing::warn!("Failed to save route profile: {:#}", e);
+            }
+        }
+    }
+}
+
+fn load_routes(path: &Path) -> Result<BTreeMap<String, RouteCount>> {
+    match std::fs::read(path) {
+        Ok(contents) => serde_json::from_slice(&contents)
+            .with_context(|| format!("Cannot parse route profile {}", path.display())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+        Err(e) => Err(e).with_context(|| format!("Cannot read route profile {}", path.display())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profile_persists_and_decays() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join(PROFILE_FILE);
+        let profile = RouteProfile::file(&path);
+        for _ in 0..8 {
+            profile.record("/api/orders", "orders");
+        }
+        profile.record("/api/users", "users");
+        profile.record("/admin", "admin");
+        profile.record("/admin/...", "admin");
+        profile.save()?;
+
+        let restarted = RouteProfile::file(&path);
+        let invocations = restarted.invocations();
+        assert_eq!(invocations["orders"], 4);
+        assert!(!invocations.contains_key("users"));
+        assert!(!invocations.contains_key("admin"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_components_are_ordered_by_invocations() {
+        let profile = RouteProfile::memory();
+        profile.record("/b", "b");
+        profile.record("/c", "c");
+        profile.record("/c/...", "c");
+
+        let mut ids = vec!["a", "b", "c", "d"];
+        profile.order(&mut ids, |id| id);
+        assert_eq!(ids, ["c", "b", "a", "d"]);
+    }
+}
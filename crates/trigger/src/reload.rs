@@ -0,0 +1,103 @@
+//! Reloading the modules of components when their files change.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use spin_engine::ExecutionContext;
+use spin_manifest::ModuleSource;
+
+/// How often module files are checked for changes.
+pub(crate) const RELOAD_INTERVAL: Duration = Duration::from_millis(500);
+
+/// The size and modification time of a file, which change when it is
+/// rewritten.
+type Stamp = Option<(u64, SystemTime)>;
+
+fn stamp(path: &Path) -> Stamp {
+    let metadata = std::fs::metadata(path).ok()?;
+    Some((metadata.len(), metadata.modified().ok()?))
+}
+
+/// The module file of a component, and the components using it.
+struct Watched {
+    components: Vec<String>,
+    /// The stamp of the module the components run.
+    loaded: Stamp,
+    /// The stamp seen by the previous check.
+    seen: Stamp,
+}
+
+/// Reloads the components whose module file changed, checking at the given
+/// interval. A file is reloaded once it is unchanged across two checks, so
+/// that modules are not read while a build is writing them.
+pub(crate) async fn reload_changed_modules<T: Default + Send + 'static>(
+    execution_context: ExecutionContext<T>,
+    interval: Duration,
+) {
+    let mut files: HashMap<PathBuf, Watched> = HashMap::new();
+    for (id, c) in &execution_context.components {
+        if let ModuleSource::FileReference(path) = &c.core.source {
+            let stamp = stamp(path);
+            files
+                .entry(path.clone())
+                .or_insert(Watched {
+                    components: vec![],
+                    loaded: stamp,
+                    seen: stamp,
+                })
+                .components
+                .push(id.clone());
+        }
+    }
+    if files.is_empty() {
+        tracing::warn!("No component is loaded from a file: nothing to hot reload");
+        return;
+    }
+
+    loop {
+        tokio::time::sleep(interval).await;
+        for (path, watched) in files.iter_mut() {
+            let current = stamp(path);
+            let settled = current == watched.seen;
+            watched.seen = current;
+            if !settled || current.is_none() || current == watched.loaded {
+                continue;
+            }
+            watched.loaded = current;
+            for id in &watched.components {
+                reload(&execution_context, id).await;
+            }
+        }
+    }
+}
+
+async fn reload<T: Default + Send + 'static>(execution_context: &ExecutionContext<T>, id: &str) {
+    match execution_context.reload(id).await {
+        Ok(Some(draining)) => {
+            println!("Reloaded component {}", id);
+            let in_flight = draining.in_flight();
+            if in_flight > 0 {
+                let id = id.to_owned();
+                tokio::spawn(async move {
+                    draining.drained().await;
+                    tracing::info!(
+                        "Drained {} requests to the previous module of component {}",
+                        in_flight,
+                        id
+                    );
+                });
+            }
+        }
+        // The component loads the new module when it is first invoked.
+        Ok(None) => (),
+        Err(e) => {
+            eprintln!(
+                "Failed to reload component {}, keeping the previous module: {:#}",
+                id, e
+            );
+        }
+    }
+}
@@ -0,0 +1,336 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+use ed25519_dalek::{Keypair, PublicKey, Signature, Signer, Verifier};
+use serde::Deserialize;
+use wasi_outbound_http::ALLOW_ALL_HOSTS;
+
+/// Runtime configuration for the host services available to an application.
+///
+/// Unlike the application manifest, this describes the environment an
+/// application runs in, and is loaded from the file passed to
+/// `--runtime-config-file`.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub struct RuntimeConfig {
+    /// The listener serving operators, if any.
+    pub admin: Option<crate::AdminConfig>,
+    /// Blob store containers, by name.
+    #[serde(default)]
+    pub blob_store: HashMap<String, spin_blobstore::ContainerConfig>,
+    /// Bounds of the in-memory cache shared by the components.
+    #[serde(default)]
+    pub cache: spin_cache::CacheConfig,
+    /// Admission of concurrent requests across components.
+    #[serde(default)]
+    pub concurrency: crate::ConcurrencyConfig,
+    /// Where the values of application variables come from, in order of
+    /// precedence. If not set, they come from `SPIN_APP_` environment
+    /// variables.
+    #[serde(default)]
+    pub config_provider: Vec<spin_config::ProviderConfig>,
+    /// Cryptographic keys, by name.
+    #[serde(default)]
+    pub crypto_key: HashMap<String, spin_crypto::KeyConfig>,
+    /// Per-application directories holding the state of applications.
+    #[serde(default)]
+    pub data_dir: spin_engine::DataDirConfig,
+    /// Quotas on the outbound traffic of components.
+    #[serde(default)]
+    pub egress: spin_engine::EgressConfig,
+    /// Databases used to enrich requests with the client's location.
+    #[serde(default)]
+    pub geoip: GeoIpConfig,
+    /// Bounds on the request and response bodies the HTTP trigger holds in
+    /// memory.
+    #[serde(default)]
+    pub http_body: HttpBodyConfig,
+    /// Bounds of the in-memory cache of the responses of HTTP routes with a
+    /// `cache`.
+    #[serde(default)]
+    pub http_cache: spin_cache::CacheConfig,
+    /// Host components loaded from dynamic libraries.
+    #[serde(default)]
+    pub host_plugin: Vec<spin_host_plugins::HostPluginConfig>,
+    /// The store holding the responses of idempotent routes.
+    #[serde(default)]
+    pub idempotency: IdempotencyConfig,
+    /// The pool instances of components are allocated from. If not set,
+    /// instances are allocated on demand.
+    pub instance_pool: Option<spin_engine::InstancePoolConfig>,
+    /// JWT validators and issuers.
+    #[serde(default)]
+    pub jwt: spin_jwt::JwtConfig,
+    /// Component lifecycle hooks.
+    #[serde(default)]
+    pub lifecycle: crate::LifecycleConfig,
+    /// Key-value stores, by name.
+    #[serde(default)]
+    pub key_value: HashMap<String, spin_key_value::StoreConfig>,
+    /// The store holding lock leases.
+    #[serde(default)]
+    pub lock: spin_lock::LockConfig,
+    /// Rotation of component log files.
+    #[serde(default)]
+    pub log_rotation: spin_engine::logs::LogRotationConfig,
+    /// Metrics served by triggers.
+    #[serde(default)]
+    pub metrics: MetricsConfig,
+    /// Restrictions on routes proxied by the HTTP trigger.
+    #[serde(default)]
+    pub proxy: ProxyConfig,
+    /// Rules the HTTP trigger applies to requests before routing them.
+    pub request_rules: Option<RequestRulesConfig>,
+    /// How guests yield to other requests. If not set, guests run until
+    /// they return.
+    pub scheduling: Option<spin_engine::SchedulingConfig>,
+    /// What host services do when their backend is unavailable at startup,
+    /// by service name.
+    #[serde(default)]
+    pub services: HashMap<String, spin_engine::ServiceConfig>,
+    /// Limits on the background tasks components enqueue.
+    #[serde(default)]
+    pub tasks: spin_tasks::TasksConfig,
+    /// Temporary directories provided to components.
+    #[serde(default)]
+    pub temp_dir: spin_engine::TempDirConfig,
+    /// The message broker used for publishing. If not set, components
+    /// cannot publish.
+    pub pubsub: Option<spin_pubsub::PubSubConfig>,
+    /// Models available to components through wasi-nn.
+    #[serde(default)]
+    pub wasi_nn: spin_wasi_nn::WasiNnConfig,
+    /// The identity presented by outbound HTTP requests to internal services,
+    /// for mutual TLS.
+    pub workload_identity: Option<wasi_outbound_http::WorkloadIdentityConfig>,
+}
+
+/// Runtime configuration for the store holding the responses replayed for
+/// requests to idempotent HTTP routes.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "snake_case", tag = "type")]
+pub enum IdempotencyConfig {
+    /// Responses are held in memory, and only replayed by the same Spin
+    /// instance.
+    Memory,
+    /// Responses are held in a Redis server shared by all replicas.
+    Redis {
+        /// Address of the Redis server.
+        address: String,
+    },
+}
+
+impl Default for IdempotencyConfig {
+    fn default() -> Self {
+        Self::Memory
+    }
+}
+
+/// Runtime configuration for the metrics served by triggers.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub struct MetricsConfig {
+    /// The path at which the HTTP trigger serves metrics, in the Prometheus
+    /// text format. If not set, metrics are not served.
+    pub path: Option<String>,
+}
+
+/// MaxMind (MMDB) databases used by triggers to resolve client addresses.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub struct GeoIpConfig {
+    /// A country database, such as GeoLite2-Country.
+    pub country_database: Option<PathBuf>,
+    /// An autonomous system database, such as GeoLite2-ASN.
+    pub asn_database: Option<PathBuf>,
+}
+
+impl GeoIpConfig {
+    /// Whether any database is configured.
+    pub fn is_enabled(&self) -> bool {
+        self.country_database.is_some() || self.asn_database.is_some()
+    }
+}
+
+/// Bounds on the bodies held in memory by the HTTP trigger.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "snake_case")]
+pub struct HttpBodyConfig {
+    /// The size past which the bodies of executors that stream them are
+    /// spooled to temporary files rather than held in memory.
+    pub max_in_memory_bytes: u64,
+    /// The largest request body accepted by executors that need whole
+    /// bodies in memory. Larger requests are rejected.
+    pub max_buffered_bytes: u64,
+    /// The directory of the temporary files bodies are spooled to. If not
+    /// set, the system's temporary directory is used.
+    pub spool_dir: Option<PathBuf>,
+}
+
+impl Default for HttpBodyConfig {
+    fn default() -> Self {
+        Self {
+            max_in_memory_bytes: 1024 * 1024,
+            max_buffered_bytes: 64 * 1024 * 1024,
+            spool_dir: None,
+        }
+    }
+}
+
+/// The rules file of the HTTP trigger.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub struct RequestRulesConfig {
+    /// The TOML file of rules, reloaded whenever it changes.
+    pub file: PathBuf,
+}
+
+/// Restrictions on the upstream services triggers may proxy requests to.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub struct ProxyConfig {
+    /// URLs of the hosts requests may be proxied to. As for components'
+    /// `allowed_http_hosts`, `insecure:allow-all` allows any host.
+    #[serde(default)]
+    pub allowed_hosts: Vec<String>,
+}
+
+impl ProxyConfig {
+    /// Whether requests may be proxied to the given upstream URL.
+    pub fn is_allowed(&self, upstream: &http::Uri) -> bool {
+        self.allowed_hosts.iter().any(|allowed| {
+            allowed == ALLOW_ALL_HOSTS
+                || allowed
+                    .parse::<http::Uri>()
+                    .map(|allowed| allowed.host() == upstream.host())
+                    .unwrap_or(false)
+        })
+    }
+}
+
+impl RuntimeConfig {
+    /// Loads runtime configuration from a TOML file.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Cannot read runtime config file {}", path.display()))?;
+        Self::parse(path, &contents)
+    }
+
+    /// Loads runtime configuration from a TOML file, which must be signed
+    /// by one of the given public keys. The signature is read from the file
+    /// of the same name with the `.sig` extension appended.
+    pub fn from_signed_file(path: impl AsRef<Path>, public_keys: &[String]) -> Result<Self> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Cannot read runtime config file {}", path.display()))?;
+        let signature_path = runtime_config_signature_path(path);
+        let signature = std::fs::read_to_string(&signature_path).with_context(|| {
+            format!(
+                "Cannot read the signature of runtime config file {} from {}",
+                path.display(),
+                signature_path.display()
+            )
+        })?;
+        verify(contents.as_bytes(), signature.trim(), public_keys).with_context(|| {
+            format!(
+                "The signature of runtime config file {} does not verify: it may have been tampered with",
+                path.display()
+            )
+        })?;
+        Self::parse(path, &contents)
+    }
+
+    fn parse(path: &Path, contents: &str) -> Result<Self> {
+        toml::from_str(contents)
+            .with_context(|| format!("Invalid runtime config file {}", path.display()))
+    }
+}
+
+/// The path of the signature of the given runtime config file.
+pub fn runtime_config_signature_path(path: &Path) -> PathBuf {
+    let mut signature = path.as_os_str().to_owned();
+    signature.push(".sig");
+    PathBuf::from(signature)
+}
+
+/// Signs the contents of a runtime config file with an ed25519 key pair,
+/// both encoded in base64 as in bindle secret key files, returning the
+/// signature in base64.
+pub fn sign_runtime_config(contents: &[u8], keypair: &str) -> Result<String> {
+    let keypair = base64::decode(keypair).context("The key pair is not valid base64")?;
+    let keypair = Keypair::from_bytes(&keypair).context("Invalid key pair")?;
+    Ok(base64::encode(keypair.sign(contents).to_bytes()))
+}
+
+/// Checks that the base64 signature of the contents of a runtime config file
+/// was made with one of the given base64 public keys.
+fn verify(contents: &[u8], signature: &str, public_keys: &[String]) -> Result<()> {
+    let signature = base64::decode(signature).context("The signature is not valid base64")?;
+    let signature = Signature::from_bytes(&signature).context("Invalid signature")?;
+    for key in public_keys {
+        let key = base64::decode(key)
+            .ok()
+            .and_then(|key| PublicKey::from_bytes(&key).ok())
+            .with_context(|| format!("Invalid public key {}", key))?;
+        if key.verify(contents, &signature).is_ok() {
+            return Ok(());
+        }
+    }
+    bail!("The file is not signed by any of the trusted keys")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_proxy_allowed_hosts() {
+        let upstream = "http://localhost:8080/api".parse().unwrap();
+        let config = |hosts: &[&str]| ProxyConfig {
+            allowed_hosts: hosts.iter().map(|h| h.to_string()).collect(),
+        };
+        assert!(!config(&[]).is_allowed(&upstream));
+        assert!(!config(&["https://example.com"]).is_allowed(&upstream));
+        assert!(config(&["http://localhost:8080"]).is_allowed(&upstream));
+        assert!(config(&[ALLOW_ALL_HOSTS]).is_allowed(&upstream));
+    }
+
+    fn test_keys(seed: u8) -> (String, String) {
+        let secret = ed25519_dalek::SecretKey::from_bytes(&[seed; 32]).unwrap();
+        let public = PublicKey::from(&secret);
+        let keypair = Keypair { secret, public };
+        (
+            base64::encode(keypair.to_bytes()),
+            base64::encode(public.to_bytes()),
+        )
+    }
+
+    #[test]
+    fn test_signed_file() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("runtime-config.toml");
+        let contents = "[metrics]\npath = \"/metrics\"\n";
+        std::fs::write(&path, contents)?;
+        let (keypair, public_key) = test_keys(1);
+        let (_, other_key) = test_keys(2);
+
+        // Unsigned files are rejected.
+        assert!(RuntimeConfig::from_signed_file(&path, &[public_key.clone()]).is_err());
+
+        let signature = sign_runtime_config(contents.as_bytes(), &keypair)?;
+        std::fs::write(runtime_config_signature_path(&path), signature)?;
+        let trusted = [other_key.clone(), public_key.clone()];
+        let config = RuntimeConfig::from_signed_file(&path, &trusted)?;
+        assert_eq!(config.metrics.path.as_deref(), Some("/metrics"));
+        assert!(RuntimeConfig::from_signed_file(&path, &[other_key]).is_err());
+
+        // Tampering with the file invalidates the signature.
+        std::fs::write(&path, "[metrics]\npath = \"/other\"\n")?;
+        assert!(RuntimeConfig::from_signed_file(&path, &[public_key]).is_err());
+        Ok(())
+    }
+}
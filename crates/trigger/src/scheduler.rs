@@ -0,0 +1,519 @@
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::{bail, Context, Result};
+use futures::channel::oneshot;
+use serde::Deserialize;
+use spin_manifest::RoutePriority;
+
+/// Runtime configuration for request admission across components.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub struct ConcurrencyConfig {
+    /// The maximum number of requests executing at once, across all
+    /// components. If not set, requests are not limited.
+    pub max_requests: Option<usize>,
+    /// Relative weights of components when requests are queued. Components
+    /// not listed have a weight of 1.
+    #[serde(default)]
+    pub weights: HashMap<String, u32>,
+    /// The maximum number of requests waiting to execute, across all
+    /// components. If not set, the queue is not limited.
+    pub max_queued: Option<usize>,
+    /// Which request is shed when the queue is full.
+    #[serde(default)]
+    pub shed: ShedPolicy,
+    /// The maximum time, in milliseconds, a request may wait to execute
+    /// before it is shed. If not set, requests wait indefinitely.
+    pub max_queue_ms: Option<u64>,
+    /// Limits on the requests each component executes at once, adjusted
+    /// from observed latency. If not set, components are not limited.
+    pub adaptive: Option<crate::AdaptiveConfig>,
+}
+
+/// Which request is shed when the request queue is full and no request of a
+/// lower priority than the incoming one is waiting.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ShedPolicy {
+    /// The incoming request is rejected.
+    RejectNew,
+    /// The request that has waited longest is rejected, and the incoming
+    /// request is queued.
+    DropOldest,
+}
+
+impl Default for ShedPolicy {
+    fn default() -> Self {
+        Self::RejectNew
+    }
+}
+
+/// The error returned when a request is shed because the scheduler is
+/// overloaded.
+#[derive(Debug)]
+pub struct Overloaded;
+
+impl std::fmt::Display for Overloaded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("request shed because the runtime is overloaded")
+    }
+}
+
+impl std::error::Error for Overloaded {}
+
+/// A snapshot of the requests executing and waiting in a scheduler.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SchedulerStats {
+    /// The maximum number of requests executing at once.
+    pub max_requests: usize,
+    /// Requests executing, by component.
+    pub executing: BTreeMap<String, usize>,
+    /// Requests waiting to execute, by component.
+    pub queued: BTreeMap<String, usize>,
+    /// Requests shed since the scheduler was created, by component.
+    pub shed: BTreeMap<String, u64>,
+}
+
+/// Admits requests to components once total concurrency is saturated.
+///
+/// When a request completes, the next request admitted is one of the
+/// highest priority waiting: the oldest one for the component with the
+/// fewest executing requests relative to its weight, so that a burst against
+/// one component cannot starve others of the same priority.
+///
+/// The queue of waiting requests may be bounded, in which case requests are
+/// shed once it is full, rather than letting memory use and latency grow
+/// without limit under overload. The oldest request of a lower priority than
+/// the incoming one is shed first, and otherwise the shed policy applies.
+pub struct Scheduler {
+    max_requests: usize,
+    weights: HashMap<String, u32>,
+    max_queued: Option<usize>,
+    shed: ShedPolicy,
+    max_queue_wait: Option<Duration>,
+    state: Mutex<State>,
+}
+
+type Admission = std::result::Result<Permit, Overloaded>;
+
+struct Waiter {
+    /// The order in which the request was queued.
+    seq: u64,
+    tx: oneshot::Sender<Admission>,
+}
+
+#[derive(Default)]
+struct State {
+    executing: usize,
+    executing_by_component: HashMap<String, usize>,
+    /// Waiting requests by priority, then by component.
+    waiting: BTreeMap<RoutePriority, HashMap<String, VecDeque<Waiter>>>,
+    next_seq: u64,
+    shed: HashMap<String, u64>,
+}
+
+impl Scheduler {
+    /// Creates a scheduler, or returns `None` if requests are not limited.
+    pub fn new(config: &ConcurrencyConfig) -> Result<Option<Arc<Self>>> {
+        let max_requests = match config.max_requests {
+            Some(max_requests) => max_requests,
+            None => return Ok(None),
+        };
+        if max_requests == 0 {
+            bail!("max_requests must be greater than zero");
+        }
+        if let Some((component, _)) = config.weights.iter().find(|(_, w)| **w == 0) {
+            bail!("weight of component {} must be greater than zero", component);
+        }
+        Ok(Some(Arc::new(Self {
+            max_requests,
+            weights: config.weights.clone(),
+            max_queued: config.max_queued,
+            shed: config.shed,
+            max_queue_wait: config.max_queue_ms.map(Duration::from_millis),
+            state: Default::default(),
+        })))
+    }
+
+    /// Waits until a request to the given component may execute. The
+    /// request holds its slot until the returned permit is dropped. Fails
+    /// with `Overloaded` if the request is shed.
+    pub async fn admit(self: &Arc<Self>, component: &str) -> Result<Permit> {
+        self.admit_with_priority(component, RoutePriority::Normal)
+            .await
+    }
+
+    /// Waits until a request of the given priority to the given component
+    /// may execute, ahead of waiting requests of lower priorities.
+    pub async fn admit_with_priority(
+        self: &Arc<Self>,
+        component: &str,
+        priority: RoutePriority,
+    ) -> Result<Permit> {
+        let rx = {
+            let mut state = self.state.lock().unwrap();
+            if state.executing < self.max_requests {
+                state.acquire(component);
+                return Ok(self.permit(component));
+            }
+            if self.max_queued.map_or(false, |max| state.queued() >= max) {
+                let lowest = state.waiting.keys().next_back().copied();
+                match (lowest, self.shed) {
+                    (Some(lowest), _) if lowest > priority => state.shed_oldest(lowest),
+                    (Some(lowest), ShedPolicy::DropOldest) if lowest == priority => {
+                        state.shed_oldest(lowest)
+                    }
+                    _ => {
+                        state.record_shed(component);
+                        return Err(Overloaded.into());
+                    }
+                }
+            }
+            let (tx, rx) = oneshot::channel();
+            let seq = state.next_seq;
+            state.next_seq += 1;
+            state
+                .waiting
+                .entry(priority)
+                .or_default()
+                .entry(component.to_string())
+                .or_default()
+                .push_back(Waiter { seq, tx });
+            rx
+        };
+
+        let admission = match self.max_queue_wait {
+            Some(max_wait) => match tokio::time::timeout(max_wait, rx).await {
+                Ok(admission) => admission,
+                Err(_) => {
+                    // Dropping the receiver leaves the waiter to be skipped
+                    // when a slot is freed.
+                    self.state.lock().unwrap().record_shed(component);
+                    return Err(Overloaded.into());
+                }
+            },
+            None => rx.await,
+        };
+        Ok(admission.context("Request scheduler stopped")??)
+    }
+
+    /// Returns a snapshot of the requests executing and waiting.
+    pub fn stats(&self) -> SchedulerStats {
+        let state = self.state.lock().unwrap();
+        SchedulerStats {
+            max_requests: self.max_requests,
+            executing: state
+                .executing_by_component
+                .iter()
+                .map(|(c, n)| (c.clone(), *n))
+                .collect(),
+            queued: state.waiting.values().flatten().fold(
+                BTreeMap::new(),
+                |mut queued, (c, queue)| {
+                    let n = queue.iter().filter(|w| !w.tx.is_canceled()).count();
+                    if n > 0 {
+                        *queued.entry(c.clone()).or_default() += n;
+                    }
+                    queued
+                },
+            ),
+            shed: state.shed.iter().map(|(c, n)| (c.clone(), *n)).collect(),
+        }
+    }
+
+    fn permit(self: &Arc<Self>, component: &str) -> Permit {
+        Permit {
+            scheduler: self.clone(),
+            component: Some(component.to_string()),
+        }
+    }
+
+    fn weight(&self, component: &str) -> u64 {
+        self.weights.get(component).copied().unwrap_or(1) as u64
+    }
+
+    fn release(self: &Arc<Self>, component: &str) {
+        let mut state = self.state.lock().unwrap();
+        state.release(component);
+        while let Some((next, tx)) = self.next_waiter(&mut state) {
+            state.acquire(&next);
+            if let Err(Ok(mut permit)) = tx.send(Ok(self.permit(&next))) {
+                // The request was cancelled while waiting.
+                state.release(&next);
+                permit.component = None;
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn next_waiter(&self, state: &mut State) -> Option<(String, oneshot::Sender<Admission>)> {
+        let (&priority, waiting) = state.waiting.iter().next()?;
+        let next = waiting
+            .iter()
+            .filter(|(_, queue)| !queue.is_empty())
+            .map(|(component, _)| component)
+            .min_by(|a, b| {
+                let executing = |c: &str| {
+                    state.executing_by_component.get(c).copied().unwrap_or(0) as u64
+                };
+                // Compare executing / weight without dividing.
+                (executing(a) * self.weight(b))
+                    .cmp(&(executing(b) * self.weight(a)))
+                    .then_with(|| self.weight(b).cmp(&self.weight(a)))
+                    .then_with(|| a.cmp(b))
+            })?
+            .clone();
+        let waiter = state.pop_front(priority, &next)?;
+        Some((next, waiter.tx))
+    }
+}
+
+impl State {
+    /// The number of requests waiting, not counting cancelled requests.
+    fn queued(&mut self) -> usize {
+        for waiting in self.waiting.values_mut() {
+            for queue in waiting.values_mut() {
+                queue.retain(|waiter| !waiter.tx.is_canceled());
+            }
+            waiting.retain(|_, queue| !queue.is_empty());
+        }
+        self.waiting.retain(|_, waiting| !waiting.is_empty());
+        self.waiting
+            .values()
+            .flat_map(HashMap::values)
+            .map(VecDeque::len)
+            .sum()
+    }
+
+    /// Removes the oldest waiting request of a priority for a component.
+    fn pop_front(&mut self, priority: RoutePriority, component: &str) -> Option<Waiter> {
+        let waiting = self.waiting.get_mut(&priority)?;
+        let queue = waiting.get_mut(component)?;
+        let waiter = queue.pop_front();
+        if queue.is_empty() {
+            waiting.remove(component);
+            if waiting.is_empty() {
+                self.waiting.remove(&priority);
+            }
+        }
+        waiter
+    }
+
+    fn record_shed(&mut self, component: &str) {
+        *self.shed.entry(component.to_string()).or_default() += 1;
+    }
+
+    /// Sheds the request of a priority that has waited longest, across all
+    /// components.
+    fn shed_oldest(&mut self, priority: RoutePriority) {
+        let oldest = self.waiting.get(&priority).and_then(|waiting| {
+            waiting
+                .iter()
+                .filter_map(|(component, queue)| Some((queue.front()?.seq, component.clone())))
+                .min()
+        });
+        if let Some((_, component)) = oldest {
+            let waiter = self.pop_front(priority, &component).unwrap();
+            if waiter.tx.send(Err(Overloaded)).is_ok() {
+                self.record_shed(&component);
+            }
+        }
+    }
+
+    fn acquire(&mut self, component: &str) {
+        self.executing += 1;
+        *self
+            .executing_by_component
+            .entry(component.to_string())
+            .or_default() += 1;
+    }
+
+    fn release(&mut self, component: &str) {
+        self.executing -= 1;
+        if let Some(count) = self.executing_by_component.get_mut(component) {
+            *count -= 1;
+            if *count == 0 {
+                self.executing_by_component.remove(component);
+            }
+        }
+    }
+}
+
+/// A slot for an executing request, released when dropped.
+pub struct Permit {
+    scheduler: Arc<Scheduler>,
+    component: Option<String>,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        if let Some(component) = self.component.take() {
+            self.scheduler.release(&component);
+        }
+    }
+}
+
+impl std::fmt::Debug for Permit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Permit")
+            .field("component", &self.component)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::FutureExt;
+
+    use super::*;
+
+    fn scheduler(max_requests: usize, weights: &[(&str, u32)]) -> Arc<Scheduler> {
+        Scheduler::new(&ConcurrencyConfig {
+            max_requests: Some(max_requests),
+            weights: weights.iter().map(|(c, w)| (c.to_string(), *w)).collect(),
+            ..Default::default()
+        })
+        .unwrap()
+        .unwrap()
+    }
+
+    fn bounded_scheduler(max_queued: usize, shed: ShedPolicy) -> Arc<Scheduler> {
+        Scheduler::new(&ConcurrencyConfig {
+            max_requests: Some(1),
+            max_queued: Some(max_queued),
+            shed,
+            ..Default::default()
+        })
+        .unwrap()
+        .unwrap()
+    }
+
+    fn is_overloaded(result: Option<Result<Permit>>) -> bool {
+        matches!(result, Some(Err(e)) if e.is::<Overloaded>())
+    }
+
+    #[test]
+    fn test_waiting_components_share_released_slots() {
+        let scheduler = scheduler(2, &[]);
+        let a1 = scheduler.admit("a").now_or_never().unwrap().unwrap();
+        let _a2 = scheduler.admit("a").now_or_never().unwrap().unwrap();
+
+        let mut a3 = Box::pin(scheduler.admit("a"));
+        let mut b1 = Box::pin(scheduler.admit("b"));
+        assert!((&mut a3).now_or_never().is_none());
+        assert!((&mut b1).now_or_never().is_none());
+
+        // Although a3 was queued first, b has no executing requests.
+        drop(a1);
+        assert!((&mut a3).now_or_never().is_none());
+        assert!((&mut b1).now_or_never().unwrap().is_ok());
+    }
+
+    #[test]
+    fn test_weights_prioritize_components() {
+        let scheduler = scheduler(4, &[("checkout", 3)]);
+        let _c1 = scheduler.admit("checkout").now_or_never().unwrap().unwrap();
+        let _c2 = scheduler.admit("checkout").now_or_never().unwrap().unwrap();
+        let _r1 = scheduler.admit("reports").now_or_never().unwrap().unwrap();
+        let x1 = scheduler.admit("other").now_or_never().unwrap().unwrap();
+
+        let mut r2 = Box::pin(scheduler.admit("reports"));
+        let mut c3 = Box::pin(scheduler.admit("checkout"));
+        assert!((&mut r2).now_or_never().is_none());
+        assert!((&mut c3).now_or_never().is_none());
+
+        // Checkout is executing more requests, but fewer relative to its weight.
+        drop(x1);
+        assert!((&mut r2).now_or_never().is_none());
+        assert!((&mut c3).now_or_never().is_some());
+    }
+
+    #[test]
+    fn test_cancelled_requests_release_slots() {
+        let scheduler = scheduler(1, &[]);
+        let a1 = scheduler.admit("a").now_or_never().unwrap().unwrap();
+        let mut a2 = Box::pin(scheduler.admit("a"));
+        assert!((&mut a2).now_or_never().is_none());
+        drop(a2);
+
+        drop(a1);
+        assert!(scheduler.admit("b").now_or_never().is_some());
+    }
+
+    #[test]
+    fn test_full_queue_rejects_new_requests() {
+        let scheduler = bounded_scheduler(1, ShedPolicy::RejectNew);
+        let _a1 = scheduler.admit("a").now_or_never().unwrap().unwrap();
+        let mut a2 = Box::pin(scheduler.admit("a"));
+        assert!((&mut a2).now_or_never().is_none());
+
+        assert!(is_overloaded(scheduler.admit("b").now_or_never()));
+        assert!((&mut a2).now_or_never().is_none());
+
+        let stats = scheduler.stats();
+        assert_eq!(stats.executing["a"], 1);
+        assert_eq!(stats.queued["a"], 1);
+        assert_eq!(stats.shed["b"], 1);
+    }
+
+    #[test]
+    fn test_full_queue_drops_oldest_requests() {
+        let scheduler = bounded_scheduler(1, ShedPolicy::DropOldest);
+        let a1 = scheduler.admit("a").now_or_never().unwrap().unwrap();
+        let mut a2 = Box::pin(scheduler.admit("a"));
+        assert!((&mut a2).now_or_never().is_none());
+
+        let mut b1 = Box::pin(scheduler.admit("b"));
+        assert!((&mut b1).now_or_never().is_none());
+        assert!(is_overloaded((&mut a2).now_or_never()));
+
+        drop(a1);
+        assert!((&mut b1).now_or_never().unwrap().is_ok());
+        assert_eq!(scheduler.stats().shed["a"], 1);
+    }
+
+    #[test]
+    fn test_higher_priorities_are_admitted_first() {
+        let scheduler = scheduler(1, &[("api", 10)]);
+        let a1 = scheduler.admit("api").now_or_never().unwrap().unwrap();
+        let mut a2 = Box::pin(scheduler.admit("api"));
+        let mut h1 = Box::pin(scheduler.admit_with_priority("health", RoutePriority::High));
+        assert!((&mut a2).now_or_never().is_none());
+        assert!((&mut h1).now_or_never().is_none());
+
+        // Although api is queued first with a higher weight, health has a
+        // higher priority.
+        drop(a1);
+        assert!((&mut a2).now_or_never().is_none());
+        let h1 = (&mut h1).now_or_never().unwrap().unwrap();
+        drop(h1);
+        assert!((&mut a2).now_or_never().unwrap().is_ok());
+    }
+
+    #[test]
+    fn test_full_queue_sheds_lower_priorities_first() {
+        let scheduler = bounded_scheduler(1, ShedPolicy::RejectNew);
+        let _a1 = scheduler.admit("a").now_or_never().unwrap().unwrap();
+        let mut low = Box::pin(scheduler.admit_with_priority("report", RoutePriority::Low));
+        assert!((&mut low).now_or_never().is_none());
+
+        let mut a2 = Box::pin(scheduler.admit("a"));
+        assert!((&mut a2).now_or_never().is_none());
+        assert!(is_overloaded((&mut low).now_or_never()));
+
+        // Queued requests of the same or a higher priority are kept.
+        assert!(is_overloaded(
+            scheduler
+                .admit_with_priority("report", RoutePriority::Low)
+                .now_or_never()
+        ));
+        assert!(is_overloaded(scheduler.admit("b").now_or_never()));
+        assert!((&mut a2).now_or_never().is_none());
+        assert_eq!(scheduler.stats().shed["report"], 2);
+    }
+}
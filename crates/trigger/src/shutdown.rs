@@ -0,0 +1,68 @@
+use std::time::Duration;
+
+use tokio::sync::watch;
+
+/// How long in-flight work may take to complete once shutdown is requested,
+/// unless configured otherwise.
+pub const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Tells a trigger executor to stop accepting work, and to return from
+/// `run` once the work in flight completes.
+#[derive(Clone, Debug)]
+pub struct ShutdownSignal {
+    requested: watch::Receiver<bool>,
+}
+
+impl ShutdownSignal {
+    /// Creates a signal, with the function requesting shutdown.
+    pub fn new() -> (Self, impl Fn() + Send + Sync + 'static) {
+        let (sender, requested) = watch::channel(false);
+        (Self { requested }, move || {
+            let _ = sender.send(true);
+        })
+    }
+
+    /// Completes once shutdown is requested, or never if it cannot be.
+    pub async fn requested(mut self) {
+        while !*self.requested.borrow() {
+            if self.requested.changed().await.is_err() {
+                return futures::future::pending().await;
+            }
+        }
+    }
+
+    /// Whether shutdown has been requested.
+    pub fn is_requested(&self) -> bool {
+        *self.requested.borrow()
+    }
+}
+
+impl Default for ShutdownSignal {
+    /// A signal that is never requested.
+    fn default() -> Self {
+        Self::new().0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::FutureExt;
+
+    use super::*;
+
+    #[test]
+    fn test_shutdown_signal() {
+        let (signal, request) = ShutdownSignal::new();
+        assert!(!signal.is_requested());
+        assert!(signal.clone().requested().now_or_never().is_none());
+
+        request();
+        assert!(signal.is_requested());
+        assert!(signal.requested().now_or_never().is_some());
+
+        assert!(ShutdownSignal::default()
+            .requested()
+            .now_or_never()
+            .is_none());
+    }
+}
@@ -0,0 +1,119 @@
+//! The logs of the triggers, and the traces and metrics they export with
+//! OpenTelemetry.
+
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use clap::Args;
+use futures::{Stream, StreamExt};
+use opentelemetry::{
+    global,
+    sdk::{metrics::PushController, propagation::TraceContextPropagator, trace, Resource},
+    KeyValue,
+};
+use opentelemetry_otlp::WithExportConfig;
+use tracing_subscriber::{filter::LevelFilter, fmt, prelude::*, registry, EnvFilter};
+
+pub const OTLP_ENDPOINT: &str = "OTEL_EXPORTER_OTLP_ENDPOINT";
+pub const OTLP_SERVICE_NAME: &str = "OTEL_SERVICE_NAME";
+
+/// The default interval at which metrics are exported.
+const DEFAULT_METRICS_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Where traces and metrics are exported to.
+#[derive(Args, Clone, Debug, Default)]
+pub struct TelemetryOpts {
+    /// The OTLP gRPC endpoint to export traces and metrics to, such as
+    /// http://localhost:4317. Nothing is exported by default.
+    #[clap(name = OTLP_ENDPOINT, long = "otlp-endpoint", env = OTLP_ENDPOINT)]
+    pub otlp_endpoint: Option<String>,
+
+    /// The service name traces and metrics are exported under. Defaults to
+    /// `spin`.
+    #[clap(long = "otlp-service-name", env = OTLP_SERVICE_NAME)]
+    pub service_name: Option<String>,
+
+    /// How often metrics are exported, in seconds.
+    #[clap(long = "otlp-metrics-interval", value_name = "SECONDS")]
+    pub metrics_interval: Option<u64>,
+}
+
+/// Flushes the traces and metrics not yet exported when dropped.
+pub struct TelemetryGuard {
+    metrics: Option<PushController>,
+}
+
+impl Drop for TelemetryGuard {
+    fn drop(&mut self) {
+        if self.metrics.take().is_some() {
+            // Dropping the controller exports the last metrics.
+            global::shutdown_tracer_provider();
+        }
+    }
+}
+
+/// Installs the subscriber printing logs to stderr, filtered by `RUST_LOG`,
+/// and, if an OTLP endpoint is given, exporting spans at the info level and
+/// above, and the metrics recorded with the global meter, to it.
+///
+/// The trace context of incoming requests is only continued, and propagated
+/// to outbound requests, when traces are exported.
+pub fn init(opts: Option<&TelemetryOpts>) -> Result<TelemetryGuard> {
+    let logs = fmt::layer()
+        .with_writer(std::io::stderr)
+        .with_ansi(atty::is(atty::Stream::Stderr))
+        .with_filter(EnvFilter::from_default_env());
+
+    let (opts, endpoint) = match opts.and_then(|o| Some((o, o.otlp_endpoint.as_ref()?))) {
+        Some(exported) => exported,
+        None => {
+            registry().with(logs).init();
+            return Ok(TelemetryGuard { metrics: None });
+        }
+    };
+    let service_name = opts.service_name.as_deref().unwrap_or("spin").to_owned();
+    let resource = Resource::new(vec![KeyValue::new("service.name", service_name)]);
+
+    let tracer = opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(
+            opentelemetry_otlp::new_exporter()
+                .tonic()
+                .with_endpoint(endpoint),
+        )
+        .with_trace_config(trace::config().with_resource(resource.clone()))
+        .install_batch(opentelemetry::runtime::Tokio)
+        .with_context(|| format!("Cannot export traces to {}", endpoint))?;
+    let metrics = opentelemetry_otlp::new_pipeline()
+        .metrics(tokio::spawn, delayed_interval)
+        .with_exporter(
+            opentelemetry_otlp::new_exporter()
+                .tonic()
+                .with_endpoint(endpoint),
+        )
+        .with_resource(resource)
+        .with_period(
+            opts.metrics_interval
+                .map(Duration::from_secs)
+                .unwrap_or(DEFAULT_METRICS_INTERVAL),
+        )
+        .build()
+        .with_context(|| format!("Cannot export metrics to {}", endpoint))?;
+    global::set_text_map_propagator(TraceContextPropagator::new());
+
+    let traces = tracing_opentelemetry::layer()
+        .with_tracer(tracer)
+        .with_filter(LevelFilter::INFO);
+    registry().with(logs).with(traces).init();
+    tracing::debug!("Exporting traces and metrics to {}", endpoint);
+
+    Ok(TelemetryGuard {
+        metrics: Some(metrics),
+    })
+}
+
+/// Ticks at the given interval, starting after the first interval rather
+/// than immediately.
+fn delayed_interval(duration: Duration) -> impl Stream<Item = tokio::time::Instant> {
+    opentelemetry::sdk::util::tokio_interval_stream(duration).skip(1)
+}
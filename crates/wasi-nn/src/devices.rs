@@ -0,0 +1,433 @@
+//! Scheduling of inference on the devices of the host.
+//!
+//! Each device runs a bounded number of computations at once. Components
+//! assigned to a device wait in its queue for a free slot, and may be held
+//! to a quota of its slots, so that one workload cannot take all of them.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Condvar, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Result};
+use serde::Deserialize;
+
+use crate::{Backend, WasiNnConfig};
+
+/// Runtime configuration for a device models run on.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub struct DeviceConfig {
+    /// The kind of device.
+    #[serde(default)]
+    pub target: ExecutionTarget,
+    /// The number of computations the device runs at once.
+    #[serde(default = "default_slots")]
+    pub slots: usize,
+    /// The maximum number of computations waiting for the device. If not
+    /// set, the queue is not limited.
+    pub max_queued: Option<usize>,
+    /// The maximum time, in milliseconds, a computation may wait for the
+    /// device. If not set, computations wait indefinitely.
+    pub max_queue_ms: Option<u64>,
+}
+
+fn default_slots() -> usize {
+    1
+}
+
+/// Runtime configuration for the device a component's models run on.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub struct ComponentDeviceConfig {
+    /// The name of the device.
+    pub device: String,
+    /// The maximum number of the device's slots the component uses at once.
+    /// If not set, the component may use all of them.
+    pub quota: Option<usize>,
+}
+
+/// The kind of device a model runs on, numbered as in wasi-nn.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ExecutionTarget {
+    /// The CPU.
+    Cpu = 0,
+    /// A GPU.
+    Gpu = 1,
+    /// A TPU or other inference accelerator.
+    Tpu = 2,
+}
+
+impl Default for ExecutionTarget {
+    fn default() -> Self {
+        Self::Cpu
+    }
+}
+
+impl ExecutionTarget {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Cpu => "cpu",
+            Self::Gpu => "gpu",
+            Self::Tpu => "tpu",
+        }
+    }
+}
+
+/// Why a computation was not given a slot on its device.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Rejected {
+    /// The queue of the device was full.
+    QueueFull,
+    /// The computation waited longer than the maximum queue time.
+    TimedOut,
+}
+
+/// The devices of the host, and the components assigned to them.
+#[derive(Default)]
+pub struct Devices {
+    devices: BTreeMap<String, Arc<Device>>,
+    assignments: HashMap<String, Assignment>,
+    default_assignment: Option<Assignment>,
+}
+
+/// The device a component runs on, and its quota of the device's slots.
+#[derive(Clone)]
+pub(crate) struct Assignment {
+    device: Arc<Device>,
+    quota: usize,
+}
+
+impl Devices {
+    /// Creates the configured devices, checking that the backend can run
+    /// models on them.
+    pub(crate) fn new(config: &WasiNnConfig) -> Result<Self> {
+        let mut devices = BTreeMap::new();
+        for (name, device) in &config.device {
+            match (config.backend, device.target) {
+                (Backend::Tract, ExecutionTarget::Cpu) => {}
+                (backend, target) => bail!(
+                    "Device {}: the {:?} backend cannot run models on {} devices",
+                    name,
+                    backend,
+                    target.name()
+                ),
+            }
+            if device.slots == 0 {
+                bail!("Device {}: slots must be at least 1", name);
+            }
+            devices.insert(name.clone(), Arc::new(Device::new(device)));
+        }
+        let assign = |owner: &str, config: &ComponentDeviceConfig| -> Result<Assignment> {
+            let device = match devices.get(&config.device) {
+                Some(device) => device.clone(),
+                None => bail!("{} is assigned to unknown device {}", owner, config.device),
+            };
+            let quota = config.quota.unwrap_or(device.slots);
+            if quota == 0 {
+                bail!("{}: quota must be at least 1", owner);
+            }
+            Ok(Assignment { device, quota })
+        };
+        let assignments = config
+            .component
+            .iter()
+            .map(|(id, c)| Ok((id.clone(), assign(&format!("Component {}", id), c)?)))
+            .collect::<Result<_>>()?;
+        let default_assignment = config
+            .default_device
+            .as_ref()
+            .map(|device| {
+                assign(
+                    "The default device",
+                    &ComponentDeviceConfig {
+                        device: device.clone(),
+                        quota: None,
+                    },
+                )
+            })
+            .transpose()?;
+        Ok(Self {
+            devices,
+            assignments,
+            default_assignment,
+        })
+    }
+
+    /// The device the given component runs on, if it is scheduled.
+    pub(crate) fn assignment(&self, component: &str) -> Option<Assignment> {
+        self.assignments
+            .get(component)
+            .or(self.default_assignment.as_ref())
+            .cloned()
+    }
+
+    /// A snapshot of the use of each device.
+    pub fn stats(&self) -> BTreeMap<String, DeviceStats> {
+        self.devices
+            .iter()
+            .map(|(name, device)| (name.clone(), device.stats()))
+            .collect()
+    }
+}
+
+impl Assignment {
+    /// The kind of device the component runs on.
+    pub(crate) fn target(&self) -> ExecutionTarget {
+        self.device.target
+    }
+
+    /// Waits for a slot on the device for the component, within its quota.
+    pub(crate) fn acquire(&self, component: &str) -> Result<Slot, Rejected> {
+        self.device.acquire(component, self.quota)
+    }
+}
+
+/// A snapshot of the use of a device.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DeviceStats {
+    /// The kind of device.
+    pub target: &'static str,
+    /// The number of computations the device runs at once.
+    pub slots: usize,
+    /// The total time computations have run on the device, which divided by
+    /// the slots and elapsed time gives its utilization.
+    pub busy: Duration,
+    /// Computations running, by component.
+    pub executing: BTreeMap<String, usize>,
+    /// Computations waiting for a slot, by component.
+    pub queued: BTreeMap<String, usize>,
+    /// Computations completed, by component.
+    pub completed: BTreeMap<String, u64>,
+    /// Computations rejected because the queue was full or they waited too
+    /// long, by component.
+    pub rejected: BTreeMap<String, u64>,
+}
+
+struct Device {
+    target: ExecutionTarget,
+    slots: usize,
+    max_queued: Option<usize>,
+    max_queue_time: Option<Duration>,
+    state: Mutex<DeviceState>,
+    freed: Condvar,
+}
+
+#[derive(Default)]
+struct DeviceState {
+    executing: usize,
+    queued: usize,
+    busy: Duration,
+    components: BTreeMap<String, ComponentUse>,
+}
+
+impl DeviceState {
+    fn usage(&mut self, component: &str) -> &mut ComponentUse {
+        self.components.entry(component.to_owned()).or_default()
+    }
+
+    fn by_component<V>(&self, f: impl Fn(&ComponentUse) -> V) -> BTreeMap<String, V> {
+        self.components
+            .iter()
+            .map(|(id, usage)| (id.clone(), f(usage)))
+            .collect()
+    }
+}
+
+#[derive(Default)]
+struct ComponentUse {
+    executing: usize,
+    queued: usize,
+    completed: u64,
+    rejected: u64,
+}
+
+impl Device {
+    fn new(config: &DeviceConfig) -> Self {
+        Self {
+            target: config.target,
+            slots: config.slots,
+            max_queued: config.max_queued,
+            max_queue_time: config.max_queue_ms.map(Duration::from_millis),
+            state: Default::default(),
+            freed: Condvar::new(),
+        }
+    }
+
+    fn acquire(self: &Arc<Self>, component: &str, quota: usize) -> Result<Slot, Rejected> {
+        let deadline = self.max_queue_time.map(|t| Instant::now() + t);
+        let mut state = self.state.lock().unwrap();
+        let is_free = |state: &DeviceState| {
+            state.executing < self.slots
+                && state
+                    .components
+                    .get(component)
+                    .map_or(true, |c| c.executing < quota)
+        };
+        if !is_free(&state) {
+            if self.max_queued.map_or(false, |max| state.queued >= max) {
+                state.usage(component).rejected += 1;
+                return Err(Rejected::QueueFull);
+            }
+            state.queued += 1;
+            state.usage(component).queued += 1;
+            while !is_free(&state) {
+                state = match deadline {
+                    None => self.freed.wait(state).unwrap(),
+                    Some(deadline) => {
+                        let now = Instant::now();
+                        if now >= deadline {
+                            state.queued -= 1;
+                            let usage = state.usage(component);
+                            usage.queued -= 1;
+                            usage.rejected += 1;
+                            return Err(Rejected::TimedOut);
+                        }
+                        self.freed.wait_timeout(state, deadline - now).unwrap().0
+                    }
+                };
+            }
+            state.queued -= 1;
+            state.usage(component).queued -= 1;
+        }
+        state.executing += 1;
+        state.usage(component).executing += 1;
+        Ok(Slot {
+            device: self.clone(),
+            component: component.to_owned(),
+            started: Instant::now(),
+        })
+    }
+
+    fn stats(&self) -> DeviceStats {
+        let state = self.state.lock().unwrap();
+        DeviceStats {
+            target: self.target.name(),
+            slots: self.slots,
+            busy: state.busy,
+            executing: state.by_component(|c| c.executing),
+            queued: state.by_component(|c| c.queued),
+            completed: state.by_component(|c| c.completed),
+            rejected: state.by_component(|c| c.rejected),
+        }
+    }
+}
+
+/// A slot on a device, held while a computation runs on it.
+pub(crate) struct Slot {
+    device: Arc<Device>,
+    component: String,
+    started: Instant,
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        let mut state = self.device.state.lock().unwrap();
+        state.executing -= 1;
+        state.busy += self.started.elapsed();
+        let usage = state.usage(&self.component);
+        usage.executing -= 1;
+        usage.completed += 1;
+        drop(state);
+        // Waiters may be held by their component's quota rather than by the
+        // device, so all of them check again.
+        self.device.freed.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn devices(config: &str) -> Result<Devices> {
+        Devices::new(&toml::from_str(config)?)
+    }
+
+    #[test]
+    fn test_quota_and_queue_limits() -> Result<()> {
+        let devices = devices(
+            r#"
+            [device.cpu0]
+            slots = 2
+            max_queued = 0
+
+            [component.chat]
+            device = "cpu0"
+            quota = 1
+
+            [component.vision]
+            device = "cpu0"
+            "#,
+        )?;
+        let chat = devices.assignment("chat").unwrap();
+        let vision = devices.assignment("vision").unwrap();
+        assert!(devices.assignment("other").is_none());
+
+        let held = chat.acquire("chat").unwrap();
+        // The chat component is at its quota, and nothing may queue.
+        assert_eq!(chat.acquire("chat").err(), Some(Rejected::QueueFull));
+        let other = vision.acquire("vision").unwrap();
+        assert_eq!(vision.acquire("vision").err(), Some(Rejected::QueueFull));
+        drop(held);
+        drop(other);
+
+        let stats = &devices.stats()["cpu0"];
+        assert_eq!(stats.target, "cpu");
+        assert_eq!(stats.slots, 2);
+        assert_eq!(stats.executing["chat"], 0);
+        assert_eq!(stats.completed["chat"], 1);
+        assert_eq!(stats.rejected["chat"], 1);
+        assert_eq!(stats.rejected["vision"], 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_queued_computations_wait_for_a_slot() -> Result<()> {
+        let devices = devices(
+            r#"
+            default_device = "cpu0"
+
+            [device.cpu0]
+
+            [device.cpu1]
+            max_queue_ms = 10
+
+            [component.batch]
+            device = "cpu1"
+            "#,
+        )?;
+        let batch = devices.assignment("batch").unwrap();
+        let _held = batch.acquire("batch").unwrap();
+        assert_eq!(batch.acquire("batch").err(), Some(Rejected::TimedOut));
+
+        let default = devices.assignment("any").unwrap();
+        let held = default.acquire("a").unwrap();
+        let waiter = {
+            let default = default.clone();
+            std::thread::spawn(move || default.acquire("b").map(drop))
+        };
+        while devices.stats()["cpu0"].queued.get("b") != Some(&1) {
+            std::thread::yield_now();
+        }
+        drop(held);
+        assert!(waiter.join().unwrap().is_ok());
+        let stats = &devices.stats()["cpu0"];
+        assert_eq!(stats.queued["b"], 0);
+        assert_eq!(stats.completed["b"], 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_rejects_invalid_devices() {
+        let err = devices("[device.gpu0]\ntarget = \"gpu\"").err().unwrap();
+        assert!(err.to_string().contains("cannot run models on gpu devices"));
+        let err = devices("[component.chat]\ndevice = \"gpu0\"")
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("unknown device gpu0"));
+        let err = devices("[device.cpu0]\nslots = 0").err().unwrap();
+        assert!(err.to_string().contains("slots must be at least 1"));
+    }
+}
@@ -0,0 +1,605 @@
+//! A wasi-nn host interface for Spin components, running models on the host
+//! so components can run inference without a remote API.
+//!
+//! Components import the `wasi_ephemeral_nn` module. They can load a model
+//! from bytes, for example read from a file mounted in the component, or
+//! load a model configured by name in the runtime configuration, which is
+//! parsed once and shared by all components.
+//!
+//! Inference can be scheduled on the devices of the host: components are
+//! assigned to devices in the runtime configuration, each of which runs a
+//! bounded number of computations at once.
+
+mod devices;
+mod tract;
+
+use std::{collections::HashMap, path::PathBuf, sync::Arc};
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use spin_engine::{
+    host_component::{HostComponent, HostComponentsStateHandle},
+    RuntimeContext,
+};
+use spin_manifest::CoreComponent;
+use wasmtime::{AsContext, AsContextMut, Caller, Extern, Linker, Memory, Trap};
+
+use crate::{devices::Assignment, tract::Model};
+
+pub use crate::devices::{
+    ComponentDeviceConfig, DeviceConfig, DeviceStats, Devices, ExecutionTarget,
+};
+
+/// The module components import wasi-nn from.
+pub const WASI_NN_MODULE: &str = "wasi_ephemeral_nn";
+
+/// Runtime configuration for wasi-nn.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub struct WasiNnConfig {
+    /// The backend running the models.
+    #[serde(default)]
+    pub backend: Backend,
+    /// Models components can load by name.
+    #[serde(default)]
+    pub model: HashMap<String, ModelConfig>,
+    /// Devices computations are scheduled on, by name.
+    #[serde(default)]
+    pub device: HashMap<String, DeviceConfig>,
+    /// The devices components' computations are scheduled on, by component
+    /// ID.
+    #[serde(default)]
+    pub component: HashMap<String, ComponentDeviceConfig>,
+    /// The device the computations of components not listed in `component`
+    /// are scheduled on. If not set, they are not scheduled.
+    pub default_device: Option<String>,
+}
+
+/// A backend running wasi-nn models.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Backend {
+    /// The tract runtime, running ONNX models on the CPU.
+    Tract,
+}
+
+impl Default for Backend {
+    fn default() -> Self {
+        Self::Tract
+    }
+}
+
+/// Runtime configuration for a model components can load by name.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub struct ModelConfig {
+    /// Path to the model file.
+    pub path: PathBuf,
+    /// The format of the model file.
+    #[serde(default)]
+    pub encoding: GraphEncoding,
+}
+
+/// The format of a model, numbered as in wasi-nn.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum GraphEncoding {
+    /// OpenVINO IR.
+    Openvino = 0,
+    /// ONNX.
+    Onnx = 1,
+    /// TensorFlow SavedModel.
+    Tensorflow = 2,
+    /// PyTorch TorchScript.
+    Pytorch = 3,
+    /// TensorFlow Lite.
+    Tensorflowlite = 4,
+}
+
+impl Default for GraphEncoding {
+    fn default() -> Self {
+        Self::Onnx
+    }
+}
+
+/// The type of the elements of a tensor, numbered as in wasi-nn.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum TensorType {
+    F16,
+    F32,
+    U8,
+    I32,
+}
+
+impl TryFrom<u8> for TensorType {
+    type Error = NnError;
+
+    fn try_from(value: u8) -> Result<Self, NnError> {
+        match value {
+            0 => Ok(Self::F16),
+            1 => Ok(Self::F32),
+            2 => Ok(Self::U8),
+            3 => Ok(Self::I32),
+            _ => Err(NnError::InvalidArgument),
+        }
+    }
+}
+
+/// A wasi-nn error, numbered as in wasi-nn.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum NnError {
+    InvalidArgument = 1,
+    InvalidEncoding = 2,
+    MissingMemory = 3,
+    Busy = 4,
+    RuntimeError = 5,
+}
+
+impl From<anyhow::Error> for NnError {
+    fn from(e: anyhow::Error) -> Self {
+        tracing::warn!("wasi-nn error: {:#}", e);
+        Self::RuntimeError
+    }
+}
+
+/// Converts the result of a wasi-nn function to the errno returned to the
+/// component.
+fn errno(result: Result<(), NnError>) -> i32 {
+    match result {
+        Ok(()) => 0,
+        Err(e) => e as i32,
+    }
+}
+
+/// The wasi-nn host component.
+#[derive(Clone, Default)]
+pub struct WasiNnComponent {
+    models: Arc<HashMap<String, Arc<Model>>>,
+    devices: Arc<Devices>,
+}
+
+impl WasiNnComponent {
+    /// Creates a wasi-nn host component, loading the configured models and
+    /// creating the configured devices.
+    pub fn new(config: &WasiNnConfig) -> Result<Self> {
+        let devices = Devices::new(config)?;
+        let models = config
+            .model
+            .iter()
+            .map(|(name, model)| {
+                let loaded = load_model_file(config.backend, model)
+                    .with_context(|| format!("Cannot load model {}", name))?;
+                Ok((name.clone(), Arc::new(loaded)))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            models: Arc::new(models),
+            devices: Arc::new(devices),
+        })
+    }
+
+    /// The devices computations are scheduled on, for reporting their use.
+    pub fn devices(&self) -> Arc<Devices> {
+        self.devices.clone()
+    }
+}
+
+fn load_model_file(backend: Backend, config: &ModelConfig) -> Result<Model> {
+    match (backend, config.encoding) {
+        (Backend::Tract, GraphEncoding::Onnx) => {}
+        (backend, encoding) => bail!("The {:?} backend cannot run {:?} models", backend, encoding),
+    }
+    let bytes = std::fs::read(&config.path)
+        .with_context(|| format!("Cannot read {}", config.path.display()))?;
+    Model::load(&bytes)
+}
+
+impl HostComponent for WasiNnComponent {
+    type State = WasiNn;
+
+    fn add_to_linker<T: Send>(
+        linker: &mut Linker<RuntimeContext<T>>,
+        state_handle: HostComponentsStateHandle<Self::State>,
+    ) -> Result<()> {
+        linker.func_wrap(
+            WASI_NN_MODULE,
+            "load",
+            move |mut caller: Caller<'_, RuntimeContext<T>>,
+                  builder: i32,
+                  builder_len: i32,
+                  encoding: i32,
+                  target: i32,
+                  graph: i32|
+                  -> Result<i32, Trap> {
+                Ok(errno(load(
+                    &mut caller,
+                    state_handle,
+                    builder,
+                    builder_len,
+                    encoding,
+                    target,
+                    graph,
+                )))
+            },
+        )?;
+        linker.func_wrap(
+            WASI_NN_MODULE,
+            "load_by_name",
+            move |mut caller: Caller<'_, RuntimeContext<T>>,
+                  name: i32,
+                  name_len: i32,
+                  graph: i32|
+                  -> Result<i32, Trap> {
+                Ok(errno(load_by_name(
+                    &mut caller,
+                    state_handle,
+                    name,
+                    name_len,
+                    graph,
+                )))
+            },
+        )?;
+        linker.func_wrap(
+            WASI_NN_MODULE,
+            "init_execution_context",
+            move |mut caller: Caller<'_, RuntimeContext<T>>,
+                  graph: i32,
+                  context: i32|
+                  -> Result<i32, Trap> {
+                Ok(errno(init_execution_context(
+                    &mut caller,
+                    state_handle,
+                    graph,
+                    context,
+                )))
+            },
+        )?;
+        linker.func_wrap(
+            WASI_NN_MODULE,
+            "set_input",
+            move |mut caller: Caller<'_, RuntimeContext<T>>,
+                  context: i32,
+                  index: i32,
+                  tensor: i32|
+                  -> Result<i32, Trap> {
+                Ok(errno(set_input(
+                    &mut caller,
+                    state_handle,
+                    context,
+                    index,
+                    tensor,
+                )))
+            },
+        )?;
+        linker.func_wrap(
+            WASI_NN_MODULE,
+            "compute",
+            move |mut caller: Caller<'_, RuntimeContext<T>>, context: i32| -> Result<i32, Trap> {
+                Ok(errno(compute(&mut caller, state_handle, context)))
+            },
+        )?;
+        linker.func_wrap(
+            WASI_NN_MODULE,
+            "get_output",
+            move |mut caller: Caller<'_, RuntimeContext<T>>,
+                  context: i32,
+                  index: i32,
+                  buffer: i32,
+                  buffer_len: i32,
+                  written: i32|
+                  -> Result<i32, Trap> {
+                Ok(errno(get_output(
+                    &mut caller,
+                    state_handle,
+                    context,
+                    index,
+                    buffer,
+                    buffer_len,
+                    written,
+                )))
+            },
+        )?;
+        Ok(())
+    }
+
+    fn build_state(&self, component: &CoreComponent) -> Result<Self::State> {
+        Ok(WasiNn {
+            component: component.id.clone(),
+            device: self.devices.assignment(&component.id),
+            models: self.models.clone(),
+            graphs: vec![],
+            contexts: vec![],
+        })
+    }
+}
+
+/// Per-component wasi-nn state: the graphs loaded and execution contexts
+/// created by an instance of the component, indexed by their handles.
+pub struct WasiNn {
+    component: String,
+    device: Option<Assignment>,
+    models: Arc<HashMap<String, Arc<Model>>>,
+    graphs: Vec<Arc<Model>>,
+    contexts: Vec<ExecutionContext>,
+}
+
+struct ExecutionContext {
+    graph: Arc<Model>,
+    inputs: Vec<Option<tract_onnx::prelude::Tensor>>,
+    outputs: Vec<Arc<tract_onnx::prelude::Tensor>>,
+}
+
+impl WasiNn {
+    /// The kind of device the component's models run on.
+    fn target(&self) -> ExecutionTarget {
+        self.device
+            .as_ref()
+            .map_or(ExecutionTarget::Cpu, Assignment::target)
+    }
+
+    fn add_graph(&mut self, graph: Arc<Model>) -> u32 {
+        self.graphs.push(graph);
+        (self.graphs.len() - 1) as u32
+    }
+
+    fn context(&mut self, context: i32) -> Result<&mut ExecutionContext, NnError> {
+        self.contexts
+            .get_mut(context as u32 as usize)
+            .ok_or(NnError::InvalidArgument)
+    }
+}
+
+type NnCaller<'a, T> = Caller<'a, RuntimeContext<T>>;
+
+fn load<T>(
+    caller: &mut NnCaller<'_, T>,
+    state_handle: HostComponentsStateHandle<WasiNn>,
+    builder: i32,
+    builder_len: i32,
+    encoding: i32,
+    target: i32,
+    graph: i32,
+) -> Result<(), NnError> {
+    if encoding != GraphEncoding::Onnx as i32 {
+        return Err(NnError::InvalidEncoding);
+    }
+    if target != state_handle.get(caller.data()).target() as i32 {
+        return Err(NnError::InvalidArgument);
+    }
+    let memory = memory(caller)?;
+    // The graph builder is a list of byte buffers, of which ONNX models use
+    // the first.
+    if builder_len < 1 {
+        return Err(NnError::InvalidArgument);
+    }
+    let ptr = read_u32(caller, memory, builder)?;
+    let len = read_u32(caller, memory, builder + 4)?;
+    let bytes = read_bytes(caller, memory, ptr, len)?;
+    let model = Arc::new(Model::load(&bytes)?);
+
+    let handle = state_handle.get_mut(caller.data_mut()).add_graph(model);
+    write_u32(caller, memory, graph, handle)
+}
+
+fn load_by_name<T>(
+    caller: &mut NnCaller<'_, T>,
+    state_handle: HostComponentsStateHandle<WasiNn>,
+    name: i32,
+    name_len: i32,
+    graph: i32,
+) -> Result<(), NnError> {
+    let memory = memory(caller)?;
+    let name = read_bytes(caller, memory, name as u32, name_len as u32)?;
+    let name = String::from_utf8(name).map_err(|_| NnError::InvalidArgument)?;
+
+    let state = state_handle.get_mut(caller.data_mut());
+    let model = state.models.get(&name).cloned().ok_or_else(|| {
+        tracing::warn!("wasi-nn error: no model named {:?}", name);
+        NnError::InvalidArgument
+    })?;
+    let handle = state.add_graph(model);
+    write_u32(caller, memory, graph, handle)
+}
+
+fn init_execution_context<T>(
+    caller: &mut NnCaller<'_, T>,
+    state_handle: HostComponentsStateHandle<WasiNn>,
+    graph: i32,
+    context: i32,
+) -> Result<(), NnError> {
+    let memory = memory(caller)?;
+    let state = state_handle.get_mut(caller.data_mut());
+    let graph = state
+        .graphs
+        .get(graph as u32 as usize)
+        .cloned()
+        .ok_or(NnError::InvalidArgument)?;
+    state.contexts.push(ExecutionContext {
+        graph,
+        inputs: vec![],
+        outputs: vec![],
+    });
+    let handle = (state.contexts.len() - 1) as u32;
+    write_u32(caller, memory, context, handle)
+}
+
+fn set_input<T>(
+    caller: &mut NnCaller<'_, T>,
+    state_handle: HostComponentsStateHandle<WasiNn>,
+    context: i32,
+    index: i32,
+    tensor: i32,
+) -> Result<(), NnError> {
+    let memory = memory(caller)?;
+    // struct tensor { dimensions: list<u32>, type: u8, data: list<u8> }
+    let dims_ptr = read_u32(caller, memory, tensor)?;
+    let dims_len = read_u32(caller, memory, tensor + 4)?;
+    let tensor_type = read_bytes(caller, memory, tensor as u32 + 8, 1)?[0];
+    let data_ptr = read_u32(caller, memory, tensor + 12)?;
+    let data_len = read_u32(caller, memory, tensor + 16)?;
+
+    let dims = read_bytes(caller, memory, dims_ptr, dims_len.saturating_mul(4))?
+        .chunks_exact(4)
+        .map(|d| u32::from_le_bytes(d.try_into().unwrap()) as usize)
+        .collect::<Vec<_>>();
+    let data = read_bytes(caller, memory, data_ptr, data_len)?;
+    let tensor = tract::tensor(TensorType::try_from(tensor_type)?, &dims, &data).map_err(|e| {
+        tracing::warn!("wasi-nn error: invalid input tensor: {:#}", e);
+        NnError::InvalidArgument
+    })?;
+
+    let context = state_handle.get_mut(caller.data_mut()).context(context)?;
+    let index = index as u32 as usize;
+    if context.inputs.len() <= index {
+        context.inputs.resize(index + 1, None);
+    }
+    context.inputs[index] = Some(tensor);
+    Ok(())
+}
+
+fn compute<T>(
+    caller: &mut NnCaller<'_, T>,
+    state_handle: HostComponentsStateHandle<WasiNn>,
+    context: i32,
+) -> Result<(), NnError> {
+    let state = state_handle.get_mut(caller.data_mut());
+    let (graph, inputs) = {
+        let context = state.context(context)?;
+        let inputs = context
+            .inputs
+            .iter()
+            .cloned()
+            .collect::<Option<Vec<_>>>()
+            .ok_or(NnError::InvalidArgument)?;
+        (context.graph.clone(), inputs)
+    };
+    // Held while the model runs, so the device's other computations wait.
+    let _slot = match &state.device {
+        Some(device) => Some(device.acquire(&state.component).map_err(|rejected| {
+            tracing::warn!(
+                "wasi-nn computation of {} rejected: {:?}",
+                state.component,
+                rejected
+            );
+            NnError::Busy
+        })?),
+        None => None,
+    };
+    let outputs = graph.run(inputs)?;
+    state.context(context)?.outputs = outputs;
+    Ok(())
+}
+
+fn get_output<T>(
+    caller: &mut NnCaller<'_, T>,
+    state_handle: HostComponentsStateHandle<WasiNn>,
+    context: i32,
+    index: i32,
+    buffer: i32,
+    buffer_len: i32,
+    written: i32,
+) -> Result<(), NnError> {
+    let memory = memory(caller)?;
+    let context = state_handle.get_mut(caller.data_mut()).context(context)?;
+    let output = context
+        .outputs
+        .get(index as u32 as usize)
+        .ok_or(NnError::InvalidArgument)?;
+    let data = tract::tensor_data(output)?;
+    if data.len() > buffer_len as u32 as usize {
+        return Err(NnError::InvalidArgument);
+    }
+    memory
+        .write(caller.as_context_mut(), buffer as u32 as usize, &data)
+        .map_err(|_| NnError::InvalidArgument)?;
+    write_u32(caller, memory, written, data.len() as u32)
+}
+
+fn memory<T>(caller: &mut NnCaller<'_, T>) -> Result<Memory, NnError> {
+    match caller.get_export("memory") {
+        Some(Extern::Memory(memory)) => Ok(memory),
+        _ => Err(NnError::MissingMemory),
+    }
+}
+
+fn read_bytes<T>(
+    caller: &NnCaller<'_, T>,
+    memory: Memory,
+    ptr: u32,
+    len: u32,
+) -> Result<Vec<u8>, NnError> {
+    let mut bytes = vec![0; len as usize];
+    memory
+        .read(caller.as_context(), ptr as usize, &mut bytes)
+        .map_err(|_| NnError::InvalidArgument)?;
+    Ok(bytes)
+}
+
+fn read_u32<T>(caller: &NnCaller<'_, T>, memory: Memory, ptr: i32) -> Result<u32, NnError> {
+    let bytes = read_bytes(caller, memory, ptr as u32, 4)?;
+    Ok(u32::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+fn write_u32<T>(
+    caller: &mut NnCaller<'_, T>,
+    memory: Memory,
+    ptr: i32,
+    value: u32,
+) -> Result<(), NnError> {
+    memory
+        .write(
+            caller.as_context_mut(),
+            ptr as u32 as usize,
+            &value.to_le_bytes(),
+        )
+        .map_err(|_| NnError::InvalidArgument)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_config() {
+        let config: WasiNnConfig = toml::from_str(
+            r#"
+            [model.mobilenet]
+            path = "models/mobilenet.onnx"
+
+            [device.cpu0]
+            slots = 4
+
+            [component.classifier]
+            device = "cpu0"
+            quota = 2
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.backend, Backend::Tract);
+        let model = &config.model["mobilenet"];
+        assert_eq!(model.path, PathBuf::from("models/mobilenet.onnx"));
+        assert_eq!(model.encoding, GraphEncoding::Onnx);
+        assert_eq!(config.device["cpu0"].target, ExecutionTarget::Cpu);
+        assert_eq!(config.device["cpu0"].slots, 4);
+        assert_eq!(config.component["classifier"].quota, Some(2));
+        assert!(config.default_device.is_none());
+    }
+
+    #[test]
+    fn test_backend_rejects_unsupported_encodings() {
+        let config = ModelConfig {
+            path: "model.xml".into(),
+            encoding: GraphEncoding::Openvino,
+        };
+        let err = load_model_file(Backend::Tract, &config).err().unwrap();
+        assert!(err.to_string().contains("cannot run Openvino models"));
+    }
+
+    #[test]
+    fn test_errno() {
+        assert_eq!(errno(Ok(())), 0);
+        assert_eq!(errno(Err(NnError::InvalidEncoding)), 2);
+        assert_eq!(errno(Err(NnError::Busy)), 4);
+        assert_eq!(errno(Err(NnError::RuntimeError)), 5);
+    }
+}
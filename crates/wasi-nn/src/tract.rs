@@ -0,0 +1,130 @@
+//! Inference with the tract ONNX runtime, on the CPU.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{bail, Result};
+use tract_onnx::prelude::*;
+
+use crate::TensorType;
+
+type Plan = TypedRunnableModel<TypedModel>;
+
+/// The types and shapes of the inputs a model is run with.
+type InputFacts = Vec<(DatumType, Vec<usize>)>;
+
+/// An ONNX model, with the plans optimized for the inputs it has been run
+/// with.
+pub(crate) struct Model {
+    model: InferenceModel,
+    plans: Mutex<HashMap<InputFacts, Arc<Plan>>>,
+}
+
+impl Model {
+    /// Parses an ONNX model.
+    pub fn load(mut bytes: &[u8]) -> Result<Self> {
+        let model = tract_onnx::onnx().model_for_read(&mut bytes)?;
+        Ok(Self {
+            model,
+            plans: Default::default(),
+        })
+    }
+
+    /// Runs the model with the given inputs, returning its outputs.
+    pub fn run(&self, inputs: Vec<Tensor>) -> Result<Vec<Arc<Tensor>>> {
+        let facts = inputs
+            .iter()
+            .map(|t| (t.datum_type(), t.shape().to_vec()))
+            .collect::<InputFacts>();
+        let plan = self.plan(facts)?;
+        Ok(plan
+            .run(inputs.into_iter().collect())?
+            .into_iter()
+            .collect())
+    }
+
+    /// The plan for the given inputs, optimizing the model for them the
+    /// first time they are seen.
+    fn plan(&self, facts: InputFacts) -> Result<Arc<Plan>> {
+        if let Some(plan) = self.plans.lock().unwrap().get(&facts) {
+            return Ok(plan.clone());
+        }
+        let mut model = self.model.clone();
+        for (index, (datum_type, shape)) in facts.iter().enumerate() {
+            model = model
+                .with_input_fact(index, InferenceFact::dt_shape(*datum_type, shape.clone()))?;
+        }
+        let plan = Arc::new(model.into_optimized()?.into_runnable()?);
+        self.plans.lock().unwrap().insert(facts, plan.clone());
+        Ok(plan)
+    }
+}
+
+/// Builds a tensor from little-endian data.
+pub(crate) fn tensor(tensor_type: TensorType, shape: &[usize], data: &[u8]) -> Result<Tensor> {
+    match tensor_type {
+        TensorType::F32 => Tensor::from_shape(shape, &from_le_bytes(data, f32::from_le_bytes)?),
+        TensorType::U8 => Tensor::from_shape(shape, data),
+        TensorType::I32 => Tensor::from_shape(shape, &from_le_bytes(data, i32::from_le_bytes)?),
+        TensorType::F16 => bail!("f16 tensors are not supported"),
+    }
+}
+
+/// The data of a tensor, in little-endian order.
+pub(crate) fn tensor_data(tensor: &Tensor) -> Result<Vec<u8>> {
+    Ok(match tensor.datum_type() {
+        DatumType::F32 => to_le_bytes(tensor.as_slice::<f32>()?, |v| v.to_le_bytes()),
+        DatumType::U8 => tensor.as_slice::<u8>()?.to_vec(),
+        DatumType::I32 => to_le_bytes(tensor.as_slice::<i32>()?, |v| v.to_le_bytes()),
+        DatumType::I64 => to_le_bytes(tensor.as_slice::<i64>()?, |v| v.to_le_bytes()),
+        other => bail!("Output tensors of type {:?} are not supported", other),
+    })
+}
+
+fn from_le_bytes<T, const N: usize>(data: &[u8], f: fn([u8; N]) -> T) -> Result<Vec<T>> {
+    if data.len() % N != 0 {
+        bail!(
+            "Tensor data of {} bytes is not a whole number of {}-byte values",
+            data.len(),
+            N
+        );
+    }
+    Ok(data
+        .chunks_exact(N)
+        .map(|chunk| f(chunk.try_into().unwrap()))
+        .collect())
+}
+
+fn to_le_bytes<T: Copy, const N: usize>(values: &[T], f: fn(T) -> [u8; N]) -> Vec<u8> {
+    values.iter().flat_map(|v| f(*v)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tensor_round_trip() -> Result<()> {
+        let data = [1.5f32, -2.0, 0.25, 8.0]
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect::<Vec<_>>();
+        let t = tensor(TensorType::F32, &[2, 2], &data)?;
+        assert_eq!(t.shape(), &[2, 2]);
+        assert_eq!(t.as_slice::<f32>()?, &[1.5, -2.0, 0.25, 8.0]);
+        assert_eq!(tensor_data(&t)?, data);
+
+        let t = tensor(TensorType::U8, &[3], &[1, 2, 3])?;
+        assert_eq!(tensor_data(&t)?, vec![1, 2, 3]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_tensor_rejects_bad_data() {
+        assert!(tensor(TensorType::F32, &[2], &[0; 7]).is_err());
+        assert!(tensor(TensorType::F32, &[3], &[0; 8]).is_err());
+        assert!(tensor(TensorType::F16, &[1], &[0; 2]).is_err());
+    }
+}
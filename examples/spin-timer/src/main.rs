@@ -6,9 +6,11 @@ use std::{sync::Arc, time::Duration};
 use anyhow::Result;
 use spin_engine::{Builder, ExecutionContextConfiguration};
 use spin_manifest::{CoreComponent, ModuleSource, WasmConfig};
-use tokio::task::spawn_blocking;
 
-wit_bindgen_wasmtime::import!("spin-timer.wit");
+wit_bindgen_wasmtime::import!({
+    paths: ["spin-timer.wit"],
+    async: *,
+});
 
 type ExecutionContext = spin_engine::ExecutionContext<spin_timer::SpinTimerData>;
 
@@ -58,21 +60,21 @@ impl TimerTrigger {
     }
     /// Execute the first component in the application configuration.
     async fn handle(&self, msg: String) -> Result<()> {
-        let (mut store, instance) = self.engine.prepare_component(
-            &self.engine.config.components[0].id,
-            None,
-            None,
-            None,
-            None,
-        )?;
+        let component = &self.engine.config.components[0].id;
+        let (mut store, instance) = self
+            .engine
+            .prepare_component(component, None, None, None, None)
+            .await?;
 
-        let res = spawn_blocking(move || -> Result<String> {
-            let t = spin_timer::SpinTimer::new(&mut store, &instance, |host| {
-                host.data.as_mut().unwrap()
-            })?;
-            Ok(t.handle_timer_request(&mut store, &msg)?)
-        })
-        .await??;
+        let res = self
+            .engine
+            .run_guest(component, async move {
+                let t = spin_timer::SpinTimer::new(&mut store, &instance, |host| {
+                    host.data.as_mut().unwrap()
+                })?;
+                Ok::<_, anyhow::Error>(t.handle_timer_request(&mut store, &msg).await?)
+            })
+            .await??;
         log::info!("{}\n", res);
 
         Ok(())
@@ -192,3 +192,46 @@ pub fn redis_component(_attr: TokenStream, item: TokenStream) -> TokenStream {
     )
     .into()
 }
+
+/// Generates the entrypoint to a Spin message queue component written in Rust.
+#[proc_macro_attribute]
+pub fn queue_component(_attr: TokenStream, item: TokenStream) -> TokenStream {
+    const QUEUE_COMPONENT_WIT: &str =
+        include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/wit/spin-queue.wit"));
+
+    let func = syn::parse_macro_input!(item as syn::ItemFn);
+    let func_name = &func.sig.ident;
+
+    quote!(
+        wit_bindgen_rust::export!({src["spin_queue"]: #QUEUE_COMPONENT_WIT});
+
+        struct SpinQueue;
+
+        impl spin_queue::SpinQueue for SpinQueue {
+            fn handle_queue_message(message: spin_queue::Message) -> Result<(), spin_queue::Error> {
+                #func
+
+                let message = spin_sdk::queue::Message {
+                    topic: message.topic,
+                    key: message.key.map(Into::into),
+                    payload: message.payload.into(),
+                    headers: message.headers,
+                    attempt: message.attempt,
+                };
+                match #func_name(message) {
+                    Ok(()) => Ok(()),
+                    Err(spin_sdk::queue::Error::Retry(e)) => {
+                        eprintln!("{}", e);
+                        Err(spin_queue::Error::Retry)
+                    },
+                    Err(spin_sdk::queue::Error::Reject(e)) => {
+                        eprintln!("{}", e);
+                        Err(spin_queue::Error::Reject)
+                    },
+                }
+            }
+        }
+
+    )
+    .into()
+}
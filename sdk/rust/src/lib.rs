@@ -46,6 +46,50 @@ pub mod redis {
     pub use outbound_redis::*;
 }
 
+/// Types for Spin message queue components.
+pub mod queue {
+    /// A message received from a Kafka topic or NATS subject.
+    #[derive(Clone, Debug)]
+    pub struct Message {
+        /// The topic or subject the message was received on.
+        pub topic: String,
+        /// The key of the message, if any.
+        pub key: Option<bytes::Bytes>,
+        /// The message payload.
+        pub payload: bytes::Bytes,
+        /// The headers of the message.
+        pub headers: Vec<(String, String)>,
+        /// The attempt at handling the message, starting at 1.
+        pub attempt: u32,
+    }
+
+    /// The failure of a message queue handler.
+    #[derive(Debug)]
+    pub enum Error {
+        /// The message is handled again after the retry delay, until the
+        /// retries configured for the component are exhausted.
+        Retry(anyhow::Error),
+        /// The message is not retried, and is published to the dead-letter
+        /// topic of the component, if it has one.
+        Reject(anyhow::Error),
+    }
+
+    impl From<anyhow::Error> for Error {
+        fn from(e: anyhow::Error) -> Self {
+            Self::Retry(e)
+        }
+    }
+
+    impl ::std::fmt::Display for Error {
+        fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+            match self {
+                Error::Retry(e) => write!(f, "{}", e),
+                Error::Reject(e) => write!(f, "message rejected: {}", e),
+            }
+        }
+    }
+}
+
 /// Implementation of the spin postgres db interface.
 #[allow(missing_docs)]
 pub mod pg {
@@ -55,6 +99,24 @@ pub mod pg {
     pub use outbound_pg::*;
 }
 
+/// Implementation of the spin MySQL db interface.
+#[allow(missing_docs)]
+pub mod mysql {
+    wit_bindgen_rust::import!("../../wit/ephemeral/outbound-mysql.wit");
+
+    /// Exports the generated outbound MySQL items.
+    pub use outbound_mysql::*;
+}
+
+/// Implementation of the spin key-value store interface.
+#[allow(missing_docs)]
+pub mod key_value {
+    wit_bindgen_rust::import!("../../wit/ephemeral/spin-key-value.wit");
+
+    /// Exports the generated key-value items.
+    pub use spin_key_value::*;
+}
+
 /// Implementation of the spin config interface.
 #[allow(missing_docs)]
 pub mod config {
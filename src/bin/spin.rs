@@ -2,22 +2,34 @@ use anyhow::Error;
 use clap::{Parser, Subcommand};
 use lazy_static::lazy_static;
 use spin_cli::commands::{
-    bindle::BindleCommands, build::BuildCommand, deploy::DeployCommand, new::NewCommand,
-    templates::TemplateCommands, up::UpCommand,
+    apps::AppsCommands, bindle::BindleCommands, build::BuildCommand, cache::CacheCommands,
+    check::CheckCommand, contract::ContractCommands, data::DataCommands, deploy::DeployCommand,
+    env::EnvCommand, fuzz::FuzzCommand, history::HistoryCommand, info::InfoCommand,
+    inspect::InspectCommand, jobs::JobsCommands, login::LoginCommand, logs::LogsCommand,
+    maintenance::MaintenanceCommands, new::AddCommand, new::NewCommand, plugins::PluginCommands,
+    precompile::PrecompileCommand, revisions::RevisionsCommands, scale::ScaleCommand,
+    self_update::SelfUpdateCommand, signing_key::SigningKeyCommands, templates::TemplateCommands,
+    undeploy::UndeployCommand, up::UpCommand, verify_lock::VerifyLockCommand, watch::WatchCommand,
 };
 use spin_http_engine::HttpTrigger;
+use spin_queue_engine::QueueTrigger;
 use spin_redis_engine::RedisTrigger;
-use spin_trigger::cli::TriggerExecutorCommand;
+use spin_trigger::{cli::TriggerExecutorCommand, telemetry::TelemetryOpts};
 
 #[tokio::main]
 async fn main() -> Result<(), Error> {
-    tracing_subscriber::fmt()
-        .with_writer(std::io::stderr)
-        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
-        .with_ansi(atty::is(atty::Stream::Stderr))
-        .init();
-
-    SpinApp::parse().run().await
+    let app = SpinApp::parse();
+    // Flushes the exported traces and metrics when Spin exits.
+    let _telemetry = spin_trigger::telemetry::init(app.command.telemetry())?;
+    if app.offline {
+        // Set for the whole process, so that it applies to libraries and
+        // to processes started by Spin, like triggers and build commands.
+        std::env::set_var(spin_loader::offline::OFFLINE_ENV, "true");
+    }
+    if app.copy {
+        std::env::set_var(spin_loader::staging::COPY_ENV, "true");
+    }
+    app.command.run().await
 }
 
 lazy_static! {
@@ -35,37 +47,126 @@ fn version() -> &'static str {
     name = "spin",
     version = version(),
 )]
-enum SpinApp {
+struct SpinApp {
+    /// Fail operations that would use the network, rather than attempting them
+    #[clap(long = "offline", global = true)]
+    offline: bool,
+
+    /// Copy application files when staging them, rather than linking them
+    /// to the originals where possible
+    #[clap(long = "copy", global = true)]
+    copy: bool,
+
+    #[clap(subcommand)]
+    command: SpinCommands,
+}
+
+#[derive(Subcommand)]
+enum SpinCommands {
     #[clap(subcommand)]
     Templates(TemplateCommands),
     New(NewCommand),
+    Add(AddCommand),
     Up(UpCommand),
+    Watch(WatchCommand),
     #[clap(subcommand)]
     Bindle(BindleCommands),
     Deploy(DeployCommand),
+    #[clap(subcommand)]
+    Contract(ContractCommands),
+    Undeploy(UndeployCommand),
+    History(HistoryCommand),
+    #[clap(subcommand)]
+    Apps(AppsCommands),
+    #[clap(subcommand)]
+    Revisions(RevisionsCommands),
+    Scale(ScaleCommand),
+    Login(LoginCommand),
     Build(BuildCommand),
+    Precompile(PrecompileCommand),
+    #[clap(subcommand)]
+    Cache(CacheCommands),
+    Check(CheckCommand),
+    Env(EnvCommand),
+    Inspect(InspectCommand),
+    VerifyLock(VerifyLockCommand),
+    Logs(LogsCommand),
+    Fuzz(FuzzCommand),
+    #[clap(subcommand)]
+    SigningKey(SigningKeyCommands),
+    Info(InfoCommand),
+    #[clap(subcommand)]
+    Jobs(JobsCommands),
+    #[clap(subcommand)]
+    Data(DataCommands),
+    #[clap(subcommand)]
+    Maintenance(MaintenanceCommands),
+    #[clap(subcommand)]
+    Plugin(PluginCommands),
+    SelfUpdate(SelfUpdateCommand),
     #[clap(subcommand, hide = true)]
     Trigger(TriggerCommands),
+    // Other commands run the installed plugin of that name.
+    #[clap(external_subcommand)]
+    External(Vec<String>),
 }
 
 #[derive(Subcommand)]
 enum TriggerCommands {
     Http(TriggerExecutorCommand<HttpTrigger>),
     Redis(TriggerExecutorCommand<RedisTrigger>),
+    Queue(TriggerExecutorCommand<QueueTrigger>),
 }
 
-impl SpinApp {
+impl SpinCommands {
+    /// Where the command exports traces and metrics: only triggers export
+    /// them.
+    fn telemetry(&self) -> Option<&TelemetryOpts> {
+        match self {
+            Self::Trigger(TriggerCommands::Http(cmd)) => Some(&cmd.telemetry),
+            Self::Trigger(TriggerCommands::Redis(cmd)) => Some(&cmd.telemetry),
+            Self::Trigger(TriggerCommands::Queue(cmd)) => Some(&cmd.telemetry),
+            _ => None,
+        }
+    }
+
     /// The main entry point to Spin.
     pub async fn run(self) -> Result<(), Error> {
         match self {
             Self::Templates(cmd) => cmd.run().await,
             Self::Up(cmd) => cmd.run().await,
+            Self::Watch(cmd) => cmd.run().await,
             Self::New(cmd) => cmd.run().await,
+            Self::Add(cmd) => cmd.run().await,
             Self::Bindle(cmd) => cmd.run().await,
             Self::Deploy(cmd) => cmd.run().await,
+            Self::Contract(cmd) => cmd.run().await,
+            Self::Undeploy(cmd) => cmd.run().await,
+            Self::History(cmd) => cmd.run().await,
+            Self::Apps(cmd) => cmd.run().await,
+            Self::Revisions(cmd) => cmd.run().await,
+            Self::Scale(cmd) => cmd.run().await,
+            Self::Login(cmd) => cmd.run().await,
             Self::Build(cmd) => cmd.run().await,
+            Self::Precompile(cmd) => cmd.run().await,
+            Self::Cache(cmd) => cmd.run().await,
+            Self::Check(cmd) => cmd.run().await,
+            Self::Env(cmd) => cmd.run().await,
+            Self::Inspect(cmd) => cmd.run().await,
+            Self::VerifyLock(cmd) => cmd.run().await,
+            Self::Logs(cmd) => cmd.run().await,
+            Self::Fuzz(cmd) => cmd.run().await,
+            Self::SigningKey(cmd) => cmd.run().await,
+            Self::Info(cmd) => cmd.run().await,
+            Self::Jobs(cmd) => cmd.run().await,
+            Self::Data(cmd) => cmd.run().await,
+            Self::Maintenance(cmd) => cmd.run().await,
+            Self::Plugin(cmd) => cmd.run().await,
+            Self::SelfUpdate(cmd) => cmd.run().await,
             Self::Trigger(TriggerCommands::Http(cmd)) => cmd.run().await,
             Self::Trigger(TriggerCommands::Redis(cmd)) => cmd.run().await,
+            Self::Trigger(TriggerCommands::Queue(cmd)) => cmd.run().await,
+            Self::External(args) => spin_cli::commands::plugins::run_plugin(args).await,
         }
     }
 }
@@ -79,3 +180,18 @@ fn build_info() -> String {
         env!("VERGEN_GIT_COMMIT_DATE")
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_offline_applies_to_every_command() {
+        let app = SpinApp::try_parse_from(["spin", "build", "--offline"]).unwrap();
+        assert!(app.offline);
+        let app = SpinApp::try_parse_from(["spin", "--offline", "build"]).unwrap();
+        assert!(app.offline);
+        let app = SpinApp::try_parse_from(["spin", "build"]).unwrap();
+        assert!(!app.offline);
+    }
+}
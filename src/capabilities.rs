@@ -0,0 +1,228 @@
+//! Checks that the platform an application is deployed to can run it.
+
+use std::{
+    collections::BTreeSet,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use spin_loader::local::config::{RawAppManifest, RawModuleSource};
+use spin_manifest::{ApplicationTrigger, HttpExecutor, HttpHandler, QueueBroker, TriggerConfig};
+
+/// The path, relative to the Hippo URL, at which Hippo lists the
+/// capabilities of its platform.
+const CAPABILITIES_PATH: &str = "/.well-known/spin/capabilities";
+
+/// The capabilities assumed for servers that do not list theirs: those of
+/// the Spin releases that predate capability listing.
+const BASELINE_CAPABILITIES: &[&str] = &[
+    "trigger:http",
+    "trigger:redis",
+    "http-executor:spin",
+    "http-executor:wagi",
+    "outbound-http",
+];
+
+#[derive(Deserialize)]
+struct ServerCapabilities {
+    capabilities: Vec<String>,
+}
+
+/// Fails if the application requires capabilities the Hippo server does not
+/// list.
+pub(crate) async fn check(
+    client: &reqwest::Client,
+    hippo_url: &str,
+    cfg: &RawAppManifest,
+    app_dir: &Path,
+) -> Result<()> {
+    let required = required_capabilities(cfg, app_dir)?;
+    let supported = server_capabilities(client, hippo_url).await?;
+    let missing: Vec<_> = required.difference(&supported).cloned().collect();
+    if !missing.is_empty() {
+        bail!(
+            "Application {} uses features that Hippo server {} cannot run: {}. Use --skip-capability-check to deploy anyway",
+            cfg.info.name,
+            hippo_url,
+            missing.join(", ")
+        );
+    }
+    Ok(())
+}
+
+async fn server_capabilities(
+    client: &reqwest::Client,
+    hippo_url: &str,
+) -> Result<BTreeSet<String>> {
+    let url = url::Url::parse(hippo_url)?.join(CAPABILITIES_PATH)?;
+    let response = client
+        .get(url.clone())
+        .send()
+        .await
+        .with_context(|| format!("Cannot query capabilities of Hippo server {}", hippo_url))?;
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(BASELINE_CAPABILITIES
+            .iter()
+            .map(|c| c.to_string())
+            .collect());
+    }
+    let body = response
+        .error_for_status()
+        .with_context(|| format!("Cannot query capabilities of Hippo server {}", hippo_url))?
+        .bytes()
+        .await?;
+    let capabilities: ServerCapabilities = serde_json::from_slice(&body)
+        .with_context(|| format!("Cannot parse capabilities returned by {}", url))?;
+    Ok(capabilities.capabilities.into_iter().collect())
+}
+
+/// Returns the capabilities the platform needs to run the application.
+fn required_capabilities(cfg: &RawAppManifest, app_dir: &Path) -> Result<BTreeSet<String>> {
+    let mut required = BTreeSet::new();
+    match &cfg.info.trigger {
+        ApplicationTrigger::Http(http) => {
+            required.insert("trigger:http".to_owned());
+            if !http.routes.is_empty() {
+                required.insert("http-native-routes".to_owned());
+            }
+            for route in &http.routes {
+                let handler = match route.handler {
+                    HttpHandler::Template(_) => "http-handler:template",
+                    HttpHandler::Proxy(_) => "http-handler:proxy",
+                };
+                required.insert(handler.to_owned());
+            }
+        }
+        ApplicationTrigger::Redis(_) => {
+            required.insert("trigger:redis".to_owned());
+        }
+        ApplicationTrigger::Queue(queue) => {
+            let broker = match queue.broker {
+                QueueBroker::Kafka => "trigger:queue:kafka",
+                QueueBroker::Nats => "trigger:queue:nats",
+            };
+            required.insert(broker.to_owned());
+        }
+        ApplicationTrigger::External(_) => {
+            required.insert(format!("trigger:{}", cfg.info.trigger.trigger_type()));
+        }
+    }
+
+    for component in &cfg.components {
+        if let TriggerConfig::Http(http) = &component.trigger {
+            let executor = match http.executor.as_ref().unwrap_or(&HttpExecutor::Spin) {
+                HttpExecutor::Spin => "http-executor:spin",
+                HttpExecutor::Wagi(_) => "http-executor:wagi",
+                HttpExecutor::Static(_) => "http-executor:static",
+            };
+            required.insert(executor.to_owned());
+            if http.auth.is_some() {
+                required.insert("http-auth".to_owned());
+            }
+            if http.audit.is_some() {
+                required.insert("http-audit".to_owned());
+            }
+            if http.idempotency.is_some() {
+                required.insert("http-idempotency".to_owned());
+            }
+            if http.cache.is_some() {
+                required.insert("http-cache".to_owned());
+            }
+            if !http.middleware.is_empty() {
+                required.insert("http-middleware".to_owned());
+            }
+        }
+        if component.wasm.allowed_http_hosts.is_some() {
+            required.insert("outbound-http".to_owned());
+        }
+        if component.wasm.allowed_blob_containers.is_some() {
+            required.insert("blob-store".to_owned());
+        }
+        if component.wasm.allowed_database_hosts.is_some() {
+            required.insert("outbound-database".to_owned());
+        }
+        if component.wasm.key_value_stores.is_some() {
+            required.insert("key-value".to_owned());
+        }
+        if let RawModuleSource::FileReference(path) = &component.source {
+            let path: PathBuf = app_dir.join(path);
+            if is_component_binary(&path)? {
+                required.insert("component-model".to_owned());
+            }
+        }
+    }
+    Ok(required)
+}
+
+/// Whether the Wasm file is a component, rather than a core module. Both
+/// start with the Wasm magic number, followed by a version and a layer
+/// number, which is 1 for components.
+fn is_component_binary(path: &Path) -> Result<bool> {
+    let mut header = [0u8; 8];
+    let mut file = std::fs::File::open(path)
+        .with_context(|| format!("Cannot open Wasm module {}", path.display()))?;
+    if file.read_exact(&mut header).is_err() {
+        return Ok(false);
+    }
+    Ok(&header[..4] == b"\0asm" && header[6..8] == [1, 0])
+}
+
+#[cfg(test)]
+mod tests {
+    use spin_loader::local::config::RawAppManifestAnyVersion;
+
+    use super::*;
+
+    #[test]
+    fn test_required_capabilities() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        std::fs::write(dir.path().join("module.wasm"), b"\0asm\x01\0\0\0")?;
+        std::fs::write(dir.path().join("component.wasm"), b"\0asm\x0d\0\x01\0")?;
+        let RawAppManifestAnyVersion::V1(cfg) = toml::from_str(
+            r#"
+            spin_version = "1"
+            name = "test"
+            version = "1.0.0"
+            trigger = { type = "http", base = "/" }
+
+            [[component]]
+            id = "module"
+            source = "module.wasm"
+            allowed_http_hosts = ["example.com"]
+            [component.trigger]
+            route = "/module"
+            executor = { type = "wagi" }
+
+            [[component]]
+            id = "component"
+            source = "component.wasm"
+            [component.trigger]
+            route = "/component"
+            auth = { jwt = "default" }
+            idempotency = { ttl_secs = 3600 }
+            cache = { ttl_secs = 60 }
+            middleware = [{ type = "rate_limit", requests = 10 }]
+            "#,
+        )?;
+
+        let required = required_capabilities(&cfg, dir.path())?;
+        let expected: BTreeSet<_> = [
+            "component-model",
+            "http-auth",
+            "http-cache",
+            "http-executor:spin",
+            "http-executor:wagi",
+            "http-idempotency",
+            "http-middleware",
+            "outbound-http",
+            "trigger:http",
+        ]
+        .into_iter()
+        .map(String::from)
+        .collect();
+        assert_eq!(required, expected);
+        Ok(())
+    }
+}
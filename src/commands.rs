@@ -1,14 +1,62 @@
 //! Commands for the Spin CLI.
 
+/// Commands for inspecting deployed applications.
+pub mod apps;
 /// Command for creating bindles.
 pub mod bindle;
 /// Commands for building Spin applications.
 pub mod build;
+/// Commands for managing the cache of pulled parcels.
+pub mod cache;
+/// Command for checking an application manifest.
+pub mod check;
+/// Commands for recording and verifying the contracts of an application.
+pub mod contract;
+/// Commands for managing the data directories of applications.
+pub mod data;
 /// Command for deploying a Spin app to Hippo
 pub mod deploy;
+/// Command for printing the effective configuration of an application.
+pub mod env;
+/// Command for fuzzing HTTP components.
+pub mod fuzz;
+/// Command for printing the deploy history.
+pub mod history;
+/// Command for printing information about Spin.
+pub mod info;
+/// Command for inspecting the contents of an application.
+pub mod inspect;
+/// Commands for inspecting the jobs of an application.
+pub mod jobs;
+/// Command for logging in to Hippo.
+pub mod login;
+/// Command for printing the output of components.
+pub mod logs;
+/// Commands for maintaining Spin and the tools around it.
+pub mod maintenance;
 /// Command for creating a new application.
 pub mod new;
+/// Commands for managing plugins.
+pub mod plugins;
+/// Command for compiling the modules of an application ahead of time.
+pub mod precompile;
+/// Commands for managing preview deployments.
+pub mod preview;
+/// Commands for managing the revisions of a deployed application.
+pub mod revisions;
+/// Command for scaling a deployed application.
+pub mod scale;
+/// Command for updating Spin itself.
+pub mod self_update;
+/// Commands for managing the keys bindles are signed with.
+pub mod signing_key;
 /// Commands for working with templates.
 pub mod templates;
+/// Command for removing a deployed Spin app from Hippo.
+pub mod undeploy;
 /// Commands for starting the runtime.
 pub mod up;
+/// Command for verifying a lock file.
+pub mod verify_lock;
+/// Command for rebuilding and restarting an application as it changes.
+pub mod watch;
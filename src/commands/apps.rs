@@ -0,0 +1,535 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use bindle::{Id, Invoice};
+use clap::{Parser, Subcommand};
+use hippo::Client;
+use serde::Serialize;
+use spin_loader::{
+    bindle::{config::RawAppManifest, SPIN_MANIFEST_MEDIA_TYPE},
+    local::config::RawAppManifestAnyVersion,
+};
+use uuid::Uuid;
+
+use crate::{
+    commands::{
+        deploy::{deployed_app_name, get_app_id, HippoAuth, SPIN_DEPLOY_CHANNEL_NAME},
+        login::{logins_path, Logins},
+    },
+    opts::*,
+};
+
+/// Commands for inspecting applications deployed to Hippo.
+#[derive(Subcommand, Debug)]
+pub enum AppsCommands {
+    /// Compare the revision of an application deployed to Hippo with the
+    /// local manifest, listing what differs.
+    Diff(DiffCommand),
+}
+
+impl AppsCommands {
+    pub async fn run(self) -> Result<()> {
+        match self {
+            Self::Diff(cmd) => cmd.run().await,
+        }
+    }
+}
+
+#[derive(Parser, Debug)]
+pub struct DiffCommand {
+    /// Path to spin.toml
+    #[clap(
+        name = APP_CONFIG_FILE_OPT,
+        short = 'f',
+        long = "file",
+        default_value = "spin.toml"
+    )]
+    pub app: PathBuf,
+
+    /// URL of bindle server (defaults to the one cached by `spin login`)
+    #[clap(
+        name = BINDLE_SERVER_URL_OPT,
+        long = "bindle-server",
+        env = BINDLE_URL_ENV,
+    )]
+    pub bindle_server_url: Option<String>,
+
+    /// Basic http auth username for the bindle server
+    #[clap(
+        name = BINDLE_USERNAME,
+        long = "bindle-username",
+        env = BINDLE_USERNAME,
+        requires = BINDLE_PASSWORD
+    )]
+    pub bindle_username: Option<String>,
+
+    /// Basic http auth password for the bindle server
+    #[clap(
+        name = BINDLE_PASSWORD,
+        long = "bindle-password",
+        env = BINDLE_PASSWORD,
+        requires = BINDLE_USERNAME
+    )]
+    pub bindle_password: Option<String>,
+
+    /// Ignore server certificate errors from bindle and hippo
+    #[clap(
+        name = INSECURE_OPT,
+        short = 'k',
+        long = "insecure",
+        takes_value = false,
+    )]
+    pub insecure: bool,
+
+    /// URL of hippo server (defaults to the one last logged in to with
+    /// `spin login`)
+    #[clap(
+        name = HIPPO_SERVER_URL_OPT,
+        long = "hippo-server",
+        env = HIPPO_URL_ENV,
+    )]
+    pub hippo_server_url: Option<String>,
+
+    /// Hippo username (not needed after `spin login`)
+    #[clap(
+        name = "HIPPO_USERNAME",
+        long = "hippo-username",
+        env = "HIPPO_USERNAME",
+        requires = "HIPPO_PASSWORD"
+    )]
+    pub hippo_username: Option<String>,
+
+    /// Hippo password (not needed after `spin login`)
+    #[clap(
+        name = "HIPPO_PASSWORD",
+        long = "hippo-password",
+        env = "HIPPO_PASSWORD",
+        requires = "HIPPO_USERNAME"
+    )]
+    pub hippo_password: Option<String>,
+
+    /// Hippo API key, used instead of logging in
+    #[clap(
+        name = "HIPPO_API_KEY",
+        long = "hippo-api-key",
+        env = "HIPPO_API_KEY",
+        conflicts_with_all = &["HIPPO_USERNAME", "HIPPO_PASSWORD"]
+    )]
+    pub hippo_api_key: Option<String>,
+
+    /// Name the application was deployed as, instead of the name in spin.toml
+    #[clap(long = "app-name")]
+    pub app_name: Option<String>,
+
+    /// Prefix the application name was deployed with
+    #[clap(long = "name-prefix")]
+    pub name_prefix: Option<String>,
+
+    /// Name of the Hippo channel whose active revision to compare (defaults
+    /// to spin-deploy)
+    #[clap(long = "channel", env = "SPIN_DEPLOY_CHANNEL")]
+    pub channel: Option<String>,
+}
+
+impl DiffCommand {
+    pub async fn run(self) -> Result<()> {
+        spin_loader::offline::ensure_online("compare an application with its deployed revision")?;
+        let RawAppManifestAnyVersion::V1(cfg) =
+            spin_loader::local::raw_manifest_from_file(&self.app).await?;
+        let name = deployed_app_name(&cfg, self.app_name.as_deref(), self.name_prefix.as_deref());
+
+        let logins = match logins_path() {
+            Ok(path) => Logins::load(&path).await?,
+            Err(_) => Logins::default(),
+        };
+        let (url, login) = match logins.get(self.hippo_server_url.as_deref()) {
+            Some((url, login)) => (url.to_owned(), Some(login)),
+            None => match &self.hippo_server_url {
+                Some(url) => (url.clone(), None),
+                None => bail!("No Hippo server given: pass --hippo-server, or run `spin login`"),
+            },
+        };
+        let insecure = self.insecure || login.map_or(false, |l| l.insecure);
+        let hippo_client = HippoAuth {
+            url: &url,
+            endpoint: &url,
+            insecure,
+            api_key: self.hippo_api_key.as_deref(),
+            username: self.hippo_username.as_deref(),
+            password: self.hippo_password.as_deref(),
+            login,
+        }
+        .client()
+        .await?;
+
+        let app_id = get_app_id(&hippo_client, &name)
+            .await?
+            .with_context(|| format!("Application {} is not deployed to {}", name, url))?;
+        let channel = self.channel.as_deref().unwrap_or(SPIN_DEPLOY_CHANNEL_NAME);
+        let revision = active_revision(&hippo_client, app_id, channel)
+            .await?
+            .with_context(|| {
+                format!(
+                    "Channel {} of application {} has no active revision",
+                    channel, name
+                )
+            })?;
+
+        let bindle_server_url = self
+            .bindle_server_url
+            .clone()
+            .or_else(|| login.and_then(|l| l.bindle_server_url.clone()))
+            .context("No bindle server given: pass --bindle-server, or run `spin login`")?;
+        let (bindle_username, bindle_password) = match (&self.bindle_username, login) {
+            (None, Some(login)) => (login.bindle_username.clone(), login.bindle_password.clone()),
+            _ => (self.bindle_username.clone(), self.bindle_password.clone()),
+        };
+        let bindle_connection_info = spin_publish::BindleConnectionInfo::new(
+            &bindle_server_url,
+            insecure,
+            bindle_username,
+            bindle_password,
+        );
+
+        let bindle_id: Id = format!("{}/{}", name, revision)
+            .parse()
+            .with_context(|| format!("Invalid bindle ID for revision {}", revision))?;
+        let deployed = deployed_bindle(&bindle_connection_info, &bindle_id)
+            .await
+            .with_context(|| {
+                format!(
+                    "Failed to get bindle {} from {}: only applications deployed through a bindle server can be compared",
+                    bindle_id, bindle_server_url
+                )
+            })?;
+        let local = local_bindle(&self.app, name.clone()).await?;
+
+        let drift = drift(&deployed, &local, true);
+        if drift.is_empty() {
+            println!(
+                "Revision {} on channel {} of {} matches {}",
+                revision,
+                channel,
+                name,
+                self.app.display()
+            );
+            return Ok(());
+        }
+        println!(
+            "Revision {} on channel {} of {} differs from {}:",
+            revision,
+            channel,
+            name,
+            self.app.display()
+        );
+        for line in &drift {
+            println!("  {}", line);
+        }
+        bail!(
+            "Found {} differences: deploying now would overwrite them",
+            drift.len()
+        )
+    }
+}
+
+/// Stages the local application as a bindle, as `spin deploy` would.
+pub(crate) async fn local_bindle(app: &Path, name: String) -> Result<Bindle> {
+    let scratch_dir = tempfile::tempdir()?;
+    let (invoice, sources) =
+        spin_publish::expand_manifest(app, Some(name), None, None, scratch_dir.path())
+            .await
+            .with_context(|| format!("Failed to expand '{}' to a bindle", app.display()))?;
+    let manifest_path = invoice
+        .parcel
+        .iter()
+        .flatten()
+        .find(|p| p.label.media_type == SPIN_MANIFEST_MEDIA_TYPE)
+        .and_then(|p| sources.source(&p.label.sha256))
+        .context("The staged bindle has no manifest")?;
+    let manifest = toml::from_slice(&tokio::fs::read(manifest_path).await?)?;
+    Ok(Bindle { invoice, manifest })
+}
+
+/// The revision number of the active revision of the given channel of an
+/// app, if it has one.
+pub(crate) async fn active_revision(
+    hippo_client: &Client,
+    app_id: Uuid,
+    channel: &str,
+) -> Result<Option<String>> {
+    let channels_vm = Client::list_channels(hippo_client).await?;
+    let channel = channels_vm
+        .items
+        .into_iter()
+        .find(|c| c.app_id == app_id && c.name == channel)
+        .with_context(|| format!("The application has no channel {}", channel))?;
+    Ok(channel.active_revision.map(|r| r.revision_number))
+}
+
+/// The invoice of a bindle and its Spin manifest.
+pub(crate) struct Bindle {
+    invoice: Invoice,
+    manifest: RawAppManifest,
+}
+
+pub(crate) async fn deployed_bindle(
+    bindle_connection_info: &spin_publish::BindleConnectionInfo,
+    bindle_id: &Id,
+) -> Result<Bindle> {
+    let client = bindle_connection_info.client()?;
+    let invoice = client.get_invoice(bindle_id).await?;
+    let manifest_parcel = invoice
+        .parcel
+        .iter()
+        .flatten()
+        .find(|p| p.label.media_type == SPIN_MANIFEST_MEDIA_TYPE)
+        .context("The bindle has no Spin manifest")?;
+    let manifest = toml::from_slice(
+        &client
+            .get_parcel(bindle_id, &manifest_parcel.label.sha256)
+            .await?,
+    )?;
+    Ok(Bindle { invoice, manifest })
+}
+
+/// Describes how the local bindle differs from the deployed one, including
+/// the files of components if `files` is set.
+pub(crate) fn drift(deployed: &Bindle, local: &Bindle, files: bool) -> Vec<String> {
+    let mut drift = vec![];
+    let (d, l) = (&deployed.manifest, &local.manifest);
+    if d.trigger != l.trigger {
+        drift.push("application trigger changed".to_owned());
+    }
+    map_drift(&mut drift, "", "variable", &d.config, &l.config);
+
+    let deployed_components: BTreeMap<_, _> =
+        d.components.iter().map(|c| (c.id.as_str(), c)).collect();
+    let local_components: BTreeMap<_, _> =
+        l.components.iter().map(|c| (c.id.as_str(), c)).collect();
+    for id in keys(&deployed_components, &local_components) {
+        let prefix = format!("component {}: ", id);
+        let (d, l) = match (deployed_components.get(id), local_components.get(id)) {
+            (Some(d), Some(l)) => (d, l),
+            (Some(_), None) => {
+                drift.push(format!("{}only in the deployed revision", prefix));
+                continue;
+            }
+            _ => {
+                drift.push(format!("{}only in the local manifest", prefix));
+                continue;
+            }
+        };
+        if d.source != l.source {
+            drift.push(format!("{}module changed", prefix));
+        }
+        if value(&d.trigger) != value(&l.trigger) {
+            drift.push(format!("{}trigger changed", prefix));
+        }
+        if d.wasm.allowed_http_hosts != l.wasm.allowed_http_hosts {
+            drift.push(format!("{}allowed HTTP hosts changed", prefix));
+        }
+        if d.wasm.allowed_blob_containers != l.wasm.allowed_blob_containers {
+            drift.push(format!("{}allowed blob containers changed", prefix));
+        }
+        if d.wasm.allowed_database_hosts != l.wasm.allowed_database_hosts {
+            drift.push(format!("{}allowed database hosts changed", prefix));
+        }
+        if d.wasm.key_value_stores != l.wasm.key_value_stores {
+            drift.push(format!("{}key-value stores changed", prefix));
+        }
+        if d.wasm.allowed_jwt_issuers != l.wasm.allowed_jwt_issuers {
+            drift.push(format!("{}allowed JWT issuers changed", prefix));
+        }
+        if d.wasm.allowed_crypto_keys != l.wasm.allowed_crypto_keys {
+            drift.push(format!("{}allowed crypto keys changed", prefix));
+        }
+        if d.wasm.load != l.wasm.load {
+            drift.push(format!("{}load policy changed", prefix));
+        }
+        if d.wasm.limits != l.wasm.limits {
+            drift.push(format!("{}resource limits changed", prefix));
+        }
+        if d.wasm.log_level != l.wasm.log_level {
+            drift.push(format!("{}log level changed", prefix));
+        }
+        map_drift(
+            &mut drift,
+            &prefix,
+            "environment variable",
+            &d.wasm.environment,
+            &l.wasm.environment,
+        );
+        map_drift(&mut drift, &prefix, "variable", &d.config, &l.config);
+        map_drift(
+            &mut drift,
+            &prefix,
+            "host configuration",
+            &d.wasm.host_config,
+            &l.wasm.host_config,
+        );
+        if files {
+            let deployed_files = group_files(&deployed.invoice, d.wasm.files.as_deref());
+            let local_files = group_files(&local.invoice, l.wasm.files.as_deref());
+            map_drift(&mut drift, &prefix, "file", &deployed_files, &local_files);
+        }
+    }
+    drift
+}
+
+/// Describes how the entries of two maps, serialized as tables, differ.
+fn map_drift(
+    drift: &mut Vec<String>,
+    prefix: &str,
+    kind: &str,
+    deployed: &impl Serialize,
+    local: &impl Serialize,
+) {
+    let (deployed, local) = (table(deployed), table(local));
+    for key in keys(&deployed, &local) {
+        match (deployed.get(key), local.get(key)) {
+            (Some(d), Some(l)) if d == l => continue,
+            (Some(_), Some(_)) => drift.push(format!("{}{} {} changed", prefix, kind, key)),
+            (Some(_), None) => drift.push(format!(
+                "{}{} {} only in the deployed revision",
+                prefix, kind, key
+            )),
+            _ => drift.push(format!(
+                "{}{} {} only in the local manifest",
+                prefix, kind, key
+            )),
+        }
+    }
+}
+
+fn keys<'a, K: Ord, V, W>(a: &'a BTreeMap<K, V>, b: &'a BTreeMap<K, W>) -> BTreeSet<&'a K> {
+    a.keys().chain(b.keys()).collect()
+}
+
+fn value(value: &impl Serialize) -> Option<toml::Value> {
+    toml::Value::try_from(value).ok()
+}
+
+fn table(value: &impl Serialize) -> BTreeMap<String, toml::Value> {
+    match toml::Value::try_from(value) {
+        Ok(toml::Value::Table(table)) => table.into_iter().collect(),
+        _ => BTreeMap::new(),
+    }
+}
+
+/// The SHAs of the files in the given parcel group, by name.
+fn group_files(invoice: &Invoice, group: Option<&str>) -> BTreeMap<String, String> {
+    let group = match group {
+        Some(group) => group,
+        None => return BTreeMap::new(),
+    };
+    invoice
+        .parcel
+        .iter()
+        .flatten()
+        .filter(|p| {
+            p.conditions
+                .as_ref()
+                .and_then(|c| c.member_of.as_ref())
+                .map_or(false, |groups| groups.iter().any(|g| g == group))
+        })
+        .map(|p| (p.label.name.clone(), p.label.sha256.clone()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bindle::{BindleSpec, Condition, Label, Parcel};
+
+    fn bindle(manifest: &str, files: &[(&str, &str)]) -> Bindle {
+        let parcel = files
+            .iter()
+            .map(|(name, sha256)| Parcel {
+                label: Label {
+                    name: name.to_string(),
+                    sha256: sha256.to_string(),
+                    size: 1,
+                    media_type: "text/plain".to_owned(),
+                    annotations: None,
+                    feature: None,
+                    origin: None,
+                },
+                conditions: Some(Condition {
+                    member_of: Some(vec!["files-api".to_owned()]),
+                    requires: None,
+                }),
+            })
+            .collect();
+        Bindle {
+            invoice: Invoice {
+                bindle_version: "1.0.0".to_owned(),
+                yanked: None,
+                bindle: BindleSpec {
+                    id: "app/1.0.0".parse().unwrap(),
+                    description: None,
+                    authors: None,
+                },
+                annotations: None,
+                parcel: Some(parcel),
+                group: None,
+                signature: None,
+                yanked_signature: None,
+            },
+            manifest: toml::from_str(manifest).unwrap(),
+        }
+    }
+
+    fn manifest(source: &str, log_level: &str) -> String {
+        format!(
+            r#"
+trigger = {{ type = "http", base = "/" }}
+variables = {{ greeting = {{ default = "hello" }} }}
+
+[[component]]
+id = "api"
+source = "{}"
+files = "files-api"
+environment = {{ LOG_LEVEL = "{}" }}
+trigger = {{ route = "/..." }}
+"#,
+            source, log_level
+        )
+    }
+
+    #[test]
+    fn test_no_drift() {
+        let deployed = bindle(&manifest("aaa", "info"), &[("index.html", "111")]);
+        let local = bindle(&manifest("aaa", "info"), &[("index.html", "111")]);
+        assert!(drift(&deployed, &local, true).is_empty());
+    }
+
+    #[test]
+    fn test_drift() {
+        let deployed = bindle(
+            &manifest("aaa", "debug"),
+            &[("index.html", "111"), ("hotfix.js", "222")],
+        );
+        let local = bindle(&manifest("bbb", "info"), &[("index.html", "333")]);
+        assert_eq!(
+            drift(&deployed, &local, true),
+            vec![
+                "component api: module changed",
+                "component api: environment variable LOG_LEVEL changed",
+                "component api: file hotfix.js only in the deployed revision",
+                "component api: file index.html changed",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_drift_without_files() {
+        let deployed = bindle(&manifest("aaa", "info"), &[("index.html", "111")]);
+        let local = bindle(&manifest("aaa", "info"), &[("index.html", "333")]);
+        assert!(drift(&deployed, &local, false).is_empty());
+        assert_eq!(
+            drift(&deployed, &local, true),
+            vec!["component api: file index.html changed"]
+        );
+    }
+}
@@ -1,10 +1,21 @@
 use std::path::PathBuf;
 
 use anyhow::{Context, Result};
-use clap::{Parser, Subcommand};
-use semver::BuildMetadata;
+use clap::{ArgEnum, Parser, Subcommand};
+use semver::{BuildMetadata, Version};
+use spin_loader::local::config::RawAppManifestAnyVersion;
+use spin_publish::BindleConnectionInfo;
 
-use crate::{opts::*, parse_buildinfo, sloth::warn_if_slow_response};
+use crate::{
+    commands::{
+        deploy::push_summary,
+        signing_key::{signing_key, SIGNING_KEY_ENV},
+    },
+    network::NetworkOpts,
+    opts::*,
+    parse_buildinfo,
+    sloth::warn_if_slow_response,
+};
 
 /// Commands for publishing applications as bindles.
 #[derive(Subcommand, Debug)]
@@ -25,6 +36,48 @@ impl BindleCommands {
     }
 }
 
+/// How the version of a published bindle is determined.
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VersionStrategy {
+    /// Use the version in the application manifest.
+    Manifest,
+    /// Increment the patch number of the latest version on the bindle server.
+    SemverBump,
+    /// Use a version derived from the current date and time.
+    Date,
+}
+
+impl VersionStrategy {
+    /// Returns the version to publish the named bindle with, or `None` to
+    /// use the manifest version.
+    pub(crate) async fn resolve(
+        self,
+        name: &str,
+        manifest_version: &str,
+        bindle_connection_info: &BindleConnectionInfo,
+    ) -> Result<Option<Version>> {
+        match self {
+            Self::Manifest => Ok(None),
+            Self::SemverBump => {
+                let manifest_version = Version::parse(manifest_version).with_context(|| {
+                    format!(
+                        "Version '{}' in manifest is not a semantic version, so cannot be bumped",
+                        manifest_version
+                    )
+                })?;
+                let version = spin_publish::next_patch_version(
+                    bindle_connection_info,
+                    name,
+                    &manifest_version,
+                )
+                .await?;
+                Ok(Some(version))
+            }
+            Self::Date => Ok(Some(spin_publish::date_version(chrono::Utc::now()))),
+        }
+    }
+}
+
 /// Create a standalone bindle for subsequent publication.
 #[derive(Parser, Debug)]
 pub struct Prepare {
@@ -51,6 +104,12 @@ pub struct Prepare {
         short = 'd',
     )]
     pub staging_dir: PathBuf,
+
+    /// Label of the key to sign the bindle with (defaults to the first key
+    /// generated or imported with `spin signing-key`; the bindle is not
+    /// signed if there is none)
+    #[clap(long = "signing-key", env = SIGNING_KEY_ENV)]
+    pub signing_key: Option<String>,
 }
 
 /// Publish an application as a bindle.
@@ -115,6 +174,26 @@ pub struct Push {
         takes_value = false,
     )]
     pub insecure: bool,
+
+    /// Pin the certificates of the servers on first use, and fail if they
+    /// change, rather than requiring them to be valid for the system CAs
+    #[clap(long = "trust-on-first-use", conflicts_with = INSECURE_OPT)]
+    pub trust_on_first_use: bool,
+
+    /// How the bindle version is determined: from the manifest, by bumping
+    /// the patch number of the latest version on the server, or from the
+    /// current date and time
+    #[clap(long = "version-strategy", arg_enum, default_value = "manifest")]
+    pub version_strategy: VersionStrategy,
+
+    /// Label of the key to sign the bindle with (defaults to the first key
+    /// generated or imported with `spin signing-key`; the bindle is not
+    /// signed if there is none)
+    #[clap(long = "signing-key", env = SIGNING_KEY_ENV)]
+    pub signing_key: Option<String>,
+
+    #[clap(flatten)]
+    pub network: NetworkOpts,
 }
 
 impl Prepare {
@@ -127,15 +206,25 @@ impl Prepare {
 
         let dest_dir = &self.staging_dir;
 
-        let (invoice, sources) = spin_publish::expand_manifest(app_file, self.buildinfo, &dest_dir)
-            .await
-            .with_context(|| format!("Failed to expand '{}' to a bindle", app_file.display()))?;
+        let (invoice, sources) =
+            spin_publish::expand_manifest(app_file, None, None, self.buildinfo, &dest_dir)
+                .await
+                .with_context(|| {
+                    format!("Failed to expand '{}' to a bindle", app_file.display())
+                })?;
 
         let bindle_id = &invoice.bindle.id;
 
-        spin_publish::write(&source_dir, &dest_dir, &invoice, &sources)
-            .await
-            .with_context(|| crate::write_failed_msg(bindle_id, dest_dir))?;
+        let signing_key = signing_key(self.signing_key.as_deref()).await?;
+        spin_publish::write(
+            &source_dir,
+            &dest_dir,
+            &invoice,
+            &sources,
+            signing_key.as_ref(),
+        )
+        .await
+        .with_context(|| crate::write_failed_msg(bindle_id, dest_dir))?;
 
         // We can't try to canonicalize it until the directory has been created
         let full_dest_dir =
@@ -150,18 +239,45 @@ impl Prepare {
 
 impl Push {
     pub async fn run(self) -> Result<()> {
+        spin_loader::offline::ensure_online("push bindle to server")?;
+        self.network.apply()?;
+        // A pinned server is reached through a relay accepting only its
+        // pinned certificate.
+        let relay = if self.trust_on_first_use {
+            crate::trust::relay_pinned(
+                &self.bindle_server_url,
+                crate::trust::DEFAULT_PROFILE,
+                self.network.client_builder(false)?,
+            )
+            .await?
+        } else {
+            None
+        };
         let app_file = self
             .app
             .as_deref()
             .unwrap_or_else(|| DEFAULT_MANIFEST_FILE.as_ref());
         let source_dir = crate::app_dir(app_file)?;
         let bindle_connection_info = spin_publish::BindleConnectionInfo::new(
-            &self.bindle_server_url,
+            relay.as_deref().unwrap_or(&self.bindle_server_url[..]),
             self.insecure,
             self.bindle_username,
             self.bindle_password,
         );
 
+        let RawAppManifestAnyVersion::V1(cfg) =
+            spin_loader::local::raw_manifest_from_file(&app_file).await?;
+        let version = self
+            .version_strategy
+            .resolve(&cfg.info.name, &cfg.info.version, &bindle_connection_info)
+            .await?;
+        // Date-based versions are not unique to the content, so identify it
+        // in the build metadata.
+        let buildinfo = match (self.buildinfo, self.version_strategy) {
+            (None, VersionStrategy::Date) => Some(crate::compute_buildinfo(app_file, &cfg)?),
+            (buildinfo, _) => buildinfo,
+        };
+
         // TODO: only create this if not given a staging dir
         let temp_dir = tempfile::tempdir()?;
 
@@ -170,21 +286,37 @@ impl Push {
             Some(path) => path.as_path(),
         };
 
-        let (invoice, sources) = spin_publish::expand_manifest(app_file, self.buildinfo, &dest_dir)
-            .await
-            .with_context(|| format!("Failed to expand '{}' to a bindle", app_file.display()))?;
+        let (invoice, sources) =
+            spin_publish::expand_manifest(app_file, None, version, buildinfo, &dest_dir)
+                .await
+                .with_context(|| {
+                    format!("Failed to expand '{}' to a bindle", app_file.display())
+                })?;
 
         let bindle_id = &invoice.bindle.id;
 
-        spin_publish::write(&source_dir, &dest_dir, &invoice, &sources)
-            .await
-            .with_context(|| crate::write_failed_msg(bindle_id, dest_dir))?;
+        let signing_key = signing_key(self.signing_key.as_deref()).await?;
+        spin_publish::write(
+            &source_dir,
+            &dest_dir,
+            &invoice,
+            &sources,
+            signing_key.as_ref(),
+        )
+        .await
+        .with_context(|| crate::write_failed_msg(bindle_id, dest_dir))?;
 
         let _sloth_warning = warn_if_slow_response(&self.bindle_server_url);
 
-        spin_publish::push_all(&dest_dir, bindle_id, bindle_connection_info)
-            .await
-            .context("Failed to push bindle to server")?;
+        let summary = spin_publish::push_all(
+            &dest_dir,
+            bindle_id,
+            bindle_connection_info,
+            Default::default(),
+        )
+        .await
+        .context("Failed to push bindle to server")?;
+        println!("{}", push_summary(&summary));
 
         println!("pushed: {}", bindle_id);
         Ok(())
@@ -3,6 +3,7 @@ use std::{ffi::OsString, path::PathBuf};
 use anyhow::Result;
 use clap::Parser;
 
+use spin_build::BuildOptions;
 use spin_loader::local::{config::RawAppManifestAnyVersion, raw_manifest_from_file};
 
 use crate::opts::{APP_CONFIG_FILE_OPT, BUILD_UP_OPT, DEFAULT_MANIFEST_FILE};
@@ -25,6 +26,16 @@ pub struct BuildCommand {
     #[clap(name = BUILD_UP_OPT, short = 'u', long = "up")]
     pub up: bool,
 
+    /// The number of build commands to run at once. Defaults to the number
+    /// of CPUs.
+    #[clap(short = 'j', long = "jobs")]
+    pub jobs: Option<usize>,
+
+    /// Build only the given component. May be repeated. The components it
+    /// depends on are not rebuilt.
+    #[clap(short = 'c', long = "component", multiple_occurrences = true)]
+    pub components: Vec<String>,
+
     #[clap(requires = BUILD_UP_OPT)]
     pub up_args: Vec<OsString>,
 }
@@ -37,7 +48,14 @@ impl BuildCommand {
             .unwrap_or_else(|| DEFAULT_MANIFEST_FILE.as_ref());
         let RawAppManifestAnyVersion::V1(app) = raw_manifest_from_file(&manifest_file).await?;
 
-        spin_build::build(app, manifest_file).await?;
+        let mut options = BuildOptions::default();
+        if let Some(jobs) = self.jobs {
+            options.jobs = jobs;
+        }
+        if !self.components.is_empty() {
+            options.components = Some(self.components.iter().cloned().collect());
+        }
+        spin_build::build(app, manifest_file, &options).await?;
 
         if self.up {
             let mut cmd = UpCommand::parse_from(
@@ -0,0 +1,102 @@
+use std::time::{Duration, SystemTime};
+
+use anyhow::{Context, Result};
+use clap::{Parser, Subcommand};
+use comfy_table::Table;
+use spin_loader::bindle::{cache as parcel_cache, ParcelCache};
+
+use super::info::format_size;
+
+/// Commands for managing the cache of parcels pulled from Bindle servers.
+#[derive(Subcommand, Debug)]
+pub enum CacheCommands {
+    /// List the cached parcels.
+    List(List),
+
+    /// Remove cached parcels.
+    Prune(Prune),
+}
+
+impl CacheCommands {
+    pub async fn run(self) -> Result<()> {
+        match self {
+            Self::List(cmd) => cmd.run().await,
+            Self::Prune(cmd) => cmd.run().await,
+        }
+    }
+}
+
+/// List the cached parcels.
+#[derive(Parser, Debug)]
+pub struct List {}
+
+/// Remove cached parcels.
+#[derive(Parser, Debug)]
+pub struct Prune {
+    /// Only remove the parcels cached more than this many days ago. All
+    /// cached parcels are removed by default.
+    #[clap(long = "older-than", value_name = "DAYS")]
+    pub older_than: Option<u64>,
+}
+
+impl List {
+    pub async fn run(self) -> Result<()> {
+        let cache = cache()?;
+        let parcels = cache.list()?;
+        println!("Parcel cache: {}", cache.path().display());
+        if parcels.is_empty() {
+            println!("No parcels are cached");
+            return Ok(());
+        }
+        let now = SystemTime::now();
+        let mut table = Table::new();
+        table.set_header(vec!["Digest", "Size", "Cached"]);
+        table.load_preset(comfy_table::presets::ASCII_BORDERS_ONLY_CONDENSED);
+        for parcel in &parcels {
+            let age = now.duration_since(parcel.cached_at).unwrap_or_default();
+            table.add_row(vec![
+                parcel.sha256.clone(),
+                format_size(parcel.bytes),
+                format_age(age),
+            ]);
+        }
+        println!("{}", table);
+        println!(
+            "{} parcel(s), {}",
+            parcels.len(),
+            format_size(parcels.iter().map(|p| p.bytes).sum())
+        );
+        Ok(())
+    }
+}
+
+impl Prune {
+    pub async fn run(self) -> Result<()> {
+        let older_than = self
+            .older_than
+            .map(|days| Duration::from_secs(days * 24 * 60 * 60));
+        let removed = cache()?
+            .prune(older_than)
+            .context("Failed to prune the parcel cache")?;
+        println!(
+            "Removed {} parcel(s), {}",
+            removed.len(),
+            format_size(removed.iter().map(|p| p.bytes).sum())
+        );
+        Ok(())
+    }
+}
+
+fn cache() -> Result<ParcelCache> {
+    let dir = parcel_cache::default_dir().context("Cannot find the cache directory")?;
+    Ok(ParcelCache::new(dir))
+}
+
+fn format_age(age: Duration) -> String {
+    let days = age.as_secs() / (24 * 60 * 60);
+    match days {
+        0 => "today".to_owned(),
+        1 => "1 day ago".to_owned(),
+        n => format!("{} days ago", n),
+    }
+}
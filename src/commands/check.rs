@@ -0,0 +1,129 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Result};
+use clap::Parser;
+use spin_loader::local::{
+    assets::MountMatches, check::check, match_component_files, raw_manifest_from_file,
+};
+
+use crate::{app_dir, opts::*};
+
+/// Check an application manifest without running it.
+#[derive(Parser, Debug)]
+#[clap(about = "Check that the application manifest and the files it declares are valid")]
+pub struct CheckCommand {
+    /// Path to spin.toml
+    #[clap(
+        name = APP_CONFIG_FILE_OPT,
+        short = 'f',
+        long = "file",
+        default_value = "spin.toml"
+    )]
+    pub app: PathBuf,
+
+    /// List the files mounted in the component, with the pattern that
+    /// matches each, and the files its exclude_files exclude.
+    #[clap(long = "explain-files", value_name = "COMPONENT")]
+    pub explain_files: Option<String>,
+}
+
+impl CheckCommand {
+    pub async fn run(self) -> Result<()> {
+        let app_dir = app_dir(&self.app)?;
+        let problems = check(&self.app).await?;
+        if !problems.is_empty() {
+            for problem in &problems {
+                eprintln!("{}", problem);
+            }
+            bail!(
+                "Found {} problem{} in {}",
+                problems.len(),
+                if problems.len() == 1 { "" } else { "s" },
+                self.app.display()
+            );
+        }
+        let manifest = raw_manifest_from_file(&self.app).await?;
+
+        let files = match_component_files(&manifest, &app_dir)?;
+        print_file_warnings(&files);
+        if let Some(component) = &self.explain_files {
+            let spin_loader::local::config::RawAppManifestAnyVersion::V1(raw) = &manifest;
+            if !raw.components.iter().any(|c| &c.id == component) {
+                return Err(anyhow!(
+                    "No component {} in {}",
+                    component,
+                    self.app.display()
+                ));
+            }
+            let matches = files
+                .iter()
+                .find(|(id, _)| id == component)
+                .map(|(_, matches)| matches.as_slice())
+                .unwrap_or_default();
+            explain_files(component, matches, &app_dir);
+            return Ok(());
+        }
+        println!("{} is valid", self.app.display());
+        Ok(())
+    }
+}
+
+/// Warns about the file patterns of the application that match no files, or
+/// more than likely intended, without failing if the files cannot be matched.
+pub(crate) async fn warn_about_files(app: &Path) {
+    let warn = async {
+        let app_dir = app_dir(app)?;
+        let manifest = raw_manifest_from_file(&app).await?;
+        let files = tokio::task::spawn_blocking(move || match_component_files(&manifest, &app_dir))
+            .await??;
+        print_file_warnings(&files);
+        Ok::<_, anyhow::Error>(())
+    };
+    if let Err(e) = warn.await {
+        tracing::debug!("Cannot check the files of the application: {:#}", e);
+    }
+}
+
+fn print_file_warnings(files: &[(String, Vec<MountMatches>)]) {
+    for (component, matches) in files {
+        for warning in matches.iter().filter_map(MountMatches::warning) {
+            eprintln!("Warning: component {}: {}", component, warning);
+        }
+    }
+}
+
+fn explain_files(component: &str, matches: &[MountMatches], app_dir: &Path) {
+    let relative = |path: &Path| {
+        path.strip_prefix(app_dir)
+            .unwrap_or(path)
+            .display()
+            .to_string()
+    };
+    let included = matches.iter().flat_map(|m| &m.included);
+    let bytes: u64 = included
+        .clone()
+        .filter_map(|f| std::fs::metadata(&f.src).ok())
+        .map(|m| m.len())
+        .sum();
+    println!(
+        "Component {} mounts {} files ({} bytes)",
+        component,
+        included.count(),
+        bytes
+    );
+    for m in matches {
+        println!();
+        println!(
+            "{}: {} files, {} excluded",
+            m.mount,
+            m.included.len(),
+            m.excluded.len()
+        );
+        for file in &m.included {
+            println!("  {} -> /{}", relative(&file.src), file.relative_dst);
+        }
+        for (file, pattern) in &m.excluded {
+            println!("  {} excluded by {}", relative(&file.src), pattern);
+        }
+    }
+}
@@ -0,0 +1,217 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+use clap::{Parser, Subcommand};
+use spin_loader::local::config::RawAppManifestAnyVersion;
+
+use crate::{
+    app_dir,
+    contract::{self, Contract, ContractRequest, Interaction, CONTRACT_FORMAT},
+    opts::*,
+};
+
+/// The URL `spin up` serves applications at by default.
+const DEFAULT_APP_URL: &str = "http://127.0.0.1:3000";
+
+/// Commands for recording and verifying the contracts of an application.
+#[derive(Subcommand, Debug)]
+pub enum ContractCommands {
+    /// Record the responses of a running application to a list of requests
+    /// as a contract.
+    Record(RecordCommand),
+
+    /// Check that a running application still responds as its contracts
+    /// recorded.
+    Verify(VerifyCommand),
+}
+
+impl ContractCommands {
+    pub async fn run(self) -> Result<()> {
+        match self {
+            Self::Record(cmd) => cmd.run().await,
+            Self::Verify(cmd) => cmd.run().await,
+        }
+    }
+}
+
+/// Record a contract from a running application.
+#[derive(Parser, Debug)]
+pub struct RecordCommand {
+    /// Path to spin.toml
+    #[clap(
+        name = APP_CONFIG_FILE_OPT,
+        short = 'f',
+        long = "file",
+        default_value = "spin.toml"
+    )]
+    pub app: PathBuf,
+
+    /// Name of the contract, saved as NAME.json in the contracts directory
+    #[clap(name = "NAME")]
+    pub name: String,
+
+    /// Base URL of the running application
+    #[clap(long = "url", default_value = DEFAULT_APP_URL)]
+    pub url: String,
+
+    /// Request to record, as `[METHOD] PATH` (GET if no method is given).
+    /// May be repeated. Defaults to the requests of the existing contract,
+    /// to record it again
+    #[clap(long = "route", value_name = "[METHOD] PATH")]
+    pub routes: Vec<String>,
+
+    /// Header (`NAME: VALUE`) to send with the recorded requests. May be
+    /// repeated
+    #[clap(long = "header", value_name = "NAME: VALUE", parse(try_from_str = parse_header))]
+    pub headers: Vec<(String, String)>,
+
+    /// Directory of the contracts (defaults to `contracts` next to spin.toml)
+    #[clap(long = "dir")]
+    pub dir: Option<PathBuf>,
+
+    /// Ignore server certificate errors from the application
+    #[clap(
+        name = INSECURE_OPT,
+        short = 'k',
+        long = "insecure",
+        takes_value = false,
+    )]
+    pub insecure: bool,
+}
+
+impl RecordCommand {
+    pub async fn run(self) -> Result<()> {
+        let RawAppManifestAnyVersion::V1(cfg) =
+            spin_loader::local::raw_manifest_from_file(&self.app).await?;
+        let dir = contracts_dir(&self.app, &self.dir)?;
+        let path = contract::contract_path(&dir, &self.name);
+
+        let requests = if self.routes.is_empty() {
+            if !path.exists() {
+                bail!("Pass the requests to record with --route");
+            }
+            Contract::load(&path)
+                .await?
+                .interactions
+                .into_iter()
+                .map(|i| i.request)
+                .collect()
+        } else {
+            self.routes
+                .iter()
+                .map(|route| self.request(route))
+                .collect::<Result<Vec<_>>>()?
+        };
+
+        let client = reqwest::Client::builder()
+            .danger_accept_invalid_certs(self.insecure)
+            .build()?;
+        let mut interactions = vec![];
+        for request in requests {
+            let response = contract::send(&client, &self.url, &request).await?;
+            println!("{} {}: {}", request.method, request.path, response.status);
+            interactions.push(Interaction { request, response });
+        }
+
+        let contract = Contract {
+            format: CONTRACT_FORMAT,
+            app: cfg.info.name,
+            app_version: cfg.info.version,
+            recorded_at: chrono::Utc::now().to_rfc3339(),
+            interactions,
+        };
+        contract.save(&path).await?;
+        println!("Recorded contract {}", path.display());
+        Ok(())
+    }
+
+    fn request(&self, route: &str) -> Result<ContractRequest> {
+        let (method, path) = match route.trim().split_once(' ') {
+            Some((method, path)) => (method.to_ascii_uppercase(), path.trim()),
+            None => ("GET".to_owned(), route.trim()),
+        };
+        if !path.starts_with('/') {
+            bail!("Invalid route '{}': the path must start with '/'", route);
+        }
+        Ok(ContractRequest {
+            method,
+            path: path.to_owned(),
+            headers: self.headers.iter().cloned().collect::<BTreeMap<_, _>>(),
+            body: None,
+        })
+    }
+}
+
+/// Verify the contracts of an application against a running build of it.
+#[derive(Parser, Debug)]
+pub struct VerifyCommand {
+    /// Path to spin.toml
+    #[clap(
+        name = APP_CONFIG_FILE_OPT,
+        short = 'f',
+        long = "file",
+        default_value = "spin.toml"
+    )]
+    pub app: PathBuf,
+
+    /// Names of the contracts to verify (defaults to all of them)
+    #[clap(name = "NAME")]
+    pub names: Vec<String>,
+
+    /// Base URL of the running application
+    #[clap(long = "url", default_value = DEFAULT_APP_URL)]
+    pub url: String,
+
+    /// Directory of the contracts (defaults to `contracts` next to spin.toml)
+    #[clap(long = "dir")]
+    pub dir: Option<PathBuf>,
+
+    /// Ignore server certificate errors from the application
+    #[clap(
+        name = INSECURE_OPT,
+        short = 'k',
+        long = "insecure",
+        takes_value = false,
+    )]
+    pub insecure: bool,
+}
+
+impl VerifyCommand {
+    pub async fn run(self) -> Result<()> {
+        let dir = contracts_dir(&self.app, &self.dir)?;
+        let names = if self.names.is_empty() {
+            contract::contract_names(&dir).await?
+        } else {
+            self.names.clone()
+        };
+        if names.is_empty() {
+            bail!("No contracts in {}", dir.display());
+        }
+        let client = reqwest::Client::builder()
+            .danger_accept_invalid_certs(self.insecure)
+            .build()?;
+        contract::verify(&client, &dir, &names, &self.url, &|line| {
+            println!("{}", line)
+        })
+        .await
+    }
+}
+
+/// The given contracts directory, or the default one next to the manifest.
+pub(crate) fn contracts_dir(app: &Path, dir: &Option<PathBuf>) -> Result<PathBuf> {
+    match dir {
+        Some(dir) => Ok(dir.clone()),
+        None => Ok(app_dir(app)?.join(contract::DEFAULT_CONTRACTS_DIR)),
+    }
+}
+
+/// Parses a header passed as `NAME: VALUE`.
+fn parse_header(s: &str) -> Result<(String, String)> {
+    let (name, value) = s
+        .split_once(':')
+        .context("Headers must be of the form `NAME: VALUE`")?;
+    Ok((name.trim().to_ascii_lowercase(), value.trim().to_owned()))
+}
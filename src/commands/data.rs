@@ -0,0 +1,111 @@
+use std::path::PathBuf;
+
+use anyhow::{bail, Result};
+use clap::{Parser, Subcommand};
+use comfy_table::Table;
+use spin_engine::DataDirConfig;
+use spin_trigger::RuntimeConfig;
+
+/// Commands for managing the data directories of applications on a host
+/// running several applications.
+#[derive(Subcommand, Debug)]
+pub enum DataCommands {
+    /// List the data directories of applications, with their usage.
+    List(List),
+
+    /// Remove the data directory of an application, for example once it is
+    /// undeployed.
+    Remove(Remove),
+}
+
+impl DataCommands {
+    pub async fn run(self) -> Result<()> {
+        match self {
+            Self::List(cmd) => cmd.run().await,
+            Self::Remove(cmd) => cmd.run().await,
+        }
+    }
+}
+
+/// The data directory configuration of the host.
+#[derive(Parser, Debug)]
+pub struct DataDirOpts {
+    /// The runtime config file applications are run with, setting
+    /// `[data_dir]`.
+    #[clap(long = "runtime-config-file", env = "RUNTIME_CONFIG_FILE")]
+    pub runtime_config_file: PathBuf,
+}
+
+impl DataDirOpts {
+    fn config(&self) -> Result<DataDirConfig> {
+        let config = RuntimeConfig::from_file(&self.runtime_config_file)?.data_dir;
+        if config.root.is_none() {
+            bail!(
+                "{} does not set a data directory root",
+                self.runtime_config_file.display()
+            );
+        }
+        Ok(config)
+    }
+}
+
+/// List the data directories of applications.
+#[derive(Parser, Debug)]
+pub struct List {
+    #[clap(flatten)]
+    pub opts: DataDirOpts,
+}
+
+impl List {
+    pub async fn run(self) -> Result<()> {
+        let dirs = self.opts.config()?.list()?;
+        if dirs.is_empty() {
+            println!("No data directories");
+            return Ok(());
+        }
+
+        let mut table = Table::new();
+        table.set_header(vec!["Application", "Used", "Quota"]);
+        table.load_preset(comfy_table::presets::ASCII_BORDERS_ONLY_CONDENSED);
+        for dir in dirs {
+            let name = dir
+                .path()
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into_owned();
+            table.add_row(vec![
+                name,
+                format!("{} bytes", dir.usage()?),
+                dir.max_bytes()
+                    .map(|max| format!("{} bytes", max))
+                    .unwrap_or_else(|| "none".to_owned()),
+            ]);
+        }
+        println!("{}", table);
+        Ok(())
+    }
+}
+
+/// Remove the data directory of an application.
+#[derive(Parser, Debug)]
+pub struct Remove {
+    #[clap(flatten)]
+    pub opts: DataDirOpts,
+
+    /// Name of the application.
+    pub app: String,
+}
+
+impl Remove {
+    pub async fn run(self) -> Result<()> {
+        // The root is checked to be set, so every application resolves.
+        let dir = self.opts.config()?.resolve(&self.app).unwrap();
+        if !dir.path().exists() {
+            bail!("Application {} has no data directory", self.app);
+        }
+        dir.remove()?;
+        println!("Removed {}", dir.path().display());
+        Ok(())
+    }
+}
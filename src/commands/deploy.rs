@@ -1,24 +1,78 @@
 use anyhow::{anyhow, bail, Context, Result};
-use bindle::Id;
-use clap::Parser;
+use bindle::{Id, Invoice};
+use clap::{ArgEnum, Parser, Subcommand};
+use futures::TryFutureExt;
 use hippo::{Client, ConnectionInfo};
 use hippo_openapi::models::ChannelRevisionSelectionStrategy;
-use semver::BuildMetadata;
+use semver::{BuildMetadata, Version};
 use serde::{Deserialize, Serialize};
-use sha2::{Digest, Sha256};
 use spin_http_engine::routes::RoutePattern;
 use spin_loader::local::config::{RawAppManifest, RawAppManifestAnyVersion};
-use spin_loader::local::{assets, config};
 use spin_manifest::{HttpTriggerConfiguration, TriggerConfig};
-use std::fs::File;
-use std::io::copy;
-use std::path::PathBuf;
+use spin_publish::OciRepository;
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
 use url::Url;
 use uuid::Uuid;
 
-use crate::{opts::*, parse_buildinfo, sloth::warn_if_slow_response};
+use crate::{
+    commands::{
+        apps::{active_revision, deployed_bindle, drift, local_bindle},
+        bindle::VersionStrategy,
+        contract::contracts_dir,
+        info::format_size,
+        login::{logins_path, Login, Logins, OidcLogin},
+        preview::PreviewCommand,
+        scale::{ChannelScale, ScaleApi},
+        signing_key::{signing_key, SIGNING_KEY_ENV},
+    },
+    contract,
+    deploy_history::{self, DeployRecord},
+    deploy_profile::{DeployOutcome, DeployProfile},
+    deploy_state::{DeployStage, DeployState},
+    expand::expand_opt,
+    network::NetworkOpts,
+    oidc::OidcProvider,
+    opts::*,
+    parse_buildinfo,
+    sloth::warn_if_slow_response,
+};
 
-const SPIN_DEPLOY_CHANNEL_NAME: &str = "spin-deploy";
+pub(crate) const SPIN_DEPLOY_CHANNEL_NAME: &str = "spin-deploy";
+
+/// How often `--readiness-timeout` polls the deployed application.
+const READINESS_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// How an application that may already exist in Hippo is deployed.
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeployStrategy {
+    /// Update the application if it exists, otherwise create it.
+    Auto,
+    /// Delete the application if it exists, and create it again.
+    Fresh,
+    /// Update the application, failing if it does not exist.
+    Upgrade,
+}
+
+/// How `spin deploy` reports a deploy.
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Messages for people reading the terminal.
+    Plain,
+    /// A JSON document describing the deploy, for scripts. Progress
+    /// messages are printed to stderr.
+    Json,
+}
+
+/// Commands for variations of the deploy workflow.
+#[derive(Subcommand, Debug)]
+pub enum DeployCommands {
+    /// Deploy, list and clean up preview deployments, for example of pull
+    /// requests.
+    Preview(PreviewCommand),
+}
 
 /// Package and upload Spin artifacts, notifying Hippo
 #[derive(Parser, Debug)]
@@ -33,13 +87,13 @@ pub struct DeployCommand {
     )]
     pub app: PathBuf,
 
-    /// URL of bindle server
+    /// URL of bindle server (defaults to the one cached by `spin login`)
     #[clap(
         name = BINDLE_SERVER_URL_OPT,
         long = "bindle-server",
         env = BINDLE_URL_ENV,
     )]
-    pub bindle_server_url: String,
+    pub bindle_server_url: Option<String>,
 
     /// Basic http auth username for the bindle server
     #[clap(
@@ -68,13 +122,14 @@ pub struct DeployCommand {
     )]
     pub insecure: bool,
 
-    /// URL of hippo server
+    /// URL of hippo server (defaults to the one last logged in to with
+    /// `spin login`)
     #[clap(
         name = HIPPO_SERVER_URL_OPT,
         long = "hippo-server",
         env = HIPPO_URL_ENV,
     )]
-    pub hippo_server_url: String,
+    pub hippo_server_url: Option<String>,
 
     /// Path to assemble the bindle before pushing (defaults to
     /// a temporary directory)
@@ -85,21 +140,38 @@ pub struct DeployCommand {
     )]
     pub staging_dir: Option<PathBuf>,
 
-    /// Hippo username
+    /// OCI repository to push the application to instead of the bindle
+    /// server, for example oci://ghcr.io/org/app
+    #[clap(long = "registry", env = "SPIN_DEPLOY_REGISTRY")]
+    pub registry: Option<OciRepository>,
+
+    /// Hippo username (not needed after `spin login`)
     #[clap(
         name = "HIPPO_USERNAME",
         long = "hippo-username",
-        env = "HIPPO_USERNAME"
+        env = "HIPPO_USERNAME",
+        requires = "HIPPO_PASSWORD"
     )]
-    pub hippo_username: String,
+    pub hippo_username: Option<String>,
 
-    /// Hippo password
+    /// Hippo password (not needed after `spin login`)
     #[clap(
         name = "HIPPO_PASSWORD",
         long = "hippo-password",
-        env = "HIPPO_PASSWORD"
+        env = "HIPPO_PASSWORD",
+        requires = "HIPPO_USERNAME"
+    )]
+    pub hippo_password: Option<String>,
+
+    /// Hippo API key, used instead of logging in, for example for a Hippo
+    /// server behind single sign-on
+    #[clap(
+        name = "HIPPO_API_KEY",
+        long = "hippo-api-key",
+        env = "HIPPO_API_KEY",
+        conflicts_with_all = &["HIPPO_USERNAME", "HIPPO_PASSWORD"]
     )]
-    pub hippo_password: String,
+    pub hippo_api_key: Option<String>,
 
     /// Disable attaching buildinfo
     #[clap(
@@ -120,223 +192,1333 @@ pub struct DeployCommand {
     /// Deploy existing bindle if it already exists on bindle server
     #[clap(short = 'e', long = "deploy-existing-bindle")]
     pub redeploy: bool,
+
+    /// Path to a deploy profile, with settings for the environment the
+    /// application is deployed to
+    #[clap(long = "profile", env = "SPIN_DEPLOY_PROFILE")]
+    pub profile: Option<PathBuf>,
+
+    /// Whether to update an existing application (upgrade), recreate it
+    /// (fresh), or update it if it exists and create it otherwise (auto)
+    #[clap(long = "strategy", arg_enum, default_value = "auto")]
+    pub strategy: DeployStrategy,
+
+    /// How the bindle version is determined: from the manifest, by bumping
+    /// the patch number of the latest version on the server, or from the
+    /// current date and time
+    #[clap(long = "version-strategy", arg_enum, default_value = "manifest")]
+    pub version_strategy: VersionStrategy,
+
+    /// Name to deploy the application as, instead of the name in spin.toml
+    #[clap(long = "app-name")]
+    pub app_name: Option<String>,
+
+    /// Prefix to add to the application name, for example to deploy it in
+    /// a namespace
+    #[clap(long = "name-prefix")]
+    pub name_prefix: Option<String>,
+
+    /// Deploy even if the application uses features Hippo does not list
+    /// as supported
+    #[clap(long = "skip-capability-check")]
+    pub skip_capability_check: bool,
+
+    /// Pin the certificates of the servers on first use, and fail if they
+    /// change, rather than requiring them to be valid for the system CAs
+    #[clap(long = "trust-on-first-use", conflicts_with = INSECURE_OPT)]
+    pub trust_on_first_use: bool,
+
+    /// Label of the key to sign the bindle with (defaults to the first key
+    /// generated or imported with `spin signing-key`; the bindle is not
+    /// signed if there is none)
+    #[clap(long = "signing-key", env = SIGNING_KEY_ENV)]
+    pub signing_key: Option<String>,
+
+    /// Write the bindle to the staging directory and print what would be
+    /// deployed, without pushing the bindle or calling Hippo
+    #[clap(long = "dry-run")]
+    pub dry_run: bool,
+
+    /// Name of the Hippo channel to deploy the application to (defaults to
+    /// spin-deploy)
+    #[clap(long = "channel", env = "SPIN_DEPLOY_CHANNEL")]
+    pub channel: Option<String>,
+
+    /// Environment in the [deploy.environments] of spin.toml to take the
+    /// servers and channel from, where not given on the command line
+    #[clap(long = "environment", env = "SPIN_DEPLOY_ENVIRONMENT")]
+    pub environment: Option<String>,
+
+    /// Maximum number of parcels to upload to the bindle server at once
+    #[clap(
+        long = "push-concurrency",
+        default_value_t = spin_publish::DEFAULT_PUSH_CONCURRENCY
+    )]
+    pub push_concurrency: usize,
+
+    /// Number of times to retry requests to the bindle server and Hippo that
+    /// fail with transient errors, such as timeouts and 5xx responses
+    #[clap(long = "retries", default_value = "3")]
+    pub retries: u32,
+
+    /// Seconds to wait before the first retry of a failed request, doubled
+    /// for each later retry
+    #[clap(long = "retry-delay", value_name = "SECONDS", default_value = "1")]
+    pub retry_delay: u64,
+
+    /// URL of a webhook to also post the record of the deploy to, as kept in
+    /// the deploy history
+    #[clap(long = "audit-webhook", env = "SPIN_DEPLOY_AUDIT_WEBHOOK")]
+    pub audit_webhook: Option<String>,
+
+    /// The file the deploy is recorded in (defaults to the deploy history in
+    /// the Spin config directory)
+    #[clap(long = "history-file", env = "SPIN_DEPLOY_HISTORY")]
+    pub history_file: Option<PathBuf>,
+
+    /// Set an environment variable (KEY=VALUE) on the Hippo channel. Variables
+    /// are kept when redeploying, so only changed ones need passing
+    #[clap(long = "variable", value_name = "KEY=VALUE", parse(try_from_str = parse_variable))]
+    pub variables: Vec<(String, String)>,
+
+    /// File of environment variables to set on the Hippo channel, one
+    /// KEY=VALUE per line (overridden by --variable)
+    #[clap(long = "variables-file")]
+    pub variables_file: Option<PathBuf>,
+
+    /// Wait up to SECONDS for the deployed application to respond before
+    /// returning, failing the deploy if it does not
+    #[clap(long = "readiness-timeout", value_name = "SECONDS")]
+    pub readiness_timeout: Option<u64>,
+
+    /// Path of the deployed application polled by --readiness-timeout
+    /// (defaults to /)
+    #[clap(long = "readiness-path", default_value = "/")]
+    pub readiness_path: String,
+
+    /// Deploy only the files of components, failing unless nothing else
+    /// changed since the revision the channel serves. The new revision reuses
+    /// the modules of that revision, and the channel is moved to it
+    #[clap(long = "assets-only")]
+    pub assets_only: bool,
+
+    /// Complete the deploy of this application that was interrupted after
+    /// pushing it, without pushing it again
+    #[clap(long = "resume", conflicts_with = "abort")]
+    pub resume: bool,
+
+    /// Roll back the deploy of this application that was interrupted after
+    /// pushing it, restoring its channel as it was before the deploy
+    #[clap(long = "abort")]
+    pub abort: bool,
+
+    /// Verify the contracts recorded with `spin contract record` against the
+    /// deployed application, failing the deploy if any is broken
+    #[clap(long = "verify-contracts")]
+    pub verify_contracts: bool,
+
+    /// How to report the deploy: `plain` prints messages, `json` prints a
+    /// JSON document with the app, version, bindle, channel domain and routes
+    #[clap(long = "output", arg_enum, default_value = "plain")]
+    pub output: OutputFormat,
+
+    #[clap(flatten)]
+    pub network: NetworkOpts,
+
+    #[clap(subcommand)]
+    pub command: Option<DeployCommands>,
+
+    /// The login cached by `spin login` for the Hippo server, if any.
+    #[clap(skip)]
+    login: Option<Login>,
+
+    /// The interrupted deploy being resumed or rolled back, if any.
+    #[clap(skip)]
+    interrupted: Option<DeployState>,
+
+    /// The URL of the relay to the bindle server, if its certificate is
+    /// pinned.
+    #[clap(skip)]
+    bindle_relay: Option<String>,
+
+    /// The URL of the relay to the Hippo server, if its certificate is
+    /// pinned.
+    #[clap(skip)]
+    hippo_relay: Option<String>,
 }
 
 impl DeployCommand {
-    pub async fn run(self) -> Result<()> {
+    pub async fn run(mut self) -> Result<()> {
+        self.expand_env_vars()?;
+        if self.dry_run {
+            if self.command.is_some() {
+                bail!("--dry-run cannot be used with a deploy subcommand");
+            }
+            if self.output == OutputFormat::Json {
+                bail!("--dry-run cannot be used with --output json");
+            }
+            if self.assets_only {
+                bail!("--dry-run cannot be used with --assets-only: run `spin apps diff` to list what changed");
+            }
+            if self.resume || self.abort {
+                bail!("--dry-run cannot be used with --resume or --abort");
+            }
+            self.apply_login().await?;
+            return self.dry_run_deploy().await;
+        }
+        if self.resume || self.abort {
+            if self.command.is_some() {
+                bail!("--resume and --abort cannot be used with a deploy subcommand");
+            }
+            if self.assets_only {
+                bail!("--assets-only cannot be used with --resume or --abort");
+            }
+            self.apply_interrupted_deploy().await?;
+        }
+        if self.assets_only {
+            if self.registry.is_some() {
+                bail!("--assets-only cannot be used with --registry: only applications deployed through a bindle server can be compared");
+            }
+            if self.strategy == DeployStrategy::Fresh {
+                bail!("--assets-only cannot be used with --strategy fresh");
+            }
+        }
+        spin_loader::offline::ensure_online("deploy to Hippo")?;
+        self.network.apply()?;
+        self.apply_login().await?;
+        if self.trust_on_first_use {
+            // Pins are kept per deploy profile, as profiles target different
+            // environments.
+            let profile = self
+                .profile
+                .as_deref()
+                .and_then(|p| p.file_stem())
+                .and_then(|s| s.to_str())
+                .unwrap_or(crate::trust::DEFAULT_PROFILE)
+                .to_owned();
+            // Each server is reached through a relay accepting only its own
+            // pinned certificate.
+            if self.registry.is_none() {
+                self.bindle_relay = crate::trust::relay_pinned(
+                    self.bindle_url(),
+                    &profile,
+                    self.network.client_builder(false)?,
+                )
+                .await?;
+            }
+            self.hippo_relay = crate::trust::relay_pinned(
+                self.hippo_url(),
+                &profile,
+                self.network.client_builder(false)?,
+            )
+            .await?;
+        }
+        if self.abort {
+            return self.abort_interrupted_deploy().await;
+        }
+        match self.command.take() {
+            Some(DeployCommands::Preview(cmd)) => cmd.run(self).await,
+            None => self.deploy_and_notify().await.map(|_| ()),
+        }
+    }
+
+    /// Deploys the application and notifies the endpoints of the deploy
+    /// profile, returning the URLs of its routes.
+    pub(crate) async fn deploy_and_notify(&self) -> Result<Vec<String>> {
         let cfg_any = spin_loader::local::raw_manifest_from_file(&self.app).await?;
         let RawAppManifestAnyVersion::V1(cfg) = cfg_any;
 
-        let buildinfo = if !self.no_buildinfo {
-            match &self.buildinfo {
-                Some(i) => Some(i.clone()),
-                None => self.compute_buildinfo(&cfg).await.map(Option::Some)?,
+        let mut profile = match &self.profile {
+            Some(path) => DeployProfile::from_file(path).await?,
+            None => DeployProfile::default(),
+        };
+        if profile.environment.is_none() {
+            profile.environment = self.environment.clone();
+        }
+
+        let result = self.deploy(&cfg).await;
+        self.record(&cfg, &result).await;
+        match result {
+            Ok(deployment) => {
+                self.print_deployment(&deployment)?;
+                let routes = deployment.urls();
+                profile
+                    .notify(&DeployOutcome {
+                        app: &deployment.app,
+                        version: &deployment.version,
+                        routes: routes.clone(),
+                        error: None,
+                    })
+                    .await;
+                Ok(routes)
             }
-        } else {
-            None
+            Err(e) => {
+                profile
+                    .notify(&DeployOutcome {
+                        app: &self.app_name(&cfg),
+                        version: &cfg.info.version,
+                        routes: vec![],
+                        error: Some(&e),
+                    })
+                    .await;
+                Err(e)
+            }
+        }
+    }
+
+    /// Records the outcome of a deploy in the deploy history, and posts it to
+    /// the audit webhook if any. Failing to record a deploy is reported, but
+    /// does not fail it.
+    async fn record(&self, cfg: &RawAppManifest, result: &Result<Deployment>) {
+        let app = self.app_name(cfg);
+        let (version, bindle_id) = match result {
+            Ok(deployment) => (deployment.version.clone(), deployment.bindle_id.clone()),
+            Err(_) => (cfg.info.version.clone(), None),
+        };
+        let record = DeployRecord {
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            app,
+            version,
+            bindle_id,
+            hippo_server: self.hippo_url().to_owned(),
+            channel: self.channel().to_owned(),
+            environment: self.environment.clone(),
+            user: self
+                .hippo_username
+                .clone()
+                .or_else(|| self.login.as_ref().map(|l| l.hippo_username.clone()))
+                .or_else(deploy_history::local_user),
+            success: result.is_ok(),
+            error: result.as_ref().err().map(|e| format!("{:#}", e)),
         };
 
-        self.check_hippo_healthz().await?;
+        let recorded = match &self.history_file {
+            Some(path) => deploy_history::append(path, &record).await,
+            None => match deploy_history::history_path() {
+                Ok(path) => deploy_history::append(&path, &record).await,
+                Err(e) => Err(e),
+            },
+        };
+        if let Err(e) = recorded {
+            eprintln!(
+                "Warning: cannot record the deploy in the deploy history: {:#}",
+                e
+            );
+        }
+        if let Some(url) = &self.audit_webhook {
+            if let Err(e) = deploy_history::post(url, &record).await {
+                eprintln!("Warning: cannot post the deploy record to {}: {:#}", url, e);
+            }
+        }
+    }
 
-        let bindle_id = self.create_and_push_bindle(buildinfo).await?;
+    /// Prints a completed deploy in the output format.
+    fn print_deployment(&self, deployment: &Deployment) -> Result<()> {
+        match self.output {
+            OutputFormat::Json => println!("{}", serde_json::to_string_pretty(deployment)?),
+            OutputFormat::Plain => {
+                println!("Deployed {} version {}", deployment.app, deployment.version);
+                if deployment.routes.is_empty() {
+                    println!("Application is running at {}", deployment.domain);
+                } else {
+                    println!("Available Routes:");
+                    for route in &deployment.routes {
+                        println!("  {}: {}", route.component, route.url);
+                        if let Some(description) = &route.description {
+                            println!("    {}", description);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
 
-        let _sloth_warning = warn_if_slow_response(&self.hippo_server_url);
+    /// Prints a progress message: to stdout, or to stderr if stdout is kept
+    /// for a JSON document.
+    fn print_status(&self, message: &str) {
+        match self.output {
+            OutputFormat::Plain => println!("{}", message),
+            OutputFormat::Json => eprintln!("{}", message),
+        }
+    }
 
-        let token = match Client::login(
-            &Client::new(ConnectionInfo {
-                url: self.hippo_server_url.clone(),
-                danger_accept_invalid_certs: self.insecure,
-                api_key: None,
-            }),
-            self.hippo_username.clone(),
-            self.hippo_password.clone(),
-        )
-        .await
-        {
-            Ok(token_info) => token_info.token.unwrap_or_default(),
-            Err(err) => bail!(format_login_error(&err)?),
+    /// Deploys the application, returning what was deployed.
+    async fn deploy(&self, cfg: &RawAppManifest) -> Result<Deployment> {
+        let buildinfo = self.buildinfo(cfg)?;
+        let manifest_scale = cfg
+            .deploy
+            .as_ref()
+            .and_then(|d| d.scale.as_ref())
+            .map(ChannelScale::from)
+            .unwrap_or_default();
+        manifest_scale.validate().context("Invalid deploy.scale")?;
+        let mut variables = match &self.variables_file {
+            Some(path) => spin_loader::env_file::load(path).await?,
+            None => vec![],
         };
+        spin_loader::env_file::merge(&mut variables, self.variables.iter().cloned());
 
+        self.check_hippo_healthz().await?;
+        if !self.skip_capability_check {
+            let app_dir = crate::app_dir(&self.app)?;
+            crate::capabilities::check(
+                &self.network.client_builder(self.insecure)?.build()?,
+                self.hippo_endpoint(),
+                cfg,
+                &app_dir,
+            )
+            .await?;
+        }
+
+        let state_path = DeployState::path(&self.app)?;
+        let mut state = match &self.interrupted {
+            Some(state) => {
+                self.print_status(&format!(
+                    "Resuming the deploy of {} version {}, started at {}",
+                    state.app, state.revision, state.started_at
+                ));
+                state.clone()
+            }
+            None => self.push(cfg, buildinfo, &state_path).await?,
+        };
+        let resuming = self.interrupted.is_some();
+        let name = state.app.clone();
+        let storage_id = state.storage_id.clone();
+        let revision = state.revision.clone();
+
+        // The scale is managed outside the Hippo client, so it needs the token.
+        let token = self.hippo_auth().token().await?;
         let hippo_client = Client::new(ConnectionInfo {
-            url: self.hippo_server_url.clone(),
+            url: self.hippo_endpoint().to_owned(),
             danger_accept_invalid_certs: self.insecure,
-            api_key: Some(token),
+            api_key: Some(token.clone()),
         });
+        let scale_api = ScaleApi {
+            client: self.network.client_builder(self.insecure)?.build()?,
+            hippo_url: self.hippo_endpoint(),
+            token: &token,
+        };
+        let retry = self.retry_policy();
 
-        let name = bindle_id.name().to_string();
         // Values for channel creation are determined by whether the app already exists
         let mut active_revision_id = None;
         let mut range_rule = None;
         let mut revision_selection_strategy = ChannelRevisionSelectionStrategy::UseRangeRule;
+        // Variables and scale of the existing channel are lost when it is
+        // recreated.
+        let mut channel_variables = vec![];
+        let mut channel_scale = None;
+
+        let existing_app_id = match (
+            self.strategy,
+            retry
+                .run("Looking up the Hippo app", || get_app_id(&hippo_client, &name))
+                .await?,
+        ) {
+            (DeployStrategy::Upgrade, None) => bail!(
+                "Cannot upgrade app {}: it does not exist in Hippo. Use `--strategy auto` or `--strategy fresh` to create it",
+                name
+            ),
+            // The app may have been recreated by the deploy being resumed.
+            (DeployStrategy::Fresh, Some(app_id)) if !resuming => {
+                retry
+                    .run_checked(
+                        "Removing the Hippo app",
+                        || Client::remove_app(&hippo_client, app_id.to_string()),
+                        || async { Ok(get_app_id(&hippo_client, &name).await?.is_none().then(|| ())) },
+                    )
+                    .await
+                    .context("Unable to remove existing Hippo app")?;
+                None
+            }
+            (_, existing_app_id) => existing_app_id,
+        };
 
         // Create or update app
-        let app_id = match self.get_app_id(&hippo_client, name.clone()).await {
-            Ok(app_id) => {
-                Client::add_revision(
-                    &hippo_client,
-                    name.clone(),
-                    bindle_id.version_string().clone(),
-                )
-                .await?;
+        let app_id = match existing_app_id {
+            Some(app_id) => {
+                // The deploy being resumed may have registered the revision.
+                let registered =
+                    resuming && self.get_revision_id(&hippo_client, &revision).await.is_ok();
+                if !registered {
+                    retry
+                        .run_checked(
+                            "Adding the Hippo revision",
+                            || {
+                                Client::add_revision(
+                                    &hippo_client,
+                                    storage_id.clone(),
+                                    revision.clone(),
+                                )
+                                .map_ok(|_| ())
+                            },
+                            || async {
+                                let registered =
+                                    self.get_revision_id(&hippo_client, &revision).await;
+                                Ok(registered.ok().map(|_| ()))
+                            },
+                        )
+                        .await?;
+                }
+
+                // What the deploy being resumed saved of the channel it
+                // removed, before it may have recreated it.
+                if let DeployStage::ChannelRemoved {
+                    variables, scale, ..
+                } = &state.stage
+                {
+                    channel_variables = variables.clone();
+                    channel_scale = scale.clone();
+                }
 
                 // Remove existing channel to prevent conflict
                 // TODO: in the future, expand hippo API to update channel rather than delete and recreate
-                let existing_channel_id = self
-                    .get_channel_id(&hippo_client, SPIN_DEPLOY_CHANNEL_NAME.to_string())
-                    .await?;
-                Client::remove_channel(&hippo_client, existing_channel_id.to_string()).await?;
+                if let Some(existing_channel_id) = retry
+                    .run("Looking up the Hippo channel", || {
+                        get_channel_id(&hippo_client, app_id, self.channel())
+                    })
+                    .await?
+                {
+                    let existing_variables = retry
+                        .run("Looking up the Hippo channel variables", || {
+                            get_channel_variables(&hippo_client, existing_channel_id)
+                        })
+                        .await?;
+                    spin_loader::env_file::merge(&mut channel_variables, existing_variables);
+                    let existing_scale = retry
+                        .run("Looking up the Hippo channel scale", || {
+                            scale_api.get(existing_channel_id)
+                        })
+                        .await?;
+                    channel_scale = channel_scale.or(existing_scale);
+                    if state.stage == DeployStage::Pushed {
+                        let previous_revision = retry
+                            .run("Looking up the Hippo channel revision", || {
+                                active_revision(&hippo_client, app_id, self.channel())
+                            })
+                            .await?;
+                        // Saved before removing the channel, so that an
+                        // interrupted deploy can restore it.
+                        state.stage = DeployStage::ChannelRemoved {
+                            previous_revision,
+                            variables: channel_variables.clone(),
+                            scale: channel_scale.clone(),
+                        };
+                        state.save(&state_path).await?;
+                    }
+                    retry
+                        .run_checked(
+                            "Removing the Hippo channel",
+                            || {
+                                Client::remove_channel(
+                                    &hippo_client,
+                                    existing_channel_id.to_string(),
+                                )
+                            },
+                            || async {
+                                let channel_id =
+                                    get_channel_id(&hippo_client, app_id, self.channel()).await?;
+                                Ok(channel_id.is_none().then(|| ()))
+                            },
+                        )
+                        .await?;
+                }
                 active_revision_id = Some(
-                    self.get_revision_id(&hippo_client, bindle_id.version_string().clone())
+                    retry
+                        .run("Looking up the Hippo revision", || {
+                            self.get_revision_id(&hippo_client, &revision)
+                        })
                         .await?,
                 );
                 revision_selection_strategy =
                     ChannelRevisionSelectionStrategy::UseSpecifiedRevision;
                 app_id
             }
-            Err(_) => {
-                range_rule = Some(bindle_id.version_string());
-                Client::add_app(&hippo_client, name.clone(), name.clone())
+            None => {
+                let app_id = retry
+                    .run_checked(
+                        "Creating the Hippo app",
+                        || Client::add_app(&hippo_client, name.clone(), storage_id.clone()),
+                        || get_app_id(&hippo_client, &name),
+                    )
                     .await
-                    .context("Unable to create Hippo app")?
+                    .context("Unable to create Hippo app")?;
+                if state.bindle_id.is_none() {
+                    // Tags are not semantic versions, so the channel uses the
+                    // registered revision rather than a range rule.
+                    retry
+                        .run_checked(
+                            "Adding the Hippo revision",
+                            || {
+                                Client::add_revision(
+                                    &hippo_client,
+                                    storage_id.clone(),
+                                    revision.clone(),
+                                )
+                                .map_ok(|_| ())
+                            },
+                            || async {
+                                let registered =
+                                    self.get_revision_id(&hippo_client, &revision).await;
+                                Ok(registered.ok().map(|_| ()))
+                            },
+                        )
+                        .await?;
+                    active_revision_id = Some(
+                        retry
+                            .run("Looking up the Hippo revision", || {
+                                self.get_revision_id(&hippo_client, &revision)
+                            })
+                            .await?,
+                    );
+                    revision_selection_strategy =
+                        ChannelRevisionSelectionStrategy::UseSpecifiedRevision;
+                } else {
+                    range_rule = Some(revision.clone());
+                }
+                app_id
+            }
+        };
+
+        let channel_id = retry
+            .run_checked(
+                "Creating the Hippo channel",
+                || {
+                    Client::add_channel(
+                        &hippo_client,
+                        app_id,
+                        self.channel().to_owned(),
+                        None,
+                        revision_selection_strategy,
+                        range_rule.clone(),
+                        active_revision_id,
+                        None,
+                    )
+                },
+                || get_channel_id(&hippo_client, app_id, self.channel()),
+            )
+            .await
+            .context("Problem creating a channel in Hippo")?;
+        spin_loader::env_file::merge(&mut channel_variables, variables);
+        for (key, value) in channel_variables {
+            retry
+                .run_checked(
+                    "Setting a Hippo channel variable",
+                    || {
+                        Client::add_environment_variable(
+                            &hippo_client,
+                            key.clone(),
+                            value.clone(),
+                            channel_id,
+                        )
+                        .map_ok(|_| ())
+                    },
+                    || async {
+                        let variables = get_channel_variables(&hippo_client, channel_id).await?;
+                        Ok(variables
+                            .iter()
+                            .any(|(k, v)| k == &key && v == &value)
+                            .then(|| ()))
+                    },
+                )
+                .await
+                .with_context(|| format!("Unable to set variable {} on the Hippo channel", key))?;
+        }
+        // The scale declared in spin.toml wins over the one last set with
+        // `spin scale`.
+        let scale = manifest_scale.clone().or(channel_scale.unwrap_or_default());
+        if !scale.is_empty() {
+            retry
+                .run("Scaling the Hippo channel", || {
+                    scale_api.set(channel_id, &scale)
+                })
+                .await?;
+        }
+        DeployState::remove(&state_path).await?;
+
+        let channel = retry
+            .run("Looking up the Hippo channel", || {
+                Client::get_channel_by_id(&hippo_client, &channel_id.to_string())
+            })
+            .await
+            .context("Problem getting channel by id")?;
+        let routes =
+            if let Ok(http_config) = HttpTriggerConfiguration::try_from(cfg.info.trigger.clone()) {
+                available_routes(&channel.domain, &http_config.base, self.hippo_url(), cfg)
+            } else {
+                vec![]
+            };
+
+        let deployment = Deployment {
+            app: name,
+            version: revision,
+            bindle_id: state.bindle_id,
+            channel: self.channel().to_owned(),
+            domain: channel.domain,
+            routes,
+        };
+        if let Some(timeout) = self.readiness_timeout {
+            self.wait_until_ready(&deployment, Duration::from_secs(timeout))
+                .await?;
+        }
+        if self.verify_contracts {
+            let dir = contracts_dir(&self.app, &None)?;
+            let names = contract::contract_names(&dir).await?;
+            if names.is_empty() {
+                bail!("No contracts in {} to verify", dir.display());
+            }
+            let base_url = self.base_url(&deployment);
+            self.print_status(&format!("Verifying contracts against {}...", base_url));
+            let client = self.network.client_builder(self.insecure)?.build()?;
+            contract::verify(&client, &dir, &names, &base_url, &|line| {
+                self.print_status(line)
+            })
+            .await
+            .with_context(|| {
+                format!(
+                    "{} version {} was deployed, but breaks its contracts",
+                    deployment.app, deployment.version
+                )
+            })?;
+        }
+        Ok(deployment)
+    }
+
+    /// Pushes the application, recording the deploy as pushed so that it can
+    /// be resumed if it is interrupted before Hippo is updated.
+    async fn push(
+        &self,
+        cfg: &RawAppManifest,
+        buildinfo: Option<BuildMetadata>,
+        state_path: &Path,
+    ) -> Result<DeployState> {
+        if let Some(state) = DeployState::load(state_path).await? {
+            bail!(
+                "The deploy of {} version {} to {}, started at {}, did not complete: run `spin deploy --resume` to complete it, or `spin deploy --abort` to roll it back",
+                state.app,
+                state.revision,
+                state.hippo_server,
+                state.started_at
+            );
+        }
+
+        let version = self
+            .version_strategy
+            .resolve(
+                &self.app_name(cfg),
+                &cfg.info.version,
+                &self.bindle_connection_info(),
+            )
+            .await?;
+        let name = self.app_name(cfg);
+        if self.assets_only {
+            self.check_assets_only(&name).await?;
+        }
+        // Hippo finds the revisions of an app in its storage: the bindles
+        // named after it, or the tags of its OCI repository.
+        let (storage_id, revision, bindle_id) = match &self.registry {
+            Some(registry) => {
+                let tag = self
+                    .create_and_push_artifact(registry, name.clone(), version, buildinfo)
+                    .await?;
+                (registry.name(), tag, None)
+            }
+            None => {
+                let bindle_id = self
+                    .create_and_push_bindle(name.clone(), version, buildinfo)
+                    .await?;
+                (
+                    name.clone(),
+                    bindle_id.version_string(),
+                    Some(bindle_id.to_string()),
+                )
             }
         };
 
+        let state = DeployState {
+            started_at: chrono::Utc::now().to_rfc3339(),
+            hippo_server: self.hippo_url().to_owned(),
+            app: name,
+            channel: self.channel().to_owned(),
+            storage_id,
+            revision,
+            bindle_id,
+            stage: DeployStage::Pushed,
+        };
+        state.save(state_path).await?;
+        Ok(state)
+    }
+
+    /// Loads the interrupted deploy of the application for --resume or
+    /// --abort, which then apply to its server, application and channel.
+    async fn apply_interrupted_deploy(&mut self) -> Result<()> {
+        let path = DeployState::path(&self.app)?;
+        let state = DeployState::load(&path).await?.with_context(|| {
+            format!(
+                "No interrupted deploy of {} to resume or abort",
+                self.app.display()
+            )
+        })?;
+        self.hippo_server_url = Some(state.hippo_server.clone());
+        self.channel = Some(state.channel.clone());
+        self.app_name = Some(state.app.clone());
+        self.name_prefix = None;
+        self.interrupted = Some(state);
+        Ok(())
+    }
+
+    /// Rolls back the interrupted deploy: the channel it removed is restored
+    /// with the revision it served, its variables and its scale. A deploy
+    /// interrupted before removing the channel left it unchanged.
+    async fn abort_interrupted_deploy(&self) -> Result<()> {
+        let state = self
+            .interrupted
+            .as_ref()
+            .context("No interrupted deploy to abort")?;
+        let state_path = DeployState::path(&self.app)?;
+        let (previous_revision, variables, scale) = match &state.stage {
+            DeployStage::Pushed => {
+                println!(
+                    "The deploy of {} version {} had not changed channel {}",
+                    state.app, state.revision, state.channel
+                );
+                return DeployState::remove(&state_path).await;
+            }
+            DeployStage::ChannelRemoved {
+                previous_revision,
+                variables,
+                scale,
+            } => (previous_revision, variables, scale),
+        };
+
+        let token = self.hippo_auth().token().await?;
+        let hippo_client = Client::new(ConnectionInfo {
+            url: self.hippo_endpoint().to_owned(),
+            danger_accept_invalid_certs: self.insecure,
+            api_key: Some(token.clone()),
+        });
+        let scale_api = ScaleApi {
+            client: self.network.client_builder(self.insecure)?.build()?,
+            hippo_url: self.hippo_endpoint(),
+            token: &token,
+        };
+        let app_id = get_app_id(&hippo_client, &state.app)
+            .await?
+            .with_context(|| {
+                format!(
+                    "Application {} is not deployed to {}",
+                    state.app,
+                    self.hippo_url()
+                )
+            })?;
+        // The deploy may have recreated the channel for the new revision.
+        if let Some(channel_id) = get_channel_id(&hippo_client, app_id, &state.channel).await? {
+            Client::remove_channel(&hippo_client, channel_id.to_string())
+                .await
+                .with_context(|| format!("Unable to remove channel {}", state.channel))?;
+        }
+        let previous_revision = match previous_revision {
+            Some(revision) => revision,
+            None => {
+                println!(
+                    "Channel {} of {} served no revision before the deploy, so was not restored",
+                    state.channel, state.app
+                );
+                return DeployState::remove(&state_path).await;
+            }
+        };
+
+        let revision_id = self
+            .get_revision_id(&hippo_client, previous_revision)
+            .await?;
         let channel_id = Client::add_channel(
             &hippo_client,
             app_id,
-            String::from(SPIN_DEPLOY_CHANNEL_NAME),
+            state.channel.clone(),
+            None,
+            ChannelRevisionSelectionStrategy::UseSpecifiedRevision,
             None,
-            revision_selection_strategy,
-            range_rule,
-            active_revision_id,
+            Some(revision_id),
             None,
         )
         .await
-        .context("Problem creating a channel in Hippo")?;
-
+        .with_context(|| format!("Unable to create channel {}", state.channel))?;
+        for (key, value) in variables {
+            Client::add_environment_variable(&hippo_client, key.clone(), value.clone(), channel_id)
+                .await
+                .with_context(|| {
+                    format!(
+                        "Unable to set variable {} on channel {}",
+                        key, state.channel
+                    )
+                })?;
+        }
+        if let Some(scale) = scale.as_ref().filter(|s| !s.is_empty()) {
+            scale_api.set(channel_id, scale).await?;
+        }
+        DeployState::remove(&state_path).await?;
         println!(
-            "Deployed {} version {}",
-            name.clone(),
-            bindle_id.version_string()
+            "Restored channel {} of {} to revision {}",
+            state.channel, state.app, previous_revision
         );
-        let channel = Client::get_channel_by_id(&hippo_client, &channel_id.to_string())
+        Ok(())
+    }
+
+    /// Checks that only the files of components changed since the revision
+    /// the channel serves, for `--assets-only`. The modules of the new
+    /// revision are then already on the bindle server, so are not uploaded
+    /// again, and the channel moves to the new revision as on any upgrade.
+    async fn check_assets_only(&self, name: &str) -> Result<()> {
+        let hippo_client = self.hippo_client().await?;
+        let app_id = get_app_id(&hippo_client, name).await?.with_context(|| {
+            format!(
+                "Cannot deploy only the assets of {}: it is not deployed to {}",
+                name,
+                self.hippo_url()
+            )
+        })?;
+        let revision = active_revision(&hippo_client, app_id, self.channel())
+            .await?
+            .with_context(|| {
+                format!(
+                    "Cannot deploy only the assets of {}: channel {} has no active revision",
+                    name,
+                    self.channel()
+                )
+            })?;
+        let bindle_id: Id = format!("{}/{}", name, revision)
+            .parse()
+            .with_context(|| format!("Invalid bindle ID for revision {}", revision))?;
+        let deployed = deployed_bindle(&self.bindle_connection_info(), &bindle_id)
             .await
-            .context("Problem getting channel by id")?;
-        if let Ok(http_config) = HttpTriggerConfiguration::try_from(cfg.info.trigger.clone()) {
-            print_available_routes(
-                &channel.domain,
-                &http_config.base,
-                &self.hippo_server_url,
-                &cfg,
+            .with_context(|| {
+                format!(
+                    "Failed to get bindle {} from {}",
+                    bindle_id,
+                    self.bindle_url()
+                )
+            })?;
+        let local = local_bindle(&self.app, name.to_owned()).await?;
+
+        let changed = drift(&deployed, &local, false);
+        if !changed.is_empty() {
+            bail!(
+                "Cannot deploy only assets: more than files changed since revision {}:\n  {}\nDeploy without --assets-only",
+                revision,
+                changed.join("\n  ")
             );
-        } else {
-            println!("Application is running at {}", channel.domain);
         }
-
+        let files = drift(&deployed, &local, true);
+        if files.is_empty() {
+            bail!(
+                "No files changed since revision {} on channel {}: nothing to deploy",
+                revision,
+                self.channel()
+            );
+        }
+        self.print_status(&format!(
+            "Deploying {} changed file(s), reusing the modules of revision {}",
+            files.len(),
+            revision
+        ));
         Ok(())
     }
 
-    async fn compute_buildinfo(&self, cfg: &RawAppManifest) -> Result<BuildMetadata> {
-        let mut sha256 = Sha256::new();
-        let app_folder = self.app.parent().with_context(|| {
-            anyhow!(
-                "Cannot get a parent directory of manifest file {}",
-                &self.app.display()
-            )
-        })?;
+    /// Polls the readiness path of a deployed application until it responds
+    /// with a status other than 404 or a server error, reporting each change
+    /// of status, and fails if it does not within the timeout.
+    async fn wait_until_ready(&self, deployment: &Deployment, timeout: Duration) -> Result<()> {
+        let url = format!(
+            "{}/{}",
+            self.base_url(deployment),
+            self.readiness_path.trim_start_matches('/')
+        );
+        let client = self
+            .network
+            .client_builder(self.insecure)?
+            .timeout(READINESS_POLL_INTERVAL)
+            .build()?;
 
-        for x in cfg.components.iter() {
-            match &x.source {
-                config::RawModuleSource::FileReference(p) => {
-                    let full_path = app_folder.join(p);
-                    let mut r = File::open(&full_path)
-                        .with_context(|| anyhow!("Cannot open file {}", &full_path.display()))?;
-                    copy(&mut r, &mut sha256)?;
+        self.print_status(&format!("Waiting for {} to be ready...", url));
+        let start = Instant::now();
+        let mut last_status = None;
+        loop {
+            let (ready, status) = match client.get(&url).send().await {
+                Ok(response) => {
+                    let status = response.status();
+                    let ready =
+                        !(status.is_server_error() || status == reqwest::StatusCode::NOT_FOUND);
+                    (ready, status.to_string())
                 }
-                config::RawModuleSource::Bindle(_b) => {}
+                Err(e) if e.is_timeout() => (false, "not responding".to_owned()),
+                Err(e) if e.is_connect() => (false, "refusing connections".to_owned()),
+                Err(e) => (false, format!("failing: {}", e)),
+            };
+            let elapsed = start.elapsed().as_secs();
+            if ready {
+                self.print_status(&format!("Ready after {}s ({})", elapsed, status));
+                return Ok(());
+            }
+            if last_status.as_ref() != Some(&status) {
+                self.print_status(&format!("  {}s: {}", elapsed, status));
+                last_status = Some(status);
+            }
+            if start.elapsed() + READINESS_POLL_INTERVAL > timeout {
+                bail!(
+                    "{} version {} was deployed, but {} was not ready after {}s: {}",
+                    deployment.app,
+                    deployment.version,
+                    url,
+                    timeout.as_secs(),
+                    last_status.unwrap_or_default()
+                );
             }
-            if let Some(files) = &x.wasm.files {
-                let source_dir = crate::app_dir(&self.app)?;
-                let exclude_files = x.wasm.exclude_files.clone().unwrap_or_default();
-                let fm = assets::collect(files, &exclude_files, &source_dir)?;
-                for f in fm.iter() {
-                    let mut r = File::open(&f.src)
-                        .with_context(|| anyhow!("Cannot open file {}", &f.src.display()))?;
-                    copy(&mut r, &mut sha256)?;
+            tokio::time::sleep(READINESS_POLL_INTERVAL).await;
+        }
+    }
+
+    /// The base URL of the deployed application, using the scheme of the
+    /// Hippo server.
+    fn base_url(&self, deployment: &Deployment) -> String {
+        let scheme = Url::parse(self.hippo_url())
+            .map(|url| url.scheme().to_owned())
+            .unwrap_or_else(|_| "http".to_owned());
+        format!("{}://{}", scheme, deployment.domain)
+    }
+
+    /// Writes the bindle of the application to the staging directory, and
+    /// prints what deploying it would upload, without contacting Hippo or
+    /// pushing to the bindle server.
+    async fn dry_run_deploy(&self) -> Result<()> {
+        let cfg_any = spin_loader::local::raw_manifest_from_file(&self.app).await?;
+        let RawAppManifestAnyVersion::V1(cfg) = cfg_any;
+
+        let buildinfo = self.buildinfo(&cfg)?;
+        if self.version_strategy == VersionStrategy::SemverBump {
+            // Finding the latest version reads from the bindle server, but
+            // does not change it.
+            spin_loader::offline::ensure_online("find the latest bindle version")?;
+        }
+        let version = self
+            .version_strategy
+            .resolve(
+                &self.app_name(&cfg),
+                &cfg.info.version,
+                &self.bindle_connection_info(),
+            )
+            .await?;
+
+        // Unlike a deploy, keep the staged bindle for inspection.
+        let dest_dir = match &self.staging_dir {
+            Some(path) => path.clone(),
+            None => tempfile::tempdir()?.into_path(),
+        };
+        let invoice = self
+            .stage_bindle(self.app_name(&cfg), version, buildinfo.clone(), &dest_dir)
+            .await?;
+
+        println!("Dry run: nothing was pushed or deployed");
+        println!("Bindle:    {}", invoice.bindle.id);
+        println!("Staged in: {}", dest_dir.display());
+        match &buildinfo {
+            Some(buildinfo) => println!("Buildinfo: {}", buildinfo),
+            None => println!("Buildinfo: none"),
+        }
+        if let Some(registry) = &self.registry {
+            let tag = spin_publish::tag_for_version(&invoice.bindle.id.version_string());
+            println!("Registry:  oci://{}", registry.reference(&tag));
+        }
+        println!(
+            "Channel:   {} of app {} on {}",
+            self.channel(),
+            invoice.bindle.id.name(),
+            self.hippo_server_url
+                .as_deref()
+                .unwrap_or("(no Hippo server set)")
+        );
+
+        let parcels = invoice.parcel.as_deref().unwrap_or_default();
+        println!("Parcels:");
+        for parcel in parcels {
+            let groups = parcel
+                .conditions
+                .as_ref()
+                .and_then(|c| c.member_of.as_ref())
+                .map(|groups| format!(" ({})", groups.join(", ")))
+                .unwrap_or_default();
+            println!(
+                "  {:>10}  {}  {}{}",
+                format_size(parcel.label.size),
+                &parcel.label.sha256[..12.min(parcel.label.sha256.len())],
+                parcel.label.name,
+                groups
+            );
+        }
+        let total = parcels.iter().map(|p| p.label.size).sum();
+        println!("{} parcels, {} in total", parcels.len(), format_size(total));
+        Ok(())
+    }
+
+    /// The build metadata to append to the bindle version, if any.
+    fn buildinfo(&self, cfg: &RawAppManifest) -> Result<Option<BuildMetadata>> {
+        if self.no_buildinfo {
+            return Ok(None);
+        }
+        match &self.buildinfo {
+            Some(i) => Ok(Some(i.clone())),
+            None => crate::compute_buildinfo(&self.app, cfg).map(Option::Some),
+        }
+    }
+
+    /// Fills in the servers and channel not given on the command line from
+    /// the deploy environment, if any, then the servers and credentials still
+    /// not given from the login cached by `spin login` for the Hippo server,
+    /// or for the server last logged in to if none is given.
+    async fn apply_login(&mut self) -> Result<()> {
+        self.apply_environment().await?;
+        let logins = match logins_path() {
+            Ok(path) => Logins::load(&path).await?,
+            Err(_) => Logins::default(),
+        };
+        if let Some((url, login)) = logins.get(self.hippo_server_url.as_deref()) {
+            self.hippo_server_url = Some(url.to_owned());
+            if self.bindle_server_url.is_none() {
+                self.bindle_server_url = login.bindle_server_url.clone();
+                if self.bindle_username.is_none() {
+                    self.bindle_username = login.bindle_username.clone();
+                    self.bindle_password = login.bindle_password.clone();
                 }
             }
+            self.insecure |= login.insecure;
+            self.login = Some(login.clone());
+        }
+        if self.registry.is_some() && self.version_strategy == VersionStrategy::SemverBump {
+            // The latest version is found on the bindle server.
+            bail!("--version-strategy semver-bump cannot be used with --registry");
+        }
+        // A dry run contacts neither server, except to find the latest
+        // version when bumping it.
+        if self.dry_run {
+            if self.version_strategy == VersionStrategy::SemverBump
+                && self.bindle_server_url.is_none()
+            {
+                bail!("No bindle server given: pass --bindle-server to find the version to bump");
+            }
+            return Ok(());
+        }
+        if self.hippo_server_url.is_none() {
+            bail!("No Hippo server given: pass --hippo-server, or run `spin login`");
+        }
+        if self.bindle_server_url.is_none() && self.registry.is_none() {
+            bail!("No bindle server given: pass --bindle-server or --registry, or run `spin login` with a bindle server");
+        }
+        Ok(())
+    }
+
+    /// Fills in the servers and channel not given on the command line from
+    /// the deploy environment in spin.toml, if one is given.
+    async fn apply_environment(&mut self) -> Result<()> {
+        let name = match &self.environment {
+            Some(name) => name,
+            None => return Ok(()),
+        };
+        let RawAppManifestAnyVersion::V1(cfg) =
+            spin_loader::local::raw_manifest_from_file(&self.app).await?;
+        let environments = cfg.deploy.unwrap_or_default().environments;
+        let environment = match environments.get(name) {
+            Some(environment) => environment,
+            None => {
+                let mut known: Vec<_> = environments.keys().map(String::as_str).collect();
+                known.sort_unstable();
+                bail!(
+                    "No environment {} in the [deploy.environments] of {} (found: {})",
+                    name,
+                    self.app.display(),
+                    if known.is_empty() {
+                        "none".to_owned()
+                    } else {
+                        known.join(", ")
+                    }
+                );
+            }
+        };
+        let mut environment = environment.clone();
+        expand_opt(&mut environment.hippo_server)?;
+        expand_opt(&mut environment.bindle_server)?;
+        expand_opt(&mut environment.channel)?;
+        if self.hippo_server_url.is_none() {
+            self.hippo_server_url = environment.hippo_server;
         }
+        if self.bindle_server_url.is_none() {
+            self.bindle_server_url = environment.bindle_server;
+        }
+        if self.channel.is_none() {
+            self.channel = environment.channel;
+        }
+        Ok(())
+    }
+
+    /// Expands the environment variables referenced in the options that
+    /// name servers, users, channels and applications. Passwords and keys
+    /// are used as given.
+    fn expand_env_vars(&mut self) -> Result<()> {
+        expand_opt(&mut self.bindle_server_url).context("Invalid --bindle-server")?;
+        expand_opt(&mut self.bindle_username).context("Invalid --bindle-username")?;
+        expand_opt(&mut self.hippo_server_url).context("Invalid --hippo-server")?;
+        expand_opt(&mut self.hippo_username).context("Invalid --hippo-username")?;
+        expand_opt(&mut self.channel).context("Invalid --channel")?;
+        expand_opt(&mut self.environment).context("Invalid --environment")?;
+        expand_opt(&mut self.app_name).context("Invalid --app-name")?;
+        expand_opt(&mut self.name_prefix).context("Invalid --name-prefix")?;
+        expand_opt(&mut self.audit_webhook).context("Invalid --audit-webhook")?;
+        Ok(())
+    }
+
+    /// The name of the Hippo channel the application is deployed to.
+    pub(crate) fn channel(&self) -> &str {
+        self.channel.as_deref().unwrap_or(SPIN_DEPLOY_CHANNEL_NAME)
+    }
+
+    /// The URL of the Hippo server, once resolved by `run`.
+    pub(crate) fn hippo_url(&self) -> &str {
+        self.hippo_server_url.as_deref().unwrap_or_default()
+    }
 
-        let mut r = File::open(&self.app)?;
-        copy(&mut r, &mut sha256)?;
+    /// The URL of the bindle server, once resolved by `run`.
+    fn bindle_url(&self) -> &str {
+        self.bindle_server_url.as_deref().unwrap_or_default()
+    }
 
-        let mut final_digest = format!("q{:x}", sha256.finalize());
-        final_digest.truncate(8);
+    /// The URL to send requests to the Hippo server at: its relay if its
+    /// certificate is pinned, or else its URL.
+    fn hippo_endpoint(&self) -> &str {
+        self.hippo_relay
+            .as_deref()
+            .unwrap_or_else(|| self.hippo_url())
+    }
 
-        let buildinfo =
-            BuildMetadata::new(&final_digest).with_context(|| "Could not compute build info")?;
+    /// The URL to send requests to the bindle server at: its relay if its
+    /// certificate is pinned, or else its URL.
+    fn bindle_endpoint(&self) -> &str {
+        self.bindle_relay
+            .as_deref()
+            .unwrap_or_else(|| self.bindle_url())
+    }
 
-        Ok(buildinfo)
+    /// Returns a client for Hippo, authenticated as given on the command line
+    /// or by the login cached by `spin login`.
+    pub(crate) async fn hippo_client(&self) -> Result<Client> {
+        self.hippo_auth().client().await
     }
 
-    async fn get_app_id(&self, hippo_client: &Client, name: String) -> Result<Uuid> {
-        let apps_vm = Client::list_apps(hippo_client).await?;
-        let app = apps_vm.items.iter().find(|&x| x.name == name.clone());
-        match app {
-            Some(a) => Ok(a.id),
-            None => anyhow::bail!("No app with name: {}", name),
+    /// How to authenticate to Hippo, as given on the command line or by the
+    /// login cached by `spin login`.
+    fn hippo_auth(&self) -> HippoAuth<'_> {
+        HippoAuth {
+            url: self.hippo_url(),
+            endpoint: self.hippo_endpoint(),
+            insecure: self.insecure,
+            api_key: self.hippo_api_key.as_deref(),
+            username: self.hippo_username.as_deref(),
+            password: self.hippo_password.as_deref(),
+            login: self.login.as_ref(),
         }
     }
 
-    async fn get_revision_id(&self, hippo_client: &Client, bindle_version: String) -> Result<Uuid> {
+    async fn get_revision_id(&self, hippo_client: &Client, revision_number: &str) -> Result<Uuid> {
         let revisions = Client::list_revisions(hippo_client).await?;
         let revision = revisions
             .items
             .iter()
-            .find(|&x| x.revision_number == bindle_version);
+            .find(|&x| x.revision_number == revision_number);
         Ok(revision
-            .ok_or_else(|| anyhow::anyhow!("No revision with version {}", bindle_version))?
+            .ok_or_else(|| anyhow::anyhow!("No revision with version {}", revision_number))?
             .id)
     }
 
-    async fn get_channel_id(&self, hippo_client: &Client, name: String) -> Result<Uuid> {
-        let channels_vm = Client::list_channels(hippo_client).await?;
-        let channel = channels_vm.items.iter().find(|&x| x.name == name.clone());
-        match channel {
-            Some(c) => Ok(c.id),
-            None => anyhow::bail!("No channel with name: {}", name),
+    /// How requests failing with transient errors are retried.
+    fn retry_policy(&self) -> spin_publish::RetryPolicy {
+        spin_publish::RetryPolicy {
+            retries: self.retries,
+            delay: Duration::from_secs(self.retry_delay),
         }
     }
 
-    async fn create_and_push_bindle(&self, buildinfo: Option<BuildMetadata>) -> Result<Id> {
-        let source_dir = crate::app_dir(&self.app)?;
-        let bindle_connection_info = spin_publish::BindleConnectionInfo::new(
-            &self.bindle_server_url,
+    /// The name the application is deployed as, which names both the
+    /// bindle and the Hippo app.
+    pub(crate) fn app_name(&self, cfg: &RawAppManifest) -> String {
+        deployed_app_name(cfg, self.app_name.as_deref(), self.name_prefix.as_deref())
+    }
+
+    fn bindle_connection_info(&self) -> spin_publish::BindleConnectionInfo {
+        spin_publish::BindleConnectionInfo::new(
+            self.bindle_endpoint(),
             self.insecure,
             self.bindle_username.clone(),
             self.bindle_password.clone(),
-        );
+        )
+    }
 
+    /// Stages the bindle of the application and pushes it to the OCI
+    /// registry, returning the tag it was pushed as.
+    async fn create_and_push_artifact(
+        &self,
+        registry: &OciRepository,
+        name: String,
+        version: Option<Version>,
+        buildinfo: Option<BuildMetadata>,
+    ) -> Result<String> {
         let temp_dir = tempfile::tempdir()?;
         let dest_dir = match &self.staging_dir {
             None => temp_dir.path(),
             Some(path) => path.as_path(),
         };
-        let (invoice, sources) = spin_publish::expand_manifest(&self.app, buildinfo, &dest_dir)
-            .await
-            .with_context(|| format!("Failed to expand '{}' to a bindle", self.app.display()))?;
+        let invoice = self
+            .stage_bindle(name, version, buildinfo, dest_dir)
+            .await?;
+        let tag = spin_publish::tag_for_version(&invoice.bindle.id.version_string());
 
-        let bindle_id = &invoice.bindle.id;
+        let _sloth_warning = warn_if_slow_response(&format!("https://{}", registry.registry()));
+        spin_publish::push_oci(dest_dir, &invoice, registry, &tag, self.insecure).await?;
+        self.print_status(&format!("Pushed {}", registry.reference(&tag)));
+        Ok(tag)
+    }
 
-        spin_publish::write(&source_dir, &dest_dir, &invoice, &sources)
-            .await
-            .with_context(|| crate::write_failed_msg(bindle_id, dest_dir))?;
+    async fn create_and_push_bindle(
+        &self,
+        name: String,
+        version: Option<Version>,
+        buildinfo: Option<BuildMetadata>,
+    ) -> Result<Id> {
+        let bindle_connection_info = self.bindle_connection_info();
+
+        let temp_dir = tempfile::tempdir()?;
+        let dest_dir = match &self.staging_dir {
+            None => temp_dir.path(),
+            Some(path) => path.as_path(),
+        };
+        let invoice = self
+            .stage_bindle(name, version, buildinfo, dest_dir)
+            .await?;
+        let bindle_id = &invoice.bindle.id;
 
-        let _sloth_warning = warn_if_slow_response(&self.bindle_server_url);
+        let _sloth_warning = warn_if_slow_response(self.bindle_url());
 
-        let publish_result =
-            spin_publish::push_all(&dest_dir, bindle_id, bindle_connection_info).await;
+        let publish_result = spin_publish::push_all(
+            &dest_dir,
+            bindle_id,
+            bindle_connection_info,
+            spin_publish::PushOptions {
+                concurrency: self.push_concurrency,
+                retry: self.retry_policy(),
+            },
+        )
+        .await;
 
+        if let Ok(summary) = &publish_result {
+            self.print_status(&push_summary(summary));
+        }
         if let Err(publish_err) = publish_result {
             // TODO: maybe use `thiserror` to return type errors.
             let already_exists = publish_err
@@ -355,7 +1537,8 @@ impl DeployCommand {
                 return Err(publish_err).with_context(|| {
                     format!(
                         "Failed to push bindle {} to server {}",
-                        bindle_id, self.bindle_server_url
+                        bindle_id,
+                        self.bindle_url()
                     )
                 });
             }
@@ -364,28 +1547,282 @@ impl DeployCommand {
         Ok(bindle_id.clone())
     }
 
+    /// Expands the application to a bindle, and writes it to the given
+    /// directory.
+    async fn stage_bindle(
+        &self,
+        name: String,
+        version: Option<Version>,
+        buildinfo: Option<BuildMetadata>,
+        dest_dir: &Path,
+    ) -> Result<Invoice> {
+        let source_dir = crate::app_dir(&self.app)?;
+        let (invoice, sources) =
+            spin_publish::expand_manifest(&self.app, Some(name), version, buildinfo, dest_dir)
+                .await
+                .with_context(|| {
+                    format!("Failed to expand '{}' to a bindle", self.app.display())
+                })?;
+
+        let signing_key = signing_key(self.signing_key.as_deref()).await?;
+        if let Some(key) = &signing_key {
+            self.print_status(&format!("Signing bindle with key {}", key.label));
+        }
+        spin_publish::write(
+            &source_dir,
+            dest_dir,
+            &invoice,
+            &sources,
+            signing_key.as_ref(),
+        )
+        .await
+        .with_context(|| crate::write_failed_msg(&invoice.bindle.id, dest_dir))?;
+        Ok(invoice)
+    }
+
     async fn check_hippo_healthz(&self) -> Result<()> {
-        let hippo_base_url = url::Url::parse(&self.hippo_server_url)?;
+        let hippo_base_url = url::Url::parse(self.hippo_endpoint())?;
         let hippo_healthz_url = hippo_base_url.join("/healthz")?;
-        reqwest::get(hippo_healthz_url.to_string())
+        self.network
+            .client_builder(self.insecure)?
+            .build()?
+            .get(hippo_healthz_url.to_string())
+            .send()
             .await?
             .error_for_status()
-            .with_context(|| format!("Hippo server {} is unhealthy", hippo_base_url))?;
+            .with_context(|| format!("Hippo server {} is unhealthy", self.hippo_url()))?;
         Ok(())
     }
 }
 
-fn print_available_routes(
+/// Describes how many parcels a push uploaded, and how many the bindle
+/// server already had.
+pub(crate) fn push_summary(summary: &spin_publish::PushSummary) -> String {
+    format!(
+        "{}Uploaded {} parcels ({}); {} parcels ({}) already on the server",
+        if summary.resumed {
+            "Resumed an interrupted push. "
+        } else {
+            ""
+        },
+        summary.uploaded,
+        format_size(summary.uploaded_bytes),
+        summary.skipped,
+        format_size(summary.skipped_bytes)
+    )
+}
+
+/// A completed deploy, as printed by `spin deploy --output json`.
+#[derive(Debug, Serialize)]
+pub(crate) struct Deployment {
+    /// The name the application was deployed as.
+    pub app: String,
+    /// The version deployed.
+    pub version: String,
+    /// The bindle deployed, unless the application was deployed from an
+    /// OCI registry.
+    pub bindle_id: Option<String>,
+    /// The Hippo channel deployed to.
+    pub channel: String,
+    /// The domain the channel serves the application at.
+    pub domain: String,
+    /// The HTTP routes of the application.
+    pub routes: Vec<DeployedRoute>,
+}
+
+impl Deployment {
+    /// The URLs of the routes of the application, or of the channel if the
+    /// application has no HTTP routes.
+    pub fn urls(&self) -> Vec<String> {
+        if self.routes.is_empty() {
+            vec![self.domain.clone()]
+        } else {
+            self.routes.iter().map(|r| r.url.clone()).collect()
+        }
+    }
+}
+
+/// An HTTP route of a deployed application.
+#[derive(Debug, Serialize)]
+pub(crate) struct DeployedRoute {
+    /// The component handling the route.
+    pub component: String,
+    /// The URL of the route.
+    pub url: String,
+    /// The description of the component, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+/// The name an application is deployed as: the given name, or the name in
+/// its manifest, with the given prefix.
+pub(crate) fn deployed_app_name(
+    cfg: &RawAppManifest,
+    app_name: Option<&str>,
+    name_prefix: Option<&str>,
+) -> String {
+    let name = app_name.unwrap_or(&cfg.info.name);
+    match name_prefix {
+        Some(prefix) => format!("{}{}", prefix, name),
+        None => name.to_owned(),
+    }
+}
+
+/// How to authenticate to a Hippo server.
+pub(crate) struct HippoAuth<'a> {
+    pub url: &'a str,
+    /// The URL to send requests to the server at, which differs from its
+    /// URL if it is reached through a relay.
+    pub endpoint: &'a str,
+    pub insecure: bool,
+    pub api_key: Option<&'a str>,
+    pub username: Option<&'a str>,
+    pub password: Option<&'a str>,
+    /// The login cached by `spin login` for the server, if any.
+    pub login: Option<&'a Login>,
+}
+
+impl HippoAuth<'_> {
+    /// Returns a client for Hippo, authenticated with the API key, by
+    /// logging in with the credentials, or with the token cached by
+    /// `spin login`. Logging in with credentials refreshes the cached token.
+    pub(crate) async fn client(&self) -> Result<Client> {
+        Ok(Client::new(ConnectionInfo {
+            url: self.endpoint.to_owned(),
+            danger_accept_invalid_certs: self.insecure,
+            api_key: Some(self.token().await?),
+        }))
+    }
+
+    /// Returns the token to authenticate to Hippo with, as for `client`.
+    pub(crate) async fn token(&self) -> Result<String> {
+        if let Some(api_key) = self.api_key {
+            return Ok(api_key.to_owned());
+        }
+        let token = match (self.username, self.password, self.login) {
+            (Some(username), Some(password), login) => {
+                let (token, expiration) =
+                    hippo_token(self.endpoint, self.insecure, username, password).await?;
+                if let Some(login) = login.filter(|l| l.hippo_username == username) {
+                    let refreshed = Login {
+                        token: token.clone(),
+                        expiration,
+                        oidc: None,
+                        ..login.clone()
+                    };
+                    self.save_login(refreshed).await?;
+                }
+                token
+            }
+            (_, _, Some(login)) if login.is_expired() && login.is_refreshable() => {
+                self.refresh_oidc_login(login).await?
+            }
+            (_, _, Some(login)) if login.is_expired() => bail!(
+                "The login to {} has expired: run `spin login` again, or pass --hippo-username and --hippo-password",
+                self.url
+            ),
+            (_, _, Some(login)) => login.token.clone(),
+            _ => bail!(
+                "No Hippo credentials: run `spin login`, or pass --hippo-api-key, or --hippo-username and --hippo-password"
+            ),
+        };
+        Ok(token)
+    }
+
+    /// Refreshes the token of a login through an OpenID Connect issuer,
+    /// caching and returning the new token.
+    async fn refresh_oidc_login(&self, login: &Login) -> Result<String> {
+        let oidc = login
+            .oidc
+            .as_ref()
+            .context("The login is not refreshable")?;
+        let refresh_token = oidc
+            .refresh_token
+            .as_deref()
+            .context("The login is not refreshable")?;
+        let provider = OidcProvider::discover(&oidc.issuer, &oidc.client_id, self.insecure).await?;
+        let tokens = provider
+            .refresh(refresh_token)
+            .await
+            .with_context(|| format!("Cannot refresh the login to {}", self.url))?;
+        let refreshed = Login {
+            expiration: tokens.expiration(),
+            token: tokens.access_token.clone(),
+            oidc: Some(OidcLogin {
+                // Issuers may rotate refresh tokens on every use.
+                refresh_token: tokens.refresh_token.or_else(|| oidc.refresh_token.clone()),
+                ..oidc.clone()
+            }),
+            ..login.clone()
+        };
+        self.save_login(refreshed).await?;
+        Ok(tokens.access_token)
+    }
+
+    /// Replaces the cached login for the Hippo server.
+    async fn save_login(&self, login: Login) -> Result<()> {
+        let path = logins_path()?;
+        let mut logins = Logins::load(&path).await?;
+        logins.servers.insert(self.url.to_owned(), login);
+        logins.save(&path).await
+    }
+}
+
+/// The ID of the Hippo app with the given name, if it exists.
+pub(crate) async fn get_app_id(hippo_client: &Client, name: &str) -> Result<Option<Uuid>> {
+    let apps_vm = Client::list_apps(hippo_client)
+        .await
+        .context("Unable to list Hippo apps")?;
+    let app = apps_vm.items.iter().find(|&x| x.name == name);
+    Ok(app.map(|a| a.id))
+}
+
+/// The ID of the channel of a Hippo app with the given name, if it exists.
+pub(crate) async fn get_channel_id(
+    hippo_client: &Client,
+    app_id: Uuid,
+    name: &str,
+) -> Result<Option<Uuid>> {
+    let channels_vm = Client::list_channels(hippo_client).await?;
+    // Channel names are only unique within an app.
+    let channel = channels_vm
+        .items
+        .iter()
+        .find(|&x| x.app_id == app_id && x.name == name);
+    Ok(channel.map(|c| c.id))
+}
+
+/// The environment variables set on a Hippo channel.
+pub(crate) async fn get_channel_variables(
+    hippo_client: &Client,
+    channel_id: Uuid,
+) -> Result<Vec<(String, String)>> {
+    let variables_vm = Client::list_environment_variables(hippo_client)
+        .await
+        .context("Unable to list Hippo environment variables")?;
+    Ok(variables_vm
+        .environment_variables
+        .into_iter()
+        .filter(|v| v.channel_id == channel_id)
+        .map(|v| (v.key, v.value))
+        .collect())
+}
+
+/// Parses a variable passed as `KEY=VALUE`.
+fn parse_variable(s: &str) -> Result<(String, String)> {
+    match s.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_owned(), value.to_owned())),
+        _ => bail!("Variables must be of the form `KEY=VALUE`"),
+    }
+}
+
+fn available_routes(
     address: &str,
     base: &str,
     hippo_url: &str,
     cfg: &spin_loader::local::config::RawAppManifest,
-) {
-    if cfg.components.is_empty() {
-        return;
-    }
-
-    println!("Available Routes:");
+) -> Vec<DeployedRoute> {
+    let mut routes = vec![];
     for component in &cfg.components {
         if let TriggerConfig::Http(http_cfg) = &component.trigger {
             let url_result = Url::parse(hippo_url);
@@ -395,12 +1832,40 @@ fn print_available_routes(
             };
 
             let route = RoutePattern::from(base, &http_cfg.route);
-            println!("  {}: {}://{}{}", component.id, scheme, address, route);
-            if let Some(description) = &component.description {
-                println!("    {}", description);
-            }
+            routes.push(DeployedRoute {
+                component: component.id.clone(),
+                url: format!("{}://{}{}", scheme, address, route),
+                description: component.description.clone(),
+            });
         }
     }
+    routes
+}
+
+/// Logs in to Hippo, returning the token for the user and when it expires,
+/// if Hippo says.
+pub(crate) async fn hippo_token(
+    url: &str,
+    insecure: bool,
+    username: &str,
+    password: &str,
+) -> Result<(String, Option<String>)> {
+    let _sloth_warning = warn_if_slow_response(url);
+
+    match Client::login(
+        &Client::new(ConnectionInfo {
+            url: url.to_owned(),
+            danger_accept_invalid_certs: insecure,
+            api_key: None,
+        }),
+        username.to_owned(),
+        password.to_owned(),
+    )
+    .await
+    {
+        Ok(token_info) => Ok((token_info.token.unwrap_or_default(), token_info.expiration)),
+        Err(err) => bail!(format_login_error(&err)?),
+    }
 }
 
 #[derive(Deserialize, Serialize)]
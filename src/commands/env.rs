@@ -0,0 +1,403 @@
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use clap::Parser;
+use serde::Serialize;
+use spin_loader::local::config::{RawAppManifestAnyVersion, RawScaleConfig};
+use spin_manifest::ApplicationTrigger;
+use spin_trigger::RuntimeConfig;
+
+use crate::{deploy_profile::DeployProfile, opts::*};
+
+/// Shown in place of secret values.
+const MASKED: &str = "<secret>";
+
+/// Parts of runtime config keys whose values are secrets.
+const SECRET_KEYS: &[&str] = &["secret", "password", "token", "private_key", "credential"];
+
+/// Print the effective configuration of an application.
+#[derive(Parser, Debug)]
+#[clap(
+    about = "Print the effective configuration of an application: variables, runtime config, deploy settings, data directory and listeners"
+)]
+pub struct EnvCommand {
+    /// Path to spin.toml
+    #[clap(
+        name = APP_CONFIG_FILE_OPT,
+        short = 'f',
+        long = "file",
+        default_value = "spin.toml"
+    )]
+    pub app: PathBuf,
+
+    /// The runtime config file the application is run with
+    #[clap(long = "runtime-config-file", env = "RUNTIME_CONFIG_FILE")]
+    pub runtime_config_file: Option<PathBuf>,
+
+    /// The address the HTTP trigger listens on, as passed to `spin up --listen`
+    #[clap(long = "listen", default_value = "127.0.0.1:3000")]
+    pub listen: String,
+
+    /// The deploy profile, as passed to `spin deploy --profile`
+    #[clap(long = "profile", env = "SPIN_DEPLOY_PROFILE")]
+    pub profile: Option<PathBuf>,
+
+    /// The environment in the [deploy.environments] of spin.toml, as passed
+    /// to `spin deploy --environment`
+    #[clap(long = "environment", env = "SPIN_DEPLOY_ENVIRONMENT")]
+    pub environment: Option<String>,
+
+    /// Print the configuration as JSON
+    #[clap(long = "json")]
+    pub json: bool,
+}
+
+/// The effective configuration of an application.
+#[derive(Debug, Serialize)]
+struct EffectiveConfig {
+    name: String,
+    version: String,
+    manifest: PathBuf,
+    trigger: String,
+    variables: Vec<Variable>,
+    runtime_config: Option<RuntimeConfigFile>,
+    deploy: Deploy,
+    data_dir: Option<DataDirectory>,
+    listeners: Vec<Listener>,
+}
+
+/// A variable, or a component config value, and its resolved value.
+#[derive(Debug, Serialize)]
+struct Variable {
+    path: String,
+    secret: bool,
+    /// The value, masked if it is a secret.
+    value: Option<String>,
+    /// Why the value cannot be resolved.
+    error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct RuntimeConfigFile {
+    path: PathBuf,
+    /// The settings of the file, with secrets masked.
+    settings: toml::Value,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct Deploy {
+    profile: Option<PathBuf>,
+    environment: Option<String>,
+    hippo_server: Option<String>,
+    bindle_server: Option<String>,
+    channel: Option<String>,
+    scale: Option<RawScaleConfig>,
+}
+
+#[derive(Debug, Serialize)]
+struct DataDirectory {
+    path: PathBuf,
+    max_bytes: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+struct Listener {
+    name: String,
+    address: String,
+}
+
+impl EnvCommand {
+    pub async fn run(self) -> Result<()> {
+        let config = self.effective_config().await?;
+        if self.json {
+            println!("{}", serde_json::to_string_pretty(&config)?);
+        } else {
+            print_config(&config)?;
+        }
+        Ok(())
+    }
+
+    async fn effective_config(&self) -> Result<EffectiveConfig> {
+        let RawAppManifestAnyVersion::V1(raw) =
+            spin_loader::local::raw_manifest_from_file(&self.app).await?;
+        let runtime_config = match &self.runtime_config_file {
+            Some(path) => Some((path, RuntimeConfig::from_file(path)?)),
+            None => None,
+        };
+        let runtime = runtime_config
+            .as_ref()
+            .map(|(_, config)| config.clone())
+            .unwrap_or_default();
+
+        let mut resolver = spin_config::Resolver::new(spin_loader::local::config_tree(&raw)?)?;
+        if runtime.config_provider.is_empty() {
+            resolver.add_providers(&[spin_config::ProviderConfig::default()]);
+        } else {
+            resolver.add_providers(&runtime.config_provider);
+        }
+        // Secret providers such as Vault fetch values with blocking requests.
+        let variables = tokio::task::spawn_blocking(move || {
+            resolver
+                .tree()
+                .paths()
+                .map(|path| {
+                    let secret = resolver.is_secret(path);
+                    let (value, error) = match resolver.resolve(path) {
+                        Ok(_) if secret => (Some(MASKED.to_owned()), None),
+                        Ok(value) => (Some(value), None),
+                        Err(e) => (None, Some(e.to_string())),
+                    };
+                    Variable {
+                        path: path.to_string(),
+                        secret,
+                        value,
+                        error,
+                    }
+                })
+                .collect()
+        })
+        .await?;
+
+        let runtime_config = match &runtime_config {
+            Some((path, _)) => {
+                let contents = std::fs::read_to_string(path)
+                    .with_context(|| format!("Cannot read {}", path.display()))?;
+                let mut settings: toml::Value = toml::from_str(&contents)?;
+                mask_secrets(&mut settings);
+                Some(RuntimeConfigFile {
+                    path: path.to_path_buf(),
+                    settings,
+                })
+            }
+            None => None,
+        };
+
+        let mut deploy = Deploy {
+            profile: self.profile.clone(),
+            environment: self.environment.clone(),
+            scale: raw.deploy.as_ref().and_then(|d| d.scale.clone()),
+            ..Default::default()
+        };
+        if let Some(path) = &self.profile {
+            let profile = DeployProfile::from_file(path).await?;
+            deploy.environment = deploy.environment.or(profile.environment);
+        }
+        if let Some(name) = &self.environment {
+            let environment = raw
+                .deploy
+                .as_ref()
+                .and_then(|d| d.environments.get(name))
+                .with_context(|| {
+                    format!(
+                        "No environment {} in the [deploy.environments] of {}",
+                        name,
+                        self.app.display()
+                    )
+                })?;
+            deploy.hippo_server = environment.hippo_server.clone();
+            deploy.bindle_server = environment.bindle_server.clone();
+            deploy.channel = environment.channel.clone();
+        }
+
+        let data_dir = runtime
+            .data_dir
+            .resolve(&raw.info.name)
+            .map(|dir| DataDirectory {
+                path: dir.path().to_owned(),
+                max_bytes: dir.max_bytes(),
+            });
+
+        let mut listeners = vec![];
+        match &raw.info.trigger {
+            ApplicationTrigger::Http(http) => {
+                // Unix domain sockets and systemd sockets have no URL.
+                let url = |path: &str| match self.listen.parse::<std::net::SocketAddr>() {
+                    Ok(_) => format!("http://{}{}", self.listen, path),
+                    Err(_) => format!("{} {}", self.listen, path),
+                };
+                listeners.push(Listener {
+                    name: "http".to_owned(),
+                    address: url(&http.base),
+                });
+                if let Some(path) = &runtime.metrics.path {
+                    listeners.push(Listener {
+                        name: "metrics".to_owned(),
+                        address: url(path),
+                    });
+                }
+            }
+            ApplicationTrigger::Redis(redis) => listeners.push(Listener {
+                name: "redis".to_owned(),
+                address: mask_url(&redis.address),
+            }),
+            ApplicationTrigger::Queue(queue) => listeners.push(Listener {
+                name: format!("{:?}", queue.broker).to_lowercase(),
+                address: mask_url(&queue.address),
+            }),
+            ApplicationTrigger::External(_) => {}
+        }
+        if let Some(admin) = &runtime.admin {
+            listeners.push(Listener {
+                name: "admin".to_owned(),
+                address: format!("http://{}", admin.listen),
+            });
+        }
+
+        Ok(EffectiveConfig {
+            name: raw.info.name.clone(),
+            version: raw.info.version.clone(),
+            manifest: self.app.clone(),
+            trigger: raw.info.trigger.trigger_type(),
+            variables,
+            runtime_config,
+            deploy,
+            data_dir,
+            listeners,
+        })
+    }
+}
+
+fn print_config(config: &EffectiveConfig) -> Result<()> {
+    println!("Application: {} {}", config.name, config.version);
+    println!("Manifest: {}", config.manifest.display());
+    println!("Trigger: {}", config.trigger);
+
+    println!();
+    println!("Variables:");
+    if config.variables.is_empty() {
+        println!("  none");
+    }
+    for variable in &config.variables {
+        match (&variable.value, &variable.error) {
+            (Some(value), _) if variable.secret => println!("  {} = {}", variable.path, value),
+            (Some(value), _) => println!("  {} = {:?}", variable.path, value),
+            (None, error) => println!(
+                "  {}: unresolved: {}",
+                variable.path,
+                error.as_deref().unwrap_or_default()
+            ),
+        }
+    }
+
+    println!();
+    match &config.runtime_config {
+        Some(runtime_config) => {
+            println!("Runtime config: {}", runtime_config.path.display());
+            for line in toml::to_string(&runtime_config.settings)?.lines() {
+                println!("  {}", line);
+            }
+        }
+        None => println!("Runtime config: none (defaults)"),
+    }
+
+    println!();
+    println!("Deploy:");
+    let deploy = &config.deploy;
+    let show = |value: &Option<String>| value.clone().unwrap_or_else(|| "-".to_owned());
+    println!(
+        "  Profile: {}",
+        deploy
+            .profile
+            .as_ref()
+            .map(|p| p.display().to_string())
+            .unwrap_or_else(|| "-".to_owned())
+    );
+    println!("  Environment: {}", show(&deploy.environment));
+    println!("  Hippo server: {}", show(&deploy.hippo_server));
+    println!("  Bindle server: {}", show(&deploy.bindle_server));
+    println!("  Channel: {}", show(&deploy.channel));
+    if let Some(scale) = &deploy.scale {
+        println!(
+            "  Scale: replicas {}-{}, memory class {}",
+            scale
+                .min_replicas
+                .map(|n| n.to_string())
+                .unwrap_or_default(),
+            scale
+                .max_replicas
+                .map(|n| n.to_string())
+                .unwrap_or_default(),
+            show(&scale.memory_class)
+        );
+    }
+
+    println!();
+    match &config.data_dir {
+        Some(dir) => println!(
+            "Data directory: {} (quota: {})",
+            dir.path.display(),
+            dir.max_bytes
+                .map(|max| format!("{} bytes", max))
+                .unwrap_or_else(|| "none".to_owned())
+        ),
+        None => println!("Data directory: none"),
+    }
+
+    println!();
+    println!("Listeners:");
+    if config.listeners.is_empty() {
+        println!("  none");
+    }
+    for listener in &config.listeners {
+        println!("  {}: {}", listener.name, listener.address);
+    }
+    Ok(())
+}
+
+/// Masks the values of the keys naming secrets, and the passwords of URLs.
+fn mask_secrets(value: &mut toml::Value) {
+    match value {
+        toml::Value::Table(table) => {
+            for (key, value) in table.iter_mut() {
+                let key = key.to_ascii_lowercase();
+                if value.is_str() && SECRET_KEYS.iter().any(|secret| key.contains(secret)) {
+                    *value = toml::Value::String(MASKED.to_owned());
+                } else {
+                    mask_secrets(value);
+                }
+            }
+        }
+        toml::Value::Array(values) => values.iter_mut().for_each(mask_secrets),
+        toml::Value::String(s) => *s = mask_url(s),
+        _ => {}
+    }
+}
+
+/// Masks the password of a URL, leaving other strings unchanged.
+fn mask_url(s: &str) -> String {
+    match url::Url::parse(s) {
+        Ok(mut url) if url.password().is_some() => {
+            // Only URLs that can have a password get here.
+            let _ = url.set_password(Some(MASKED));
+            url.to_string()
+        }
+        _ => s.to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mask_secrets() {
+        let mut settings: toml::Value = toml::from_str(
+            r#"
+            [blob_store.images]
+            type = "s3"
+            endpoint = "http://localhost:9000"
+            secret_key = "minio123"
+
+            [lock]
+            type = "redis"
+            address = "redis://:hunter2@localhost:6379"
+            "#,
+        )
+        .unwrap();
+        mask_secrets(&mut settings);
+        let masked = toml::to_string(&settings).unwrap();
+        assert!(!masked.contains("minio123"), "{}", masked);
+        assert!(!masked.contains("hunter2"), "{}", masked);
+        assert!(masked.contains("http://localhost:9000"), "{}", masked);
+    }
+}
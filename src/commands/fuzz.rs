@@ -0,0 +1,248 @@
+use std::{
+    collections::HashSet,
+    net::SocketAddr,
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{bail, Context, Result};
+use clap::Parser;
+use hyper::{Body, Request};
+use spin_http_engine::{routes::RoutePattern, HttpTrigger};
+use spin_manifest::{ApplicationTrigger, TriggerConfig};
+use spin_trigger::TriggerExecutorBuilder;
+
+use crate::{
+    app_dir,
+    fuzz::{self, CorpusEntry, Failure, FuzzDir, FuzzInput, Rng},
+    opts::*,
+};
+
+/// Fuzz an HTTP component of an application.
+#[derive(Parser, Debug)]
+#[clap(about = "Send mutated requests to an HTTP component to find inputs it fails on")]
+pub struct FuzzCommand {
+    /// Path to spin.toml
+    #[clap(
+        name = APP_CONFIG_FILE_OPT,
+        short = 'f',
+        long = "file",
+        default_value = "spin.toml"
+    )]
+    pub app: PathBuf,
+
+    /// ID of the component to fuzz
+    #[clap(name = "COMPONENT")]
+    pub component: String,
+
+    /// Number of mutated requests to send
+    #[clap(short = 'n', long = "iterations", default_value = "1000")]
+    pub iterations: usize,
+
+    /// Seconds a request may take before it counts as a failure
+    #[clap(long = "timeout", value_name = "SECONDS", default_value = "5")]
+    pub timeout: u64,
+
+    /// Seed of the mutations, to repeat a run (defaults to the current time)
+    #[clap(long = "seed")]
+    pub seed: Option<u64>,
+}
+
+/// How the component handled an input.
+enum Outcome {
+    Response {
+        status: u16,
+        signature: String,
+    },
+    Failed(String),
+    /// The component did not respond in time, and cannot be interrupted.
+    TimedOut,
+}
+
+impl FuzzCommand {
+    pub async fn run(self) -> Result<()> {
+        let app_dir = app_dir(&self.app)?;
+        let working_dir = tempfile::tempdir()?;
+        let app =
+            spin_loader::from_file(&self.app, working_dir.path(), &None, false, false).await?;
+        let base = match &app.info.trigger {
+            ApplicationTrigger::Http(config) => config.base.clone(),
+            _ => bail!("Only the components of HTTP applications can be fuzzed"),
+        };
+        let route = match app.component_triggers.get(&self.component) {
+            Some(TriggerConfig::Http(config)) => config.route.clone(),
+            _ => bail!("No HTTP component {} in the application", self.component),
+        };
+        let route_prefix = match RoutePattern::from(base.as_str(), route.as_str()) {
+            RoutePattern::Exact(path) => path,
+            RoutePattern::Wildcard(prefix) => format!("{}/", prefix),
+            // Named segments are fuzzed with a fixed value.
+            RoutePattern::Parameterized { pattern, wildcard } => {
+                let path = pattern
+                    .split('/')
+                    .map(|s| if s.starts_with(':') { "1" } else { s })
+                    .collect::<Vec<_>>()
+                    .join("/");
+                if wildcard {
+                    format!("{}/", path)
+                } else {
+                    path
+                }
+            }
+        };
+        let trigger: Arc<HttpTrigger> = Arc::new(TriggerExecutorBuilder::new(app).build().await?);
+
+        let fuzz_dir = FuzzDir::new(&app_dir, &self.component);
+        let mut corpus = fuzz_dir.load_corpus().await?;
+        let seed = self.seed.unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default()
+        });
+        println!(
+            "Fuzzing component {} with seed {} ({} corpus inputs)",
+            self.component,
+            seed,
+            corpus.len()
+        );
+
+        let mut signatures = HashSet::new();
+        let mut failures = 0;
+
+        // Inputs of the corpus are replayed first, so that inputs that used
+        // to be handled and now get server errors are reported as regressions.
+        let mut replays: Vec<_> = corpus.iter().map(|e| (e.input.clone(), e.status)).collect();
+        if replays.is_empty() {
+            replays = fuzz::seeds(&route_prefix)
+                .into_iter()
+                .map(|input| (input, None))
+                .collect();
+        }
+        let requests = replays.len() + self.iterations;
+        let mut replays = replays.into_iter();
+        let mut rng = Rng::new(seed);
+        for _ in 0..requests {
+            let (input, previous_status) = match replays.next() {
+                Some(replay) => replay,
+                None => {
+                    let parent = match corpus.len() {
+                        0 => fuzz::seeds(&route_prefix).remove(0),
+                        len => corpus[rng.below(len)].input.clone(),
+                    };
+                    (fuzz::mutate(&parent, &route_prefix, &mut rng), None)
+                }
+            };
+
+            let outcome = self.send(&trigger, &input).await;
+            let failure = match &outcome {
+                Outcome::Response { status, signature } => {
+                    if signatures.insert(signature.clone()) && previous_status.is_none() {
+                        let entry = CorpusEntry {
+                            input: input.clone(),
+                            status: Some(*status),
+                        };
+                        fuzz_dir.save_corpus_entry(&entry).await?;
+                        corpus.push(entry);
+                    }
+                    match previous_status {
+                        Some(previous) if *status >= 500 && previous < 500 => {
+                            Some(format!("regression: status {} (was {})", status, previous))
+                        }
+                        _ if *status >= 500 => Some(format!("status {}", status)),
+                        _ => None,
+                    }
+                }
+                Outcome::Failed(error) => Some(error.clone()),
+                Outcome::TimedOut => Some(format!("no response after {}s", self.timeout)),
+            };
+
+            if let Some(failure) = failure {
+                failures += 1;
+                let path = fuzz_dir
+                    .save_failure(&Failure {
+                        input: input.clone(),
+                        failure: failure.clone(),
+                    })
+                    .await?;
+                println!(
+                    "FAILED {} {}: {} (saved in {})",
+                    input.method,
+                    input.path,
+                    failure,
+                    path.display()
+                );
+                if let Outcome::TimedOut = outcome {
+                    // Wasm code cannot be interrupted, so the component may
+                    // still be running: exit rather than wait for it.
+                    println!("Stopping, as the component cannot be interrupted");
+                    std::process::exit(1);
+                }
+            }
+        }
+
+        println!(
+            "Sent {} requests: {} corpus inputs, {} failures",
+            requests,
+            corpus.len(),
+            failures
+        );
+        if failures > 0 {
+            bail!(
+                "Component {} failed on {} inputs, saved in {}",
+                self.component,
+                failures,
+                fuzz_dir.failures_dir().display()
+            );
+        }
+        Ok(())
+    }
+
+    async fn send(&self, trigger: &Arc<HttpTrigger>, input: &FuzzInput) -> Outcome {
+        let req = match build_request(input) {
+            Ok(req) => req,
+            Err(e) => return Outcome::Failed(format!("invalid input: {:#}", e)),
+        };
+        // The component runs in its own task, so that a timeout can be
+        // detected even if it never yields.
+        let trigger = trigger.clone();
+        let component = self.component.clone();
+        let addr = SocketAddr::from(([127, 0, 0, 1], 0));
+        let task = tokio::spawn(async move {
+            let res = trigger.execute_component(&component, req, addr).await?;
+            let status = res.status().as_u16();
+            let content_type = res
+                .headers()
+                .get(hyper::header::CONTENT_TYPE)
+                .and_then(|v| v.to_str().ok())
+                .map(|v| v.to_owned());
+            let body = hyper::body::to_bytes(res.into_body()).await?;
+            Ok::<_, anyhow::Error>((status, content_type, body.len()))
+        });
+        match tokio::time::timeout(Duration::from_secs(self.timeout), task).await {
+            Ok(Ok(Ok((status, content_type, body_len)))) => Outcome::Response {
+                status,
+                signature: fuzz::signature(status, content_type.as_deref(), body_len),
+            },
+            Ok(Ok(Err(e))) if e.chain().any(|c| c.is::<wasmtime::Trap>()) => {
+                Outcome::Failed(format!("trap: {:#}", e))
+            }
+            Ok(Ok(Err(e))) => Outcome::Failed(format!("error: {:#}", e)),
+            Ok(Err(e)) => Outcome::Failed(format!("panic: {}", e)),
+            Err(_) => Outcome::TimedOut,
+        }
+    }
+}
+
+fn build_request(input: &FuzzInput) -> Result<Request<Body>> {
+    let mut builder = Request::builder()
+        .method(input.method.as_str())
+        .uri(format!("http://localhost{}", input.path));
+    for (name, value) in &input.headers {
+        builder = builder.header(name, value);
+    }
+    builder
+        .body(Body::from(input.body.clone()))
+        .context("Cannot build request")
+}
@@ -0,0 +1,104 @@
+use std::path::PathBuf;
+
+use anyhow::Result;
+use clap::Parser;
+use comfy_table::Table;
+
+use crate::deploy_history::{self, DeployRecord};
+
+/// Print the deploys made from this machine, most recent first.
+#[derive(Parser, Debug)]
+#[clap(about = "Print the deploys made from this machine")]
+pub struct HistoryCommand {
+    /// Only print deploys of the application with this name
+    #[clap(long = "app-name")]
+    pub app_name: Option<String>,
+
+    /// Only print deploys to this Hippo server
+    #[clap(long = "hippo-server")]
+    pub hippo_server_url: Option<String>,
+
+    /// Only print failed deploys
+    #[clap(long = "failed")]
+    pub failed: bool,
+
+    /// Maximum number of deploys to print
+    #[clap(short = 'n', long = "limit", default_value = "20")]
+    pub limit: usize,
+
+    /// Print the deploys as JSON lines, as they are recorded
+    #[clap(long = "json")]
+    pub json: bool,
+
+    /// The deploy history file (defaults to the one in the Spin config
+    /// directory)
+    #[clap(long = "history-file", env = "SPIN_DEPLOY_HISTORY")]
+    pub history_file: Option<PathBuf>,
+}
+
+impl HistoryCommand {
+    pub async fn run(self) -> Result<()> {
+        let path = match &self.history_file {
+            Some(path) => path.clone(),
+            None => deploy_history::history_path()?,
+        };
+        let records = self.select(deploy_history::load(&path).await?);
+        if records.is_empty() {
+            if !self.json {
+                println!("No deploys");
+            }
+            return Ok(());
+        }
+
+        if self.json {
+            for record in records {
+                println!("{}", serde_json::to_string(&record)?);
+            }
+            return Ok(());
+        }
+
+        let mut table = Table::new();
+        table.set_header(vec!["Time", "App", "Version", "Target", "User", "Result"]);
+        table.load_preset(comfy_table::presets::ASCII_BORDERS_ONLY_CONDENSED);
+        for record in records {
+            let target = match &record.environment {
+                Some(environment) => format!(
+                    "{} ({}, {})",
+                    record.hippo_server, record.channel, environment
+                ),
+                None => format!("{} ({})", record.hippo_server, record.channel),
+            };
+            let result = match (&record.bindle_id, &record.error) {
+                (_, Some(error)) => format!("failed: {}", error),
+                (Some(bindle_id), None) => format!("deployed {}", bindle_id),
+                (None, None) => "deployed".to_owned(),
+            };
+            table.add_row(vec![
+                record.timestamp,
+                record.app,
+                record.version,
+                target,
+                record.user.unwrap_or_default(),
+                result,
+            ]);
+        }
+        println!("{}", table);
+        Ok(())
+    }
+
+    /// The records matching the filters, most recent first.
+    fn select(&self, records: Vec<DeployRecord>) -> Vec<DeployRecord> {
+        records
+            .into_iter()
+            .rev()
+            .filter(|r| self.app_name.as_deref().map_or(true, |app| r.app == app))
+            .filter(|r| {
+                self.hippo_server_url
+                    .as_deref()
+                    .map_or(true, |url| r.hippo_server == url)
+            })
+            .filter(|r| !self.failed || !r.success)
+            .take(self.limit)
+            .collect()
+    }
+}
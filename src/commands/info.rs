@@ -0,0 +1,66 @@
+use anyhow::Result;
+use clap::Parser;
+use spin_engine::module_cache::{self, ModuleCacheDir};
+use spin_loader::bindle::{cache as parcel_cache, ParcelCache};
+
+/// Print information about Spin and its caches
+#[derive(Parser, Debug)]
+#[clap(about = "Print information about Spin and its caches")]
+pub struct InfoCommand {}
+
+impl InfoCommand {
+    pub async fn run(self) -> Result<()> {
+        println!("Spin version: {}", env!("CARGO_PKG_VERSION"));
+        match module_cache::default_dir() {
+            Some(dir) => {
+                let cache = ModuleCacheDir::new(dir);
+                let stats = cache.stats()?;
+                println!("Compiled module cache: {}", cache.path().display());
+                println!("  Modules: {}", stats.entries);
+                println!("  Size: {}", format_size(stats.bytes));
+            }
+            None => println!("Compiled module cache: unavailable"),
+        }
+        match parcel_cache::default_dir() {
+            Some(dir) => {
+                let cache = ParcelCache::new(dir);
+                let parcels = cache.list()?;
+                println!("Bindle parcel cache: {}", cache.path().display());
+                println!("  Parcels: {}", parcels.len());
+                println!(
+                    "  Size: {}",
+                    format_size(parcels.iter().map(|p| p.bytes).sum())
+                );
+            }
+            None => println!("Bindle parcel cache: unavailable"),
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(1536), "1.5 KiB");
+        assert_eq!(format_size(3 * 1024 * 1024), "3.0 MiB");
+    }
+}
@@ -0,0 +1,296 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+use clap::Parser;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use spin_loader::bindle::{
+    cache as parcel_cache, BindleConnectionInfo, ParcelCache, SignaturePolicy,
+};
+use spin_manifest::{Application, ApplicationOrigin, CoreComponent, ModuleSource, TriggerConfig};
+
+use super::deploy::OutputFormat;
+use crate::opts::*;
+
+/// Print what is inside an application: its components, their sources and
+/// digests, routes, mounted files and environment.
+#[derive(Parser, Debug)]
+#[clap(
+    about = "Print the components of a local or bindled application, with their sources, triggers and files"
+)]
+pub struct InspectCommand {
+    /// Path to spin.toml.
+    #[clap(
+        name = APP_CONFIG_FILE_OPT,
+        short = 'f',
+        long = "file",
+        conflicts_with = BINDLE_ID_OPT,
+    )]
+    pub app: Option<PathBuf>,
+
+    /// ID of application bindle.
+    #[clap(
+        name = BINDLE_ID_OPT,
+        short = 'b',
+        long = "bindle",
+        conflicts_with = APP_CONFIG_FILE_OPT,
+        requires = BINDLE_SERVER_URL_OPT,
+    )]
+    pub bindle: Option<String>,
+
+    /// URL of bindle server.
+    #[clap(
+        name = BINDLE_SERVER_URL_OPT,
+        long = "bindle-server",
+        env = BINDLE_URL_ENV,
+    )]
+    pub server: Option<String>,
+
+    /// Basic http auth username for the bindle server
+    #[clap(
+        name = BINDLE_USERNAME,
+        long = "bindle-username",
+        env = BINDLE_USERNAME,
+        requires = BINDLE_PASSWORD
+    )]
+    pub bindle_username: Option<String>,
+
+    /// Basic http auth password for the bindle server
+    #[clap(
+        name = BINDLE_PASSWORD,
+        long = "bindle-password",
+        env = BINDLE_PASSWORD,
+        requires = BINDLE_USERNAME
+    )]
+    pub bindle_password: Option<String>,
+
+    /// Bearer token for the bindle server, instead of a username and password
+    #[clap(
+        name = BINDLE_TOKEN,
+        long = "bindle-token",
+        env = BINDLE_TOKEN,
+        hide_env_values = true,
+        conflicts_with = BINDLE_USERNAME
+    )]
+    pub bindle_token: Option<String>,
+
+    /// Ignore server certificate errors from bindle server
+    #[clap(
+        name = INSECURE_OPT,
+        short = 'k',
+        long = "insecure",
+        takes_value = false,
+    )]
+    pub insecure: bool,
+
+    /// How to print the application: `plain` for people, `json` for tools
+    #[clap(long = "output", arg_enum, default_value = "plain")]
+    pub output: OutputFormat,
+}
+
+/// What is inside an application.
+#[derive(Debug, Serialize)]
+struct Inspection {
+    name: String,
+    version: String,
+    description: Option<String>,
+    authors: Vec<String>,
+    origin: String,
+    trigger: serde_json::Value,
+    components: Vec<ComponentInspection>,
+}
+
+#[derive(Debug, Serialize)]
+struct ComponentInspection {
+    id: String,
+    description: Option<String>,
+    source: String,
+    /// The SHA-256 digest of the module.
+    digest: String,
+    size: u64,
+    trigger: Option<serde_json::Value>,
+    environment: BTreeMap<String, String>,
+    allowed_http_hosts: Vec<String>,
+    key_value_stores: Vec<String>,
+    files: Vec<MountedFile>,
+}
+
+#[derive(Debug, Serialize)]
+struct MountedFile {
+    /// The path of the file in the guest.
+    path: String,
+    size: u64,
+}
+
+impl InspectCommand {
+    pub async fn run(self) -> Result<()> {
+        // Files are copied for the components as they would be to run them.
+        let working_dir = tempfile::tempdir()?;
+        let app = self.load(working_dir.path()).await?;
+        let inspection = inspect(&app)?;
+        match self.output {
+            OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&inspection)?),
+            OutputFormat::Plain => print_inspection(&inspection),
+        }
+        Ok(())
+    }
+
+    async fn load(&self, working_dir: &Path) -> Result<Application> {
+        let bindle_connection = self.server.as_ref().map(|url| match &self.bindle_token {
+            Some(token) => BindleConnectionInfo::with_token(url, self.insecure, token.clone()),
+            None => BindleConnectionInfo::new(
+                url,
+                self.insecure,
+                self.bindle_username.clone(),
+                self.bindle_password.clone(),
+            ),
+        });
+        match (&self.app, &self.bindle) {
+            (app, None) => {
+                let manifest_file = app
+                    .as_deref()
+                    .unwrap_or_else(|| DEFAULT_MANIFEST_FILE.as_ref());
+                spin_loader::from_file(manifest_file, working_dir, &bindle_connection, false, true)
+                    .await
+            }
+            (None, Some(bindle)) => match &bindle_connection {
+                Some(bindle_connection) => {
+                    spin_loader::from_bindle(
+                        bindle,
+                        bindle_connection,
+                        working_dir,
+                        false,
+                        &SignaturePolicy::default(),
+                        parcel_cache::default_dir().map(ParcelCache::new),
+                    )
+                    .await
+                }
+                None => bail!("Loading from a bindle requires a Bindle server URL"),
+            },
+            (Some(_), Some(_)) => bail!("Specify only one of app file or bindle ID"),
+        }
+    }
+}
+
+fn inspect(app: &Application) -> Result<Inspection> {
+    let origin = match &app.info.origin {
+        ApplicationOrigin::File(path) => path.display().to_string(),
+        ApplicationOrigin::Bindle { id, server } => format!("bindle {} on {}", id, server),
+    };
+    let components = app
+        .components
+        .iter()
+        .map(|c| inspect_component(c, app.component_triggers.get(&c.id)))
+        .collect::<Result<_>>()?;
+    Ok(Inspection {
+        name: app.info.name.clone(),
+        version: app.info.version.clone(),
+        description: app.info.description.clone(),
+        authors: app.info.authors.clone(),
+        origin,
+        trigger: serde_json::to_value(&app.info.trigger)?,
+        components,
+    })
+}
+
+fn inspect_component(
+    c: &CoreComponent,
+    trigger: Option<&TriggerConfig>,
+) -> Result<ComponentInspection> {
+    let (source, module) = match &c.source {
+        ModuleSource::FileReference(path) => (
+            path.display().to_string(),
+            std::fs::read(path)
+                .with_context(|| format!("Cannot read module {}", path.display()))?,
+        ),
+        ModuleSource::Buffer(bytes, info) => (info.clone(), bytes.clone()),
+    };
+    let mut files = vec![];
+    for mount in &c.wasm.mounts {
+        collect_files(&mount.host, &mount.guest, &mut files)?;
+    }
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(ComponentInspection {
+        id: c.id.clone(),
+        description: c.description.clone(),
+        source,
+        digest: format!("sha256:{:x}", Sha256::digest(&module)),
+        size: module.len() as u64,
+        trigger: trigger.map(serde_json::to_value).transpose()?,
+        environment: c.wasm.environment.clone().into_iter().collect(),
+        allowed_http_hosts: c.wasm.allowed_http_hosts.clone(),
+        key_value_stores: c.wasm.key_value_stores.clone(),
+        files,
+    })
+}
+
+/// Lists the files under a mounted directory, by their path in the guest.
+fn collect_files(host: &Path, guest: &str, files: &mut Vec<MountedFile>) -> Result<()> {
+    let entries =
+        std::fs::read_dir(host).with_context(|| format!("Cannot read {}", host.display()))?;
+    for entry in entries {
+        let entry = entry?;
+        let path = format!(
+            "{}/{}",
+            guest.trim_end_matches('/'),
+            entry.file_name().to_string_lossy()
+        );
+        let metadata = std::fs::metadata(entry.path())?;
+        if metadata.is_dir() {
+            collect_files(&entry.path(), &path, files)?;
+        } else {
+            files.push(MountedFile {
+                path,
+                size: metadata.len(),
+            });
+        }
+    }
+    Ok(())
+}
+
+fn print_inspection(app: &Inspection) {
+    println!("{} {}", app.name, app.version);
+    if let Some(description) = &app.description {
+        println!("{}", description);
+    }
+    println!("Origin: {}", app.origin);
+    if !app.authors.is_empty() {
+        println!("Authors: {}", app.authors.join(", "));
+    }
+    println!("Trigger: {}", app.trigger);
+
+    for c in &app.components {
+        println!();
+        println!("Component {}", c.id);
+        if let Some(description) = &c.description {
+            println!("  Description: {}", description);
+        }
+        println!("  Source: {}", c.source);
+        println!("  Digest: {} ({} bytes)", c.digest, c.size);
+        if let Some(trigger) = &c.trigger {
+            println!("  Trigger: {}", trigger);
+        }
+        if !c.allowed_http_hosts.is_empty() {
+            println!("  Allowed HTTP hosts: {}", c.allowed_http_hosts.join(", "));
+        }
+        if !c.key_value_stores.is_empty() {
+            println!("  Key-value stores: {}", c.key_value_stores.join(", "));
+        }
+        if !c.environment.is_empty() {
+            println!("  Environment:");
+            for (name, value) in &c.environment {
+                println!("    {}={}", name, value);
+            }
+        }
+        if !c.files.is_empty() {
+            let bytes: u64 = c.files.iter().map(|f| f.size).sum();
+            println!("  Files: {} ({} bytes)", c.files.len(), bytes);
+            for file in &c.files {
+                println!("    {} ({} bytes)", file.path, file.size);
+            }
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use std::path::PathBuf;
+
+use anyhow::{bail, Result};
+use chrono::{TimeZone, Utc};
+use clap::{Parser, Subcommand};
+use comfy_table::Table;
+use spin_tasks::{JobStore, JOBS_FILE};
+
+use crate::opts::*;
+
+/// Commands for inspecting the delayed tasks and recurring jobs of an application.
+#[derive(Subcommand, Debug)]
+pub enum JobsCommands {
+    /// List the jobs of the application, in the order they next run.
+    List(List),
+
+    /// Cancel a job, so that it does not run again.
+    Cancel(Cancel),
+}
+
+impl JobsCommands {
+    pub async fn run(self) -> Result<()> {
+        match self {
+            Self::List(cmd) => cmd.run().await,
+            Self::Cancel(cmd) => cmd.run().await,
+        }
+    }
+}
+
+/// The job store of an application.
+#[derive(Parser, Debug)]
+pub struct StoreOpts {
+    /// Path to spin.toml
+    #[clap(
+        name = APP_CONFIG_FILE_OPT,
+        short = 'f',
+        long = "file",
+    )]
+    pub app: Option<PathBuf>,
+
+    /// The file jobs are stored in, if the runtime configuration sets one.
+    #[clap(long = "store", conflicts_with = APP_CONFIG_FILE_OPT)]
+    pub store: Option<PathBuf>,
+}
+
+impl StoreOpts {
+    fn job_store(&self) -> Result<JobStore> {
+        let path = match &self.store {
+            Some(path) => path.clone(),
+            None => {
+                let manifest_file = self
+                    .app
+                    .as_deref()
+                    .unwrap_or_else(|| DEFAULT_MANIFEST_FILE.as_ref());
+                crate::app_dir(manifest_file)?.join(JOBS_FILE)
+            }
+        };
+        Ok(JobStore::file(path))
+    }
+}
+
+/// List the jobs of the application.
+#[derive(Parser, Debug)]
+pub struct List {
+    #[clap(flatten)]
+    pub store: StoreOpts,
+}
+
+impl List {
+    pub async fn run(self) -> Result<()> {
+        let jobs = self.store.job_store()?.list()?;
+        if jobs.is_empty() {
+            println!("No jobs");
+            return Ok(());
+        }
+
+        let mut table = Table::new();
+        table.set_header(vec!["ID", "Component", "Next run", "Schedule", "Payload"]);
+        table.load_preset(comfy_table::presets::ASCII_BORDERS_ONLY_CONDENSED);
+        for job in jobs {
+            let next_run = Utc
+                .timestamp_millis_opt(job.next_run_ms as i64)
+                .single()
+                .map(|t| t.to_rfc3339())
+                .unwrap_or_default();
+            table.add_row(vec![
+                job.id,
+                job.component,
+                next_run,
+                job.cron.unwrap_or_else(|| "once".to_owned()),
+                format!("{} bytes", job.payload.len()),
+            ]);
+        }
+        println!("{}", table);
+        Ok(())
+    }
+}
+
+/// Cancel a job of the application.
+#[derive(Parser, Debug)]
+pub struct Cancel {
+    #[clap(flatten)]
+    pub store: StoreOpts,
+
+    /// ID of the job, as listed by `spin jobs list`.
+    pub id: String,
+}
+
+impl Cancel {
+    pub async fn run(self) -> Result<()> {
+        match self.store.job_store()?.remove(&self.id)? {
+            Some(job) => {
+                println!("Cancelled job {} of component {}", job.id, job.component);
+                Ok(())
+            }
+            None => bail!("No job has ID {}", self.id),
+        }
+    }
+}
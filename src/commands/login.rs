@@ -0,0 +1,365 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+
+use crate::{commands::deploy::hippo_token, oidc::OidcProvider, opts::*};
+
+/// The file, in the Spin config directory, holding cached logins.
+const LOGINS_FILE: &str = "logins.toml";
+
+/// The client ID Spin logs in to OpenID Connect issuers as, unless another is
+/// given.
+const DEFAULT_OIDC_CLIENT_ID: &str = "spin";
+
+const OIDC_ISSUER_OPT: &str = "OIDC_ISSUER";
+
+/// Log in to Hippo, caching the token for later deploys
+#[derive(Parser, Debug)]
+#[clap(about = "Log in to a Hippo server and cache the credentials for deploying")]
+pub struct LoginCommand {
+    /// URL of hippo server
+    #[clap(
+        name = HIPPO_SERVER_URL_OPT,
+        long = "hippo-server",
+        env = HIPPO_URL_ENV,
+    )]
+    pub hippo_server_url: String,
+
+    /// Hippo username
+    #[clap(
+        name = "HIPPO_USERNAME",
+        long = "hippo-username",
+        env = "HIPPO_USERNAME",
+        required_unless_present = OIDC_ISSUER_OPT,
+        conflicts_with = OIDC_ISSUER_OPT
+    )]
+    pub hippo_username: Option<String>,
+
+    /// Hippo password
+    #[clap(
+        name = "HIPPO_PASSWORD",
+        long = "hippo-password",
+        env = "HIPPO_PASSWORD",
+        required_unless_present = OIDC_ISSUER_OPT,
+        conflicts_with = OIDC_ISSUER_OPT
+    )]
+    pub hippo_password: Option<String>,
+
+    /// OpenID Connect issuer to log in with, for Hippo servers fronted by
+    /// single sign-on
+    #[clap(name = OIDC_ISSUER_OPT, long = "oidc-issuer", env = "SPIN_OIDC_ISSUER")]
+    pub oidc_issuer: Option<String>,
+
+    /// Client ID Spin is registered as with the OpenID Connect issuer
+    /// (defaults to "spin")
+    #[clap(
+        name = "OIDC_CLIENT_ID",
+        long = "oidc-client-id",
+        env = "SPIN_OIDC_CLIENT_ID",
+        requires = OIDC_ISSUER_OPT
+    )]
+    pub oidc_client_id: Option<String>,
+
+    /// Scopes to request from the OpenID Connect issuer, besides "openid"
+    /// and "offline_access"
+    #[clap(
+        name = "OIDC_SCOPE",
+        long = "oidc-scope",
+        multiple_occurrences = true,
+        requires = OIDC_ISSUER_OPT
+    )]
+    pub oidc_scopes: Vec<String>,
+
+    /// URL of bindle server, cached for deploying to this Hippo server
+    #[clap(
+        name = BINDLE_SERVER_URL_OPT,
+        long = "bindle-server",
+        env = BINDLE_URL_ENV,
+    )]
+    pub bindle_server_url: Option<String>,
+
+    /// Basic http auth username for the bindle server
+    #[clap(
+        name = BINDLE_USERNAME,
+        long = "bindle-username",
+        env = BINDLE_USERNAME,
+        requires = BINDLE_PASSWORD
+    )]
+    pub bindle_username: Option<String>,
+
+    /// Basic http auth password for the bindle server
+    #[clap(
+        name = BINDLE_PASSWORD,
+        long = "bindle-password",
+        env = BINDLE_PASSWORD,
+        requires = BINDLE_USERNAME
+    )]
+    pub bindle_password: Option<String>,
+
+    /// Ignore server certificate errors from bindle and hippo
+    #[clap(
+        name = INSECURE_OPT,
+        short = 'k',
+        long = "insecure",
+        takes_value = false,
+    )]
+    pub insecure: bool,
+}
+
+impl LoginCommand {
+    pub async fn run(self) -> Result<()> {
+        spin_loader::offline::ensure_online("log in to Hippo")?;
+        let (hippo_username, token, expiration, oidc) = match &self.oidc_issuer {
+            Some(issuer) => self.oidc_login(issuer).await?,
+            None => {
+                // Clap requires both when there is no issuer.
+                let username = self.hippo_username.clone().unwrap_or_default();
+                let password = self.hippo_password.as_deref().unwrap_or_default();
+                let (token, expiration) =
+                    hippo_token(&self.hippo_server_url, self.insecure, &username, password).await?;
+                (username, token, expiration, None)
+            }
+        };
+
+        let login = Login {
+            hippo_username: hippo_username.clone(),
+            token,
+            expiration,
+            bindle_server_url: self.bindle_server_url,
+            bindle_username: self.bindle_username,
+            bindle_password: self.bindle_password,
+            insecure: self.insecure,
+            oidc,
+        };
+        let path = logins_path()?;
+        let mut logins = Logins::load(&path).await?;
+        logins.current = Some(self.hippo_server_url.clone());
+        logins.servers.insert(self.hippo_server_url.clone(), login);
+        logins.save(&path).await?;
+
+        if hippo_username.is_empty() {
+            println!("Logged in to {}", self.hippo_server_url);
+        } else {
+            println!(
+                "Logged in to {} as {}",
+                self.hippo_server_url, hippo_username
+            );
+        }
+        Ok(())
+    }
+
+    /// Logs in through the OpenID Connect issuer with the device
+    /// authorization grant, returning the name of the user, the access token
+    /// and when it expires, and what is needed to refresh it.
+    async fn oidc_login(
+        &self,
+        issuer: &str,
+    ) -> Result<(String, String, Option<String>, Option<OidcLogin>)> {
+        let client_id = self
+            .oidc_client_id
+            .as_deref()
+            .unwrap_or(DEFAULT_OIDC_CLIENT_ID);
+        let provider = OidcProvider::discover(issuer, client_id, self.insecure).await?;
+        let tokens = provider.device_login(&self.oidc_scopes).await?;
+        let username = provider.username(&tokens).await.unwrap_or_default();
+        let expiration = tokens.expiration();
+        let oidc = OidcLogin {
+            issuer: issuer.to_owned(),
+            client_id: client_id.to_owned(),
+            refresh_token: tokens.refresh_token,
+        };
+        Ok((username, tokens.access_token, expiration, Some(oidc)))
+    }
+}
+
+/// The logins cached by `spin login`.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub(crate) struct Logins {
+    /// The Hippo server deployed to when none is given.
+    pub current: Option<String>,
+    /// Logins by Hippo server URL.
+    #[serde(default)]
+    pub servers: BTreeMap<String, Login>,
+}
+
+/// A cached login to a Hippo server, with the bindle server used with it.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize)]
+pub(crate) struct Login {
+    pub hippo_username: String,
+    pub token: String,
+    /// When the token expires, in RFC 3339 format, if Hippo said.
+    pub expiration: Option<String>,
+    pub bindle_server_url: Option<String>,
+    pub bindle_username: Option<String>,
+    pub bindle_password: Option<String>,
+    #[serde(default)]
+    pub insecure: bool,
+    /// The OpenID Connect issuer the token was issued by, if the login was
+    /// through single sign-on rather than with a Hippo password.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub oidc: Option<OidcLogin>,
+}
+
+/// A login through an OpenID Connect issuer, with what is needed to refresh
+/// its token.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize)]
+pub(crate) struct OidcLogin {
+    pub issuer: String,
+    pub client_id: String,
+    pub refresh_token: Option<String>,
+}
+
+impl Login {
+    /// Whether the token has expired, or expires in the next minute.
+    pub fn is_expired(&self) -> bool {
+        match self.expiration.as_deref().map(DateTime::parse_from_rfc3339) {
+            Some(Ok(expiration)) => expiration < Utc::now() + chrono::Duration::minutes(1),
+            _ => false,
+        }
+    }
+
+    /// Whether the token can be refreshed without asking the user to log in
+    /// again.
+    pub fn is_refreshable(&self) -> bool {
+        matches!(&self.oidc, Some(oidc) if oidc.refresh_token.is_some())
+    }
+}
+
+impl Logins {
+    /// Loads the cached logins, or none if nobody has logged in.
+    pub async fn load(path: &Path) -> Result<Self> {
+        match tokio::fs::read_to_string(path).await {
+            Ok(contents) => toml::from_str(&contents)
+                .with_context(|| format!("Cannot parse logins file {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e).with_context(|| format!("Cannot read logins file {}", path.display())),
+        }
+    }
+
+    /// Saves the logins, readable only by the user as they hold tokens.
+    pub async fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+        // Written aside and renamed, so that the tokens are never readable
+        // by others, even while they are written.
+        let temp_path = path.with_extension("toml.tmp");
+        let contents = toml::to_string(self)?;
+        let write = async {
+            match tokio::fs::remove_file(&temp_path).await {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+                _ => {}
+            }
+            let mut options = tokio::fs::OpenOptions::new();
+            options.write(true).create_new(true);
+            #[cfg(unix)]
+            options.mode(0o600);
+            let mut file = options.open(&temp_path).await?;
+            file.write_all(contents.as_bytes()).await?;
+            file.sync_all().await?;
+            tokio::fs::rename(&temp_path, path).await
+        };
+        write
+            .await
+            .with_context(|| format!("Cannot write logins file {}", path.display()))
+    }
+
+    /// The login to the given Hippo server, or to the current one if none
+    /// is given, with the URL of its server.
+    pub fn get(&self, hippo_server_url: Option<&str>) -> Option<(&str, &Login)> {
+        let url = hippo_server_url.or(self.current.as_deref())?;
+        self.servers
+            .get_key_value(url)
+            .map(|(url, login)| (url.as_str(), login))
+    }
+}
+
+/// The path of the logins file.
+pub(crate) fn logins_path() -> Result<PathBuf> {
+    let dir = dirs::config_dir().ok_or_else(|| anyhow!("Cannot find the config directory"))?;
+    Ok(dir.join("spin").join(LOGINS_FILE))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn login(expiration: Option<&str>) -> Login {
+        Login {
+            hippo_username: "alice".to_owned(),
+            token: "token".to_owned(),
+            expiration: expiration.map(str::to_owned),
+            bindle_server_url: Some("https://bindle.example.com/v1".to_owned()),
+            bindle_username: None,
+            bindle_password: None,
+            insecure: false,
+            oidc: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_logins_round_trip() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("spin").join(LOGINS_FILE);
+        assert!(Logins::load(&path).await?.get(None).is_none());
+
+        let logins = Logins {
+            current: Some("https://hippo.example.com".to_owned()),
+            servers: [("https://hippo.example.com".to_owned(), login(None))]
+                .into_iter()
+                .collect(),
+        };
+        logins.save(&path).await?;
+
+        let loaded = Logins::load(&path).await?;
+        let (url, current) = loaded.get(None).unwrap();
+        assert_eq!(url, "https://hippo.example.com");
+        assert_eq!(*current, login(None));
+        assert!(loaded.get(Some("https://other.example.com")).is_none());
+
+        // Saving again replaces the file, which only the user can read.
+        logins.save(&path).await?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path)?.permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_login_expiry() {
+        assert!(!login(None).is_expired());
+        assert!(login(Some("2000-01-01T00:00:00Z")).is_expired());
+        assert!(!login(Some("2999-01-01T00:00:00Z")).is_expired());
+    }
+
+    #[test]
+    fn test_oidc_login_round_trip() -> Result<()> {
+        // Logins cached before OpenID Connect support still load.
+        let password_login: Login = toml::from_str(&toml::to_string(&login(None))?)?;
+        assert!(password_login.oidc.is_none());
+        assert!(!password_login.is_refreshable());
+
+        let oidc_login = Login {
+            oidc: Some(OidcLogin {
+                issuer: "https://sso.example.com".to_owned(),
+                client_id: "spin".to_owned(),
+                refresh_token: Some("refresh".to_owned()),
+            }),
+            ..login(Some("2000-01-01T00:00:00Z"))
+        };
+        let loaded: Login = toml::from_str(&toml::to_string(&oidc_login)?)?;
+        assert_eq!(loaded, oidc_login);
+        assert!(loaded.is_expired() && loaded.is_refreshable());
+        Ok(())
+    }
+}
@@ -0,0 +1,467 @@
+use std::{
+    fs::File,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{DateTime, Utc};
+use clap::Parser;
+use hippo::{Client, ConnectionInfo};
+use serde::Deserialize;
+use spin_loader::local::{config::RawAppManifestAnyVersion, raw_manifest_from_file};
+
+use crate::{
+    commands::{
+        deploy::{
+            deployed_app_name, get_app_id, get_channel_id, HippoAuth, SPIN_DEPLOY_CHANNEL_NAME,
+        },
+        login::{logins_path, Login, Logins},
+    },
+    opts::*,
+};
+
+/// How often followed log files are checked for new output.
+const FOLLOW_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How often followed remote logs are fetched.
+const REMOTE_FOLLOW_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Print the output of a component.
+#[derive(Parser, Debug)]
+#[clap(about = "Print the output of a component of a running Spin application")]
+pub struct LogsCommand {
+    /// Path to spin.toml.
+    #[clap(
+            name = APP_CONFIG_FILE_OPT,
+            short = 'f',
+            long = "file",
+        )]
+    pub app: Option<PathBuf>,
+
+    /// Log directory passed to `spin up`, if any.
+    #[clap(short = 'L', long = "log-dir")]
+    pub log_dir: Option<PathBuf>,
+
+    /// Print the standard error of the component instead of its standard output.
+    #[clap(long = "stderr")]
+    pub stderr: bool,
+
+    /// Keep printing output as it is written, across log file rotations.
+    #[clap(long = "follow")]
+    pub follow: bool,
+
+    /// Print the logs of the application deployed to Hippo, rather than of
+    /// the local application
+    #[clap(long = "remote")]
+    pub remote: bool,
+
+    /// URL of hippo server (defaults to the one last logged in to with
+    /// `spin login`)
+    #[clap(
+        name = HIPPO_SERVER_URL_OPT,
+        long = "hippo-server",
+        env = HIPPO_URL_ENV,
+    )]
+    pub hippo_server_url: Option<String>,
+
+    /// Hippo username (not needed after `spin login`)
+    #[clap(
+        name = "HIPPO_USERNAME",
+        long = "hippo-username",
+        env = "HIPPO_USERNAME",
+        requires = "HIPPO_PASSWORD"
+    )]
+    pub hippo_username: Option<String>,
+
+    /// Hippo password (not needed after `spin login`)
+    #[clap(
+        name = "HIPPO_PASSWORD",
+        long = "hippo-password",
+        env = "HIPPO_PASSWORD",
+        requires = "HIPPO_USERNAME"
+    )]
+    pub hippo_password: Option<String>,
+
+    /// Hippo API key, used instead of logging in
+    #[clap(
+        name = "HIPPO_API_KEY",
+        long = "hippo-api-key",
+        env = "HIPPO_API_KEY",
+        conflicts_with_all = &["HIPPO_USERNAME", "HIPPO_PASSWORD"]
+    )]
+    pub hippo_api_key: Option<String>,
+
+    /// Ignore server certificate errors from hippo
+    #[clap(
+        name = INSECURE_OPT,
+        short = 'k',
+        long = "insecure",
+        takes_value = false,
+    )]
+    pub insecure: bool,
+
+    /// Name the application was deployed as, if not the name in spin.toml
+    #[clap(long = "app-name", requires = "remote")]
+    pub app_name: Option<String>,
+
+    /// Prefix the application name was deployed with
+    #[clap(long = "name-prefix", requires = "remote")]
+    pub name_prefix: Option<String>,
+
+    /// Name of the Hippo channel whose logs to print (defaults to spin-deploy)
+    #[clap(long = "channel", env = "SPIN_DEPLOY_CHANNEL", requires = "remote")]
+    pub channel: Option<String>,
+
+    /// Only print remote log lines written in the last SECONDS seconds
+    #[clap(long = "since", value_name = "SECONDS", requires = "remote")]
+    pub since: Option<u64>,
+
+    /// ID of the component. Remote logs are filtered to the lines the
+    /// component wrote.
+    pub component: Option<String>,
+}
+
+impl LogsCommand {
+    pub async fn run(self) -> Result<()> {
+        let manifest_file = self
+            .app
+            .as_deref()
+            .unwrap_or_else(|| DEFAULT_MANIFEST_FILE.as_ref());
+        let RawAppManifestAnyVersion::V1(app) = raw_manifest_from_file(&manifest_file).await?;
+        if let Some(component) = &self.component {
+            if !app.components.iter().any(|c| &c.id == component) {
+                bail!("Unknown component {}", component);
+            }
+        }
+
+        if self.remote {
+            let name =
+                deployed_app_name(&app, self.app_name.as_deref(), self.name_prefix.as_deref());
+            return self.run_remote(&name).await;
+        }
+
+        let component = self
+            .component
+            .as_deref()
+            .ok_or_else(|| anyhow!("A component is required to print local logs"))?;
+        let log_dir = spin_engine::logs::log_dir(&app.info.name, self.log_dir.as_deref());
+        let stream = if self.stderr { "stderr" } else { "stdout" };
+        let path = spin_engine::logs::log_file(&log_dir, component, stream);
+
+        let mut file = open(&path)?;
+        let mut stdout = std::io::stdout();
+        copy(&mut file, &mut stdout)?;
+        if !self.follow {
+            return Ok(());
+        }
+
+        loop {
+            tokio::time::sleep(FOLLOW_INTERVAL).await;
+            copy(&mut file, &mut stdout)?;
+            if is_rotated(&file, &path) {
+                // Output may have been written to the rotated file after the
+                // last read, so drain it before switching to the new file.
+                copy(&mut file, &mut stdout)?;
+                file = open(&path)?;
+                copy(&mut file, &mut stdout)?;
+            }
+        }
+    }
+
+    async fn run_remote(&self, name: &str) -> Result<()> {
+        spin_loader::offline::ensure_online("fetch logs from Hippo")?;
+        let logins = match logins_path() {
+            Ok(path) => Logins::load(&path).await?,
+            Err(_) => Logins::default(),
+        };
+        let (url, login) = hippo_server(&logins, self.hippo_server_url.as_deref())?;
+        let insecure = self.insecure || login.map_or(false, |l| l.insecure);
+        // The logs are fetched outside the Hippo client, so they need the token.
+        let token = HippoAuth {
+            url: &url,
+            endpoint: &url,
+            insecure,
+            api_key: self.hippo_api_key.as_deref(),
+            username: self.hippo_username.as_deref(),
+            password: self.hippo_password.as_deref(),
+            login,
+        }
+        .token()
+        .await?;
+        let hippo_client = Client::new(ConnectionInfo {
+            url: url.clone(),
+            danger_accept_invalid_certs: insecure,
+            api_key: Some(token.clone()),
+        });
+
+        let channel = self.channel.as_deref().unwrap_or(SPIN_DEPLOY_CHANNEL_NAME);
+        let app_id = get_app_id(&hippo_client, name)
+            .await?
+            .ok_or_else(|| anyhow!("Application {} is not deployed to {}", name, url))?;
+        let channel_id = get_channel_id(&hippo_client, app_id, channel)
+            .await?
+            .ok_or_else(|| anyhow!("Application {} has no {} channel", name, channel))?;
+
+        let logs_url = api_url(&url, &format!("api/channel/{}/logs", channel_id))?;
+        let client = reqwest::Client::builder()
+            .danger_accept_invalid_certs(insecure)
+            .build()?;
+        let since = self
+            .since
+            .map(|secs| Utc::now() - chrono::Duration::seconds(secs as i64));
+        let mut filter = LineFilter::new(self.component.as_deref(), since);
+
+        let mut previous = vec![];
+        loop {
+            let lines = fetch_remote_logs(&client, &logs_url, &token).await?;
+            for line in new_lines(&previous, &lines) {
+                if filter.matches(line) {
+                    println!("{}", line);
+                }
+            }
+            previous = lines;
+            if !self.follow {
+                return Ok(());
+            }
+            tokio::time::sleep(REMOTE_FOLLOW_INTERVAL).await;
+        }
+    }
+}
+
+/// The Hippo server to fetch logs from, and the login cached for it: the
+/// given server, or else the one last logged in to.
+fn hippo_server<'a>(
+    logins: &'a Logins,
+    hippo_server_url: Option<&str>,
+) -> Result<(String, Option<&'a Login>)> {
+    match (logins.get(hippo_server_url), hippo_server_url) {
+        (Some((url, login)), _) => Ok((url.to_owned(), Some(login))),
+        (None, Some(url)) => Ok((url.to_owned(), None)),
+        (None, None) => bail!("No Hippo server given: pass --hippo-server, or run `spin login`"),
+    }
+}
+
+/// The URL of a Hippo API path, under the path of the Hippo URL if it has one.
+fn api_url(hippo_url: &str, path: &str) -> Result<url::Url> {
+    let mut url = url::Url::parse(hippo_url)
+        .with_context(|| format!("Invalid Hippo server URL {}", hippo_url))?;
+    if !url.path().ends_with('/') {
+        let base = format!("{}/", url.path());
+        url.set_path(&base);
+    }
+    Ok(url.join(path)?)
+}
+
+/// The lines of the log Hippo returned that were not in the lines it returned
+/// before. Hippo returns the recent lines of the log each time, so the new
+/// lines follow the end of the previous lines; if those are not found, as
+/// when more lines were written than Hippo returns, all lines are new.
+fn new_lines<'a>(previous: &[String], lines: &'a [String]) -> &'a [String] {
+    let seen = (1..=previous.len().min(lines.len()))
+        .rev()
+        .find(|&len| previous[previous.len() - len..] == lines[..len])
+        .unwrap_or(0);
+    &lines[seen..]
+}
+
+#[derive(Deserialize)]
+struct ChannelLogs {
+    logs: Vec<String>,
+}
+
+async fn fetch_remote_logs(
+    client: &reqwest::Client,
+    url: &url::Url,
+    token: &str,
+) -> Result<Vec<String>> {
+    let response = client
+        .get(url.clone())
+        .bearer_auth(token)
+        .send()
+        .await?
+        .error_for_status()
+        .context("Hippo does not support fetching the logs of this channel")?;
+    let logs: ChannelLogs = serde_json::from_slice(&response.bytes().await?)
+        .context("Cannot parse logs returned by Hippo")?;
+    Ok(logs.logs)
+}
+
+/// Filters remote log lines, of the form `<timestamp> <level> <component>:
+/// <message>`, by component and time.
+struct LineFilter<'a> {
+    component: Option<&'a str>,
+    since: Option<DateTime<Utc>>,
+    // Lines without a timestamp continue the previous line, so are kept if
+    // it was.
+    matched: bool,
+}
+
+impl<'a> LineFilter<'a> {
+    fn new(component: Option<&'a str>, since: Option<DateTime<Utc>>) -> Self {
+        Self {
+            component,
+            since,
+            matched: component.is_none() && since.is_none(),
+        }
+    }
+
+    fn matches(&mut self, line: &str) -> bool {
+        let mut fields = line.split_whitespace();
+        let time = fields
+            .next()
+            .and_then(|timestamp| DateTime::parse_from_rfc3339(timestamp).ok());
+        if let Some(time) = time {
+            let in_range = self.since.map_or(true, |since| time >= since);
+            let component = fields.nth(1).and_then(|c| c.strip_suffix(':'));
+            self.matched = in_range && self.component.map_or(true, |c| component == Some(c));
+        }
+        self.matched
+    }
+}
+
+fn open(path: &Path) -> Result<File> {
+    File::open(path).with_context(|| format!("Cannot open log file {}", path.display()))
+}
+
+fn copy(file: &mut File, out: &mut impl Write) -> Result<()> {
+    let mut buf = vec![];
+    file.read_to_end(&mut buf)?;
+    out.write_all(&buf)?;
+    out.flush()?;
+    Ok(())
+}
+
+/// Whether the open file is no longer the one at the given path. A missing
+/// path means the file was rotated, but no output was written since.
+#[cfg(unix)]
+fn is_rotated(file: &File, path: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (file.metadata(), std::fs::metadata(path)) {
+        (Ok(open), Ok(current)) => open.ino() != current.ino() || open.dev() != current.dev(),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn is_rotated(file: &File, path: &Path) -> bool {
+    // Without file identities, a file at the path shorter than the open one
+    // is taken to be a new file.
+    match (file.metadata(), std::fs::metadata(path)) {
+        (Ok(open), Ok(current)) => current.len() < open.len(),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line_filter() {
+        let since = DateTime::parse_from_rfc3339("2022-06-01T12:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let mut filter = LineFilter::new(Some("hello"), Some(since));
+        assert!(!filter.matches("  continued before the first line"));
+        assert!(!filter.matches("2022-06-01T11:59:59Z  INFO hello: old"));
+        assert!(!filter.matches("  continued old"));
+        assert!(filter.matches("2022-06-01T12:00:01Z  INFO hello: new"));
+        assert!(filter.matches("  continued new"));
+        assert!(!filter.matches("2022-06-01T12:00:02Z  INFO goodbye: hello: new"));
+        assert!(!filter.matches("2022-06-01T12:00:03Z  INFO hello-world: new"));
+
+        let mut filter = LineFilter::new(None, None);
+        assert!(filter.matches("  continued before the first line"));
+        assert!(filter.matches("2022-06-01T12:00:01Z  INFO hello: new"));
+    }
+
+    #[test]
+    fn test_new_lines() {
+        let lines = |lines: &[&str]| lines.iter().map(|l| l.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(new_lines(&[], &lines(&["a", "b"])), lines(&["a", "b"]));
+        assert_eq!(
+            new_lines(&lines(&["a", "b"]), &lines(&["a", "b", "c"])),
+            lines(&["c"])
+        );
+        // Once the window Hippo returns is full, it moves along the log.
+        assert_eq!(
+            new_lines(&lines(&["a", "b", "c"]), &lines(&["c", "d", "e"])),
+            lines(&["d", "e"])
+        );
+        assert_eq!(
+            new_lines(&lines(&["a", "b", "a"]), &lines(&["b", "a", "a"])),
+            lines(&["a"])
+        );
+        assert!(new_lines(&lines(&["a", "b"]), &lines(&["a", "b"])).is_empty());
+        assert_eq!(
+            new_lines(&lines(&["a", "b"]), &lines(&["c", "d"])),
+            lines(&["c", "d"])
+        );
+    }
+
+    #[test]
+    fn test_api_url() -> Result<()> {
+        assert_eq!(
+            api_url("https://hippo.example.com", "api/channel/1/logs")?.as_str(),
+            "https://hippo.example.com/api/channel/1/logs"
+        );
+        assert_eq!(
+            api_url("https://example.com/hippo", "api/channel/1/logs")?.as_str(),
+            "https://example.com/hippo/api/channel/1/logs"
+        );
+        assert_eq!(
+            api_url("https://example.com/hippo/", "api/channel/1/logs")?.as_str(),
+            "https://example.com/hippo/api/channel/1/logs"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_remote_options() {
+        let command =
+            LogsCommand::try_parse_from(["logs", "--remote", "--channel", "staging", "hello"])
+                .unwrap();
+        assert!(command.remote);
+        assert_eq!(command.channel.as_deref(), Some("staging"));
+        assert_eq!(command.component.as_deref(), Some("hello"));
+
+        // Options of remote logs are rejected for local logs.
+        for option in ["--channel=staging", "--since=60", "--app-name=hello"] {
+            assert!(LogsCommand::try_parse_from(["logs", option]).is_err());
+        }
+    }
+
+    #[test]
+    fn test_hippo_server() -> Result<()> {
+        let login = Login {
+            hippo_username: "alice".to_owned(),
+            token: "token".to_owned(),
+            expiration: None,
+            bindle_server_url: None,
+            bindle_username: None,
+            bindle_password: None,
+            insecure: true,
+            oidc: None,
+        };
+        let logins = Logins {
+            current: Some("https://hippo.example.com".to_owned()),
+            servers: [("https://hippo.example.com".to_owned(), login.clone())]
+                .into_iter()
+                .collect(),
+        };
+
+        let (url, cached) = hippo_server(&logins, None)?;
+        assert_eq!(url, "https://hippo.example.com");
+        assert_eq!(cached, Some(&login));
+        // Other servers are used without the cached login.
+        let (url, cached) = hippo_server(&logins, Some("https://other.example.com"))?;
+        assert_eq!(url, "https://other.example.com");
+        assert!(cached.is_none());
+
+        assert!(hippo_server(&Logins::default(), None).is_err());
+        Ok(())
+    }
+}
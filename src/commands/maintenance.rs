@@ -0,0 +1,42 @@
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use clap::{Parser, Subcommand};
+
+/// Commands for maintaining Spin and the tools around it.
+#[derive(Subcommand, Debug)]
+pub enum MaintenanceCommands {
+    /// Print the JSON Schema of spin.toml, for editors and linters to check
+    /// manifests with.
+    GenerateSchema(GenerateSchema),
+}
+
+impl MaintenanceCommands {
+    pub async fn run(self) -> Result<()> {
+        match self {
+            Self::GenerateSchema(cmd) => cmd.run().await,
+        }
+    }
+}
+
+/// Print the JSON Schema of spin.toml.
+#[derive(Parser, Debug)]
+pub struct GenerateSchema {
+    /// Write the schema to the file rather than printing it.
+    #[clap(short = 'o', long = "output")]
+    pub output: Option<PathBuf>,
+}
+
+impl GenerateSchema {
+    pub async fn run(self) -> Result<()> {
+        let schema = spin_loader::local::config::json_schema();
+        let json = serde_json::to_string_pretty(&schema)?;
+        match &self.output {
+            Some(path) => tokio::fs::write(path, json + "\n")
+                .await
+                .with_context(|| format!("Cannot write the schema to {}", path.display()))?,
+            None => println!("{}", json),
+        }
+        Ok(())
+    }
+}
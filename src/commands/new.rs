@@ -4,16 +4,45 @@ use std::{
     str::FromStr,
 };
 
-use anyhow::{anyhow, Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use clap::Parser;
 use path_absolutize::Absolutize;
 use tokio::{fs::File, io::AsyncReadExt};
 
-use spin_templates::{RunOptions, TemplateManager};
+use spin_templates::{RunOptions, TemplateManager, TemplateVariantInfo};
+
+use crate::opts::{APP_CONFIG_FILE_OPT, DEFAULT_MANIFEST_FILE};
 
 /// Scaffold a new application or component based on a template.
 #[derive(Parser, Debug)]
 pub struct NewCommand {
+    #[clap(flatten)]
+    pub options: TemplateNewCommandCore,
+}
+
+/// Add a component to an existing application, based on a template.
+///
+/// The files of the component are generated in a directory of the
+/// application, and its entry is appended to the `spin.toml` of the
+/// application.
+#[derive(Parser, Debug)]
+pub struct AddCommand {
+    #[clap(flatten)]
+    pub options: TemplateNewCommandCore,
+
+    /// Path to the spin.toml of the application to add the component to.
+    #[clap(
+        name = APP_CONFIG_FILE_OPT,
+        short = 'f',
+        long = "file",
+        default_value = DEFAULT_MANIFEST_FILE
+    )]
+    pub app: PathBuf,
+}
+
+/// The options shared by `spin new` and `spin add`.
+#[derive(Parser, Debug)]
+pub struct TemplateNewCommandCore {
     /// The template from which to create the new application or component. Run `spin templates list` to see available options.
     pub template_id: String,
 
@@ -21,7 +50,8 @@ pub struct NewCommand {
     pub name: String,
 
     /// The directory in which to create the new application or component.
-    /// The default is the name argument.
+    /// The default is the name argument, in the directory of the application
+    /// for a component.
     #[clap(short = 'o', long = "output")]
     pub output_path: Option<PathBuf>,
 
@@ -39,19 +69,79 @@ pub struct NewCommand {
     /// by accepting the defaults if available on the template
     #[clap(long = "accept-defaults", takes_value = false)]
     pub accept_defaults: bool,
+
+    /// Fail instead of prompting for parameter values that were not
+    /// provided. This is the default when standard input is not a terminal.
+    #[clap(long = "non-interactive", takes_value = false)]
+    pub non_interactive: bool,
+
+    /// Run the command the template declares to complete the setup, such as
+    /// `cargo fmt` or `npm install`, without asking for confirmation. It is
+    /// not run without this option when standard input is not a terminal.
+    #[clap(long = "accept-hooks", takes_value = false)]
+    pub accept_hooks: bool,
 }
 
 impl NewCommand {
     pub async fn run(&self) -> Result<()> {
+        self.options.run(TemplateVariantInfo::NewApplication).await
+    }
+}
+
+impl AddCommand {
+    pub async fn run(&self) -> Result<()> {
+        if !self.app.exists() {
+            bail!(
+                "No application manifest at {}: run `spin add` in the directory of an application, or pass its spin.toml with --file",
+                self.app.display()
+            );
+        }
+        let variant = TemplateVariantInfo::AddComponent {
+            manifest_path: self.app.clone(),
+        };
+        let original = tokio::fs::read_to_string(&self.app)
+            .await
+            .with_context(|| format!("Cannot read {}", self.app.display()))?;
+        self.options.run(variant).await?;
+        if tokio::fs::read_to_string(&self.app).await? == original {
+            // The run was cancelled at a prompt.
+            return Ok(());
+        }
+        // The template checks the entry it adds, but not how it fits with the
+        // rest of the application.
+        spin_loader::local::raw_manifest_from_file(&self.app)
+            .await
+            .with_context(|| {
+                format!(
+                    "Added component {}, but {} is no longer a valid manifest",
+                    self.options.name,
+                    self.app.display()
+                )
+            })?;
+        println!(
+            "Added component {} to {}",
+            self.options.name,
+            self.app.display()
+        );
+        Ok(())
+    }
+}
+
+impl TemplateNewCommandCore {
+    async fn run(&self, variant: TemplateVariantInfo) -> Result<()> {
         let template_manager =
             TemplateManager::default().context("Failed to construct template directory path")?;
         let template = template_manager
             .get(&self.template_id)
             .with_context(|| format!("Error retrieving template {}", self.template_id))?;
-        let output_path = self
-            .output_path
-            .clone()
-            .unwrap_or_else(|| path_safe(&self.name));
+        let output_path = match (&self.output_path, &variant) {
+            (Some(path), _) => path.clone(),
+            (None, TemplateVariantInfo::NewApplication) => path_safe(&self.name),
+            (None, TemplateVariantInfo::AddComponent { manifest_path }) => manifest_path
+                .parent()
+                .unwrap_or_else(|| Path::new("."))
+                .join(path_safe(&self.name)),
+        };
         let values = {
             let mut values = match self.values_file.as_ref() {
                 Some(file) => values_from_file(file.as_path()).await?,
@@ -65,10 +155,31 @@ impl NewCommand {
             output_path,
             values,
             accept_defaults: self.accept_defaults,
+            accept_hooks: self.accept_hooks,
+            variant,
         };
 
+        // Prompting would hang scripts and CI jobs, so without a terminal all
+        // values must be given as options.
+        let interactive = !self.non_interactive && atty::is(atty::Stream::Stdin);
         match template {
-            Some(template) => template.run(options).interactive().await.execute().await,
+            Some(template) if interactive => {
+                template.run(options).interactive().await.execute().await
+            }
+            Some(template) => template
+                .run(options)
+                .silent()
+                .await
+                .execute()
+                .await
+                .with_context(|| {
+                    format!(
+                        "Cannot create {} from template {} without prompting",
+                        self.name, self.template_id
+                    )
+                }),
+            // Scripts need a failure to notice the missing template.
+            None if !interactive => bail!("Template {} not found", self.template_id),
             None => {
                 // TODO: guidance experience
                 println!("Template {} not found", self.template_id);
@@ -0,0 +1,261 @@
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use clap::{Parser, Subcommand};
+use comfy_table::Table;
+
+use crate::opts::DEFAULT_MANIFEST_FILE;
+use crate::plugins::{spin_data_dir, InstalledPlugin, ManifestSource, PluginStore};
+
+const SPIN_VERSION: &str = env!("VERGEN_BUILD_SEMVER");
+
+/// Commands for managing plugins: executables run as `spin <name>`.
+#[derive(Subcommand, Debug)]
+pub enum PluginCommands {
+    /// Install a plugin from its manifest.
+    ///
+    /// The executable for this platform is downloaded, checked against the
+    /// digest in the manifest, and kept in the plugin store: a directory in
+    /// your data or home directory. The plugin then runs as `spin <name>`.
+    Install(Install),
+
+    /// Remove a plugin from your installation.
+    Uninstall(Uninstall),
+
+    /// Upgrade plugins to the version in the manifest they were installed from.
+    Upgrade(Upgrade),
+
+    /// List the installed plugins.
+    List(List),
+}
+
+impl PluginCommands {
+    pub async fn run(self) -> Result<()> {
+        match self {
+            PluginCommands::Install(cmd) => cmd.run().await,
+            PluginCommands::Uninstall(cmd) => cmd.run().await,
+            PluginCommands::Upgrade(cmd) => cmd.run().await,
+            PluginCommands::List(cmd) => cmd.run().await,
+        }
+    }
+}
+
+/// Install a plugin from its manifest.
+#[derive(Parser, Debug)]
+pub struct Install {
+    /// The path or URL of the plugin manifest.
+    pub source: String,
+
+    /// Install the plugin even if it replaces a later version, or does not
+    /// declare this version of Spin compatible.
+    #[clap(long = "force")]
+    pub force: bool,
+}
+
+/// Remove a plugin from your installation.
+#[derive(Parser, Debug)]
+pub struct Uninstall {
+    /// The plugin to uninstall.
+    pub name: String,
+}
+
+/// Upgrade plugins to the version in the manifest they were installed from.
+#[derive(Parser, Debug)]
+pub struct Upgrade {
+    /// The plugin to upgrade.
+    #[clap(required_unless_present = "all", conflicts_with = "all")]
+    pub name: Option<String>,
+
+    /// Upgrade all installed plugins.
+    #[clap(long = "all")]
+    pub all: bool,
+
+    /// Upgrade from another manifest than the one the plugin was installed
+    /// from.
+    #[clap(long = "from", conflicts_with = "all")]
+    pub from: Option<String>,
+
+    /// Install the version in the manifest even if it is earlier than the
+    /// installed one.
+    #[clap(long = "downgrade")]
+    pub downgrade: bool,
+}
+
+/// List the installed plugins.
+#[derive(Parser, Debug)]
+pub struct List {}
+
+impl Install {
+    pub async fn run(self) -> Result<()> {
+        let store = PluginStore::local()?;
+        let source = ManifestSource::parse(&self.source)?;
+        let manifest = source.read_manifest().await?;
+        if let Some(installed) = store.get(&manifest.name)? {
+            if installed.manifest.version > manifest.version && !self.force {
+                bail!(
+                    "Plugin {} {} is installed, which is later than {}: pass --force to replace it",
+                    manifest.name,
+                    installed.manifest.version,
+                    manifest.version
+                );
+            }
+        }
+        if !self.force {
+            manifest.check_compatibility(SPIN_VERSION)?;
+        }
+        let executable = source.read_package(manifest.package()?).await?;
+        let plugin = store.install(&manifest, &source, &executable)?;
+        println!(
+            "Installed plugin {} {}: run it with `spin {}`",
+            plugin.manifest.name, plugin.manifest.version, plugin.manifest.name
+        );
+        Ok(())
+    }
+}
+
+impl Uninstall {
+    pub async fn run(self) -> Result<()> {
+        PluginStore::local()?
+            .uninstall(&self.name)
+            .context("Failed to uninstall plugin")?;
+        println!("Uninstalled plugin {}", self.name);
+        Ok(())
+    }
+}
+
+impl Upgrade {
+    pub async fn run(self) -> Result<()> {
+        let store = PluginStore::local()?;
+        let plugins = match &self.name {
+            Some(name) => match store.get(name)? {
+                Some(plugin) => vec![plugin],
+                None => bail!("Plugin {} is not installed", name),
+            },
+            None => store.list()?,
+        };
+        let mut failures = 0;
+        for plugin in plugins {
+            if let Err(e) = self.upgrade(&store, &plugin).await {
+                eprintln!("Failed to upgrade plugin {}: {:#}", plugin.manifest.name, e);
+                failures += 1;
+            }
+        }
+        if failures > 0 {
+            bail!("Failed to upgrade {} plugin(s)", failures);
+        }
+        Ok(())
+    }
+
+    async fn upgrade(&self, store: &PluginStore, plugin: &InstalledPlugin) -> Result<()> {
+        let source = match &self.from {
+            Some(from) => ManifestSource::parse(from)?,
+            None => plugin.source.clone(),
+        };
+        let manifest = source.read_manifest().await?;
+        if manifest.name != plugin.manifest.name {
+            bail!(
+                "The manifest {} is for plugin {}, not {}",
+                source,
+                manifest.name,
+                plugin.manifest.name
+            );
+        }
+        let installed = &plugin.manifest.version;
+        if manifest.version == *installed || (manifest.version < *installed && !self.downgrade) {
+            println!(
+                "Plugin {} {} is up to date",
+                plugin.manifest.name, plugin.manifest.version
+            );
+            return Ok(());
+        }
+        manifest.check_compatibility(SPIN_VERSION)?;
+        let executable = source.read_package(manifest.package()?).await?;
+        store.install(&manifest, &source, &executable)?;
+        println!(
+            "Upgraded plugin {} from {} to {}",
+            manifest.name, installed, manifest.version
+        );
+        Ok(())
+    }
+}
+
+impl List {
+    pub async fn run(self) -> Result<()> {
+        let plugins = PluginStore::local()?.list()?;
+        if plugins.is_empty() {
+            println!("You have no plugins installed. Run");
+            println!("spin plugin install <manifest path or URL>");
+            println!("to install one.");
+            return Ok(());
+        }
+        let mut table = Table::new();
+        table.set_header(vec!["Name", "Version", "Description", "Compatible"]);
+        table.load_preset(comfy_table::presets::ASCII_BORDERS_ONLY_CONDENSED);
+        for plugin in &plugins {
+            let manifest = &plugin.manifest;
+            let compatible = match manifest.check_compatibility(SPIN_VERSION) {
+                Ok(()) => "yes".to_owned(),
+                Err(_) => format!(
+                    "no (requires Spin {})",
+                    manifest
+                        .spin_compatibility
+                        .as_ref()
+                        .map(|r| r.to_string())
+                        .unwrap_or_default()
+                ),
+            };
+            table.add_row(vec![
+                manifest.name.clone(),
+                manifest.version.to_string(),
+                manifest.description.clone().unwrap_or_default(),
+                compatible,
+            ]);
+        }
+        println!("{}", table);
+        Ok(())
+    }
+}
+
+/// Runs the plugin named by the first argument with the rest of the
+/// arguments, exiting with its exit code.
+///
+/// The plugin is passed the Spin executable, version and data directory,
+/// and the application manifest in the current directory if there is one, in
+/// `SPIN_*` environment variables.
+pub async fn run_plugin(args: Vec<String>) -> Result<()> {
+    let (name, args) = match args.split_first() {
+        Some((name, args)) => (name, args),
+        None => bail!("No command given"),
+    };
+    let plugin = match PluginStore::local()?.get(name)? {
+        Some(plugin) => plugin,
+        None => bail!(
+            "'{}' is not a Spin command, and there is no plugin of that name installed. Run `spin --help` for the commands, and `spin plugin list` for the installed plugins",
+            name
+        ),
+    };
+    if let Err(e) = plugin.manifest.check_compatibility(SPIN_VERSION) {
+        eprintln!("Warning: {:#}", e);
+    }
+
+    let mut command = tokio::process::Command::new(plugin.executable());
+    command
+        .args(args)
+        .env("SPIN_VERSION", SPIN_VERSION)
+        .env("SPIN_DATA_DIR", spin_data_dir()?)
+        .env("SPIN_PLUGIN_DIR", &plugin.dir);
+    if let Ok(spin) = std::env::current_exe() {
+        command.env("SPIN_BIN_PATH", spin);
+    }
+    if Path::new(DEFAULT_MANIFEST_FILE).exists() {
+        command.env(
+            "SPIN_MANIFEST_FILE",
+            dunce::canonicalize(DEFAULT_MANIFEST_FILE)?,
+        );
+    }
+    let status = command
+        .status()
+        .await
+        .with_context(|| format!("Failed to run plugin {}", name))?;
+    std::process::exit(status.code().unwrap_or(1));
+}
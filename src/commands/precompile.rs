@@ -0,0 +1,33 @@
+use std::ffi::OsString;
+
+use anyhow::Result;
+use clap::Parser;
+
+use super::up::UpCommand;
+
+/// Compile the modules of an application ahead of time.
+#[derive(Parser, Debug)]
+#[clap(
+    about = "Compile the modules of the Spin application ahead of time",
+    allow_hyphen_values = true
+)]
+pub struct PrecompileCommand {
+    /// The arguments `spin up` would be run with, such as `--file` or
+    /// `--runtime-config-file`. Modules are only reused by `spin up` runs
+    /// with the same runtime options.
+    pub up_args: Vec<OsString>,
+}
+
+impl PrecompileCommand {
+    pub async fn run(self) -> Result<()> {
+        let mut cmd = UpCommand::parse_from(
+            std::iter::once(OsString::from(format!(
+                "{} up",
+                std::env::args().next().unwrap()
+            )))
+            .chain(self.up_args),
+        );
+        cmd.trigger_args.push(OsString::from("--precompile"));
+        cmd.run().await
+    }
+}
@@ -0,0 +1,134 @@
+use std::{collections::BTreeMap, path::Path};
+
+use anyhow::{bail, Context, Result};
+use clap::{ArgGroup, Parser};
+use hippo::Client;
+use serde::{Deserialize, Serialize};
+use spin_loader::local::config::RawAppManifestAnyVersion;
+
+use crate::commands::deploy::{get_app_id, DeployCommand};
+
+/// The file, relative to the application directory, recording the previews
+/// deployed from it.
+const PREVIEWS_FILE: &str = ".spin/previews.json";
+
+/// Deploy, list and clean up preview deployments of an application.
+#[derive(Parser, Debug)]
+#[clap(group(ArgGroup::new("action").required(true).args(&["ID", "list", "cleanup"])))]
+pub struct PreviewCommand {
+    /// Deploy the application as the preview with this ID, for example a
+    /// pull request number
+    #[clap(name = "ID", long = "id", env = "SPIN_PREVIEW_ID")]
+    pub id: Option<String>,
+
+    /// List the previews of the application deployed to Hippo
+    #[clap(long = "list")]
+    pub list: bool,
+
+    /// Remove the preview with this ID from Hippo
+    #[clap(long = "cleanup", value_name = "ID")]
+    pub cleanup: Option<String>,
+}
+
+/// A preview deployed from the local application.
+#[derive(Debug, Deserialize, Serialize)]
+struct PreviewRecord {
+    app: String,
+    routes: Vec<String>,
+    deployed_at: String,
+}
+
+impl PreviewCommand {
+    pub async fn run(self, mut deploy: DeployCommand) -> Result<()> {
+        let RawAppManifestAnyVersion::V1(cfg) =
+            spin_loader::local::raw_manifest_from_file(&deploy.app).await?;
+        // Previews are named after the application, so that they can be
+        // found in Hippo without a local record.
+        let prefix = format!("{}-pr-", deploy.app_name(&cfg));
+        let records_file = crate::app_dir(&deploy.app)?.join(PREVIEWS_FILE);
+        let mut records = load_records(&records_file).await?;
+
+        if let Some(id) = &self.id {
+            let name = preview_name(&prefix, id)?;
+            deploy.app_name = Some(name.clone());
+            deploy.name_prefix = None;
+            let routes = deploy.deploy_and_notify().await?;
+            records.insert(
+                id.clone(),
+                PreviewRecord {
+                    app: name,
+                    routes,
+                    deployed_at: chrono::Utc::now().to_rfc3339(),
+                },
+            );
+            save_records(&records_file, &records).await?;
+        } else if let Some(id) = &self.cleanup {
+            let name = preview_name(&prefix, id)?;
+            let hippo_client = deploy.hippo_client().await?;
+            match get_app_id(&hippo_client, &name).await? {
+                Some(app_id) => {
+                    Client::remove_app(&hippo_client, app_id.to_string())
+                        .await
+                        .with_context(|| format!("Unable to remove Hippo app {}", name))?;
+                    println!("Removed preview {} ({})", id, name);
+                }
+                None => println!("Preview {} ({}) is not deployed", id, name),
+            }
+            if records.remove(id).is_some() {
+                save_records(&records_file, &records).await?;
+            }
+        } else {
+            let hippo_client = deploy.hippo_client().await?;
+            let apps = Client::list_apps(&hippo_client)
+                .await
+                .context("Unable to list Hippo apps")?;
+            let mut previews: Vec<_> = apps
+                .items
+                .iter()
+                .filter_map(|app| Some((app.name.strip_prefix(&prefix)?, &app.name)))
+                .collect();
+            previews.sort();
+            if previews.is_empty() {
+                println!("No previews deployed");
+            }
+            for (id, name) in previews {
+                println!("{}: {}", id, name);
+                if let Some(record) = records.get(id) {
+                    println!("  deployed at {}", record.deployed_at);
+                    for route in &record.routes {
+                        println!("  {}", route);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn preview_name(prefix: &str, id: &str) -> Result<String> {
+    if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+        bail!(
+            "Invalid preview ID '{}': IDs may only contain letters, digits and '-'",
+            id
+        );
+    }
+    Ok(format!("{}{}", prefix, id))
+}
+
+async fn load_records(path: &Path) -> Result<BTreeMap<String, PreviewRecord>> {
+    match tokio::fs::read(path).await {
+        Ok(contents) => serde_json::from_slice(&contents)
+            .with_context(|| format!("Cannot parse preview records {}", path.display())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+        Err(e) => Err(e).with_context(|| format!("Cannot read {}", path.display())),
+    }
+}
+
+async fn save_records(path: &Path, records: &BTreeMap<String, PreviewRecord>) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+    tokio::fs::write(path, serde_json::to_vec_pretty(records)?)
+        .await
+        .with_context(|| format!("Cannot write {}", path.display()))
+}
@@ -0,0 +1,406 @@
+use std::cmp::Ordering;
+use std::path::PathBuf;
+
+use anyhow::{bail, Context, Result};
+use clap::{ArgGroup, Parser, Subcommand};
+use comfy_table::Table;
+use hippo::{Client, ConnectionInfo};
+use hippo_openapi::models::{ChannelItem, ChannelRevisionSelectionStrategy};
+use semver::Version;
+use spin_loader::local::config::RawAppManifestAnyVersion;
+use uuid::Uuid;
+
+use crate::{
+    commands::{
+        deploy::{
+            deployed_app_name, get_app_id, get_channel_variables, HippoAuth,
+            SPIN_DEPLOY_CHANNEL_NAME,
+        },
+        login::{logins_path, Logins},
+        scale::ScaleApi,
+    },
+    opts::*,
+};
+
+/// Commands for the revisions of an application registered in Hippo.
+#[derive(Subcommand, Debug)]
+pub enum RevisionsCommands {
+    /// List the revisions of the application, marking the one the channel
+    /// serves.
+    List(ListCommand),
+
+    /// Point the channel at another revision of the application, for
+    /// example to roll back a bad deploy, without building or pushing it
+    /// again.
+    Activate(ActivateCommand),
+}
+
+impl RevisionsCommands {
+    pub async fn run(self) -> Result<()> {
+        match self {
+            Self::List(cmd) => cmd.run().await,
+            Self::Activate(cmd) => cmd.run().await,
+        }
+    }
+}
+
+/// The Hippo server, application and channel whose revisions to manage.
+#[derive(Parser, Debug)]
+pub struct HippoAppOpts {
+    /// Path to spin.toml
+    #[clap(
+        name = APP_CONFIG_FILE_OPT,
+        short = 'f',
+        long = "file",
+        default_value = "spin.toml"
+    )]
+    pub app: PathBuf,
+
+    /// Ignore server certificate errors from hippo
+    #[clap(
+        name = INSECURE_OPT,
+        short = 'k',
+        long = "insecure",
+        takes_value = false,
+    )]
+    pub insecure: bool,
+
+    /// URL of hippo server (defaults to the one last logged in to with
+    /// `spin login`)
+    #[clap(
+        name = HIPPO_SERVER_URL_OPT,
+        long = "hippo-server",
+        env = HIPPO_URL_ENV,
+    )]
+    pub hippo_server_url: Option<String>,
+
+    /// Hippo username (not needed after `spin login`)
+    #[clap(
+        name = "HIPPO_USERNAME",
+        long = "hippo-username",
+        env = "HIPPO_USERNAME",
+        requires = "HIPPO_PASSWORD"
+    )]
+    pub hippo_username: Option<String>,
+
+    /// Hippo password (not needed after `spin login`)
+    #[clap(
+        name = "HIPPO_PASSWORD",
+        long = "hippo-password",
+        env = "HIPPO_PASSWORD",
+        requires = "HIPPO_USERNAME"
+    )]
+    pub hippo_password: Option<String>,
+
+    /// Hippo API key, used instead of logging in
+    #[clap(
+        name = "HIPPO_API_KEY",
+        long = "hippo-api-key",
+        env = "HIPPO_API_KEY",
+        conflicts_with_all = &["HIPPO_USERNAME", "HIPPO_PASSWORD"]
+    )]
+    pub hippo_api_key: Option<String>,
+
+    /// Name the application was deployed as, instead of the name in spin.toml
+    #[clap(long = "app-name")]
+    pub app_name: Option<String>,
+
+    /// Prefix the application name was deployed with
+    #[clap(long = "name-prefix")]
+    pub name_prefix: Option<String>,
+
+    /// Name of the Hippo channel (defaults to spin-deploy)
+    #[clap(long = "channel", env = "SPIN_DEPLOY_CHANNEL")]
+    pub channel: Option<String>,
+}
+
+/// A deployed application, with its revisions sorted oldest first.
+struct DeployedApp {
+    client: Client,
+    url: String,
+    insecure: bool,
+    token: String,
+    name: String,
+    id: Uuid,
+    revisions: Vec<Revision>,
+}
+
+struct Revision {
+    id: Uuid,
+    number: String,
+}
+
+impl HippoAppOpts {
+    pub(crate) fn channel(&self) -> &str {
+        self.channel.as_deref().unwrap_or(SPIN_DEPLOY_CHANNEL_NAME)
+    }
+
+    async fn connect(&self) -> Result<DeployedApp> {
+        spin_loader::offline::ensure_online("manage the revisions of an application in Hippo")?;
+        let RawAppManifestAnyVersion::V1(cfg) =
+            spin_loader::local::raw_manifest_from_file(&self.app).await?;
+        let name = deployed_app_name(&cfg, self.app_name.as_deref(), self.name_prefix.as_deref());
+
+        let logins = match logins_path() {
+            Ok(path) => Logins::load(&path).await?,
+            Err(_) => Logins::default(),
+        };
+        let (url, login) = match logins.get(self.hippo_server_url.as_deref()) {
+            Some((url, login)) => (url.to_owned(), Some(login)),
+            None => match &self.hippo_server_url {
+                Some(url) => (url.clone(), None),
+                None => bail!("No Hippo server given: pass --hippo-server, or run `spin login`"),
+            },
+        };
+        let insecure = self.insecure || login.map_or(false, |l| l.insecure);
+        // The scale is managed outside the Hippo client, so it needs the token.
+        let token = HippoAuth {
+            url: &url,
+            endpoint: &url,
+            insecure,
+            api_key: self.hippo_api_key.as_deref(),
+            username: self.hippo_username.as_deref(),
+            password: self.hippo_password.as_deref(),
+            login,
+        }
+        .token()
+        .await?;
+        let client = Client::new(ConnectionInfo {
+            url: url.clone(),
+            danger_accept_invalid_certs: insecure,
+            api_key: Some(token.clone()),
+        });
+
+        let id = get_app_id(&client, &name)
+            .await?
+            .with_context(|| format!("Application {} is not deployed to {}", name, url))?;
+        let mut revisions: Vec<_> = Client::list_revisions(&client)
+            .await
+            .context("Unable to list Hippo revisions")?
+            .items
+            .into_iter()
+            .filter(|r| r.app_id == id)
+            .map(|r| Revision {
+                id: r.id,
+                number: r.revision_number,
+            })
+            .collect();
+        revisions.sort_by(|a, b| compare_revisions(&a.number, &b.number));
+        Ok(DeployedApp {
+            client,
+            url,
+            insecure,
+            token,
+            name,
+            id,
+            revisions,
+        })
+    }
+}
+
+impl DeployedApp {
+    /// The channel of the application with the given name, if any.
+    async fn channel(&self, name: &str) -> Result<Option<ChannelItem>> {
+        let channels = Client::list_channels(&self.client)
+            .await
+            .context("Unable to list Hippo channels")?;
+        Ok(channels
+            .items
+            .into_iter()
+            .find(|c| c.app_id == self.id && c.name == name))
+    }
+
+    fn scale_api(&self) -> Result<ScaleApi<'_>> {
+        Ok(ScaleApi {
+            client: reqwest::Client::builder()
+                .danger_accept_invalid_certs(self.insecure)
+                .build()?,
+            hippo_url: &self.url,
+            token: &self.token,
+        })
+    }
+}
+
+/// List the revisions of an application registered in Hippo.
+#[derive(Parser, Debug)]
+pub struct ListCommand {
+    #[clap(flatten)]
+    pub opts: HippoAppOpts,
+}
+
+impl ListCommand {
+    pub async fn run(self) -> Result<()> {
+        let app = self.opts.connect().await?;
+        if app.revisions.is_empty() {
+            println!("Application {} has no revisions", app.name);
+            return Ok(());
+        }
+        let active = app
+            .channel(self.opts.channel())
+            .await?
+            .and_then(|c| c.active_revision)
+            .map(|r| r.revision_number);
+
+        let mut table = Table::new();
+        table.set_header(vec!["Revision", self.opts.channel()]);
+        table.load_preset(comfy_table::presets::ASCII_BORDERS_ONLY_CONDENSED);
+        for revision in app.revisions.iter().rev() {
+            let marker = match &active {
+                Some(active) if *active == revision.number => "active",
+                _ => "",
+            };
+            table.add_row(vec![revision.number.as_str(), marker]);
+        }
+        println!("{}", table);
+        Ok(())
+    }
+}
+
+/// Point a channel of an application at another of its revisions.
+#[derive(Parser, Debug)]
+#[clap(group(ArgGroup::new("target").required(true).args(&["REVISION", "previous"])))]
+pub struct ActivateCommand {
+    #[clap(flatten)]
+    pub opts: HippoAppOpts,
+
+    /// The revision to activate, as listed by `spin revisions list`
+    #[clap(name = "REVISION")]
+    pub revision: Option<String>,
+
+    /// Activate the revision before the one the channel serves, to roll back
+    /// the last deploy
+    #[clap(long = "previous")]
+    pub previous: bool,
+}
+
+impl ActivateCommand {
+    pub async fn run(self) -> Result<()> {
+        let app = self.opts.connect().await?;
+        let channel_name = self.opts.channel();
+        let channel = app.channel(channel_name).await?;
+        let active = channel
+            .as_ref()
+            .and_then(|c| c.active_revision.as_ref())
+            .map(|r| r.revision_number.clone());
+
+        let revision = match &self.revision {
+            Some(number) => app
+                .revisions
+                .iter()
+                .find(|r| r.number == *number)
+                .with_context(|| format!("Application {} has no revision {}", app.name, number))?,
+            None => {
+                let active = active.as_deref().with_context(|| {
+                    format!(
+                        "Channel {} of application {} has no active revision to roll back",
+                        channel_name, app.name
+                    )
+                })?;
+                previous_revision(&app.revisions, active).with_context(|| {
+                    format!("Application {} has no revision before {}", app.name, active)
+                })?
+            }
+        };
+        if active.as_deref() == Some(revision.number.as_str()) {
+            println!(
+                "Channel {} of application {} already serves revision {}",
+                channel_name, app.name, revision.number
+            );
+            return Ok(());
+        }
+
+        // Hippo cannot update the revision of a channel, so it is recreated,
+        // along with its variables and scale.
+        let scale_api = app.scale_api()?;
+        let mut variables = vec![];
+        let mut scale = None;
+        if let Some(channel) = channel {
+            variables = get_channel_variables(&app.client, channel.id).await?;
+            scale = scale_api.get(channel.id).await?;
+            Client::remove_channel(&app.client, channel.id.to_string())
+                .await
+                .with_context(|| format!("Unable to remove channel {}", channel_name))?;
+        }
+        let channel_id = Client::add_channel(
+            &app.client,
+            app.id,
+            channel_name.to_owned(),
+            None,
+            ChannelRevisionSelectionStrategy::UseSpecifiedRevision,
+            None,
+            Some(revision.id),
+            None,
+        )
+        .await
+        .with_context(|| format!("Unable to create channel {}", channel_name))?;
+        for (key, value) in variables {
+            Client::add_environment_variable(&app.client, key.clone(), value, channel_id)
+                .await
+                .with_context(|| {
+                    format!("Unable to set variable {} on channel {}", key, channel_name)
+                })?;
+        }
+        if let Some(scale) = scale.filter(|s| !s.is_empty()) {
+            scale_api.set(channel_id, &scale).await?;
+        }
+
+        match active {
+            Some(active) => println!(
+                "Channel {} of application {} now serves revision {} (was {})",
+                channel_name, app.name, revision.number, active
+            ),
+            None => println!(
+                "Channel {} of application {} now serves revision {}",
+                channel_name, app.name, revision.number
+            ),
+        }
+        Ok(())
+    }
+}
+
+/// Orders revision numbers as semantic versions where both are, and as
+/// strings otherwise, such as OCI tags.
+fn compare_revisions(a: &str, b: &str) -> Ordering {
+    match (Version::parse(a), Version::parse(b)) {
+        (Ok(a), Ok(b)) => a.cmp(&b),
+        _ => a.cmp(b),
+    }
+}
+
+/// The latest revision before the given one, of revisions sorted oldest
+/// first.
+fn previous_revision<'a>(revisions: &'a [Revision], active: &str) -> Option<&'a Revision> {
+    revisions
+        .iter()
+        .rev()
+        .find(|r| compare_revisions(&r.number, active) == Ordering::Less)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn revisions(numbers: &[&str]) -> Vec<Revision> {
+        let mut revisions: Vec<_> = numbers
+            .iter()
+            .map(|n| Revision {
+                id: Uuid::nil(),
+                number: n.to_string(),
+            })
+            .collect();
+        revisions.sort_by(|a, b| compare_revisions(&a.number, &b.number));
+        revisions
+    }
+
+    #[test]
+    fn test_previous_revision() {
+        let revisions = revisions(&["1.10.0", "1.2.0", "1.9.0-rc.1", "1.9.0"]);
+        let numbers: Vec<_> = revisions.iter().map(|r| r.number.as_str()).collect();
+        assert_eq!(numbers, ["1.2.0", "1.9.0-rc.1", "1.9.0", "1.10.0"]);
+
+        let previous = |active| previous_revision(&revisions, active).map(|r| r.number.as_str());
+        assert_eq!(previous("1.10.0"), Some("1.9.0"));
+        assert_eq!(previous("1.9.0-rc.1"), Some("1.2.0"));
+        assert_eq!(previous("1.2.0"), None);
+    }
+}
@@ -0,0 +1,269 @@
+use anyhow::{bail, Context, Result};
+use clap::Parser;
+use hippo::{Client, ConnectionInfo};
+use serde::{Deserialize, Serialize};
+use spin_loader::local::config::{RawAppManifestAnyVersion, RawScaleConfig};
+use url::Url;
+use uuid::Uuid;
+
+use crate::commands::{
+    deploy::{deployed_app_name, get_app_id, get_channel_id, HippoAuth},
+    login::{logins_path, Logins},
+    revisions::HippoAppOpts,
+};
+
+/// View or set the scaling settings of a deployed application.
+#[derive(Parser, Debug)]
+#[clap(about = "Print or set the replicas and memory class of a deployed application's channel")]
+pub struct ScaleCommand {
+    #[clap(flatten)]
+    pub opts: HippoAppOpts,
+
+    /// The fewest replicas of the application to run
+    #[clap(long = "min-replicas")]
+    pub min_replicas: Option<u32>,
+
+    /// The most replicas of the application to run
+    #[clap(long = "max-replicas")]
+    pub max_replicas: Option<u32>,
+
+    /// The memory class of each replica, as named by the platform
+    #[clap(long = "memory-class")]
+    pub memory_class: Option<String>,
+}
+
+impl ScaleCommand {
+    pub async fn run(self) -> Result<()> {
+        spin_loader::offline::ensure_online("scale an application in Hippo")?;
+        let opts = &self.opts;
+        let RawAppManifestAnyVersion::V1(cfg) =
+            spin_loader::local::raw_manifest_from_file(&opts.app).await?;
+        let name = deployed_app_name(&cfg, opts.app_name.as_deref(), opts.name_prefix.as_deref());
+
+        let logins = match logins_path() {
+            Ok(path) => Logins::load(&path).await?,
+            Err(_) => Logins::default(),
+        };
+        let (url, login) = match logins.get(opts.hippo_server_url.as_deref()) {
+            Some((url, login)) => (url.to_owned(), Some(login)),
+            None => match &opts.hippo_server_url {
+                Some(url) => (url.clone(), None),
+                None => bail!("No Hippo server given: pass --hippo-server, or run `spin login`"),
+            },
+        };
+        let insecure = opts.insecure || login.map_or(false, |l| l.insecure);
+        // The scale is managed outside the Hippo client, so it needs the token.
+        let token = HippoAuth {
+            url: &url,
+            endpoint: &url,
+            insecure,
+            api_key: opts.hippo_api_key.as_deref(),
+            username: opts.hippo_username.as_deref(),
+            password: opts.hippo_password.as_deref(),
+            login,
+        }
+        .token()
+        .await?;
+        let hippo_client = Client::new(ConnectionInfo {
+            url: url.clone(),
+            danger_accept_invalid_certs: insecure,
+            api_key: Some(token.clone()),
+        });
+
+        let channel = opts.channel();
+        let app_id = get_app_id(&hippo_client, &name)
+            .await?
+            .with_context(|| format!("Application {} is not deployed to {}", name, url))?;
+        let channel_id = get_channel_id(&hippo_client, app_id, channel)
+            .await?
+            .with_context(|| format!("Application {} has no {} channel", name, channel))?;
+
+        let api = ScaleApi {
+            client: reqwest::Client::builder()
+                .danger_accept_invalid_certs(insecure)
+                .build()?,
+            hippo_url: &url,
+            token: &token,
+        };
+        let current = api
+            .get(channel_id)
+            .await?
+            .context("Hippo does not support scaling channels")?;
+
+        let requested = ChannelScale {
+            min_replicas: self.min_replicas,
+            max_replicas: self.max_replicas,
+            memory_class: self.memory_class.clone(),
+        };
+        if requested.is_empty() {
+            println!("Channel {} of {}:", channel, name);
+            print!("{}", current);
+            return Ok(());
+        }
+        // Settings not given are kept, so must be consistent with those given.
+        requested.clone().or(current).validate()?;
+        api.set(channel_id, &requested).await?;
+        println!("Scaled channel {} of {}", channel, name);
+        Ok(())
+    }
+}
+
+/// The scaling settings of a Hippo channel. Settings that are not set are
+/// those of the platform, or are left unchanged when setting the scale.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ChannelScale {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_replicas: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_replicas: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory_class: Option<String>,
+}
+
+impl ChannelScale {
+    /// Whether no setting is set.
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// The settings of this scale, and those of the other where this one
+    /// does not set them.
+    pub fn or(self, other: Self) -> Self {
+        Self {
+            min_replicas: self.min_replicas.or(other.min_replicas),
+            max_replicas: self.max_replicas.or(other.max_replicas),
+            memory_class: self.memory_class.or(other.memory_class),
+        }
+    }
+
+    /// Checks that the settings are consistent with each other.
+    pub fn validate(&self) -> Result<()> {
+        match (self.min_replicas, self.max_replicas) {
+            (_, Some(0)) => bail!("The maximum number of replicas must be at least 1"),
+            (Some(min), Some(max)) if min > max => bail!(
+                "The minimum number of replicas ({}) is more than the maximum ({})",
+                min,
+                max
+            ),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl From<&RawScaleConfig> for ChannelScale {
+    fn from(raw: &RawScaleConfig) -> Self {
+        Self {
+            min_replicas: raw.min_replicas,
+            max_replicas: raw.max_replicas,
+            memory_class: raw.memory_class.clone(),
+        }
+    }
+}
+
+impl std::fmt::Display for ChannelScale {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let or_default = |setting: Option<String>| setting.unwrap_or_else(|| "default".to_owned());
+        writeln!(
+            f,
+            "  Min replicas: {}",
+            or_default(self.min_replicas.map(|n| n.to_string()))
+        )?;
+        writeln!(
+            f,
+            "  Max replicas: {}",
+            or_default(self.max_replicas.map(|n| n.to_string()))
+        )?;
+        writeln!(
+            f,
+            "  Memory class: {}",
+            or_default(self.memory_class.clone())
+        )
+    }
+}
+
+/// The Hippo API for the scale of channels, which the Hippo client does not
+/// cover.
+pub(crate) struct ScaleApi<'a> {
+    pub client: reqwest::Client,
+    pub hippo_url: &'a str,
+    pub token: &'a str,
+}
+
+impl ScaleApi<'_> {
+    /// The scale of the channel, or none if Hippo does not support scaling.
+    pub async fn get(&self, channel_id: Uuid) -> Result<Option<ChannelScale>> {
+        let response = self
+            .client
+            .get(self.url(channel_id)?)
+            .bearer_auth(self.token)
+            .send()
+            .await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let response = response
+            .error_for_status()
+            .context("Unable to get the scale of the Hippo channel")?;
+        let scale = serde_json::from_slice(&response.bytes().await?)
+            .context("Cannot parse the scale returned by Hippo")?;
+        Ok(Some(scale))
+    }
+
+    /// Sets the given settings of the scale of the channel, leaving the
+    /// others unchanged.
+    pub async fn set(&self, channel_id: Uuid, scale: &ChannelScale) -> Result<()> {
+        let response = self
+            .client
+            .put(self.url(channel_id)?)
+            .bearer_auth(self.token)
+            .json(scale)
+            .send()
+            .await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            bail!("Hippo does not support scaling channels");
+        }
+        response
+            .error_for_status()
+            .context("Unable to set the scale of the Hippo channel")?;
+        Ok(())
+    }
+
+    fn url(&self, channel_id: Uuid) -> Result<Url> {
+        Ok(Url::parse(self.hippo_url)?.join(&format!("/api/channel/{}/scale", channel_id))?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_channel_scale() -> Result<()> {
+        let manifest = ChannelScale {
+            min_replicas: Some(2),
+            max_replicas: None,
+            memory_class: Some("large".to_owned()),
+        };
+        let current: ChannelScale = serde_json::from_str(r#"{"minReplicas":1,"maxReplicas":4}"#)?;
+        let merged = manifest.clone().or(current);
+        assert_eq!(merged.min_replicas, Some(2));
+        assert_eq!(merged.max_replicas, Some(4));
+        assert_eq!(merged.memory_class.as_deref(), Some("large"));
+        merged.validate()?;
+
+        // Only the settings given are sent, so the others are unchanged.
+        assert_eq!(
+            serde_json::to_string(&manifest)?,
+            r#"{"minReplicas":2,"memoryClass":"large"}"#
+        );
+        assert!(ChannelScale::default().is_empty());
+
+        let inverted = ChannelScale {
+            min_replicas: Some(5),
+            ..merged
+        };
+        assert!(inverted.validate().is_err());
+        Ok(())
+    }
+}
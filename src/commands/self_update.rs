@@ -0,0 +1,303 @@
+use std::{
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use clap::Parser;
+use semver::Version;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+const SPIN_VERSION: &str = env!("VERGEN_BUILD_SEMVER");
+
+/// The feed of Spin releases, in the format of the GitHub releases API.
+const DEFAULT_RELEASES_URL: &str = "https://api.github.com/repos/fermyon/spin/releases";
+const RELEASES_URL_ENV: &str = "SPIN_RELEASES_URL";
+
+/// Update Spin to the latest release, or to a given one
+#[derive(Parser, Debug)]
+#[clap(about = "Update Spin to the latest release, or to a given one")]
+pub struct SelfUpdateCommand {
+    /// Only report whether a newer release is available, without installing
+    /// it.
+    #[clap(long = "check")]
+    pub check: bool,
+
+    /// The release to install, such as v0.5.0, even if it is older than this
+    /// version of Spin. Defaults to the latest release.
+    #[clap(long = "version", value_name = "TAG")]
+    pub version: Option<String>,
+
+    /// The URL of the release feed, for mirrors of the Spin releases.
+    #[clap(
+        long = "releases-url",
+        env = RELEASES_URL_ENV,
+        default_value = DEFAULT_RELEASES_URL,
+        hide = true
+    )]
+    pub releases_url: String,
+}
+
+/// A release in the feed.
+#[derive(Debug, Deserialize)]
+struct Release {
+    tag_name: String,
+    #[serde(default)]
+    assets: Vec<Asset>,
+}
+
+/// A file attached to a release.
+#[derive(Debug, Deserialize)]
+struct Asset {
+    name: String,
+    browser_download_url: String,
+}
+
+impl Release {
+    fn asset(&self, name: &str) -> Result<&Asset> {
+        self.assets
+            .iter()
+            .find(|a| a.name == name)
+            .ok_or_else(|| anyhow!("Release {} has no {}", self.tag_name, name))
+    }
+}
+
+impl SelfUpdateCommand {
+    pub async fn run(self) -> Result<()> {
+        let current = Version::parse(SPIN_VERSION)
+            .with_context(|| format!("Invalid Spin version {}", SPIN_VERSION))?;
+        let client = reqwest::Client::builder()
+            .user_agent(format!("spin/{}", SPIN_VERSION))
+            .build()?;
+        let release = self.fetch_release(&client).await?;
+        let version = release_version(&release.tag_name)?;
+
+        if self.version.is_none() && version <= current {
+            println!("Spin {} is up to date", SPIN_VERSION);
+            return Ok(());
+        }
+        if self.check {
+            let command = match &self.version {
+                Some(tag) => format!("spin self-update --version {}", tag),
+                None => "spin self-update".to_owned(),
+            };
+            println!(
+                "Spin {} is available: this is Spin {}. Run `{}` to install it.",
+                release.tag_name, SPIN_VERSION, command
+            );
+            return Ok(());
+        }
+
+        let archive_name = archive_name(&release.tag_name)?;
+        let archive_asset = release.asset(&archive_name)?;
+        let checksums_asset = release.asset(&format!("checksums-{}.txt", release.tag_name))?;
+        let checksums = String::from_utf8(download(&client, checksums_asset).await?)
+            .context("Invalid checksums file")?;
+        let expected = checksum_for(&checksums, &archive_name).ok_or_else(|| {
+            anyhow!(
+                "The checksums of release {} do not list {}",
+                release.tag_name,
+                archive_name
+            )
+        })?;
+
+        let archive = download(&client, archive_asset).await?;
+        let digest = format!("{:x}", Sha256::digest(&archive));
+        if !digest.eq_ignore_ascii_case(expected) {
+            bail!(
+                "{} has digest {}, but the release checksums list {}: it was not installed",
+                archive_name,
+                digest,
+                expected
+            );
+        }
+        let executable = extract_executable(&archive)
+            .with_context(|| format!("Invalid release archive {}", archive_name))?;
+        let path = replace_current_exe(&executable)?;
+        println!(
+            "Updated Spin from {} to {} at {}",
+            SPIN_VERSION,
+            release.tag_name,
+            path.display()
+        );
+        Ok(())
+    }
+
+    async fn fetch_release(&self, client: &reqwest::Client) -> Result<Release> {
+        let base = self.releases_url.trim_end_matches('/');
+        let url = match &self.version {
+            Some(tag) if tag.starts_with('v') => format!("{}/tags/{}", base, tag),
+            Some(tag) => format!("{}/tags/v{}", base, tag),
+            None => format!("{}/latest", base),
+        };
+        spin_loader::offline::ensure_online(format!("check for Spin releases at {}", url))?;
+        let response = client
+            .get(&url)
+            .send()
+            .await
+            .with_context(|| format!("Cannot reach the release feed {}", url))?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            match &self.version {
+                Some(tag) => bail!("Spin release {} does not exist", tag),
+                None => bail!("No Spin release was found at {}", url),
+            }
+        }
+        response
+            .error_for_status()?
+            .json()
+            .await
+            .with_context(|| format!("Invalid release feed {}", url))
+    }
+}
+
+async fn download(client: &reqwest::Client, asset: &Asset) -> Result<Vec<u8>> {
+    let bytes = client
+        .get(&asset.browser_download_url)
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .with_context(|| format!("Cannot download {}", asset.browser_download_url))?
+        .bytes()
+        .await
+        .with_context(|| format!("Cannot download {}", asset.browser_download_url))?;
+    Ok(bytes.to_vec())
+}
+
+/// The version of a release, from its tag, such as `v0.5.0`.
+fn release_version(tag: &str) -> Result<Version> {
+    Version::parse(tag.strip_prefix('v').unwrap_or(tag))
+        .with_context(|| format!("Invalid release tag {}", tag))
+}
+
+/// The name of the release archive for the platform Spin runs on, as the
+/// release workflow names it.
+fn archive_name(tag: &str) -> Result<String> {
+    let os = match std::env::consts::OS {
+        os @ ("linux" | "macos") => os,
+        "windows" => bail!(
+            "spin self-update is not supported on Windows yet: download the release from https://github.com/fermyon/spin/releases"
+        ),
+        os => bail!("Spin releases have no archive for {}", os),
+    };
+    let arch = match std::env::consts::ARCH {
+        "x86_64" => "amd64",
+        "aarch64" => "aarch64",
+        arch => bail!("Spin releases have no archive for {}/{}", os, arch),
+    };
+    Ok(format!("spin-{}-{}-{}.tar.gz", tag, os, arch))
+}
+
+/// Finds the digest of a file in checksums in the `sha256sum` format.
+fn checksum_for<'a>(checksums: &'a str, name: &str) -> Option<&'a str> {
+    checksums.lines().find_map(|line| {
+        let (digest, file) = line.split_once(char::is_whitespace)?;
+        // Files hashed in binary mode are marked with `*`.
+        let file = file.trim_start();
+        let file = file.strip_prefix('*').unwrap_or(file);
+        (file == name).then(|| digest)
+    })
+}
+
+/// Reads the `spin` executable from a release archive.
+fn extract_executable(archive: &[u8]) -> Result<Vec<u8>> {
+    let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(archive));
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if entry.path()?.file_name() == Some("spin".as_ref()) {
+            let mut executable = vec![];
+            entry.read_to_end(&mut executable)?;
+            return Ok(executable);
+        }
+    }
+    bail!("the archive has no spin executable")
+}
+
+/// Replaces the running executable. The new executable is written next to
+/// it, checked to run on this platform, then renamed over it, so that the
+/// running executable is either the old or the new one.
+fn replace_current_exe(executable: &[u8]) -> Result<PathBuf> {
+    let current = std::env::current_exe().context("Cannot find the Spin executable")?;
+    let current = dunce::canonicalize(&current)
+        .with_context(|| format!("Cannot find the Spin executable {}", current.display()))?;
+    let dir = current.parent().unwrap_or_else(|| Path::new("."));
+    let cannot_write = || {
+        format!(
+            "Cannot replace {}: check that you can write to {}",
+            current.display(),
+            dir.display()
+        )
+    };
+
+    let mut staged = tempfile::Builder::new()
+        .prefix(".spin-update")
+        .tempfile_in(dir)
+        .with_context(cannot_write)?;
+    staged.write_all(executable).with_context(cannot_write)?;
+    staged.as_file().sync_all()?;
+    let permissions = std::fs::metadata(&current)?.permissions();
+    std::fs::set_permissions(staged.path(), permissions)?;
+    // The file is closed before it runs, as busy files cannot be executed.
+    let staged = staged.into_temp_path();
+
+    let status = std::process::Command::new(&staged)
+        .arg("--version")
+        .stdout(std::process::Stdio::null())
+        .status()
+        .context("The downloaded Spin executable does not run on this platform")?;
+    if !status.success() {
+        bail!("The downloaded Spin executable does not run on this platform");
+    }
+    staged.persist(&current).with_context(cannot_write)?;
+    Ok(current)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_release_versions() -> Result<()> {
+        assert_eq!(release_version("v0.5.0")?, Version::new(0, 5, 0));
+        assert_eq!(release_version("0.5.1")?, Version::new(0, 5, 1));
+        assert!(release_version("canary").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_checksums() {
+        let checksums = "\
+            1111  spin-v0.5.0-linux-amd64.tar.gz\n\
+            2222 *spin-v0.5.0-macos-aarch64.tar.gz\n";
+        assert_eq!(
+            checksum_for(checksums, "spin-v0.5.0-linux-amd64.tar.gz"),
+            Some("1111")
+        );
+        assert_eq!(
+            checksum_for(checksums, "spin-v0.5.0-macos-aarch64.tar.gz"),
+            Some("2222")
+        );
+        assert_eq!(
+            checksum_for(checksums, "spin-v0.5.0-linux-aarch64.tar.gz"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_extract_executable() -> Result<()> {
+        let mut builder = tar::Builder::new(flate2::write::GzEncoder::new(
+            vec![],
+            flate2::Compression::default(),
+        ));
+        for (name, content) in [("README.md", &b"readme"[..]), ("spin", &b"binary"[..])] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o755);
+            header.set_cksum();
+            builder.append_data(&mut header, name, content)?;
+        }
+        let archive = builder.into_inner()?.finish()?;
+        assert_eq!(extract_executable(&archive)?, b"binary");
+        Ok(())
+    }
+}
@@ -0,0 +1,225 @@
+use std::path::PathBuf;
+
+use anyhow::{bail, Context, Result};
+use bindle::invoice::signature::{KeyEntry, SecretKeyEntry, SecretKeyFile, SignatureRole};
+use clap::{Parser, Subcommand};
+use spin_loader::bindle::signature::{keyring_path, load_keyring, save_keyring};
+use spin_publish::{
+    find_signing_key, generate_signing_key, load_signing_keys, public_key, save_signing_keys,
+    signing_keys_path,
+};
+use spin_trigger::{runtime_config_signature_path, sign_runtime_config};
+
+/// Environment variable naming the key bindles are signed with.
+pub const SIGNING_KEY_ENV: &str = "SPIN_SIGNING_KEY";
+
+/// Commands for managing the keys bindles are signed and verified with.
+#[derive(Subcommand, Debug)]
+pub enum SigningKeyCommands {
+    /// Generate a key to sign bindles with.
+    Generate(GenerateCommand),
+
+    /// Import the keys of a bindle secret key file to sign bindles with.
+    Import(ImportCommand),
+
+    /// Trust a public key to sign the bindles run by `spin up`.
+    Trust(TrustCommand),
+
+    /// List the signing keys and the trusted keys.
+    List(ListCommand),
+
+    /// Sign a runtime config file, for triggers run with
+    /// `--runtime-config-key` to apply.
+    Sign(SignCommand),
+}
+
+impl SigningKeyCommands {
+    pub async fn run(self) -> Result<()> {
+        match self {
+            Self::Generate(cmd) => cmd.run().await,
+            Self::Import(cmd) => cmd.run().await,
+            Self::Trust(cmd) => cmd.run().await,
+            Self::List(cmd) => cmd.run().await,
+            Self::Sign(cmd) => cmd.run().await,
+        }
+    }
+}
+
+/// Generate a signing key.
+#[derive(Parser, Debug)]
+pub struct GenerateCommand {
+    /// Label of the key, usually the name and email address of its owner,
+    /// for example "Ada <ada@example.com>"
+    #[clap(name = "LABEL")]
+    pub label: String,
+}
+
+impl GenerateCommand {
+    pub async fn run(self) -> Result<()> {
+        let key = generate_signing_key(&self.label);
+        add_signing_keys(vec![key.clone()]).await?;
+        println!("Generated signing key {}", key.label);
+        println!("Public key: {}", public_key(&key)?.key);
+        Ok(())
+    }
+}
+
+/// Import signing keys.
+#[derive(Parser, Debug)]
+pub struct ImportCommand {
+    /// Path of the bindle secret key file to import the keys of
+    #[clap(name = "FILE")]
+    pub file: PathBuf,
+}
+
+impl ImportCommand {
+    pub async fn run(self) -> Result<()> {
+        let contents = tokio::fs::read_to_string(&self.file)
+            .await
+            .with_context(|| format!("Cannot read {}", self.file.display()))?;
+        let imported: SecretKeyFile = toml::from_str(&contents)
+            .with_context(|| format!("Invalid secret key file {}", self.file.display()))?;
+        let keys: Vec<_> = imported
+            .key
+            .into_iter()
+            .filter(|k| k.roles.contains(&SignatureRole::Creator))
+            .collect();
+        if keys.is_empty() {
+            bail!(
+                "{} contains no keys with the creator role",
+                self.file.display()
+            );
+        }
+        let labels: Vec<_> = keys.iter().map(|k| k.label.clone()).collect();
+        add_signing_keys(keys).await?;
+        for label in labels {
+            println!("Imported signing key {}", label);
+        }
+        Ok(())
+    }
+}
+
+/// Trust a public key.
+#[derive(Parser, Debug)]
+pub struct TrustCommand {
+    /// Label of the key, usually the name and email address of its owner
+    #[clap(name = "LABEL")]
+    pub label: String,
+
+    /// The public key, as printed by `spin signing-key list`
+    #[clap(name = "PUBLIC_KEY")]
+    pub key: String,
+}
+
+impl TrustCommand {
+    pub async fn run(self) -> Result<()> {
+        let entry = KeyEntry {
+            label: self.label,
+            roles: vec![SignatureRole::Creator],
+            key: self.key,
+            label_signature: None,
+        };
+        let label = entry.label.clone();
+        add_trusted_keys(vec![entry]).await?;
+        println!("Trusted key {}", label);
+        Ok(())
+    }
+}
+
+/// List signing and trusted keys.
+#[derive(Parser, Debug)]
+pub struct ListCommand {}
+
+impl ListCommand {
+    pub async fn run(self) -> Result<()> {
+        let signing_keys = load_signing_keys(&signing_keys_path()?).await?;
+        let keyring = load_keyring(&keyring_path()?).await?;
+        if signing_keys.key.is_empty() && keyring.key.is_empty() {
+            println!("No keys: generate one with `spin signing-key generate`");
+            return Ok(());
+        }
+        println!("Signing keys:");
+        for key in &signing_keys.key {
+            println!("  {}: {}", key.label, public_key(key)?.key);
+        }
+        println!("Trusted keys:");
+        for key in &keyring.key {
+            println!("  {}: {}", key.label, key.key);
+        }
+        Ok(())
+    }
+}
+
+/// Sign a runtime config file.
+#[derive(Parser, Debug)]
+pub struct SignCommand {
+    /// Path of the runtime config file to sign. The signature is written to
+    /// the file of the same name with `.sig` appended.
+    #[clap(name = "FILE")]
+    pub file: PathBuf,
+
+    /// Label of the key to sign with. Defaults to the first signing key.
+    #[clap(long = "signing-key", env = SIGNING_KEY_ENV)]
+    pub key: Option<String>,
+}
+
+impl SignCommand {
+    pub async fn run(self) -> Result<()> {
+        let key = match signing_key(self.key.as_deref()).await? {
+            Some(key) => key,
+            None => bail!("No signing key: generate one with `spin signing-key generate`"),
+        };
+        let contents = tokio::fs::read(&self.file)
+            .await
+            .with_context(|| format!("Cannot read {}", self.file.display()))?;
+        let signature = sign_runtime_config(&contents, &key.keypair).with_context(|| {
+            format!("Cannot sign {} with key {}", self.file.display(), key.label)
+        })?;
+        let signature_path = runtime_config_signature_path(&self.file);
+        tokio::fs::write(&signature_path, signature)
+            .await
+            .with_context(|| format!("Cannot write {}", signature_path.display()))?;
+        println!(
+            "Signed {} with key {}: the signature is in {}",
+            self.file.display(),
+            key.label,
+            signature_path.display()
+        );
+        Ok(())
+    }
+}
+
+/// Adds keys to the signing keys, and their public keys to the trusted keys
+/// so that the bindles they sign can be run locally.
+async fn add_signing_keys(keys: Vec<SecretKeyEntry>) -> Result<()> {
+    let path = signing_keys_path()?;
+    let mut signing_keys = load_signing_keys(&path).await?;
+    for key in &keys {
+        if signing_keys.key.iter().any(|k| k.label == key.label) {
+            bail!("There is already a signing key labelled {}", key.label);
+        }
+    }
+    let public_keys = keys.iter().map(public_key).collect::<Result<Vec<_>>>()?;
+    signing_keys.key.extend(keys);
+    save_signing_keys(&path, &signing_keys).await?;
+    add_trusted_keys(public_keys).await
+}
+
+async fn add_trusted_keys(keys: Vec<KeyEntry>) -> Result<()> {
+    let path = keyring_path()?;
+    let mut keyring = load_keyring(&path).await?;
+    for key in keys {
+        if !keyring.key.iter().any(|k| k.key == key.key) {
+            keyring.key.push(key);
+        }
+    }
+    save_keyring(&path, &keyring).await
+}
+
+/// The key with the given label, or the first signing key if no label is
+/// given, to sign published bindles with. Returns `None` if there are no
+/// signing keys.
+pub(crate) async fn signing_key(label: Option<&str>) -> Result<Option<SecretKeyEntry>> {
+    let keys = load_signing_keys(&signing_keys_path()?).await?;
+    Ok(find_signing_key(&keys, label)?.cloned())
+}
@@ -5,12 +5,14 @@ use clap::{Parser, Subcommand};
 use comfy_table::Table;
 
 use spin_templates::{
-    InstallOptions, InstallationResults, InstalledTemplateWarning, ListResults, ProgressReporter,
-    SkippedReason, TemplateManager, TemplateSource,
+    GitAuth, InstallOptions, InstallationResults, InstalledTemplateWarning, ListResults,
+    ProgressReporter, SkippedReason, TemplateManager, TemplateSource,
 };
 
 const INSTALL_FROM_DIR_OPT: &str = "FROM_DIR";
 const INSTALL_FROM_GIT_OPT: &str = "FROM_GIT";
+const BRANCH_OPT: &str = "BRANCH";
+const TAG_OPT: &str = "TAG";
 
 /// Commands for working with WebAssembly component templates.
 #[derive(Subcommand, Debug)]
@@ -24,6 +26,9 @@ pub enum TemplateCommands {
     /// Remove a template from your installation.
     Uninstall(Uninstall),
 
+    /// Upgrade installed templates from the sources they were installed from.
+    Upgrade(Upgrade),
+
     /// List the installed templates.
     List(List),
 }
@@ -33,6 +38,7 @@ impl TemplateCommands {
         match self {
             TemplateCommands::Install(cmd) => cmd.run().await,
             TemplateCommands::Uninstall(cmd) => cmd.run().await,
+            TemplateCommands::Upgrade(cmd) => cmd.run().await,
             TemplateCommands::List(cmd) => cmd.run().await,
         }
     }
@@ -41,7 +47,8 @@ impl TemplateCommands {
 /// Install templates from a Git repository or local directory.
 #[derive(Parser, Debug)]
 pub struct Install {
-    /// The URL of the templates git repository.
+    /// The URL of the templates git repository, such as
+    /// https://github.com/org/repo or git@github.com:org/repo.git.
     /// The templates must be in a git repository in a "templates" directory.
     #[clap(
         name = INSTALL_FROM_GIT_OPT,
@@ -51,9 +58,23 @@ pub struct Install {
     pub git: Option<String>,
 
     /// The optional branch of the git repository.
-    #[clap(long = "branch", requires = INSTALL_FROM_GIT_OPT)]
+    #[clap(
+        name = BRANCH_OPT,
+        long = "branch",
+        requires = INSTALL_FROM_GIT_OPT,
+        conflicts_with = TAG_OPT,
+    )]
     pub branch: Option<String>,
 
+    /// The optional tag of the git repository.
+    #[clap(
+        name = TAG_OPT,
+        long = "tag",
+        requires = INSTALL_FROM_GIT_OPT,
+        conflicts_with = BRANCH_OPT,
+    )]
+    pub tag: Option<String>,
+
     /// Local directory containing the template(s) to install.
     #[clap(
         name = INSTALL_FROM_DIR_OPT,
@@ -65,6 +86,48 @@ pub struct Install {
     /// If present, updates existing templates instead of skipping.
     #[structopt(long = "update")]
     pub update: bool,
+
+    #[clap(flatten)]
+    pub auth: GitAuthOptions,
+}
+
+/// Credentials for private HTTPS git repositories. Repositories accessed
+/// over SSH use your SSH keys instead.
+#[derive(Parser, Debug)]
+pub struct GitAuthOptions {
+    /// A token authenticating to the git repository over HTTPS, such as a
+    /// personal access token. It is not recorded with the templates, so it
+    /// must be given again to upgrade them.
+    #[clap(
+        long = "token",
+        env = "SPIN_TEMPLATES_GIT_TOKEN",
+        hide_env_values = true
+    )]
+    pub token: Option<String>,
+
+    /// The user name the token is sent with.
+    #[clap(long = "token-user", default_value = "x-access-token")]
+    pub token_user: String,
+}
+
+impl GitAuthOptions {
+    fn git_auth(&self) -> Option<GitAuth> {
+        self.token.as_ref().map(|token| GitAuth {
+            username: self.token_user.clone(),
+            token: token.clone(),
+        })
+    }
+}
+
+/// Upgrade installed templates from the sources they were installed from.
+#[derive(Parser, Debug)]
+pub struct Upgrade {
+    /// The templates to upgrade. All installed templates are upgraded if
+    /// none are given.
+    pub template_ids: Vec<String>,
+
+    #[clap(flatten)]
+    pub auth: GitAuthOptions,
 }
 
 /// Remove a template from your installation.
@@ -80,7 +143,10 @@ impl Install {
             TemplateManager::default().context("Failed to construct template directory path")?;
         let source = match (&self.git, &self.dir) {
             (Some(git), None) => {
-                TemplateSource::try_from_git(&git, &self.branch, env!("VERGEN_BUILD_SEMVER"))?
+                spin_loader::offline::ensure_online(format!("install templates from {}", git))?;
+                let reference = self.branch.clone().or_else(|| self.tag.clone());
+                TemplateSource::try_from_git(&git, &reference, env!("VERGEN_BUILD_SEMVER"))?
+                    .with_git_auth(self.auth.git_auth())
             }
             (None, Some(dir)) => TemplateSource::File(dir.clone()),
             _ => anyhow::bail!("Exactly one of `git` and `dir` sources must be specified"),
@@ -94,46 +160,69 @@ impl Install {
             .await
             .context("Failed to install one or more templates")?;
 
-        self.print_installed_templates(&installation_results);
+        print_installed_templates(&installation_results, "Installed");
 
         Ok(())
     }
+}
 
-    fn print_installed_templates(&self, installation_results: &InstallationResults) {
-        let templates = &installation_results.installed;
-        let skipped = &installation_results.skipped;
-
-        if templates.is_empty() && skipped.is_empty() {
-            println!("The specified source contained no templates");
+impl Upgrade {
+    pub async fn run(self) -> Result<()> {
+        spin_loader::offline::ensure_online("upgrade templates from their sources")?;
+        let template_manager =
+            TemplateManager::default().context("Failed to construct template directory path")?;
+        let results = template_manager
+            .upgrade(
+                &self.template_ids,
+                env!("VERGEN_BUILD_SEMVER"),
+                self.auth.git_auth(),
+                &ConsoleProgressReporter,
+            )
+            .await
+            .context("Failed to upgrade one or more templates")?;
+        if results.is_empty() {
+            println!("No templates to upgrade");
         } else {
-            println!("Installed {} template(s)", templates.len());
-            if !templates.is_empty() {
-                let mut table = Table::new();
-                table.set_header(vec!["Name", "Description"]);
-                table.load_preset(comfy_table::presets::ASCII_BORDERS_ONLY_CONDENSED);
+            print_installed_templates(&results, "Upgraded");
+        }
+        Ok(())
+    }
+}
 
-                for template in templates {
-                    table.add_row(vec![template.id(), template.description_or_empty()]);
-                }
+fn print_installed_templates(installation_results: &InstallationResults, verb: &str) {
+    let templates = &installation_results.installed;
+    let skipped = &installation_results.skipped;
 
-                println!();
-                println!("{}", table);
+    if templates.is_empty() && skipped.is_empty() {
+        println!("The specified source contained no templates");
+    } else {
+        println!("{} {} template(s)", verb, templates.len());
+        if !templates.is_empty() {
+            let mut table = Table::new();
+            table.set_header(vec!["Name", "Description"]);
+            table.load_preset(comfy_table::presets::ASCII_BORDERS_ONLY_CONDENSED);
+
+            for template in templates {
+                table.add_row(vec![template.id(), template.description_or_empty()]);
             }
-            if !skipped.is_empty() {
-                println!();
-                println!("Skipped {} template(s)", skipped.len());
 
-                let mut table = Table::new();
-                table.set_header(vec!["Name", "Reason skipped"]);
-                table.load_preset(comfy_table::presets::ASCII_BORDERS_ONLY_CONDENSED);
+            println!();
+            println!("{}", table);
+        }
+        if !skipped.is_empty() {
+            println!();
+            println!("Skipped {} template(s)", skipped.len());
 
-                for (id, reason) in skipped {
-                    table.add_row(vec![id.clone(), skipped_reason_text(reason)]);
-                }
+            let mut table = Table::new();
+            table.set_header(vec!["Name", "Reason skipped"]);
+            table.load_preset(comfy_table::presets::ASCII_BORDERS_ONLY_CONDENSED);
 
-                println!();
-                println!("{}", table);
+            for (id, reason) in skipped {
+                table.add_row(vec![id.clone(), skipped_reason_text(reason)]);
             }
+
+            println!();
+            println!("{}", table);
         }
     }
 }
@@ -218,6 +307,10 @@ fn skipped_reason_text(reason: &SkippedReason) -> String {
     match reason {
         SkippedReason::AlreadyExists => "Already exists".to_owned(),
         SkippedReason::InvalidManifest(msg) => format!("Template load error: {}", msg),
+        SkippedReason::UnknownSource => {
+            "Source not recorded: reinstall it with `spin templates install --update`".to_owned()
+        }
+        SkippedReason::NotInSource => "No longer in its source".to_owned(),
     }
 }
 
@@ -0,0 +1,211 @@
+use std::io::{BufRead, Write};
+use std::path::PathBuf;
+
+use anyhow::{bail, Context, Result};
+use clap::Parser;
+use hippo::Client;
+use spin_loader::local::config::RawAppManifestAnyVersion;
+
+use crate::{
+    commands::{
+        deploy::{
+            deployed_app_name, get_app_id, get_channel_id, HippoAuth, SPIN_DEPLOY_CHANNEL_NAME,
+        },
+        login::{logins_path, Logins},
+    },
+    opts::*,
+};
+
+/// Remove a Spin application deployed with `spin deploy` from Hippo
+#[derive(Parser, Debug)]
+#[clap(about = "Remove a deployed Spin application")]
+pub struct UndeployCommand {
+    /// Path to spin.toml
+    #[clap(
+        name = APP_CONFIG_FILE_OPT,
+        short = 'f',
+        long = "file",
+        default_value = "spin.toml"
+    )]
+    pub app: PathBuf,
+
+    /// Ignore server certificate errors from hippo
+    #[clap(
+        name = INSECURE_OPT,
+        short = 'k',
+        long = "insecure",
+        takes_value = false,
+    )]
+    pub insecure: bool,
+
+    /// URL of hippo server (defaults to the one last logged in to with
+    /// `spin login`)
+    #[clap(
+        name = HIPPO_SERVER_URL_OPT,
+        long = "hippo-server",
+        env = HIPPO_URL_ENV,
+    )]
+    pub hippo_server_url: Option<String>,
+
+    /// Hippo username (not needed after `spin login`)
+    #[clap(
+        name = "HIPPO_USERNAME",
+        long = "hippo-username",
+        env = "HIPPO_USERNAME",
+        requires = "HIPPO_PASSWORD"
+    )]
+    pub hippo_username: Option<String>,
+
+    /// Hippo password (not needed after `spin login`)
+    #[clap(
+        name = "HIPPO_PASSWORD",
+        long = "hippo-password",
+        env = "HIPPO_PASSWORD",
+        requires = "HIPPO_USERNAME"
+    )]
+    pub hippo_password: Option<String>,
+
+    /// Hippo API key, used instead of logging in
+    #[clap(
+        name = "HIPPO_API_KEY",
+        long = "hippo-api-key",
+        env = "HIPPO_API_KEY",
+        conflicts_with_all = &["HIPPO_USERNAME", "HIPPO_PASSWORD"]
+    )]
+    pub hippo_api_key: Option<String>,
+
+    /// Name the application was deployed as, instead of the name in spin.toml
+    #[clap(long = "app-name")]
+    pub app_name: Option<String>,
+
+    /// Prefix the application name was deployed with
+    #[clap(long = "name-prefix")]
+    pub name_prefix: Option<String>,
+
+    /// Name of the Hippo channel to remove (defaults to spin-deploy)
+    #[clap(long = "channel", env = "SPIN_DEPLOY_CHANNEL")]
+    pub channel: Option<String>,
+
+    /// Delete the application and all its revisions, rather than only the
+    /// channel `spin deploy` serves it on
+    #[clap(long = "delete-app")]
+    pub delete_app: bool,
+
+    /// Do not ask for confirmation, for example in scripts
+    #[clap(short = 'y', long = "yes")]
+    pub yes: bool,
+}
+
+impl UndeployCommand {
+    pub async fn run(self) -> Result<()> {
+        spin_loader::offline::ensure_online("remove an application from Hippo")?;
+        let RawAppManifestAnyVersion::V1(cfg) =
+            spin_loader::local::raw_manifest_from_file(&self.app).await?;
+        let name = deployed_app_name(&cfg, self.app_name.as_deref(), self.name_prefix.as_deref());
+
+        let logins = match logins_path() {
+            Ok(path) => Logins::load(&path).await?,
+            Err(_) => Logins::default(),
+        };
+        let (url, login) = match logins.get(self.hippo_server_url.as_deref()) {
+            Some((url, login)) => (url.to_owned(), Some(login)),
+            None => match &self.hippo_server_url {
+                Some(url) => (url.clone(), None),
+                None => bail!("No Hippo server given: pass --hippo-server, or run `spin login`"),
+            },
+        };
+        let hippo_client = HippoAuth {
+            url: &url,
+            endpoint: &url,
+            insecure: self.insecure || login.map_or(false, |l| l.insecure),
+            api_key: self.hippo_api_key.as_deref(),
+            username: self.hippo_username.as_deref(),
+            password: self.hippo_password.as_deref(),
+            login,
+        }
+        .client()
+        .await?;
+
+        let app_id = match get_app_id(&hippo_client, &name).await? {
+            Some(app_id) => app_id,
+            None => {
+                println!("Application {} is not deployed to {}", name, url);
+                return Ok(());
+            }
+        };
+
+        if self.delete_app {
+            self.confirm(&format!(
+                "Delete application {} and all its revisions from {}?",
+                name, url
+            ))?;
+            Client::remove_app(&hippo_client, app_id.to_string())
+                .await
+                .with_context(|| format!("Unable to remove Hippo app {}", name))?;
+            println!("Deleted application {}", name);
+            return Ok(());
+        }
+
+        let channel = self.channel.as_deref().unwrap_or(SPIN_DEPLOY_CHANNEL_NAME);
+        match get_channel_id(&hippo_client, app_id, channel).await? {
+            Some(channel_id) => {
+                self.confirm(&format!(
+                    "Remove channel {} of application {} from {}?",
+                    channel, name, url
+                ))?;
+                Client::remove_channel(&hippo_client, channel_id.to_string())
+                    .await
+                    .with_context(|| format!("Unable to remove channel {}", channel))?;
+                println!(
+                    "Removed channel {} of application {}; pass --delete-app to also delete its revisions",
+                    channel, name
+                );
+            }
+            None => println!(
+                "Application {} has no {} channel; pass --delete-app to delete it",
+                name, channel
+            ),
+        }
+        Ok(())
+    }
+
+    /// Asks the user to confirm the given action, unless `--yes` was passed.
+    /// Fails if the user declines, or if there is no terminal to ask on.
+    fn confirm(&self, prompt: &str) -> Result<()> {
+        if self.yes {
+            return Ok(());
+        }
+        if !atty::is(atty::Stream::Stdin) {
+            bail!(
+                "{} Pass --yes to confirm when not running in a terminal",
+                prompt
+            );
+        }
+        print!("{} [y/N] ", prompt);
+        std::io::stdout().flush()?;
+        let mut answer = String::new();
+        std::io::stdin().lock().read_line(&mut answer)?;
+        if !is_yes(&answer) {
+            bail!("Cancelled");
+        }
+        Ok(())
+    }
+}
+
+fn is_yes(answer: &str) -> bool {
+    matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_yes() {
+        assert!(is_yes("y\n"));
+        assert!(is_yes(" YES \n"));
+        assert!(!is_yes("\n"));
+        assert!(!is_yes("n\n"));
+        assert!(!is_yes("yep\n"));
+    }
+}
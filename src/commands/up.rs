@@ -6,12 +6,30 @@ use std::{
 
 use anyhow::{bail, Context, Result};
 use clap::{CommandFactory, Parser};
-use spin_loader::bindle::BindleConnectionInfo;
-use spin_manifest::ApplicationTrigger;
+use spin_loader::bindle::{
+    cache as parcel_cache, signature::REQUIRE_SIGNED_ENV, BindleConnectionInfo, ParcelCache,
+    SignaturePolicy, BINDLE_INSECURE_ENV, BINDLE_PASSWORD_ENV, BINDLE_TOKEN_ENV,
+    BINDLE_USERNAME_ENV,
+};
+use spin_manifest::{ApplicationOrigin, ApplicationTrigger};
 use tempfile::TempDir;
 
 use crate::opts::*;
 
+/// The env file loaded by default, next to spin.toml.
+const DEFAULT_ENV_FILE: &str = ".env";
+
+/// The prefix of the executables implementing external triggers, found on the
+/// `PATH` by the name in their `command:<name>` trigger type.
+const EXTERNAL_TRIGGER_BIN_PREFIX: &str = "spin-trigger-";
+
+/// The lock file handed to external triggers, in the working directory.
+const EXTERNAL_TRIGGER_LOCK_FILE: &str = "spin.lock";
+
+/// The version of the interface between Spin and external triggers, passed
+/// to them in `SPIN_TRIGGER_PROTOCOL_VERSION`.
+const EXTERNAL_TRIGGER_PROTOCOL_VERSION: &str = "1";
+
 /// Start the Fermyon runtime.
 #[derive(Parser, Debug, Default)]
 #[clap(
@@ -68,6 +86,16 @@ pub struct UpCommand {
     )]
     pub bindle_password: Option<String>,
 
+    /// Bearer token for the bindle server, instead of a username and password
+    #[clap(
+        name = BINDLE_TOKEN,
+        long = "bindle-token",
+        env = BINDLE_TOKEN,
+        hide_env_values = true,
+        conflicts_with = BINDLE_USERNAME
+    )]
+    pub bindle_token: Option<String>,
+
     /// Ignore server certificate errors from bindle server
     #[clap(
         name = INSECURE_OPT,
@@ -77,6 +105,11 @@ pub struct UpCommand {
     )]
     pub insecure: bool,
 
+    /// Fail if the bindle is not signed by a key in the keyring of trusted
+    /// keys, rather than only warning
+    #[clap(long = "require-signed", requires = BINDLE_ID_OPT)]
+    pub require_signed: bool,
+
     /// Temporary directory for the static assets of the components.
     #[clap(long = "temp")]
     pub tmp: Option<PathBuf>,
@@ -85,6 +118,34 @@ pub struct UpCommand {
     #[clap(long = "allow-transient-write")]
     pub allow_transient_write: bool,
 
+    /// Mount directories placed whole from the application directory, rather
+    /// than copying them, so that changes to them are visible immediately.
+    /// Components can modify the mounted files.
+    #[clap(long = "direct-mounts", conflicts_with = BINDLE_ID_OPT)]
+    pub direct_mounts: bool,
+
+    /// Version of the application seen by components in SPIN_APP_VERSION,
+    /// instead of the version in the manifest or bindle ID.
+    #[clap(long = "version-label")]
+    pub version_label: Option<String>,
+
+    /// Prepare the application and write it to the given lock file, to be
+    /// run later with `spin trigger <TYPE> --from-lock`, instead of running it.
+    /// Files copied for the components are kept in the --temp directory.
+    #[clap(long = "write-lock", conflicts_with = BINDLE_ID_OPT)]
+    pub write_lock: Option<PathBuf>,
+
+    /// File of environment variables for the components, one KEY=VALUE per
+    /// line in the dotenv format. May be repeated, later files overriding
+    /// earlier ones. Defaults to the .env file next to spin.toml, if any.
+    /// Variables passed with --env take precedence. For local development only.
+    #[clap(long = "env-file", value_name = "PATH")]
+    pub env_files: Vec<PathBuf>,
+
+    /// Do not load the .env file next to spin.toml.
+    #[clap(long = "no-env-file", conflicts_with = "env_files")]
+    pub no_env_file: bool,
+
     /// All other args, to be passed through to the trigger
     #[clap(hide = true)]
     pub trigger_args: Vec<OsString>,
@@ -122,22 +183,28 @@ impl UpCommand {
                 let manifest_file = app
                     .as_deref()
                     .unwrap_or_else(|| DEFAULT_MANIFEST_FILE.as_ref());
+                if !self.help {
+                    super::check::warn_about_files(manifest_file).await;
+                }
                 let bindle_connection = self.bindle_connection();
                 spin_loader::from_file(
                     manifest_file,
                     working_dir,
                     &bindle_connection,
                     self.allow_transient_write,
+                    self.direct_mounts,
                 )
                 .await?
             }
-            (None, Some(bindle)) => match &self.server {
-                Some(server) => {
+            (None, Some(bindle)) => match self.bindle_connection() {
+                Some(bindle_connection) => {
                     spin_loader::from_bindle(
                         bindle,
-                        server,
+                        &bindle_connection,
                         working_dir,
                         self.allow_transient_write,
+                        &self.signature_policy(),
+                        parcel_cache::default_dir().map(ParcelCache::new),
                     )
                     .await?
                 }
@@ -146,6 +213,27 @@ impl UpCommand {
             (Some(_), Some(_)) => bail!("Specify only one of app file or bindle ID"),
         };
 
+        if let Some(lock_file) = &self.write_lock {
+            if let WorkingDirectory::Temporary(_) = working_dir_holder {
+                let copies_files = app
+                    .components
+                    .iter()
+                    .flat_map(|c| &c.wasm.mounts)
+                    .any(|m| m.host.starts_with(working_dir));
+                if copies_files {
+                    bail!("The components of the application mount copied files, which would be deleted on exit: pass --temp to keep them in a directory, or --direct-mounts to mount them from the application directory");
+                }
+            }
+            spin_loader::locked::write(&app, lock_file).await?;
+            println!("Wrote lock file {}", lock_file.display());
+            return Ok(());
+        }
+
+        if let ApplicationTrigger::External(external) = &app.info.trigger {
+            let command = external_trigger_command(&external.command, &app.info.origin)?;
+            return self.run_external_trigger(command, &app, working_dir).await;
+        }
+
         let manifest_url = match app.info.origin {
             spin_manifest::ApplicationOrigin::File(path) => {
                 format!("file://{}", path.canonicalize()?.to_string_lossy())
@@ -155,9 +243,12 @@ impl UpCommand {
             }
         };
 
-        let trigger_type = match app.info.trigger {
-            ApplicationTrigger::Http(_) => "http",
-            ApplicationTrigger::Redis(_) => "redis",
+        let trigger_type = app.info.trigger.trigger_type();
+
+        let env_file_vars = if self.help {
+            vec![]
+        } else {
+            self.env_file_vars().await?
         };
 
         let trigger_args = if self.help {
@@ -172,49 +263,131 @@ impl UpCommand {
         cmd.arg("trigger")
             .env("SPIN_WORKING_DIR", working_dir)
             .env("SPIN_MANIFEST_URL", manifest_url)
-            .env("SPIN_TRIGGER_TYPE", trigger_type)
+            .env("SPIN_TRIGGER_TYPE", &trigger_type)
             .env(
                 "SPIN_ALLOW_TRANSIENT_WRITE",
                 self.allow_transient_write.to_string(),
             )
-            .arg(trigger_type)
+            .env("SPIN_DIRECT_MOUNTS", self.direct_mounts.to_string())
+            .arg(&trigger_type)
             .args(trigger_args);
 
         if let Some(bindle_server) = self.server {
             cmd.env(BINDLE_URL_ENV, bindle_server);
         }
+        // The trigger loads the application again, with the same credentials.
+        if let Some(token) = self.bindle_token {
+            cmd.env(BINDLE_TOKEN_ENV, token);
+        }
+        if let (Some(username), Some(password)) = (self.bindle_username, self.bindle_password) {
+            cmd.env(BINDLE_USERNAME_ENV, username)
+                .env(BINDLE_PASSWORD_ENV, password);
+        }
+        if self.insecure {
+            cmd.env(BINDLE_INSECURE_ENV, "true");
+        }
+        if let Some(version_label) = self.version_label {
+            cmd.env("SPIN_VERSION_LABEL", version_label);
+        }
+        if self.require_signed {
+            cmd.env(REQUIRE_SIGNED_ENV, "true");
+        }
+        if !env_file_vars.is_empty() {
+            cmd.env("SPIN_ENV_FILE_VARS", serde_json::to_string(&env_file_vars)?);
+        }
 
-        tracing::trace!("Running trigger executor: {:?}", cmd);
+        supervise(cmd)
+    }
 
-        let mut child = cmd.spawn().context("Failed to execute trigger")?;
+    /// Runs a trigger implemented by an external executable, handing it the
+    /// application as a lock file in the working directory.
+    async fn run_external_trigger(
+        self,
+        command: PathBuf,
+        app: &spin_manifest::Application,
+        working_dir: &Path,
+    ) -> Result<()> {
+        if self.help {
+            // External triggers document their own arguments.
+            return Ok(());
+        }
+        let lock_file = working_dir.join(EXTERNAL_TRIGGER_LOCK_FILE);
+        spin_loader::locked::write(app, &lock_file).await?;
+        let env_file_vars = self.env_file_vars().await?;
 
-        #[cfg(not(windows))]
-        {
-            // https://github.com/nix-rust/nix/issues/656
-            let pid = nix::unistd::Pid::from_raw(child.id() as i32);
-            ctrlc::set_handler(move || {
-                if let Err(err) = nix::sys::signal::kill(pid, nix::sys::signal::SIGTERM) {
-                    tracing::warn!("Failed to kill trigger handler process: {:?}", err)
-                }
-            })?;
+        let mut cmd = std::process::Command::new(&command);
+        cmd.env(
+            "SPIN_TRIGGER_PROTOCOL_VERSION",
+            EXTERNAL_TRIGGER_PROTOCOL_VERSION,
+        )
+        .env("SPIN_TRIGGER_TYPE", app.info.trigger.trigger_type())
+        .env("SPIN_LOCK_FILE", &lock_file)
+        .env("SPIN_WORKING_DIR", working_dir)
+        .env("SPIN_BIN", std::env::current_exe()?)
+        .args(&self.trigger_args);
+        if let Some(version_label) = &self.version_label {
+            cmd.env("SPIN_VERSION_LABEL", version_label);
         }
+        if !env_file_vars.is_empty() {
+            cmd.env("SPIN_ENV_FILE_VARS", serde_json::to_string(&env_file_vars)?);
+        }
+        supervise(cmd).map_err(|e| match e.downcast_ref::<std::io::Error>() {
+            Some(io) if io.kind() == std::io::ErrorKind::NotFound => anyhow::anyhow!(
+                "Trigger executable {} not found: install the trigger, or check the `command:` trigger type in spin.toml",
+                command.display()
+            ),
+            _ => e,
+        })
+    }
 
-        let status = child.wait()?;
-        if status.success() {
-            Ok(())
+    /// The variables of the env files, later files overriding earlier ones.
+    async fn env_file_vars(&self) -> Result<Vec<(String, String)>> {
+        let files = if !self.env_files.is_empty() {
+            self.env_files.clone()
+        } else if self.no_env_file || self.bindle.is_some() {
+            vec![]
         } else {
-            bail!(status);
+            let manifest_file = self
+                .app
+                .as_deref()
+                .unwrap_or_else(|| DEFAULT_MANIFEST_FILE.as_ref());
+            let default_file = manifest_file
+                .parent()
+                .unwrap_or_else(|| Path::new("."))
+                .join(DEFAULT_ENV_FILE);
+            if !default_file.is_file() {
+                return Ok(vec![]);
+            }
+            vec![default_file]
+        };
+
+        let mut variables = vec![];
+        for file in &files {
+            spin_loader::env_file::merge(&mut variables, spin_loader::env_file::load(file).await?);
+            eprintln!(
+                "Loaded environment variables from {}: env files are meant for local development, and are not used by `spin deploy`",
+                file.display()
+            );
+        }
+        Ok(variables)
+    }
+
+    fn signature_policy(&self) -> SignaturePolicy {
+        SignaturePolicy {
+            keyring: None,
+            require_signed: self.require_signed,
         }
     }
 
     fn bindle_connection(&self) -> Option<BindleConnectionInfo> {
-        self.server.as_ref().map(|url| {
-            BindleConnectionInfo::new(
+        self.server.as_ref().map(|url| match &self.bindle_token {
+            Some(token) => BindleConnectionInfo::with_token(url, self.insecure, token.clone()),
+            None => BindleConnectionInfo::new(
                 url,
                 self.insecure,
                 self.bindle_username.clone(),
                 self.bindle_password.clone(),
-            )
+            ),
         })
     }
 }
@@ -232,3 +405,62 @@ impl WorkingDirectory {
         }
     }
 }
+
+/// Runs a trigger process until it exits, forwarding Ctrl+C to it.
+fn supervise(mut cmd: std::process::Command) -> Result<()> {
+    // The trigger runs in its own process group, so that Ctrl+C in the
+    // terminal only reaches it as forwarded below: a second signal makes
+    // it skip draining requests in flight.
+    #[cfg(not(windows))]
+    unsafe {
+        use std::os::unix::process::CommandExt;
+        cmd.pre_exec(|| {
+            nix::unistd::setpgid(nix::unistd::Pid::from_raw(0), nix::unistd::Pid::from_raw(0))
+                .map_err(std::io::Error::from)
+        });
+    }
+
+    tracing::trace!("Running trigger executor: {:?}", cmd);
+
+    let mut child = cmd.spawn().context("Failed to execute trigger")?;
+
+    #[cfg(not(windows))]
+    {
+        // https://github.com/nix-rust/nix/issues/656
+        let pid = nix::unistd::Pid::from_raw(child.id() as i32);
+        ctrlc::set_handler(move || {
+            if let Err(err) = nix::sys::signal::kill(pid, nix::sys::signal::SIGTERM) {
+                tracing::warn!("Failed to kill trigger handler process: {:?}", err)
+            }
+        })?;
+    }
+
+    let status = child.wait()?;
+    if status.success() {
+        Ok(())
+    } else {
+        bail!(status);
+    }
+}
+
+/// The executable of an external trigger: a path, relative to the
+/// application directory, if the command has a path separator, or else a
+/// `spin-trigger-<command>` executable on the `PATH`.
+fn external_trigger_command(command: &str, origin: &ApplicationOrigin) -> Result<PathBuf> {
+    if !command.contains(std::path::is_separator) {
+        return Ok(PathBuf::from(format!(
+            "{}{}",
+            EXTERNAL_TRIGGER_BIN_PREFIX, command
+        )));
+    }
+    match origin {
+        ApplicationOrigin::File(manifest) => Ok(manifest
+            .parent()
+            .unwrap_or_else(|| Path::new("."))
+            .join(command)),
+        ApplicationOrigin::Bindle { .. } => bail!(
+            "The trigger command {} is a path, which cannot be resolved for an application run from a bindle",
+            command
+        ),
+    }
+}
@@ -0,0 +1,36 @@
+use std::path::PathBuf;
+
+use anyhow::{bail, Result};
+use clap::Parser;
+
+/// Verify a lock file against the artifacts it references.
+#[derive(Parser, Debug)]
+#[clap(about = "Verify that a lock file and the modules and files it references are unchanged")]
+pub struct VerifyLockCommand {
+    /// Path to the lock file, as written by `spin up --write-lock`.
+    #[clap(default_value = "spin.lock")]
+    pub lock_file: PathBuf,
+}
+
+impl VerifyLockCommand {
+    pub async fn run(self) -> Result<()> {
+        let verification = spin_loader::locked::verify(&self.lock_file).await?;
+        for mismatch in &verification.mismatches {
+            eprintln!("{}", mismatch);
+        }
+        if !verification.mismatches.is_empty() {
+            bail!(
+                "{} failed verification: {} mismatch(es)",
+                self.lock_file.display(),
+                verification.mismatches.len()
+            );
+        }
+        println!(
+            "{} is valid: content hash {}, {} artifact(s) verified",
+            self.lock_file.display(),
+            verification.content_hash,
+            verification.artifacts
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,190 @@
+use std::{collections::BTreeSet, ffi::OsString, path::PathBuf, time::Duration};
+
+use anyhow::{Context, Result};
+use clap::Parser;
+use notify::{DebouncedEvent, RecommendedWatcher, RecursiveMode, Watcher};
+use spin_build::BuildOptions;
+use spin_loader::local::{config::RawAppManifestAnyVersion, raw_manifest_from_file};
+use tokio::{
+    process::{Child, Command},
+    sync::mpsc,
+};
+
+use crate::{
+    opts::*,
+    watch::{Changes, WatchPlan},
+};
+
+/// Build and run the application, rebuilding and restarting it when its
+/// files change.
+#[derive(Parser, Debug)]
+#[clap(
+    about = "Build and run the application, rebuilding and restarting it when its files change",
+    allow_hyphen_values = true
+)]
+pub struct WatchCommand {
+    /// Path to spin.toml
+    #[clap(
+        name = APP_CONFIG_FILE_OPT,
+        short = 'f',
+        long = "file",
+        default_value = "spin.toml"
+    )]
+    pub app: PathBuf,
+
+    /// Only restart the application when files change, without running the
+    /// build commands of the components
+    #[clap(long = "skip-build")]
+    pub skip_build: bool,
+
+    /// Milliseconds to wait for changes to settle before rebuilding, so that
+    /// saving several files rebuilds once
+    #[clap(long = "debounce", value_name = "MILLISECONDS", default_value = "300")]
+    pub debounce: u64,
+
+    /// Arguments passed to `spin up`, after `--`
+    #[clap(last = true)]
+    pub up_args: Vec<OsString>,
+}
+
+impl WatchCommand {
+    pub async fn run(self) -> Result<()> {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let (watch_tx, watch_rx) = std::sync::mpsc::channel();
+        let mut watcher = notify::watcher(watch_tx, Duration::from_millis(self.debounce))?;
+        // Debounced events arrive on a blocking channel, forwarded to the
+        // async loop by a thread.
+        std::thread::spawn(move || {
+            while let Ok(event) = watch_rx.recv() {
+                if tx.send(event).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut plan = self.watch(&mut watcher, None).await?;
+        if !self.skip_build {
+            self.build(None).await?;
+        }
+        let mut child = Some(self.start()?);
+
+        while let Some(event) = rx.recv().await {
+            let mut changes = Changes::default();
+            for path in event_paths(event) {
+                plan.classify(&path, &mut changes);
+            }
+            // Events that arrived during the debounce are handled together.
+            while let Ok(event) = rx.try_recv() {
+                for path in event_paths(event) {
+                    plan.classify(&path, &mut changes);
+                }
+            }
+            if changes.is_empty() {
+                continue;
+            }
+
+            if changes.manifest {
+                println!("Manifest changed: reloading");
+                plan = match self.watch(&mut watcher, Some(&plan)).await {
+                    Ok(plan) => plan,
+                    Err(e) => {
+                        eprintln!("{:#}: fix the manifest to continue", e);
+                        continue;
+                    }
+                };
+            }
+            if !self.skip_build && (changes.manifest || !changes.components.is_empty()) {
+                let components = if changes.manifest {
+                    None
+                } else {
+                    Some(changes.components)
+                };
+                // The previous version keeps running if the build fails.
+                if let Err(e) = self.build(components).await {
+                    eprintln!("{:#}: not restarting", e);
+                    continue;
+                }
+            }
+
+            println!("Restarting the application");
+            if let Some(child) = child.take() {
+                stop(child).await?;
+            }
+            child = Some(self.start()?);
+        }
+        Ok(())
+    }
+
+    /// Loads the manifest and watches the directories of the application,
+    /// unwatching those of the previous plan.
+    async fn watch(
+        &self,
+        watcher: &mut RecommendedWatcher,
+        previous: Option<&WatchPlan>,
+    ) -> Result<WatchPlan> {
+        let RawAppManifestAnyVersion::V1(app) = raw_manifest_from_file(&self.app).await?;
+        let plan = WatchPlan::new(&self.app, &app, self.skip_build)?;
+        if let Some(previous) = previous {
+            for root in previous.roots() {
+                let _ = watcher.unwatch(&root);
+            }
+        }
+        for root in plan.roots() {
+            watcher
+                .watch(&root, RecursiveMode::Recursive)
+                .with_context(|| format!("Cannot watch {}", root.display()))?;
+        }
+        Ok(plan)
+    }
+
+    /// Runs the build commands of the given components and of those
+    /// depending on them, or of all of them.
+    async fn build(&self, components: Option<BTreeSet<String>>) -> Result<()> {
+        let RawAppManifestAnyVersion::V1(app) = raw_manifest_from_file(&self.app).await?;
+        let options = BuildOptions {
+            components: components.map(|c| c.into_iter().collect()),
+            dependents: true,
+            ..Default::default()
+        };
+        spin_build::build(app, &self.app, &options).await
+    }
+
+    /// Starts `spin up` for the application.
+    fn start(&self) -> Result<Child> {
+        let mut cmd = Command::new(std::env::current_exe()?);
+        cmd.arg("up")
+            .arg("--file")
+            .arg(&self.app)
+            .args(&self.up_args);
+        cmd.spawn().context("Failed to start spin up")
+    }
+}
+
+/// Stops a `spin up` process, letting it stop its trigger.
+async fn stop(mut child: Child) -> Result<()> {
+    #[cfg(not(windows))]
+    if let Some(pid) = child.id() {
+        let pid = nix::unistd::Pid::from_raw(pid as i32);
+        if nix::sys::signal::kill(pid, nix::sys::signal::SIGTERM).is_ok() {
+            child.wait().await?;
+            return Ok(());
+        }
+    }
+    child.kill().await?;
+    Ok(())
+}
+
+/// The paths whose contents changed in an event.
+fn event_paths(event: DebouncedEvent) -> Vec<PathBuf> {
+    match event {
+        DebouncedEvent::Create(path)
+        | DebouncedEvent::Write(path)
+        | DebouncedEvent::Remove(path) => vec![path],
+        DebouncedEvent::Rename(from, to) => vec![from, to],
+        DebouncedEvent::Error(e, path) => {
+            tracing::warn!("Error watching {:?}: {}", path, e);
+            vec![]
+        }
+        _ => vec![],
+    }
+}
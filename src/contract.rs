@@ -0,0 +1,327 @@
+//! Contracts: request/response pairs recorded from a running application,
+//! replayed against later builds to check that they still respond the same.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+
+/// The version of the contract file format written by this version of Spin.
+pub(crate) const CONTRACT_FORMAT: u32 = 1;
+
+/// The directory contracts are kept in, next to spin.toml.
+pub(crate) const DEFAULT_CONTRACTS_DIR: &str = "contracts";
+
+/// The response headers recorded in contracts. Other headers, such as dates,
+/// change between responses.
+const RECORDED_HEADERS: &[&str] = &["content-type"];
+
+/// A contract: the responses of an application to a list of requests.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub(crate) struct Contract {
+    /// The version of the file format.
+    pub format: u32,
+    /// The application the contract was recorded from.
+    pub app: String,
+    /// The version of the application the contract was recorded from.
+    pub app_version: String,
+    /// When the contract was recorded, in RFC 3339 format.
+    pub recorded_at: String,
+    /// The requests, and the responses they must get.
+    pub interactions: Vec<Interaction>,
+}
+
+/// A request, and the response recorded for it.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub(crate) struct Interaction {
+    pub request: ContractRequest,
+    pub response: ContractResponse,
+}
+
+/// A request of a contract, relative to the base URL of the application.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub(crate) struct ContractRequest {
+    pub method: String,
+    /// The path and query of the request.
+    pub path: String,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<String>,
+}
+
+/// A response of a contract.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub(crate) struct ContractResponse {
+    pub status: u16,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+    #[serde(default)]
+    pub body: String,
+}
+
+impl Contract {
+    /// Loads a contract file.
+    pub async fn load(path: &Path) -> Result<Self> {
+        let contents = tokio::fs::read_to_string(path)
+            .await
+            .with_context(|| format!("Cannot read contract {}", path.display()))?;
+        let contract: Self = serde_json::from_str(&contents)
+            .with_context(|| format!("Invalid contract {}", path.display()))?;
+        if contract.format > CONTRACT_FORMAT {
+            bail!(
+                "Contract {} has format {}, but this version of Spin reads up to format {}: upgrade Spin",
+                path.display(),
+                contract.format,
+                CONTRACT_FORMAT
+            );
+        }
+        Ok(contract)
+    }
+
+    /// Writes the contract to a file, creating its directory if needed.
+    pub async fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+        let mut contents = serde_json::to_string_pretty(self)?;
+        contents.push('\n');
+        tokio::fs::write(path, contents)
+            .await
+            .with_context(|| format!("Cannot write contract {}", path.display()))
+    }
+}
+
+/// The path of the contract with the given name.
+pub(crate) fn contract_path(dir: &Path, name: &str) -> PathBuf {
+    dir.join(format!("{}.json", name))
+}
+
+/// The names of the contracts in a directory, sorted.
+pub(crate) async fn contract_names(dir: &Path) -> Result<Vec<String>> {
+    let mut entries = tokio::fs::read_dir(dir)
+        .await
+        .with_context(|| format!("Cannot read contracts directory {}", dir.display()))?;
+    let mut names = vec![];
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        if path.extension().map_or(false, |e| e == "json") {
+            if let Some(stem) = path.file_stem() {
+                names.push(stem.to_string_lossy().into_owned());
+            }
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+/// Sends a request of a contract to the application at the given base URL,
+/// returning the response as it would be recorded.
+pub(crate) async fn send(
+    client: &reqwest::Client,
+    base_url: &str,
+    request: &ContractRequest,
+) -> Result<ContractResponse> {
+    let url = format!(
+        "{}/{}",
+        base_url.trim_end_matches('/'),
+        request.path.trim_start_matches('/')
+    );
+    let method = reqwest::Method::from_bytes(request.method.as_bytes())
+        .with_context(|| format!("Invalid method {}", request.method))?;
+    let mut builder = client.request(method, &url);
+    for (name, value) in &request.headers {
+        builder = builder.header(name, value);
+    }
+    if let Some(body) = &request.body {
+        builder = builder.body(body.clone());
+    }
+    let response = builder
+        .send()
+        .await
+        .with_context(|| format!("{} {} failed", request.method, url))?;
+
+    let status = response.status().as_u16();
+    let headers = RECORDED_HEADERS
+        .iter()
+        .filter_map(|name| {
+            let value = response.headers().get(*name)?.to_str().ok()?;
+            Some((name.to_string(), value.to_owned()))
+        })
+        .collect();
+    let body = response.text().await?;
+    Ok(ContractResponse {
+        status,
+        headers,
+        body,
+    })
+}
+
+/// The differences between the recorded response of a contract and an
+/// actual response, as lines to print. JSON bodies are compared as values,
+/// so that formatting and key order do not matter.
+pub(crate) fn compare(expected: &ContractResponse, actual: &ContractResponse) -> Vec<String> {
+    let mut differences = vec![];
+    if expected.status != actual.status {
+        differences.push(format!(
+            "status: expected {}, got {}",
+            expected.status, actual.status
+        ));
+    }
+    for (name, value) in &expected.headers {
+        match actual.headers.get(name) {
+            Some(actual) if actual == value => (),
+            Some(actual) => {
+                differences.push(format!("{}: expected {}, got {}", name, value, actual))
+            }
+            None => differences.push(format!("{}: expected {}, got none", name, value)),
+        }
+    }
+
+    let json = |body: &str| serde_json::from_str::<serde_json::Value>(body).ok();
+    let (expected_body, actual_body) = match (json(&expected.body), json(&actual.body)) {
+        (Some(e), Some(a)) if e == a => return differences,
+        (Some(e), Some(a)) => (pretty(&e), pretty(&a)),
+        _ if expected.body == actual.body => return differences,
+        _ => (expected.body.clone(), actual.body.clone()),
+    };
+    differences.push("body:".to_owned());
+    differences.extend(diff_lines(&expected_body, &actual_body));
+    differences
+}
+
+fn pretty(value: &serde_json::Value) -> String {
+    serde_json::to_string_pretty(value).unwrap_or_else(|_| value.to_string())
+}
+
+/// A line diff of two texts: lines only in the expected text are prefixed
+/// with `-`, lines only in the actual text with `+`, and common lines with a
+/// space.
+fn diff_lines(expected: &str, actual: &str) -> Vec<String> {
+    let expected: Vec<_> = expected.lines().collect();
+    let actual: Vec<_> = actual.lines().collect();
+
+    // Lengths of the longest common subsequences of the suffixes.
+    let mut lcs = vec![vec![0usize; actual.len() + 1]; expected.len() + 1];
+    for i in (0..expected.len()).rev() {
+        for j in (0..actual.len()).rev() {
+            lcs[i][j] = if expected[i] == actual[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut lines = vec![];
+    let (mut i, mut j) = (0, 0);
+    while i < expected.len() || j < actual.len() {
+        if i < expected.len() && j < actual.len() && expected[i] == actual[j] {
+            lines.push(format!("  {}", expected[i]));
+            i += 1;
+            j += 1;
+        } else if j == actual.len() || (i < expected.len() && lcs[i + 1][j] >= lcs[i][j + 1]) {
+            lines.push(format!("- {}", expected[i]));
+            i += 1;
+        } else {
+            lines.push(format!("+ {}", actual[j]));
+            j += 1;
+        }
+    }
+    lines
+}
+
+/// Replays the contracts with the given names against the application at
+/// the given base URL, reporting the result of each interaction, and fails
+/// if any contract is broken.
+pub(crate) async fn verify(
+    client: &reqwest::Client,
+    dir: &Path,
+    names: &[String],
+    base_url: &str,
+    report: &dyn Fn(&str),
+) -> Result<()> {
+    let (mut total, mut broken) = (0, 0);
+    for name in names {
+        let contract = Contract::load(&contract_path(dir, name)).await?;
+        for interaction in &contract.interactions {
+            total += 1;
+            let request = &interaction.request;
+            let differences = match send(client, base_url, request).await {
+                Ok(actual) => compare(&interaction.response, &actual),
+                Err(e) => vec![format!("{:#}", e)],
+            };
+            if differences.is_empty() {
+                report(&format!("{}: {} {} ok", name, request.method, request.path));
+            } else {
+                broken += 1;
+                report(&format!(
+                    "{}: {} {} BROKEN",
+                    name, request.method, request.path
+                ));
+                for line in differences {
+                    report(&format!("    {}", line));
+                }
+            }
+        }
+    }
+    if broken > 0 {
+        bail!(
+            "{} of {} contract interactions broken against {}",
+            broken,
+            total,
+            base_url
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(status: u16, content_type: &str, body: &str) -> ContractResponse {
+        ContractResponse {
+            status,
+            headers: [("content-type".to_owned(), content_type.to_owned())].into(),
+            body: body.to_owned(),
+        }
+    }
+
+    #[test]
+    fn test_compare() {
+        let expected = response(200, "application/json", r#"{"id": 1, "name": "one"}"#);
+        let reordered = response(200, "application/json", r#"{"name":"one","id":1}"#);
+        assert!(compare(&expected, &reordered).is_empty());
+
+        let changed = response(201, "application/json", r#"{"id": 1, "name": "uno"}"#);
+        assert_eq!(
+            compare(&expected, &changed),
+            [
+                "status: expected 200, got 201",
+                "body:",
+                "  {",
+                "    \"id\": 1,",
+                "-   \"name\": \"one\"",
+                "+   \"name\": \"uno\"",
+                "  }",
+            ]
+        );
+
+        let text = response(200, "text/plain", "a\nb\nc");
+        assert_eq!(
+            compare(&text, &response(200, "text/html", "a\nc\nd")),
+            [
+                "content-type: expected text/plain, got text/html",
+                "body:",
+                "  a",
+                "- b",
+                "  c",
+                "+ d",
+            ]
+        );
+    }
+}
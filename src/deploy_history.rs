@@ -0,0 +1,146 @@
+//! The deploy history: an append-only audit log of the deploys made by
+//! `spin deploy` on this machine, one JSON record per line.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+
+/// The name of the deploy history file, in the Spin config directory.
+pub(crate) const HISTORY_FILE: &str = "deploy-history.jsonl";
+
+/// A deploy, as recorded in the deploy history.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) struct DeployRecord {
+    /// When the deploy completed, in RFC 3339 format.
+    pub timestamp: String,
+    /// The name the application was deployed as.
+    pub app: String,
+    /// The version deployed, or that was being deployed if the deploy failed.
+    pub version: String,
+    /// The bindle deployed, if the deploy pushed one and succeeded.
+    #[serde(default)]
+    pub bindle_id: Option<String>,
+    /// The URL of the Hippo server deployed to.
+    pub hippo_server: String,
+    /// The Hippo channel deployed to.
+    pub channel: String,
+    /// The environment deployed to, if any.
+    #[serde(default)]
+    pub environment: Option<String>,
+    /// The user who deployed: the Hippo user if known, or the local user.
+    #[serde(default)]
+    pub user: Option<String>,
+    /// Whether the deploy succeeded.
+    pub success: bool,
+    /// The error the deploy failed with, if any.
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+/// Appends a record to the deploy history file, creating it if needed.
+/// Existing records are never rewritten.
+pub(crate) async fn append(path: &Path, record: &DeployRecord) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+    let mut line = serde_json::to_string(record)?;
+    line.push('\n');
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await
+        .with_context(|| format!("Cannot open deploy history {}", path.display()))?;
+    file.write_all(line.as_bytes())
+        .await
+        .with_context(|| format!("Cannot write deploy history {}", path.display()))
+}
+
+/// Loads the records of the deploy history file, oldest first. A missing
+/// file is an empty history.
+pub(crate) async fn load(path: &Path) -> Result<Vec<DeployRecord>> {
+    let contents = match tokio::fs::read_to_string(path).await {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => {
+            return Err(e).with_context(|| format!("Cannot read deploy history {}", path.display()))
+        }
+    };
+    contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            serde_json::from_str(line).with_context(|| {
+                format!(
+                    "Invalid record on line {} of deploy history {}",
+                    index + 1,
+                    path.display()
+                )
+            })
+        })
+        .collect()
+}
+
+/// Posts a record to a remote audit webhook, as a JSON document.
+pub(crate) async fn post(url: &str, record: &DeployRecord) -> Result<()> {
+    reqwest::Client::new()
+        .post(url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(serde_json::to_string(record)?)
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+/// The path of the deploy history file.
+pub(crate) fn history_path() -> Result<PathBuf> {
+    let dir = dirs::config_dir().ok_or_else(|| anyhow!("Cannot find the config directory"))?;
+    Ok(dir.join("spin").join(HISTORY_FILE))
+}
+
+/// The name of the local user, if known.
+pub(crate) fn local_user() -> Option<String> {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(app: &str, success: bool) -> DeployRecord {
+        DeployRecord {
+            timestamp: "2022-06-01T12:00:00Z".to_owned(),
+            app: app.to_owned(),
+            version: "1.0.0".to_owned(),
+            bindle_id: success.then(|| format!("{}/1.0.0", app)),
+            hippo_server: "https://hippo.example.com".to_owned(),
+            channel: "spin-deploy".to_owned(),
+            environment: None,
+            user: Some("alice".to_owned()),
+            success,
+            error: (!success).then(|| "Problem creating a channel in Hippo".to_owned()),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_history_is_appended() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("spin").join(HISTORY_FILE);
+        assert!(load(&path).await?.is_empty());
+
+        append(&path, &record("hello", true)).await?;
+        append(&path, &record("goodbye", false)).await?;
+        assert_eq!(
+            load(&path).await?,
+            vec![record("hello", true), record("goodbye", false)]
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,244 @@
+//! Deploy profiles: settings for `spin deploy` that are specific to the
+//! environment an application is deployed to, rather than to the application.
+
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::Deserialize;
+
+use crate::expand::{expand_env_vars, expand_opt};
+
+/// The message sent when no template is configured.
+const DEFAULT_TEMPLATE: &str =
+    "Deploy of {{ app }} version {{ version }} to {{ environment }}: {{ result }}";
+
+/// Settings for deploying an application to an environment.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub(crate) struct DeployProfile {
+    /// The name of the environment, used in notifications.
+    #[serde(default)]
+    pub environment: Option<String>,
+    /// Endpoints notified when a deploy completes.
+    #[serde(default, rename = "notification")]
+    pub notifications: Vec<Notification>,
+}
+
+impl DeployProfile {
+    /// Loads a deploy profile from a TOML file, expanding the environment
+    /// variables referenced in its environment and notification URLs.
+    pub async fn from_file(path: &Path) -> Result<Self> {
+        let contents = tokio::fs::read_to_string(path)
+            .await
+            .with_context(|| format!("Cannot read deploy profile {}", path.display()))?;
+        let mut profile: Self = toml::from_str(&contents)
+            .with_context(|| format!("Cannot parse deploy profile {}", path.display()))?;
+        profile
+            .expand_env_vars()
+            .with_context(|| format!("Invalid deploy profile {}", path.display()))?;
+        Ok(profile)
+    }
+
+    fn expand_env_vars(&mut self) -> Result<()> {
+        expand_opt(&mut self.environment)?;
+        for notification in &mut self.notifications {
+            notification.url = expand_env_vars(&notification.url)?;
+        }
+        Ok(())
+    }
+
+    /// Posts the outcome of a deploy to the endpoints configured for it.
+    /// Notifications that fail are reported, but do not fail the deploy.
+    pub async fn notify(&self, outcome: &DeployOutcome<'_>) {
+        if self.notifications.is_empty() {
+            return;
+        }
+        let environment = self.environment.as_deref().unwrap_or("default");
+        let client = reqwest::Client::new();
+        for notification in &self.notifications {
+            if !notification.on.contains(&outcome.result()) {
+                continue;
+            }
+            if let Err(e) = notification.send(&client, environment, outcome).await {
+                eprintln!(
+                    "Warning: cannot send deploy notification to {}: {:#}",
+                    notification.url, e
+                );
+            }
+        }
+    }
+}
+
+/// When a notification is sent.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum DeployResult {
+    Success,
+    Failure,
+}
+
+impl DeployResult {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Success => "success",
+            Self::Failure => "failure",
+        }
+    }
+}
+
+fn default_on() -> Vec<DeployResult> {
+    vec![DeployResult::Success, DeployResult::Failure]
+}
+
+/// The kind of endpoint a notification is posted to.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum NotificationKind {
+    /// A JSON document with the details of the deploy and the message.
+    Webhook,
+    /// A Slack incoming webhook message.
+    Slack,
+}
+
+/// An endpoint notified when a deploy completes.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub(crate) struct Notification {
+    /// The kind of endpoint.
+    pub kind: NotificationKind,
+    /// The URL the notification is posted to.
+    pub url: String,
+    /// The results the notification is sent for. Defaults to all.
+    #[serde(default = "default_on")]
+    pub on: Vec<DeployResult>,
+    /// The message template. `{{ app }}`, `{{ version }}`,
+    /// `{{ environment }}`, `{{ routes }}`, `{{ result }}` and `{{ error }}`
+    /// are replaced with the details of the deploy.
+    pub template: Option<String>,
+}
+
+impl Notification {
+    async fn send(
+        &self,
+        client: &reqwest::Client,
+        environment: &str,
+        outcome: &DeployOutcome<'_>,
+    ) -> Result<()> {
+        let routes = outcome.routes.join("\n");
+        let error = outcome
+            .error
+            .map(|e| format!("{:#}", e))
+            .unwrap_or_default();
+        let template = self.template.as_deref().unwrap_or(DEFAULT_TEMPLATE);
+        let message = render(template, |name| match name {
+            "app" => Ok(outcome.app),
+            "version" => Ok(outcome.version),
+            "environment" => Ok(environment),
+            "routes" => Ok(routes.as_str()),
+            "result" => Ok(outcome.result().as_str()),
+            "error" => Ok(error.as_str()),
+            _ => Err(anyhow!("unknown variable")),
+        })?;
+
+        let body = match self.kind {
+            NotificationKind::Webhook => serde_json::json!({
+                "app": outcome.app,
+                "version": outcome.version,
+                "environment": environment,
+                "routes": outcome.routes,
+                "result": outcome.result().as_str(),
+                "error": outcome.error.map(|e| format!("{:#}", e)),
+                "message": message,
+            }),
+            NotificationKind::Slack => serde_json::json!({ "text": message }),
+        };
+        client
+            .post(&self.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body.to_string())
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+/// The details of a completed deploy.
+pub(crate) struct DeployOutcome<'a> {
+    pub app: &'a str,
+    pub version: &'a str,
+    /// The URLs of the routes of the application, if it was deployed.
+    pub routes: Vec<String>,
+    /// The error the deploy failed with, if any.
+    pub error: Option<&'a anyhow::Error>,
+}
+
+impl DeployOutcome<'_> {
+    fn result(&self) -> DeployResult {
+        match self.error {
+            Some(_) => DeployResult::Failure,
+            None => DeployResult::Success,
+        }
+    }
+}
+
+fn render<'a>(template: &str, resolve: impl Fn(&str) -> Result<&'a str>) -> Result<String> {
+    let mut rendered = String::with_capacity(template.len());
+    let mut remainder = template;
+    while let Some(start) = remainder.find("{{") {
+        rendered.push_str(&remainder[..start]);
+        let (expr, rest) = remainder[start + 2..]
+            .split_once("}}")
+            .ok_or_else(|| anyhow!("unmatched '{{{{' in notification template"))?;
+        let name = expr.trim();
+        if name.is_empty() {
+            bail!("empty expression in notification template");
+        }
+        rendered
+            .push_str(resolve(name).with_context(|| format!("Cannot resolve variable {}", name))?);
+        remainder = rest;
+    }
+    rendered.push_str(remainder);
+    Ok(rendered)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_profile() -> Result<()> {
+        let profile: DeployProfile = toml::from_str(
+            r#"
+            environment = "staging"
+
+            [[notification]]
+            kind = "slack"
+            url = "https://hooks.slack.com/services/x"
+            on = ["failure"]
+
+            [[notification]]
+            kind = "webhook"
+            url = "https://ci.example.com/deploys"
+            template = "{{ app }} is {{result}}"
+            "#,
+        )?;
+        assert_eq!(profile.environment.as_deref(), Some("staging"));
+        assert_eq!(profile.notifications[0].on, vec![DeployResult::Failure]);
+        assert_eq!(profile.notifications[1].on, default_on());
+        assert_eq!(profile.notifications[1].kind, NotificationKind::Webhook);
+        Ok(())
+    }
+
+    #[test]
+    fn test_render() -> Result<()> {
+        let resolve = |name: &str| match name {
+            "app" => Ok("hello"),
+            _ => Err(anyhow!("unknown variable")),
+        };
+        assert_eq!(render("{{ app }} deployed", resolve)?, "hello deployed");
+        assert!(render("{{ nope }}", resolve).is_err());
+        assert!(render("{{ app", resolve).is_err());
+        Ok(())
+    }
+}
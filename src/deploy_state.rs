@@ -0,0 +1,148 @@
+//! The progress of a deploy, recorded in the application directory so that a
+//! deploy interrupted between pushing the application and updating its Hippo
+//! channel can be resumed or rolled back with `spin deploy --resume` or
+//! `--abort`.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::commands::scale::ChannelScale;
+
+/// The file, relative to the application directory, recording the deploy in
+/// progress.
+const DEPLOY_STATE_FILE: &str = ".spin/deploy-state.json";
+
+/// A deploy in progress.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) struct DeployState {
+    /// When the deploy started, in RFC 3339 format.
+    pub started_at: String,
+    /// The URL of the Hippo server deployed to.
+    pub hippo_server: String,
+    /// The name the application is deployed as.
+    pub app: String,
+    /// The Hippo channel deployed to.
+    pub channel: String,
+    /// Where Hippo finds the revisions of the application: its bindle name,
+    /// or its OCI repository.
+    pub storage_id: String,
+    /// The revision pushed.
+    pub revision: String,
+    /// The bindle pushed, if the application was pushed to a bindle server.
+    pub bindle_id: Option<String>,
+    /// How far the deploy got.
+    pub stage: DeployStage,
+}
+
+/// How far a deploy got.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case", tag = "stage")]
+pub(crate) enum DeployStage {
+    /// The revision is pushed, and the Hippo channel is unchanged.
+    Pushed,
+    /// The channel is being recreated for the new revision, with what is
+    /// needed to restore it.
+    ChannelRemoved {
+        /// The revision the channel served, if any.
+        previous_revision: Option<String>,
+        /// The variables of the channel.
+        variables: Vec<(String, String)>,
+        /// The scale of the channel, if Hippo supports scaling.
+        scale: Option<ChannelScale>,
+    },
+}
+
+impl DeployState {
+    /// The path of the deploy state file of the application with the given
+    /// manifest.
+    pub fn path(app_file: &Path) -> Result<PathBuf> {
+        Ok(crate::app_dir(app_file)?.join(DEPLOY_STATE_FILE))
+    }
+
+    /// Loads the deploy in progress, if any.
+    pub async fn load(path: &Path) -> Result<Option<Self>> {
+        match tokio::fs::read(path).await {
+            Ok(contents) => serde_json::from_slice(&contents)
+                .map(Some)
+                .with_context(|| format!("Cannot parse deploy state {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => {
+                Err(e).with_context(|| format!("Cannot read deploy state {}", path.display()))
+            }
+        }
+    }
+
+    /// Saves the deploy in progress, readable only by the user as it may
+    /// hold the variables of the channel.
+    pub async fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+        // Written aside and renamed, so that an interrupted write does not
+        // lose the state of the last stage.
+        let temp_path = path.with_extension("json.tmp");
+        tokio::fs::write(&temp_path, serde_json::to_vec_pretty(self)?)
+            .await
+            .with_context(|| format!("Cannot write deploy state {}", path.display()))?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            tokio::fs::set_permissions(&temp_path, std::fs::Permissions::from_mode(0o600)).await?;
+        }
+        tokio::fs::rename(&temp_path, path)
+            .await
+            .with_context(|| format!("Cannot write deploy state {}", path.display()))
+    }
+
+    /// Removes the deploy state once the deploy completed or was rolled
+    /// back.
+    pub async fn remove(path: &Path) -> Result<()> {
+        match tokio::fs::remove_file(path).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                Err(e).with_context(|| format!("Cannot remove deploy state {}", path.display()))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_deploy_state_round_trip() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = DeployState::path(&dir.path().join("spin.toml"))?;
+        assert!(DeployState::load(&path).await?.is_none());
+
+        let mut state = DeployState {
+            started_at: "2022-06-01T12:00:00Z".to_owned(),
+            hippo_server: "https://hippo.example.com".to_owned(),
+            app: "hello".to_owned(),
+            channel: "spin-deploy".to_owned(),
+            storage_id: "hello".to_owned(),
+            revision: "1.0.1".to_owned(),
+            bindle_id: Some("hello/1.0.1".to_owned()),
+            stage: DeployStage::Pushed,
+        };
+        state.save(&path).await?;
+        assert_eq!(DeployState::load(&path).await?, Some(state.clone()));
+
+        state.stage = DeployStage::ChannelRemoved {
+            previous_revision: Some("1.0.0".to_owned()),
+            variables: vec![("LOG_LEVEL".to_owned(), "warn".to_owned())],
+            scale: None,
+        };
+        state.save(&path).await?;
+        assert_eq!(DeployState::load(&path).await?, Some(state));
+
+        DeployState::remove(&path).await?;
+        assert!(DeployState::load(&path).await?.is_none());
+        DeployState::remove(&path).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,109 @@
+//! Expansion of environment variables in deploy settings, so that one deploy
+//! profile or command line can serve several CI contexts.
+
+use anyhow::{anyhow, bail, Result};
+
+/// Expands the environment variables referenced in a value: `${VAR}` is
+/// replaced with the value of `VAR`, failing if it is not set,
+/// `${VAR:-default}` with `default` if `VAR` is unset or empty, and
+/// `${VAR-default}` with `default` if `VAR` is unset. `$${` is a literal `${`.
+pub(crate) fn expand_env_vars(value: &str) -> Result<String> {
+    expand(value, |name| std::env::var(name).ok())
+}
+
+/// Expands the environment variables referenced in an optional value.
+pub(crate) fn expand_opt(value: &mut Option<String>) -> Result<()> {
+    if let Some(v) = value {
+        *v = expand_env_vars(v)?;
+    }
+    Ok(())
+}
+
+fn expand(value: &str, lookup: impl Fn(&str) -> Option<String>) -> Result<String> {
+    let mut expanded = String::with_capacity(value.len());
+    let mut remainder = value;
+    while let Some(start) = remainder.find('$') {
+        expanded.push_str(&remainder[..start]);
+        let rest = &remainder[start..];
+        if let Some(rest) = rest.strip_prefix("$${") {
+            expanded.push_str("${");
+            remainder = rest;
+            continue;
+        }
+        let rest = match rest.strip_prefix("${") {
+            Some(rest) => rest,
+            None => {
+                // A lone '$' is kept as is.
+                expanded.push('$');
+                remainder = &rest[1..];
+                continue;
+            }
+        };
+        let (expr, rest) = rest
+            .split_once('}')
+            .ok_or_else(|| anyhow!("Unmatched '${{' in '{}'", value))?;
+        expanded.push_str(&resolve(expr, &lookup).map_err(|e| anyhow!("{} in '{}'", e, value))?);
+        remainder = rest;
+    }
+    expanded.push_str(remainder);
+    Ok(expanded)
+}
+
+fn resolve(expr: &str, lookup: &impl Fn(&str) -> Option<String>) -> Result<String> {
+    let (name, default) = match expr.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')) {
+        None => (expr, None),
+        Some(index) => {
+            let (name, operator) = expr.split_at(index);
+            match operator.strip_prefix(":-") {
+                Some(default) => (name, Some((default, true))),
+                None => match operator.strip_prefix('-') {
+                    Some(default) => (name, Some((default, false))),
+                    None => bail!("Invalid expression '${{{}}}'", expr),
+                },
+            }
+        }
+    };
+    if name.is_empty() {
+        bail!("Missing variable name in '${{{}}}'", expr);
+    }
+    match (lookup(name), default) {
+        (Some(value), Some((default, true))) if value.is_empty() => Ok(default.to_owned()),
+        (Some(value), _) => Ok(value),
+        (None, Some((default, _))) => Ok(default.to_owned()),
+        (None, None) => bail!("Environment variable {} is not set", name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "BRANCH" => Some("main".to_owned()),
+            "EMPTY" => Some(String::new()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_expand() -> Result<()> {
+        assert_eq!(
+            expand("https://bindle-${BRANCH}.example.com", lookup)?,
+            "https://bindle-main.example.com"
+        );
+        assert_eq!(expand("${MISSING:-staging}", lookup)?, "staging");
+        assert_eq!(expand("${EMPTY:-staging}", lookup)?, "staging");
+        assert_eq!(expand("${EMPTY-staging}", lookup)?, "");
+        assert_eq!(expand("${MISSING-}", lookup)?, "");
+        assert_eq!(expand("$${BRANCH}", lookup)?, "${BRANCH}");
+        assert_eq!(expand("$5 on ${BRANCH}", lookup)?, "$5 on main");
+        assert_eq!(expand("no variables", lookup)?, "no variables");
+
+        assert!(expand("${MISSING}", lookup).is_err());
+        assert!(expand("${BRANCH", lookup).is_err());
+        assert!(expand("${BRANCH?}", lookup).is_err());
+        assert!(expand("${:-x}", lookup).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,366 @@
+//! Fuzzing of HTTP components: inputs, their mutation, and the corpus of
+//! inputs kept between runs.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// The directory fuzzing state is kept in, relative to the application
+/// directory, with a subdirectory per component.
+pub(crate) const FUZZ_DIR: &str = ".spin/fuzz";
+
+/// Methods requests are sent with.
+const METHODS: &[&str] = &["GET", "POST", "PUT", "PATCH", "DELETE", "HEAD", "OPTIONS"];
+
+/// Headers added to requests, with values mutated.
+const HEADERS: &[&str] = &[
+    "content-type",
+    "accept",
+    "authorization",
+    "cookie",
+    "user-agent",
+    "x-forwarded-for",
+    "content-encoding",
+];
+
+/// Values likely to reach edge cases in parsers.
+const INTERESTING: &[&[u8]] = &[
+    b"",
+    b"0",
+    b"-1",
+    b"4294967296",
+    b"null",
+    b"{}",
+    b"[]",
+    b"{\"\":",
+    b"\"",
+    b"'",
+    b"../",
+    b"%00",
+    b"%",
+    b"\r\n",
+    b"\xff\xfe",
+    b"\xc3\x28",
+    b"application/json",
+    b"text/plain; charset=utf-16",
+];
+
+/// The largest body mutations grow a body to.
+const MAX_BODY: usize = 64 * 1024;
+
+/// A request sent to a component.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq, Hash)]
+pub(crate) struct FuzzInput {
+    pub method: String,
+    /// The path and query of the request.
+    pub path: String,
+    #[serde(default)]
+    pub headers: Vec<(String, String)>,
+    #[serde(default)]
+    pub body: Vec<u8>,
+}
+
+impl FuzzInput {
+    /// A short, stable identifier of the input, used to name its files.
+    pub fn id(&self) -> String {
+        let json = serde_json::to_vec(self).unwrap_or_default();
+        let digest = Sha256::digest(&json);
+        digest[..8].iter().map(|b| format!("{:02x}", b)).collect()
+    }
+}
+
+/// An input of the corpus, with the status it got when it was added, if the
+/// component responded.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub(crate) struct CorpusEntry {
+    pub input: FuzzInput,
+    #[serde(default)]
+    pub status: Option<u16>,
+}
+
+/// An input the component failed on, and how it failed.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub(crate) struct Failure {
+    pub input: FuzzInput,
+    pub failure: String,
+}
+
+/// The corpus and failures of a component, in the fuzzing directory.
+pub(crate) struct FuzzDir {
+    dir: PathBuf,
+}
+
+impl FuzzDir {
+    pub fn new(app_dir: &Path, component: &str) -> Self {
+        Self {
+            dir: app_dir.join(FUZZ_DIR).join(component),
+        }
+    }
+
+    pub fn failures_dir(&self) -> PathBuf {
+        self.dir.join("failures")
+    }
+
+    /// The inputs of the corpus, in a stable order.
+    pub async fn load_corpus(&self) -> Result<Vec<CorpusEntry>> {
+        let corpus_dir = self.dir.join("corpus");
+        let mut entries = match tokio::fs::read_dir(&corpus_dir).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("Cannot read corpus {}", corpus_dir.display()))
+            }
+        };
+        let mut paths = vec![];
+        while let Some(entry) = entries.next_entry().await? {
+            if entry.path().extension().map_or(false, |e| e == "json") {
+                paths.push(entry.path());
+            }
+        }
+        paths.sort();
+
+        let mut corpus = vec![];
+        for path in paths {
+            let contents = tokio::fs::read(&path).await?;
+            let entry = serde_json::from_slice(&contents)
+                .with_context(|| format!("Invalid corpus entry {}", path.display()))?;
+            corpus.push(entry);
+        }
+        Ok(corpus)
+    }
+
+    pub async fn save_corpus_entry(&self, entry: &CorpusEntry) -> Result<()> {
+        let path = self
+            .dir
+            .join("corpus")
+            .join(format!("{}.json", entry.input.id()));
+        write_json(&path, entry).await
+    }
+
+    /// Saves a failure, returning the path it was saved to.
+    pub async fn save_failure(&self, failure: &Failure) -> Result<PathBuf> {
+        let path = self
+            .failures_dir()
+            .join(format!("{}.json", failure.input.id()));
+        write_json(&path, failure).await?;
+        Ok(path)
+    }
+}
+
+async fn write_json(path: &Path, value: &impl Serialize) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+    let mut contents = serde_json::to_string_pretty(value)?;
+    contents.push('\n');
+    tokio::fs::write(path, contents)
+        .await
+        .with_context(|| format!("Cannot write {}", path.display()))
+}
+
+/// The inputs fuzzing starts from when the corpus is empty: requests to the
+/// route of the component.
+pub(crate) fn seeds(route_path: &str) -> Vec<FuzzInput> {
+    let input = |method: &str, headers: &[(&str, &str)], body: &[u8]| FuzzInput {
+        method: method.to_owned(),
+        path: route_path.to_owned(),
+        headers: headers
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect(),
+        body: body.to_vec(),
+    };
+    vec![
+        input("GET", &[], b""),
+        input("POST", &[("content-type", "application/json")], b"{}"),
+        input("POST", &[("content-type", "text/plain")], b"hello"),
+    ]
+}
+
+/// A small deterministic pseudo-random number generator (xorshift64*), so
+/// that a run can be repeated from its seed.
+pub(crate) struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // The state must not be zero.
+        Self(seed ^ 0x9e37_79b9_7f4a_7c15)
+    }
+
+    pub fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// A number below `n`, which must not be zero.
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.below(items.len())]
+    }
+}
+
+/// Mutates an input, keeping its path under the given route prefix so that
+/// requests still reach the component.
+pub(crate) fn mutate(input: &FuzzInput, route_prefix: &str, rng: &mut Rng) -> FuzzInput {
+    let mut input = input.clone();
+    // Several mutations are stacked, as single ones rarely go far.
+    for _ in 0..=rng.below(3) {
+        match rng.below(8) {
+            0 => input.method = rng.pick(METHODS).to_string(),
+            1 => input.path = mutate_path(&input.path, route_prefix, rng),
+            2 => {
+                let name = rng.pick(HEADERS).to_string();
+                let value = header_value(rng);
+                match input.headers.iter_mut().find(|(n, _)| *n == name) {
+                    Some(header) => header.1 = value,
+                    None => input.headers.push((name, value)),
+                }
+            }
+            3 if !input.headers.is_empty() => {
+                let index = rng.below(input.headers.len());
+                input.headers.remove(index);
+            }
+            4 if !input.body.is_empty() => {
+                let index = rng.below(input.body.len());
+                input.body[index] ^= 1 << rng.below(8);
+            }
+            5 => {
+                let index = rng.below(input.body.len() + 1);
+                let bytes = rng.pick(INTERESTING).to_vec();
+                input.body.splice(index..index, bytes);
+            }
+            6 if !input.body.is_empty() => {
+                let len = rng.below(input.body.len());
+                input.body.truncate(len);
+            }
+            _ if !input.body.is_empty() => {
+                // Repeats a chunk, growing the body to find size limits.
+                let start = rng.below(input.body.len());
+                let end = start + 1 + rng.below(input.body.len() - start);
+                let chunk = input.body[start..end].repeat(1 + rng.below(64));
+                input.body.splice(end..end, chunk);
+            }
+            _ => input.body = rng.pick(INTERESTING).to_vec(),
+        }
+    }
+    input.body.truncate(MAX_BODY);
+    input
+}
+
+fn mutate_path(path: &str, route_prefix: &str, rng: &mut Rng) -> String {
+    let (path, _) = path.split_once('?').unwrap_or((path, ""));
+    match rng.below(3) {
+        // Wildcard routes take any path under their prefix; exact routes
+        // only get new query strings.
+        0 if route_prefix.ends_with('/') => {
+            let mut segment = vec![];
+            for _ in 0..=rng.below(3) {
+                segment.extend_from_slice(rng.pick(INTERESTING));
+            }
+            format!("{}{}", route_prefix, percent_encode(&segment))
+        }
+        1 if route_prefix.ends_with('/') => {
+            let repeat = 1 + rng.below(256);
+            format!("{}{}", route_prefix, "a/".repeat(repeat))
+        }
+        _ => {
+            let value = percent_encode(rng.pick(INTERESTING));
+            format!("{}?q={}&q={}", path, value, rng.below(1 << 16))
+        }
+    }
+}
+
+/// Percent-encodes the bytes that cannot appear as they are in a path.
+fn percent_encode(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|&b| {
+            if b.is_ascii_alphanumeric() || b"-._~".contains(&b) {
+                (b as char).to_string()
+            } else {
+                format!("%{:02X}", b)
+            }
+        })
+        .collect()
+}
+
+/// A printable header value, as header values must be.
+fn header_value(rng: &mut Rng) -> String {
+    let value = rng.pick(INTERESTING);
+    let value: String = value
+        .iter()
+        .filter(|b| (0x20..0x7f).contains(*b))
+        .map(|&b| b as char)
+        .collect();
+    value.repeat(1 + rng.below(4))
+}
+
+/// A summary of how a component responded, used to keep inputs that make it
+/// respond in new ways.
+pub(crate) fn signature(status: u16, content_type: Option<&str>, body_len: usize) -> String {
+    // Body lengths are bucketed by order of magnitude, so that responses
+    // echoing their input do not make every input new.
+    let magnitude = usize::BITS - body_len.leading_zeros();
+    format!("{} {} {}", status, content_type.unwrap_or("-"), magnitude)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mutations_are_valid_and_repeatable() {
+        let seeds = seeds("/api/");
+        let run = |seed| {
+            let mut rng = Rng::new(seed);
+            let mut input = seeds[1].clone();
+            (0..500)
+                .map(|_| {
+                    input = mutate(&input, "/api/", &mut rng);
+                    input.clone()
+                })
+                .collect::<Vec<_>>()
+        };
+        let inputs = run(42);
+        assert_eq!(inputs, run(42));
+        assert_ne!(inputs, run(43));
+
+        for input in &inputs {
+            assert!(input.path.starts_with("/api/"), "{}", input.path);
+            assert!(
+                format!("http://localhost{}", input.path)
+                    .parse::<hyper::Uri>()
+                    .is_ok(),
+                "{}",
+                input.path
+            );
+            assert!(input
+                .headers
+                .iter()
+                .all(|(_, v)| hyper::header::HeaderValue::from_str(v).is_ok()));
+            assert!(input.body.len() <= MAX_BODY);
+        }
+    }
+
+    #[test]
+    fn test_exact_routes_keep_their_path() {
+        let mut rng = Rng::new(7);
+        let mut input = seeds("/health").remove(0);
+        for _ in 0..200 {
+            input = mutate(&input, "/health", &mut rng);
+            assert!(
+                input.path == "/health" || input.path.starts_with("/health?"),
+                "{}",
+                input.path
+            );
+        }
+    }
+}
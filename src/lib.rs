@@ -1,11 +1,36 @@
+mod capabilities;
 pub mod commands;
+mod contract;
+mod deploy_history;
+mod deploy_profile;
+mod deploy_state;
+mod expand;
+mod fuzz;
+mod network;
+mod oidc;
 pub(crate) mod opts;
+mod plugins;
 mod sloth;
+mod trust;
+mod watch;
 
-use std::path::{Path, PathBuf};
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+};
 
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, Context, Result};
 use semver::BuildMetadata;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use spin_loader::{
+    file_sha256_digest_string,
+    local::{
+        assets,
+        config::{RawAppInformation, RawAppManifest, RawModuleSource},
+    },
+};
+use spin_manifest::{ComponentLimits, LoadPolicy, TriggerConfig};
 
 pub(crate) fn app_dir(app_file: impl AsRef<Path>) -> Result<PathBuf> {
     let path_buf = app_file
@@ -32,3 +57,240 @@ pub(crate) fn write_failed_msg(bindle_id: &bindle::Id, dest_dir: &Path) -> Strin
 pub(crate) fn parse_buildinfo(buildinfo: &str) -> Result<BuildMetadata> {
     Ok(BuildMetadata::new(buildinfo)?)
 }
+
+/// Computes build metadata from the content of the application: its
+/// settings, and the Wasm modules and the asset files of its components.
+///
+/// The content is summarised as a list of entries: the settings of the
+/// application and of each component, in a canonical serialization, and
+/// the '/'-separated path of each file and the digest of its content,
+/// sorted within each component so that the result does not depend on the
+/// order in which the filesystem lists files. Components are listed in
+/// manifest order, as the order of components is meaningful. Changes to
+/// the formatting of the manifest, or to settings that do not affect the
+/// application at runtime, such as build commands, do not change the result.
+pub(crate) fn compute_buildinfo(app_file: &Path, cfg: &RawAppManifest) -> Result<BuildMetadata> {
+    let mut sha256 = Sha256::new();
+    for entry in buildinfo_entries(app_file, cfg)? {
+        sha256.update(entry.as_bytes());
+        sha256.update(b"\n");
+    }
+
+    let mut final_digest = format!("q{:x}", sha256.finalize());
+    final_digest.truncate(8);
+
+    let buildinfo =
+        BuildMetadata::new(&final_digest).with_context(|| "Could not compute build info")?;
+
+    Ok(buildinfo)
+}
+
+fn buildinfo_entries(app_file: &Path, cfg: &RawAppManifest) -> Result<Vec<String>> {
+    let source_dir = app_dir(app_file)?;
+    let digest = |path: &Path| {
+        file_sha256_digest_string(path)
+            .with_context(|| anyhow!("Cannot open file {}", path.display()))
+    };
+
+    let application = ApplicationSettings {
+        info: &cfg.info,
+        variables: &cfg.config,
+    };
+    let mut entries = vec![format!("application {}", canonical_json(&application)?)];
+    for (index, x) in cfg.components.iter().enumerate() {
+        entries.push(format!("component {} {}", index, x.id));
+        let settings = ComponentSettings {
+            description: &x.description,
+            trigger: &x.trigger,
+            environment: &x.wasm.environment,
+            allowed_http_hosts: &x.wasm.allowed_http_hosts,
+            allowed_blob_containers: &x.wasm.allowed_blob_containers,
+            allowed_database_hosts: x.wasm.allowed_database_hosts.as_ref(),
+            key_value_stores: x.wasm.key_value_stores.as_ref(),
+            allowed_jwt_issuers: x.wasm.allowed_jwt_issuers.as_ref(),
+            allowed_crypto_keys: x.wasm.allowed_crypto_keys.as_ref(),
+            load: x.wasm.load.as_ref(),
+            limits: x.wasm.limits.as_ref(),
+            host_config: x.wasm.host_config.as_ref(),
+            config: &x.config,
+        };
+        entries.push(format!("settings {}", canonical_json(&settings)?));
+
+        let mut files = vec![];
+        match &x.source {
+            RawModuleSource::FileReference(p) => {
+                let full_path = source_dir.join(p);
+                files.push(("source", relative_path(p, &source_dir), digest(&full_path)?));
+            }
+            RawModuleSource::Bindle(b) => {
+                files.push(("source", b.reference.clone(), b.parcel.clone()));
+            }
+            RawModuleSource::Url(u) => {
+                files.push(("source", u.url.clone(), u.digest.clone()));
+            }
+        }
+        if let Some(mounts) = &x.wasm.files {
+            let exclude_files = x.wasm.exclude_files.clone().unwrap_or_default();
+            for f in assets::collect(mounts, &exclude_files, &source_dir)? {
+                files.push(("file", f.relative_dst, digest(&f.src)?));
+            }
+        }
+        files.sort();
+        entries.extend(
+            files
+                .into_iter()
+                .map(|(kind, path, digest)| format!("{} {} {}", kind, path, digest)),
+        );
+    }
+    Ok(entries)
+}
+
+/// The settings of an application that affect it at runtime.
+#[derive(Serialize)]
+struct ApplicationSettings<'a> {
+    info: &'a RawAppInformation,
+    variables: &'a Option<spin_config::Tree>,
+}
+
+/// The settings of a component that affect it at runtime, besides its
+/// Wasm module and files.
+#[derive(Serialize)]
+struct ComponentSettings<'a> {
+    description: &'a Option<String>,
+    trigger: &'a TriggerConfig,
+    environment: &'a Option<HashMap<String, String>>,
+    allowed_http_hosts: &'a Option<Vec<String>>,
+    allowed_blob_containers: &'a Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    allowed_database_hosts: Option<&'a Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    key_value_stores: Option<&'a Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    allowed_jwt_issuers: Option<&'a Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    allowed_crypto_keys: Option<&'a Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    load: Option<&'a LoadPolicy>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    limits: Option<&'a ComponentLimits>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    host_config: Option<&'a HashMap<String, toml::Value>>,
+    config: &'a Option<HashMap<String, String>>,
+}
+
+/// Serializes a value as JSON with the keys of every object sorted, so that
+/// maps serialize the same whatever their iteration order.
+fn canonical_json(value: &impl Serialize) -> Result<String> {
+    fn sort_keys(value: serde_json::Value) -> serde_json::Value {
+        match value {
+            serde_json::Value::Object(map) => serde_json::Value::Object(
+                map.into_iter()
+                    .map(|(k, v)| (k, sort_keys(v)))
+                    .collect::<BTreeMap<_, _>>()
+                    .into_iter()
+                    .collect(),
+            ),
+            serde_json::Value::Array(values) => {
+                serde_json::Value::Array(values.into_iter().map(sort_keys).collect())
+            }
+            value => value,
+        }
+    }
+    Ok(sort_keys(serde_json::to_value(value)?).to_string())
+}
+
+/// The '/'-separated path of a file relative to a directory, or the
+/// path itself if it is not under the directory.
+fn relative_path(path: &Path, dir: impl AsRef<Path>) -> String {
+    path.strip_prefix(dir)
+        .unwrap_or(path)
+        .components()
+        .filter(|c| !matches!(c, std::path::Component::CurDir))
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use spin_loader::local::config::RawAppManifestAnyVersion;
+
+    use super::*;
+
+    const MANIFEST: &str = r#"
+        spin_version = "1"
+        name = "test"
+        version = "1.0.0"
+        trigger = { type = "http", base = "/" }
+
+        [[component]]
+        id = "app"
+        source = "app.wasm"
+        files = ["static/*"]
+        environment = { ONE = "1", TWO = "2" }
+        [component.trigger]
+        route = "/..."
+        "#;
+
+    fn buildinfo_of(dir: &Path, files: &[(&str, &str)]) -> Result<BuildMetadata> {
+        buildinfo_of_manifest(dir, files, MANIFEST)
+    }
+
+    fn buildinfo_of_manifest(
+        dir: &Path,
+        files: &[(&str, &str)],
+        manifest: &str,
+    ) -> Result<BuildMetadata> {
+        std::fs::create_dir_all(dir.join("static"))?;
+        std::fs::write(dir.join("app.wasm"), b"\0asm")?;
+        for (name, content) in files {
+            std::fs::write(dir.join("static").join(name), content)?;
+        }
+        let app_file = dir.join("spin.toml");
+        std::fs::write(&app_file, manifest)?;
+        let RawAppManifestAnyVersion::V1(cfg) = toml::from_str(manifest)?;
+        compute_buildinfo(&app_file, &cfg)
+    }
+
+    #[test]
+    fn test_buildinfo_is_independent_of_file_order() -> Result<()> {
+        let (first, second, renamed) = (
+            tempfile::tempdir()?,
+            tempfile::tempdir()?,
+            tempfile::tempdir()?,
+        );
+        let forward = buildinfo_of(first.path(), &[("a.txt", "a"), ("b.txt", "b")])?;
+        let backward = buildinfo_of(second.path(), &[("b.txt", "b"), ("a.txt", "a")])?;
+        assert_eq!(forward, backward);
+
+        // The same content at different paths is a different application.
+        let swapped = buildinfo_of(renamed.path(), &[("a.txt", "b"), ("b.txt", "a")])?;
+        assert_ne!(forward, swapped);
+        Ok(())
+    }
+
+    #[test]
+    fn test_buildinfo_covers_settings_but_not_formatting() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let files = [("a.txt", "a")];
+        let original = buildinfo_of(dir.path(), &files)?;
+
+        let reformatted = format!("# A comment\n{}", MANIFEST.replace(" = ", "="));
+        assert_eq!(
+            original,
+            buildinfo_of_manifest(dir.path(), &files, &reformatted)?
+        );
+
+        let changed_environment = MANIFEST.replace(r#"TWO = "2""#, r#"TWO = "two""#);
+        assert_ne!(
+            original,
+            buildinfo_of_manifest(dir.path(), &files, &changed_environment)?
+        );
+        let changed_route = MANIFEST.replace(r#"route = "/...""#, r#"route = "/app/...""#);
+        assert_ne!(
+            original,
+            buildinfo_of_manifest(dir.path(), &files, &changed_route)?
+        );
+        Ok(())
+    }
+}
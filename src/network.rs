@@ -0,0 +1,115 @@
+//! Proxy and certificate authority settings for commands that talk to
+//! bindle and Hippo servers, for example from behind a corporate proxy.
+
+use std::path::PathBuf;
+
+use anyhow::{bail, Context, Result};
+use clap::Parser;
+
+/// How to reach bindle and Hippo servers.
+#[derive(Parser, Clone, Debug, Default)]
+pub struct NetworkOpts {
+    /// Proxy to send requests to bindle and Hippo servers through, for
+    /// example http://proxy.example.com:3128 (defaults to the HTTPS_PROXY and
+    /// HTTP_PROXY environment variables)
+    #[clap(long = "proxy", env = "SPIN_PROXY")]
+    pub proxy: Option<String>,
+
+    /// PEM file of the certificate authorities to trust instead of the
+    /// system ones, for example including the one of a TLS-inspecting proxy
+    #[clap(long = "ca-bundle", env = "SPIN_CA_BUNDLE")]
+    pub ca_bundle: Option<PathBuf>,
+}
+
+impl NetworkOpts {
+    /// Applies the settings to the whole process. The bindle and Hippo
+    /// clients build their own HTTP clients, which take the proxy from the
+    /// HTTPS_PROXY and HTTP_PROXY environment variables, and the certificate
+    /// authorities from SSL_CERT_FILE where the TLS library reads it, as
+    /// OpenSSL does.
+    pub(crate) fn apply(&self) -> Result<()> {
+        if let Some(proxy) = &self.proxy {
+            reqwest::Proxy::all(proxy).with_context(|| format!("Invalid proxy {}", proxy))?;
+            std::env::set_var("HTTPS_PROXY", proxy);
+            std::env::set_var("HTTP_PROXY", proxy);
+        }
+        if let Some(ca_bundle) = &self.ca_bundle {
+            // Fails early on a missing or invalid bundle.
+            self.certificates()?;
+            let path = ca_bundle
+                .canonicalize()
+                .with_context(|| format!("Cannot find CA bundle {}", ca_bundle.display()))?;
+            std::env::set_var("SSL_CERT_FILE", path);
+        }
+        Ok(())
+    }
+
+    /// A builder of HTTP clients using the proxy and certificate authorities.
+    pub(crate) fn client_builder(&self, insecure: bool) -> Result<reqwest::ClientBuilder> {
+        let mut builder = reqwest::Client::builder().danger_accept_invalid_certs(insecure);
+        if let Some(proxy) = &self.proxy {
+            builder = builder.proxy(
+                reqwest::Proxy::all(proxy).with_context(|| format!("Invalid proxy {}", proxy))?,
+            );
+        }
+        if self.ca_bundle.is_some() {
+            builder = builder.tls_built_in_root_certs(false);
+            for certificate in self.certificates()? {
+                builder = builder.add_root_certificate(certificate);
+            }
+        }
+        Ok(builder)
+    }
+
+    fn certificates(&self) -> Result<Vec<reqwest::Certificate>> {
+        let path = match &self.ca_bundle {
+            Some(path) => path,
+            None => return Ok(vec![]),
+        };
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Cannot read CA bundle {}", path.display()))?;
+        let certificates = pem_certificates(&contents)
+            .into_iter()
+            .map(|pem| reqwest::Certificate::from_pem(pem.as_bytes()))
+            .collect::<reqwest::Result<Vec<_>>>()
+            .with_context(|| format!("Invalid certificate in CA bundle {}", path.display()))?;
+        if certificates.is_empty() {
+            bail!("CA bundle {} contains no certificates", path.display());
+        }
+        Ok(certificates)
+    }
+}
+
+/// The PEM blocks of the certificates in a bundle.
+fn pem_certificates(bundle: &str) -> Vec<&str> {
+    const BEGIN: &str = "-----BEGIN CERTIFICATE-----";
+    const END: &str = "-----END CERTIFICATE-----";
+    let mut certificates = vec![];
+    let mut remainder = bundle;
+    while let Some(start) = remainder.find(BEGIN) {
+        let end = match remainder[start..].find(END) {
+            Some(end) => start + end + END.len(),
+            None => break,
+        };
+        certificates.push(&remainder[start..end]);
+        remainder = &remainder[end..];
+    }
+    certificates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pem_certificates() {
+        let bundle = "# Corporate proxy\n-----BEGIN CERTIFICATE-----\nAAAA\n-----END CERTIFICATE-----\n\nIssuer: root\n-----BEGIN CERTIFICATE-----\nBBBB\n-----END CERTIFICATE-----\n-----BEGIN CERTIFICATE-----\ntruncated";
+        assert_eq!(
+            pem_certificates(bundle),
+            [
+                "-----BEGIN CERTIFICATE-----\nAAAA\n-----END CERTIFICATE-----",
+                "-----BEGIN CERTIFICATE-----\nBBBB\n-----END CERTIFICATE-----",
+            ]
+        );
+    }
+}
@@ -0,0 +1,281 @@
+//! Logging in to Hippo servers fronted by single sign-on, with the OpenID
+//! Connect device authorization grant (RFC 8628): the user approves the login
+//! in a browser, on any device, and Spin receives tokens it refreshes without
+//! asking again.
+
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::Utc;
+use serde::Deserialize;
+
+const DEVICE_CODE_GRANT: &str = "urn:ietf:params:oauth:grant-type:device_code";
+
+/// The interval between polls for tokens, unless the issuer says otherwise.
+const DEFAULT_POLL_INTERVAL: u64 = 5;
+
+/// An OpenID Connect issuer, with the client Spin is registered as.
+pub(crate) struct OidcProvider {
+    client_id: String,
+    metadata: Metadata,
+    http: reqwest::Client,
+}
+
+/// The endpoints of an issuer, from its discovery document.
+#[derive(Debug, Deserialize)]
+struct Metadata {
+    device_authorization_endpoint: Option<String>,
+    token_endpoint: String,
+    userinfo_endpoint: Option<String>,
+}
+
+/// A pending device authorization, for the user to approve.
+#[derive(Debug, Deserialize)]
+struct DeviceAuthorization {
+    device_code: String,
+    user_code: String,
+    // Some issuers predate the standard name.
+    #[serde(alias = "verification_url")]
+    verification_uri: String,
+    verification_uri_complete: Option<String>,
+    expires_in: u64,
+    #[serde(default = "default_poll_interval")]
+    interval: u64,
+}
+
+fn default_poll_interval() -> u64 {
+    DEFAULT_POLL_INTERVAL
+}
+
+/// Tokens issued by the token endpoint.
+#[derive(Debug, Deserialize)]
+pub(crate) struct Tokens {
+    pub access_token: String,
+    pub refresh_token: Option<String>,
+    /// The lifetime of the access token, in seconds.
+    pub expires_in: Option<i64>,
+}
+
+impl Tokens {
+    /// When the access token expires, in RFC 3339 format, as Hippo reports
+    /// the expiration of its own tokens.
+    pub fn expiration(&self) -> Option<String> {
+        self.expires_in
+            .map(|secs| (Utc::now() + chrono::Duration::seconds(secs)).to_rfc3339())
+    }
+}
+
+/// An error response of the token endpoint.
+#[derive(Debug, Deserialize)]
+struct TokenError {
+    error: String,
+    error_description: Option<String>,
+}
+
+/// The outcome of polling the token endpoint for a device authorization.
+#[derive(Debug)]
+enum Poll {
+    Issued(Tokens),
+    Pending,
+    SlowDown,
+}
+
+impl OidcProvider {
+    /// Fetches the discovery document of the issuer.
+    pub async fn discover(issuer: &str, client_id: &str, insecure: bool) -> Result<Self> {
+        let http = reqwest::Client::builder()
+            .danger_accept_invalid_certs(insecure)
+            .build()?;
+        let url = format!(
+            "{}/.well-known/openid-configuration",
+            issuer.trim_end_matches('/')
+        );
+        let metadata = http
+            .get(&url)
+            .send()
+            .await
+            .and_then(|res| res.error_for_status())
+            .with_context(|| format!("Cannot fetch the OpenID configuration from {}", url))?
+            .json()
+            .await
+            .with_context(|| format!("Invalid OpenID configuration at {}", url))?;
+        Ok(Self {
+            client_id: client_id.to_owned(),
+            metadata,
+            http,
+        })
+    }
+
+    /// Asks the user to approve the login in a browser, and waits for the
+    /// tokens issued once they do.
+    pub async fn device_login(&self, scopes: &[String]) -> Result<Tokens> {
+        let endpoint = self
+            .metadata
+            .device_authorization_endpoint
+            .as_deref()
+            .context("The issuer does not support the device authorization grant")?;
+        let mut scope = vec!["openid", "offline_access"];
+        scope.extend(scopes.iter().map(String::as_str));
+        let authorization: DeviceAuthorization = self
+            .http
+            .post(endpoint)
+            .form(&[
+                ("client_id", self.client_id.as_str()),
+                ("scope", &scope.join(" ")),
+            ])
+            .send()
+            .await
+            .and_then(|res| res.error_for_status())
+            .context("Cannot start the device authorization")?
+            .json()
+            .await
+            .context("Invalid device authorization response")?;
+
+        match &authorization.verification_uri_complete {
+            Some(uri) => println!("To log in, open {} in a browser", uri),
+            None => println!(
+                "To log in, open {} in a browser and enter the code {}",
+                authorization.verification_uri, authorization.user_code
+            ),
+        }
+
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(authorization.expires_in);
+        let mut interval = authorization.interval;
+        loop {
+            tokio::time::sleep(Duration::from_secs(interval)).await;
+            if tokio::time::Instant::now() > deadline {
+                bail!("The login was not approved before the code expired");
+            }
+            let params = [
+                ("grant_type", DEVICE_CODE_GRANT),
+                ("device_code", authorization.device_code.as_str()),
+                ("client_id", self.client_id.as_str()),
+            ];
+            match self.request_tokens(&params).await? {
+                Poll::Issued(tokens) => return Ok(tokens),
+                Poll::Pending => {}
+                // Polling too fast adds 5 seconds to the interval, per
+                // RFC 8628.
+                Poll::SlowDown => interval += 5,
+            }
+        }
+    }
+
+    /// Exchanges a refresh token for new tokens.
+    pub async fn refresh(&self, refresh_token: &str) -> Result<Tokens> {
+        let params = [
+            ("grant_type", "refresh_token"),
+            ("refresh_token", refresh_token),
+            ("client_id", self.client_id.as_str()),
+        ];
+        match self.request_tokens(&params).await? {
+            Poll::Issued(tokens) => Ok(tokens),
+            Poll::Pending | Poll::SlowDown => bail!("Unexpected response to a token refresh"),
+        }
+    }
+
+    /// The name of the user the access token was issued to, if the issuer
+    /// has a user info endpoint.
+    pub async fn username(&self, tokens: &Tokens) -> Option<String> {
+        #[derive(Deserialize)]
+        struct UserInfo {
+            sub: String,
+            preferred_username: Option<String>,
+            email: Option<String>,
+        }
+
+        let endpoint = self.metadata.userinfo_endpoint.as_deref()?;
+        let info: UserInfo = self
+            .http
+            .get(endpoint)
+            .bearer_auth(&tokens.access_token)
+            .send()
+            .await
+            .ok()?
+            .error_for_status()
+            .ok()?
+            .json()
+            .await
+            .ok()?;
+        Some(info.preferred_username.or(info.email).unwrap_or(info.sub))
+    }
+
+    async fn request_tokens(&self, params: &[(&str, &str)]) -> Result<Poll> {
+        let res = self
+            .http
+            .post(&self.metadata.token_endpoint)
+            .form(params)
+            .send()
+            .await
+            .context("Cannot reach the token endpoint")?;
+        let status = res.status();
+        let body = res.bytes().await?;
+        token_response(status, &body)
+    }
+}
+
+fn token_response(status: reqwest::StatusCode, body: &[u8]) -> Result<Poll> {
+    if status.is_success() {
+        let tokens = serde_json::from_slice(body).context("Invalid token response")?;
+        return Ok(Poll::Issued(tokens));
+    }
+    let error: TokenError = serde_json::from_slice(body)
+        .map_err(|_| anyhow!("The token endpoint returned {}", status))?;
+    match error.error.as_str() {
+        "authorization_pending" => Ok(Poll::Pending),
+        "slow_down" => Ok(Poll::SlowDown),
+        "access_denied" => bail!("The login was denied"),
+        "expired_token" => bail!("The login was not approved before the code expired"),
+        "invalid_grant" => bail!("The login has expired or was revoked: run `spin login` again"),
+        _ => bail!(
+            "The token endpoint returned {}: {}",
+            error.error,
+            error.error_description.unwrap_or_default()
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::StatusCode;
+
+    use super::*;
+
+    #[test]
+    fn test_token_response() {
+        let body = br#"{"access_token":"at","refresh_token":"rt","expires_in":3600,"token_type":"Bearer"}"#;
+        match token_response(StatusCode::OK, body).unwrap() {
+            Poll::Issued(tokens) => {
+                assert_eq!(tokens.access_token, "at");
+                assert_eq!(tokens.refresh_token.as_deref(), Some("rt"));
+                assert!(tokens.expiration().is_some());
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+
+        let error = |e: &str| format!(r#"{{"error":"{}"}}"#, e).into_bytes();
+        assert!(matches!(
+            token_response(StatusCode::BAD_REQUEST, &error("authorization_pending")).unwrap(),
+            Poll::Pending
+        ));
+        assert!(matches!(
+            token_response(StatusCode::BAD_REQUEST, &error("slow_down")).unwrap(),
+            Poll::SlowDown
+        ));
+        assert!(token_response(StatusCode::BAD_REQUEST, &error("access_denied")).is_err());
+        assert!(token_response(StatusCode::BAD_GATEWAY, b"<html>").is_err());
+    }
+
+    #[test]
+    fn test_device_authorization() {
+        let authorization: DeviceAuthorization = serde_json::from_str(
+            r#"{"device_code":"dc","user_code":"ABCD-EFGH","verification_url":"https://sso.example.com/device","expires_in":600}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            authorization.verification_uri,
+            "https://sso.example.com/device"
+        );
+        assert_eq!(authorization.interval, DEFAULT_POLL_INTERVAL);
+    }
+}
@@ -5,6 +5,7 @@ pub const BINDLE_SERVER_URL_OPT: &str = "BINDLE_SERVER_URL";
 pub const BINDLE_URL_ENV: &str = "BINDLE_URL";
 pub const BINDLE_USERNAME: &str = "BINDLE_USERNAME";
 pub const BINDLE_PASSWORD: &str = "BINDLE_PASSWORD";
+pub const BINDLE_TOKEN: &str = "BINDLE_TOKEN";
 pub const BUILDINFO_OPT: &str = "BUILDINFO";
 pub const INSECURE_OPT: &str = "INSECURE";
 pub const STAGING_DIR_OPT: &str = "STAGING_DIR";
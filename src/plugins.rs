@@ -0,0 +1,453 @@
+//! Plugins: executables installed from a manifest, and run as `spin <name>`.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Context, Result};
+use semver::{Version, VersionReq};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// The file of an installed plugin holding its manifest.
+const MANIFEST_FILE: &str = "plugin.toml";
+/// The file of an installed plugin recording where it was installed from.
+const SOURCE_FILE: &str = "source";
+
+lazy_static::lazy_static! {
+    static ref PLUGIN_NAME: regex::Regex = regex::Regex::new("^[a-z0-9][a-z0-9-]*$").expect("Invalid plugin name regex");
+}
+
+/// The manifest of a plugin version, listing its executable for each
+/// platform.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct PluginManifest {
+    /// The name of the plugin, which is also the subcommand running it.
+    pub name: String,
+    pub version: Version,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub homepage: Option<String>,
+    #[serde(default)]
+    pub license: Option<String>,
+    /// The versions of Spin the plugin works with, such as `>=0.4`.
+    #[serde(default)]
+    pub spin_compatibility: Option<VersionReq>,
+    #[serde(rename = "package")]
+    pub packages: Vec<PluginPackage>,
+}
+
+/// The executable of a plugin for a platform.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct PluginPackage {
+    /// The operating system, as in Rust's `std::env::consts::OS`.
+    pub os: String,
+    /// The architecture, as in Rust's `std::env::consts::ARCH`.
+    pub arch: String,
+    /// The URL of the executable, or its path relative to the manifest.
+    pub url: String,
+    /// The SHA-256 digest of the executable.
+    pub sha256: String,
+}
+
+impl PluginManifest {
+    /// Parses a manifest, checking its name.
+    pub fn parse(contents: &str) -> Result<Self> {
+        let manifest: Self = toml::from_str(contents)?;
+        if !PLUGIN_NAME.is_match(&manifest.name) {
+            bail!(
+                "Invalid plugin name {:?}: names must be lowercase letters, digits and hyphens",
+                manifest.name
+            );
+        }
+        Ok(manifest)
+    }
+
+    /// The package for the platform Spin runs on.
+    pub fn package(&self) -> Result<&PluginPackage> {
+        let (os, arch) = (std::env::consts::OS, std::env::consts::ARCH);
+        self.packages
+            .iter()
+            .find(|p| p.os == os && p.arch == arch)
+            .ok_or_else(|| {
+                anyhow!(
+                    "Plugin {} {} has no package for {}/{}",
+                    self.name,
+                    self.version,
+                    os,
+                    arch
+                )
+            })
+    }
+
+    /// Checks that the plugin supports a version of Spin.
+    pub fn check_compatibility(&self, spin_version: &str) -> Result<()> {
+        let requirement = match &self.spin_compatibility {
+            Some(requirement) => requirement,
+            None => return Ok(()),
+        };
+        let version = Version::parse(spin_version)
+            .with_context(|| format!("Invalid Spin version {}", spin_version))?;
+        // Pre-release builds of Spin are checked as the release they lead to.
+        let release = Version::new(version.major, version.minor, version.patch);
+        if !requirement.matches(&release) {
+            bail!(
+                "Plugin {} {} requires Spin {}, but this is Spin {}",
+                self.name,
+                self.version,
+                requirement,
+                spin_version
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Where a plugin manifest is read from: a local file or a URL.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum ManifestSource {
+    File(PathBuf),
+    Url(url::Url),
+}
+
+impl ManifestSource {
+    pub fn parse(source: &str) -> Result<Self> {
+        if source.starts_with("http://") || source.starts_with("https://") {
+            Ok(Self::Url(source.parse().with_context(|| {
+                format!("Invalid plugin manifest URL {}", source)
+            })?))
+        } else {
+            let path = Path::new(source);
+            let path = dunce::canonicalize(path)
+                .with_context(|| format!("Cannot find plugin manifest {}", path.display()))?;
+            Ok(Self::File(path))
+        }
+    }
+
+    pub async fn read_manifest(&self) -> Result<PluginManifest> {
+        let contents = match self {
+            Self::File(path) => tokio::fs::read_to_string(path)
+                .await
+                .with_context(|| format!("Cannot read plugin manifest {}", path.display()))?,
+            Self::Url(url) => {
+                spin_loader::offline::ensure_online(format!(
+                    "download the plugin manifest {}",
+                    url
+                ))?;
+                reqwest::get(url.clone())
+                    .await?
+                    .error_for_status()?
+                    .text()
+                    .await
+                    .with_context(|| format!("Cannot download plugin manifest {}", url))?
+            }
+        };
+        PluginManifest::parse(&contents)
+            .with_context(|| format!("Invalid plugin manifest {}", self))
+    }
+
+    /// Reads a package, checking its digest.
+    pub async fn read_package(&self, package: &PluginPackage) -> Result<Vec<u8>> {
+        let bytes = match self.resolve(&package.url)? {
+            Self::File(path) => tokio::fs::read(&path)
+                .await
+                .with_context(|| format!("Cannot read plugin package {}", path.display()))?,
+            Self::Url(url) => {
+                spin_loader::offline::ensure_online(format!(
+                    "download the plugin package {}",
+                    url
+                ))?;
+                reqwest::get(url.clone())
+                    .await?
+                    .error_for_status()?
+                    .bytes()
+                    .await
+                    .with_context(|| format!("Cannot download plugin package {}", url))?
+                    .to_vec()
+            }
+        };
+        let digest = format!("{:x}", Sha256::digest(&bytes));
+        if !digest.eq_ignore_ascii_case(&package.sha256) {
+            bail!(
+                "The plugin package {} has digest {}, but the manifest expects {}",
+                package.url,
+                digest,
+                package.sha256
+            );
+        }
+        Ok(bytes)
+    }
+
+    /// Resolves the URL of a package relative to the manifest.
+    fn resolve(&self, reference: &str) -> Result<Self> {
+        if reference.starts_with("http://") || reference.starts_with("https://") {
+            return Ok(Self::Url(reference.parse()?));
+        }
+        match self {
+            Self::File(path) => Ok(Self::File(
+                path.parent()
+                    .unwrap_or_else(|| Path::new("."))
+                    .join(reference),
+            )),
+            Self::Url(url) => Ok(Self::Url(url.join(reference)?)),
+        }
+    }
+}
+
+impl std::fmt::Display for ManifestSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::File(path) => write!(f, "{}", path.display()),
+            Self::Url(url) => write!(f, "{}", url),
+        }
+    }
+}
+
+/// A plugin installed in the plugin store.
+#[derive(Clone, Debug)]
+pub(crate) struct InstalledPlugin {
+    pub manifest: PluginManifest,
+    /// Where the plugin was installed from, and is upgraded from.
+    pub source: ManifestSource,
+    pub dir: PathBuf,
+}
+
+impl InstalledPlugin {
+    /// The executable of the plugin.
+    pub fn executable(&self) -> PathBuf {
+        self.dir.join(executable_name(&self.manifest.name))
+    }
+}
+
+/// The directory plugins are installed in, with a subdirectory for each.
+pub(crate) struct PluginStore {
+    root: PathBuf,
+}
+
+impl PluginStore {
+    pub fn new(root: impl AsRef<Path>) -> Self {
+        Self {
+            root: root.as_ref().to_owned(),
+        }
+    }
+
+    /// The plugin store in the local data directory.
+    pub fn local() -> Result<Self> {
+        Ok(Self::new(spin_data_dir()?.join("plugins")))
+    }
+
+    fn plugin_dir(&self, name: &str) -> PathBuf {
+        self.root.join(name)
+    }
+
+    /// The installed plugin of a name, if any.
+    pub fn get(&self, name: &str) -> Result<Option<InstalledPlugin>> {
+        if !PLUGIN_NAME.is_match(name) {
+            return Ok(None);
+        }
+        let dir = self.plugin_dir(name);
+        let manifest_file = dir.join(MANIFEST_FILE);
+        if !manifest_file.exists() {
+            return Ok(None);
+        }
+        let manifest = std::fs::read_to_string(&manifest_file)
+            .with_context(|| format!("Cannot read {}", manifest_file.display()))?;
+        let manifest = PluginManifest::parse(&manifest)
+            .with_context(|| format!("Invalid plugin manifest {}", manifest_file.display()))?;
+        let source = std::fs::read_to_string(dir.join(SOURCE_FILE))
+            .with_context(|| format!("Cannot read the source of plugin {}", name))?;
+        let source = match source.trim().parse::<url::Url>() {
+            Ok(url) if url.scheme() == "http" || url.scheme() == "https" => {
+                ManifestSource::Url(url)
+            }
+            _ => ManifestSource::File(PathBuf::from(source.trim())),
+        };
+        Ok(Some(InstalledPlugin {
+            manifest,
+            source,
+            dir,
+        }))
+    }
+
+    /// The installed plugins, sorted by name.
+    pub fn list(&self) -> Result<Vec<InstalledPlugin>> {
+        if !self.root.exists() {
+            return Ok(vec![]);
+        }
+        let mut plugins = vec![];
+        for entry in std::fs::read_dir(&self.root)
+            .with_context(|| format!("Cannot read {}", self.root.display()))?
+        {
+            let name = entry?.file_name().to_string_lossy().into_owned();
+            if let Some(plugin) = self.get(&name)? {
+                plugins.push(plugin);
+            }
+        }
+        plugins.sort_by(|a, b| a.manifest.name.cmp(&b.manifest.name));
+        Ok(plugins)
+    }
+
+    /// Installs a plugin executable, replacing any installed version.
+    ///
+    /// The plugin is written to a staging directory, which then replaces the
+    /// plugin's directory, so that a failed installation leaves any previous
+    /// version in place, and a plugin is never run with the files of two
+    /// versions.
+    pub fn install(
+        &self,
+        manifest: &PluginManifest,
+        source: &ManifestSource,
+        executable: &[u8],
+    ) -> Result<InstalledPlugin> {
+        let dir = self.plugin_dir(&manifest.name);
+        // Plugin names cannot start with a dot, so these are never listed.
+        let staged = self.root.join(format!(".{}.new", manifest.name));
+        let replaced = self.root.join(format!(".{}.old", manifest.name));
+        remove_dir_if_exists(&staged)?;
+        std::fs::create_dir_all(&staged)
+            .with_context(|| format!("Cannot create {}", staged.display()))?;
+
+        let executable_path = staged.join(executable_name(&manifest.name));
+        std::fs::write(&executable_path, executable)
+            .with_context(|| format!("Cannot write {}", executable_path.display()))?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&executable_path, std::fs::Permissions::from_mode(0o755))?;
+        }
+        std::fs::write(staged.join(SOURCE_FILE), source.to_string())?;
+        std::fs::write(staged.join(MANIFEST_FILE), toml::to_string(manifest)?)?;
+
+        if dir.exists() {
+            remove_dir_if_exists(&replaced)?;
+            std::fs::rename(&dir, &replaced)
+                .with_context(|| format!("Cannot replace {}", dir.display()))?;
+        }
+        if let Err(e) = std::fs::rename(&staged, &dir) {
+            // Put the previous version back.
+            if replaced.exists() {
+                let _ = std::fs::rename(&replaced, &dir);
+            }
+            return Err(e).with_context(|| format!("Cannot write {}", dir.display()));
+        }
+        remove_dir_if_exists(&replaced)?;
+        Ok(InstalledPlugin {
+            manifest: manifest.clone(),
+            source: source.clone(),
+            dir,
+        })
+    }
+
+    /// Removes a plugin, failing if it is not installed.
+    pub fn uninstall(&self, name: &str) -> Result<()> {
+        match self.get(name)? {
+            Some(plugin) => std::fs::remove_dir_all(&plugin.dir)
+                .with_context(|| format!("Cannot remove {}", plugin.dir.display())),
+            None => bail!("Plugin {} is not installed", name),
+        }
+    }
+}
+
+/// The directory Spin keeps its local data in, such as plugins and
+/// templates.
+pub(crate) fn spin_data_dir() -> Result<PathBuf> {
+    let data_dir = dirs::data_local_dir()
+        .or_else(|| dirs::home_dir().map(|p| p.join(".spin")))
+        .ok_or_else(|| anyhow!("Unable to get local data directory or home directory"))?;
+    Ok(data_dir.join("spin"))
+}
+
+fn remove_dir_if_exists(dir: &Path) -> Result<()> {
+    match std::fs::remove_dir_all(dir) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+            Err(e).with_context(|| format!("Cannot remove {}", dir.display()))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn executable_name(name: &str) -> String {
+    format!("spin-{}{}", name, std::env::consts::EXE_SUFFIX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest(extra: &str) -> String {
+        format!(
+            r#"
+            name = "hello"
+            version = "1.2.0"
+            {}
+
+            [[package]]
+            os = "{}"
+            arch = "{}"
+            url = "spin-hello"
+            sha256 = "{:x}"
+            "#,
+            extra,
+            std::env::consts::OS,
+            std::env::consts::ARCH,
+            Sha256::digest(b"#!/bin/sh\necho hello\n")
+        )
+    }
+
+    #[test]
+    fn test_manifest_compatibility() -> Result<()> {
+        let any = PluginManifest::parse(&manifest(""))?;
+        assert!(any.check_compatibility("0.4.0").is_ok());
+
+        let compatible = PluginManifest::parse(&manifest(r#"spin_compatibility = ">=0.4""#))?;
+        assert!(compatible.check_compatibility("0.4.0").is_ok());
+        assert!(compatible.check_compatibility("0.5.0-pre0").is_ok());
+        assert!(compatible.check_compatibility("0.3.0").is_err());
+
+        let invalid = manifest("").replace("\"hello\"", "\"Hello World\"");
+        assert!(PluginManifest::parse(&invalid).is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_install_from_file() -> Result<()> {
+        let source_dir = tempfile::tempdir()?;
+        let manifest_path = source_dir.path().join("hello.toml");
+        std::fs::write(&manifest_path, manifest(""))?;
+        std::fs::write(
+            source_dir.path().join("spin-hello"),
+            "#!/bin/sh\necho hello\n",
+        )?;
+
+        let source = ManifestSource::parse(&manifest_path.to_string_lossy())?;
+        let manifest = source.read_manifest().await?;
+        let executable = source.read_package(manifest.package()?).await?;
+
+        let store_dir = tempfile::tempdir()?;
+        let store = PluginStore::new(store_dir.path());
+        store.install(&manifest, &source, &executable)?;
+        let installed = store.get("hello")?.expect("plugin should be installed");
+        assert_eq!(installed.manifest.version, Version::new(1, 2, 0));
+        assert_eq!(installed.source, source);
+        assert!(installed.executable().exists());
+        assert_eq!(store.list()?.len(), 1);
+
+        // Upgrades replace the whole directory of the plugin.
+        std::fs::write(installed.dir.join("stale"), "from 1.2.0")?;
+        let upgrade = PluginManifest::parse(&manifest("").replace("1.2.0", "1.3.0"))?;
+        store.install(&upgrade, &source, &executable)?;
+        let installed = store.get("hello")?.expect("plugin should be installed");
+        assert_eq!(installed.manifest.version, Version::new(1, 3, 0));
+        assert!(!installed.dir.join("stale").exists());
+        assert_eq!(std::fs::read_dir(store_dir.path())?.count(), 1);
+
+        store.uninstall("hello")?;
+        assert!(store.get("hello")?.is_none());
+        assert!(store.uninstall("hello").is_err());
+
+        std::fs::write(source_dir.path().join("spin-hello"), "tampered")?;
+        assert!(source.read_package(manifest.package()?).await.is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,417 @@
+//! Trust-on-first-use pinning of server certificates, as a middle ground
+//! between full CA validation and `--insecure`.
+
+use std::{
+    collections::BTreeMap,
+    convert::Infallible,
+    net::{Ipv4Addr, TcpListener},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use hyper::{
+    header,
+    service::{make_service_fn, service_fn},
+    Body, Request, Response, StatusCode,
+};
+use sha2::{Digest, Sha256};
+use spin_http_engine::remove_hop_by_hop_headers;
+use tokio::io::AsyncWriteExt;
+use tokio_rustls::{
+    rustls::{
+        client::{ServerCertVerified, ServerCertVerifier},
+        Certificate, ClientConfig, ServerName,
+    },
+    TlsConnector,
+};
+use url::Url;
+use uuid::Uuid;
+
+/// The file, in the Spin home directory, holding pinned fingerprints.
+const TRUST_STORE_FILE: &str = "trusted-servers.toml";
+
+/// The profile name used when no deploy profile is given.
+pub(crate) const DEFAULT_PROFILE: &str = "default";
+
+/// Pinned certificate fingerprints, by profile and then by server address.
+type Pins = BTreeMap<String, BTreeMap<String, String>>;
+
+/// Checks the certificate of the server at the given URL against the
+/// fingerprint pinned for it in the profile, pinning it if none is, and
+/// relays requests to the server over connections that accept only that
+/// certificate. Returns the URL to send requests to the server at instead,
+/// if the server is reached over HTTPS.
+///
+/// The bindle and Hippo clients configure their own TLS, so cannot check
+/// the pin of each of their connections themselves: they reach the server
+/// through the relay, on a loopback address, instead. Other local users can
+/// connect to the relay too, so it only relays requests whose path starts
+/// with a random token, which only the returned URL holds.
+pub(crate) async fn relay_pinned(
+    url: &str,
+    profile: &str,
+    client: reqwest::ClientBuilder,
+) -> Result<Option<String>> {
+    let url = Url::parse(url).with_context(|| format!("Invalid server URL {}", url))?;
+    if url.scheme() != "https" {
+        return Ok(None);
+    }
+    let fingerprint = pin(&url, profile).await?;
+    let client = client
+        .use_preconfigured_tls(pinned_tls_config(fingerprint))
+        // Redirects are for the clients to follow.
+        .redirect(reqwest::redirect::Policy::none())
+        .build()?;
+    let token = Uuid::new_v4().simple().to_string();
+    let addr = start_relay(client, url.clone(), token.clone())?;
+
+    let mut relay_url = url;
+    relay_url.set_path(&format!("/{}{}", token, relay_url.path()));
+    relay_url.set_host(Some(&addr.ip().to_string()))?;
+    relay_url
+        .set_port(Some(addr.port()))
+        .and_then(|()| relay_url.set_scheme("http"))
+        .map_err(|()| anyhow!("Cannot relay requests to {}", addr))?;
+    Ok(Some(relay_url.to_string()))
+}
+
+/// Checks the certificate of the server against the fingerprint pinned for
+/// it in the profile, pinning it if none is, and returns the fingerprint.
+async fn pin(url: &Url, profile: &str) -> Result<String> {
+    let host = url
+        .host_str()
+        .ok_or_else(|| anyhow!("Server URL {} has no host", url))?;
+    let port = url.port_or_known_default().unwrap_or(443);
+    let address = format!("{}:{}", host, port);
+
+    let fingerprint = fetch_fingerprint(host, port)
+        .await
+        .with_context(|| format!("Cannot fetch the certificate of {}", address))?;
+    check_pin(&trust_store_path()?, profile, address, fingerprint).await
+}
+
+/// Checks the fingerprint of the certificate of the server at the address
+/// against the one pinned for it in the profile, in the trust store at the
+/// given path, pinning it if none is.
+async fn check_pin(
+    path: &Path,
+    profile: &str,
+    address: String,
+    fingerprint: String,
+) -> Result<String> {
+    let mut pins = load_pins(path).await?;
+    let profile_pins = pins.entry(profile.to_owned()).or_default();
+    match profile_pins.get(&address) {
+        Some(pinned) if *pinned == fingerprint => Ok(fingerprint),
+        Some(pinned) => {
+            eprintln!("@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@");
+            eprintln!("WARNING: THE CERTIFICATE OF {} HAS CHANGED!", address);
+            eprintln!("@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@");
+            eprintln!("Someone could be intercepting your connection, or the server");
+            eprintln!("certificate may have been replaced.");
+            eprintln!("Pinned fingerprint:   {}", pinned);
+            eprintln!("Received fingerprint: {}", fingerprint);
+            eprintln!(
+                "If the change is expected, remove the entry for {} in profile '{}' from {}.",
+                address,
+                profile,
+                path.display()
+            );
+            bail!(
+                "Certificate of {} does not match the fingerprint pinned for it",
+                address
+            )
+        }
+        None => {
+            eprintln!(
+                "Trusting the certificate of {} on first use: {}",
+                address, fingerprint
+            );
+            profile_pins.insert(address, fingerprint.clone());
+            save_pins(path, &pins).await?;
+            Ok(fingerprint)
+        }
+    }
+}
+
+/// Connects to the server, returning the SHA-256 fingerprint of its
+/// certificate.
+async fn fetch_fingerprint(host: &str, port: u16) -> Result<String> {
+    let verifier = Arc::new(CapturingVerifier::default());
+    let config = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_custom_certificate_verifier(verifier.clone())
+        .with_no_client_auth();
+    let server_name = ServerName::try_from(host)?;
+    let stream = tokio::net::TcpStream::connect((host, port)).await?;
+    TlsConnector::from(Arc::new(config))
+        .connect(server_name, stream)
+        .await?;
+
+    let captured = verifier.captured.lock().unwrap().take();
+    let certificate = captured.ok_or_else(|| anyhow!("Server presented no certificate"))?;
+    Ok(fingerprint(&certificate))
+}
+
+fn fingerprint(certificate: &Certificate) -> String {
+    format!("sha256:{:x}", Sha256::digest(&certificate.0))
+}
+
+/// Accepts any server certificate, recording it so that it can be checked
+/// against its pin.
+#[derive(Default)]
+struct CapturingVerifier {
+    captured: Mutex<Option<Certificate>>,
+}
+
+impl ServerCertVerifier for CapturingVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        _intermediates: &[Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> Result<ServerCertVerified, tokio_rustls::rustls::Error> {
+        *self.captured.lock().unwrap() = Some(end_entity.clone());
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+/// A TLS configuration accepting only the certificate with the fingerprint.
+fn pinned_tls_config(fingerprint: String) -> ClientConfig {
+    ClientConfig::builder()
+        .with_safe_defaults()
+        .with_custom_certificate_verifier(Arc::new(PinnedVerifier { fingerprint }))
+        .with_no_client_auth()
+}
+
+/// Accepts only the pinned server certificate, whether or not it is valid
+/// for the system CAs.
+struct PinnedVerifier {
+    fingerprint: String,
+}
+
+impl ServerCertVerifier for PinnedVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        _intermediates: &[Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> Result<ServerCertVerified, tokio_rustls::rustls::Error> {
+        if fingerprint(end_entity) != self.fingerprint {
+            return Err(tokio_rustls::rustls::Error::General(
+                "the server certificate does not match the fingerprint pinned for it".to_owned(),
+            ));
+        }
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+/// Serves, on a loopback address, the requests to relay to the server, for
+/// as long as the process runs. Only requests whose path starts with the
+/// token are relayed.
+fn start_relay(
+    client: reqwest::Client,
+    server: Url,
+    token: String,
+) -> Result<std::net::SocketAddr> {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?;
+    listener.set_nonblocking(true)?;
+    let addr = listener.local_addr()?;
+    let make_service = make_service_fn(move |_| {
+        let client = client.clone();
+        let server = server.clone();
+        let token = token.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| {
+                relay(client.clone(), server.clone(), token.clone(), req)
+            }))
+        }
+    });
+    let relay = hyper::Server::from_tcp(listener)?.serve(make_service);
+    tokio::spawn(async move {
+        if let Err(e) = relay.await {
+            tracing::warn!("Relay to pinned server failed: {}", e);
+        }
+    });
+    Ok(addr)
+}
+
+async fn relay(
+    client: reqwest::Client,
+    server: Url,
+    token: String,
+    req: Request<Body>,
+) -> Result<Response<Body>, Infallible> {
+    let path_and_query = req.uri().path_and_query().map_or("/", |p| p.as_str());
+    let path = match relayed_path(path_and_query, &token) {
+        Some(path) => path.to_owned(),
+        None => {
+            let mut res = Response::new(Body::empty());
+            *res.status_mut() = StatusCode::NOT_FOUND;
+            return Ok(res);
+        }
+    };
+    Ok(match send(&client, &server, &path, req).await {
+        Ok(res) => res,
+        Err(e) => {
+            let mut res = Response::new(Body::from(format!("{:#}", e)));
+            *res.status_mut() = StatusCode::BAD_GATEWAY;
+            res
+        }
+    })
+}
+
+/// The path and query of a request to the relay without the leading token,
+/// or `None` if it does not start with the token.
+fn relayed_path<'a>(path_and_query: &'a str, token: &str) -> Option<&'a str> {
+    let rest = path_and_query.strip_prefix('/')?.strip_prefix(token)?;
+    match rest.chars().next() {
+        None => Some("/"),
+        Some('/') => Some(rest),
+        _ => None,
+    }
+}
+
+/// Sends a relayed request for the given path and query to the server,
+/// returning its response.
+async fn send(
+    client: &reqwest::Client,
+    server: &Url,
+    path: &str,
+    req: Request<Body>,
+) -> Result<Response<Body>> {
+    let (parts, body) = req.into_parts();
+    let url = server.join(path)?;
+    let mut headers = parts.headers;
+    remove_hop_by_hop_headers(&mut headers);
+    // The client addressed the relay.
+    headers.remove(header::HOST);
+
+    let mut upstream = client.request(parts.method, url).headers(headers);
+    if hyper::body::HttpBody::size_hint(&body).exact() != Some(0) {
+        upstream = upstream.body(reqwest::Body::wrap_stream(body));
+    }
+    let res = upstream.send().await?;
+
+    let mut relayed = Response::builder().status(res.status());
+    let mut headers = res.headers().clone();
+    remove_hop_by_hop_headers(&mut headers);
+    *relayed.headers_mut().context("Invalid response")? = headers;
+    Ok(relayed.body(Body::wrap_stream(res.bytes_stream()))?)
+}
+
+fn trust_store_path() -> Result<PathBuf> {
+    let home = dirs::home_dir().ok_or_else(|| anyhow!("Cannot find the home directory"))?;
+    Ok(home.join(".spin").join(TRUST_STORE_FILE))
+}
+
+async fn load_pins(path: &Path) -> Result<Pins> {
+    match tokio::fs::read_to_string(path).await {
+        Ok(contents) => toml::from_str(&contents)
+            .with_context(|| format!("Cannot parse trust store {}", path.display())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Pins::new()),
+        Err(e) => Err(e).with_context(|| format!("Cannot read trust store {}", path.display())),
+    }
+}
+
+/// Saves the pins, in a file only the user can read or write, so that no
+/// other user can pin a certificate of theirs.
+async fn save_pins(path: &Path, pins: &Pins) -> Result<()> {
+    let contents = toml::to_string(pins)?;
+    let temp_path = path.with_extension("toml.tmp");
+    let write = async {
+        if let Some(dir) = path.parent() {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+        match tokio::fs::remove_file(&temp_path).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+        let mut options = tokio::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        options.mode(0o600);
+        let mut file = options.open(&temp_path).await?;
+        file.write_all(contents.as_bytes()).await?;
+        file.sync_all().await?;
+        tokio::fs::rename(&temp_path, path).await
+    };
+    write
+        .await
+        .with_context(|| format!("Cannot write trust store {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_only_the_pinned_certificate_is_accepted() {
+        let pinned = Certificate(b"pinned".to_vec());
+        let verifier = PinnedVerifier {
+            fingerprint: fingerprint(&pinned),
+        };
+        let verify = |certificate: &Certificate| {
+            verifier.verify_server_cert(
+                certificate,
+                &[],
+                &ServerName::try_from("bindle.example.com").unwrap(),
+                &mut std::iter::empty::<&[u8]>(),
+                &[],
+                SystemTime::now(),
+            )
+        };
+
+        assert!(verify(&pinned).is_ok());
+        assert!(verify(&Certificate(b"other".to_vec())).is_err());
+    }
+
+    #[test]
+    fn test_only_requests_with_the_token_are_relayed() {
+        assert_eq!(
+            relayed_path("/abc/v1/_i?yanked=true", "abc"),
+            Some("/v1/_i?yanked=true")
+        );
+        assert_eq!(relayed_path("/abc", "abc"), Some("/"));
+        assert_eq!(relayed_path("/v1/_i", "abc"), None);
+        assert_eq!(relayed_path("/abcd/v1/_i", "abc"), None);
+        assert_eq!(relayed_path("/abc?x=1", "abc"), None);
+    }
+
+    #[tokio::test]
+    async fn test_certificates_are_pinned_on_first_use() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join(".spin").join(TRUST_STORE_FILE);
+        let address = || "bindle.example.com:443".to_owned();
+
+        check_pin(&path, "default", address(), "sha256:aa".to_owned()).await?;
+        assert_eq!(load_pins(&path).await?["default"][&address()], "sha256:aa");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path)?.permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        // The pinned certificate is accepted again, in its profile only.
+        check_pin(&path, "default", address(), "sha256:aa".to_owned()).await?;
+        check_pin(&path, "staging", address(), "sha256:bb".to_owned()).await?;
+
+        // A later change of certificate is rejected and not pinned.
+        assert!(
+            check_pin(&path, "default", address(), "sha256:bb".to_owned())
+                .await
+                .is_err()
+        );
+        assert_eq!(load_pins(&path).await?["default"][&address()], "sha256:aa");
+        Ok(())
+    }
+}
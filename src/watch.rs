@@ -0,0 +1,234 @@
+//! Which files `spin watch` watches, and what a change to each requires.
+
+use std::{
+    collections::BTreeSet,
+    path::{Component, Path, PathBuf},
+};
+
+use anyhow::Result;
+use path_absolutize::Absolutize;
+use spin_loader::local::config::{RawAppManifest, RawModuleSource};
+
+/// Directories whose contents never trigger a rebuild or restart: build
+/// outputs, dependencies and version control.
+const IGNORED_DIRS: &[&str] = &["target", "node_modules", ".git", ".spin"];
+
+/// What a set of changed files requires.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct Changes {
+    /// The manifest changed, so every component is rebuilt.
+    pub manifest: bool,
+    /// The components whose build inputs changed.
+    pub components: BTreeSet<String>,
+    /// Files the running application uses changed, so it must be restarted.
+    pub restart: bool,
+}
+
+impl Changes {
+    pub fn is_empty(&self) -> bool {
+        !self.manifest && self.components.is_empty() && !self.restart
+    }
+}
+
+/// A component with a build command, and the directory it builds from.
+#[derive(Debug)]
+struct Build {
+    id: String,
+    workdir: PathBuf,
+    /// The module the build produces, whose changes are not build inputs.
+    output: Option<PathBuf>,
+}
+
+/// The files of an application, and what a change to each requires.
+#[derive(Debug)]
+pub(crate) struct WatchPlan {
+    app_dir: PathBuf,
+    manifest_files: Vec<PathBuf>,
+    builds: Vec<Build>,
+    skip_build: bool,
+}
+
+impl WatchPlan {
+    pub fn new(manifest_file: &Path, app: &RawAppManifest, skip_build: bool) -> Result<Self> {
+        let manifest_file = manifest_file.absolutize()?.into_owned();
+        let app_dir = manifest_file
+            .parent()
+            .unwrap_or_else(|| Path::new("/"))
+            .to_owned();
+        let mut manifest_files = vec![manifest_file];
+        manifest_files.extend(app.components.iter().filter_map(|c| c.origin.clone()));
+        // Env files are read when the application loads, so changes to them
+        // restart it like changes to the manifest.
+        for file in app
+            .components
+            .iter()
+            .filter_map(|c| c.wasm.environment_file.as_ref())
+        {
+            manifest_files.push(app_dir.join(file).absolutize()?.into_owned());
+        }
+
+        let mut builds = vec![];
+        for c in &app.components {
+            if let Some(build) = &c.build {
+                let workdir = match &build.workdir {
+                    Some(workdir) => app_dir.join(workdir),
+                    None => app_dir.clone(),
+                };
+                let output = match &c.source {
+                    RawModuleSource::FileReference(path) => Some(app_dir.join(path)),
+                    RawModuleSource::Bindle(_) | RawModuleSource::Url(_) => None,
+                };
+                builds.push(Build {
+                    id: c.id.clone(),
+                    workdir: workdir.absolutize()?.into_owned(),
+                    output: output
+                        .map(|p| p.absolutize().map(|p| p.into_owned()))
+                        .transpose()?,
+                });
+            }
+        }
+        Ok(Self {
+            app_dir,
+            manifest_files,
+            builds,
+            skip_build,
+        })
+    }
+
+    /// The directories to watch recursively: the application directory, and
+    /// the build directories outside it.
+    pub fn roots(&self) -> Vec<PathBuf> {
+        let mut roots = vec![self.app_dir.clone()];
+        for build in &self.builds {
+            if !roots.iter().any(|r| build.workdir.starts_with(r)) {
+                roots.push(build.workdir.clone());
+            }
+        }
+        roots
+    }
+
+    /// Adds what a change to a file requires to the changes.
+    pub fn classify(&self, path: &Path, changes: &mut Changes) {
+        if self.is_ignored(path) {
+            return;
+        }
+        if self.manifest_files.iter().any(|f| f == path) {
+            changes.manifest = true;
+            return;
+        }
+        if !self.skip_build {
+            let mut is_output = false;
+            for build in &self.builds {
+                if build.output.as_deref() == Some(path) {
+                    is_output = true;
+                } else if path.starts_with(&build.workdir) {
+                    changes.components.insert(build.id.clone());
+                }
+            }
+            if is_output {
+                // The build that wrote it is followed by a restart anyway.
+                return;
+            }
+        }
+        changes.restart = true;
+    }
+
+    fn is_ignored(&self, path: &Path) -> bool {
+        let relative = self
+            .roots()
+            .into_iter()
+            .find_map(|root| path.strip_prefix(&root).ok().map(|p| p.to_owned()));
+        match relative {
+            Some(relative) => relative.components().any(|c| match c {
+                Component::Normal(name) => IGNORED_DIRS.iter().any(|d| name == *d),
+                _ => false,
+            }),
+            None => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spin_loader::local::config::RawAppManifestAnyVersion;
+
+    const MANIFEST: &str = r#"
+spin_version = "1"
+name = "watched"
+version = "1.0.0"
+trigger = { type = "http", base = "/" }
+
+[[component]]
+id = "api"
+source = "api/target/wasm32-wasi/release/api.wasm"
+files = ["static/**/*"]
+[component.trigger]
+route = "/api/..."
+[component.build]
+command = "cargo build --release"
+workdir = "api"
+
+[[component]]
+id = "prebuilt"
+source = "modules/prebuilt.wasm"
+[component.trigger]
+route = "/prebuilt"
+"#;
+
+    fn changes(plan: &WatchPlan, paths: &[&str]) -> Changes {
+        let mut changes = Changes::default();
+        for path in paths {
+            plan.classify(&Path::new("/app").join(path), &mut changes);
+        }
+        changes
+    }
+
+    #[test]
+    fn test_classify() -> Result<()> {
+        let RawAppManifestAnyVersion::V1(app) = toml::from_str(MANIFEST)?;
+        let plan = WatchPlan::new(Path::new("/app/spin.toml"), &app, false)?;
+        assert_eq!(plan.roots(), [PathBuf::from("/app")]);
+
+        assert_eq!(
+            changes(&plan, &["spin.toml"]),
+            Changes {
+                manifest: true,
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            changes(&plan, &["api/src/lib.rs"]),
+            Changes {
+                components: ["api".to_owned()].into(),
+                ..Default::default()
+            }
+        );
+        assert!(changes(
+            &plan,
+            &[
+                "api/target/wasm32-wasi/release/api.wasm",
+                "api/target/debug/build.log",
+                ".git/index"
+            ]
+        )
+        .is_empty());
+        assert_eq!(
+            changes(&plan, &["modules/prebuilt.wasm", "static/index.html"]),
+            Changes {
+                restart: true,
+                ..Default::default()
+            }
+        );
+
+        let plan = WatchPlan::new(Path::new("/app/spin.toml"), &app, true)?;
+        assert_eq!(
+            changes(&plan, &["api/src/lib.rs"]),
+            Changes {
+                restart: true,
+                ..Default::default()
+            }
+        );
+        Ok(())
+    }
+}